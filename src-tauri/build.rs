@@ -1,3 +1,10 @@
 fn main() {
+    // protoc is bundled so building doesn't need it installed
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("bundled protoc"));
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/weather_station.proto"], &["proto"])
+        .expect("failed to compile proto/weather_station.proto");
+
     tauri_build::build()
-}
\ No newline at end of file
+}
@@ -0,0 +1,229 @@
+use crate::config::{AlertRule, RuleComparison, RuleCondition, RuleExpression, RuleMetric};
+use crate::types::{AlertData, SensorData, UnitSystem, WeatherData};
+use std::collections::HashSet;
+
+// What a rule can compare against: the reading itself plus the latest weather report
+pub struct RuleInputs<'a> {
+    pub sensor: &'a SensorData,
+    pub weather: Option<&'a WeatherData>,
+}
+
+impl RuleMetric {
+    pub fn value(self, inputs: &RuleInputs) -> Option<f64> {
+        let sensor = inputs.sensor;
+        match self {
+            RuleMetric::Temperature => Some(sensor.temperature),
+            RuleMetric::Humidity => Some(sensor.humidity),
+            RuleMetric::Pressure => Some(sensor.pressure),
+            RuleMetric::Co2 => sensor.co2,
+            RuleMetric::Tvoc => sensor.tvoc,
+            RuleMetric::Lux => sensor.lux,
+            RuleMetric::WindSpeed => inputs.weather.map(|weather| weather.units.convert_speed(weather.wind_speed, UnitSystem::Metric)),
+            RuleMetric::PressureChange => sensor.pressure_tendency.map(|tendency| tendency.change_3h),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            RuleMetric::Temperature => "temperature",
+            RuleMetric::Humidity => "humidity",
+            RuleMetric::Pressure => "pressure",
+            RuleMetric::Co2 => "CO2",
+            RuleMetric::Tvoc => "TVOC",
+            RuleMetric::Lux => "light",
+            RuleMetric::WindSpeed => "wind speed",
+            RuleMetric::PressureChange => "3h pressure change",
+        }
+    }
+}
+
+impl RuleCondition {
+    fn matches(self, value: f64, threshold: f64) -> bool {
+        match self {
+            RuleCondition::Above => value > threshold,
+            RuleCondition::Below => value < threshold,
+        }
+    }
+
+    // Back past the clear threshold, or the trigger threshold without hysteresis
+    fn cleared(self, value: f64, threshold: f64, clear_threshold: Option<f64>) -> bool {
+        match clear_threshold {
+            Some(clear) => match self {
+                RuleCondition::Above => value < clear,
+                RuleCondition::Below => value > clear,
+            },
+            None => !self.matches(value, threshold),
+        }
+    }
+}
+
+// Missing values never match and always count as cleared
+impl RuleExpression {
+    fn matches(&self, inputs: &RuleInputs) -> bool {
+        match self {
+            RuleExpression::And { conditions } => conditions.iter().all(|condition| condition.matches(inputs)),
+            RuleExpression::Or { conditions } => conditions.iter().any(|condition| condition.matches(inputs)),
+            RuleExpression::Compare(comparison) => comparison.metric.value(inputs)
+                .is_some_and(|value| comparison.condition.matches(value, comparison.threshold)),
+        }
+    }
+
+    // An AND clears once any of its terms clears, an OR once all of them have
+    fn cleared(&self, inputs: &RuleInputs) -> bool {
+        match self {
+            RuleExpression::And { conditions } => conditions.iter().any(|condition| condition.cleared(inputs)),
+            RuleExpression::Or { conditions } => conditions.iter().all(|condition| condition.cleared(inputs)),
+            RuleExpression::Compare(comparison) => comparison.metric.value(inputs)
+                .map_or(true, |value| comparison.condition.cleared(value, comparison.threshold, comparison.clear_threshold)),
+        }
+    }
+
+    fn comparisons(&self) -> Vec<&RuleComparison> {
+        match self {
+            RuleExpression::And { conditions } | RuleExpression::Or { conditions } => {
+                conditions.iter().flat_map(|condition| condition.comparisons()).collect()
+            }
+            RuleExpression::Compare(comparison) => vec![comparison],
+        }
+    }
+}
+
+// Fires a rule's alert when a device's readings first meet its condition,
+// and re-arms it once they are back past the clear thresholds
+#[derive(Default)]
+pub struct RuleEvaluator {
+    // (rule id, device id) pairs whose condition currently holds
+    active: HashSet<(u32, String)>,
+}
+
+impl RuleEvaluator {
+    pub fn evaluate(&mut self, inputs: &RuleInputs, rules: &[AlertRule]) -> Vec<AlertData> {
+        let sensor = inputs.sensor;
+        let device_id = sensor.device_id.clone().unwrap_or_default();
+        let mut alerts = Vec::new();
+
+        for rule in rules {
+            if rule.device_id.as_ref().is_some_and(|id| *id != device_id) {
+                continue;
+            }
+            let key = (rule.id, device_id.clone());
+            if !rule.enabled {
+                self.active.remove(&key);
+                continue;
+            }
+            if rule.skip_flagged_readings && !sensor.quality_ok() {
+                continue;
+            }
+
+            let expression = rule.expression();
+            if self.active.contains(&key) {
+                if expression.cleared(inputs) {
+                    self.active.remove(&key);
+                }
+            } else if expression.matches(inputs) {
+                self.active.insert(key);
+                let message = match rule.message.as_deref().filter(|template| !template.trim().is_empty()) {
+                    Some(template) => render_message(template, rule, &expression, inputs),
+                    None => default_message(rule, &expression, inputs),
+                };
+                alerts.push(AlertData {
+                    message,
+                    level: rule.level.clone(),
+                    timestamp: chrono::Utc::now(),
+                    id: None,
+                });
+            }
+        }
+
+        // Forget rules that were deleted
+        self.active.retain(|(id, _)| rules.iter().any(|rule| rule.id == *id));
+        alerts
+    }
+}
+
+fn default_message(rule: &AlertRule, expression: &RuleExpression, inputs: &RuleInputs) -> String {
+    let sensor = inputs.sensor;
+    let device = sensor.device_name.as_deref()
+        .or(sensor.device_id.as_deref())
+        .map(|name| format!(" on {}", name))
+        .unwrap_or_default();
+    let comparisons = expression.comparisons();
+    if let [comparison] = comparisons.as_slice() {
+        let value = comparison.metric.value(inputs).unwrap_or_default();
+        return format!(
+            "{}: {} {:.1} is {} {}{}",
+            rule.name, comparison.metric.label(), value, condition_label(comparison.condition), comparison.threshold, device
+        );
+    }
+
+    // Composite rules list each metric involved once
+    let mut metrics: Vec<RuleMetric> = Vec::new();
+    for comparison in comparisons {
+        if !metrics.contains(&comparison.metric) {
+            metrics.push(comparison.metric);
+        }
+    }
+    let values: Vec<String> = metrics.iter()
+        .filter_map(|metric| metric.value(inputs).map(|value| format!("{} {:.1}", metric.label(), value)))
+        .collect();
+    format!("{}: {}{}", rule.name, values.join(", "), device)
+}
+
+// Substitutes {placeholders} from the triggering reading. Unknown placeholders are
+// left as they are; readings that aren't available render as "-". In composite rules
+// {metric}, {value}, {threshold} and {condition} refer to the first comparison.
+fn render_message(template: &str, rule: &AlertRule, expression: &RuleExpression, inputs: &RuleInputs) -> String {
+    let sensor = inputs.sensor;
+    let optional = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.1}", v));
+    let device_id = sensor.device_id.clone().unwrap_or_default();
+    let at = sensor.received_at.unwrap_or_else(chrono::Utc::now).with_timezone(&chrono::Local);
+    let first = expression.comparisons().first().map(|comparison| (*comparison).clone());
+    let values = [
+        ("rule", rule.name.clone()),
+        ("metric", first.as_ref().map_or("-".to_string(), |c| c.metric.label().to_string())),
+        ("value", optional(first.as_ref().and_then(|c| c.metric.value(inputs)))),
+        ("threshold", first.as_ref().map_or("-".to_string(), |c| c.threshold.to_string())),
+        ("condition", first.as_ref().map_or("-".to_string(), |c| condition_label(c.condition).to_string())),
+        ("temperature", format!("{:.1}", sensor.temperature)),
+        ("humidity", format!("{:.1}", sensor.humidity)),
+        ("pressure", format!("{:.1}", sensor.pressure)),
+        ("co2", optional(sensor.co2)),
+        ("tvoc", optional(sensor.tvoc)),
+        ("lux", optional(sensor.lux)),
+        ("wind_speed", optional(RuleMetric::WindSpeed.value(inputs))),
+        ("pressure_change", optional(RuleMetric::PressureChange.value(inputs))),
+        ("device_name", sensor.device_name.clone().unwrap_or_else(|| device_id.clone())),
+        ("device_id", device_id),
+        ("time", at.format("%H:%M").to_string()),
+        ("date", at.format("%Y-%m-%d").to_string()),
+    ];
+
+    // Single pass, so substituted text is never expanded again
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        message.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let placeholder = after.find('}')
+            .and_then(|close| values.iter().find(|(name, _)| *name == &after[..close]).map(|(_, value)| (close, value)));
+        match placeholder {
+            Some((close, value)) => {
+                message.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                message.push('{');
+                rest = after;
+            }
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+fn condition_label(condition: RuleCondition) -> &'static str {
+    match condition {
+        RuleCondition::Above => "above",
+        RuleCondition::Below => "below",
+    }
+}
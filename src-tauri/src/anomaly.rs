@@ -0,0 +1,71 @@
+use crate::config::AnomalySettings;
+use crate::types::{QualityFlag, SensorData};
+use std::collections::HashMap;
+use tracing::info;
+
+struct DeviceBaseline {
+    last_accepted: SensorData,
+    rejected_in_a_row: u32,
+}
+
+// Flags readings that change faster than physically plausible compared with the
+// last accepted reading from the same device
+#[derive(Default)]
+pub struct AnomalyDetector {
+    baselines: HashMap<String, DeviceBaseline>,
+}
+
+impl AnomalyDetector {
+    // Returns the reasons a reading is implausible; empty when it is accepted
+    pub fn check(&mut self, reading: &SensorData, settings: &AnomalySettings) -> Vec<QualityFlag> {
+        if !settings.enabled {
+            return Vec::new();
+        }
+
+        let key = reading.device_id.clone().unwrap_or_default();
+        let Some(baseline) = self.baselines.get_mut(&key) else {
+            self.baselines.insert(key, DeviceBaseline { last_accepted: reading.clone(), rejected_in_a_row: 0 });
+            return Vec::new();
+        };
+
+        let flags = Self::rate_flags(&baseline.last_accepted, reading, settings);
+        if flags.is_empty() {
+            baseline.last_accepted = reading.clone();
+            baseline.rejected_in_a_row = 0;
+            return flags;
+        }
+
+        // A sustained change is real (e.g. the station was moved), so stop rejecting it
+        baseline.rejected_in_a_row += 1;
+        if baseline.rejected_in_a_row > settings.max_consecutive_anomalies {
+            info!("Accepting new sensor baseline for '{}' after {} anomalous readings", key, baseline.rejected_in_a_row - 1);
+            baseline.last_accepted = reading.clone();
+            baseline.rejected_in_a_row = 0;
+            return Vec::new();
+        }
+        flags
+    }
+
+    fn rate_flags(previous: &SensorData, reading: &SensorData, settings: &AnomalySettings) -> Vec<QualityFlag> {
+        let (Some(previous_at), Some(at)) = (previous.received_at, reading.received_at) else {
+            return Vec::new();
+        };
+        // Short intervals are allowed a full minute's change so sensor noise doesn't trip the check
+        let minutes = ((at - previous_at).num_milliseconds() as f64 / 60_000.0).max(1.0);
+        let seconds = (at - previous_at).num_seconds();
+
+        let mut flags = Vec::new();
+        let mut check = |field: &str, delta: f64, max_per_minute: f64, unit: &str| {
+            if delta.abs() > max_per_minute * minutes {
+                flags.push(QualityFlag {
+                    field: field.to_string(),
+                    reason: format!("{} changed by {:+.1}{} in {}s", field, delta, unit, seconds),
+                });
+            }
+        };
+        check("temperature", reading.temperature - previous.temperature, settings.max_temperature_change_per_min, "°C");
+        check("humidity", reading.humidity - previous.humidity, settings.max_humidity_change_per_min, "%");
+        check("pressure", reading.pressure - previous.pressure, settings.max_pressure_change_per_min, " hPa");
+        flags
+    }
+}
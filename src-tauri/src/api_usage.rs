@@ -0,0 +1,183 @@
+use crate::storage;
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+const USAGE_FILE_NAME: &str = "api_usage.json";
+// How long calls are held back after the API reports no calls left, unless it says
+// when to retry
+const RATE_LIMIT_BACKOFF_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiUsage {
+    // Local day the counter applies to; it resets at midnight
+    pub date: NaiveDate,
+    pub calls: u32,
+    // 0 means unlimited
+    pub daily_budget: u32,
+    pub refused: u32,
+    pub last_call: Option<DateTime<Utc>>,
+    // From X-RateLimit-* headers when the API sends them
+    pub rate_limit_limit: Option<u32>,
+    pub rate_limit_remaining: Option<u32>,
+    // Calls are refused until then after the API reported none remaining
+    #[serde(default)]
+    pub rate_limited_until: Option<DateTime<Utc>>,
+}
+
+impl ApiUsage {
+    fn new(daily_budget: u32) -> Self {
+        Self {
+            date: Local::now().date_naive(),
+            calls: 0,
+            daily_budget,
+            refused: 0,
+            last_call: None,
+            rate_limit_limit: None,
+            rate_limit_remaining: None,
+            rate_limited_until: None,
+        }
+    }
+}
+
+// Returned instead of making a call once today's budget is used up
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    pub calls: u32,
+    pub daily_budget: u32,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Daily weather API budget reached ({} of {} calls), try again tomorrow", self.calls, self.daily_budget)
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+// Returned instead of making a call while the API's own rate limit is exhausted
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_at: DateTime<Utc>,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Weather API rate limit reached, try again after {}", self.retry_at.with_timezone(&Local).format("%H:%M:%S"))
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+// Counts OpenWeatherMap calls per day and persists the count so restarts
+// don't reset the budget
+pub struct ApiUsageTracker {
+    path: PathBuf,
+    usage: Mutex<ApiUsage>,
+}
+
+impl ApiUsageTracker {
+    pub fn new(daily_budget: u32) -> Self {
+        let path = Self::get_usage_path();
+        let mut usage = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<ApiUsage>(&content).ok())
+            .unwrap_or_else(|| ApiUsage::new(daily_budget));
+        usage.daily_budget = daily_budget;
+
+        Self {
+            path,
+            usage: Mutex::new(usage),
+        }
+    }
+
+    fn get_usage_path() -> PathBuf {
+        storage::data_dir().join(USAGE_FILE_NAME)
+    }
+
+    pub fn set_budget(&self, daily_budget: u32) {
+        self.usage.lock().unwrap().daily_budget = daily_budget;
+    }
+
+    // Counts a call, or refuses it once today's budget is used up or while the API's
+    // rate limit is exhausted
+    pub fn try_acquire(&self) -> Result<()> {
+        let mut usage = self.usage.lock().unwrap();
+        Self::roll_over(&mut usage);
+
+        let now = Utc::now();
+        if let Some(retry_at) = usage.rate_limited_until {
+            if retry_at > now {
+                usage.refused += 1;
+                self.save(&usage);
+                warn!("Weather API call refused: rate limited until {}", retry_at);
+                return Err(RateLimited { retry_at }.into());
+            }
+            // The window has reset; the next response brings fresh numbers
+            usage.rate_limited_until = None;
+            usage.rate_limit_remaining = None;
+        }
+        if usage.daily_budget > 0 && usage.calls >= usage.daily_budget {
+            usage.refused += 1;
+            self.save(&usage);
+            warn!("Weather API call refused: {} of {} daily calls used", usage.calls, usage.daily_budget);
+            return Err(BudgetExceeded { calls: usage.calls, daily_budget: usage.daily_budget }.into());
+        }
+
+        usage.calls += 1;
+        usage.last_call = Some(now);
+        self.save(&usage);
+        Ok(())
+    }
+
+    pub fn record_headers(&self, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u32>().ok());
+
+        let limit = header("x-ratelimit-limit");
+        let remaining = header("x-ratelimit-remaining");
+        if limit.is_none() && remaining.is_none() {
+            return;
+        }
+
+        let mut usage = self.usage.lock().unwrap();
+        usage.rate_limit_limit = limit.or(usage.rate_limit_limit);
+        usage.rate_limit_remaining = remaining;
+        usage.rate_limited_until = (remaining == Some(0)).then(|| {
+            let wait = header("retry-after").map_or(RATE_LIMIT_BACKOFF_SECS, i64::from);
+            Utc::now() + chrono::Duration::seconds(wait)
+        });
+        self.save(&usage);
+    }
+
+    pub fn snapshot(&self) -> ApiUsage {
+        let mut usage = self.usage.lock().unwrap();
+        Self::roll_over(&mut usage);
+        usage.clone()
+    }
+
+    fn roll_over(usage: &mut ApiUsage) {
+        let today = Local::now().date_naive();
+        if usage.date != today {
+            info!("New day, resetting weather API usage ({} calls yesterday)", usage.calls);
+            *usage = ApiUsage::new(usage.daily_budget);
+        }
+    }
+
+    fn save(&self, usage: &ApiUsage) {
+        match serde_json::to_string_pretty(usage) {
+            Ok(content) => {
+                if let Err(e) = fs::write(&self.path, content) {
+                    warn!("Failed to save API usage: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to serialize API usage: {}", e),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::events::{self, AppEvent};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+// Background failures the user didn't trigger directly, so no command returns them
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AppErrorCode {
+    EventLoopStopped,
+    CacheRefreshFailed,
+    PublishFailed,
+    TaskStopped,
+    ConfigSaveFailed,
+}
+
+// Emitted as "app-error" so the UI can show a toast
+#[derive(Debug, Clone, Serialize)]
+pub struct AppErrorEvent {
+    pub code: AppErrorCode,
+    pub module: String,
+    pub message: String,
+    // The app keeps retrying on its own; false means the user has to step in
+    pub recoverable: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+pub fn report(code: AppErrorCode, module: &str, message: impl Into<String>, recoverable: bool) {
+    let event = AppErrorEvent {
+        code,
+        module: module.to_string(),
+        message: message.into(),
+        recoverable,
+        timestamp: Utc::now(),
+    };
+    events::publish(AppEvent::AppError(event));
+}
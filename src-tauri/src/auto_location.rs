@@ -0,0 +1,108 @@
+use crate::config::{AutoLocationSettings, ConfigManager};
+use crate::events::{self, AppEvent};
+use crate::geo;
+use crate::mqtt_client::MqttHandle;
+use crate::types::LocationChange;
+use crate::weather_api::WeatherApiClient;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, debug, warn};
+
+// How soon switching it on takes effect
+const IDLE_CHECK_SECS: u64 = 60;
+
+// Moves weather fetching and auto-publishing over to new coordinates
+pub async fn switch(
+    weather_api: &WeatherApiClient,
+    mqtt_manager: &MqttHandle,
+    from: (f64, f64),
+    to: (f64, f64),
+) -> Result<()> {
+    if from == to {
+        return Ok(());
+    }
+    if let Err(e) = weather_api.invalidate_cache(from.0, from.1).await {
+        warn!("Failed to clear the weather cache for the previous location: {}", e);
+    }
+    mqtt_manager.change_location(to.0, to.1).await
+}
+
+// Looks up where this machine is every check interval. The OS location services need
+// a permission prompt per platform, so IP geolocation is used; it is only accurate to
+// a town or so, which is why saved locations are matched by distance.
+pub fn spawn(
+    config_manager: Arc<Mutex<ConfigManager>>,
+    weather_api: Arc<WeatherApiClient>,
+    mqtt_manager: MqttHandle,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        info!("Automatic location switching started");
+        loop {
+            let settings = config_manager.lock().await.weather_api_settings().auto_location.clone();
+            let wait = if settings.enabled {
+                if let Err(e) = check(&settings, &config_manager, &weather_api, &mqtt_manager).await {
+                    warn!("Automatic location check failed: {}", e);
+                }
+                u64::from(settings.check_interval_minutes.max(1)) * 60
+            } else {
+                IDLE_CHECK_SECS
+            };
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+        }
+    })
+}
+
+async fn check(
+    settings: &AutoLocationSettings,
+    config_manager: &Arc<Mutex<ConfigManager>>,
+    weather_api: &WeatherApiClient,
+    mqtt_manager: &MqttHandle,
+) -> Result<()> {
+    let here = weather_api.detect_location().await?;
+    let (active, locations) = {
+        let config_manager = config_manager.lock().await;
+        (config_manager.active_coordinates(), config_manager.weather_api_settings().locations.clone())
+    };
+    let moved_km = geo::distance_km(active.0, active.1, here.lat, here.lon);
+    if moved_km < settings.switch_distance_km {
+        debug!("Still within {:.1} km of the active location", moved_km);
+        return Ok(());
+    }
+
+    let nearest = locations.into_iter()
+        .map(|location| {
+            let distance = geo::distance_km(location.latitude, location.longitude, here.lat, here.lon);
+            (location, distance)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    let Some((location, _)) = nearest.filter(|(_, distance)| *distance < settings.switch_distance_km) else {
+        info!(
+            "Moved {:.1} km to near {}, but no saved location is within {} km",
+            moved_km, here.name, settings.switch_distance_km
+        );
+        return Ok(());
+    };
+
+    info!("Moved {:.1} km, switching to saved location {}", moved_km, location.name);
+    let (previous, current) = {
+        let mut config_manager = config_manager.lock().await;
+        let previous = config_manager.active_coordinates();
+        config_manager.set_active_location(Some(location.name.clone())).await?;
+        (previous, config_manager.active_coordinates())
+    };
+    // Fetched before publishing restarts, so the publisher finds it cached
+    if let Err(e) = weather_api.ensure_daily_cache(current.0, current.1).await {
+        warn!("Failed to refresh the weather cache for {}: {}", location.name, e);
+    }
+    switch(weather_api, mqtt_manager, previous, current).await?;
+    events::publish(AppEvent::WeatherLocationChanged(LocationChange {
+        lat: current.0,
+        lon: current.1,
+        source: format!("preset:{}", location.name),
+        distance_km: Some(moved_km),
+    }));
+    Ok(())
+}
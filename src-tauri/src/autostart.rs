@@ -0,0 +1,33 @@
+use anyhow::Result;
+use tauri::plugin::TauriPlugin;
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_autostart::{MacosLauncher, ManagerExt};
+use tracing::info;
+
+// Passed by the OS login entry, so a launch at login can be told apart from a manual one
+const LAUNCHED_AT_LOGIN_ARG: &str = "--autostart";
+
+// Registers a login item (macOS launch agent), a Run registry key (Windows) or an
+// XDG autostart desktop entry (Linux) when enabled
+pub fn plugin<R: Runtime>() -> TauriPlugin<R> {
+    tauri_plugin_autostart::init(MacosLauncher::LaunchAgent, Some(vec![LAUNCHED_AT_LOGIN_ARG]))
+}
+
+pub fn launched_at_login() -> bool {
+    std::env::args().any(|arg| arg == LAUNCHED_AT_LOGIN_ARG)
+}
+
+// Brings the OS login entry in line with the launch_at_login setting
+pub fn apply<R: Runtime>(app: &AppHandle<R>, enabled: bool) -> Result<()> {
+    let autolaunch = app.autolaunch();
+    if autolaunch.is_enabled()? == enabled {
+        return Ok(());
+    }
+    if enabled {
+        autolaunch.enable()?;
+    } else {
+        autolaunch.disable()?;
+    }
+    info!("Launch at login {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
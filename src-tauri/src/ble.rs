@@ -0,0 +1,250 @@
+use crate::config::BleSettings;
+use crate::devices;
+use crate::mqtt_client::MqttHandle;
+use crate::supervisor::StoppableTask;
+use anyhow::{Result, anyhow};
+use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter};
+use btleplug::platform::{Adapter, Manager, Peripheral};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+const TASK_NAME: &str = "BLE transport";
+const RETRY_DELAY_SECS: u64 = 10;
+const SCAN_POLL_MS: u64 = 500;
+// A reading split over more notifications than this is assumed lost
+const MAX_READING_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BleStatus {
+    pub running: bool,
+    pub connected: bool,
+    // Advertised name of the device in use
+    pub device_name: Option<String>,
+    pub readings: u64,
+    pub last_reading_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+static STATUS: Mutex<Option<BleStatus>> = Mutex::new(None);
+
+// None until the transport has been started once
+pub fn status() -> Option<BleStatus> {
+    STATUS.lock().unwrap().clone()
+}
+
+fn update_status(update: impl FnOnce(&mut BleStatus)) {
+    if let Some(status) = STATUS.lock().unwrap().as_mut() {
+        update(status);
+    }
+}
+
+// Reads an M5Go over Bluetooth LE for when there is no broker around. Readings arrive
+// as notifications on one characteristic and go through the same handling as ones
+// from the broker, heartbeats included.
+pub struct BleTransport {
+    settings: BleSettings,
+    task: StoppableTask,
+}
+
+impl BleTransport {
+    pub fn start(settings: BleSettings, mqtt_manager: MqttHandle) -> Result<Self> {
+        let service = Uuid::parse_str(settings.service_uuid.trim())
+            .map_err(|e| anyhow!("Invalid BLE service UUID: {}", e))?;
+        let characteristic = Uuid::parse_str(settings.characteristic_uuid.trim())
+            .map_err(|e| anyhow!("Invalid BLE characteristic UUID: {}", e))?;
+
+        info!("Starting BLE transport for devices named {}", settings.device_name);
+        *STATUS.lock().unwrap() = Some(BleStatus {
+            running: true,
+            ..Default::default()
+        });
+
+        let task_settings = settings.clone();
+        let task = StoppableTask::spawn(TASK_NAME, move |cancel| {
+            tokio::spawn(run(task_settings.clone(), service, characteristic, mqtt_manager.clone(), cancel))
+        });
+        Ok(Self { settings, task })
+    }
+
+    pub fn settings(&self) -> &BleSettings {
+        &self.settings
+    }
+
+    pub async fn stop(self) {
+        self.task.stop().await;
+        update_status(|status| {
+            status.running = false;
+            status.connected = false;
+        });
+        info!("BLE transport stopped");
+    }
+}
+
+async fn run(settings: BleSettings, service: Uuid, characteristic: Uuid, mqtt_manager: MqttHandle, cancel: CancellationToken) {
+    loop {
+        match session(&settings, service, characteristic, &mqtt_manager, &cancel).await {
+            Ok(()) => info!("BLE device disconnected"),
+            Err(e) => {
+                warn!("BLE transport: {}", e);
+                update_status(|status| status.last_error = Some(e.to_string()));
+            }
+        }
+        update_status(|status| status.connected = false);
+        if cancel.is_cancelled() {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(RETRY_DELAY_SECS)) => {}
+            _ = cancel.cancelled() => break,
+        }
+    }
+}
+
+// One scan, connect and read cycle; returns when the device goes away or on stop
+async fn session(
+    settings: &BleSettings,
+    service: Uuid,
+    characteristic: Uuid,
+    mqtt_manager: &MqttHandle,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let manager = Manager::new().await?;
+    let adapter = manager.adapters().await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No Bluetooth adapter found"))?;
+
+    let timeout = Duration::from_secs(settings.scan_timeout_secs.max(1));
+    let peripheral = tokio::select! {
+        found = find_device(&adapter, &settings.device_name, timeout) => found?,
+        _ = cancel.cancelled() => {
+            let _ = adapter.stop_scan().await;
+            return Ok(());
+        }
+    };
+    let name = advertised_name(&peripheral).await.unwrap_or_else(|| settings.device_name.clone());
+    info!("Connecting to {} over Bluetooth", name);
+    peripheral.connect().await?;
+
+    let result = read_notifications(&peripheral, &name, settings, service, characteristic, mqtt_manager, cancel).await;
+    if let Err(e) = peripheral.disconnect().await {
+        debug!("Failed to disconnect from {}: {}", name, e);
+    }
+    result
+}
+
+async fn find_device(adapter: &Adapter, device_name: &str, timeout: Duration) -> Result<Peripheral> {
+    // Not filtered by service: the M5Go only lists it in the scan response, which some
+    // platforms don't match against
+    adapter.start_scan(ScanFilter::default()).await?;
+    let deadline = Instant::now() + timeout;
+    let found: Result<Option<Peripheral>> = async {
+        loop {
+            for peripheral in adapter.peripherals().await? {
+                if advertised_name(&peripheral).await.is_some_and(|name| name.starts_with(device_name)) {
+                    return Ok(Some(peripheral));
+                }
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            tokio::time::sleep(Duration::from_millis(SCAN_POLL_MS)).await;
+        }
+    }.await;
+    let _ = adapter.stop_scan().await;
+    found?.ok_or_else(|| anyhow!("No device named {}* found within {}s", device_name, timeout.as_secs()))
+}
+
+async fn advertised_name(peripheral: &Peripheral) -> Option<String> {
+    peripheral.properties().await.ok().flatten().and_then(|properties| properties.local_name)
+}
+
+async fn read_notifications(
+    peripheral: &Peripheral,
+    name: &str,
+    settings: &BleSettings,
+    service: Uuid,
+    characteristic: Uuid,
+    mqtt_manager: &MqttHandle,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    peripheral.discover_services().await?;
+    let target = peripheral.characteristics()
+        .into_iter()
+        .find(|c| c.uuid == characteristic && c.service_uuid == service)
+        .ok_or_else(|| anyhow!("{} has no characteristic {} in service {}", name, characteristic, service))?;
+    let mut notifications = peripheral.notifications().await?;
+    peripheral.subscribe(&target).await?;
+
+    info!("Receiving readings from {} over Bluetooth", name);
+    update_status(|status| {
+        status.connected = true;
+        status.device_name = Some(name.to_string());
+        status.last_error = None;
+    });
+
+    // Readings longer than the MTU arrive in pieces
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut online: Option<String> = None;
+    loop {
+        let notification = tokio::select! {
+            notification = notifications.next() => notification,
+            _ = cancel.cancelled() => break,
+        };
+        let Some(notification) = notification else {
+            break;
+        };
+        if notification.uuid != characteristic {
+            continue;
+        }
+        buffer.extend_from_slice(&notification.value);
+
+        let mut reading = match serde_json::from_slice::<Value>(&buffer) {
+            Ok(reading) => reading,
+            Err(e) if e.is_eof() && buffer.len() < MAX_READING_BYTES => continue,
+            Err(e) => {
+                warn!("Dropping unreadable BLE payload from {}: {}", name, e);
+                buffer.clear();
+                continue;
+            }
+        };
+        buffer.clear();
+
+        // Older firmware leaves the id out when not on MQTT
+        let Some(fields) = reading.as_object_mut() else {
+            warn!("Dropping BLE payload from {} that is not a JSON object", name);
+            continue;
+        };
+        let device_id = match fields.get("device_id").and_then(|id| id.as_str()) {
+            Some(id) => id.to_string(),
+            None => {
+                let id = settings.device_id.clone().unwrap_or_else(|| name.to_string());
+                fields.insert("device_id".to_string(), Value::String(id.clone()));
+                id
+            }
+        };
+
+        if online.as_deref() != Some(device_id.as_str()) {
+            mqtt_manager.ingest(&devices::device_topic(&device_id, "status"), b"online".to_vec()).await?;
+            online = Some(device_id);
+        }
+        mqtt_manager.ingest("weather/sensor_data", reading.to_string().into_bytes()).await?;
+        update_status(|status| {
+            status.readings += 1;
+            status.last_reading_at = Some(Utc::now());
+        });
+    }
+
+    if let Some(device_id) = online {
+        mqtt_manager.ingest(&devices::device_topic(&device_id, "status"), b"offline".to_vec()).await?;
+    }
+    Ok(())
+}
+
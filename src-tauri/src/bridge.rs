@@ -0,0 +1,116 @@
+use crate::config::UplinkSettings;
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use rumqttc::{AsyncClient, MqttOptions, Event, Packet, QoS, TlsConfiguration, Transport};
+use std::fs;
+use tokio::time::Duration;
+use tracing::{info, error, warn, debug};
+
+// Mirrors messages from the local broker to a cloud broker
+pub struct UplinkBridge {
+    client: AsyncClient,
+    settings: UplinkSettings,
+    event_loop_handle: tokio::task::JoinHandle<()>,
+}
+
+impl UplinkBridge {
+    pub fn start(settings: UplinkSettings) -> Result<Self> {
+        if settings.broker_host.is_empty() {
+            return Err(anyhow!("Uplink broker host is not configured"));
+        }
+
+        info!("Starting uplink bridge to {}:{}", settings.broker_host, settings.broker_port);
+
+        let mut mqttoptions = MqttOptions::new(&settings.client_id, &settings.broker_host, settings.broker_port);
+        mqttoptions.set_keep_alive(Duration::from_secs(60));
+
+        if let Some(username) = &settings.username {
+            mqttoptions.set_credentials(username, settings.password.clone().unwrap_or_default());
+        }
+
+        if settings.use_tls {
+            mqttoptions.set_transport(Self::build_transport(&settings)?);
+        }
+
+        let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
+
+        // The uplink event loop reconnects on its own; we only need to keep polling
+        let event_loop_handle = tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Uplink connection established");
+                    }
+                    Ok(_) => continue,
+                    Err(e) => {
+                        warn!("Uplink event loop error: {}, retrying in 5 seconds", e);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            client,
+            settings,
+            event_loop_handle,
+        })
+    }
+
+    fn build_transport(settings: &UplinkSettings) -> Result<Transport> {
+        let client_auth = match (&settings.client_cert_path, &settings.client_key_path) {
+            (Some(cert_path), Some(key_path)) => Some((fs::read(cert_path)?, fs::read(key_path)?)),
+            (None, None) => None,
+            _ => return Err(anyhow!("Uplink client certificate and key must be configured together")),
+        };
+
+        match &settings.ca_cert_path {
+            Some(ca_path) => {
+                let ca = fs::read(ca_path)?;
+                Ok(Transport::Tls(TlsConfiguration::Simple {
+                    ca,
+                    alpn: None,
+                    client_auth,
+                }))
+            }
+            None if client_auth.is_none() => Ok(Transport::tls_with_default_config()),
+            None => Err(anyhow!("Uplink CA certificate is required when using client certificates")),
+        }
+    }
+
+    fn map_topic(&self, topic: &str) -> String {
+        match self.settings.topic_map.get(topic) {
+            Some(mapped) => mapped.clone(),
+            None => format!("{}{}", self.settings.topic_prefix, topic),
+        }
+    }
+
+    pub fn should_mirror(&self, topic: &str) -> bool {
+        match topic {
+            "weather/sensor_data" => self.settings.mirror_sensor_data,
+            "weather/alert_trigger" => self.settings.mirror_alerts,
+            _ => false,
+        }
+    }
+
+    // Bytes is shared with the event loop's copy, so mirroring doesn't copy the payload
+    pub async fn forward(&self, topic: &str, payload: Bytes) {
+        if !self.should_mirror(topic) {
+            return;
+        }
+
+        let cloud_topic = self.map_topic(topic);
+        match self.client.publish_bytes(&cloud_topic, QoS::AtLeastOnce, false, payload).await {
+            Ok(_) => debug!("Mirrored {} to uplink topic {}", topic, cloud_topic),
+            Err(e) => error!("Failed to mirror {} to uplink: {}", topic, e),
+        }
+    }
+
+    pub async fn shutdown(&self) {
+        info!("Stopping uplink bridge");
+        if let Err(e) = self.client.disconnect().await {
+            warn!("Failed to disconnect uplink client: {}", e);
+        }
+        self.event_loop_handle.abort();
+    }
+}
@@ -0,0 +1,174 @@
+use crate::history::SensorHistory;
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MAX_POINTS: usize = 1000;
+const MAX_POINTS: usize = 5000;
+// Fewer than this can't keep both ends plus a point in between
+const MIN_POINTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartMetric {
+    Temperature,
+    Humidity,
+    Pressure,
+    Co2,
+    Tvoc,
+    Lux,
+}
+
+impl ChartMetric {
+    // Column in sensor_readings; the hourly table has the same name with _avg
+    fn column(self) -> &'static str {
+        match self {
+            ChartMetric::Temperature => "temperature",
+            ChartMetric::Humidity => "humidity",
+            ChartMetric::Pressure => "pressure",
+            ChartMetric::Co2 => "co2",
+            ChartMetric::Tvoc => "tvoc",
+            ChartMetric::Lux => "lux",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartRange {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl ChartRange {
+    fn duration(self) -> chrono::Duration {
+        match self {
+            ChartRange::Hour => chrono::Duration::hours(1),
+            ChartRange::Day => chrono::Duration::days(1),
+            ChartRange::Week => chrono::Duration::days(7),
+            ChartRange::Month => chrono::Duration::days(30),
+            ChartRange::Year => chrono::Duration::days(365),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartPoint {
+    pub at: DateTime<Utc>,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartSeries {
+    pub metric: ChartMetric,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    // Stored samples in the range, before downsampling
+    pub source_points: usize,
+    pub points: Vec<ChartPoint>,
+}
+
+// The last `range` of `metric`, reduced to at most `max_points`. Hours that have been
+// pruned down to hourly aggregates contribute their average.
+pub fn series(
+    history: &SensorHistory,
+    metric: ChartMetric,
+    range: ChartRange,
+    max_points: usize,
+    device_id: Option<&str>,
+) -> Result<ChartSeries> {
+    let to = Utc::now();
+    let from = to - range.duration();
+    let column = metric.column();
+
+    let threshold = max_points.clamp(MIN_POINTS, MAX_POINTS);
+    // SQLite keeps the lowest and highest sample of each bucket, two buckets per
+    // output point, so only that much reaches LTTB
+    let bucket_ms = ((to - from).num_milliseconds() / (threshold as i64 * 2)).max(1);
+
+    let (source_points, samples) = history.with_connection(|connection| {
+        let mut statement = connection.prepare(&format!(
+            "WITH samples (at, value) AS (
+                 SELECT recorded_at, {column}
+                 FROM sensor_readings
+                 WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3 AND {column} IS NOT NULL
+                 UNION ALL
+                 SELECT hour_start, {column}_avg
+                 FROM sensor_hourly
+                 WHERE (?1 IS NULL OR device_id = ?1) AND hour_start BETWEEN ?2 AND ?3 AND {column}_avg IS NOT NULL
+                   AND hour_start < (SELECT COALESCE(MIN(recorded_at), ?3 + 1) FROM sensor_readings
+                                     WHERE ?1 IS NULL OR device_id = ?1)
+             )
+             SELECT at, MIN(value), COUNT(*) FROM samples GROUP BY at / ?4
+             UNION ALL
+             SELECT at, MAX(value), 0 FROM samples GROUP BY at / ?4
+             ORDER BY 1, 2",
+            column = column
+        ))?;
+        let mut source_points = 0;
+        let mut samples = Vec::new();
+        let mut rows = statement.query(params![device_id, from.timestamp_millis(), to.timestamp_millis(), bucket_ms])?;
+        while let Some(row) = rows.next()? {
+            source_points += row.get::<_, i64>(2)? as usize;
+            samples.push((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?));
+        }
+        // A bucket's minimum and maximum can be the same sample. Only exact repeats go, so
+        // two devices reporting in the same millisecond both stay.
+        samples.dedup();
+        Ok((source_points, samples))
+    })?;
+
+    let points = lttb(&samples, threshold)
+        .into_iter()
+        .filter_map(|(at, value)| Some(ChartPoint { at: Utc.timestamp_millis_opt(at).single()?, value }))
+        .collect();
+    Ok(ChartSeries {
+        metric,
+        from,
+        to,
+        source_points,
+        points,
+    })
+}
+
+// Largest-Triangle-Three-Buckets. Keeps the first and last sample and, from each bucket
+// in between, the sample forming the largest triangle with the previous pick and the
+// average of the next bucket, so peaks and dips survive where averaging would flatten them.
+fn lttb(samples: &[(i64, f64)], threshold: usize) -> Vec<(i64, f64)> {
+    if samples.len() <= threshold {
+        return samples.to_vec();
+    }
+    let bucket_size = (samples.len() - 2) as f64 / (threshold - 2) as f64;
+    let bucket_start = |bucket: usize| (bucket as f64 * bucket_size) as usize + 1;
+    // Relative to the first sample, to keep the areas well within f64 precision
+    let x = |index: usize| (samples[index].0 - samples[0].0) as f64;
+
+    let mut picked = Vec::with_capacity(threshold);
+    picked.push(samples[0]);
+    let mut previous = 0;
+    for bucket in 0..threshold - 2 {
+        let next = bucket_start(bucket + 1)..bucket_start(bucket + 2).min(samples.len());
+        let count = next.len() as f64;
+        let avg_x = next.clone().map(x).sum::<f64>() / count;
+        let avg_y = samples[next].iter().map(|sample| sample.1).sum::<f64>() / count;
+
+        let (prev_x, prev_y) = (x(previous), samples[previous].1);
+        let mut best = bucket_start(bucket);
+        let mut best_area = -1.0;
+        for index in bucket_start(bucket)..bucket_start(bucket + 1) {
+            let area = ((prev_x - avg_x) * (samples[index].1 - prev_y) - (prev_x - x(index)) * (avg_y - prev_y)).abs();
+            if area > best_area {
+                best_area = area;
+                best = index;
+            }
+        }
+        picked.push(samples[best]);
+        previous = best;
+    }
+    picked.push(samples[samples.len() - 1]);
+    picked
+}
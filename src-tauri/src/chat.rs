@@ -0,0 +1,129 @@
+use crate::config::{DiscordSettings, TelegramSettings};
+use crate::types::{AlertData, AlertLevel};
+use crate::webhooks::post_with_retry;
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use tracing::error;
+
+const MAX_RETRIES: u32 = 3;
+const TIMEOUT_SECS: u64 = 10;
+// Discord rejects message content longer than this
+const DISCORD_MAX_CHARS: usize = 2000;
+
+// Posts alerts to a Telegram chat and a Discord channel
+pub struct ChatNotifier {
+    client: Client,
+    telegram: RwLock<TelegramSettings>,
+    discord: RwLock<DiscordSettings>,
+}
+
+impl Default for ChatNotifier {
+    fn default() -> Self {
+        Self {
+            client: Client::new(),
+            telegram: RwLock::new(TelegramSettings::default()),
+            discord: RwLock::new(DiscordSettings::default()),
+        }
+    }
+}
+
+impl ChatNotifier {
+    pub fn set_settings(&self, telegram: TelegramSettings, discord: DiscordSettings) {
+        if let Ok(mut guard) = self.telegram.write() {
+            *guard = telegram;
+        }
+        if let Ok(mut guard) = self.discord.write() {
+            *guard = discord;
+        }
+    }
+
+    // Sends in the background to each enabled channel whose minimum level the alert meets
+    pub fn notify_alert(&self, alert: &AlertData) {
+        let text = alert_text(alert);
+
+        let telegram = self.telegram.read().ok()
+            .map(|guard| guard.clone())
+            .filter(|settings| settings.enabled && alert.level >= settings.min_level);
+        if let Some(settings) = telegram {
+            let client = self.client.clone();
+            let text = text.clone();
+            tokio::spawn(async move {
+                if let Err(e) = send_telegram(&client, &settings, &text).await {
+                    error!("Failed to send Telegram alert: {}", e);
+                }
+            });
+        }
+
+        let discord = self.discord.read().ok()
+            .map(|guard| guard.clone())
+            .filter(|settings| settings.enabled && alert.level >= settings.min_level);
+        if let Some(settings) = discord {
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                if let Err(e) = send_discord(&client, &settings, &text).await {
+                    error!("Failed to send Discord alert: {}", e);
+                }
+            });
+        }
+    }
+
+    pub fn telegram_settings(&self) -> TelegramSettings {
+        self.telegram.read().map(|guard| guard.clone()).unwrap_or_default()
+    }
+
+    pub fn discord_settings(&self) -> DiscordSettings {
+        self.discord.read().map(|guard| guard.clone()).unwrap_or_default()
+    }
+
+    // Post the alert with the saved settings and wait for the result
+    pub async fn send_telegram_now(&self, alert: &AlertData) -> Result<()> {
+        send_telegram(&self.client, &self.telegram_settings(), &alert_text(alert)).await
+    }
+
+    pub async fn send_discord_now(&self, alert: &AlertData) -> Result<()> {
+        send_discord(&self.client, &self.discord_settings(), &alert_text(alert)).await
+    }
+
+    pub async fn send_test_telegram(&self, settings: &TelegramSettings) -> Result<()> {
+        send_telegram(&self.client, settings, "Test message from the weather station").await
+    }
+
+    pub async fn send_test_discord(&self, settings: &DiscordSettings) -> Result<()> {
+        send_discord(&self.client, settings, "Test message from the weather station").await
+    }
+}
+
+async fn send_telegram(client: &Client, settings: &TelegramSettings, text: &str) -> Result<()> {
+    if settings.bot_token.is_empty() || settings.chat_id.is_empty() {
+        return Err(anyhow!("Telegram bot token and chat id must be configured"));
+    }
+    let url = format!("https://api.telegram.org/bot{}/sendMessage", settings.bot_token);
+    let body = json!({ "chat_id": settings.chat_id, "text": text }).to_string();
+    post_with_retry(client, "Telegram", &url, &BTreeMap::new(), body, MAX_RETRIES, TIMEOUT_SECS).await
+}
+
+async fn send_discord(client: &Client, settings: &DiscordSettings, text: &str) -> Result<()> {
+    if settings.webhook_url.is_empty() {
+        return Err(anyhow!("Discord webhook URL must be configured"));
+    }
+    let content: String = text.chars().take(DISCORD_MAX_CHARS).collect();
+    let body = json!({ "content": content }).to_string();
+    post_with_retry(client, "Discord", &settings.webhook_url, &BTreeMap::new(), body, MAX_RETRIES, TIMEOUT_SECS).await
+}
+
+fn alert_text(alert: &AlertData) -> String {
+    let level = match alert.level {
+        AlertLevel::Info => "Info",
+        AlertLevel::Warning => "Warning",
+        AlertLevel::Emergency => "Emergency",
+    };
+    format!(
+        "{}: {}\n{}",
+        level,
+        alert.message,
+        alert.timestamp.with_timezone(&chrono::Local).format("%d/%m %H:%M"),
+    )
+}
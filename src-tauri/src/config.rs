@@ -1,40 +1,1653 @@
+use crate::app_error::{self, AppErrorCode};
+use crate::config_validation;
+use crate::icons::default_icon_map;
+use crate::logging;
+use crate::scheduler::CronSchedule;
+use crate::storage;
+use crate::supervisor;
+use crate::types::{AlertLevel, Locale, UnitSystem};
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
-use tracing::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Notify;
+use tokio::time::Duration;
+use tracing::{debug, error, info, warn};
+
+const CONFIG_BACKUP_DIR: &str = "config_backups";
+const CONFIG_BACKUP_TIMESTAMP: &str = "%Y%m%d-%H%M%S%.3f";
+// Saves closer together than this are written as one
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigBackup {
+    // Identifies the backup for restore_config_backup, e.g. "20261016-142501.123"
+    pub timestamp: String,
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppConfig {
+    pub mqtt: MqttSettings,
+    pub weather_api: WeatherApiSettings,
+    pub app: AppSettings,
+    // Per-device settings keyed by device id ("default" applies to unidentified devices)
+    #[serde(default)]
+    pub devices: HashMap<String, DeviceSettings>,
+    #[serde(default)]
+    pub alert_rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub scheduled_tasks: Vec<ScheduledTask>,
+}
+
+// User-defined threshold alert, checked against every sensor reading
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    // Assigned when the rule is created
+    #[serde(default)]
+    pub id: u32,
+    pub name: String,
+    #[serde(default = "default_rule_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub metric: RuleMetric,
+    #[serde(default)]
+    pub condition: RuleCondition,
+    #[serde(default)]
+    pub threshold: f64,
+    // Where an active alert clears, e.g. alert above 30 and clear below 28 so a reading
+    // hovering around the threshold doesn't re-fire. None clears at the threshold itself.
+    #[serde(default)]
+    pub clear_threshold: Option<f64>,
+    #[serde(default = "default_rule_level")]
+    pub level: AlertLevel,
+    // Only readings from this device; None matches every device
+    #[serde(default)]
+    pub device_id: Option<String>,
+    // Alert text with placeholders such as {temperature}, {threshold}, {device_name}
+    // and {time}; None uses a generated message
+    #[serde(default)]
+    pub message: Option<String>,
+    // Several comparisons combined with AND/OR, e.g. temperature above 28 and humidity
+    // above 70. Replaces metric, condition and threshold when set.
+    #[serde(default)]
+    pub expression: Option<RuleExpression>,
+    // Passes over readings flagged out of range, stale or interpolated; they neither
+    // fire nor clear the alert
+    #[serde(default)]
+    pub skip_flagged_readings: bool,
+}
+
+impl AlertRule {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("Alert rule name must not be empty"));
+        }
+        self.expression().validate(0)
+    }
+
+    // The single comparison is treated as a one-term expression
+    pub fn expression(&self) -> RuleExpression {
+        match &self.expression {
+            Some(expression) => expression.clone(),
+            None => RuleExpression::Compare(RuleComparison {
+                metric: self.metric,
+                condition: self.condition,
+                threshold: self.threshold,
+                clear_threshold: self.clear_threshold,
+            }),
+        }
+    }
+}
+
+// Serialized with an "op" tag, e.g.
+// {"op": "and", "conditions": [{"op": "compare", "metric": "temperature", "condition": "above", "threshold": 28}, ...]}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum RuleExpression {
+    And { conditions: Vec<RuleExpression> },
+    Or { conditions: Vec<RuleExpression> },
+    Compare(RuleComparison),
+}
+
+// Deep enough for any expression a person would build
+const MAX_EXPRESSION_DEPTH: usize = 8;
+
+impl RuleExpression {
+    fn validate(&self, depth: usize) -> Result<()> {
+        if depth > MAX_EXPRESSION_DEPTH {
+            return Err(anyhow!("Alert rule expression is nested too deeply"));
+        }
+        match self {
+            RuleExpression::And { conditions } | RuleExpression::Or { conditions } => {
+                if conditions.is_empty() {
+                    return Err(anyhow!("AND/OR groups in an alert rule need at least one condition"));
+                }
+                conditions.iter().try_for_each(|condition| condition.validate(depth + 1))
+            }
+            RuleExpression::Compare(comparison) => comparison.validate(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleComparison {
+    pub metric: RuleMetric,
+    pub condition: RuleCondition,
+    pub threshold: f64,
+    #[serde(default)]
+    pub clear_threshold: Option<f64>,
+}
+
+impl RuleComparison {
+    fn validate(&self) -> Result<()> {
+        if !self.threshold.is_finite() {
+            return Err(anyhow!("Alert rule threshold must be a number"));
+        }
+        if let Some(clear) = self.clear_threshold {
+            if !clear.is_finite() {
+                return Err(anyhow!("Alert rule clear threshold must be a number"));
+            }
+            let on_alert_side = match self.condition {
+                RuleCondition::Above => clear > self.threshold,
+                RuleCondition::Below => clear < self.threshold,
+            };
+            if on_alert_side {
+                return Err(anyhow!(
+                    "Alert rule clear threshold must be {} the threshold",
+                    if self.condition == RuleCondition::Above { "at or below" } else { "at or above" }
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMetric {
+    #[default]
+    Temperature,
+    Humidity,
+    Pressure,
+    Co2,
+    Tvoc,
+    Lux,
+    // From the latest weather report, in m/s
+    WindSpeed,
+    // Sensor pressure change over the last 3 hours, in hPa; negative when falling
+    PressureChange,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleCondition {
+    #[default]
+    Above,
+    Below,
+}
+
+fn default_rule_enabled() -> bool {
+    true
+}
+
+// An action run by the scheduler, e.g. refresh the weather cache every day at 06:00
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledTask {
+    // Assigned when the task is created
+    #[serde(default)]
+    pub id: u32,
+    pub name: String,
+    #[serde(default = "default_rule_enabled")]
+    pub enabled: bool,
+    // Cron expression in local time, e.g. "0 6 * * *" or "@daily"
+    pub schedule: String,
+    pub action: ScheduledAction,
+}
+
+impl ScheduledTask {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("Scheduled task name must not be empty"));
+        }
+        CronSchedule::parse(&self.schedule)?;
+        if let ScheduledAction::PublishForecast { topic } = &self.action {
+            if topic.trim().is_empty() || topic.contains(['+', '#']) {
+                return Err(anyhow!("Expected a topic without wildcards, got '{}'", topic));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Serialized with a "type" tag, e.g. {"type": "publish_forecast", "topic": "weather/forecast"}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduledAction {
+    // Fetches a fresh report for the active location even if today's is cached
+    RefreshWeatherCache,
+    // The cached report to weather/data, as the automated publisher sends it
+    PublishWeather,
+    PublishForecast {
+        #[serde(default = "default_forecast_topic")]
+        topic: String,
+    },
+    // Yesterday's summary, announced like the end-of-day one
+    DailySummary,
+    PruneStorage,
+}
+
+fn default_forecast_topic() -> String {
+    "weather/forecast".to_string()
+}
+
+fn default_rule_level() -> AlertLevel {
+    AlertLevel::Warning
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    // Friendly name shown instead of the device id, e.g. "Balcony station"
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub calibration: CalibrationOffsets,
+    // Alerts raised from this device's readings are dropped until then
+    pub muted_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DeviceSettings {
+    pub fn is_muted(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.muted_until.is_some_and(|until| until > now)
+    }
+}
+
+// Added to the raw sensor values before they are stored or emitted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalibrationOffsets {
+    pub temperature: f64,
+    pub humidity: f64,
+    pub pressure: f64,
+}
+
+impl CalibrationOffsets {
+    pub fn is_zero(&self) -> bool {
+        self.temperature == 0.0 && self.humidity == 0.0 && self.pressure == 0.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttSettings {
+    pub broker_host: String,
+    pub broker_port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub client_id: String,
+    pub auto_connect: bool,
+    // Start automated weather publishing once the launch auto-connect succeeds
+    #[serde(default)]
+    pub auto_publish: bool,
+    // Named location to publish for at launch; None uses the active location
+    #[serde(default)]
+    pub auto_publish_location: Option<String>,
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+    // Disable to keep subscriptions and queued QoS1 messages across reconnects
+    #[serde(default = "default_clean_session")]
+    pub clean_session: bool,
+    // Start a fresh session if we were offline longer than this (0 = never expire)
+    #[serde(default)]
+    pub session_expiry_secs: u64,
+    // Retain weather/data so a freshly booted device gets the last snapshot immediately
+    #[serde(default = "default_retain_weather_data")]
+    pub retain_weather_data: bool,
+    // Subscribe to the data topics via $share/<group>/... so redundant instances split
+    // the incoming load; device status, telemetry and acks reach every instance
+    #[serde(default)]
+    pub shared_subscription_group: Option<String>,
+    // Devices without a heartbeat for this long are reported offline
+    #[serde(default = "default_device_offline_timeout_secs")]
+    pub device_offline_timeout_secs: u64,
+    #[serde(default = "default_low_battery_threshold_percent")]
+    pub low_battery_threshold_percent: f64,
+    // 0 disables the periodic time sync
+    #[serde(default = "default_time_sync_interval_secs")]
+    pub time_sync_interval_secs: u64,
+    #[serde(default = "default_time_sync_topic")]
+    pub time_sync_topic: String,
+    // Air quality alert thresholds for devices with a TVOC/eCO2 unit
+    #[serde(default = "default_co2_alert_threshold_ppm")]
+    pub co2_alert_threshold_ppm: f64,
+    #[serde(default = "default_tvoc_alert_threshold_ppb")]
+    pub tvoc_alert_threshold_ppb: f64,
+    // Sensor feed is considered stale after this many minutes without data (0 disables)
+    #[serde(default = "default_sensor_stale_after_minutes")]
+    pub sensor_stale_after_minutes: u64,
+    #[serde(default)]
+    pub alert_on_stale_sensor: bool,
+    // Send dew point, heat index and humidex back to the device after each reading
+    #[serde(default)]
+    pub publish_comfort_metrics: bool,
+    #[serde(default)]
+    pub local_forecast: LocalForecastSettings,
+    // Follow GPS coordinates reported in device telemetry for weather fetching
+    #[serde(default = "default_follow_device_gps")]
+    pub follow_device_gps: bool,
+    #[serde(default = "default_gps_min_distance_km")]
+    pub gps_min_distance_km: f64,
+    // Forward active warning/emergency weather alerts from the provider to weather/alert_trigger
+    #[serde(default = "default_forward_weather_alerts")]
+    pub forward_weather_alerts: bool,
+    // Provider icon code -> device icon name, applied before publishing; empty publishes raw codes
+    #[serde(default = "default_icon_map")]
+    pub icon_map: BTreeMap<String, String>,
+    // Reshape weather/data and alert payloads for firmware expecting other field names
+    #[serde(default)]
+    pub payload_templates: PayloadTemplateSettings,
+    #[serde(default = "default_publish_interval_secs")]
+    pub publish_interval_secs: u64,
+    // Publish only changed fields to weather/data/delta between full snapshots
+    #[serde(default)]
+    pub delta_publishing: bool,
+    #[serde(default = "default_full_snapshot_interval_minutes")]
+    pub full_snapshot_interval_minutes: u64,
+    // Publish each field as its own retained topic (weather/current/temp, weather/forecast/0/temp)
+    #[serde(default)]
+    pub flat_topics: bool,
+    #[serde(default)]
+    pub battery_saver: BatterySaverSettings,
+    #[serde(default)]
+    pub alert_outputs: AlertOutputSettings,
+    // Device button ("A", "B", "C") -> action performed by the app
+    #[serde(default = "default_button_actions")]
+    pub button_actions: HashMap<String, ButtonAction>,
+    #[serde(default)]
+    pub proxy: ProxySettings,
+    #[serde(default)]
+    pub uplink: UplinkSettings,
+    #[serde(default)]
+    pub influxdb: InfluxSettings,
+    #[serde(default)]
+    pub csv_log: CsvLogSettings,
+    #[serde(default)]
+    pub wunderground: WundergroundSettings,
+    #[serde(default)]
+    pub opensensemap: OpenSenseMapSettings,
+    #[serde(default)]
+    pub anomaly_detection: AnomalySettings,
+    #[serde(default)]
+    pub smoothing: SmoothingSettings,
+    #[serde(default)]
+    pub coordination: CoordinationSettings,
+}
+
+// Handlebars templates rendered with the payload's standard fields, e.g.
+// {"temp": {{round current_temp 1}}, "cond": {{json condition}}}; empty sends the standard payload
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PayloadTemplateSettings {
+    pub weather: String,
+    pub alert: String,
+}
+
+// Zambretti forecast computed from the station's own pressure readings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalForecastSettings {
+    // Used to reduce the sensor's station pressure to sea level
+    pub station_altitude_m: f64,
+    // Publish to weather/local_forecast (or the device's own topic) when the forecast changes
+    pub publish: bool,
+}
+
+// Rate limits for incoming sensor readings; faster changes are treated as glitches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalySettings {
+    pub enabled: bool,
+    pub max_temperature_change_per_min: f64,
+    pub max_humidity_change_per_min: f64,
+    pub max_pressure_change_per_min: f64,
+    // After this many anomalies in a row the new values are accepted as real
+    pub max_consecutive_anomalies: u32,
+}
+
+impl Default for AnomalySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_temperature_change_per_min: 5.0,
+            max_humidity_change_per_min: 20.0,
+            max_pressure_change_per_min: 10.0,
+            max_consecutive_anomalies: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmoothingMethod {
+    // Mean of the last `window` readings
+    MovingAverage,
+    // Each reading moves the value `alpha` of the way towards it
+    #[default]
+    Exponential,
+}
+
+// Evens out sensor noise before readings are stored, shown or checked against alert
+// rules; the reported values are kept in each reading's raw values for export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmoothingSettings {
+    pub enabled: bool,
+    pub method: SmoothingMethod,
+    pub window: usize,
+    // 0-1; higher follows changes faster
+    pub alpha: f64,
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: SmoothingMethod::default(),
+            window: 5,
+            alpha: 0.3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceRole {
+    // Publishes while no other instance holds the claim, taking over when it lapses
+    #[default]
+    Auto,
+    // Always publishes; automatic instances defer to it. Two of these both publish.
+    Publisher,
+    // Never publishes weather, only shows what the others send
+    Viewer,
+}
+
+// Several desktops on one broker: the publishing instance keeps a retained claim on
+// `topic`, and the others hold back their weather publishing until it lapses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoordinationSettings {
+    pub enabled: bool,
+    pub role: InstanceRole,
+    pub topic: String,
+}
+
+impl Default for CoordinationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            role: InstanceRole::default(),
+            topic: "weather/instances/publisher".to_string(),
+        }
+    }
+}
+
+// Stretches the publish interval and trims payloads while a device runs low on battery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatterySaverSettings {
+    pub enabled: bool,
+    pub battery_threshold_percent: f64,
+    pub publish_interval_secs: u64,
+    // Drop history and trim the forecast while saving power
+    pub reduce_payload: bool,
+    pub reduced_forecast_days: usize,
+}
+
+impl Default for BatterySaverSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            battery_threshold_percent: 30.0,
+            publish_interval_secs: 60,
+            reduce_payload: true,
+            reduced_forecast_days: 3,
+        }
+    }
+}
+
+// LED bar and speaker behaviour on the M5Go for each alert level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertOutputSettings {
+    pub info: AlertOutput,
+    pub warning: AlertOutput,
+    pub emergency: AlertOutput,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertOutput {
+    pub enabled: bool,
+    // Hex color, e.g. "#FF0000"
+    pub led_color: String,
+    // "solid", "blink" or "pulse"
+    pub led_pattern: String,
+    pub blink_interval_ms: u32,
+    pub led_duration_secs: u32,
+    // 0 keeps the speaker silent
+    pub tone_hz: u32,
+    pub tone_duration_ms: u32,
+    pub tone_repeat: u32,
+}
+
+impl AlertOutputSettings {
+    pub fn for_level(&self, level: &AlertLevel) -> &AlertOutput {
+        match level {
+            AlertLevel::Info => &self.info,
+            AlertLevel::Warning => &self.warning,
+            AlertLevel::Emergency => &self.emergency,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonAction {
+    None,
+    RefreshWeather,
+    SendTestAlert,
+    PublishSnapshot,
+    // Acknowledges the newest unacknowledged alert, for firmware that doesn't ack by id
+    AcknowledgeAlert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyType {
+    Http,
+    Socks5,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxySettings {
+    pub enabled: bool,
+    pub proxy_type: ProxyType,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+// Generated once and saved with the config, so the broker sees the same client across
+// restarts (persistent sessions, per-client ACLs)
+pub fn new_client_id() -> String {
+    format!("weather-desktop-{}", uuid::Uuid::new_v4().simple())
+}
+
+fn default_keep_alive_secs() -> u64 {
+    60
+}
+
+fn default_clean_session() -> bool {
+    true
+}
+
+fn default_retain_weather_data() -> bool {
+    true
+}
+
+fn default_device_offline_timeout_secs() -> u64 {
+    120
+}
+
+fn default_low_battery_threshold_percent() -> f64 {
+    20.0
+}
+
+fn default_time_sync_interval_secs() -> u64 {
+    3600
+}
+
+fn default_time_sync_topic() -> String {
+    "weather/time".to_string()
+}
+
+// Free One Call tier allows 1000 calls/day, keep some headroom
+fn default_daily_call_budget() -> u32 {
+    900
+}
+
+fn default_min_fetch_interval_secs() -> u64 {
+    30
+}
+
+fn default_fallback_provider() -> Option<WeatherProviderType> {
+    Some(WeatherProviderType::OpenMeteo)
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_read_timeout_secs() -> u64 {
+    30
+}
+
+fn default_forecast_days() -> usize {
+    6
+}
+
+fn default_hourly_forecast_hours() -> usize {
+    24
+}
+
+fn default_publish_interval_secs() -> u64 {
+    5
+}
+
+fn default_full_snapshot_interval_minutes() -> u64 {
+    10
+}
+
+fn default_sensor_stale_after_minutes() -> u64 {
+    5
+}
+
+fn default_follow_device_gps() -> bool {
+    true
+}
+
+fn default_forward_weather_alerts() -> bool {
+    true
+}
+
+fn default_gps_min_distance_km() -> f64 {
+    1.0
+}
+
+fn default_button_actions() -> HashMap<String, ButtonAction> {
+    HashMap::from([
+        ("A".to_string(), ButtonAction::RefreshWeather),
+        ("B".to_string(), ButtonAction::SendTestAlert),
+        ("C".to_string(), ButtonAction::None),
+    ])
+}
+
+fn default_co2_alert_threshold_ppm() -> f64 {
+    1500.0
+}
+
+fn default_tvoc_alert_threshold_ppb() -> f64 {
+    660.0
+}
+
+// Secondary "uplink" connection that mirrors local traffic to a cloud broker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UplinkSettings {
+    pub enabled: bool,
+    pub broker_host: String,
+    pub broker_port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub use_tls: bool,
+    pub ca_cert_path: Option<String>,
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+    // Prefix prepended to local topics that have no explicit mapping
+    pub topic_prefix: String,
+    // Local topic -> cloud topic (e.g. "weather/sensor_data" -> "devices/m5go/messages/events/")
+    pub topic_map: HashMap<String, String>,
+    pub mirror_sensor_data: bool,
+    pub mirror_alerts: bool,
+}
+
+// Optional sink that writes each sensor reading to InfluxDB v2
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InfluxSettings {
+    pub enabled: bool,
+    // Base URL, e.g. "http://localhost:8086"
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    pub token: String,
+    pub measurement: String,
+    // Points are written once this many are queued or every flush_interval_secs
+    pub batch_size: usize,
+    pub flush_interval_secs: u64,
+    pub max_retries: u32,
+    pub timeout_secs: u64,
+}
+
+// Appends each reading, and optionally each weather report, to one CSV file per day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvLogSettings {
+    pub enabled: bool,
+    // Defaults to a "csv" folder in the app data directory
+    pub directory: Option<String>,
+    pub include_weather: bool,
+}
+
+// Uploads the latest reading to Weather Underground as a personal weather station
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WundergroundSettings {
+    pub enabled: bool,
+    // Station ID and key from the Weather Underground "My Devices" page
+    pub station_id: String,
+    pub station_key: String,
+    // At most one upload per interval; 60 at the least
+    pub interval_secs: u64,
+    // Device whose readings are uploaded; any device when unset
+    pub device_id: Option<String>,
+}
+
+// Posts readings to a senseBox on openSenseMap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenSenseMapSettings {
+    pub enabled: bool,
+    pub box_id: String,
+    // The box's access token, sent as the Authorization header
+    pub api_key: String,
+    pub interval_secs: u64,
+    // Device whose readings are uploaded; any device when unset
+    pub device_id: Option<String>,
+    pub sensors: OpenSenseMapSensors,
+}
+
+// openSenseMap sensor id for each value; values without one aren't uploaded
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenSenseMapSensors {
+    pub temperature: String,
+    pub humidity: String,
+    // Sent in hPa
+    pub pressure: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherApiSettings {
+    pub api_key: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub auto_fetch_interval_minutes: u32,
+    // Human-readable name for latitude/longitude, e.g. from detect_location
+    #[serde(default)]
+    pub location_name: Option<String>,
+    #[serde(default)]
+    pub provider: WeatherProviderType,
+    // Used when the primary provider fails; None disables the fallback
+    #[serde(default = "default_fallback_provider")]
+    pub fallback_provider: Option<WeatherProviderType>,
+    // Named places to switch between, e.g. "Home" and "Parents"
+    #[serde(default)]
+    pub locations: Vec<NamedLocation>,
+    // Name of the location used by the automated publisher; latitude/longitude when unset
+    #[serde(default)]
+    pub active_location: Option<String>,
+    #[serde(default)]
+    pub units: UnitSystem,
+    #[serde(default)]
+    pub locale: Locale,
+    #[serde(default)]
+    pub retry: HttpRetrySettings,
+    #[serde(default)]
+    pub severe_weather: SevereWeatherSettings,
+    #[serde(default)]
+    pub forecast_warnings: ForecastWarningSettings,
+    #[serde(default)]
+    pub rain_alerts: RainAlertSettings,
+    #[serde(default)]
+    pub open_meteo: OpenMeteoSettings,
+    #[serde(default)]
+    pub icon_pack: IconPackSettings,
+    #[serde(default)]
+    pub auto_location: AutoLocationSettings,
+    // Applied to the HTTP client; a hung connection or stalled response fails instead of blocking
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    // OpenWeatherMap calls allowed per day before further calls are refused (0 = unlimited)
+    #[serde(default = "default_daily_call_budget")]
+    pub daily_call_budget: u32,
+    // Fetches requested by the UI for the same place within this many seconds return the
+    // previous result instead of calling the provider again (0 only merges concurrent ones)
+    #[serde(default = "default_min_fetch_interval_secs")]
+    pub min_fetch_interval_secs: u64,
+    // Include an hourly forecast in WeatherData (the M5Go display may only want daily)
+    #[serde(default)]
+    pub include_hourly: bool,
+    #[serde(default = "default_hourly_forecast_hours")]
+    pub hourly_forecast_hours: usize,
+    // Daily forecast entries including today, 1-8
+    #[serde(default = "default_forecast_days")]
+    pub forecast_days: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenMeteoSettings {
+    pub model: OpenMeteoModel,
+    pub variables: OpenMeteoVariableSet,
+}
+
+// Forecast model; best_match lets Open-Meteo pick per region
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenMeteoModel {
+    #[default]
+    BestMatch,
+    IconSeamless,
+    GfsSeamless,
+    EcmwfIfs025,
+}
+
+impl OpenMeteoModel {
+    pub fn as_param(self) -> &'static str {
+        match self {
+            OpenMeteoModel::BestMatch => "best_match",
+            OpenMeteoModel::IconSeamless => "icon_seamless",
+            OpenMeteoModel::GfsSeamless => "gfs_seamless",
+            OpenMeteoModel::EcmwfIfs025 => "ecmwf_ifs025",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenMeteoVariableSet {
+    // Everything WeatherData can hold, including hourly, UV and visibility
+    #[default]
+    Full,
+    // Current conditions and daily values only; not every model provides the rest
+    Compact,
+}
+
+// Polls for official warnings even when automated publishing is off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SevereWeatherSettings {
+    // Off by default: each poll costs a call, and only some providers report warnings
+    pub enabled: bool,
+    // Each poll is a fresh API call, keep this well inside the daily budget
+    pub poll_interval_minutes: u32,
+}
+
+impl Default for SevereWeatherSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_minutes: 30,
+        }
+    }
+}
+
+// Alerts raised from the cached daily forecast once each morning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForecastWarningSettings {
+    pub enabled: bool,
+    // Local time of the daily check, HH:MM
+    pub check_time: String,
+    // Tonight's low at or below the threshold
+    pub frost: ForecastWarningRule,
+    // Today's or tomorrow's high at or above the threshold
+    pub heat: ForecastWarningRule,
+}
+
+impl Default for ForecastWarningSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_time: "07:00".to_string(),
+            frost: ForecastWarningRule {
+                enabled: true,
+                threshold_c: 0.0,
+                level: AlertLevel::Warning,
+            },
+            heat: ForecastWarningRule {
+                enabled: true,
+                threshold_c: 35.0,
+                level: AlertLevel::Warning,
+            },
+        }
+    }
+}
+
+// "Rain starting in X minutes" from the precipitation nowcast, once per rain event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RainAlertSettings {
+    pub enabled: bool,
+    // Each poll is a fresh API call
+    pub poll_interval_minutes: u32,
+    // Alert when rain is due within this many minutes
+    pub lead_time_minutes: u32,
+    // Lighter rates count as dry
+    pub min_intensity_mm_h: f64,
+    pub level: AlertLevel,
+}
+
+impl Default for RainAlertSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_minutes: 10,
+            lead_time_minutes: 30,
+            min_intensity_mm_h: 0.2,
+            level: AlertLevel::Info,
+        }
+    }
+}
+
+// Switches the active location to the nearest saved one when this machine's IP
+// geolocation moves, e.g. a laptop station taken between home and the allotment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoLocationSettings {
+    pub enabled: bool,
+    // Each check is one IP geolocation lookup
+    pub check_interval_minutes: u32,
+    // Smaller moves are ignored, and a saved location must be within this distance to be picked
+    pub switch_distance_km: f64,
+}
+
+impl Default for AutoLocationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            check_interval_minutes: 15,
+            switch_distance_km: 10.0,
+        }
+    }
+}
+
+// Where weather icons are downloaded from; each one is fetched once and kept in the
+// data dir, so the UI and reports work offline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IconPackSettings {
+    // {code} is replaced with the OpenWeatherMap icon code, e.g. "10d"
+    pub url_template: String,
+    // Download every icon at startup rather than on first use
+    pub prefetch: bool,
+}
+
+impl Default for IconPackSettings {
+    fn default() -> Self {
+        Self {
+            url_template: "https://openweathermap.org/img/wn/{code}@2x.png".to_string(),
+            prefetch: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastWarningRule {
+    pub enabled: bool,
+    // Always in °C, whatever the display units
+    pub threshold_c: f64,
+    pub level: AlertLevel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedLocation {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// Retries weather API requests that fail with a timeout, connection error, 429 or 5xx
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpRetrySettings {
+    // Total attempts including the first request
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub request_timeout_secs: u64,
+}
+
+impl Default for HttpRetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+            request_timeout_secs: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherProviderType {
+    #[default]
+    OpenWeatherMap,
+    // Free, no API key required
+    OpenMeteo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppSettings {
+    pub auto_refresh_data: bool,
+    pub desktop_notifications: bool,
+    pub dark_mode: bool,
+    pub data_refresh_interval_seconds: u32,
+    // Start with the OS; a launch at login connects and resumes publishing straight away
+    #[serde(default)]
+    pub launch_at_login: bool,
+    // Closing the window hides it to the tray and keeps MQTT and publishing running
+    #[serde(default)]
+    pub run_in_background: bool,
+    #[serde(default)]
+    pub storage: StorageSettings,
+    #[serde(default)]
+    pub daily_summary: DailySummarySettings,
+    #[serde(default)]
+    pub sun_automation: SunAutomationSettings,
+    #[serde(default)]
+    pub grafana: GrafanaSettings,
+    #[serde(default)]
+    pub rest_api: RestApiSettings,
+    #[serde(default)]
+    pub grpc: GrpcSettings,
+    #[serde(default)]
+    pub ble: BleSettings,
+    // Alerts are POSTed to each enabled webhook
+    #[serde(default)]
+    pub webhooks: Vec<WebhookSettings>,
+    #[serde(default)]
+    pub email: EmailSettings,
+    #[serde(default)]
+    pub telegram: TelegramSettings,
+    #[serde(default)]
+    pub discord: DiscordSettings,
+    #[serde(default)]
+    pub triggers: TriggerSettings,
+    #[serde(default)]
+    pub quiet_hours: QuietHoursSettings,
+    #[serde(default)]
+    pub alert_routing: AlertRoutingSettings,
+    #[serde(default)]
+    pub sensor_events: SensorEventSettings,
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    #[serde(default)]
+    pub scripting: ScriptingSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuietHoursMode {
+    // Drop Info and Warning notifications
+    Suppress,
+    // Hold them and deliver when quiet hours end
+    Queue,
+}
+
+// Local time window in which Info and Warning notifications are held back on every
+// channel; Emergency alerts always go through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuietHoursSettings {
+    pub enabled: bool,
+    // "HH:MM"; the window may wrap past midnight
+    pub start: String,
+    pub end: String,
+    pub mode: QuietHoursMode,
+}
+
+impl Default for QuietHoursSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            mode: QuietHoursMode::Queue,
+        }
+    }
+}
+
+// Where and how alerts of each level are delivered, for every alert the app raises
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertRoutingSettings {
+    pub info: AlertRoute,
+    pub warning: AlertRoute,
+    pub emergency: AlertRoute,
+}
+
+impl AlertRoutingSettings {
+    pub fn for_level(&self, level: &AlertLevel) -> &AlertRoute {
+        match level {
+            AlertLevel::Info => &self.info,
+            AlertLevel::Warning => &self.warning,
+            AlertLevel::Emergency => &self.emergency,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertRoute {
+    // MQTT topic the alert is published on; empty uses weather/alert_trigger
+    pub topic: String,
+    pub qos: AlertQos,
+    pub retain: bool,
+    // Desktop notifications at this level
+    pub desktop: bool,
+    pub webhooks: bool,
+    // Registered data sinks other than webhooks that take alerts
+    pub sinks: bool,
+    pub email: bool,
+    // Telegram and Discord
+    pub chat: bool,
+    // Play the level's alert tone on the M5Go; the LED bar is signalled either way
+    pub buzz: bool,
+}
+
+impl Default for AlertRoute {
+    fn default() -> Self {
+        Self {
+            topic: String::new(),
+            qos: AlertQos::AtLeastOnce,
+            retain: false,
+            desktop: true,
+            webhooks: true,
+            sinks: true,
+            email: true,
+            chat: true,
+            buzz: true,
+        }
+    }
+}
+
+// QoS 2 isn't offered: delivery tracking follows PubAcks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertQos {
+    AtMostOnce,
+    #[default]
+    AtLeastOnce,
+}
+
+// IFTTT Webhooks and ntfy.sh pushes fired on selected events. IFTTT gets value1-3:
+// alert_fired sends level, message and time; device_offline sends name, id and last
+// seen; daily_summary sends date, summary text and alert count.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerSettings {
+    pub ifttt: IftttSettings,
+    pub ntfy: NtfySettings,
+    pub alert_fired: TriggerEventSettings,
+    // Alerts below this level don't fire alert_fired
+    pub alert_min_level: AlertLevel,
+    pub device_offline: TriggerEventSettings,
+    pub daily_summary: TriggerEventSettings,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IftttSettings {
+    pub enabled: bool,
+    // From the Webhooks service settings page
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NtfySettings {
+    pub enabled: bool,
+    pub server: String,
+    pub topic: String,
+    // Access token for protected topics
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerEventSettings {
+    pub enabled: bool,
+    // Event name in the IFTTT trigger URL
+    pub ifttt_event: String,
+    // ntfy priority, 1 (min) to 5 (max)
+    pub ntfy_priority: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelegramSettings {
+    pub enabled: bool,
+    // From @BotFather
+    pub bot_token: String,
+    // User, group or channel the bot posts to
+    pub chat_id: String,
+    // Alerts below this level are not sent
+    pub min_level: AlertLevel,
+}
+
+impl Default for TriggerSettings {
+    fn default() -> Self {
+        Self {
+            ifttt: IftttSettings::default(),
+            ntfy: NtfySettings::default(),
+            alert_fired: TriggerEventSettings::named("weather_station_alert", 4),
+            alert_min_level: AlertLevel::Warning,
+            device_offline: TriggerEventSettings::named("weather_station_offline", 4),
+            daily_summary: TriggerEventSettings::named("weather_station_summary", 2),
+        }
+    }
+}
+
+impl Default for NtfySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            server: "https://ntfy.sh".to_string(),
+            topic: String::new(),
+            token: String::new(),
+        }
+    }
+}
+
+impl TriggerEventSettings {
+    fn named(ifttt_event: &str, ntfy_priority: u8) -> Self {
+        Self {
+            enabled: false,
+            ifttt_event: ifttt_event.to_string(),
+            ntfy_priority,
+        }
+    }
+}
+
+impl Default for TriggerEventSettings {
+    fn default() -> Self {
+        Self::named("", 3)
+    }
+}
+
+impl Default for TelegramSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bot_token: String::new(),
+            chat_id: String::new(),
+            min_level: AlertLevel::Warning,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscordSettings {
+    pub enabled: bool,
+    pub webhook_url: String,
+    // Alerts below this level are not sent
+    pub min_level: AlertLevel,
+}
+
+impl Default for DiscordSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            webhook_url: String::new(),
+            min_level: AlertLevel::Warning,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    // Plain connection; only for local relays
+    None,
+    StartTls,
+    // Implicit TLS, usually port 465
+    Tls,
+}
+
+// SMTP channel for emergency alerts and the daily summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Sender address, e.g. "Weather Station <station@example.com>"
+    pub from: String,
+    pub recipients: Vec<String>,
+    // Alerts below this level are not emailed
+    pub min_level: AlertLevel,
+    pub send_daily_summary: bool,
+    pub timeout_secs: u64,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: 587,
+            security: SmtpSecurity::StartTls,
+            username: None,
+            password: None,
+            from: String::new(),
+            recipients: Vec::new(),
+            min_level: AlertLevel::Emergency,
+            send_daily_summary: true,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    pub name: String,
+    pub enabled: bool,
+    pub url: String,
+    // Extra request headers, e.g. Authorization or ntfy's Title
+    pub headers: BTreeMap<String, String>,
+    // Request body with {{message}}, {{level}}, {{source}}, {{direction}} and {{timestamp}}
+    // placeholders, substituted JSON-escaped. None sends the alert as JSON.
+    pub body_template: Option<String>,
+    // Alerts below this level are not sent
+    pub min_level: AlertLevel,
+    // Also forward alerts other clients published on weather/alert_trigger
+    pub include_received: bool,
+    pub max_retries: u32,
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            url: String::new(),
+            headers: BTreeMap::new(),
+            body_template: None,
+            min_level: AlertLevel::Info,
+            include_received: false,
+            max_retries: 3,
+            timeout_secs: 10,
+        }
+    }
+}
+
+// Read-only station data plus POST /alert for scripts and other machines on the LAN.
+// Every request needs "Authorization: Bearer <token>".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestApiSettings {
+    pub enabled: bool,
+    // 0.0.0.0 to accept requests from the LAN
+    pub bind_address: String,
+    pub port: u16,
+    pub token: String,
+}
+
+impl Default for RestApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 3004,
+            token: String::new(),
+        }
+    }
+}
+
+// gRPC service defined in proto/weather_station.proto. Every call needs
+// "authorization: Bearer <token>" metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcSettings {
+    pub enabled: bool,
+    // 0.0.0.0 to accept calls from the LAN
+    pub bind_address: String,
+    pub port: u16,
+    pub token: String,
+}
+
+impl Default for GrpcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 50051,
+            token: String::new(),
+        }
+    }
+}
+
+// Reads the M5Go over Bluetooth LE instead of, or as well as, the broker
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BleSettings {
+    pub enabled: bool,
+    // Connects to the first device whose advertised name starts with this
+    pub device_name: String,
+    pub service_uuid: String,
+    // Notifies with the sensor JSON
+    pub characteristic_uuid: String,
+    // For readings that don't carry one; defaults to the advertised name
+    pub device_id: Option<String>,
+    pub scan_timeout_secs: u64,
+}
+
+impl Default for BleSettings {
+    fn default() -> Self {
+        // Nordic UART service, which the M5Go firmware uses for its BLE output
+        Self {
+            enabled: false,
+            device_name: "M5Go".to_string(),
+            service_uuid: "6e400001-b5a3-f393-e0a9-e50e24dcca9e".to_string(),
+            characteristic_uuid: "6e400003-b5a3-f393-e0a9-e50e24dcca9e".to_string(),
+            device_id: None,
+            scan_timeout_secs: 15,
+        }
+    }
+}
+
+// HTTP endpoint implementing the Grafana SimpleJSON datasource contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrafanaSettings {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl Default for GrafanaSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 3003,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppConfig {
-    pub mqtt: MqttSettings,
-    pub weather_api: WeatherApiSettings,
-    pub app: AppSettings,
+#[serde(default)]
+pub struct DailySummarySettings {
+    pub enabled: bool,
+    // Local time the day's summary is generated, "HH:MM"
+    pub time: String,
+}
+
+impl Default for DailySummarySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            time: "21:00".to_string(),
+        }
+    }
 }
 
+// Actions run at sunrise and sunset, using the times from the latest weather report
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MqttSettings {
-    pub broker_host: String,
-    pub broker_port: u16,
-    pub username: Option<String>,
-    pub password: Option<String>,
-    pub client_id: String,
-    pub auto_connect: bool,
+#[serde(default)]
+pub struct SunAutomationSettings {
+    pub enabled: bool,
+    // Added to each event; negative runs the actions earlier
+    pub sunrise_offset_minutes: i32,
+    pub sunset_offset_minutes: i32,
+    // Publishes {"enabled": true} to weather/output/night_mode at sunset and false at sunrise
+    pub device_night_mode: bool,
+    // Dark theme from sunset, light from sunrise
+    pub toggle_dark_mode: bool,
+    // Publish interval between sunset and sunrise; 0 keeps the configured one
+    pub night_publish_interval_secs: u64,
+}
+
+impl Default for SunAutomationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sunrise_offset_minutes: 0,
+            sunset_offset_minutes: 0,
+            device_night_mode: true,
+            toggle_dark_mode: true,
+            night_publish_interval_secs: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorEventMode {
+    // Newest reading of each window as "sensor-data-updated"
+    Latest,
+    // Every reading of the window as one "sensor-data-batch" array
+    Batch,
 }
 
+// Coalesces live sensor events so a fast publishing device doesn't flood the webview
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WeatherApiSettings {
-    pub api_key: String,
-    pub latitude: f64,
-    pub longitude: f64,
-    pub auto_fetch_interval_minutes: u32,
+#[serde(default)]
+pub struct SensorEventSettings {
+    // At most one event per window; 0 forwards every message as it arrives
+    pub interval_ms: u64,
+    pub mode: SensorEventMode,
+}
+
+impl Default for SensorEventSettings {
+    fn default() -> Self {
+        Self {
+            interval_ms: 250,
+            mode: SensorEventMode::Latest,
+        }
+    }
+}
+
+// Rhai scripts in the data dir's scripts folder, run on sensor, weather and alert events
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptingSettings {
+    pub enabled: bool,
+    // Per hook call; stops runaway loops
+    pub max_operations: u64,
+}
+
+impl Default for ScriptingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_operations: 100_000,
+        }
+    }
+}
+
+// Exports spans for the publish path, provider fetches and commands to an OpenTelemetry
+// collector; logs are written either way
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TracingSettings {
+    pub otlp_enabled: bool,
+    // OTLP over HTTP, e.g. a local collector or Jaeger
+    pub otlp_endpoint: String,
+    pub service_name: String,
+    // Debug builds only: payloads printed to the console keep their secrets. Logs are
+    // masked regardless.
+    pub reveal_raw_payloads: bool,
+}
+
+impl Default for TracingSettings {
+    fn default() -> Self {
+        Self {
+            otlp_enabled: false,
+            otlp_endpoint: "http://localhost:4318/v1/traces".to_string(),
+            service_name: "weather-station-desktop".to_string(),
+            reveal_raw_payloads: false,
+        }
+    }
 }
 
+// Limits for the files the app keeps in its data directory
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppSettings {
-    pub auto_refresh_data: bool,
-    pub desktop_notifications: bool,
-    pub dark_mode: bool,
-    pub data_refresh_interval_seconds: u32,
+#[serde(default)]
+pub struct StorageSettings {
+    pub max_cache_age_days: u32,
+    // Per-location weather caches to keep, newest first
+    pub max_cache_files: usize,
+    pub max_debug_file_kb: u64,
+    // Previous versions of config.toml kept on save, 0 disables
+    pub config_backups: usize,
+    pub retention: RetentionSettings,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        Self {
+            max_cache_age_days: 7,
+            max_cache_files: 20,
+            max_debug_file_kb: 1024,
+            config_backups: 10,
+            retention: RetentionSettings::default(),
+        }
+    }
+}
+
+// How long the sensor history database keeps its rows. Raw readings are rolled up
+// into hourly aggregates after downsample_after_days and deleted after raw_days.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    pub enabled: bool,
+    // Raw readings older than this are rolled up into hourly min/max/avg rows
+    pub downsample_after_days: u32,
+    // Raw sensor readings and weather snapshots
+    pub raw_days: u32,
+    pub aggregate_days: u32,
+    pub prune_interval_hours: u32,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            downsample_after_days: 7,
+            raw_days: 90,
+            aggregate_days: 730,
+            prune_interval_hours: 24,
+        }
+    }
 }
 
 impl Default for AppConfig {
@@ -43,6 +1656,9 @@ impl Default for AppConfig {
             mqtt: MqttSettings::default(),
             weather_api: WeatherApiSettings::default(),
             app: AppSettings::default(),
+            devices: HashMap::new(),
+            alert_rules: Vec::new(),
+            scheduled_tasks: Vec::new(),
         }
     }
 }
@@ -54,19 +1670,221 @@ impl Default for MqttSettings {
             broker_port: 1883,
             username: None,
             password: None,
-            client_id: format!("weather-desktop-{}", chrono::Utc::now().timestamp()),
+            client_id: new_client_id(),
             auto_connect: true,
+            auto_publish: false,
+            auto_publish_location: None,
+            keep_alive_secs: default_keep_alive_secs(),
+            clean_session: default_clean_session(),
+            session_expiry_secs: 0,
+            retain_weather_data: default_retain_weather_data(),
+            shared_subscription_group: None,
+            device_offline_timeout_secs: default_device_offline_timeout_secs(),
+            low_battery_threshold_percent: default_low_battery_threshold_percent(),
+            time_sync_interval_secs: default_time_sync_interval_secs(),
+            time_sync_topic: default_time_sync_topic(),
+            co2_alert_threshold_ppm: default_co2_alert_threshold_ppm(),
+            tvoc_alert_threshold_ppb: default_tvoc_alert_threshold_ppb(),
+            sensor_stale_after_minutes: default_sensor_stale_after_minutes(),
+            alert_on_stale_sensor: false,
+            publish_comfort_metrics: false,
+            local_forecast: LocalForecastSettings::default(),
+            follow_device_gps: default_follow_device_gps(),
+            gps_min_distance_km: default_gps_min_distance_km(),
+            forward_weather_alerts: default_forward_weather_alerts(),
+            icon_map: default_icon_map(),
+            payload_templates: PayloadTemplateSettings::default(),
+            publish_interval_secs: default_publish_interval_secs(),
+            delta_publishing: false,
+            full_snapshot_interval_minutes: default_full_snapshot_interval_minutes(),
+            flat_topics: false,
+            battery_saver: BatterySaverSettings::default(),
+            alert_outputs: AlertOutputSettings::default(),
+            button_actions: default_button_actions(),
+            proxy: ProxySettings::default(),
+            uplink: UplinkSettings::default(),
+            influxdb: InfluxSettings::default(),
+            csv_log: CsvLogSettings::default(),
+            wunderground: WundergroundSettings::default(),
+            opensensemap: OpenSenseMapSettings::default(),
+            anomaly_detection: AnomalySettings::default(),
+            smoothing: SmoothingSettings::default(),
+            coordination: CoordinationSettings::default(),
+        }
+    }
+}
+
+impl Default for AlertOutputSettings {
+    fn default() -> Self {
+        Self {
+            info: AlertOutput {
+                enabled: false,
+                led_color: "#0080FF".to_string(),
+                led_pattern: "solid".to_string(),
+                led_duration_secs: 5,
+                ..AlertOutput::default()
+            },
+            warning: AlertOutput {
+                enabled: true,
+                led_color: "#FF8000".to_string(),
+                led_pattern: "blink".to_string(),
+                blink_interval_ms: 500,
+                led_duration_secs: 30,
+                ..AlertOutput::default()
+            },
+            emergency: AlertOutput {
+                enabled: true,
+                led_color: "#FF0000".to_string(),
+                led_pattern: "blink".to_string(),
+                blink_interval_ms: 200,
+                led_duration_secs: 60,
+                tone_hz: 2000,
+                tone_duration_ms: 300,
+                tone_repeat: 5,
+            },
+        }
+    }
+}
+
+impl Default for AlertOutput {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            led_color: "#FFFFFF".to_string(),
+            led_pattern: "solid".to_string(),
+            blink_interval_ms: 0,
+            led_duration_secs: 10,
+            tone_hz: 0,
+            tone_duration_ms: 0,
+            tone_repeat: 0,
+        }
+    }
+}
+
+impl Default for ProxySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            proxy_type: ProxyType::Http,
+            host: String::new(),
+            port: 8080,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl Default for UplinkSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            broker_host: String::new(),
+            broker_port: 8883,
+            client_id: format!("weather-desktop-uplink-{}", chrono::Utc::now().timestamp()),
+            username: None,
+            password: None,
+            use_tls: true,
+            ca_cert_path: None,
+            client_cert_path: None,
+            client_key_path: None,
+            topic_prefix: String::new(),
+            topic_map: HashMap::new(),
+            mirror_sensor_data: true,
+            mirror_alerts: true,
+        }
+    }
+}
+
+impl Default for InfluxSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "http://localhost:8086".to_string(),
+            org: String::new(),
+            bucket: "weather".to_string(),
+            token: String::new(),
+            measurement: "m5go".to_string(),
+            batch_size: 100,
+            flush_interval_secs: 10,
+            max_retries: 3,
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl Default for CsvLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            include_weather: true,
+        }
+    }
+}
+
+impl Default for WundergroundSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            station_id: String::new(),
+            station_key: String::new(),
+            interval_secs: 300,
+            device_id: None,
+        }
+    }
+}
+
+impl Default for OpenSenseMapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            box_id: String::new(),
+            api_key: String::new(),
+            interval_secs: 300,
+            device_id: None,
+            sensors: OpenSenseMapSensors::default(),
         }
     }
 }
 
+impl WeatherApiSettings {
+    // Coordinates of the active named location, falling back to latitude/longitude
+    pub fn active_coordinates(&self) -> (f64, f64) {
+        self.active_location.as_deref()
+            .and_then(|name| self.locations.iter().find(|l| l.name.eq_ignore_ascii_case(name)))
+            .map(|l| (l.latitude, l.longitude))
+            .unwrap_or((self.latitude, self.longitude))
+    }
+}
+
 impl Default for WeatherApiSettings {
     fn default() -> Self {
         Self {
-            api_key: "API_KEY_HERE".to_string(),
+            api_key: String::new(),
             latitude: 48.7758,
             longitude: 9.1829,
             auto_fetch_interval_minutes: 30,
+            location_name: None,
+            provider: WeatherProviderType::default(),
+            fallback_provider: default_fallback_provider(),
+            severe_weather: SevereWeatherSettings::default(),
+            forecast_warnings: ForecastWarningSettings::default(),
+            rain_alerts: RainAlertSettings::default(),
+            open_meteo: OpenMeteoSettings::default(),
+            icon_pack: IconPackSettings::default(),
+            auto_location: AutoLocationSettings::default(),
+            locations: Vec::new(),
+            active_location: None,
+            units: UnitSystem::default(),
+            locale: Locale::default(),
+            retry: HttpRetrySettings::default(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: default_read_timeout_secs(),
+            daily_call_budget: default_daily_call_budget(),
+            min_fetch_interval_secs: default_min_fetch_interval_secs(),
+            include_hourly: false,
+            hourly_forecast_hours: default_hourly_forecast_hours(),
+            forecast_days: default_forecast_days(),
         }
     }
 }
@@ -78,6 +1896,25 @@ impl Default for AppSettings {
             desktop_notifications: false,
             dark_mode: false,
             data_refresh_interval_seconds: 30,
+            launch_at_login: false,
+            run_in_background: false,
+            storage: StorageSettings::default(),
+            daily_summary: DailySummarySettings::default(),
+            sun_automation: SunAutomationSettings::default(),
+            grafana: GrafanaSettings::default(),
+            rest_api: RestApiSettings::default(),
+            grpc: GrpcSettings::default(),
+            ble: BleSettings::default(),
+            webhooks: Vec::new(),
+            email: EmailSettings::default(),
+            telegram: TelegramSettings::default(),
+            discord: DiscordSettings::default(),
+            triggers: TriggerSettings::default(),
+            quiet_hours: QuietHoursSettings::default(),
+            alert_routing: AlertRoutingSettings::default(),
+            sensor_events: SensorEventSettings::default(),
+            tracing: TracingSettings::default(),
+            scripting: ScriptingSettings::default(),
         }
     }
 }
@@ -85,77 +1922,353 @@ impl Default for AppSettings {
 pub struct ConfigManager {
     config_path: PathBuf,
     config: AppConfig,
+    writer: ConfigWriter,
+}
+
+struct PendingSave {
+    content: String,
+    keep_backups: usize,
+}
+
+// Writes config.toml in the background. A settings panel saving field by field
+// produces one write once it goes quiet, not one per field.
+#[derive(Clone)]
+pub struct ConfigWriter {
+    path: PathBuf,
+    pending: Arc<std::sync::Mutex<Option<PendingSave>>>,
+    saved: Arc<Notify>,
+}
+
+impl ConfigWriter {
+    fn spawn(path: PathBuf) -> Self {
+        let writer = Self {
+            path,
+            pending: Arc::new(std::sync::Mutex::new(None)),
+            saved: Arc::new(Notify::new()),
+        };
+        supervisor::supervise("config writer", {
+            let writer = writer.clone();
+            move || tokio::spawn(writer.clone().run())
+        });
+        writer
+    }
+
+    // True while a save is waiting out the debounce
+    pub fn is_pending(&self) -> bool {
+        self.pending.lock().unwrap().is_some()
+    }
+
+    fn schedule(&self, content: String, keep_backups: usize) {
+        *self.pending.lock().unwrap() = Some(PendingSave { content, keep_backups });
+        self.saved.notify_one();
+    }
+
+    async fn run(self) {
+        loop {
+            self.saved.notified().await;
+            while tokio::time::timeout(SAVE_DEBOUNCE, self.saved.notified()).await.is_ok() {}
+            if let Err(e) = self.flush().await {
+                error!("Failed to save config: {}", e);
+                app_error::report(AppErrorCode::ConfigSaveFailed, "config", format!("Saving settings failed: {}", e), true);
+            }
+        }
+    }
+
+    // Writes a pending save now; a failed one stays pending unless a newer save replaced it
+    pub async fn flush(&self) -> Result<()> {
+        let Some(save) = self.pending.lock().unwrap().take() else {
+            return Ok(());
+        };
+        if let Err(e) = backup_config(&self.path, &save.content, save.keep_backups).await {
+            warn!("Failed to back up config: {}", e);
+        }
+        if let Err(e) = write_atomic(&self.path, &save.content).await {
+            self.pending.lock().unwrap().get_or_insert(save);
+            return Err(e);
+        }
+        info!("Saved config to {:?}", self.path);
+        Ok(())
+    }
+
+    // For app exit, where the runtime can't be waited on. Skips the backup.
+    pub fn flush_blocking(&self) {
+        let Some(save) = self.pending.lock().unwrap().take() else {
+            return;
+        };
+        match write_atomic_blocking(&self.path, &save.content) {
+            Ok(_) => info!("Saved config to {:?} on exit", self.path),
+            Err(e) => error!("Failed to save config on exit: {}", e),
+        }
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    path.with_extension("toml.tmp")
+}
+
+// Writes a temp file next to the config and renames it over, so a crash mid-write
+// leaves either the old or the new file and never a truncated one
+async fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let temp = temp_path(path);
+    let mut file = fs::File::create(&temp).await?;
+    file.write_all(content.as_bytes()).await?;
+    file.sync_all().await?;
+    fs::rename(&temp, path).await?;
+    Ok(())
+}
+
+fn write_atomic_blocking(path: &Path, content: &str) -> Result<()> {
+    let temp = temp_path(path);
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+// Copies the file about to be overwritten into config_backups/, then drops the
+// oldest copies beyond `keep`
+async fn backup_config(path: &Path, new_content: &str, keep: usize) -> Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+    match fs::read_to_string(path).await {
+        Ok(current) if !same_config(&current, new_content) => {}
+        Ok(_) => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+
+    let dir = path.with_file_name(CONFIG_BACKUP_DIR);
+    fs::create_dir_all(&dir).await?;
+    let timestamp = chrono::Local::now().format(CONFIG_BACKUP_TIMESTAMP).to_string();
+    fs::copy(path, dir.join(format!("config-{}.toml", timestamp))).await?;
+    for backup in list_backups(path).await?.into_iter().skip(keep) {
+        fs::remove_file(&backup.path).await?;
+    }
+    Ok(())
+}
+
+// Compared parsed, since the HashMap fields serialize in no fixed order
+fn same_config(a: &str, b: &str) -> bool {
+    match (toml::from_str::<toml::Value>(a), toml::from_str::<toml::Value>(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// Newest first
+async fn list_backups(config_path: &Path) -> Result<Vec<ConfigBackup>> {
+    let dir = config_path.with_file_name(CONFIG_BACKUP_DIR);
+    let mut entries = match fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(timestamp) = name.strip_prefix("config-").and_then(|n| n.strip_suffix(".toml")) else {
+            continue;
+        };
+        backups.push(ConfigBackup {
+            timestamp: timestamp.to_string(),
+            path: entry.path().display().to_string(),
+            size_bytes: entry.metadata().await?.len(),
+        });
+    }
+    // The timestamp format sorts chronologically
+    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(backups)
 }
 
 impl ConfigManager {
-    pub fn new() -> Result<Self> {
-        let config_path = Self::get_config_path()?;
-        let config = Self::load_config(&config_path).unwrap_or_else(|e| {
+    pub async fn new() -> Result<Self> {
+        let config_path = Self::get_config_path().await?;
+        let config = Self::load_config(&config_path).await.unwrap_or_else(|e| {
             warn!("Failed to load config: {}, using defaults", e);
             AppConfig::default()
         });
+        // Loaded anyway so the settings UI can show and fix the problems
+        if let Err(e) = config_validation::check(&config) {
+            warn!("{}", e);
+        }
+        logging::apply_redaction(&config);
 
+        let writer = ConfigWriter::spawn(config_path.clone());
         Ok(Self {
             config_path,
             config,
+            writer,
         })
     }
 
-    fn get_config_path() -> Result<PathBuf> {
-        let config_dir = dirs::config_dir()
-            .ok_or_else(|| anyhow!("Could not find config directory"))?
-            .join("weather-station-desktop");
+    async fn get_config_path() -> Result<PathBuf> {
+        let config_dir = storage::config_dir()
+            .ok_or_else(|| anyhow!("Could not find config directory"))?;
 
         // Create config directory if it doesn't exist
-        if !config_dir.exists() {
-            fs::create_dir_all(&config_dir)?;
+        if !fs::try_exists(&config_dir).await? {
+            fs::create_dir_all(&config_dir).await?;
             info!("Created config directory: {:?}", config_dir);
         }
 
         Ok(config_dir.join("config.toml"))
     }
 
-    fn load_config(path: &PathBuf) -> Result<AppConfig> {
-        if !path.exists() {
+    async fn load_config(path: &PathBuf) -> Result<AppConfig> {
+        if !fs::try_exists(path).await? {
             info!("Config file not found at {:?}, using defaults", path);
             return Ok(AppConfig::default());
         }
 
-        let content = fs::read_to_string(path)?;
+        let content = fs::read_to_string(path).await?;
         let config: AppConfig = toml::from_str(&content)?;
         info!("Loaded config from {:?}", path);
         Ok(config)
     }
 
-    pub fn save_config(&self) -> Result<()> {
+    // Queues the config for the background writer; see ConfigWriter
+    pub async fn save_config(&self) -> Result<()> {
+        logging::apply_redaction(&self.config);
         let content = toml::to_string_pretty(&self.config)?;
-        fs::write(&self.config_path, content)?;
-        info!("Saved config to {:?}", self.config_path);
+        self.writer.schedule(content, self.config.app.storage.config_backups);
+        Ok(())
+    }
+
+    // Shared with the exit handler, which flushes a save still waiting on the debounce
+    pub fn writer(&self) -> ConfigWriter {
+        self.writer.clone()
+    }
+
+    // Newest first
+    pub async fn config_backups(&self) -> Result<Vec<ConfigBackup>> {
+        list_backups(&self.config_path).await
+    }
+
+    // The config being replaced is backed up too, so a restore can itself be undone
+    pub async fn restore_config_backup(&mut self, timestamp: &str) -> Result<()> {
+        let backup = self.config_backups().await?
+            .into_iter()
+            .find(|backup| backup.timestamp == timestamp)
+            .ok_or_else(|| anyhow!("No config backup from {}", timestamp))?;
+        let config = Self::load_config(&PathBuf::from(&backup.path)).await?;
+        self.update_config(config).await?;
+        info!("Restored config from backup {}", timestamp);
         Ok(())
     }
 
+    // Re-reads config.toml after it was edited outside the app. Returns the previous
+    // config, or None when the file matches what's loaded (e.g. our own save).
+    pub async fn reload(&mut self) -> Result<Option<AppConfig>> {
+        // The file is older than what's loaded until the pending save lands, and that
+        // save triggers another reload anyway
+        if self.writer.is_pending() {
+            debug!("Ignoring config file change while a save is pending");
+            return Ok(None);
+        }
+        let config = Self::load_config(&self.config_path).await?;
+        // Compared as values, since the HashMap fields serialize in no fixed order
+        if serde_json::to_value(&config)? == serde_json::to_value(&self.config)? {
+            return Ok(None);
+        }
+        config_validation::check(&config)?;
+        logging::apply_redaction(&config);
+        Ok(Some(std::mem::replace(&mut self.config, config)))
+    }
+
+    pub async fn regenerate_client_id(&mut self) -> Result<String> {
+        let client_id = new_client_id();
+        self.update_mqtt_settings(MqttSettings { client_id: client_id.clone(), ..self.config.mqtt.clone() }).await?;
+        info!("Generated new MQTT client id {}", client_id);
+        Ok(client_id)
+    }
+
+    // Sets one setting by dotted path, e.g. "app.dark_mode" or "app.webhooks.0.enabled",
+    // leaving everything else as stored
+    pub async fn update_field(&mut self, path: &str, value: serde_json::Value) -> Result<()> {
+        let mut document = serde_json::to_value(&self.config)?;
+        let mut target = &mut document;
+        for key in path.split('.') {
+            target = match target {
+                serde_json::Value::Object(object) => object.get_mut(key),
+                serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+                _ => None,
+            }
+            .ok_or_else(|| anyhow!("Unknown setting: {}", path))?;
+        }
+        *target = value;
+
+        let config: AppConfig = serde_path_to_error::deserialize(document)
+            .map_err(|e| anyhow!("Invalid value for {}: {}", path, e.inner()))?;
+        self.update_config(config).await
+    }
+
+    // Restores everything, or one of "mqtt", "weather_api" and "app", to the defaults.
+    // The previous file is kept as config.toml.<timestamp>.bak; returns its path.
+    pub async fn reset(&mut self, section: Option<&str>) -> Result<Option<PathBuf>> {
+        let mut config = self.config.clone();
+        match section {
+            None => config = AppConfig::default(),
+            Some("mqtt") => config.mqtt = MqttSettings::default(),
+            Some("weather_api") => config.weather_api = WeatherApiSettings::default(),
+            Some("app") => config.app = AppSettings::default(),
+            Some(other) => return Err(anyhow!("Unknown config section '{}', expected mqtt, weather_api or app", other)),
+        }
+        // The client id is this installation's identity on the broker, not a setting
+        config.mqtt.client_id = self.config.mqtt.client_id.clone();
+
+        // The archive should hold what was last saved, not what was last written
+        self.writer.flush().await?;
+        let archive = if fs::try_exists(&self.config_path).await? {
+            let file_name = format!("config.toml.{}.bak", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+            let archive = self.config_path.with_file_name(file_name);
+            fs::copy(&self.config_path, &archive).await?;
+            info!("Archived config to {:?}", archive);
+            Some(archive)
+        } else {
+            None
+        };
+        self.update_config(config).await?;
+        Ok(archive)
+    }
+
+    pub fn config_path(&self) -> &PathBuf {
+        &self.config_path
+    }
+
     pub fn get_config(&self) -> &AppConfig {
         &self.config
     }
 
-    pub fn update_config(&mut self, config: AppConfig) -> Result<()> {
+    // Rejects the config with a ConfigValidationError, leaving the current one in place
+    pub async fn update_config(&mut self, config: AppConfig) -> Result<()> {
+        config_validation::check(&config)?;
         self.config = config;
-        self.save_config()
+        self.save_config().await
+    }
+
+    pub async fn update_mqtt_settings(&mut self, mqtt: MqttSettings) -> Result<()> {
+        self.update_config(AppConfig { mqtt, ..self.config.clone() }).await
+    }
+
+    pub async fn update_weather_api_settings(&mut self, weather_api: WeatherApiSettings) -> Result<()> {
+        self.update_config(AppConfig { weather_api, ..self.config.clone() }).await
     }
 
-    pub fn update_mqtt_settings(&mut self, mqtt: MqttSettings) -> Result<()> {
-        self.config.mqtt = mqtt;
-        self.save_config()
+    pub async fn update_app_settings(&mut self, app: AppSettings) -> Result<()> {
+        self.update_config(AppConfig { app, ..self.config.clone() }).await
     }
 
-    pub fn update_weather_api_settings(&mut self, weather_api: WeatherApiSettings) -> Result<()> {
-        self.config.weather_api = weather_api;
-        self.save_config()
+    pub async fn update_device_settings(&mut self, device_id: String, settings: DeviceSettings) -> Result<()> {
+        self.config.devices.insert(device_id, settings);
+        self.save_config().await
     }
 
-    pub fn update_app_settings(&mut self, app: AppSettings) -> Result<()> {
-        self.config.app = app;
-        self.save_config()
+    // None unmutes
+    pub async fn set_device_muted_until(&mut self, device_id: &str, until: Option<chrono::DateTime<chrono::Utc>>) -> Result<()> {
+        self.config.devices.entry(device_id.to_string()).or_default().muted_until = until;
+        self.save_config().await
     }
 
     // Convenience getters
@@ -163,19 +2276,150 @@ impl ConfigManager {
         &self.config.mqtt
     }
 
+    pub fn weather_api_settings(&self) -> &WeatherApiSettings {
+        &self.config.weather_api
+    }
+
+    pub fn find_location(&self, name: &str) -> Option<&NamedLocation> {
+        self.config.weather_api.locations.iter().find(|l| l.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn active_coordinates(&self) -> (f64, f64) {
+        self.config.weather_api.active_coordinates()
+    }
+
+    // Explicit coordinates replace the active named location
+    pub async fn set_coordinates(&mut self, latitude: f64, longitude: f64) -> Result<()> {
+        let weather_api = WeatherApiSettings {
+            latitude,
+            longitude,
+            active_location: None,
+            ..self.config.weather_api.clone()
+        };
+        self.update_weather_api_settings(weather_api).await
+    }
+
+    pub async fn set_active_location(&mut self, name: Option<String>) -> Result<()> {
+        if let Some(name) = &name {
+            if self.find_location(name).is_none() {
+                return Err(anyhow!("Unknown location: {}", name));
+            }
+        }
+        self.config.weather_api.active_location = name;
+        self.save_config().await
+    }
+
+    pub async fn add_location(&mut self, location: NamedLocation) -> Result<()> {
+        let mut weather_api = self.config.weather_api.clone();
+        weather_api.locations.push(location);
+        self.update_weather_api_settings(weather_api).await
+    }
+
+    // Removing the active location falls back to latitude/longitude
+    pub async fn remove_location(&mut self, name: &str) -> Result<()> {
+        let mut weather_api = self.config.weather_api.clone();
+        let before = weather_api.locations.len();
+        weather_api.locations.retain(|l| !l.name.eq_ignore_ascii_case(name));
+        if weather_api.locations.len() == before {
+            return Err(anyhow!("Unknown location: {}", name));
+        }
+        if weather_api.active_location.as_deref().is_some_and(|active| active.eq_ignore_ascii_case(name)) {
+            weather_api.active_location = None;
+        }
+        self.update_weather_api_settings(weather_api).await
+    }
+
+    pub fn device_settings(&self) -> &HashMap<String, DeviceSettings> {
+        &self.config.devices
+    }
+
+    pub fn alert_rules(&self) -> &[AlertRule] {
+        &self.config.alert_rules
+    }
+
+    // Stores a new rule under the next free id
+    pub async fn add_alert_rule(&mut self, mut rule: AlertRule) -> Result<AlertRule> {
+        rule.validate()?;
+        rule.id = self.config.alert_rules.iter().map(|r| r.id).max().unwrap_or(0) + 1;
+        self.config.alert_rules.push(rule.clone());
+        self.save_config().await?;
+        Ok(rule)
+    }
+
+    pub async fn update_alert_rule(&mut self, rule: AlertRule) -> Result<AlertRule> {
+        rule.validate()?;
+        let existing = self.config.alert_rules.iter_mut()
+            .find(|r| r.id == rule.id)
+            .ok_or_else(|| anyhow!("Unknown alert rule: {}", rule.id))?;
+        *existing = rule.clone();
+        self.save_config().await?;
+        Ok(rule)
+    }
+
+    pub async fn delete_alert_rule(&mut self, id: u32) -> Result<()> {
+        let count = self.config.alert_rules.len();
+        self.config.alert_rules.retain(|r| r.id != id);
+        if self.config.alert_rules.len() == count {
+            return Err(anyhow!("Unknown alert rule: {}", id));
+        }
+        self.save_config().await
+    }
+
+    pub async fn set_alert_rule_enabled(&mut self, id: u32, enabled: bool) -> Result<AlertRule> {
+        let rule = self.config.alert_rules.iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| anyhow!("Unknown alert rule: {}", id))?;
+        rule.enabled = enabled;
+        let rule = rule.clone();
+        self.save_config().await?;
+        Ok(rule)
+    }
+
+    pub fn scheduled_tasks(&self) -> &[ScheduledTask] {
+        &self.config.scheduled_tasks
+    }
+
+    // Stores a new task under the next free id
+    pub async fn add_scheduled_task(&mut self, mut task: ScheduledTask) -> Result<ScheduledTask> {
+        task.validate()?;
+        task.id = self.config.scheduled_tasks.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+        self.config.scheduled_tasks.push(task.clone());
+        self.save_config().await?;
+        Ok(task)
+    }
+
+    pub async fn delete_scheduled_task(&mut self, id: u32) -> Result<()> {
+        let count = self.config.scheduled_tasks.len();
+        self.config.scheduled_tasks.retain(|t| t.id != id);
+        if self.config.scheduled_tasks.len() == count {
+            return Err(anyhow!("Unknown scheduled task: {}", id));
+        }
+        self.save_config().await
+    }
+
     pub fn should_auto_connect_mqtt(&self) -> bool {
         self.config.mqtt.auto_connect
     }
+
+    // Coordinates to start publishing for at launch, or None if auto-publish is off
+    pub fn auto_publish_coordinates(&self) -> Option<(f64, f64)> {
+        let mqtt = &self.config.mqtt;
+        if !mqtt.auto_publish {
+            return None;
+        }
+        let preset = mqtt.auto_publish_location.as_deref().and_then(|name| self.find_location(name));
+        Some(preset.map(|l| (l.latitude, l.longitude)).unwrap_or_else(|| self.active_coordinates()))
+    }
 }
 
 // Create initial config file if it doesn't exist
-pub fn ensure_config_file_exists() -> Result<PathBuf> {
-    let config_path = ConfigManager::get_config_path()?;
+pub async fn ensure_config_file_exists() -> Result<PathBuf> {
+    let config_path = ConfigManager::get_config_path().await?;
     
-    if !config_path.exists() {
+    if !fs::try_exists(&config_path).await? {
         let default_config = AppConfig::default();
         let content = toml::to_string_pretty(&default_config)?;
-        fs::write(&config_path, content)?;
+        write_atomic(&config_path, &content).await?;
         info!("Created default config file at {:?}", config_path);
     }
 
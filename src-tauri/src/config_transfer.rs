@@ -0,0 +1,237 @@
+use crate::config::AppConfig;
+use crate::config_validation;
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+// Shown instead of secret values in import previews
+const MASK: &str = "***";
+
+// One setting that an import would change, keyed by its dotted path, e.g. "mqtt.broker_host"
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChange {
+    pub path: String,
+    // None when the setting doesn't exist on that side
+    pub current: Option<Value>,
+    pub imported: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigImportPreview {
+    pub changes: Vec<ConfigChange>,
+    // Secrets left blank in the file, which keep this machine's values
+    pub kept_secrets: Vec<String>,
+}
+
+// Writes the config as TOML; without secrets, passwords, tokens, API keys and
+// webhook URLs/headers are blanked so the file can be shared
+pub fn export(config: &AppConfig, path: &Path, include_secrets: bool) -> Result<()> {
+    let mut config = config.clone();
+    if !include_secrets {
+        strip_secrets(&mut config);
+    }
+    fs::write(path, toml::to_string_pretty(&config)?)?;
+    info!("Exported config to {:?} ({} secrets)", path, if include_secrets { "with" } else { "without" });
+    Ok(())
+}
+
+// Reads and validates a config file. Blank secrets are filled in from `current`
+// so importing a shared config doesn't wipe this machine's credentials.
+pub fn load(path: &Path, current: &AppConfig) -> Result<(AppConfig, Vec<String>)> {
+    let content = fs::read_to_string(path)?;
+    let mut config: AppConfig = toml::from_str(&content).map_err(|e| anyhow!("Not a valid config file: {}", e))?;
+    // Two machines sharing a client id would keep disconnecting each other
+    config.mqtt.client_id = current.mqtt.client_id.clone();
+    let kept_secrets = fill_secrets(&mut config, current);
+    // After filling secrets, so enabled channels don't fail on a blanked token
+    config_validation::check(&config)?;
+    Ok((config, kept_secrets))
+}
+
+pub fn preview(path: &Path, current: &AppConfig) -> Result<ConfigImportPreview> {
+    let (imported, kept_secrets) = load(path, current)?;
+    let mut changes = Vec::new();
+    diff("", &serde_json::to_value(current)?, &serde_json::to_value(&imported)?, &mut changes);
+    Ok(ConfigImportPreview { changes, kept_secrets })
+}
+
+// Every secret that is set, e.g. for masking in the logs
+pub fn secret_values(config: &AppConfig) -> Vec<String> {
+    let mut values = Vec::new();
+    if let Ok(document) = serde_json::to_value(config) {
+        collect_secrets("", &document, &mut values);
+    }
+    values
+}
+
+fn collect_secrets(path: &str, value: &Value, values: &mut Vec<String>) {
+    let child = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                collect_secrets(&child(key), value, values);
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                collect_secrets(&child(&index.to_string()), value, values);
+            }
+        }
+        Value::String(text) if !text.is_empty() && is_secret(path) => values.push(text.clone()),
+        _ => {}
+    }
+}
+
+fn strip_secrets(config: &mut AppConfig) {
+    config.mqtt.password = None;
+    config.mqtt.proxy.password = None;
+    config.mqtt.uplink.password = None;
+    config.mqtt.influxdb.token.clear();
+    config.mqtt.wunderground.station_key.clear();
+    config.mqtt.opensensemap.api_key.clear();
+    config.weather_api.api_key.clear();
+    config.app.email.password = None;
+    config.app.telegram.bot_token.clear();
+    config.app.discord.webhook_url.clear();
+    config.app.triggers.ifttt.key.clear();
+    config.app.triggers.ntfy.token.clear();
+    config.app.rest_api.token.clear();
+    config.app.grpc.token.clear();
+    // Webhook URLs often embed a token (Slack, Discord), headers carry Authorization
+    for webhook in &mut config.app.webhooks {
+        webhook.url.clear();
+        webhook.headers.values_mut().for_each(String::clear);
+    }
+}
+
+// Returns the paths of the secrets taken from `current`
+fn fill_secrets(config: &mut AppConfig, current: &AppConfig) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut keep_option = |name: &str, imported: &mut Option<String>, current: &Option<String>| {
+        if imported.as_deref().map_or(true, str::is_empty) && current.as_deref().is_some_and(|value| !value.is_empty()) {
+            *imported = current.clone();
+            kept.push(name.to_string());
+        }
+    };
+    keep_option("mqtt.password", &mut config.mqtt.password, &current.mqtt.password);
+    keep_option("mqtt.proxy.password", &mut config.mqtt.proxy.password, &current.mqtt.proxy.password);
+    keep_option("mqtt.uplink.password", &mut config.mqtt.uplink.password, &current.mqtt.uplink.password);
+    keep_option("app.email.password", &mut config.app.email.password, &current.app.email.password);
+
+    let mut keep = |name: &str, imported: &mut String, current: &String| {
+        if imported.is_empty() && !current.is_empty() {
+            *imported = current.clone();
+            kept.push(name.to_string());
+        }
+    };
+    keep("mqtt.influxdb.token", &mut config.mqtt.influxdb.token, &current.mqtt.influxdb.token);
+    keep("mqtt.wunderground.station_key", &mut config.mqtt.wunderground.station_key, &current.mqtt.wunderground.station_key);
+    keep("mqtt.opensensemap.api_key", &mut config.mqtt.opensensemap.api_key, &current.mqtt.opensensemap.api_key);
+    keep("weather_api.api_key", &mut config.weather_api.api_key, &current.weather_api.api_key);
+    keep("app.telegram.bot_token", &mut config.app.telegram.bot_token, &current.app.telegram.bot_token);
+    keep("app.discord.webhook_url", &mut config.app.discord.webhook_url, &current.app.discord.webhook_url);
+    keep("app.triggers.ifttt.key", &mut config.app.triggers.ifttt.key, &current.app.triggers.ifttt.key);
+    keep("app.triggers.ntfy.token", &mut config.app.triggers.ntfy.token, &current.app.triggers.ntfy.token);
+    keep("app.rest_api.token", &mut config.app.rest_api.token, &current.app.rest_api.token);
+    keep("app.grpc.token", &mut config.app.grpc.token, &current.app.grpc.token);
+
+    // Webhooks are matched by name
+    for (index, webhook) in config.app.webhooks.iter_mut().enumerate() {
+        let Some(existing) = current.app.webhooks.iter().find(|existing| existing.name == webhook.name) else {
+            continue;
+        };
+        keep(&format!("app.webhooks.{}.url", index), &mut webhook.url, &existing.url);
+        for (header, value) in webhook.headers.iter_mut() {
+            if let Some(existing_value) = existing.headers.get(header) {
+                keep(&format!("app.webhooks.{}.headers.{}", index, header), value, existing_value);
+            }
+        }
+    }
+    kept
+}
+
+fn is_secret(path: &str) -> bool {
+    const SECRETS: [&str; 14] = [
+        "mqtt.password",
+        "mqtt.proxy.password",
+        "mqtt.uplink.password",
+        "mqtt.influxdb.token",
+        "mqtt.wunderground.station_key",
+        "mqtt.opensensemap.api_key",
+        "weather_api.api_key",
+        "app.email.password",
+        "app.telegram.bot_token",
+        "app.discord.webhook_url",
+        "app.triggers.ifttt.key",
+        "app.triggers.ntfy.token",
+        "app.rest_api.token",
+        "app.grpc.token",
+    ];
+    if SECRETS.contains(&path) {
+        return true;
+    }
+    let Some(rest) = path.strip_prefix("app.webhooks.") else {
+        return false;
+    };
+    let field = rest.split_once('.').map_or("", |(_, field)| field);
+    field == "url" || field.starts_with("headers.")
+}
+
+fn diff(path: &str, current: &Value, imported: &Value, changes: &mut Vec<ConfigChange>) {
+    let child = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+    match (current, imported) {
+        (Value::Object(current), Value::Object(imported)) => {
+            let keys: BTreeSet<&String> = current.keys().chain(imported.keys()).collect();
+            for key in keys {
+                match (current.get(key), imported.get(key)) {
+                    (Some(a), Some(b)) => diff(&child(key), a, b, changes),
+                    (a, b) => push_change(&child(key), a, b, changes),
+                }
+            }
+        }
+        (Value::Array(current), Value::Array(imported)) => {
+            for index in 0..current.len().max(imported.len()) {
+                match (current.get(index), imported.get(index)) {
+                    (Some(a), Some(b)) => diff(&child(&index.to_string()), a, b, changes),
+                    (a, b) => push_change(&child(&index.to_string()), a, b, changes),
+                }
+            }
+        }
+        (a, b) if a != b => push_change(path, Some(a), Some(b), changes),
+        _ => {}
+    }
+}
+
+fn push_change(path: &str, current: Option<&Value>, imported: Option<&Value>, changes: &mut Vec<ConfigChange>) {
+    let shown = |value: Option<&Value>| value.map(|value| mask(path, value));
+    changes.push(ConfigChange {
+        path: path.to_string(),
+        current: shown(current),
+        imported: shown(imported),
+    });
+}
+
+// Secrets inside `value`, which may be a whole section that was added or removed
+fn mask(path: &str, value: &Value) -> Value {
+    if is_secret(path) {
+        return match value {
+            Value::Null => Value::Null,
+            Value::String(text) if text.is_empty() => value.clone(),
+            _ => Value::String(MASK.to_string()),
+        };
+    }
+    match value {
+        Value::Object(object) => Value::Object(object.iter()
+            .map(|(key, value)| (key.clone(), mask(&format!("{}.{}", path, key), value)))
+            .collect()),
+        Value::Array(items) => Value::Array(items.iter()
+            .enumerate()
+            .map(|(index, value)| mask(&format!("{}.{}", path, index), value))
+            .collect()),
+        _ => value.clone(),
+    }
+}
@@ -0,0 +1,383 @@
+use crate::config::{AppConfig, AppSettings, MqttSettings, SmoothingMethod, TriggerSettings, WeatherApiSettings};
+use crate::icon_cache::CODE_PLACEHOLDER as ICON_CODE_PLACEHOLDER;
+use crate::opensensemap::MIN_INTERVAL_SECS as MIN_OPENSENSEMAP_INTERVAL_SECS;
+use crate::payload_templates;
+use crate::providers::MAX_FORECAST_DAYS;
+use crate::wunderground::MIN_INTERVAL_SECS as MIN_WUNDERGROUND_INTERVAL_SECS;
+use chrono::NaiveTime;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Longer windows make the live view feel stuck
+const MAX_SENSOR_EVENT_INTERVAL_MS: f64 = 10_000.0;
+// Further out and an offset sunset could land before the offset sunrise
+const MAX_SUN_OFFSET_MINUTES: f64 = 180.0;
+
+// One invalid setting, keyed by its dotted path, e.g. "mqtt.broker_port" or "app.webhooks.0.url"
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigFieldError {
+    pub path: String,
+    pub message: String,
+}
+
+// Returned by ConfigManager when a config fails validation and wasn't saved
+#[derive(Debug)]
+pub struct ConfigValidationError(pub Vec<ConfigFieldError>);
+
+impl fmt::Display for ConfigValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(|e| format!("{}: {}", e.path, e.message)).collect();
+        write!(f, "Invalid configuration ({})", errors.join("; "))
+    }
+}
+
+impl std::error::Error for ConfigValidationError {}
+
+pub fn check(config: &AppConfig) -> anyhow::Result<()> {
+    let errors = validate(config);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigValidationError(errors).into())
+    }
+}
+
+pub fn validate(config: &AppConfig) -> Vec<ConfigFieldError> {
+    let mut errors = Errors::default();
+    validate_mqtt(&mut errors, &config.mqtt);
+    validate_weather_api(&mut errors, &config.weather_api);
+    validate_app(&mut errors, &config.app);
+
+    if let Some(name) = &config.mqtt.auto_publish_location {
+        if !config.weather_api.locations.iter().any(|l| l.name.eq_ignore_ascii_case(name)) {
+            errors.push("mqtt.auto_publish_location", format!("Unknown location: {}", name));
+        }
+    }
+
+    let mut ids = BTreeSet::new();
+    for (i, rule) in config.alert_rules.iter().enumerate() {
+        if let Err(e) = rule.validate() {
+            errors.push(&format!("alert_rules.{}", i), e.to_string());
+        }
+        if !ids.insert(rule.id) {
+            errors.push(&format!("alert_rules.{}.id", i), format!("Rule id {} is used more than once", rule.id));
+        }
+    }
+
+    let mut task_ids = BTreeSet::new();
+    for (i, task) in config.scheduled_tasks.iter().enumerate() {
+        if let Err(e) = task.validate() {
+            errors.push(&format!("scheduled_tasks.{}", i), e.to_string());
+        }
+        if !task_ids.insert(task.id) {
+            errors.push(&format!("scheduled_tasks.{}.id", i), format!("Task id {} is used more than once", task.id));
+        }
+    }
+    errors.0
+}
+
+#[derive(Default)]
+struct Errors(Vec<ConfigFieldError>);
+
+impl Errors {
+    fn push(&mut self, path: &str, message: impl Into<String>) {
+        self.0.push(ConfigFieldError { path: path.to_string(), message: message.into() });
+    }
+
+    fn require(&mut self, path: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.push(path, "Must not be empty");
+        }
+    }
+
+    fn port(&mut self, path: &str, port: u16) {
+        if port == 0 {
+            self.push(path, "Port must be between 1 and 65535");
+        }
+    }
+
+    fn positive(&mut self, path: &str, value: u64) {
+        if value == 0 {
+            self.push(path, "Must be greater than 0");
+        }
+    }
+
+    fn uuid(&mut self, path: &str, value: &str) {
+        if uuid::Uuid::parse_str(value.trim()).is_err() {
+            self.push(path, format!("Expected a UUID, got '{}'", value));
+        }
+    }
+
+    fn range(&mut self, path: &str, value: f64, min: f64, max: f64) {
+        if !(min..=max).contains(&value) {
+            self.push(path, format!("Must be between {} and {}, got {}", min, max, value));
+        }
+    }
+
+    fn time_of_day(&mut self, path: &str, value: &str) {
+        if NaiveTime::parse_from_str(value, "%H:%M").is_err() {
+            self.push(path, format!("Expected a time as HH:MM, got '{}'", value));
+        }
+    }
+
+    fn http_url(&mut self, path: &str, value: &str) {
+        if !(value.starts_with("http://") || value.starts_with("https://")) {
+            self.push(path, "Must be an http:// or https:// URL");
+        }
+    }
+}
+
+fn validate_mqtt(errors: &mut Errors, mqtt: &MqttSettings) {
+    errors.require("mqtt.broker_host", &mqtt.broker_host);
+    errors.port("mqtt.broker_port", mqtt.broker_port);
+    errors.require("mqtt.client_id", &mqtt.client_id);
+    if mqtt.smoothing.enabled {
+        match mqtt.smoothing.method {
+            SmoothingMethod::MovingAverage => errors.positive("mqtt.smoothing.window", mqtt.smoothing.window as u64),
+            SmoothingMethod::Exponential => {
+                if !(mqtt.smoothing.alpha > 0.0 && mqtt.smoothing.alpha <= 1.0) {
+                    errors.push("mqtt.smoothing.alpha", format!("Expected a value above 0 and up to 1, got {}", mqtt.smoothing.alpha));
+                }
+            }
+        }
+    }
+    if mqtt.coordination.enabled {
+        let topic = &mqtt.coordination.topic;
+        if topic.trim().is_empty() || topic.contains(['+', '#']) {
+            errors.push("mqtt.coordination.topic", format!("Expected a topic without wildcards, got '{}'", topic));
+        }
+    }
+    // MQTT encodes keep-alive as a 16-bit number of seconds
+    if !(1..=u16::MAX as u64).contains(&mqtt.keep_alive_secs) {
+        errors.push("mqtt.keep_alive_secs", "Must be between 1 and 65535 seconds");
+    }
+    if let Some(group) = &mqtt.shared_subscription_group {
+        if group.is_empty() || group.contains(['/', '+', '#']) {
+            errors.push("mqtt.shared_subscription_group", "Must be a non-empty name without '/', '+' or '#'");
+        }
+    }
+    errors.positive("mqtt.device_offline_timeout_secs", mqtt.device_offline_timeout_secs);
+    errors.range("mqtt.low_battery_threshold_percent", mqtt.low_battery_threshold_percent, 0.0, 100.0);
+    if mqtt.time_sync_interval_secs > 0 {
+        errors.require("mqtt.time_sync_topic", &mqtt.time_sync_topic);
+    }
+    errors.positive("mqtt.publish_interval_secs", mqtt.publish_interval_secs);
+    errors.positive("mqtt.sensor_stale_after_minutes", mqtt.sensor_stale_after_minutes);
+    if mqtt.gps_min_distance_km < 0.0 {
+        errors.push("mqtt.gps_min_distance_km", "Must not be negative");
+    }
+    errors.range("mqtt.battery_saver.battery_threshold_percent", mqtt.battery_saver.battery_threshold_percent, 0.0, 100.0);
+    if mqtt.battery_saver.enabled {
+        errors.positive("mqtt.battery_saver.publish_interval_secs", mqtt.battery_saver.publish_interval_secs);
+    }
+
+    for (path, template) in [
+        ("mqtt.payload_templates.weather", &mqtt.payload_templates.weather),
+        ("mqtt.payload_templates.alert", &mqtt.payload_templates.alert),
+    ] {
+        if let Err(e) = payload_templates::check(template) {
+            errors.push(path, e.to_string());
+        }
+    }
+
+    if mqtt.proxy.enabled {
+        errors.require("mqtt.proxy.host", &mqtt.proxy.host);
+        errors.port("mqtt.proxy.port", mqtt.proxy.port);
+    }
+    if mqtt.uplink.enabled {
+        errors.require("mqtt.uplink.broker_host", &mqtt.uplink.broker_host);
+        errors.port("mqtt.uplink.broker_port", mqtt.uplink.broker_port);
+        errors.require("mqtt.uplink.client_id", &mqtt.uplink.client_id);
+    }
+    if mqtt.influxdb.enabled {
+        errors.http_url("mqtt.influxdb.url", &mqtt.influxdb.url);
+        errors.require("mqtt.influxdb.org", &mqtt.influxdb.org);
+        errors.require("mqtt.influxdb.bucket", &mqtt.influxdb.bucket);
+        errors.require("mqtt.influxdb.measurement", &mqtt.influxdb.measurement);
+        errors.positive("mqtt.influxdb.batch_size", mqtt.influxdb.batch_size as u64);
+    }
+    if mqtt.wunderground.enabled {
+        errors.require("mqtt.wunderground.station_id", &mqtt.wunderground.station_id);
+        errors.require("mqtt.wunderground.station_key", &mqtt.wunderground.station_key);
+        if mqtt.wunderground.interval_secs < MIN_WUNDERGROUND_INTERVAL_SECS {
+            errors.push("mqtt.wunderground.interval_secs", format!("Must be at least {}", MIN_WUNDERGROUND_INTERVAL_SECS));
+        }
+    }
+    if mqtt.opensensemap.enabled {
+        let sensors = &mqtt.opensensemap.sensors;
+        errors.require("mqtt.opensensemap.box_id", &mqtt.opensensemap.box_id);
+        errors.require("mqtt.opensensemap.api_key", &mqtt.opensensemap.api_key);
+        if [&sensors.temperature, &sensors.humidity, &sensors.pressure].iter().all(|id| id.trim().is_empty()) {
+            errors.push("mqtt.opensensemap.sensors", "At least one sensor id is required");
+        }
+        if mqtt.opensensemap.interval_secs < MIN_OPENSENSEMAP_INTERVAL_SECS {
+            errors.push("mqtt.opensensemap.interval_secs", format!("Must be at least {}", MIN_OPENSENSEMAP_INTERVAL_SECS));
+        }
+    }
+    if let Some(directory) = mqtt.csv_log.directory.as_deref().filter(|_| mqtt.csv_log.enabled) {
+        errors.require("mqtt.csv_log.directory", directory);
+    }
+}
+
+fn validate_triggers(errors: &mut Errors, triggers: &TriggerSettings) {
+    if triggers.ifttt.enabled {
+        errors.require("app.triggers.ifttt.key", &triggers.ifttt.key);
+    }
+    if triggers.ntfy.enabled {
+        errors.http_url("app.triggers.ntfy.server", &triggers.ntfy.server);
+        errors.require("app.triggers.ntfy.topic", &triggers.ntfy.topic);
+    }
+    let events = [
+        ("alert_fired", &triggers.alert_fired),
+        ("device_offline", &triggers.device_offline),
+        ("daily_summary", &triggers.daily_summary),
+    ];
+    for (name, event) in events.into_iter().filter(|(_, event)| event.enabled) {
+        if triggers.ifttt.enabled {
+            errors.require(&format!("app.triggers.{}.ifttt_event", name), &event.ifttt_event);
+        }
+        if !(1..=5).contains(&event.ntfy_priority) {
+            errors.push(&format!("app.triggers.{}.ntfy_priority", name), "Must be between 1 and 5");
+        }
+    }
+}
+
+fn validate_weather_api(errors: &mut Errors, weather_api: &WeatherApiSettings) {
+    errors.range("weather_api.latitude", weather_api.latitude, -90.0, 90.0);
+    errors.range("weather_api.longitude", weather_api.longitude, -180.0, 180.0);
+    errors.positive("weather_api.auto_fetch_interval_minutes", weather_api.auto_fetch_interval_minutes as u64);
+    if !(1..=MAX_FORECAST_DAYS).contains(&weather_api.forecast_days) {
+        errors.push(
+            "weather_api.forecast_days",
+            format!("Must be between 1 and {}, got {}", MAX_FORECAST_DAYS, weather_api.forecast_days),
+        );
+    }
+
+    let mut names = BTreeSet::new();
+    for (i, location) in weather_api.locations.iter().enumerate() {
+        errors.require(&format!("weather_api.locations.{}.name", i), &location.name);
+        if !names.insert(location.name.to_lowercase()) {
+            errors.push(&format!("weather_api.locations.{}.name", i), format!("Location '{}' is defined more than once", location.name));
+        }
+        errors.range(&format!("weather_api.locations.{}.latitude", i), location.latitude, -90.0, 90.0);
+        errors.range(&format!("weather_api.locations.{}.longitude", i), location.longitude, -180.0, 180.0);
+    }
+    if let Some(active) = &weather_api.active_location {
+        if !weather_api.locations.iter().any(|l| l.name.eq_ignore_ascii_case(active)) {
+            errors.push("weather_api.active_location", format!("Unknown location: {}", active));
+        }
+    }
+
+    errors.positive("weather_api.retry.max_attempts", weather_api.retry.max_attempts as u64);
+    if weather_api.retry.initial_backoff_ms > weather_api.retry.max_backoff_ms {
+        errors.push("weather_api.retry.initial_backoff_ms", "Must not exceed max_backoff_ms");
+    }
+    if weather_api.auto_location.enabled {
+        errors.positive("weather_api.auto_location.check_interval_minutes", weather_api.auto_location.check_interval_minutes as u64);
+        if weather_api.auto_location.switch_distance_km <= 0.0 {
+            errors.push("weather_api.auto_location.switch_distance_km", "Must be greater than 0");
+        }
+        if weather_api.locations.is_empty() {
+            errors.push("weather_api.auto_location.enabled", "Needs at least one saved location to switch between");
+        }
+    }
+    errors.http_url("weather_api.icon_pack.url_template", &weather_api.icon_pack.url_template);
+    if !weather_api.icon_pack.url_template.contains(ICON_CODE_PLACEHOLDER) {
+        errors.push("weather_api.icon_pack.url_template", format!("Must contain {}", ICON_CODE_PLACEHOLDER));
+    }
+    errors.positive("weather_api.connect_timeout_secs", weather_api.connect_timeout_secs);
+    errors.positive("weather_api.read_timeout_secs", weather_api.read_timeout_secs);
+    if weather_api.severe_weather.enabled {
+        errors.positive("weather_api.severe_weather.poll_interval_minutes", weather_api.severe_weather.poll_interval_minutes as u64);
+    }
+    if weather_api.forecast_warnings.enabled {
+        errors.time_of_day("weather_api.forecast_warnings.check_time", &weather_api.forecast_warnings.check_time);
+    }
+    if weather_api.rain_alerts.enabled {
+        errors.positive("weather_api.rain_alerts.poll_interval_minutes", weather_api.rain_alerts.poll_interval_minutes as u64);
+        if weather_api.rain_alerts.min_intensity_mm_h < 0.0 {
+            errors.push("weather_api.rain_alerts.min_intensity_mm_h", "Must not be negative");
+        }
+    }
+}
+
+fn validate_app(errors: &mut Errors, app: &AppSettings) {
+    errors.positive("app.data_refresh_interval_seconds", app.data_refresh_interval_seconds as u64);
+    for (path, minutes) in [
+        ("app.sun_automation.sunrise_offset_minutes", app.sun_automation.sunrise_offset_minutes),
+        ("app.sun_automation.sunset_offset_minutes", app.sun_automation.sunset_offset_minutes),
+    ] {
+        errors.range(path, minutes as f64, -MAX_SUN_OFFSET_MINUTES, MAX_SUN_OFFSET_MINUTES);
+    }
+    if app.daily_summary.enabled {
+        errors.time_of_day("app.daily_summary.time", &app.daily_summary.time);
+    }
+    if app.quiet_hours.enabled {
+        errors.time_of_day("app.quiet_hours.start", &app.quiet_hours.start);
+        errors.time_of_day("app.quiet_hours.end", &app.quiet_hours.end);
+    }
+    errors.range("app.sensor_events.interval_ms", app.sensor_events.interval_ms as f64, 0.0, MAX_SENSOR_EVENT_INTERVAL_MS);
+    if app.scripting.enabled {
+        errors.positive("app.scripting.max_operations", app.scripting.max_operations);
+    }
+    if app.tracing.otlp_enabled {
+        errors.http_url("app.tracing.otlp_endpoint", &app.tracing.otlp_endpoint);
+        errors.require("app.tracing.service_name", &app.tracing.service_name);
+    }
+    if app.grafana.enabled {
+        errors.require("app.grafana.bind_address", &app.grafana.bind_address);
+        errors.port("app.grafana.port", app.grafana.port);
+    }
+    if app.rest_api.enabled {
+        errors.require("app.rest_api.bind_address", &app.rest_api.bind_address);
+        errors.port("app.rest_api.port", app.rest_api.port);
+        errors.require("app.rest_api.token", &app.rest_api.token);
+    }
+    if app.grpc.enabled {
+        errors.require("app.grpc.bind_address", &app.grpc.bind_address);
+        errors.port("app.grpc.port", app.grpc.port);
+        errors.require("app.grpc.token", &app.grpc.token);
+    }
+    if app.ble.enabled && !cfg!(feature = "ble") {
+        errors.push("app.ble.enabled", "This build has no Bluetooth support");
+    }
+    if app.ble.enabled {
+        errors.require("app.ble.device_name", &app.ble.device_name);
+        errors.uuid("app.ble.service_uuid", &app.ble.service_uuid);
+        errors.uuid("app.ble.characteristic_uuid", &app.ble.characteristic_uuid);
+        errors.positive("app.ble.scan_timeout_secs", app.ble.scan_timeout_secs);
+    }
+
+    let retention = &app.storage.retention;
+    if retention.enabled {
+        errors.positive("app.storage.retention.raw_days", retention.raw_days as u64);
+        errors.positive("app.storage.retention.prune_interval_hours", retention.prune_interval_hours as u64);
+        if retention.aggregate_days < retention.raw_days {
+            errors.push("app.storage.retention.aggregate_days", "Must not be shorter than raw_days");
+        }
+    }
+
+    for (i, webhook) in app.webhooks.iter().enumerate() {
+        errors.require(&format!("app.webhooks.{}.name", i), &webhook.name);
+        if webhook.enabled {
+            errors.http_url(&format!("app.webhooks.{}.url", i), &webhook.url);
+        }
+    }
+    if app.email.enabled {
+        errors.require("app.email.host", &app.email.host);
+        errors.port("app.email.port", app.email.port);
+        errors.require("app.email.from", &app.email.from);
+        if app.email.recipients.iter().all(|r| r.trim().is_empty()) {
+            errors.push("app.email.recipients", "At least one recipient is required");
+        }
+    }
+    if app.telegram.enabled {
+        errors.require("app.telegram.bot_token", &app.telegram.bot_token);
+        errors.require("app.telegram.chat_id", &app.telegram.chat_id);
+    }
+    if app.discord.enabled {
+        errors.http_url("app.discord.webhook_url", &app.discord.webhook_url);
+    }
+    validate_triggers(errors, &app.triggers);
+}
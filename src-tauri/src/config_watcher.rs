@@ -0,0 +1,46 @@
+use anyhow::{Result, anyhow};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+// Editors write a file in several steps; changes within this window are reported once
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Watches the config file and yields once per burst of changes. The directory is
+// watched rather than the file, so editors that save by renaming a temp file over
+// it are still seen. Dropping the watcher stops the notifications.
+pub fn watch(path: &Path) -> Result<(RecommendedWatcher, ConfigChanges)> {
+    let dir = path.parent().ok_or_else(|| anyhow!("Config path {:?} has no parent directory", path))?;
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+        Ok(event) => {
+            let ours = event.paths.iter().any(|changed| changed.file_name().map(|name| name.to_os_string()) == file_name);
+            if ours && (event.kind.is_create() || event.kind.is_modify()) {
+                let _ = tx.send(());
+            }
+        }
+        Err(e) => warn!("Config watcher error: {}", e),
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    info!("Watching {:?} for changes", path);
+    Ok((watcher, ConfigChanges(rx)))
+}
+
+pub struct ConfigChanges(mpsc::UnboundedReceiver<()>);
+
+impl ConfigChanges {
+    // Resolves after a change once the file has been quiet for DEBOUNCE; None when the watcher is gone
+    pub async fn next(&mut self) -> Option<()> {
+        self.0.recv().await?;
+        loop {
+            match tokio::time::timeout(DEBOUNCE, self.0.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) | Err(_) => return Some(()),
+            }
+        }
+    }
+}
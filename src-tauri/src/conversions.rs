@@ -0,0 +1,224 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const HPA_PER_INHG: f64 = 33.863_886;
+const HPA_PER_MMHG: f64 = 1.333_224;
+const MS_PER_KMH: f64 = 1.0 / 3.6;
+const MS_PER_MPH: f64 = 0.447_04;
+const MS_PER_KNOT: f64 = 1852.0 / 3600.0;
+const MM_PER_INCH: f64 = 25.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantity {
+    Temperature,
+    Pressure,
+    Speed,
+    Precipitation,
+}
+
+// Serialized as its symbol, e.g. "hPa"; parsed from the symbol or the name, ignoring case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+    Hectopascal,
+    InchOfMercury,
+    MillimetreOfMercury,
+    MetrePerSecond,
+    KilometrePerHour,
+    MilePerHour,
+    Knot,
+    Millimetre,
+    Inch,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 12] = [
+        Unit::Celsius,
+        Unit::Fahrenheit,
+        Unit::Kelvin,
+        Unit::Hectopascal,
+        Unit::InchOfMercury,
+        Unit::MillimetreOfMercury,
+        Unit::MetrePerSecond,
+        Unit::KilometrePerHour,
+        Unit::MilePerHour,
+        Unit::Knot,
+        Unit::Millimetre,
+        Unit::Inch,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Kelvin => "K",
+            Unit::Hectopascal => "hPa",
+            Unit::InchOfMercury => "inHg",
+            Unit::MillimetreOfMercury => "mmHg",
+            Unit::MetrePerSecond => "m/s",
+            Unit::KilometrePerHour => "km/h",
+            Unit::MilePerHour => "mph",
+            Unit::Knot => "kn",
+            Unit::Millimetre => "mm",
+            Unit::Inch => "in",
+        }
+    }
+
+    // Other spellings accepted when parsing, lowercase
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Unit::Celsius => &["c", "celsius", "degc"],
+            Unit::Fahrenheit => &["f", "fahrenheit", "degf"],
+            Unit::Kelvin => &["kelvin"],
+            Unit::Hectopascal => &["hectopascal", "mbar", "mb"],
+            Unit::InchOfMercury => &["inch_of_mercury", "inches_of_mercury"],
+            Unit::MillimetreOfMercury => &["millimetre_of_mercury", "torr"],
+            Unit::MetrePerSecond => &["ms", "mps", "metre_per_second"],
+            Unit::KilometrePerHour => &["kmh", "kph", "kilometre_per_hour"],
+            Unit::MilePerHour => &["mile_per_hour", "miles_per_hour"],
+            Unit::Knot => &["kt", "kts", "knot", "knots"],
+            Unit::Millimetre => &["millimetre", "millimeter"],
+            Unit::Inch => &["inch", "inches"],
+        }
+    }
+
+    pub fn quantity(self) -> Quantity {
+        match self {
+            Unit::Celsius | Unit::Fahrenheit | Unit::Kelvin => Quantity::Temperature,
+            Unit::Hectopascal | Unit::InchOfMercury | Unit::MillimetreOfMercury => Quantity::Pressure,
+            Unit::MetrePerSecond | Unit::KilometrePerHour | Unit::MilePerHour | Unit::Knot => Quantity::Speed,
+            Unit::Millimetre | Unit::Inch => Quantity::Precipitation,
+        }
+    }
+
+    // Into °C, hPa, m/s or mm
+    fn to_base(self, value: f64) -> f64 {
+        match self {
+            Unit::Celsius | Unit::Hectopascal | Unit::MetrePerSecond | Unit::Millimetre => value,
+            Unit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+            Unit::Kelvin => value - 273.15,
+            Unit::InchOfMercury => value * HPA_PER_INHG,
+            Unit::MillimetreOfMercury => value * HPA_PER_MMHG,
+            Unit::KilometrePerHour => value * MS_PER_KMH,
+            Unit::MilePerHour => value * MS_PER_MPH,
+            Unit::Knot => value * MS_PER_KNOT,
+            Unit::Inch => value * MM_PER_INCH,
+        }
+    }
+
+    fn from_base(self, value: f64) -> f64 {
+        match self {
+            Unit::Celsius | Unit::Hectopascal | Unit::MetrePerSecond | Unit::Millimetre => value,
+            Unit::Fahrenheit => value * 9.0 / 5.0 + 32.0,
+            Unit::Kelvin => value + 273.15,
+            Unit::InchOfMercury => value / HPA_PER_INHG,
+            Unit::MillimetreOfMercury => value / HPA_PER_MMHG,
+            Unit::KilometrePerHour => value / MS_PER_KMH,
+            Unit::MilePerHour => value / MS_PER_MPH,
+            Unit::Knot => value / MS_PER_KNOT,
+            Unit::Inch => value / MM_PER_INCH,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl FromStr for Unit {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let wanted = text.trim().to_lowercase();
+        let wanted = wanted.trim_start_matches('°');
+        Unit::ALL.into_iter()
+            .find(|unit| unit.symbol().trim_start_matches('°').eq_ignore_ascii_case(wanted) || unit.aliases().contains(&wanted))
+            .ok_or_else(|| anyhow!("Unknown unit '{}'", text))
+    }
+}
+
+impl TryFrom<String> for Unit {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> Result<Self> {
+        text.parse()
+    }
+}
+
+impl From<Unit> for String {
+    fn from(unit: Unit) -> Self {
+        unit.symbol().to_string()
+    }
+}
+
+pub fn convert(value: f64, from: Unit, to: Unit) -> Result<f64> {
+    if from.quantity() != to.quantity() {
+        return Err(anyhow!("Can't convert {} to {}", from, to));
+    }
+    Ok(to.from_base(from.to_base(value)))
+}
+
+// For conversions between units of the same quantity, which can't fail
+pub fn convert_same(value: f64, from: Unit, to: Unit) -> f64 {
+    debug_assert_eq!(from.quantity(), to.quantity());
+    to.from_base(from.to_base(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "expected {} ± {}, got {}", expected, tolerance, actual);
+    }
+
+    #[test]
+    fn standard_pressure_in_inches_of_mercury() {
+        assert_close(convert(1013.25, Unit::Hectopascal, Unit::InchOfMercury).unwrap(), 29.92, 0.005);
+        assert_close(convert(1013.25, Unit::Hectopascal, Unit::MillimetreOfMercury).unwrap(), 760.0, 0.01);
+    }
+
+    #[test]
+    fn freezing_point_in_every_temperature_unit() {
+        assert_close(convert(0.0, Unit::Celsius, Unit::Fahrenheit).unwrap(), 32.0, 1e-9);
+        assert_close(convert(0.0, Unit::Celsius, Unit::Kelvin).unwrap(), 273.15, 1e-9);
+        assert_close(convert(32.0, Unit::Fahrenheit, Unit::Kelvin).unwrap(), 273.15, 1e-9);
+    }
+
+    #[test]
+    fn knot_is_one_nautical_mile_per_hour() {
+        assert_close(convert(1.0, Unit::Knot, Unit::KilometrePerHour).unwrap(), 1.852, 1e-9);
+        assert_close(convert(1.0, Unit::Inch, Unit::Millimetre).unwrap(), 25.4, 1e-9);
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        for from in Unit::ALL {
+            for to in Unit::ALL.into_iter().filter(|to| to.quantity() == from.quantity()) {
+                let there = convert(12.5, from, to).unwrap();
+                assert_close(convert(there, to, from).unwrap(), 12.5, 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_other_quantities() {
+        assert!(convert(1.0, Unit::Celsius, Unit::Hectopascal).is_err());
+    }
+
+    #[test]
+    fn parses_symbols_and_aliases() {
+        assert_eq!("°F".parse::<Unit>().unwrap(), Unit::Fahrenheit);
+        assert_eq!("KTS".parse::<Unit>().unwrap(), Unit::Knot);
+        assert_eq!("mbar".parse::<Unit>().unwrap(), Unit::Hectopascal);
+        assert!("furlong".parse::<Unit>().is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::config::{CoordinationSettings, InstanceRole};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::{info, debug, warn};
+
+// Publish intervals a claim survives without renewal before another instance takes over
+const LEASE_INTERVALS: u64 = 3;
+// How far apart two machines' clocks may be before a claim's age is held against it
+const CLOCK_TOLERANCE_SECS: u64 = 60;
+
+// Retained on the coordination topic by the instance publishing weather, renewed every
+// publish. An empty retained message means it stepped down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublisherClaim {
+    // The instance's MQTT client id
+    pub instance_id: String,
+    pub role: InstanceRole,
+    pub lease_secs: u64,
+    pub claimed_at: DateTime<Utc>,
+}
+
+impl PublisherClaim {
+    pub fn new(instance_id: &str, role: InstanceRole, publish_interval_secs: u64) -> Self {
+        Self {
+            instance_id: instance_id.to_string(),
+            role,
+            lease_secs: publish_interval_secs.max(1) * LEASE_INTERVALS,
+            claimed_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoordinationStatus {
+    pub enabled: bool,
+    pub role: InstanceRole,
+    pub instance_id: String,
+    // None when no instance holds a live claim
+    pub publisher: Option<PublisherClaim>,
+    pub publishing_here: bool,
+}
+
+struct ReceivedClaim {
+    claim: PublisherClaim,
+    // Local time, so the lease doesn't depend on the two machines' clocks agreeing,
+    // backdated when the claim is clearly older than its delivery
+    received: Instant,
+}
+
+impl ReceivedClaim {
+    fn is_live(&self) -> bool {
+        self.received.elapsed() < Duration::from_secs(self.claim.lease_secs)
+    }
+}
+
+// Newest claim seen on the coordination topic. When two instances claim at once the
+// broker delivers both in the same order everywhere, so the later one wins for all.
+#[derive(Default)]
+pub struct Coordinator {
+    current: std::sync::Mutex<Option<ReceivedClaim>>,
+}
+
+impl Coordinator {
+    pub fn record(&self, payload: &[u8]) {
+        let mut current = self.current.lock().unwrap();
+        if payload.is_empty() {
+            if let Some(previous) = current.take() {
+                info!("{} stopped publishing weather", previous.claim.instance_id);
+            }
+            return;
+        }
+        match serde_json::from_slice::<PublisherClaim>(payload) {
+            Ok(claim) => {
+                // A retained claim can be left by an instance that crashed long ago
+                let age = (Utc::now() - claim.claimed_at).to_std().unwrap_or_default()
+                    .saturating_sub(Duration::from_secs(CLOCK_TOLERANCE_SECS));
+                if age >= Duration::from_secs(claim.lease_secs) {
+                    debug!("Ignoring expired publisher claim from {}", claim.instance_id);
+                    return;
+                }
+                let received = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+                let changed = current.as_ref()
+                    .map_or(true, |previous| previous.claim.instance_id != claim.instance_id);
+                if changed {
+                    info!("{} is now publishing weather ({:?})", claim.instance_id, claim.role);
+                }
+                *current = Some(ReceivedClaim { claim, received });
+            }
+            Err(e) => warn!("Ignoring unreadable publisher claim: {}", e),
+        }
+    }
+
+    // Recorded straight away rather than waiting for the broker to echo it back
+    pub fn claim(&self, claim: PublisherClaim) {
+        *self.current.lock().unwrap() = Some(ReceivedClaim { claim, received: Instant::now() });
+    }
+
+    pub fn should_publish(&self, settings: &CoordinationSettings, instance_id: &str) -> bool {
+        if !settings.enabled {
+            return true;
+        }
+        match settings.role {
+            InstanceRole::Viewer => false,
+            InstanceRole::Publisher => true,
+            InstanceRole::Auto => self.live_claim().map_or(true, |claim| claim.instance_id == instance_id),
+        }
+    }
+
+    pub fn holds_claim(&self, instance_id: &str) -> bool {
+        self.live_claim().is_some_and(|claim| claim.instance_id == instance_id)
+    }
+
+    pub fn status(&self, settings: &CoordinationSettings, instance_id: &str, publishing: bool) -> CoordinationStatus {
+        CoordinationStatus {
+            enabled: settings.enabled,
+            role: settings.role,
+            instance_id: instance_id.to_string(),
+            publisher: self.live_claim(),
+            publishing_here: publishing && (!settings.enabled || self.holds_claim(instance_id)),
+        }
+    }
+
+    fn live_claim(&self) -> Option<PublisherClaim> {
+        self.current.lock().unwrap().as_ref()
+            .filter(|received| received.is_live())
+            .map(|received| received.claim.clone())
+    }
+}
@@ -0,0 +1,161 @@
+use crate::logging;
+use crate::storage;
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_opener::OpenerExt;
+use tracing::{error, warn};
+
+const CRASH_DIR_NAME: &str = "crash_reports";
+const CRASH_FILE_PREFIX: &str = "crash-";
+// Name of the newest report the user was already told about
+const LAST_SHOWN_FILE: &str = ".last_shown";
+const LOG_LINES_IN_REPORT: usize = 100;
+
+type StateSummary = Box<dyn Fn() -> String + Send + Sync>;
+
+// Describes the running app for crash reports. It runs inside the panic hook, so
+// it must not block (try_lock, not lock).
+static STATE_SUMMARY: OnceLock<StateSummary> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub name: String,
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+pub fn crash_dir() -> PathBuf {
+    storage::data_dir().join(CRASH_DIR_NAME)
+}
+
+pub fn set_state_summary(summary: impl Fn() -> String + Send + Sync + 'static) {
+    let _ = STATE_SUMMARY.set(Box::new(summary));
+}
+
+// Writes a report for every panic, including ones inside tokio tasks that would
+// otherwise only stop that task, then runs the default hook
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_report(info) {
+            Ok(path) => error!("Panic, crash report written to {:?}", path),
+            Err(e) => error!("Panic, and writing the crash report failed: {}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+fn write_report(info: &PanicHookInfo) -> Result<PathBuf> {
+    let message = info.payload().downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string());
+    let location = info.location().map(|l| format!("{}:{}", l.file(), l.line())).unwrap_or_default();
+    let thread = std::thread::current().name().unwrap_or("unnamed").to_string();
+
+    let mut report = String::new();
+    writeln!(report, "Weather Station Desktop {} crash report", env!("CARGO_PKG_VERSION"))?;
+    writeln!(report, "Time: {}", Local::now().to_rfc3339())?;
+    writeln!(report, "Platform: {} {}", std::env::consts::OS, std::env::consts::ARCH)?;
+    writeln!(report, "Thread: {}", thread)?;
+    writeln!(report, "Panic: {}", logging::redact(&message))?;
+    writeln!(report, "Location: {}", location)?;
+    if let Some(summary) = STATE_SUMMARY.get() {
+        writeln!(report, "\n== State ==\n{}", summary())?;
+    }
+    writeln!(report, "\n== Backtrace ==\n{}", Backtrace::force_capture())?;
+    writeln!(report, "\n== Recent log ==")?;
+    match logging::recent(None, Some(LOG_LINES_IN_REPORT)) {
+        Ok(entries) => {
+            for entry in entries {
+                writeln!(report, "{} {} {}: {}", entry.timestamp, entry.level, entry.target, entry.message)?;
+            }
+        }
+        Err(e) => writeln!(report, "(log unavailable: {})", e)?,
+    }
+
+    let dir = crash_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}{}.txt", CRASH_FILE_PREFIX, Local::now().format("%Y%m%d-%H%M%S%.3f")));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+// Newest first
+pub fn reports() -> Result<Vec<CrashReport>> {
+    let entries = match fs::read_dir(crash_dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut reports = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(CRASH_FILE_PREFIX) {
+            reports.push(CrashReport {
+                name,
+                path: entry.path().display().to_string(),
+                size_bytes: entry.metadata()?.len(),
+            });
+        }
+    }
+    // The timestamp in the name sorts chronologically
+    reports.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(reports)
+}
+
+pub fn export(name: &str, path: &std::path::Path) -> Result<()> {
+    let report = reports()?
+        .into_iter()
+        .find(|report| report.name == name)
+        .ok_or_else(|| anyhow::anyhow!("No crash report named {}", name))?;
+    fs::copy(&report.path, path)?;
+    Ok(())
+}
+
+// Offers to open a crash report written since the last start, once per report
+pub fn offer_new_report(app: &AppHandle) {
+    let newest = match reports() {
+        Ok(reports) => reports.into_iter().next(),
+        Err(e) => {
+            warn!("Failed to look for crash reports: {}", e);
+            return;
+        }
+    };
+    let Some(report) = newest else {
+        return;
+    };
+    let marker = crash_dir().join(LAST_SHOWN_FILE);
+    if fs::read_to_string(&marker).is_ok_and(|shown| shown.trim() == report.name) {
+        return;
+    }
+    if let Err(e) = fs::write(&marker, &report.name) {
+        warn!("Failed to record the shown crash report: {}", e);
+    }
+
+    let app_handle = app.clone();
+    app.dialog()
+        .message(format!(
+            "Weather Station Desktop crashed last time. A report was saved to:\n{}\n\nIt can also be exported from the diagnostics panel.",
+            report.path
+        ))
+        .title("Crash report")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom("Open report".to_string(), "Dismiss".to_string()))
+        .show(move |open| {
+            if open {
+                if let Err(e) = app_handle.opener().open_path(&report.path, None::<&str>) {
+                    warn!("Failed to open crash report: {}", e);
+                }
+            }
+        });
+}
@@ -0,0 +1,228 @@
+use crate::history::SensorHistory;
+use crate::metrics::ComfortMetrics;
+use crate::types::{ReadingQuality, SensorData, SENSOR_SCHEMA_VERSION};
+use crate::validation::validate_sensor_reading;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
+
+// Rows inserted per transaction
+const IMPORT_BATCH_SIZE: usize = 1000;
+// Row errors returned to the UI; the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 100;
+// Formats tried, in order, when the mapping doesn't name one
+const FALLBACK_TIMESTAMP_FORMATS: [&str; 4] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%d/%m/%Y %H:%M:%S", "%d/%m/%Y %H:%M"];
+
+// Which CSV header holds each value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvColumnMapping {
+    pub timestamp: String,
+    pub temperature: String,
+    pub humidity: String,
+    pub pressure: String,
+    #[serde(default)]
+    pub co2: Option<String>,
+    #[serde(default)]
+    pub tvoc: Option<String>,
+    #[serde(default)]
+    pub lux: Option<String>,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    // Used for rows without a device id column
+    #[serde(default)]
+    pub default_device_id: Option<String>,
+    // chrono format, e.g. "%d.%m.%Y %H:%M"; RFC 3339, unix epochs and common formats otherwise
+    #[serde(default)]
+    pub timestamp_format: Option<String>,
+    // Timestamps without an offset are local time unless this is set
+    #[serde(default)]
+    pub timestamps_utc: bool,
+    #[serde(default)]
+    pub delimiter: Option<char>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRowError {
+    // 1-based line in the file, counting the header
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub imported: u64,
+    pub skipped: u64,
+    pub errors: Vec<ImportRowError>,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+}
+
+struct ColumnIndexes {
+    timestamp: usize,
+    temperature: usize,
+    humidity: usize,
+    pressure: usize,
+    co2: Option<usize>,
+    tvoc: Option<usize>,
+    lux: Option<usize>,
+    device_id: Option<usize>,
+}
+
+// Reads readings from a CSV written by another logger, validates each row and
+// bulk-inserts the valid ones into the sensor history
+pub fn import_sensor_csv(history: &SensorHistory, path: &Path, mapping: &CsvColumnMapping) -> Result<ImportSummary> {
+    let delimiter = mapping.delimiter.unwrap_or(',');
+    if !delimiter.is_ascii() {
+        return Err(anyhow!("Delimiter must be a single ASCII character"));
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let columns = resolve_columns(reader.headers()?, mapping)?;
+
+    let mark = history.begin_import()?;
+    let mut summary = ImportSummary::default();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    for (index, record) in reader.records().enumerate() {
+        // The header is line 1
+        let line = index as u64 + 2;
+        match record.map_err(anyhow::Error::from).and_then(|record| parse_row(&record, &columns, mapping)) {
+            Ok(reading) => {
+                let at = reading.received_at;
+                summary.first = summary.first.min(at).or(at);
+                summary.last = summary.last.max(at);
+                batch.push(reading);
+            }
+            Err(e) => {
+                summary.skipped += 1;
+                if summary.errors.len() < MAX_REPORTED_ERRORS {
+                    summary.errors.push(ImportRowError { line, message: e.to_string() });
+                }
+            }
+        }
+
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            summary.imported += history.insert_many(&batch)?;
+            batch.clear();
+        }
+    }
+    summary.imported += history.insert_many(&batch)?;
+    history.finish_import(mark)?;
+
+    if summary.skipped > 0 {
+        warn!("Skipped {} invalid rows importing {:?}", summary.skipped, path);
+    }
+    info!("Imported {} sensor readings from {:?}", summary.imported, path);
+    Ok(summary)
+}
+
+fn resolve_columns(headers: &csv::StringRecord, mapping: &CsvColumnMapping) -> Result<ColumnIndexes> {
+    let find = |name: &str| headers.iter().position(|header| header.eq_ignore_ascii_case(name));
+    let required = |name: &str| find(name).ok_or_else(|| anyhow!("Column '{}' not found in CSV header", name));
+    let optional = |name: &Option<String>| -> Result<Option<usize>> {
+        name.as_deref().map(required).transpose()
+    };
+
+    Ok(ColumnIndexes {
+        timestamp: required(&mapping.timestamp)?,
+        temperature: required(&mapping.temperature)?,
+        humidity: required(&mapping.humidity)?,
+        pressure: required(&mapping.pressure)?,
+        co2: optional(&mapping.co2)?,
+        tvoc: optional(&mapping.tvoc)?,
+        lux: optional(&mapping.lux)?,
+        device_id: optional(&mapping.device_id)?,
+    })
+}
+
+fn parse_row(record: &csv::StringRecord, columns: &ColumnIndexes, mapping: &CsvColumnMapping) -> Result<SensorData> {
+    let field = |index: usize, name: &str| {
+        record.get(index).filter(|value| !value.is_empty()).ok_or_else(|| anyhow!("{} is empty", name))
+    };
+    let number = |index: usize, name: &str| -> Result<f64> {
+        let value = field(index, name)?;
+        value.parse::<f64>().map_err(|_| anyhow!("{} '{}' is not a number", name, value))
+    };
+    let optional_number = |index: Option<usize>, name: &str| -> Result<Option<f64>> {
+        match index.and_then(|index| record.get(index)).filter(|value| !value.is_empty()) {
+            Some(value) => value.parse::<f64>().map(Some).map_err(|_| anyhow!("{} '{}' is not a number", name, value)),
+            None => Ok(None),
+        }
+    };
+
+    let timestamp = field(columns.timestamp, "timestamp")?;
+    let recorded_at = parse_timestamp(timestamp, mapping)?;
+    let device_id = columns.device_id
+        .and_then(|index| record.get(index))
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .or_else(|| mapping.default_device_id.clone());
+
+    let mut reading = SensorData {
+        temperature: number(columns.temperature, "temperature")?,
+        humidity: number(columns.humidity, "humidity")?,
+        pressure: number(columns.pressure, "pressure")?,
+        timestamp: timestamp.to_string(),
+        co2: optional_number(columns.co2, "co2")?,
+        tvoc: optional_number(columns.tvoc, "tvoc")?,
+        lux: optional_number(columns.lux, "lux")?,
+        schema_version: SENSOR_SCHEMA_VERSION,
+        device_id,
+        device_name: None,
+        raw: None,
+        received_at: Some(recorded_at),
+        age_secs: None,
+        stale: false,
+        anomalies: Vec::new(),
+        // Out-of-range rows are rejected below
+        quality: vec![ReadingQuality::Ok],
+        comfort: None,
+        pressure_tendency: None,
+    };
+    validate_sensor_reading(&reading)?;
+    reading.comfort = Some(ComfortMetrics::from_reading(reading.temperature, reading.humidity));
+    Ok(reading)
+}
+
+fn parse_timestamp(value: &str, mapping: &CsvColumnMapping) -> Result<DateTime<Utc>> {
+    if let Some(format) = &mapping.timestamp_format {
+        if let Ok(parsed) = DateTime::parse_from_str(value, format) {
+            return Ok(parsed.with_timezone(&Utc));
+        }
+        let naive = NaiveDateTime::parse_from_str(value, format)
+            .map_err(|e| anyhow!("timestamp '{}' doesn't match '{}': {}", value, format, e))?;
+        return from_naive(naive, mapping.timestamps_utc);
+    }
+
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(value) {
+        return Ok(parsed.with_timezone(&Utc));
+    }
+    if let Ok(epoch) = value.parse::<i64>() {
+        // Anything past year 5138 in seconds is taken as milliseconds
+        let parsed = if epoch.abs() < 100_000_000_000 {
+            DateTime::from_timestamp(epoch, 0)
+        } else {
+            DateTime::from_timestamp_millis(epoch)
+        };
+        return parsed.ok_or_else(|| anyhow!("timestamp {} is out of range", epoch));
+    }
+    FALLBACK_TIMESTAMP_FORMATS.iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .ok_or_else(|| anyhow!("unrecognised timestamp '{}'", value))
+        .and_then(|naive| from_naive(naive, mapping.timestamps_utc))
+}
+
+fn from_naive(naive: NaiveDateTime, utc: bool) -> Result<DateTime<Utc>> {
+    if utc {
+        return Ok(naive.and_utc());
+    }
+    // Ambiguous times at a DST change resolve to the earlier one
+    Local.from_local_datetime(&naive)
+        .earliest()
+        .map(|local| local.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("{} doesn't exist in the local time zone", naive))
+}
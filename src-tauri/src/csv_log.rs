@@ -0,0 +1,115 @@
+use crate::config::CsvLogSettings;
+use crate::export::{sensor_row, weather_row, SENSOR_CSV_HEADER, WEATHER_CSV_HEADER};
+use crate::sinks::DataSink;
+use crate::storage;
+use crate::types::{SensorData, WeatherData};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Local;
+use std::path::{Path, PathBuf};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+pub const SINK_NAME: &str = "csv";
+const QUEUE_CAPACITY: usize = 1000;
+const SHUTDOWN_TIMEOUT_SECS: u64 = 5;
+
+enum Row {
+    Sensor(String),
+    Weather(String),
+    Shutdown,
+}
+
+// Appends rows to sensor-YYYY-MM-DD.csv and weather-YYYY-MM-DD.csv, with the same
+// columns as a CSV export
+pub struct CsvLogSink {
+    sender: mpsc::Sender<Row>,
+    worker_handle: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+    include_weather: bool,
+}
+
+impl CsvLogSink {
+    pub fn start(settings: &CsvLogSettings) -> Result<Self> {
+        let directory = settings.directory.as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| storage::data_dir().join("csv"));
+        std::fs::create_dir_all(&directory)?;
+
+        info!("Starting CSV log in {:?}", directory);
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let worker_handle = tokio::spawn(Self::run(directory, receiver));
+        Ok(Self {
+            sender,
+            worker_handle: std::sync::Mutex::new(Some(worker_handle)),
+            include_weather: settings.include_weather,
+        })
+    }
+
+    fn queue(&self, row: Row) {
+        if let Err(e) = self.sender.try_send(row) {
+            warn!("Dropping CSV log row: {}", e);
+        }
+    }
+
+    async fn run(directory: PathBuf, mut receiver: mpsc::Receiver<Row>) {
+        while let Some(row) = receiver.recv().await {
+            let (dataset, header, line) = match row {
+                Row::Sensor(line) => ("sensor", SENSOR_CSV_HEADER, line),
+                Row::Weather(line) => ("weather", WEATHER_CSV_HEADER, line),
+                Row::Shutdown => break,
+            };
+            // A new file each day, named for the local date
+            let path = directory.join(format!("{}-{}.csv", dataset, Local::now().format("%Y-%m-%d")));
+            if let Err(e) = append(&path, header, &line).await {
+                error!("Failed to write CSV log {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+async fn append(path: &Path, header: &str, line: &str) -> Result<()> {
+    let is_new = !tokio::fs::try_exists(path).await.unwrap_or(false);
+    let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+    let mut text = String::new();
+    if is_new {
+        text.push_str(header);
+        text.push('\n');
+    }
+    text.push_str(line);
+    text.push('\n');
+    file.write_all(text.as_bytes()).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl DataSink for CsvLogSink {
+    fn name(&self) -> &str {
+        SINK_NAME
+    }
+
+    fn handle_sensor_data(&self, reading: &SensorData) {
+        self.queue(Row::Sensor(sensor_row(reading)));
+    }
+
+    fn handle_weather_data(&self, weather: &WeatherData) {
+        if self.include_weather {
+            self.queue(Row::Weather(weather_row(weather)));
+        }
+    }
+
+    // Writes the queued rows, then stops the worker
+    async fn shutdown(&self) {
+        info!("Stopping CSV log");
+        let _ = self.sender.send(Row::Shutdown).await;
+        let handle = self.worker_handle.lock().ok().and_then(|mut guard| guard.take());
+        if let Some(mut handle) = handle {
+            if tokio::time::timeout(Duration::from_secs(SHUTDOWN_TIMEOUT_SECS), &mut handle).await.is_err() {
+                warn!("CSV log did not finish writing in time, aborting");
+                handle.abort();
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::config::ConfigManager;
+use crate::events::{self, AppEvent};
+use crate::notifications::AlertChannels;
+use crate::history::{enum_text, MetricSummary, SensorHistory};
+use crate::types::{AlertLevel, WeatherData};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, error, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailySummary {
+    // Local calendar day
+    pub date: NaiveDate,
+    pub samples: u64,
+    pub temperature: Option<MetricSummary>,
+    pub humidity: Option<MetricSummary>,
+    pub pressure: Option<MetricSummary>,
+    pub co2: Option<MetricSummary>,
+    pub tvoc: Option<MetricSummary>,
+    pub lux: Option<MetricSummary>,
+    // Day total from the last weather report recorded that day
+    pub rain_mm: Option<f64>,
+    // Warning and emergency alerts sent or received that day
+    pub alerts: Vec<String>,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl DailySummary {
+    pub(crate) fn notification_body(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(temperature) = &self.temperature {
+            parts.push(format!("{:.1}–{:.1}°C (avg {:.1})", temperature.min, temperature.max, temperature.avg));
+        }
+        if let Some(humidity) = &self.humidity {
+            parts.push(format!("humidity avg {:.0}%", humidity.avg));
+        }
+        if let Some(rain) = self.rain_mm.filter(|rain| *rain > 0.0) {
+            parts.push(format!("rain {:.1} mm", rain));
+        }
+        if !self.alerts.is_empty() {
+            parts.push(format!("{} alert(s)", self.alerts.len()));
+        }
+        if parts.is_empty() {
+            "No readings recorded".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+// Stored summary for `date`, or one computed on the fly for days not summarised yet
+pub fn get(history: &SensorHistory, date: NaiveDate) -> Result<DailySummary> {
+    match load(history, date)? {
+        Some(summary) => Ok(summary),
+        None => generate(history, date),
+    }
+}
+
+pub fn generate(history: &SensorHistory, date: NaiveDate) -> Result<DailySummary> {
+    let (from_ms, to_ms) = day_bounds(date)?;
+    let level_warning = enum_text(&AlertLevel::Warning);
+    let level_emergency = enum_text(&AlertLevel::Emergency);
+
+    history.with_connection(|connection| {
+        let summary_of = |expression: &str| -> rusqlite::Result<Option<MetricSummary>> {
+            connection.query_row(
+                &format!(
+                    "SELECT AVG({e}), MIN({e}), MAX({e}) FROM sensor_readings WHERE recorded_at >= ?1 AND recorded_at < ?2",
+                    e = expression
+                ),
+                params![from_ms, to_ms],
+                |row| Ok(row.get::<_, Option<f64>>(0)?.zip(row.get::<_, Option<f64>>(1)?).zip(row.get::<_, Option<f64>>(2)?)),
+            ).map(|values| values.map(|((avg, min), max)| MetricSummary { avg, min, max }))
+        };
+        let hourly_summary_of = |metric: &str| -> rusqlite::Result<Option<MetricSummary>> {
+            connection.query_row(
+                &format!(
+                    "SELECT SUM({m}_avg * samples) / SUM(samples), MIN({m}_min), MAX({m}_max)
+                     FROM sensor_hourly WHERE hour_start >= ?1 AND hour_start < ?2",
+                    m = metric
+                ),
+                params![from_ms, to_ms],
+                |row| Ok(row.get::<_, Option<f64>>(0)?.zip(row.get::<_, Option<f64>>(1)?).zip(row.get::<_, Option<f64>>(2)?)),
+            ).map(|values| values.map(|((avg, min), max)| MetricSummary { avg, min, max }))
+        };
+
+        let raw_samples: i64 = connection.query_row(
+            "SELECT COUNT(*) FROM sensor_readings WHERE recorded_at >= ?1 AND recorded_at < ?2",
+            params![from_ms, to_ms],
+            |row| row.get(0),
+        )?;
+        // Days whose raw readings were pruned are summarised from the hourly aggregates
+        let (samples, metric): (i64, &dyn Fn(&str) -> rusqlite::Result<Option<MetricSummary>>) = if raw_samples > 0 {
+            (raw_samples, &summary_of)
+        } else {
+            let hourly_samples: i64 = connection.query_row(
+                "SELECT COALESCE(SUM(samples), 0) FROM sensor_hourly WHERE hour_start >= ?1 AND hour_start < ?2",
+                params![from_ms, to_ms],
+                |row| row.get(0),
+            )?;
+            (hourly_samples, &hourly_summary_of)
+        };
+
+        let last_weather: Option<String> = connection.query_row(
+            "SELECT payload FROM weather_snapshots WHERE recorded_at >= ?1 AND recorded_at < ?2
+             ORDER BY recorded_at DESC LIMIT 1",
+            params![from_ms, to_ms],
+            |row| row.get(0),
+        ).optional()?;
+        let rain_mm = last_weather
+            .and_then(|payload| serde_json::from_str::<WeatherData>(&payload).ok())
+            .and_then(|weather| weather.forecast.first().map(|day| day.rain_mm));
+
+        let mut statement = connection.prepare(
+            "SELECT message FROM alert_history
+             WHERE recorded_at >= ?1 AND recorded_at < ?2 AND level IN (?3, ?4)
+             ORDER BY recorded_at ASC",
+        )?;
+        let alerts = statement
+            .query_map(params![from_ms, to_ms, level_warning, level_emergency], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        Ok(DailySummary {
+            date,
+            samples: samples as u64,
+            temperature: metric("temperature")?,
+            humidity: metric("humidity")?,
+            pressure: metric("pressure")?,
+            co2: metric("co2")?,
+            tvoc: metric("tvoc")?,
+            lux: metric("lux")?,
+            rain_mm,
+            alerts,
+            generated_at: Utc::now(),
+        })
+    })
+}
+
+fn load(history: &SensorHistory, date: NaiveDate) -> Result<Option<DailySummary>> {
+    let payload: Option<String> = history.with_connection(|connection| {
+        connection.query_row(
+            "SELECT payload FROM daily_summaries WHERE date = ?1",
+            params![date.to_string()],
+            |row| row.get(0),
+        ).optional()
+    })?;
+    Ok(payload.and_then(|payload| serde_json::from_str(&payload).ok()))
+}
+
+fn store(history: &SensorHistory, summary: &DailySummary) -> Result<()> {
+    let payload = serde_json::to_string(summary)?;
+    history.with_connection(|connection| {
+        connection.execute(
+            "INSERT OR REPLACE INTO daily_summaries (date, generated_at, payload) VALUES (?1, ?2, ?3)",
+            params![summary.date.to_string(), summary.generated_at.timestamp_millis(), payload],
+        )
+    })?;
+    Ok(())
+}
+
+// Local midnight to local midnight, in ms
+fn day_bounds(date: NaiveDate) -> Result<(i64, i64)> {
+    let midnight = |date: NaiveDate| {
+        Local.from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|t| t.timestamp_millis())
+            .ok_or_else(|| anyhow!("Midnight doesn't exist on {} in the local time zone", date))
+    };
+    Ok((midnight(date)?, midnight(date + chrono::Duration::days(1))?))
+}
+
+// Generates, stores and announces the day's summary once the configured local time has
+// passed. That one only covers the day so far, so it is regenerated quietly once the day
+// is over, as is a summary for yesterday that was missed. Called by the scheduler on
+// startup and every minute.
+pub async fn run_due(
+    config_manager: &Arc<Mutex<ConfigManager>>,
+    history: &Arc<SensorHistory>,
+    alert_channels: &AlertChannels,
+) {
+    let yesterday = Local::now().date_naive() - chrono::Duration::days(1);
+    if let Err(e) = complete(history, yesterday).await {
+        warn!("Failed to complete the daily summary for {}: {}", yesterday, e);
+    }
+
+    let settings = config_manager.lock().await.get_config().app.daily_summary.clone();
+    if !settings.enabled {
+        return;
+    }
+    let Ok(time) = NaiveTime::parse_from_str(&settings.time, "%H:%M") else {
+        warn!("Invalid daily summary time '{}', expected HH:MM", settings.time);
+        return;
+    };
+
+    let now = Local::now();
+    let today = now.date_naive();
+    if now.time() >= time && !matches!(load(history, today), Ok(Some(_))) {
+        if let Err(e) = generate_and_send(history, alert_channels, today).await {
+            error!("Failed to generate daily summary: {}", e);
+        }
+    }
+}
+
+async fn run(history: &Arc<SensorHistory>, date: NaiveDate) -> Result<DailySummary> {
+    let history = Arc::clone(history);
+    tokio::task::spawn_blocking(move || {
+        let summary = generate(&history, date)?;
+        store(&history, &summary)?;
+        info!("Generated daily summary for {}", date);
+        Ok(summary)
+    }).await?
+}
+
+// Regenerates the stored summary for `date` unless it was made after the day ended
+async fn complete(history: &Arc<SensorHistory>, date: NaiveDate) -> Result<()> {
+    let (_, day_end) = day_bounds(date)?;
+    let stored = load(history, date)?;
+    if stored.as_ref().is_some_and(|summary| summary.generated_at.timestamp_millis() >= day_end) {
+        return Ok(());
+    }
+    let summary = run(history, date).await?;
+    if stored.is_none() && summary.samples == 0 {
+        info!("No readings recorded on {}", date);
+    }
+    Ok(())
+}
+
+// Generates and stores the summary, then sends it out on every channel
+pub async fn generate_and_send(
+    history: &Arc<SensorHistory>,
+    alert_channels: &AlertChannels,
+    date: NaiveDate,
+) -> Result<DailySummary> {
+    let summary = run(history, date).await?;
+    announce(alert_channels, &summary);
+    alert_channels.send_daily_summary(&summary);
+    Ok(summary)
+}
+
+fn announce(alert_channels: &AlertChannels, summary: &DailySummary) {
+    events::publish(AppEvent::DailySummary(summary.clone()));
+    alert_channels.notify_desktop(
+        &AlertLevel::Info,
+        &format!("Weather summary for {}", summary.date.format("%d/%m")),
+        &summary.notification_body(),
+    );
+}
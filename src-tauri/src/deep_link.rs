@@ -0,0 +1,59 @@
+use crate::types::AlertLevel;
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use url::Url;
+
+pub const SCHEME: &str = "weatherstation";
+// Keeps a link from pushing an essay to the device display
+const MAX_MESSAGE_LEN: usize = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeepLinkAction {
+    // weatherstation://alert?level=warning&msg=Door%20open
+    Alert { level: AlertLevel, message: String },
+    // weatherstation://location?lat=48.7758&lon=9.1829
+    Coordinates { lat: f64, lon: f64 },
+    // weatherstation://location?name=Home selects a saved location
+    Location(String),
+}
+
+pub fn parse(url: &Url) -> Result<DeepLinkAction> {
+    if url.scheme() != SCHEME {
+        return Err(anyhow!("Not a {}:// link", SCHEME));
+    }
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    // "weatherstation://alert" has the action as its host, "weatherstation:alert" as its path
+    let action = url.host_str().unwrap_or_else(|| url.path().trim_matches('/'));
+
+    match action {
+        "alert" => {
+            let message = query.get("msg").or_else(|| query.get("message")).map(|m| m.trim()).unwrap_or_default();
+            if message.is_empty() {
+                return Err(anyhow!("Alert link needs a msg parameter"));
+            }
+            let level = match query.get("level") {
+                Some(level) => serde_json::from_value(serde_json::Value::String(level.to_lowercase()))
+                    .map_err(|_| anyhow!("Unknown alert level: {}", level))?,
+                None => AlertLevel::Info,
+            };
+            Ok(DeepLinkAction::Alert { level, message: message.chars().take(MAX_MESSAGE_LEN).collect() })
+        }
+        "location" => {
+            if let Some(name) = query.get("name") {
+                return Ok(DeepLinkAction::Location(name.clone()));
+            }
+            let coordinate = |key: &str, limit: f64| -> Result<f64> {
+                let value: f64 = query.get(key)
+                    .ok_or_else(|| anyhow!("Location link needs name, or lat and lon"))?
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid {}", key))?;
+                if !value.is_finite() || value.abs() > limit {
+                    return Err(anyhow!("{} out of range: {}", key, value));
+                }
+                Ok(value)
+            };
+            Ok(DeepLinkAction::Coordinates { lat: coordinate("lat", 90.0)?, lon: coordinate("lon", 180.0)? })
+        }
+        other => Err(anyhow!("Unknown action '{}'", other)),
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::time::Duration;
+
+// How long a QoS1 publish may wait for its PubAck before it's considered failed
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RECORDS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Queued,
+    Sent,
+    Confirmed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    pub message_id: u64,
+    pub topic: String,
+    pub status: DeliveryStatus,
+    pub queued_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryEvent {
+    pub message_id: u64,
+    pub topic: String,
+    pub error: Option<String>,
+}
+
+impl From<&DeliveryRecord> for DeliveryEvent {
+    fn from(record: &DeliveryRecord) -> Self {
+        Self {
+            message_id: record.message_id,
+            topic: record.topic.clone(),
+            error: record.error.clone(),
+        }
+    }
+}
+
+// Tracks QoS1 publishes from request to PubAck.
+//
+// rumqttc doesn't hand back packet ids from publish(), but it reports
+// Outgoing::Publish(pkid) in request order, so queued messages are matched
+// to packet ids as they leave the client. That only holds while every QoS1
+// publish on the client is registered or reserved here, under the same lock
+// as the client call; anything else goes out at QoS0.
+#[derive(Default)]
+pub struct DeliveryTracker {
+    next_id: u64,
+    // None for a reserved publish that isn't tracked
+    awaiting_pkid: VecDeque<Option<u64>>,
+    inflight: HashMap<u16, Option<u64>>,
+    // Packet ids in flight when the connection dropped, which a resumed session
+    // sends again
+    resent: HashSet<u16>,
+    records: VecDeque<DeliveryRecord>,
+}
+
+impl DeliveryTracker {
+    pub fn register(&mut self, topic: &str) -> u64 {
+        self.next_id += 1;
+        let now = Utc::now();
+        self.awaiting_pkid.push_back(Some(self.next_id));
+        self.records.push_back(DeliveryRecord {
+            message_id: self.next_id,
+            topic: topic.to_string(),
+            status: DeliveryStatus::Queued,
+            queued_at: now,
+            updated_at: now,
+            error: None,
+        });
+
+        while self.records.len() > MAX_RECORDS {
+            self.records.pop_front();
+        }
+
+        self.next_id
+    }
+
+    // Holds a place in the packet id order for a QoS1 publish without a delivery record
+    pub fn reserve(&mut self) {
+        self.awaiting_pkid.push_back(None);
+    }
+
+    // Takes back the last registered or reserved publish when the client refused it
+    pub fn cancel_last(&mut self, error: &str) -> Option<DeliveryRecord> {
+        let message_id = self.awaiting_pkid.pop_back()??;
+        self.update(message_id, DeliveryStatus::Failed, Some(error.to_string()))
+    }
+
+    pub fn mark_sent(&mut self, pkid: u16) {
+        // QoS0 publishes carry pkid 0 and are never tracked. A packet id already in
+        // flight is a retransmission, not a new publish.
+        if pkid == 0 || self.inflight.contains_key(&pkid) || self.resent.remove(&pkid) {
+            return;
+        }
+
+        if let Some(message_id) = self.awaiting_pkid.pop_front() {
+            self.inflight.insert(pkid, message_id);
+            if let Some(message_id) = message_id {
+                self.update(message_id, DeliveryStatus::Sent, None);
+            }
+        }
+    }
+
+    pub fn confirm(&mut self, pkid: u16) -> Option<DeliveryRecord> {
+        self.resent.remove(&pkid);
+        let message_id = self.inflight.remove(&pkid)??;
+        self.update(message_id, DeliveryStatus::Confirmed, None)
+    }
+
+    pub fn fail(&mut self, message_id: u64, error: &str) -> Option<DeliveryRecord> {
+        self.awaiting_pkid.retain(|id| *id != Some(message_id));
+        self.inflight.retain(|_, id| *id != Some(message_id));
+        self.update(message_id, DeliveryStatus::Failed, Some(error.to_string()))
+    }
+
+    // Fails everything not yet acknowledged. Publishes still queued in the client go
+    // out after the reconnect in no order that can be matched up, and a resumed
+    // session resends the in-flight ones under their old packet ids.
+    pub fn connection_lost(&mut self) -> Vec<DeliveryRecord> {
+        self.resent.extend(self.inflight.keys().copied());
+        let pending: Vec<u64> = self.awaiting_pkid.drain(..)
+            .chain(self.inflight.drain().map(|(_, message_id)| message_id))
+            .flatten()
+            .collect();
+        pending.into_iter()
+            .filter_map(|message_id| {
+                self.update(message_id, DeliveryStatus::Failed, Some("Connection lost before acknowledgement".to_string()))
+            })
+            .collect()
+    }
+
+    // Nothing is resent when the broker started a clean session
+    pub fn connected(&mut self, session_present: bool) {
+        if !session_present {
+            self.resent.clear();
+        }
+    }
+
+    // Fails every message that has waited longer than ACK_TIMEOUT
+    pub fn expire_stale(&mut self) -> Vec<DeliveryRecord> {
+        let cutoff = Utc::now() - chrono::Duration::seconds(ACK_TIMEOUT.as_secs() as i64);
+        let stale: Vec<u64> = self.records.iter()
+            .filter(|r| matches!(r.status, DeliveryStatus::Queued | DeliveryStatus::Sent) && r.updated_at < cutoff)
+            .map(|r| r.message_id)
+            .collect();
+
+        stale.into_iter()
+            .filter_map(|id| self.fail(id, "No acknowledgement received"))
+            .collect()
+    }
+
+    pub fn get(&self, message_id: u64) -> Option<DeliveryRecord> {
+        self.records.iter().find(|r| r.message_id == message_id).cloned()
+    }
+
+    pub fn records(&self) -> Vec<DeliveryRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    fn update(&mut self, message_id: u64, status: DeliveryStatus, error: Option<String>) -> Option<DeliveryRecord> {
+        let record = self.records.iter_mut().find(|r| r.message_id == message_id)?;
+        record.status = status;
+        record.updated_at = Utc::now();
+        record.error = error;
+        Some(record.clone())
+    }
+}
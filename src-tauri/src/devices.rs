@@ -0,0 +1,168 @@
+use crate::types::DeviceTelemetry;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DEVICE_STATUS_TOPIC: &str = "weather/devices/+/status";
+pub const DEVICE_TELEMETRY_TOPIC: &str = "weather/devices/+/telemetry";
+pub const DEVICE_ACK_TOPIC: &str = "weather/devices/+/ack";
+pub const DEVICE_BUTTON_TOPIC: &str = "weather/devices/+/button";
+
+pub fn device_topic(device_id: &str, kind: &str) -> String {
+    format!("weather/devices/{}/{}", device_id, kind)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub device_id: String,
+    pub online: bool,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    // Last heartbeat payload as published by the device
+    pub last_status: Option<serde_json::Value>,
+    pub telemetry: Option<DeviceTelemetry>,
+    pub low_battery: bool,
+    // Reported by the device in its heartbeat
+    pub firmware_version: Option<String>,
+    // User-assigned metadata from the device settings
+    pub name: Option<String>,
+    pub location: Option<String>,
+    // Set while the device's alerts are muted
+    pub muted_until: Option<DateTime<Utc>>,
+}
+
+pub struct TelemetryUpdate {
+    pub state_change: Option<DeviceInfo>,
+    // Set only when the battery first drops below the threshold
+    pub low_battery: bool,
+}
+
+#[derive(Default)]
+pub struct DeviceRegistry {
+    devices: HashMap<String, DeviceInfo>,
+}
+
+// Extracts the device id from weather/devices/<id>/<kind>
+pub fn device_id_from_topic<'a>(topic: &'a str, kind: &str) -> Option<&'a str> {
+    let id = topic.strip_prefix("weather/devices/")?.strip_suffix(kind)?.strip_suffix('/')?;
+    if id.is_empty() || id.contains('/') {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+impl DeviceRegistry {
+    // Records a heartbeat and returns the device if its online state changed
+    pub fn record_heartbeat(&mut self, device_id: &str, payload: &[u8]) -> Option<DeviceInfo> {
+        let now = Utc::now();
+        let status = serde_json::from_slice::<serde_json::Value>(payload).ok();
+
+        // A last-will "offline" message means the device dropped off the broker
+        if Self::is_offline_payload(payload, &status) {
+            return self.mark_offline(device_id);
+        }
+
+        let came_online = self.touch(device_id, now);
+        if let Some(device) = self.devices.get_mut(device_id) {
+            let firmware = status.as_ref()
+                .and_then(|s| s.get("firmware_version").or_else(|| s.get("firmware")))
+                .and_then(|f| f.as_str());
+            if let Some(firmware) = firmware {
+                device.firmware_version = Some(firmware.to_string());
+            }
+            device.last_status = status;
+        }
+        came_online
+    }
+
+    pub fn record_telemetry(&mut self, telemetry: DeviceTelemetry, low_battery_threshold: f64) -> TelemetryUpdate {
+        let state_change = self.touch(&telemetry.device_id, Utc::now());
+        let mut low_battery = false;
+
+        if let Some(device) = self.devices.get_mut(&telemetry.device_id) {
+            let is_low = telemetry.battery_percent
+                .map(|percent| percent < low_battery_threshold && telemetry.charging != Some(true))
+                .unwrap_or(false);
+            low_battery = is_low && !device.low_battery;
+            device.low_battery = is_low;
+            device.telemetry = Some(telemetry);
+        }
+
+        TelemetryUpdate {
+            state_change,
+            low_battery,
+        }
+    }
+
+    // Updates last_seen and returns the device if it just came online
+    fn touch(&mut self, device_id: &str, now: DateTime<Utc>) -> Option<DeviceInfo> {
+        let device = self.devices.entry(device_id.to_string()).or_insert_with(|| DeviceInfo {
+            device_id: device_id.to_string(),
+            online: false,
+            first_seen: now,
+            last_seen: now,
+            last_status: None,
+            telemetry: None,
+            low_battery: false,
+            firmware_version: None,
+            name: None,
+            location: None,
+            muted_until: None,
+        });
+
+        let came_online = !device.online;
+        device.online = true;
+        device.last_seen = now;
+
+        if came_online {
+            Some(device.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn mark_offline(&mut self, device_id: &str) -> Option<DeviceInfo> {
+        let device = self.devices.get_mut(device_id)?;
+        if !device.online {
+            return None;
+        }
+        device.online = false;
+        Some(device.clone())
+    }
+
+    // Marks devices silent for longer than the timeout as offline and returns them
+    pub fn expire(&mut self, timeout_secs: u64) -> Vec<DeviceInfo> {
+        let cutoff = Utc::now() - chrono::Duration::seconds(timeout_secs as i64);
+        self.devices.values_mut()
+            .filter(|device| device.online && device.last_seen < cutoff)
+            .map(|device| {
+                device.online = false;
+                device.clone()
+            })
+            .collect()
+    }
+
+    // True when any online device is discharging below the threshold
+    pub fn any_low_battery(&self, threshold_percent: f64) -> bool {
+        self.devices.values().any(|device| {
+            device.online && device.telemetry.as_ref().map_or(false, |t| {
+                t.charging != Some(true) && t.battery_percent.map_or(false, |p| p < threshold_percent)
+            })
+        })
+    }
+
+    pub fn list(&self) -> Vec<DeviceInfo> {
+        let mut devices: Vec<DeviceInfo> = self.devices.values().cloned().collect();
+        devices.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        devices
+    }
+
+    fn is_offline_payload(payload: &[u8], status: &Option<serde_json::Value>) -> bool {
+        match status {
+            Some(value) => value.get("status").and_then(|s| s.as_str()) == Some("offline")
+                || value.as_str() == Some("offline"),
+            None => payload.eq_ignore_ascii_case(b"offline"),
+        }
+    }
+}
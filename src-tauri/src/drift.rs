@@ -0,0 +1,136 @@
+use crate::forecasting::sea_level_pressure;
+use crate::history::SensorHistory;
+use crate::types::{UnitSystem, WeatherData};
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+// How far the sensor reading paired with an API snapshot may be from it
+const PAIRING_TOLERANCE_MS: i64 = 15 * 60 * 1000;
+// Mean drift beyond which the sensor likely needs recalibrating
+const TEMPERATURE_THRESHOLD: f64 = 2.0;
+const HUMIDITY_THRESHOLD: f64 = 10.0;
+const PRESSURE_THRESHOLD: f64 = 3.0;
+
+// Sensor minus API at one point in time. Temperatures in °C, pressure at sea level.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftPoint {
+    pub at: DateTime<Utc>,
+    pub sensor_temperature: f64,
+    pub api_temperature: f64,
+    pub temperature: f64,
+    pub sensor_humidity: f64,
+    pub api_humidity: f64,
+    pub humidity: f64,
+    pub sensor_pressure: f64,
+    pub api_pressure: f64,
+    pub pressure: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricDrift {
+    // Signed mean, positive when the sensor reads high
+    pub mean: f64,
+    pub mean_abs: f64,
+    pub max_abs: f64,
+    pub needs_recalibration: bool,
+}
+
+impl MetricDrift {
+    fn from_differences(differences: impl Iterator<Item = f64> + Clone, threshold: f64) -> Option<Self> {
+        let count = differences.clone().count();
+        if count == 0 {
+            return None;
+        }
+        let mean = differences.clone().sum::<f64>() / count as f64;
+        Some(Self {
+            mean,
+            mean_abs: differences.clone().map(f64::abs).sum::<f64>() / count as f64,
+            max_abs: differences.map(f64::abs).fold(0.0, f64::max),
+            needs_recalibration: mean.abs() > threshold,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftComparison {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub points: Vec<DriftPoint>,
+    pub temperature: Option<MetricDrift>,
+    pub humidity: Option<MetricDrift>,
+    pub pressure: Option<MetricDrift>,
+}
+
+// Pairs every recorded API snapshot in the window with the closest sensor reading.
+// The sensor's station pressure is reduced to sea level using `altitude_m` so it
+// compares with the provider's figure.
+pub fn compare(
+    history: &SensorHistory,
+    device_id: Option<&str>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    altitude_m: f64,
+) -> Result<DriftComparison> {
+    let points = history.with_connection(|connection| {
+        let mut snapshots = connection.prepare(
+            "SELECT recorded_at, payload FROM weather_snapshots
+             WHERE recorded_at BETWEEN ?1 AND ?2 ORDER BY recorded_at ASC",
+        )?;
+        let mut nearest = connection.prepare(
+            "SELECT temperature, humidity, pressure FROM sensor_readings
+             WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3
+             ORDER BY ABS(recorded_at - ?4) ASC LIMIT 1",
+        )?;
+
+        let rows = snapshots
+            .query_map(params![from.timestamp_millis(), to.timestamp_millis()], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut points = Vec::new();
+        for (recorded_at, payload) in rows {
+            let Ok(weather) = serde_json::from_str::<WeatherData>(&payload) else {
+                continue;
+            };
+            let Some((temperature, humidity, pressure)) = nearest.query_row(
+                params![device_id, recorded_at - PAIRING_TOLERANCE_MS, recorded_at + PAIRING_TOLERANCE_MS, recorded_at],
+                |row| Ok((row.get::<_, f64>(0)?, row.get::<_, f64>(1)?, row.get::<_, f64>(2)?)),
+            ).optional()? else {
+                continue;
+            };
+            let Some(at) = Utc.timestamp_millis_opt(recorded_at).single() else {
+                continue;
+            };
+
+            let api_temperature = weather.units.convert_temp(weather.current_temp, UnitSystem::Metric);
+            let api_humidity = weather.humidity as f64;
+            let api_pressure = weather.pressure as f64;
+            let sensor_pressure = sea_level_pressure(pressure, temperature, altitude_m);
+            points.push(DriftPoint {
+                at,
+                sensor_temperature: temperature,
+                api_temperature,
+                temperature: temperature - api_temperature,
+                sensor_humidity: humidity,
+                api_humidity,
+                humidity: humidity - api_humidity,
+                sensor_pressure,
+                api_pressure,
+                pressure: sensor_pressure - api_pressure,
+            });
+        }
+        Ok(points)
+    })?;
+
+    Ok(DriftComparison {
+        from,
+        to,
+        temperature: MetricDrift::from_differences(points.iter().map(|p| p.temperature), TEMPERATURE_THRESHOLD),
+        humidity: MetricDrift::from_differences(points.iter().map(|p| p.humidity), HUMIDITY_THRESHOLD),
+        pressure: MetricDrift::from_differences(points.iter().map(|p| p.pressure), PRESSURE_THRESHOLD),
+        points,
+    })
+}
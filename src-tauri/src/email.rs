@@ -0,0 +1,170 @@
+use crate::config::{EmailSettings, SmtpSecurity};
+use crate::daily_summary::DailySummary;
+use crate::types::{AlertData, AlertLevel};
+use anyhow::{Result, anyhow};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::fmt::Write;
+use std::sync::RwLock;
+use tokio::time::Duration;
+use tracing::{info, error};
+
+// Emails alerts at or above the configured level, and the daily summary
+#[derive(Default)]
+pub struct EmailNotifier {
+    settings: RwLock<EmailSettings>,
+}
+
+impl EmailNotifier {
+    pub fn set_settings(&self, settings: EmailSettings) {
+        if let Ok(mut guard) = self.settings.write() {
+            *guard = settings;
+        }
+    }
+
+    // Sends in the background so alert handling never waits on SMTP
+    pub fn notify_alert(&self, alert: &AlertData) {
+        let Some(settings) = self.active_settings() else {
+            return;
+        };
+        if alert.level < settings.min_level {
+            return;
+        }
+
+        let (subject, body) = alert_email(alert);
+        tokio::spawn(async move {
+            if let Err(e) = send(&settings, &subject, body).await {
+                error!("Failed to email alert: {}", e);
+            }
+        });
+    }
+
+    pub fn notify_daily_summary(&self, summary: &DailySummary) {
+        let Some(settings) = self.active_settings().filter(|settings| settings.send_daily_summary) else {
+            return;
+        };
+
+        let subject = format!("Weather summary for {}", summary.date);
+        let body = summary_body(summary);
+        tokio::spawn(async move {
+            if let Err(e) = send(&settings, &subject, body).await {
+                error!("Failed to email daily summary: {}", e);
+            }
+        });
+    }
+
+    // Sends a test message using `settings`, which need not be saved or enabled yet
+    pub async fn send_test(&self, settings: &EmailSettings) -> Result<()> {
+        send(settings, "Weather station test email", "Email notifications are set up correctly.\n".to_string()).await
+    }
+
+    pub fn settings(&self) -> EmailSettings {
+        self.settings.read().map(|guard| guard.clone()).unwrap_or_default()
+    }
+
+    // Emails the alert with the saved settings and waits for the result
+    pub async fn send_alert_now(&self, alert: &AlertData) -> Result<()> {
+        let (subject, body) = alert_email(alert);
+        send(&self.settings(), &subject, body).await
+    }
+
+    fn active_settings(&self) -> Option<EmailSettings> {
+        self.settings.read().ok()
+            .map(|guard| guard.clone())
+            .filter(|settings| settings.enabled && !settings.recipients.is_empty())
+    }
+}
+
+async fn send(settings: &EmailSettings, subject: &str, body: String) -> Result<()> {
+    if settings.host.is_empty() {
+        return Err(anyhow!("SMTP host is not configured"));
+    }
+    if settings.recipients.is_empty() {
+        return Err(anyhow!("No email recipients configured"));
+    }
+
+    let mut builder = Message::builder()
+        .from(settings.from.parse::<Mailbox>().map_err(|e| anyhow!("Invalid sender address {}: {}", settings.from, e))?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in &settings.recipients {
+        builder = builder.to(recipient.parse::<Mailbox>().map_err(|e| anyhow!("Invalid recipient {}: {}", recipient, e))?);
+    }
+    let message = builder.body(body)?;
+
+    let mut transport = match settings.security {
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+        SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?,
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host),
+    }
+    .port(settings.port)
+    .timeout(Some(Duration::from_secs(settings.timeout_secs.max(1))));
+    if let Some(username) = &settings.username {
+        transport = transport.credentials(Credentials::new(username.clone(), settings.password.clone().unwrap_or_default()));
+    }
+
+    transport.build().send(message).await?;
+    info!("Sent email \"{}\" to {} recipient(s)", subject, settings.recipients.len());
+    Ok(())
+}
+
+fn alert_email(alert: &AlertData) -> (String, String) {
+    let subject = format!("Weather station {}: {}", level_label(&alert.level), truncate(&alert.message, 60));
+    let body = format!(
+        "{}\n\nLevel: {}\nTime: {}\n",
+        alert.message,
+        level_label(&alert.level),
+        alert.timestamp.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+    );
+    (subject, body)
+}
+
+fn summary_body(summary: &DailySummary) -> String {
+    let mut body = format!("Weather station summary for {}\n\n", summary.date);
+    let metrics = [
+        ("Temperature", &summary.temperature, "°C"),
+        ("Humidity", &summary.humidity, "%"),
+        ("Pressure", &summary.pressure, " hPa"),
+        ("CO2", &summary.co2, " ppm"),
+        ("TVOC", &summary.tvoc, " ppb"),
+        ("Light", &summary.lux, " lx"),
+    ];
+    for (label, metric, unit) in metrics {
+        if let Some(metric) = metric {
+            let _ = writeln!(
+                body,
+                "{}: min {:.1}{unit}, max {:.1}{unit}, avg {:.1}{unit}",
+                label, metric.min, metric.max, metric.avg
+            );
+        }
+    }
+    if let Some(rain) = summary.rain_mm {
+        let _ = writeln!(body, "Rain: {:.1} mm", rain);
+    }
+    let _ = writeln!(body, "Readings: {}", summary.samples);
+
+    if !summary.alerts.is_empty() {
+        let _ = writeln!(body, "\nAlerts:");
+        for alert in &summary.alerts {
+            let _ = writeln!(body, "- {}", alert);
+        }
+    }
+    body
+}
+
+fn level_label(level: &AlertLevel) -> &'static str {
+    match level {
+        AlertLevel::Info => "info",
+        AlertLevel::Warning => "warning",
+        AlertLevel::Emergency => "emergency",
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
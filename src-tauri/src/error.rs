@@ -0,0 +1,69 @@
+use crate::api_usage::{BudgetExceeded, RateLimited};
+use crate::config_validation::{ConfigFieldError, ConfigValidationError};
+use crate::mqtt_client::NotConnected;
+use crate::weather_api::ApiKeyMissing;
+use serde::Serialize;
+use std::fmt;
+
+// Error returned by every Tauri command. Serialized as {"kind": "...", "message": "...", ...}
+// so the frontend can branch on the kind and still show the message as is.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AppError {
+    MqttNotConnected { message: String },
+    ApiQuotaExceeded { message: String },
+    ApiKeyMissing { message: String },
+    // field is the first invalid setting's dotted path, errors has all of them
+    ConfigInvalid { field: String, message: String, errors: Vec<ConfigFieldError> },
+    DeviceTimeout { message: String },
+    DeviceRejected { message: String },
+    NotFound { message: String },
+    Failed { message: String },
+}
+
+impl AppError {
+    // Classifies an error from the backend by its source type, prefixing the message with
+    // what the command was doing
+    pub fn from_error(context: &str, error: impl Into<anyhow::Error>) -> Self {
+        let error = error.into();
+        let message = format!("{}: {}", context, error);
+        if let Some(invalid) = error.downcast_ref::<ConfigValidationError>() {
+            let field = invalid.0.first().map(|e| e.path.clone()).unwrap_or_default();
+            return AppError::ConfigInvalid { field, message, errors: invalid.0.clone() };
+        }
+        if error.downcast_ref::<NotConnected>().is_some() {
+            AppError::MqttNotConnected { message }
+        } else if error.downcast_ref::<BudgetExceeded>().is_some() || error.downcast_ref::<RateLimited>().is_some() {
+            AppError::ApiQuotaExceeded { message }
+        } else if error.downcast_ref::<ApiKeyMissing>().is_some() {
+            AppError::ApiKeyMissing { message }
+        } else {
+            AppError::Failed { message }
+        }
+    }
+
+    pub fn failed(message: impl Into<String>) -> Self {
+        AppError::Failed { message: message.into() }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::MqttNotConnected { message }
+            | AppError::ApiQuotaExceeded { message }
+            | AppError::ApiKeyMissing { message }
+            | AppError::ConfigInvalid { message, .. }
+            | AppError::DeviceTimeout { message }
+            | AppError::DeviceRejected { message }
+            | AppError::NotFound { message }
+            | AppError::Failed { message } => message,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
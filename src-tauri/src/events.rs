@@ -0,0 +1,298 @@
+use crate::app_error::AppErrorEvent;
+use crate::autostart;
+use crate::config::{AppConfig, SensorEventMode, SensorEventSettings};
+use crate::daily_summary::DailySummary;
+use crate::delivery::DeliveryEvent;
+use crate::devices::DeviceInfo;
+use crate::mqtt_client::{AlertAcknowledgedEvent, ConnectionLostEvent, SensorStaleEvent};
+use crate::startup::StartupProgress;
+use crate::types::*;
+use crate::weather_api::ProviderDegradedEvent;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+// Enough for a burst of device and delivery events while the webview is busy
+const EVENT_BUS_CAPACITY: usize = 256;
+const DESKTOP_ACTION_CAPACITY: usize = 64;
+
+static BUS: OnceLock<broadcast::Sender<AppEvent>> = OnceLock::new();
+static DESKTOP_ACTIONS: OnceLock<(mpsc::Sender<DesktopAction>, Mutex<Option<mpsc::Receiver<DesktopAction>>>)> = OnceLock::new();
+static FORWARDER_READY: OnceLock<watch::Sender<bool>> = OnceLock::new();
+
+// Mirror AppSettings.sensor_events for the forwarder, which can't wait on the config lock
+static SENSOR_EVENT_INTERVAL_MS: AtomicU64 = AtomicU64::new(0);
+static SENSOR_EVENT_BATCH: AtomicBool = AtomicBool::new(false);
+
+// Everything the backend tells the webview about. Background tasks publish these
+// without holding an AppHandle; the forwarder owns the handle and emits them.
+#[derive(Debug, Clone)]
+pub enum AppEvent {
+    SensorDataUpdated(SensorData),
+    AnomalousReading(SensorData),
+    SensorDataStale(SensorStaleEvent),
+    WeatherDataUpdated(WeatherData),
+    WeatherLocationChanged(LocationChange),
+    WeatherProviderDegraded(ProviderDegradedEvent),
+    WeatherProviderRestored(String),
+    WeatherAlert(WeatherAlert),
+    AlertReceived(AlertData),
+    AlertAcknowledged(AlertAcknowledgedEvent),
+    ForecastWarning(AlertData),
+    RainAlert(AlertData),
+    DailySummary(DailySummary),
+    MqttReconnected,
+    MqttConnectionLost(ConnectionLostEvent),
+    PublishConfirmed(DeliveryEvent),
+    PublishFailed(DeliveryEvent),
+    DeviceOnline(DeviceInfo),
+    DeviceOffline(DeviceInfo),
+    DeviceTelemetryUpdated(DeviceTelemetry),
+    DeviceButtonPressed(ButtonEvent),
+    ConnectionStatus(ConnectionStatus),
+    ConfigReloaded(AppConfig),
+    ConfigReloadFailed(String),
+    AppError(AppErrorEvent),
+    StartupProgress(StartupProgress),
+    // Switched by the sun automation
+    DarkModeChanged(bool),
+}
+
+impl AppEvent {
+    // Name the webview listens on
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SensorDataUpdated(_) => "sensor-data-updated",
+            Self::AnomalousReading(_) => "anomalous-reading",
+            Self::SensorDataStale(_) => "sensor-data-stale",
+            Self::WeatherDataUpdated(_) => "weather-data-updated",
+            Self::WeatherLocationChanged(_) => "weather-location-changed",
+            Self::WeatherProviderDegraded(_) => "weather-provider-degraded",
+            Self::WeatherProviderRestored(_) => "weather-provider-restored",
+            Self::WeatherAlert(_) => "weather-alert",
+            Self::AlertReceived(_) => "alert-received",
+            Self::AlertAcknowledged(_) => "alert-acknowledged",
+            Self::ForecastWarning(_) => "forecast-warning",
+            Self::RainAlert(_) => "rain-alert",
+            Self::DailySummary(_) => "daily-summary",
+            Self::MqttReconnected => "mqtt-reconnected",
+            Self::MqttConnectionLost(_) => "mqtt-connection-lost",
+            Self::PublishConfirmed(_) => "publish-confirmed",
+            Self::PublishFailed(_) => "publish-failed",
+            Self::DeviceOnline(_) => "device-online",
+            Self::DeviceOffline(_) => "device-offline",
+            Self::DeviceTelemetryUpdated(_) => "device-telemetry-updated",
+            Self::DeviceButtonPressed(_) => "device-button-pressed",
+            Self::ConnectionStatus(_) => "connection-status",
+            Self::ConfigReloaded(_) => "config-reloaded",
+            Self::ConfigReloadFailed(_) => "config-reload-failed",
+            Self::AppError(_) => "app-error",
+            Self::StartupProgress(_) => "startup-progress",
+            Self::DarkModeChanged(_) => "dark-mode-changed",
+        }
+    }
+
+    fn emit(&self, app: &AppHandle) -> tauri::Result<()> {
+        let name = self.name();
+        match self {
+            Self::SensorDataUpdated(sensor) => emit_to_main(app, name, sensor),
+            Self::AnomalousReading(sensor) => app.emit(name, sensor),
+            Self::SensorDataStale(event) => app.emit(name, event),
+            Self::WeatherDataUpdated(weather) => app.emit(name, weather),
+            Self::WeatherLocationChanged(change) => app.emit(name, change),
+            Self::WeatherProviderDegraded(event) => app.emit(name, event),
+            Self::WeatherProviderRestored(provider) => app.emit(name, provider),
+            Self::WeatherAlert(alert) => app.emit(name, alert),
+            Self::AlertReceived(alert) | Self::ForecastWarning(alert) | Self::RainAlert(alert) => app.emit(name, alert),
+            Self::AlertAcknowledged(event) => app.emit(name, event),
+            Self::DailySummary(summary) => app.emit(name, summary),
+            Self::MqttReconnected => app.emit(name, true),
+            Self::MqttConnectionLost(event) => app.emit(name, event),
+            Self::PublishConfirmed(event) | Self::PublishFailed(event) => app.emit(name, event),
+            Self::DeviceOnline(device) | Self::DeviceOffline(device) => app.emit(name, device),
+            Self::DeviceTelemetryUpdated(telemetry) => app.emit(name, telemetry),
+            Self::DeviceButtonPressed(event) => app.emit(name, event),
+            Self::ConnectionStatus(status) => app.emit(name, status),
+            Self::ConfigReloaded(config) => app.emit(name, config),
+            Self::ConfigReloadFailed(error) => app.emit(name, error),
+            Self::AppError(event) => app.emit(name, event),
+            Self::StartupProgress(progress) => app.emit(name, progress),
+            Self::DarkModeChanged(enabled) => app.emit(name, enabled),
+        }
+    }
+}
+
+// Done with the AppHandle instead of emitted. They have a queue of their own so a burst
+// on the event bus can't make them lag out.
+#[derive(Debug)]
+pub enum DesktopAction {
+    // Shown through the notification plugin
+    Notify { title: String, body: String },
+    // Brings the OS login entry in line with the setting
+    LaunchAtLogin(bool),
+}
+
+impl DesktopAction {
+    fn perform(self, app: &AppHandle) {
+        match self {
+            Self::Notify { title, body } => {
+                if let Err(e) = app.notification().builder().title(title).body(body).show() {
+                    warn!("Failed to show desktop notification: {}", e);
+                }
+            }
+            Self::LaunchAtLogin(enabled) => {
+                if let Err(e) = autostart::apply(app, enabled) {
+                    warn!("Failed to update launch at login: {}", e);
+                }
+            }
+        }
+    }
+}
+
+// Sensor updates go to the dashboard window, or everywhere if it isn't open
+fn emit_to_main<S: Serialize + Clone>(app: &AppHandle, name: &str, payload: S) -> tauri::Result<()> {
+    app.emit_to("main", name, payload.clone()).or_else(|e| {
+        debug!("Failed to emit {} to the main window ({}), emitting globally", name, e);
+        app.emit(name, payload)
+    })
+}
+
+pub fn set_sensor_event_settings(settings: &SensorEventSettings) {
+    SENSOR_EVENT_INTERVAL_MS.store(settings.interval_ms, Ordering::SeqCst);
+    SENSOR_EVENT_BATCH.store(settings.mode == SensorEventMode::Batch, Ordering::SeqCst);
+}
+
+// Holds back sensor readings that arrive within the window after the last emit.
+// The first reading after a quiet spell goes straight through.
+#[derive(Default)]
+struct SensorThrottle {
+    pending: Vec<SensorData>,
+    last_emit: Option<Instant>,
+}
+
+impl SensorThrottle {
+    fn interval() -> Duration {
+        Duration::from_millis(SENSOR_EVENT_INTERVAL_MS.load(Ordering::SeqCst))
+    }
+
+    // When the held readings are due, if there are any
+    fn deadline(&self) -> Option<Instant> {
+        match (self.pending.is_empty(), self.last_emit) {
+            (false, Some(last_emit)) => Some(last_emit + Self::interval()),
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, app: &AppHandle, sensor: SensorData) {
+        let interval = Self::interval();
+        let quiet = self.last_emit.is_none_or(|last_emit| last_emit.elapsed() >= interval);
+        self.pending.push(sensor);
+        if interval.is_zero() || (quiet && self.pending.len() == 1) {
+            self.flush(app);
+        }
+    }
+
+    fn flush(&mut self, app: &AppHandle) {
+        let mut batch = std::mem::take(&mut self.pending);
+        let result = if SENSOR_EVENT_BATCH.load(Ordering::SeqCst) && !batch.is_empty() {
+            emit_to_main(app, "sensor-data-batch", batch)
+        } else if let Some(latest) = batch.pop() {
+            emit_to_main(app, "sensor-data-updated", latest)
+        } else {
+            return;
+        };
+        self.last_emit = Some(Instant::now());
+        if let Err(e) = result {
+            warn!("Failed to emit sensor data: {}", e);
+        }
+    }
+}
+
+fn bus() -> &'static broadcast::Sender<AppEvent> {
+    BUS.get_or_init(|| broadcast::channel(EVENT_BUS_CAPACITY).0)
+}
+
+// Events published before the forwarder starts, or with no window, are dropped
+pub fn publish(event: AppEvent) {
+    if bus().send(event).is_err() {
+        debug!("No event subscribers yet, dropping event");
+    }
+}
+
+pub fn subscribe() -> broadcast::Receiver<AppEvent> {
+    bus().subscribe()
+}
+
+fn desktop_actions() -> &'static (mpsc::Sender<DesktopAction>, Mutex<Option<mpsc::Receiver<DesktopAction>>>) {
+    DESKTOP_ACTIONS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel(DESKTOP_ACTION_CAPACITY);
+        (sender, Mutex::new(Some(receiver)))
+    })
+}
+
+// Queued until the forwarder starts; dropped only when the queue is full, e.g. when
+// running headless where nothing performs them
+pub fn perform(action: DesktopAction) {
+    if let Err(e) = desktop_actions().0.try_send(action) {
+        debug!("Desktop action queue full, dropping {:?}", e.into_inner());
+    }
+}
+
+fn forwarder_ready_flag() -> &'static watch::Sender<bool> {
+    FORWARDER_READY.get_or_init(|| watch::channel(false).0)
+}
+
+// Resolves once the forwarder is listening, so events published after it aren't dropped
+pub async fn forwarder_ready() {
+    let mut ready = forwarder_ready_flag().subscribe();
+    let _ = ready.wait_for(|ready| *ready).await;
+}
+
+// The only place events reach the webview; started once from setup
+pub fn spawn_forwarder(app: AppHandle) -> JoinHandle<()> {
+    let mut events = subscribe();
+    forwarder_ready_flag().send_replace(true);
+    // Taken on the first start; the task outlives forwarder restarts
+    if let Some(mut actions) = desktop_actions().1.lock().ok().and_then(|mut receiver| receiver.take()) {
+        let app = app.clone();
+        tokio::spawn(async move {
+            while let Some(action) = actions.recv().await {
+                action.perform(&app);
+            }
+        });
+    }
+    tokio::spawn(async move {
+        info!("Event forwarder started");
+        let mut sensor_throttle = SensorThrottle::default();
+        loop {
+            let received = match sensor_throttle.deadline() {
+                Some(deadline) => tokio::select! {
+                    received = events.recv() => received,
+                    _ = tokio::time::sleep_until(deadline) => {
+                        sensor_throttle.flush(&app);
+                        continue;
+                    }
+                },
+                None => events.recv().await,
+            };
+            match received {
+                Ok(AppEvent::SensorDataUpdated(sensor)) => sensor_throttle.push(&app, sensor),
+                Ok(event) => {
+                    if let Err(e) = event.emit(&app) {
+                        warn!("Failed to emit {}: {}", event.name(), e);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("Event forwarder fell behind, dropped {} events", skipped),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
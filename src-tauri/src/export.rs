@@ -0,0 +1,423 @@
+use crate::history::{enum_text, AlertHistoryFilter, AlertRecord, SensorHistory};
+use crate::metrics::{absolute_humidity, dew_point};
+use crate::types::{SensorData, WeatherData};
+use anyhow::Result;
+use arrow::array::{ArrayRef, Float64Array, StringArray, TimestampMillisecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+// Rows read from the database per batch; progress is reported after each one
+const EXPORT_BATCH_SIZE: u32 = 1000;
+pub(crate) const SENSOR_CSV_HEADER: &str = "received_at,device_id,device_name,temperature,humidity,pressure,co2,tvoc,lux,device_timestamp,raw_temperature,raw_humidity,raw_pressure,raw_co2,raw_tvoc,raw_lux,quality";
+pub(crate) const WEATHER_CSV_HEADER: &str = "timestamp,location,provider,units,condition,temperature,feels_like,humidity,pressure,wind_speed,wind_gust,wind_direction";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProgress {
+    // "sensor", "weather" or "alerts"
+    pub dataset: String,
+    pub written: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportSummary {
+    pub path: String,
+    pub rows: u64,
+    pub weather_path: Option<String>,
+    pub weather_rows: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    // One JSON object per line, for pandas.read_json(lines=True) or DuckDB
+    Jsonl,
+    Parquet,
+}
+
+// Flat sensor row with derived metrics, shared by the JSONL and Parquet writers
+#[derive(Debug, Clone, Serialize)]
+struct SensorRow {
+    received_at: Option<DateTime<Utc>>,
+    device_id: Option<String>,
+    device_name: Option<String>,
+    temperature: f64,
+    humidity: f64,
+    pressure: f64,
+    co2: Option<f64>,
+    tvoc: Option<f64>,
+    lux: Option<f64>,
+    dew_point: f64,
+    // Grams of water vapour per cubic metre of air
+    absolute_humidity: f64,
+    // As reported by the device, before calibration and smoothing
+    raw_temperature: f64,
+    raw_humidity: f64,
+    raw_pressure: f64,
+    raw_co2: Option<f64>,
+    raw_tvoc: Option<f64>,
+    raw_lux: Option<f64>,
+    // Flags separated by ";", e.g. "smoothed;stale"
+    quality: String,
+}
+
+impl SensorRow {
+    fn from_reading(reading: &SensorData) -> Self {
+        Self {
+            received_at: reading.received_at,
+            device_id: reading.device_id.clone(),
+            device_name: reading.device_name.clone(),
+            temperature: reading.temperature,
+            humidity: reading.humidity,
+            pressure: reading.pressure,
+            co2: reading.co2,
+            tvoc: reading.tvoc,
+            lux: reading.lux,
+            dew_point: dew_point(reading.temperature, reading.humidity),
+            absolute_humidity: absolute_humidity(reading.temperature, reading.humidity),
+            raw_temperature: reading.raw.as_ref().map_or(reading.temperature, |raw| raw.temperature),
+            raw_humidity: reading.raw.as_ref().map_or(reading.humidity, |raw| raw.humidity),
+            raw_pressure: reading.raw.as_ref().map_or(reading.pressure, |raw| raw.pressure),
+            raw_co2: reading.raw.as_ref().and_then(|raw| raw.co2).or(reading.co2),
+            raw_tvoc: reading.raw.as_ref().and_then(|raw| raw.tvoc).or(reading.tvoc),
+            raw_lux: reading.raw.as_ref().and_then(|raw| raw.lux).or(reading.lux),
+            quality: reading.quality.iter().map(|flag| flag.as_str()).collect::<Vec<_>>().join(";"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportRange {
+    pub device_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+// Writes sensor readings to `path` and, when asked, weather snapshots to a
+// sibling file ending in _weather.csv
+pub fn export_csv(
+    history: &SensorHistory,
+    path: &Path,
+    range: &ExportRange,
+    include_weather: bool,
+    mut on_progress: impl FnMut(ExportProgress),
+) -> Result<ExportSummary> {
+    let rows = write_sensor_csv(history, path, range, &mut on_progress)?;
+    info!("Exported {} sensor readings to {:?}", rows, path);
+
+    let (weather_path, weather_rows) = if include_weather {
+        let weather_path = sibling_path(path, "weather");
+        let weather_rows = write_weather_csv(history, &weather_path, range, &mut on_progress)?;
+        info!("Exported {} weather snapshots to {:?}", weather_rows, weather_path);
+        (Some(weather_path.display().to_string()), Some(weather_rows))
+    } else {
+        (None, None)
+    };
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        rows,
+        weather_path,
+        weather_rows,
+    })
+}
+
+// Alert history, newest first, for auditing what was sent to the device
+pub fn export_alerts_csv(
+    history: &SensorHistory,
+    path: &Path,
+    filter: &AlertHistoryFilter,
+    mut on_progress: impl FnMut(ExportProgress),
+) -> Result<ExportSummary> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "recorded_at,direction,source,level,message,alert_timestamp,message_id,delivery_status,delivery_error,acknowledged_at,acknowledged_by")?;
+
+    let mut written = 0u64;
+    loop {
+        let page = history.query_alerts(filter, EXPORT_BATCH_SIZE, written as u32)?;
+        if page.alerts.is_empty() {
+            break;
+        }
+        for record in &page.alerts {
+            writeln!(writer, "{}", alert_row(record))?;
+        }
+        written += page.alerts.len() as u64;
+        on_progress(ExportProgress { dataset: "alerts".to_string(), written, total: page.total });
+    }
+    writer.flush()?;
+    info!("Exported {} alerts to {:?}", written, path);
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        rows: written,
+        weather_path: None,
+        weather_rows: None,
+    })
+}
+
+// JSONL or Parquet export of the sensor history with derived metrics
+pub fn export_sensor_history(
+    history: &SensorHistory,
+    path: &Path,
+    format: ExportFormat,
+    range: &ExportRange,
+    mut on_progress: impl FnMut(ExportProgress),
+) -> Result<ExportSummary> {
+    let rows = match format {
+        ExportFormat::Csv => write_sensor_csv(history, path, range, &mut on_progress)?,
+        ExportFormat::Jsonl => write_sensor_jsonl(history, path, range, &mut on_progress)?,
+        ExportFormat::Parquet => write_sensor_parquet(history, path, range, &mut on_progress)?,
+    };
+    info!("Exported {} sensor readings to {:?} as {:?}", rows, path, format);
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        rows,
+        weather_path: None,
+        weather_rows: None,
+    })
+}
+
+// Feeds every matching reading to `write_batch`, one database page at a time
+fn for_each_batch(
+    history: &SensorHistory,
+    range: &ExportRange,
+    on_progress: &mut impl FnMut(ExportProgress),
+    mut write_batch: impl FnMut(&[SensorData]) -> Result<()>,
+) -> Result<u64> {
+    let total = history.count(range.device_id.as_deref(), range.from, range.to)?;
+    let mut written = 0u64;
+    let mut cursor = None;
+    loop {
+        let (readings, next) = history.query_after(range.device_id.as_deref(), range.from, range.to, cursor, EXPORT_BATCH_SIZE)?;
+        let Some(next) = next else {
+            break;
+        };
+        cursor = Some(next);
+        write_batch(&readings)?;
+        written += readings.len() as u64;
+        on_progress(ExportProgress { dataset: "sensor".to_string(), written, total });
+    }
+    Ok(written)
+}
+
+fn write_sensor_jsonl(
+    history: &SensorHistory,
+    path: &Path,
+    range: &ExportRange,
+    on_progress: &mut impl FnMut(ExportProgress),
+) -> Result<u64> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let written = for_each_batch(history, range, on_progress, |readings| {
+        for reading in readings {
+            serde_json::to_writer(&mut writer, &SensorRow::from_reading(reading))?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(written)
+}
+
+fn write_sensor_parquet(
+    history: &SensorHistory,
+    path: &Path,
+    range: &ExportRange,
+    on_progress: &mut impl FnMut(ExportProgress),
+) -> Result<u64> {
+    let utc = Some(Arc::from("UTC"));
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("received_at", DataType::Timestamp(TimeUnit::Millisecond, utc.clone()), true),
+        Field::new("device_id", DataType::Utf8, true),
+        Field::new("device_name", DataType::Utf8, true),
+        Field::new("temperature", DataType::Float64, false),
+        Field::new("humidity", DataType::Float64, false),
+        Field::new("pressure", DataType::Float64, false),
+        Field::new("co2", DataType::Float64, true),
+        Field::new("tvoc", DataType::Float64, true),
+        Field::new("lux", DataType::Float64, true),
+        Field::new("dew_point", DataType::Float64, false),
+        Field::new("absolute_humidity", DataType::Float64, false),
+        Field::new("raw_temperature", DataType::Float64, false),
+        Field::new("raw_humidity", DataType::Float64, false),
+        Field::new("raw_pressure", DataType::Float64, false),
+        Field::new("raw_co2", DataType::Float64, true),
+        Field::new("raw_tvoc", DataType::Float64, true),
+        Field::new("raw_lux", DataType::Float64, true),
+        Field::new("quality", DataType::Utf8, false),
+    ]));
+
+    let mut writer = ArrowWriter::try_new(File::create(path)?, Arc::clone(&schema), None)?;
+    let written = for_each_batch(history, range, on_progress, |readings| {
+        let rows: Vec<SensorRow> = readings.iter().map(SensorRow::from_reading).collect();
+        let float = |f: fn(&SensorRow) -> f64| Arc::new(Float64Array::from_iter_values(rows.iter().map(f))) as ArrayRef;
+        let optional = |f: fn(&SensorRow) -> Option<f64>| Arc::new(rows.iter().map(f).collect::<Float64Array>()) as ArrayRef;
+        let text = |f: fn(&SensorRow) -> Option<&str>| Arc::new(rows.iter().map(f).collect::<StringArray>()) as ArrayRef;
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(rows.iter()
+                .map(|r| r.received_at.map(|t| t.timestamp_millis()))
+                .collect::<TimestampMillisecondArray>()
+                .with_timezone("UTC")),
+            text(|r| r.device_id.as_deref()),
+            text(|r| r.device_name.as_deref()),
+            float(|r| r.temperature),
+            float(|r| r.humidity),
+            float(|r| r.pressure),
+            optional(|r| r.co2),
+            optional(|r| r.tvoc),
+            optional(|r| r.lux),
+            float(|r| r.dew_point),
+            float(|r| r.absolute_humidity),
+            float(|r| r.raw_temperature),
+            float(|r| r.raw_humidity),
+            float(|r| r.raw_pressure),
+            optional(|r| r.raw_co2),
+            optional(|r| r.raw_tvoc),
+            optional(|r| r.raw_lux),
+            Arc::new(rows.iter().map(|r| Some(r.quality.as_str())).collect::<StringArray>()),
+        ];
+        writer.write(&RecordBatch::try_new(Arc::clone(&schema), columns)?)?;
+        Ok(())
+    })?;
+    writer.close()?;
+    Ok(written)
+}
+
+fn write_sensor_csv(
+    history: &SensorHistory,
+    path: &Path,
+    range: &ExportRange,
+    on_progress: &mut impl FnMut(ExportProgress),
+) -> Result<u64> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", SENSOR_CSV_HEADER)?;
+
+    let written = for_each_batch(history, range, on_progress, |readings| {
+        for reading in readings {
+            writeln!(writer, "{}", sensor_row(reading))?;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(written)
+}
+
+fn write_weather_csv(
+    history: &SensorHistory,
+    path: &Path,
+    range: &ExportRange,
+    on_progress: &mut impl FnMut(ExportProgress),
+) -> Result<u64> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", WEATHER_CSV_HEADER)?;
+
+    let mut written = 0u64;
+    loop {
+        let (total, snapshots) = history.query_weather(range.from, range.to, EXPORT_BATCH_SIZE, written as u32)?;
+        if snapshots.is_empty() {
+            break;
+        }
+        for snapshot in &snapshots {
+            writeln!(writer, "{}", weather_row(snapshot))?;
+        }
+        written += snapshots.len() as u64;
+        on_progress(ExportProgress { dataset: "weather".to_string(), written, total });
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
+
+pub(crate) fn sensor_row(reading: &SensorData) -> String {
+    [
+        reading.received_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+        csv_field(reading.device_id.as_deref().unwrap_or_default()),
+        csv_field(reading.device_name.as_deref().unwrap_or_default()),
+        reading.temperature.to_string(),
+        reading.humidity.to_string(),
+        reading.pressure.to_string(),
+        optional(reading.co2),
+        optional(reading.tvoc),
+        optional(reading.lux),
+        csv_field(&reading.timestamp),
+    ].into_iter().chain(derived_fields(reading)).collect::<Vec<_>>().join(",")
+}
+
+// Raw values and quality. Where neither calibration nor smoothing changed the reading,
+// raw is the value itself.
+fn derived_fields(reading: &SensorData) -> [String; 7] {
+    let row = SensorRow::from_reading(reading);
+    [
+        row.raw_temperature.to_string(),
+        row.raw_humidity.to_string(),
+        row.raw_pressure.to_string(),
+        optional(row.raw_co2),
+        optional(row.raw_tvoc),
+        optional(row.raw_lux),
+        row.quality,
+    ]
+}
+
+pub(crate) fn weather_row(data: &WeatherData) -> String {
+    [
+        data.timestamp.to_rfc3339(),
+        csv_field(&data.location),
+        csv_field(&data.provider),
+        format!("{:?}", data.units).to_lowercase(),
+        csv_field(&data.condition),
+        data.current_temp.to_string(),
+        optional(data.feels_like),
+        data.humidity.to_string(),
+        data.pressure.to_string(),
+        data.wind_speed.to_string(),
+        optional(data.wind_gust),
+        csv_field(&data.wind_direction),
+    ].join(",")
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+// Quotes fields containing separators, quotes or newlines
+fn alert_row(record: &AlertRecord) -> String {
+    [
+        record.recorded_at.to_rfc3339(),
+        enum_text(&record.direction),
+        enum_text(&record.source),
+        enum_text(&record.level),
+        csv_field(&record.message),
+        record.alert_timestamp.to_rfc3339(),
+        record.message_id.map(|id| id.to_string()).unwrap_or_default(),
+        record.delivery_status.as_ref().map(enum_text).unwrap_or_default(),
+        csv_field(record.delivery_error.as_deref().unwrap_or_default()),
+        record.acknowledged_at.map(|at| at.to_rfc3339()).unwrap_or_default(),
+        csv_field(record.acknowledged_by.as_deref().unwrap_or_default()),
+    ]
+    .join(",")
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// data.csv -> data_weather.csv
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "export".to_string());
+    let extension = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_else(|| "csv".to_string());
+    path.with_file_name(format!("{}_{}.{}", stem, suffix, extension))
+}
@@ -0,0 +1,73 @@
+use crate::error::AppError;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tracing::debug;
+
+struct Outcome<T> {
+    at: Instant,
+    result: Result<T, AppError>,
+}
+
+type Slot<T> = Arc<Mutex<Option<Outcome<T>>>>;
+
+// Guards provider fetches the frontend asks for. Identical requests wait for the one in
+// flight and share its result, and within the minimum interval the last result is
+// returned again, failures included, so a rapidly clicked button costs one API call.
+pub struct FetchLimiter<T> {
+    slots: std::sync::Mutex<HashMap<String, Slot<T>>>,
+}
+
+impl<T> Default for FetchLimiter<T> {
+    fn default() -> Self {
+        Self { slots: std::sync::Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<T: Clone> FetchLimiter<T> {
+    pub async fn fetch<F, Fut>(&self, key: String, min_interval: Duration, fetch: F) -> Result<T, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        let requested = Instant::now();
+        let slot = {
+            let mut slots = self.slots.lock().unwrap();
+            // Drops keys nobody is waiting on whose result has expired
+            slots.retain(|_, slot| {
+                Arc::strong_count(slot) > 1
+                    || slot.try_lock().map_or(true, |outcome| {
+                        outcome.as_ref().is_some_and(|outcome| outcome.at.elapsed() < min_interval)
+                    })
+            });
+            Arc::clone(slots.entry(key.clone()).or_default())
+        };
+
+        let mut outcome = slot.lock().await;
+        // Finished while this request waited, or recently enough
+        let reusable = |last: &&Outcome<T>| last.at >= requested || last.at.elapsed() < min_interval;
+        if let Some(last) = outcome.as_ref().filter(reusable) {
+            debug!("Reusing the result fetched {:.1}s ago for {}", last.at.elapsed().as_secs_f64(), key);
+            return last.result.clone();
+        }
+        let result = fetch().await;
+        *outcome = Some(Outcome { at: Instant::now(), result: result.clone() });
+        result
+    }
+}
+
+// Coordinates to about 10 m, so the same place typed twice shares a key
+pub fn key(scope: &str, lat: f64, lon: f64) -> String {
+    format!("{}:{:.4},{:.4}", scope, lat, lon)
+}
+
+// Scope for requests made with an API key, without holding on to the key itself
+pub fn api_key_scope(api_key: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    api_key.hash(&mut hasher);
+    format!("api_key:{:016x}", hasher.finish())
+}
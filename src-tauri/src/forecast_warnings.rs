@@ -0,0 +1,143 @@
+use crate::config::{ConfigManager, ForecastWarningSettings};
+use crate::events::{self, AppEvent};
+use crate::history::SensorHistory;
+use crate::mqtt_client::MqttHandle;
+use crate::types::{AlertData, AlertLevel, AlertSource, UnitSystem, WeatherData};
+use crate::weather_api::WeatherApiClient;
+use anyhow::Result;
+use chrono::{Local, NaiveDate, NaiveTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+// Upper bound on each sleep, so changes to the configured time are picked up
+const MAX_SLEEP_SECS: u64 = 600;
+
+// Frost and heat alerts worked out from the cached daily forecast
+pub fn evaluate(weather: &WeatherData, settings: &ForecastWarningSettings) -> Vec<AlertData> {
+    let celsius = |value: f64| (weather.units.convert_temp(value, UnitSystem::Metric) * 10.0).round() / 10.0;
+    let mut alerts = Vec::new();
+
+    // The next day's low falls in the early hours, i.e. tonight
+    if settings.frost.enabled {
+        if let Some(low) = weather.forecast.get(1).and_then(|day| day.temp_min).map(celsius) {
+            if low <= settings.frost.threshold_c {
+                alerts.push(alert(format!("Frost expected tonight (min {}°C)", weather.locale.format_number(low, 0)), settings.frost.level.clone()));
+            }
+        }
+    }
+
+    if settings.heat.enabled {
+        let hot_day = weather.forecast.iter()
+            .take(2)
+            .enumerate()
+            .map(|(i, day)| (i, celsius(day.temp)))
+            .find(|(_, high)| *high >= settings.heat.threshold_c);
+        if let Some((i, high)) = hot_day {
+            let when = if i == 0 { "today" } else { "tomorrow" };
+            alerts.push(alert(format!("Heat warning {} ({}°C)", when, weather.locale.format_number(high, 0)), settings.heat.level.clone()));
+        }
+    }
+
+    alerts
+}
+
+fn alert(message: String, level: AlertLevel) -> AlertData {
+    AlertData {
+        message,
+        level,
+        timestamp: chrono::Utc::now(),
+        id: None,
+    }
+}
+
+// Checks the forecast once a day at the configured local time, or at startup if that time has passed
+pub fn spawn(
+    config_manager: Arc<Mutex<ConfigManager>>,
+    weather_api: Arc<WeatherApiClient>,
+    mqtt_manager: MqttHandle,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        info!("Forecast warning job started");
+        let history = mqtt_manager.sensor_history();
+        // Kept in the history database so a restart later in the day doesn't warn twice
+        let mut last_checked = last_check(&history).unwrap_or_else(|e| {
+            warn!("Failed to read the last forecast warning check: {}", e);
+            None
+        });
+
+        loop {
+            let (settings, (lat, lon)) = {
+                let config_manager = config_manager.lock().await;
+                (config_manager.weather_api_settings().forecast_warnings.clone(), config_manager.active_coordinates())
+            };
+            let Ok(time) = NaiveTime::parse_from_str(&settings.check_time, "%H:%M") else {
+                warn!("Invalid forecast warning time '{}', expected HH:MM", settings.check_time);
+                tokio::time::sleep(Duration::from_secs(MAX_SLEEP_SECS)).await;
+                continue;
+            };
+
+            let now = Local::now();
+            let today = now.date_naive();
+            let due = now.time() >= time;
+            if due && settings.enabled && last_checked != Some(today) {
+                match weather_api.read_cached_weather_only(lat, lon).await {
+                    // Nothing cached yet, try again on the next round
+                    Ok(None) => info!("No cached forecast for the forecast warning check yet"),
+                    Ok(Some(weather)) => {
+                        last_checked = Some(today);
+                        let alerts = evaluate(&weather, &settings);
+                        for alert in &alerts {
+                            raise(&mqtt_manager, alert.clone()).await;
+                        }
+                        if let Err(e) = record_check(&history, today, &alerts) {
+                            error!("Failed to record the forecast warning check: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to read cached forecast: {}", e),
+                }
+            }
+
+            let next = if due { (time - now.time()) + chrono::Duration::days(1) } else { time - now.time() };
+            let wait = next.to_std().unwrap_or_default().as_secs().clamp(1, MAX_SLEEP_SECS);
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+        }
+    })
+}
+
+async fn raise(mqtt_manager: &MqttHandle, alert: AlertData) {
+    info!("Forecast warning: {}", alert.message);
+    if mqtt_manager.is_connected() {
+        if let Err(e) = mqtt_manager.send_alert(&alert, AlertSource::ForecastWarning).await {
+            error!("Failed to send forecast warning over MQTT: {}", e);
+        }
+    }
+    mqtt_manager.alert_channels().notify_desktop(&alert.level, "Forecast warning", &alert.message);
+    events::publish(AppEvent::ForecastWarning(alert));
+}
+
+fn last_check(history: &SensorHistory) -> Result<Option<NaiveDate>> {
+    let date: Option<String> = history.with_connection(|connection| {
+        connection.query_row(
+            "SELECT date FROM forecast_warning_checks ORDER BY date DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        ).optional()
+    })?;
+    Ok(date.and_then(|date| date.parse().ok()))
+}
+
+// Stores the day's check along with the warnings it raised
+fn record_check(history: &SensorHistory, date: NaiveDate, alerts: &[AlertData]) -> Result<()> {
+    let alerts = serde_json::to_string(alerts)?;
+    history.with_connection(|connection| {
+        connection.execute(
+            "INSERT OR REPLACE INTO forecast_warning_checks (date, checked_at, alerts) VALUES (?1, ?2, ?3)",
+            params![date.to_string(), Utc::now().timestamp_millis(), alerts],
+        )
+    })?;
+    Ok(())
+}
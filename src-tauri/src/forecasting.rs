@@ -0,0 +1,158 @@
+use crate::metrics::PressureTrend;
+use crate::types::{SensorData, WeatherData};
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+
+// Barometer scale of the original Negretti & Zambra forecaster, in hPa
+const BARO_TOP: f64 = 1050.0;
+const BARO_BOTTOM: f64 = 950.0;
+
+const FORECASTS: [&str; 26] = [
+    "Settled fine",
+    "Fine weather",
+    "Becoming fine",
+    "Fine, becoming less settled",
+    "Fine, possible showers",
+    "Fairly fine, improving",
+    "Fairly fine, possible showers early",
+    "Fairly fine, showery later",
+    "Showery early, improving",
+    "Changeable, mending",
+    "Fairly fine, showers likely",
+    "Rather unsettled, clearing later",
+    "Unsettled, probably improving",
+    "Showery, bright intervals",
+    "Showery, becoming less settled",
+    "Changeable, some rain",
+    "Unsettled, short fine intervals",
+    "Unsettled, rain later",
+    "Unsettled, some rain",
+    "Mostly very unsettled",
+    "Occasional rain, worsening",
+    "Rain at times, very unsettled",
+    "Rain at frequent intervals",
+    "Rain, very unsettled",
+    "Stormy, may improve",
+    "Stormy, much rain",
+];
+
+// Forecast index for each of the 22 pressure bands, lowest pressure first
+const RISING_OPTIONS: [usize; 22] = [25, 25, 25, 24, 24, 19, 16, 12, 11, 9, 8, 6, 5, 2, 1, 1, 0, 0, 0, 0, 0, 0];
+const STEADY_OPTIONS: [usize; 22] = [25, 25, 25, 25, 25, 25, 23, 23, 22, 18, 15, 13, 10, 4, 1, 1, 0, 0, 0, 0, 0, 0];
+const FALLING_OPTIONS: [usize; 22] = [25, 25, 25, 25, 25, 25, 25, 25, 23, 23, 21, 20, 17, 14, 7, 3, 1, 1, 1, 0, 0, 0];
+
+// Pressure adjustment for wind from each compass point, as a percentage of the
+// barometer scale, for the northern hemisphere (N, NNE, ... NNW)
+const WIND_ADJUSTMENTS: [f64; 16] = [6.0, 5.0, 5.0, 2.0, -0.5, -2.0, -5.0, -8.5, -12.0, -10.0, -6.0, -4.5, -3.0, -0.5, 1.5, 3.0];
+const COMPASS_POINTS: [&str; 16] = ["N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW", "NNW"];
+const SUMMER_ADJUSTMENT: f64 = 7.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForecastConfidence {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalForecast {
+    // Zambretti letter, A (settled fine) to Z (stormy, much rain)
+    pub letter: String,
+    pub text: String,
+    pub confidence: ForecastConfidence,
+    pub sea_level_pressure: f64,
+    pub trend: Option<PressureTrend>,
+    pub wind_direction: Option<String>,
+    pub generated_at: DateTime<Utc>,
+}
+
+pub struct ZambrettiInput<'a> {
+    // Station pressure as measured by the sensor
+    pub pressure: f64,
+    pub temperature: f64,
+    pub altitude_m: f64,
+    // None when less than three hours of readings are stored
+    pub trend: Option<PressureTrend>,
+    pub wind_direction: Option<&'a str>,
+    pub latitude: f64,
+    pub at: DateTime<Utc>,
+}
+
+// Short-range forecast from local observations only, after the Negretti & Zambra
+// pocket forecaster: pressure band, tendency, wind direction and season
+pub fn zambretti(input: &ZambrettiInput) -> LocalForecast {
+    let sea_level_pressure = sea_level_pressure(input.pressure, input.temperature, input.altitude_m);
+    let range = BARO_TOP - BARO_BOTTOM;
+    let northern = input.latitude >= 0.0;
+    let rising = matches!(input.trend, Some(PressureTrend::Rising | PressureTrend::RisingRapidly));
+    let falling = matches!(input.trend, Some(PressureTrend::Falling | PressureTrend::FallingRapidly));
+
+    let mut adjusted = sea_level_pressure;
+    let wind_index = input.wind_direction.and_then(|direction| {
+        COMPASS_POINTS.iter().position(|point| point.eq_ignore_ascii_case(direction.trim()))
+    });
+    if let Some(index) = wind_index {
+        // The table is for the northern hemisphere; south of the equator the compass flips
+        let index = if northern { index } else { (index + 8) % 16 };
+        adjusted += range * WIND_ADJUSTMENTS[index] / 100.0;
+    }
+
+    let month = input.at.month();
+    let summer = if northern { (4..=9).contains(&month) } else { !(4..=9).contains(&month) };
+    if summer && rising {
+        adjusted += range * SUMMER_ADJUSTMENT / 100.0;
+    } else if summer && falling {
+        adjusted -= range * SUMMER_ADJUSTMENT / 100.0;
+    }
+
+    let band_width = range / RISING_OPTIONS.len() as f64;
+    let band = ((adjusted.clamp(BARO_BOTTOM, BARO_TOP - 0.01) - BARO_BOTTOM) / band_width) as usize;
+    let band = band.min(RISING_OPTIONS.len() - 1);
+    let index = if rising {
+        RISING_OPTIONS[band]
+    } else if falling {
+        FALLING_OPTIONS[band]
+    } else {
+        STEADY_OPTIONS[band]
+    };
+
+    let in_scale = (BARO_BOTTOM..=BARO_TOP).contains(&sea_level_pressure);
+    let confidence = match (input.trend.is_some(), wind_index.is_some(), in_scale) {
+        (true, true, true) => ForecastConfidence::High,
+        (true, _, true) => ForecastConfidence::Medium,
+        _ => ForecastConfidence::Low,
+    };
+
+    LocalForecast {
+        letter: ((b'A' + index as u8) as char).to_string(),
+        text: FORECASTS[index].to_string(),
+        confidence,
+        sea_level_pressure: (sea_level_pressure * 10.0).round() / 10.0,
+        trend: input.trend,
+        wind_direction: wind_index.map(|index| COMPASS_POINTS[index].to_string()),
+        generated_at: input.at,
+    }
+}
+
+// Combines the latest reading with the wind direction from the weather provider
+pub fn from_observations(sensor: &SensorData, weather: Option<&WeatherData>, latitude: f64, altitude_m: f64) -> LocalForecast {
+    zambretti(&ZambrettiInput {
+        pressure: sensor.pressure,
+        temperature: sensor.temperature,
+        altitude_m,
+        trend: sensor.pressure_tendency.map(|tendency| tendency.trend),
+        wind_direction: weather.map(|weather| weather.wind_direction.as_str()),
+        latitude,
+        at: sensor.received_at.unwrap_or_else(Utc::now),
+    })
+}
+
+// Hypsometric reduction of station pressure to sea level
+pub(crate) fn sea_level_pressure(pressure: f64, temp_c: f64, altitude_m: f64) -> f64 {
+    if altitude_m == 0.0 {
+        return pressure;
+    }
+    let lapse = 0.0065 * altitude_m;
+    pressure * (1.0 - lapse / (temp_c + lapse + 273.15)).powf(-5.257)
+}
@@ -0,0 +1,244 @@
+use crate::config::GrafanaSettings;
+use crate::history::{enum_text, AlertHistoryFilter, SensorHistory};
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tracing::{info, error};
+
+// Metrics exposed as targets; each maps to a sensor_readings column and its sensor_hourly aggregates
+const METRICS: &[&str] = &["temperature", "humidity", "pressure", "co2", "tvoc", "lux"];
+const MAX_ANNOTATIONS: u32 = 1000;
+
+type ApiResult = std::result::Result<Json<Value>, (StatusCode, String)>;
+
+#[derive(Debug, Deserialize)]
+struct TimeRange {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SearchRequest {
+    target: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryRequest {
+    range: TimeRange,
+    #[serde(default)]
+    interval_ms: Option<i64>,
+    #[serde(default)]
+    max_data_points: Option<i64>,
+    #[serde(default)]
+    targets: Vec<QueryTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryTarget {
+    #[serde(default)]
+    target: String,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    hide: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnnotationRequest {
+    range: TimeRange,
+    #[serde(default)]
+    annotation: Value,
+}
+
+// Serves /search, /query and /annotations from the sensor history database.
+// Targets are "<metric>" across all devices or "<metric>:<device id>".
+pub struct GrafanaServer {
+    settings: GrafanaSettings,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    server_handle: tokio::task::JoinHandle<()>,
+}
+
+impl GrafanaServer {
+    pub async fn start(settings: GrafanaSettings, history: Arc<SensorHistory>) -> Result<Self> {
+        let listener = TcpListener::bind((settings.bind_address.as_str(), settings.port)).await?;
+        info!("Grafana datasource listening on {}", listener.local_addr()?);
+
+        let router = Router::new()
+            .route("/", get(|| async { StatusCode::OK }))
+            .route("/search", post(search))
+            .route("/query", post(query))
+            .route("/annotations", post(annotations))
+            .with_state(history);
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server_handle = tokio::spawn(async move {
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                error!("Grafana datasource stopped: {}", e);
+            }
+        });
+
+        Ok(Self {
+            settings,
+            shutdown_tx: Some(shutdown_tx),
+            server_handle,
+        })
+    }
+
+    pub fn settings(&self) -> &GrafanaSettings {
+        &self.settings
+    }
+
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        let _ = self.server_handle.await;
+        info!("Grafana datasource stopped");
+    }
+}
+
+async fn search(State(history): State<Arc<SensorHistory>>, body: Option<Json<SearchRequest>>) -> ApiResult {
+    let filter = body.map(|Json(request)| request.target).unwrap_or_default();
+    let devices = blocking(history, |history| {
+        history.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT DISTINCT device_id FROM sensor_readings WHERE device_id IS NOT NULL ORDER BY device_id",
+            )?;
+            let devices = statement.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<String>>>();
+            devices
+        })
+    }).await?;
+
+    let targets: Vec<String> = METRICS.iter()
+        .map(|metric| metric.to_string())
+        .chain(devices.iter().flat_map(|device| METRICS.iter().map(move |metric| format!("{}:{}", metric, device))))
+        .filter(|target| target.contains(&filter))
+        .collect();
+    Ok(Json(json!(targets)))
+}
+
+async fn query(State(history): State<Arc<SensorHistory>>, Json(request): Json<QueryRequest>) -> ApiResult {
+    let from_ms = request.range.from.timestamp_millis();
+    let to_ms = request.range.to.timestamp_millis();
+    // Bucket width honours both the panel's interval and its point budget
+    let span_per_point = request.max_data_points.filter(|points| *points > 0).map_or(0, |points| (to_ms - from_ms) / points);
+    let bucket_ms = request.interval_ms.unwrap_or(0).max(span_per_point).max(1000);
+
+    let targets: Vec<QueryTarget> = request.targets.into_iter().filter(|target| !target.hide).collect();
+    let results = blocking(history, move |history| {
+        targets.into_iter()
+            .map(|target| {
+                let (metric, device_id) = match target.target.split_once(':') {
+                    Some((metric, device)) => (metric.to_string(), Some(device.to_string())),
+                    None => (target.target.clone(), None),
+                };
+                let points = if METRICS.contains(&metric.as_str()) {
+                    series(history, &metric, device_id.as_deref(), from_ms, to_ms, bucket_ms)?
+                } else {
+                    Vec::new()
+                };
+                Ok(match target.kind.as_deref() {
+                    Some("table") => json!({
+                        "type": "table",
+                        "columns": [{"text": "Time", "type": "time"}, {"text": target.target, "type": "number"}],
+                        "rows": points.iter().map(|(at, value)| json!([at, value])).collect::<Vec<_>>(),
+                    }),
+                    _ => json!({
+                        "target": target.target,
+                        "datapoints": points.iter().map(|(at, value)| json!([value, at])).collect::<Vec<_>>(),
+                    }),
+                })
+            })
+            .collect::<Result<Vec<Value>>>()
+    }).await?;
+    Ok(Json(json!(results)))
+}
+
+async fn annotations(State(history): State<Arc<SensorHistory>>, Json(request): Json<AnnotationRequest>) -> ApiResult {
+    let filter = AlertHistoryFilter {
+        from: Some(request.range.from),
+        to: Some(request.range.to),
+        ..Default::default()
+    };
+    let page = blocking(history, move |history| history.query_alerts(&filter, MAX_ANNOTATIONS, 0)).await?;
+
+    // The annotation's query text, if any, narrows the alerts to a level or source
+    let wanted = request.annotation.get("query").and_then(Value::as_str).unwrap_or_default().trim().to_string();
+    let annotations: Vec<Value> = page.alerts.iter()
+        .map(|alert| (alert, enum_text(&alert.level), enum_text(&alert.source)))
+        .filter(|(_, level, source)| wanted.is_empty() || *level == wanted || *source == wanted)
+        .map(|(alert, level, source)| json!({
+            "annotation": request.annotation,
+            "time": alert.recorded_at.timestamp_millis(),
+            "title": format!("{} alert", level),
+            "text": alert.message,
+            "tags": [level, source, enum_text(&alert.direction)],
+        }))
+        .collect();
+    Ok(Json(json!(annotations)))
+}
+
+// Average of `metric` per bucket, from raw readings plus hourly aggregates for pruned hours
+fn series(
+    history: &SensorHistory,
+    metric: &str,
+    device_id: Option<&str>,
+    from_ms: i64,
+    to_ms: i64,
+    bucket_ms: i64,
+) -> Result<Vec<(i64, f64)>> {
+    let sql = format!(
+        "SELECT bucket, SUM(total) / SUM(n) FROM (
+             SELECT (recorded_at / ?4) * ?4 AS bucket, SUM({m}) AS total, COUNT({m}) AS n
+             FROM sensor_readings
+             WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3 AND {m} IS NOT NULL
+             GROUP BY bucket
+             UNION ALL
+             SELECT (hour_start / ?4) * ?4 AS bucket, SUM({m}_avg * samples), SUM(samples)
+             FROM sensor_hourly
+             WHERE (?1 IS NULL OR device_id = ?1) AND hour_start BETWEEN ?2 AND ?3 AND {m}_avg IS NOT NULL
+               AND hour_start < (SELECT COALESCE(MIN(recorded_at), ?3 + 1) FROM sensor_readings)
+             GROUP BY bucket
+         )
+         GROUP BY bucket ORDER BY bucket",
+        m = metric
+    );
+    history.with_connection(|connection| {
+        let mut statement = connection.prepare(&sql)?;
+        let points = statement
+            .query_map(params![device_id, from_ms, to_ms, bucket_ms], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<(i64, f64)>>>();
+        points
+    })
+}
+
+// Runs a database call off the async runtime, mapping failures to a 500
+async fn blocking<T: Send + 'static>(
+    history: Arc<SensorHistory>,
+    f: impl FnOnce(&SensorHistory) -> Result<T> + Send + 'static,
+) -> std::result::Result<T, (StatusCode, String)> {
+    match tokio::task::spawn_blocking(move || f(&history)).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => {
+            error!("Grafana query failed: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Query failed: {}", e)))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Query failed: {}", e))),
+    }
+}
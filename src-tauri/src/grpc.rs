@@ -0,0 +1,238 @@
+use crate::config::GrpcSettings;
+use crate::events::{self, AppEvent};
+use crate::history::DEFAULT_PAGE_SIZE;
+use crate::mqtt_client::MqttHandle;
+use crate::rest_api::token_matches;
+use crate::types::{AlertData, AlertLevel, AlertSource, SensorData, WeatherData};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{info, error, warn};
+
+mod proto {
+    tonic::include_proto!("weather_station.v1");
+}
+
+use proto::weather_station_server::{WeatherStation, WeatherStationServer};
+
+// Readings buffered per stream before a slow client starts missing them
+const STREAM_BUFFER: usize = 64;
+
+// The gRPC counterpart of the REST API, for backends that would rather not poll
+pub struct GrpcServer {
+    settings: GrpcSettings,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    server_handle: tokio::task::JoinHandle<()>,
+}
+
+impl GrpcServer {
+    pub async fn start(settings: GrpcSettings, mqtt_manager: MqttHandle) -> Result<Self> {
+        let listener = TcpListener::bind((settings.bind_address.as_str(), settings.port)).await?;
+        info!("gRPC API listening on {}", listener.local_addr()?);
+
+        let token = settings.token.clone();
+        let service = WeatherStationServer::with_interceptor(StationService { mqtt_manager }, move |request: Request<()>| {
+            let given = request.metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "));
+            match given {
+                Some(given) if !token.is_empty() && token_matches(given, &token) => Ok(request),
+                _ => {
+                    warn!("Rejected gRPC call without a valid token");
+                    Err(Status::unauthenticated("Missing or invalid bearer token"))
+                }
+            }
+        });
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server_handle = tokio::spawn(async move {
+            let result = Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                error!("gRPC API stopped: {}", e);
+            }
+        });
+
+        Ok(Self {
+            settings,
+            shutdown_tx: Some(shutdown_tx),
+            server_handle,
+        })
+    }
+
+    pub fn settings(&self) -> &GrpcSettings {
+        &self.settings
+    }
+
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        let _ = self.server_handle.await;
+        info!("gRPC API stopped");
+    }
+}
+
+struct StationService {
+    mqtt_manager: MqttHandle,
+}
+
+fn to_reading(reading: &SensorData) -> proto::SensorReading {
+    proto::SensorReading {
+        device_id: reading.device_id.clone(),
+        device_name: reading.device_name.clone(),
+        temperature: reading.temperature,
+        humidity: reading.humidity,
+        pressure: reading.pressure,
+        co2: reading.co2,
+        tvoc: reading.tvoc,
+        lux: reading.lux,
+        received_at_ms: reading.received_at.map(|at| at.timestamp_millis()).unwrap_or_default(),
+    }
+}
+
+fn to_weather(weather: &WeatherData) -> proto::Weather {
+    proto::Weather {
+        location: weather.location.clone(),
+        condition: weather.condition.clone(),
+        temperature: weather.current_temp,
+        feels_like: weather.feels_like,
+        humidity: weather.humidity,
+        pressure: weather.pressure,
+        wind_speed: weather.wind_speed,
+        wind_gust: weather.wind_gust,
+        wind_direction: weather.wind_direction.clone(),
+        provider: weather.provider.clone(),
+        timestamp_ms: weather.timestamp.timestamp_millis(),
+    }
+}
+
+fn from_millis(field: &str, millis: Option<i64>) -> Result<Option<DateTime<Utc>>, Status> {
+    millis
+        .map(|ms| DateTime::from_timestamp_millis(ms).ok_or_else(|| Status::invalid_argument(format!("{} is out of range", field))))
+        .transpose()
+}
+
+#[tonic::async_trait]
+impl WeatherStation for StationService {
+    type StreamSensorDataStream = ReceiverStream<Result<proto::SensorReading, Status>>;
+
+    async fn stream_sensor_data(
+        &self,
+        request: Request<proto::StreamSensorDataRequest>,
+    ) -> Result<Response<Self::StreamSensorDataStream>, Status> {
+        let device_id = request.into_inner().device_id;
+        let mut events = events::subscribe();
+        let latest = self.mqtt_manager.get_latest_sensor_data();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+
+        tokio::spawn(async move {
+            let wanted = |reading: &SensorData| device_id.is_none() || reading.device_id == device_id;
+            if let Some(reading) = latest.filter(|reading| wanted(reading)) {
+                if tx.send(Ok(to_reading(&reading))).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                let event = tokio::select! {
+                    received = events.recv() => received,
+                    _ = tx.closed() => break,
+                };
+                let reading = match event {
+                    Ok(AppEvent::SensorDataUpdated(reading)) => reading,
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("gRPC sensor stream fell behind, dropped {} events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if wanted(&reading) && tx.send(Ok(to_reading(&reading))).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_current_weather(
+        &self,
+        _request: Request<proto::GetCurrentWeatherRequest>,
+    ) -> Result<Response<proto::Weather>, Status> {
+        match self.mqtt_manager.get_latest_weather_data() {
+            Some(weather) => Ok(Response::new(to_weather(&weather))),
+            None => Err(Status::not_found("No weather data yet")),
+        }
+    }
+
+    async fn query_sensor_history(
+        &self,
+        request: Request<proto::SensorHistoryRequest>,
+    ) -> Result<Response<proto::SensorHistoryResponse>, Status> {
+        let query = request.into_inner();
+        let from = from_millis("from_ms", query.from_ms)?;
+        let to = from_millis("to_ms", query.to_ms)?;
+        let history = self.mqtt_manager.sensor_history();
+        let result = tokio::task::spawn_blocking(move || {
+            history.query(
+                query.device_id.as_deref(),
+                from,
+                to,
+                query.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+                query.offset.unwrap_or(0),
+            )
+        }).await;
+        match result {
+            Ok(Ok(page)) => Ok(Response::new(proto::SensorHistoryResponse {
+                readings: page.readings.iter().map(to_reading).collect(),
+                total: page.total,
+            })),
+            Ok(Err(e)) => {
+                error!("gRPC history query failed: {}", e);
+                Err(Status::internal(format!("Query failed: {}", e)))
+            }
+            Err(e) => Err(Status::internal(format!("Query failed: {}", e))),
+        }
+    }
+
+    async fn send_alert(
+        &self,
+        request: Request<proto::SendAlertRequest>,
+    ) -> Result<Response<proto::SendAlertResponse>, Status> {
+        let request = request.into_inner();
+        if request.message.trim().is_empty() {
+            return Err(Status::invalid_argument("Message must not be empty"));
+        }
+        let level = match proto::AlertLevel::try_from(request.level) {
+            Ok(proto::AlertLevel::Info) => AlertLevel::Info,
+            Ok(proto::AlertLevel::Warning) => AlertLevel::Warning,
+            Ok(proto::AlertLevel::Emergency) => AlertLevel::Emergency,
+            Err(_) => return Err(Status::invalid_argument(format!("Unknown alert level {}", request.level))),
+        };
+        info!("Sending alert from the gRPC API: {} (level: {:?})", request.message, level);
+        let alert = AlertData {
+            message: request.message,
+            level,
+            timestamp: Utc::now(),
+            id: None,
+        };
+        match self.mqtt_manager.send_alert(&alert, AlertSource::Grpc).await {
+            Ok(message_id) => Ok(Response::new(proto::SendAlertResponse { message_id })),
+            Err(e) => {
+                error!("Failed to send alert from the gRPC API: {}", e);
+                Err(Status::unavailable(format!("Alert failed: {}", e)))
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::api_usage::{BudgetExceeded, RateLimited};
+use crate::config::{MqttSettings, WeatherApiSettings};
+use crate::history::SensorHistory;
+use crate::proxy::ProxyTunnel;
+use crate::storage;
+use crate::weather_api::{ApiKeyMissing, WeatherApiClient};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration, Instant};
+use tracing::info;
+
+const TIMEOUT_SECS: u64 = 10;
+const PROBE_FILE_NAME: &str = ".health_check";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    // Works, but needs attention
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentCheck {
+    // broker, weather_api, cache or database
+    pub component: String,
+    pub status: CheckStatus,
+    pub message: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub checked_at: DateTime<Utc>,
+    // No component failed; warnings still count as healthy
+    pub healthy: bool,
+    pub components: Vec<ComponentCheck>,
+}
+
+// Checks each component for real rather than reporting the last known state, e.g. a
+// fresh connection to the broker. The checks run side by side.
+pub async fn run(
+    mqtt: MqttSettings,
+    weather_api: WeatherApiSettings,
+    weather_client: Arc<WeatherApiClient>,
+    history: Arc<SensorHistory>,
+) -> HealthReport {
+    let (broker, api, cache, database) = tokio::join!(
+        check("broker", check_broker(&mqtt)),
+        check("weather_api", check_weather_api(&weather_api, &weather_client)),
+        check("cache", check_cache()),
+        check("database", check_database(history)),
+    );
+    let components = vec![broker, api, cache, database];
+    let healthy = components.iter().all(|c| c.status != CheckStatus::Failed);
+    info!("Health check finished, {}", if healthy { "healthy" } else { "problems found" });
+    HealthReport {
+        checked_at: Utc::now(),
+        healthy,
+        components,
+    }
+}
+
+async fn check(component: &str, probe: impl Future<Output = Result<(CheckStatus, String)>>) -> ComponentCheck {
+    let started = Instant::now();
+    let (status, message) = match timeout(Duration::from_secs(TIMEOUT_SECS), probe).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => (CheckStatus::Failed, e.to_string()),
+        Err(_) => (CheckStatus::Failed, format!("No answer within {}s", TIMEOUT_SECS)),
+    };
+    ComponentCheck {
+        component: component.to_string(),
+        status,
+        message,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+// TCP first, so an unreachable host and a refused login read differently
+async fn check_broker(settings: &MqttSettings) -> Result<(CheckStatus, String)> {
+    let broker = format!("{}:{}", settings.broker_host, settings.broker_port);
+    let tunnel = if settings.proxy.enabled {
+        Some(ProxyTunnel::start(settings.proxy.clone(), settings.broker_host.clone(), settings.broker_port).await?)
+    } else {
+        None
+    };
+    let (host, port) = match &tunnel {
+        Some(tunnel) => (tunnel.local_addr().ip().to_string(), tunnel.local_addr().port()),
+        None => (settings.broker_host.clone(), settings.broker_port),
+    };
+    let result = connect_broker(settings, &broker, host, port).await;
+    if let Some(tunnel) = tunnel {
+        tunnel.shutdown();
+    }
+    result
+}
+
+async fn connect_broker(settings: &MqttSettings, broker: &str, host: String, port: u16) -> Result<(CheckStatus, String)> {
+    TcpStream::connect((host.as_str(), port)).await
+        .map_err(|e| anyhow!("Can't reach {}: {}", broker, e))?;
+
+    // Own client id, so the app's live session isn't kicked off the broker
+    let client_id = format!("{}-health-{}", settings.client_id, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_clean_session(true);
+    if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+        options.set_credentials(username, password);
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 10);
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => break,
+            Ok(_) => continue,
+            Err(e) => return Err(anyhow!("{} reachable, but the MQTT connection failed: {}", broker, e)),
+        }
+    }
+    let _ = client.disconnect().await;
+    let _ = eventloop.poll().await;
+    Ok((CheckStatus::Ok, format!("Connected to {}", broker)))
+}
+
+// Costs one API call, which counts towards the daily budget
+async fn check_weather_api(settings: &WeatherApiSettings, client: &WeatherApiClient) -> Result<(CheckStatus, String)> {
+    let (lat, lon) = settings.active_coordinates();
+    match client.fetch_current_conditions(lat, lon).await {
+        Ok(current) => Ok((CheckStatus::Ok, format!("{} answered with current conditions", current.provider))),
+        Err(e) if e.downcast_ref::<BudgetExceeded>().is_some() => {
+            Ok((CheckStatus::Warning, format!("Not checked, the daily call budget is used up: {}", e)))
+        }
+        Err(e) if e.downcast_ref::<RateLimited>().is_some() => {
+            Ok((CheckStatus::Warning, format!("Not checked: {}", e)))
+        }
+        Err(e) if e.downcast_ref::<ApiKeyMissing>().is_some() => Err(anyhow!("No API key set")),
+        Err(e) => Err(e),
+    }
+}
+
+// Writes, reads back and removes a file in the data dir
+async fn check_cache() -> Result<(CheckStatus, String)> {
+    let dir = storage::data_dir();
+    tokio::fs::create_dir_all(&dir).await
+        .map_err(|e| anyhow!("Can't create {:?}: {}", dir, e))?;
+    let probe = dir.join(PROBE_FILE_NAME);
+    let written = Utc::now().to_rfc3339();
+    tokio::fs::write(&probe, &written).await
+        .map_err(|e| anyhow!("{:?} is not writable: {}", dir, e))?;
+    let read = tokio::fs::read_to_string(&probe).await;
+    let _ = tokio::fs::remove_file(&probe).await;
+    if read? != written {
+        return Err(anyhow!("{:?} returned different contents than were written", probe));
+    }
+    Ok((CheckStatus::Ok, format!("{} is writable", dir.display())))
+}
+
+async fn check_database(history: Arc<SensorHistory>) -> Result<(CheckStatus, String)> {
+    // quick_check skips the index cross-checks, which take minutes on a large history
+    let problems = tokio::task::spawn_blocking(move || {
+        history.with_connection(|connection| {
+            let mut statement = connection.prepare("PRAGMA quick_check")?;
+            let rows = statement.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        })
+    }).await??;
+    if problems.len() == 1 && problems[0] == "ok" {
+        Ok((CheckStatus::Ok, "Integrity check passed".to_string()))
+    } else {
+        Err(anyhow!("Integrity check failed: {}", problems.join("; ")))
+    }
+}
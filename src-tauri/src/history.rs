@@ -0,0 +1,1085 @@
+use crate::config::RetentionSettings;
+use crate::delivery::DeliveryStatus;
+use crate::storage;
+use crate::types::{AlertData, AlertDirection, AlertLevel, AlertSource, SensorData, WeatherData};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::info;
+
+const HOUR_MS: i64 = 3_600_000;
+
+// Rolls raw readings in [?1, ?2) into one row per device and hour (?3 = HOUR_MS)
+const ROLLUP_SQL: &str = "INSERT OR REPLACE INTO sensor_hourly
+         (device_id, hour_start, samples,
+          temperature_avg, temperature_min, temperature_max,
+          humidity_avg, humidity_min, humidity_max,
+          pressure_avg, pressure_min, pressure_max,
+          co2_avg, co2_min, co2_max, tvoc_avg, tvoc_min, tvoc_max, lux_avg, lux_min, lux_max)
+     SELECT COALESCE(device_id, ''), recorded_at / ?3 * ?3, COUNT(*),
+            AVG(temperature), MIN(temperature), MAX(temperature),
+            AVG(humidity), MIN(humidity), MAX(humidity),
+            AVG(pressure), MIN(pressure), MAX(pressure),
+            AVG(co2), MIN(co2), MAX(co2), AVG(tvoc), MIN(tvoc), MAX(tvoc), AVG(lux), MIN(lux), MAX(lux)
+     FROM sensor_readings WHERE recorded_at >= ?1 AND recorded_at < ?2
+     GROUP BY COALESCE(device_id, ''), recorded_at / ?3";
+
+// Folds readings with id > ?1 in hours before ?2 into the existing aggregates (?3 = HOUR_MS).
+// SET expressions see the row as it was before the update.
+const MERGE_SQL: &str = "INSERT INTO sensor_hourly
+         (device_id, hour_start, samples,
+          temperature_avg, temperature_min, temperature_max,
+          humidity_avg, humidity_min, humidity_max,
+          pressure_avg, pressure_min, pressure_max,
+          co2_avg, co2_min, co2_max, tvoc_avg, tvoc_min, tvoc_max, lux_avg, lux_min, lux_max)
+     SELECT COALESCE(device_id, ''), recorded_at / ?3 * ?3, COUNT(*),
+            AVG(temperature), MIN(temperature), MAX(temperature),
+            AVG(humidity), MIN(humidity), MAX(humidity),
+            AVG(pressure), MIN(pressure), MAX(pressure),
+            AVG(co2), MIN(co2), MAX(co2), AVG(tvoc), MIN(tvoc), MAX(tvoc), AVG(lux), MIN(lux), MAX(lux)
+     FROM sensor_readings WHERE id > ?1 AND recorded_at < ?2
+     GROUP BY COALESCE(device_id, ''), recorded_at / ?3
+     ON CONFLICT (device_id, hour_start) DO UPDATE SET
+         samples = samples + excluded.samples,
+         temperature_avg = (temperature_avg * samples + excluded.temperature_avg * excluded.samples) / (samples + excluded.samples),
+         temperature_min = MIN(temperature_min, excluded.temperature_min),
+         temperature_max = MAX(temperature_max, excluded.temperature_max),
+         humidity_avg = (humidity_avg * samples + excluded.humidity_avg * excluded.samples) / (samples + excluded.samples),
+         humidity_min = MIN(humidity_min, excluded.humidity_min),
+         humidity_max = MAX(humidity_max, excluded.humidity_max),
+         pressure_avg = (pressure_avg * samples + excluded.pressure_avg * excluded.samples) / (samples + excluded.samples),
+         pressure_min = MIN(pressure_min, excluded.pressure_min),
+         pressure_max = MAX(pressure_max, excluded.pressure_max),
+         co2_avg = COALESCE((co2_avg * samples + excluded.co2_avg * excluded.samples) / (samples + excluded.samples), co2_avg, excluded.co2_avg),
+         co2_min = COALESCE(MIN(co2_min, excluded.co2_min), co2_min, excluded.co2_min),
+         co2_max = COALESCE(MAX(co2_max, excluded.co2_max), co2_max, excluded.co2_max),
+         tvoc_avg = COALESCE((tvoc_avg * samples + excluded.tvoc_avg * excluded.samples) / (samples + excluded.samples), tvoc_avg, excluded.tvoc_avg),
+         tvoc_min = COALESCE(MIN(tvoc_min, excluded.tvoc_min), tvoc_min, excluded.tvoc_min),
+         tvoc_max = COALESCE(MAX(tvoc_max, excluded.tvoc_max), tvoc_max, excluded.tvoc_max),
+         lux_avg = COALESCE((lux_avg * samples + excluded.lux_avg * excluded.samples) / (samples + excluded.samples), lux_avg, excluded.lux_avg),
+         lux_min = COALESCE(MIN(lux_min, excluded.lux_min), lux_min, excluded.lux_min),
+         lux_max = COALESCE(MAX(lux_max, excluded.lux_max), lux_max, excluded.lux_max)";
+
+pub const HISTORY_FILE_NAME: &str = "sensor_history.db";
+pub const DEFAULT_PAGE_SIZE: u32 = 500;
+const MAX_PAGE_SIZE: u32 = 5000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorHistoryPage {
+    // Oldest first
+    pub readings: Vec<SensorData>,
+    // Matching readings across all pages
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+// Last row of a page from SensorHistory::query_after
+#[derive(Debug, Clone, Copy)]
+pub struct ReadingCursor {
+    recorded_at: i64,
+    id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherSnapshotPage {
+    // Oldest first
+    pub snapshots: Vec<WeatherData>,
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+// Where an import started, see SensorHistory::begin_import
+pub struct ImportMark {
+    last_id: i64,
+    // End of the newest hour already rolled up
+    watermark: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricSummary {
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+// One hour of readings from one device, produced by downsampling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourlyAggregate {
+    // Empty for devices that don't report an id
+    pub device_id: String,
+    pub hour_start: DateTime<Utc>,
+    pub samples: u32,
+    pub temperature: MetricSummary,
+    pub humidity: MetricSummary,
+    pub pressure: MetricSummary,
+    pub co2: Option<MetricSummary>,
+    pub tvoc: Option<MetricSummary>,
+    pub lux: Option<MetricSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourlyHistoryPage {
+    // Oldest first
+    pub aggregates: Vec<HourlyAggregate>,
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRecord {
+    pub id: i64,
+    pub recorded_at: DateTime<Utc>,
+    pub direction: AlertDirection,
+    pub source: AlertSource,
+    pub level: AlertLevel,
+    pub message: String,
+    // Timestamp carried in the alert payload
+    pub alert_timestamp: DateTime<Utc>,
+    // Delivery tracking for sent alerts; ids restart with the app
+    pub message_id: Option<u64>,
+    pub delivery_status: Option<DeliveryStatus>,
+    pub delivery_error: Option<String>,
+    pub alert_id: Option<String>,
+    // Set when a device acknowledged the alert
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub acknowledged_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertHistoryFilter {
+    pub level: Option<AlertLevel>,
+    pub source: Option<AlertSource>,
+    pub direction: Option<AlertDirection>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    // Some(false) lists only alerts no device has acknowledged yet
+    pub acknowledged: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertHistoryPage {
+    // Newest first
+    pub alerts: Vec<AlertRecord>,
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub readings_deleted: u64,
+    pub hours_aggregated: u64,
+    pub aggregates_deleted: u64,
+    pub weather_deleted: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionStatus {
+    pub sensor_readings: u64,
+    pub hourly_aggregates: u64,
+    pub weather_snapshots: u64,
+    pub oldest_reading: Option<DateTime<Utc>>,
+    pub oldest_aggregate: Option<DateTime<Utc>>,
+    // Database file plus its write-ahead log
+    pub database_bytes: u64,
+    pub last_pruned: Option<DateTime<Utc>>,
+    pub last_report: Option<RetentionReport>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub bytes: u64,
+    pub sensor_readings: u64,
+    pub weather_snapshots: u64,
+}
+
+// Every sensor reading, persisted in SQLite so history survives restarts.
+// The database is opened on first use.
+pub struct SensorHistory {
+    path: PathBuf,
+    connection: Mutex<Option<Connection>>,
+    last_prune: Mutex<Option<(DateTime<Utc>, RetentionReport)>>,
+    // Set while a backup is being restored; reads and writes fail fast instead of blocking
+    restoring: AtomicBool,
+}
+
+impl SensorHistory {
+    pub fn new() -> Self {
+        Self {
+            path: storage::data_dir().join(HISTORY_FILE_NAME),
+            connection: Mutex::new(None),
+            last_prune: Mutex::new(None),
+            restoring: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn with_connection<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T> {
+        if self.restoring.load(Ordering::SeqCst) {
+            return Err(anyhow!("Sensor history is being restored from a backup"));
+        }
+        let mut guard = self.connection.lock().map_err(|_| anyhow!("Sensor history lock poisoned"))?;
+        if guard.is_none() {
+            *guard = Some(Self::open(&self.path)?);
+        }
+        let connection = guard.as_ref().expect("connection was just opened");
+        Ok(f(connection)?)
+    }
+
+    fn open(path: &PathBuf) -> Result<Connection> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS sensor_readings (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 device_id TEXT,
+                 recorded_at INTEGER NOT NULL,
+                 temperature REAL NOT NULL,
+                 humidity REAL NOT NULL,
+                 pressure REAL NOT NULL,
+                 co2 REAL,
+                 tvoc REAL,
+                 lux REAL,
+                 payload TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_sensor_readings_device_time
+                 ON sensor_readings (device_id, recorded_at);
+             CREATE TABLE IF NOT EXISTS weather_snapshots (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 recorded_at INTEGER NOT NULL,
+                 payload TEXT NOT NULL
+             );
+             CREATE UNIQUE INDEX IF NOT EXISTS idx_weather_snapshots_time
+                 ON weather_snapshots (recorded_at);
+             CREATE TABLE IF NOT EXISTS sensor_hourly (
+                 device_id TEXT NOT NULL DEFAULT '',
+                 hour_start INTEGER NOT NULL,
+                 samples INTEGER NOT NULL,
+                 temperature_avg REAL NOT NULL,
+                 temperature_min REAL NOT NULL,
+                 temperature_max REAL NOT NULL,
+                 humidity_avg REAL NOT NULL,
+                 humidity_min REAL NOT NULL,
+                 humidity_max REAL NOT NULL,
+                 pressure_avg REAL NOT NULL,
+                 pressure_min REAL NOT NULL,
+                 pressure_max REAL NOT NULL,
+                 co2_avg REAL,
+                 co2_min REAL,
+                 co2_max REAL,
+                 tvoc_avg REAL,
+                 tvoc_min REAL,
+                 tvoc_max REAL,
+                 lux_avg REAL,
+                 lux_min REAL,
+                 lux_max REAL,
+                 PRIMARY KEY (device_id, hour_start)
+             );
+             CREATE TABLE IF NOT EXISTS alert_history (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 recorded_at INTEGER NOT NULL,
+                 direction TEXT NOT NULL,
+                 source TEXT NOT NULL,
+                 level TEXT NOT NULL,
+                 message TEXT NOT NULL,
+                 alert_timestamp INTEGER NOT NULL,
+                 message_id INTEGER,
+                 delivery_status TEXT,
+                 delivery_error TEXT
+             );
+             CREATE INDEX IF NOT EXISTS idx_alert_history_time
+                 ON alert_history (recorded_at);
+             CREATE TABLE IF NOT EXISTS daily_summaries (
+                 date TEXT PRIMARY KEY,
+                 generated_at INTEGER NOT NULL,
+                 payload TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS forecast_warning_checks (
+                 date TEXT PRIMARY KEY,
+                 checked_at INTEGER NOT NULL,
+                 alerts TEXT NOT NULL
+             );",
+        )?;
+        Self::add_missing_columns(&connection, "alert_history", &[
+            ("alert_id", "TEXT"),
+            ("acknowledged_at", "INTEGER"),
+            ("acknowledged_by", "TEXT"),
+        ])?;
+        connection.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_alert_history_alert_id ON alert_history (alert_id);",
+        )?;
+        info!("Opened sensor history database at {:?}", path);
+        Ok(connection)
+    }
+
+    // Brings tables created by older versions up to date
+    fn add_missing_columns(connection: &Connection, table: &str, columns: &[(&str, &str)]) -> rusqlite::Result<()> {
+        let mut statement = connection.prepare(&format!("PRAGMA table_info({})", table))?;
+        let existing = statement
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        for (name, definition) in columns {
+            if !existing.iter().any(|column| column == name) {
+                connection.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {};", table, name, definition))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes a consistent, compacted copy of the database to `destination`
+    pub fn backup(&self, destination: &Path) -> Result<BackupInfo> {
+        if Self::resolve(destination)? == Self::resolve(&self.path)? {
+            return Err(anyhow!("Choose a different file; the backup can't replace the live database"));
+        }
+        // VACUUM INTO refuses to overwrite; the save dialog has already confirmed that
+        if destination.exists() {
+            std::fs::remove_file(destination)?;
+        }
+        let target = destination.to_string_lossy().to_string();
+        self.with_connection(|connection| connection.execute("VACUUM INTO ?1", params![target]))?;
+        info!("Backed up sensor history to {:?}", destination);
+        Self::inspect_backup(destination)
+    }
+
+    // Canonical form of a path whose file may not exist yet
+    fn resolve(path: &Path) -> Result<PathBuf> {
+        if path.exists() {
+            return Ok(path.canonicalize()?);
+        }
+        let file_name = path.file_name().ok_or_else(|| anyhow!("{:?} is not a file path", path))?;
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+            _ => std::env::current_dir()?,
+        };
+        Ok(parent.join(file_name))
+    }
+
+    // Replaces the database with a verified backup. Reads and writes fail while the
+    // file is swapped, and the previous database is put back if the backup won't open.
+    pub fn restore(&self, source: &Path) -> Result<BackupInfo> {
+        let info = Self::inspect_backup(source)?;
+        if self.restoring.swap(true, Ordering::SeqCst) {
+            return Err(anyhow!("A restore is already in progress"));
+        }
+        let result = self.swap_database(source);
+        self.restoring.store(false, Ordering::SeqCst);
+        result?;
+
+        if let Ok(mut last_prune) = self.last_prune.lock() {
+            *last_prune = None;
+        }
+        info!("Restored sensor history from {:?}", source);
+        Ok(info)
+    }
+
+    fn swap_database(&self, source: &Path) -> Result<()> {
+        let mut guard = self.connection.lock().map_err(|_| anyhow!("Sensor history lock poisoned"))?;
+        // Closing checkpoints the write-ahead log into the main file
+        *guard = None;
+
+        let previous = self.path.with_extension("db.previous");
+        let had_previous = self.path.exists();
+        if had_previous {
+            std::fs::rename(&self.path, &previous)?;
+        }
+        for suffix in ["-wal", "-shm"] {
+            let mut sidecar = self.path.clone().into_os_string();
+            sidecar.push(suffix);
+            let _ = std::fs::remove_file(PathBuf::from(sidecar));
+        }
+
+        let opened = std::fs::copy(source, &self.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Self::open(&self.path));
+        match opened {
+            Ok(connection) => {
+                *guard = Some(connection);
+                if had_previous {
+                    let _ = std::fs::remove_file(&previous);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if had_previous {
+                    std::fs::rename(&previous, &self.path)?;
+                }
+                Err(anyhow!("Restored database could not be opened, kept the previous one: {}", e))
+            }
+        }
+    }
+
+    // Opens a backup read-only and checks it is an intact sensor history database
+    fn inspect_backup(path: &Path) -> Result<BackupInfo> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let integrity: String = connection.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+        if integrity != "ok" {
+            return Err(anyhow!("Backup failed the integrity check: {}", integrity));
+        }
+        let has_table = |table: &str| -> rusqlite::Result<bool> {
+            connection.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |row| row.get::<_, i64>(0),
+            ).map(|count| count > 0)
+        };
+        if !has_table("sensor_readings")? || !has_table("weather_snapshots")? {
+            return Err(anyhow!("{:?} is not a sensor history backup", path));
+        }
+
+        let count = |table: &str| connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0));
+        Ok(BackupInfo {
+            path: path.display().to_string(),
+            bytes: std::fs::metadata(path)?.len(),
+            sensor_readings: count("sensor_readings")? as u64,
+            weather_snapshots: count("weather_snapshots")? as u64,
+        })
+    }
+
+    // Pressure of the stored reading closest to `at`, if one lies within `tolerance`
+    pub fn pressure_near(&self, device_id: Option<&str>, at: DateTime<Utc>, tolerance: Duration) -> Result<Option<f64>> {
+        let target = at.timestamp_millis();
+        let tolerance = tolerance.num_milliseconds();
+        self.with_connection(|connection| {
+            connection.query_row(
+                "SELECT pressure FROM sensor_readings
+                 WHERE device_id IS ?1 AND recorded_at BETWEEN ?2 AND ?3
+                 ORDER BY ABS(recorded_at - ?4) ASC LIMIT 1",
+                params![device_id, target - tolerance, target + tolerance, target],
+                |row| row.get(0),
+            ).optional()
+        })
+    }
+
+    // Remembers where an import starts so finish_import can tell the imported rows apart
+    pub fn begin_import(&self) -> Result<ImportMark> {
+        self.with_connection(|connection| {
+            connection.query_row(
+                "SELECT COALESCE((SELECT MAX(id) FROM sensor_readings), 0),
+                        (SELECT MAX(hour_start) + ?1 FROM sensor_hourly)",
+                params![HOUR_MS],
+                |row| Ok(ImportMark { last_id: row.get(0)?, watermark: row.get(1)? }),
+            )
+        })
+    }
+
+    // Inserts readings in one transaction, e.g. one batch of an import
+    pub fn insert_many(&self, readings: &[SensorData]) -> Result<u64> {
+        let recorded: Vec<(i64, String)> = readings.iter()
+            .map(|reading| Ok((
+                reading.received_at.unwrap_or_else(chrono::Utc::now).timestamp_millis(),
+                serde_json::to_string(reading)?,
+            )))
+            .collect::<Result<_>>()?;
+        if recorded.is_empty() {
+            return Ok(0);
+        }
+
+        self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            {
+                let mut statement = transaction.prepare(
+                    "INSERT INTO sensor_readings
+                         (device_id, recorded_at, temperature, humidity, pressure, co2, tvoc, lux, payload)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                )?;
+                for (reading, (recorded_at, payload)) in readings.iter().zip(&recorded) {
+                    statement.execute(params![
+                        reading.device_id,
+                        recorded_at,
+                        reading.temperature,
+                        reading.humidity,
+                        reading.pressure,
+                        reading.co2,
+                        reading.tvoc,
+                        reading.lux,
+                        payload,
+                    ])?;
+                }
+            }
+            transaction.commit()
+        })?;
+        Ok(readings.len() as u64)
+    }
+
+    // Merges imported readings into hours the downsampling job had already passed when
+    // the import started; later hours are rolled up by the job as usual. Existing
+    // aggregates are combined rather than rebuilt, since their raw rows may be pruned.
+    pub fn finish_import(&self, mark: ImportMark) -> Result<u64> {
+        let Some(watermark) = mark.watermark else {
+            return Ok(0);
+        };
+        let hours = self.with_connection(|connection| connection.execute(MERGE_SQL, params![mark.last_id, watermark, HOUR_MS]))?;
+        if hours > 0 {
+            info!("Merged imported readings into {} hourly aggregates", hours);
+        }
+        Ok(hours as u64)
+    }
+
+    pub fn insert(&self, reading: &SensorData) -> Result<()> {
+        // received_at is set on arrival; the device clock may be wrong
+        let recorded_at = reading.received_at.unwrap_or_else(chrono::Utc::now).timestamp_millis();
+        let payload = serde_json::to_string(reading)?;
+        self.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO sensor_readings
+                     (device_id, recorded_at, temperature, humidity, pressure, co2, tvoc, lux, payload)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    reading.device_id,
+                    recorded_at,
+                    reading.temperature,
+                    reading.humidity,
+                    reading.pressure,
+                    reading.co2,
+                    reading.tvoc,
+                    reading.lux,
+                    payload,
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    // One row per fetched weather report, keyed by the report's own timestamp so
+    // re-reading the same cache after a restart doesn't duplicate it
+    pub fn insert_weather(&self, data: &WeatherData) -> Result<()> {
+        let payload = serde_json::to_string(data)?;
+        self.with_connection(|connection| {
+            connection.execute(
+                "INSERT OR IGNORE INTO weather_snapshots (recorded_at, payload) VALUES (?1, ?2)",
+                params![data.timestamp.timestamp_millis(), payload],
+            )
+        })?;
+        Ok(())
+    }
+
+    // Newest stored weather snapshot and sensor reading, to fill the dashboard at startup
+    pub fn latest_weather(&self) -> Result<Option<WeatherData>> {
+        self.latest_payload("SELECT payload FROM weather_snapshots ORDER BY recorded_at DESC, id DESC LIMIT 1")
+    }
+
+    pub fn latest_reading(&self) -> Result<Option<SensorData>> {
+        self.latest_payload("SELECT payload FROM sensor_readings ORDER BY recorded_at DESC, id DESC LIMIT 1")
+    }
+
+    fn latest_payload<T: serde::de::DeserializeOwned>(&self, sql: &str) -> Result<Option<T>> {
+        let payload = self.with_connection(|connection| {
+            connection.query_row(sql, [], |row| row.get::<_, String>(0)).optional()
+        })?;
+        payload.map(|payload| serde_json::from_str(&payload).map_err(anyhow::Error::from)).transpose()
+    }
+
+    // Weather snapshots in [from, to], oldest first, with the total matching count
+    pub fn query_weather(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(u64, Vec<WeatherData>)> {
+        let from_ms = from.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN);
+        let to_ms = to.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX);
+
+        let (total, payloads) = self.with_connection(|connection| {
+            let total: i64 = connection.query_row(
+                "SELECT COUNT(*) FROM weather_snapshots WHERE recorded_at BETWEEN ?1 AND ?2",
+                params![from_ms, to_ms],
+                |row| row.get(0),
+            )?;
+            let mut statement = connection.prepare(
+                "SELECT payload FROM weather_snapshots WHERE recorded_at BETWEEN ?1 AND ?2
+                 ORDER BY recorded_at ASC, id ASC LIMIT ?3 OFFSET ?4",
+            )?;
+            let payloads = statement
+                .query_map(params![from_ms, to_ms, limit.clamp(1, MAX_PAGE_SIZE), offset], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok((total, payloads))
+        })?;
+
+        let snapshots = payloads.iter()
+            .filter_map(|payload| serde_json::from_str::<WeatherData>(payload).ok())
+            .collect();
+        Ok((total as u64, snapshots))
+    }
+
+    pub fn weather_snapshots(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<WeatherSnapshotPage> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let (total, snapshots) = self.query_weather(from, to, limit, offset)?;
+        Ok(WeatherSnapshotPage { snapshots, total, limit, offset })
+    }
+
+    // Readings in [from, to], optionally for one device, oldest first
+    pub fn query(
+        &self,
+        device_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<SensorHistoryPage> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let from_ms = from.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN);
+        let to_ms = to.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX);
+
+        let (total, payloads) = self.with_connection(|connection| {
+            // NULL device filter matches every device
+            let filter = "WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3";
+            let total: i64 = connection.query_row(
+                &format!("SELECT COUNT(*) FROM sensor_readings {}", filter),
+                params![device_id, from_ms, to_ms],
+                |row| row.get(0),
+            )?;
+
+            let mut statement = connection.prepare(&format!(
+                "SELECT payload FROM sensor_readings {} ORDER BY recorded_at ASC, id ASC LIMIT ?4 OFFSET ?5",
+                filter
+            ))?;
+            let payloads = statement
+                .query_map(params![device_id, from_ms, to_ms, limit, offset], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok((total, payloads))
+        })?;
+
+        let readings = payloads.iter()
+            .filter_map(|payload| serde_json::from_str::<SensorData>(payload).ok())
+            .collect();
+
+        Ok(SensorHistoryPage {
+            readings,
+            total: total as u64,
+            limit,
+            offset,
+        })
+    }
+
+    // Number of readings in [from, to], optionally for one device
+    pub fn count(&self, device_id: Option<&str>, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<u64> {
+        let from_ms = from.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN);
+        let to_ms = to.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX);
+        let total: i64 = self.with_connection(|connection| {
+            connection.query_row(
+                "SELECT COUNT(*) FROM sensor_readings WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3",
+                params![device_id, from_ms, to_ms],
+                |row| row.get(0),
+            )
+        })?;
+        Ok(total as u64)
+    }
+
+    // Readings in [from, to] after `after`, oldest first, for walking a whole range.
+    // Seeks on (recorded_at, id), so late pages cost no more than the first.
+    pub fn query_after(
+        &self,
+        device_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        after: Option<ReadingCursor>,
+        limit: u32,
+    ) -> Result<(Vec<SensorData>, Option<ReadingCursor>)> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let from_ms = from.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN);
+        let to_ms = to.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX);
+        let (after_at, after_id) = after.map_or((i64::MIN, i64::MIN), |cursor| (cursor.recorded_at, cursor.id));
+
+        let rows = self.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT recorded_at, id, payload FROM sensor_readings
+                 WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3
+                   AND (recorded_at > ?4 OR (recorded_at = ?4 AND id > ?5))
+                 ORDER BY recorded_at ASC, id ASC LIMIT ?6",
+            )?;
+            let rows = statement
+                .query_map(params![device_id, from_ms, to_ms, after_at, after_id, limit], |row| {
+                    Ok((ReadingCursor { recorded_at: row.get(0)?, id: row.get(1)? }, row.get::<_, String>(2)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        })?;
+
+        let next = rows.last().map(|(cursor, _)| *cursor);
+        let readings = rows.iter()
+            .filter_map(|(_, payload)| serde_json::from_str::<SensorData>(payload).ok())
+            .collect();
+        Ok((readings, next))
+    }
+
+    // Records an alert this app published; message_id links it to the delivery tracker
+    pub fn insert_sent_alert(
+        &self,
+        alert: &AlertData,
+        source: AlertSource,
+        message_id: Option<u64>,
+        status: DeliveryStatus,
+        error: Option<&str>,
+    ) -> Result<()> {
+        self.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO alert_history
+                     (recorded_at, direction, source, level, message, alert_timestamp, message_id, delivery_status, delivery_error, alert_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    Utc::now().timestamp_millis(),
+                    enum_text(&AlertDirection::Sent),
+                    enum_text(&source),
+                    enum_text(&alert.level),
+                    alert.message,
+                    alert.timestamp.timestamp_millis(),
+                    message_id.map(|id| id as i64),
+                    enum_text(&status),
+                    error,
+                    alert.id,
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    // Records an alert seen on the broker, skipping the echo of one we sent ourselves
+    pub fn insert_received_alert(&self, alert: &AlertData) -> Result<bool> {
+        let inserted = self.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO alert_history (recorded_at, direction, source, level, message, alert_timestamp, alert_id)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?8
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM alert_history
+                     WHERE direction = ?7 AND message = ?5 AND alert_timestamp = ?6
+                 )",
+                params![
+                    Utc::now().timestamp_millis(),
+                    enum_text(&AlertDirection::Received),
+                    enum_text(&AlertSource::External),
+                    enum_text(&alert.level),
+                    alert.message,
+                    alert.timestamp.timestamp_millis(),
+                    enum_text(&AlertDirection::Sent),
+                    alert.id,
+                ],
+            )
+        })?;
+        Ok(inserted > 0)
+    }
+
+    // Marks every record of the alert as acknowledged; false when it's unknown or already acked
+    pub fn acknowledge_alert(&self, alert_id: &str, acknowledged_by: &str) -> Result<bool> {
+        let updated = self.with_connection(|connection| {
+            connection.execute(
+                "UPDATE alert_history SET acknowledged_at = ?1, acknowledged_by = ?2
+                 WHERE alert_id = ?3 AND acknowledged_at IS NULL",
+                params![Utc::now().timestamp_millis(), acknowledged_by, alert_id],
+            )
+        })?;
+        Ok(updated > 0)
+    }
+
+    // Applies a delivery result to the most recent alert sent with this message id
+    pub fn update_alert_delivery(&self, message_id: u64, status: DeliveryStatus, error: Option<&str>) -> Result<()> {
+        self.with_connection(|connection| {
+            connection.execute(
+                "UPDATE alert_history SET delivery_status = ?2, delivery_error = ?3
+                 WHERE id = (SELECT MAX(id) FROM alert_history WHERE message_id = ?1)",
+                params![message_id as i64, enum_text(&status), error],
+            )
+        })?;
+        Ok(())
+    }
+
+    pub fn query_alerts(&self, filter: &AlertHistoryFilter, limit: u32, offset: u32) -> Result<AlertHistoryPage> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let from_ms = filter.from.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN);
+        let to_ms = filter.to.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX);
+        let level = filter.level.as_ref().map(enum_text);
+        let source = filter.source.as_ref().map(enum_text);
+        let direction = filter.direction.as_ref().map(enum_text);
+        let acknowledged = filter.acknowledged;
+
+        let (total, alerts) = self.with_connection(|connection| {
+            // NULL filters match everything
+            let condition = "WHERE recorded_at BETWEEN ?1 AND ?2
+                 AND (?3 IS NULL OR level = ?3)
+                 AND (?4 IS NULL OR source = ?4)
+                 AND (?5 IS NULL OR direction = ?5)
+                 AND (?6 IS NULL OR (acknowledged_at IS NOT NULL) = ?6)";
+            let total: i64 = connection.query_row(
+                &format!("SELECT COUNT(*) FROM alert_history {}", condition),
+                params![from_ms, to_ms, level, source, direction, acknowledged],
+                |row| row.get(0),
+            )?;
+
+            let mut statement = connection.prepare(&format!(
+                "SELECT id, recorded_at, direction, source, level, message, alert_timestamp,
+                        message_id, delivery_status, delivery_error, alert_id, acknowledged_at, acknowledged_by
+                 FROM alert_history {} ORDER BY recorded_at DESC, id DESC LIMIT ?7 OFFSET ?8",
+                condition
+            ))?;
+            let alerts = statement
+                .query_map(params![from_ms, to_ms, level, source, direction, acknowledged, limit, offset], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, i64>(6)?,
+                        row.get::<_, Option<i64>>(7)?,
+                        row.get::<_, Option<String>>(8)?,
+                        row.get::<_, Option<String>>(9)?,
+                        row.get::<_, Option<String>>(10)?,
+                        row.get::<_, Option<i64>>(11)?,
+                        row.get::<_, Option<String>>(12)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((total, alerts))
+        })?;
+
+        let alerts = alerts.into_iter()
+            .filter_map(|(id, recorded_at, direction, source, level, message, alert_timestamp, message_id, status, error, alert_id, acknowledged_at, acknowledged_by)| {
+                Some(AlertRecord {
+                    id,
+                    recorded_at: DateTime::from_timestamp_millis(recorded_at)?,
+                    direction: parse_enum(&direction)?,
+                    source: parse_enum(&source)?,
+                    level: parse_enum(&level)?,
+                    message,
+                    alert_timestamp: DateTime::from_timestamp_millis(alert_timestamp)?,
+                    message_id: message_id.map(|id| id as u64),
+                    delivery_status: status.as_deref().and_then(parse_enum),
+                    delivery_error: error,
+                    alert_id,
+                    acknowledged_at: acknowledged_at.and_then(DateTime::from_timestamp_millis),
+                    acknowledged_by,
+                })
+            })
+            .collect();
+
+        Ok(AlertHistoryPage {
+            alerts,
+            total: total as u64,
+            limit,
+            offset,
+        })
+    }
+
+    // Rolls complete hours older than `older_than_days` into sensor_hourly. Raw rows
+    // are left in place for the retention policy to prune.
+    pub fn downsample(&self, older_than_days: u32) -> Result<u64> {
+        let cutoff = (Utc::now() - Duration::days(i64::from(older_than_days))).timestamp_millis() / HOUR_MS * HOUR_MS;
+        let hours = self.with_connection(|connection| Self::rollup(connection, cutoff))?;
+        if hours > 0 {
+            info!("Downsampled {} hours of sensor readings", hours);
+        }
+        Ok(hours)
+    }
+
+    // Aggregates every hour after the newest existing aggregate and before `cutoff`
+    fn rollup(connection: &Connection, cutoff: i64) -> rusqlite::Result<u64> {
+        let watermark: i64 = connection.query_row(
+            "SELECT COALESCE(MAX(hour_start) + ?1, 0) FROM sensor_hourly",
+            params![HOUR_MS],
+            |row| row.get(0),
+        )?;
+        if watermark >= cutoff {
+            return Ok(0);
+        }
+        let hours = connection.execute(ROLLUP_SQL, params![watermark, cutoff, HOUR_MS])?;
+        Ok(hours as u64)
+    }
+
+    // Hourly min/max/avg rows in [from, to], optionally for one device, oldest first
+    pub fn query_hourly(
+        &self,
+        device_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<HourlyHistoryPage> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let from_ms = from.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN);
+        let to_ms = to.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX);
+
+        let (total, aggregates) = self.with_connection(|connection| {
+            let filter = "WHERE (?1 IS NULL OR device_id = ?1) AND hour_start BETWEEN ?2 AND ?3";
+            let total: i64 = connection.query_row(
+                &format!("SELECT COUNT(*) FROM sensor_hourly {}", filter),
+                params![device_id, from_ms, to_ms],
+                |row| row.get(0),
+            )?;
+
+            let mut statement = connection.prepare(&format!(
+                "SELECT device_id, hour_start, samples,
+                        temperature_avg, temperature_min, temperature_max,
+                        humidity_avg, humidity_min, humidity_max,
+                        pressure_avg, pressure_min, pressure_max,
+                        co2_avg, co2_min, co2_max, tvoc_avg, tvoc_min, tvoc_max, lux_avg, lux_min, lux_max
+                 FROM sensor_hourly {} ORDER BY hour_start ASC, device_id ASC LIMIT ?4 OFFSET ?5",
+                filter
+            ))?;
+            let summary = |row: &rusqlite::Row, index: usize| -> rusqlite::Result<MetricSummary> {
+                Ok(MetricSummary { avg: row.get(index)?, min: row.get(index + 1)?, max: row.get(index + 2)? })
+            };
+            let optional_summary = |row: &rusqlite::Row, index: usize| -> rusqlite::Result<Option<MetricSummary>> {
+                let avg: Option<f64> = row.get(index)?;
+                let min: Option<f64> = row.get(index + 1)?;
+                let max: Option<f64> = row.get(index + 2)?;
+                Ok(avg.zip(min).zip(max).map(|((avg, min), max)| MetricSummary { avg, min, max }))
+            };
+            let aggregates = statement
+                .query_map(params![device_id, from_ms, to_ms, limit, offset], |row| {
+                    let hour_start: i64 = row.get(1)?;
+                    Ok(HourlyAggregate {
+                        device_id: row.get(0)?,
+                        hour_start: DateTime::from_timestamp_millis(hour_start).unwrap_or_default(),
+                        samples: row.get(2)?,
+                        temperature: summary(row, 3)?,
+                        humidity: summary(row, 6)?,
+                        pressure: summary(row, 9)?,
+                        co2: optional_summary(row, 12)?,
+                        tvoc: optional_summary(row, 15)?,
+                        lux: optional_summary(row, 18)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((total, aggregates))
+        })?;
+
+        Ok(HourlyHistoryPage {
+            aggregates,
+            total: total as u64,
+            limit,
+            offset,
+        })
+    }
+
+    // Deletes rows past the retention window, rolling raw readings into hourly
+    // aggregates first, and vacuums the database if anything was removed
+    pub fn apply_retention(&self, settings: &RetentionSettings) -> Result<RetentionReport> {
+        let now = Utc::now();
+        // Aligned to the hour so only complete hours are rolled up
+        let raw_cutoff = (now - Duration::days(i64::from(settings.raw_days.max(1)))).timestamp_millis() / HOUR_MS * HOUR_MS;
+        let aggregate_cutoff = (now - Duration::days(i64::from(settings.aggregate_days.max(settings.raw_days).max(1)))).timestamp_millis();
+
+        let report = self.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            // Catch up on hours the downsampling job hasn't reached yet before deleting them
+            let hours_aggregated = Self::rollup(&transaction, raw_cutoff)?;
+            let readings_deleted = transaction.execute("DELETE FROM sensor_readings WHERE recorded_at < ?1", params![raw_cutoff])?;
+            let weather_deleted = transaction.execute("DELETE FROM weather_snapshots WHERE recorded_at < ?1", params![raw_cutoff])?;
+            let aggregates_deleted = transaction.execute("DELETE FROM sensor_hourly WHERE hour_start < ?1", params![aggregate_cutoff])?;
+            transaction.commit()?;
+
+            Ok(RetentionReport {
+                readings_deleted: readings_deleted as u64,
+                hours_aggregated: hours_aggregated as u64,
+                aggregates_deleted: aggregates_deleted as u64,
+                weather_deleted: weather_deleted as u64,
+            })
+        })?;
+        if report.readings_deleted + report.aggregates_deleted + report.weather_deleted > 0 {
+            self.vacuum()?;
+        }
+
+        info!(
+            "Retention pruned {} readings, {} weather snapshots and {} aggregates ({} hours aggregated)",
+            report.readings_deleted, report.weather_deleted, report.aggregates_deleted, report.hours_aggregated
+        );
+        if let Ok(mut last_prune) = self.last_prune.lock() {
+            *last_prune = Some((now, report.clone()));
+        }
+        Ok(report)
+    }
+
+    // Vacuums on a connection of its own so the shared one isn't locked for the whole rebuild
+    fn vacuum(&self) -> Result<()> {
+        if self.restoring.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        Connection::open(&self.path)?.execute_batch("VACUUM;")?;
+        Ok(())
+    }
+
+    pub fn retention_status(&self) -> Result<RetentionStatus> {
+        let (sensor_readings, hourly_aggregates, weather_snapshots, oldest_reading, oldest_aggregate) = self.with_connection(|connection| {
+            let count = |table: &str| connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0));
+            Ok((
+                count("sensor_readings")?,
+                count("sensor_hourly")?,
+                count("weather_snapshots")?,
+                connection.query_row("SELECT MIN(recorded_at) FROM sensor_readings", [], |row| row.get::<_, Option<i64>>(0))?,
+                connection.query_row("SELECT MIN(hour_start) FROM sensor_hourly", [], |row| row.get::<_, Option<i64>>(0))?,
+            ))
+        })?;
+
+        let mut wal_path = self.path.clone().into_os_string();
+        wal_path.push("-wal");
+        let database_bytes = [self.path.clone(), PathBuf::from(wal_path)].iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        let last_prune = self.last_prune.lock().ok().and_then(|guard| guard.clone());
+
+        Ok(RetentionStatus {
+            sensor_readings: sensor_readings as u64,
+            hourly_aggregates: hourly_aggregates as u64,
+            weather_snapshots: weather_snapshots as u64,
+            oldest_reading: oldest_reading.and_then(DateTime::from_timestamp_millis),
+            oldest_aggregate: oldest_aggregate.and_then(DateTime::from_timestamp_millis),
+            database_bytes,
+            last_pruned: last_prune.as_ref().map(|(at, _)| *at),
+            last_report: last_prune.map(|(_, report)| report),
+        })
+    }
+}
+
+// Enums are stored by their serde names so the column values match the JSON API
+pub(crate) fn enum_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(text)) => text,
+        _ => String::new(),
+    }
+}
+
+fn parse_enum<T: serde::de::DeserializeOwned>(text: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(text.to_string())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_a_fresh_database() {
+        let dir = std::env::temp_dir().join(format!("history-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join(HISTORY_FILE_NAME);
+        let connection = SensorHistory::open(&path).unwrap();
+        let tables: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'
+                 AND name IN ('sensor_readings', 'alert_history', 'daily_summaries', 'forecast_warning_checks')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        drop(connection);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(tables, 4);
+    }
+}
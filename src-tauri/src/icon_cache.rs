@@ -0,0 +1,142 @@
+use crate::config::IconPackSettings;
+use crate::icons::PROVIDER_ICONS;
+use crate::storage;
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::time::Duration;
+use tracing::{info, debug, warn};
+
+pub const CODE_PLACEHOLDER: &str = "{code}";
+const ICON_DIR_NAME: &str = "icons";
+const TIMEOUT_SECS: u64 = 15;
+// Anything bigger is not an icon
+const MAX_ICON_BYTES: usize = 512 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IconAsset {
+    pub code: String,
+    pub path: String,
+    pub mime_type: String,
+    // data: URL, usable as an <img> source without network or file access
+    pub data_url: String,
+}
+
+fn client() -> Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(Duration::from_secs(TIMEOUT_SECS))
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to build icon HTTP client, using defaults: {}", e);
+                Client::new()
+            })
+    }).clone()
+}
+
+// One folder per pack, so switching packs never serves the previous pack's icons
+fn pack_dir(settings: &IconPackSettings) -> PathBuf {
+    // FNV-1a, stable across builds unlike the std hasher
+    let hash = settings.url_template.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    storage::data_dir().join(ICON_DIR_NAME).join(format!("{:016x}", hash))
+}
+
+// Taken from the template, e.g. "png" or "svg"
+fn extension(settings: &IconPackSettings) -> &str {
+    let path = settings.url_template.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit_once('.')
+        .map(|(_, extension)| extension)
+        .filter(|extension| !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("png")
+}
+
+fn mime_type(extension: &str) -> &'static str {
+    match extension.to_ascii_lowercase().as_str() {
+        "svg" => "image/svg+xml",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "jpg" | "jpeg" => "image/jpeg",
+        _ => "image/png",
+    }
+}
+
+// Codes become file names, so anything but letters and digits is refused
+fn check_code(code: &str) -> Result<()> {
+    if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(anyhow!("Invalid icon code '{}'", code));
+    }
+    Ok(())
+}
+
+fn icon_path(settings: &IconPackSettings, code: &str) -> PathBuf {
+    pack_dir(settings).join(format!("{}.{}", code, extension(settings)))
+}
+
+// The icon from the cache, downloading it the first time
+pub async fn get(settings: &IconPackSettings, code: &str) -> Result<IconAsset> {
+    check_code(code)?;
+    let path = icon_path(settings, code);
+    let bytes = match tokio::fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(_) => download(settings, code, &path).await?,
+    };
+    let mime_type = mime_type(extension(settings));
+    Ok(IconAsset {
+        code: code.to_string(),
+        path: path.display().to_string(),
+        mime_type: mime_type.to_string(),
+        data_url: format!("data:{};base64,{}", mime_type, BASE64.encode(&bytes)),
+    })
+}
+
+async fn download(settings: &IconPackSettings, code: &str, path: &Path) -> Result<Vec<u8>> {
+    let url = settings.url_template.replace(CODE_PLACEHOLDER, code);
+    debug!("Downloading icon {} from {}", code, url);
+    let response = client().get(&url).send().await?.error_for_status()?;
+    let bytes = response.bytes().await?;
+    if bytes.is_empty() || bytes.len() > MAX_ICON_BYTES {
+        return Err(anyhow!("Icon {} from {} is {} bytes, not an icon", code, url, bytes.len()));
+    }
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    // Written beside and renamed, so an interrupted download never leaves a partial icon
+    let partial = path.with_extension("part");
+    tokio::fs::write(&partial, &bytes).await?;
+    tokio::fs::rename(&partial, path).await?;
+    Ok(bytes.to_vec())
+}
+
+// Downloads every provider icon that isn't cached yet; returns how many were fetched
+pub async fn prefetch(settings: &IconPackSettings) -> Result<usize> {
+    let mut downloaded = 0;
+    let mut failed = Vec::new();
+    for code in PROVIDER_ICONS {
+        let path = icon_path(settings, code);
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            continue;
+        }
+        match download(settings, code, &path).await {
+            Ok(_) => downloaded += 1,
+            Err(e) => {
+                debug!("Failed to download icon {}: {}", code, e);
+                failed.push(*code);
+            }
+        }
+    }
+    if downloaded > 0 {
+        info!("Cached {} weather icon(s)", downloaded);
+    }
+    if !failed.is_empty() {
+        return Err(anyhow!("Failed to download icon(s) {}", failed.join(", ")));
+    }
+    Ok(downloaded)
+}
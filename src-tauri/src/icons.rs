@@ -0,0 +1,106 @@
+use crate::types::WeatherData;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Icons the M5Go firmware can draw
+pub const DEVICE_ICONS: &[&str] = &[
+    "clear_day",
+    "clear_night",
+    "partly_cloudy_day",
+    "partly_cloudy_night",
+    "cloudy",
+    "showers",
+    "rain",
+    "thunderstorm",
+    "snow",
+    "fog",
+];
+
+// OpenWeatherMap-style codes; Open-Meteo weather codes are translated into the same set
+pub const PROVIDER_ICONS: &[&str] = &[
+    "01d", "01n", "02d", "02n", "03d", "03n", "04d", "04n", "09d", "09n",
+    "10d", "10n", "11d", "11n", "13d", "13n", "50d", "50n",
+];
+
+pub fn default_icon_map() -> BTreeMap<String, String> {
+    [
+        ("01d", "clear_day"),
+        ("01n", "clear_night"),
+        ("02d", "partly_cloudy_day"),
+        ("02n", "partly_cloudy_night"),
+        ("03d", "cloudy"),
+        ("03n", "cloudy"),
+        ("04d", "cloudy"),
+        ("04n", "cloudy"),
+        ("09d", "showers"),
+        ("09n", "showers"),
+        ("10d", "rain"),
+        ("10n", "rain"),
+        ("11d", "thunderstorm"),
+        ("11n", "thunderstorm"),
+        ("13d", "snow"),
+        ("13n", "snow"),
+        ("50d", "fog"),
+        ("50n", "fog"),
+    ]
+    .into_iter()
+    .map(|(provider, device)| (provider.to_string(), device.to_string()))
+    .collect()
+}
+
+// Unmapped codes are passed through unchanged, so an empty map publishes raw provider icons
+pub fn map_icon(icon_map: &BTreeMap<String, String>, icon: &str) -> String {
+    icon_map.get(icon).cloned().unwrap_or_else(|| icon.to_string())
+}
+
+pub fn apply_icon_map(data: &mut WeatherData, icon_map: &BTreeMap<String, String>) {
+    if icon_map.is_empty() {
+        return;
+    }
+    data.current_icon = map_icon(icon_map, &data.current_icon);
+    for day in &mut data.forecast {
+        day.icon = map_icon(icon_map, &day.icon);
+    }
+    for hour in &mut data.hourly {
+        hour.icon = map_icon(icon_map, &hour.icon);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IconMapValidation {
+    pub valid: bool,
+    // Provider codes with no entry; they would be published raw
+    pub unmapped_codes: Vec<String>,
+    // Keys that no provider produces, probably typos
+    pub unknown_codes: Vec<String>,
+    // Values the firmware can't draw
+    pub unknown_device_icons: Vec<String>,
+}
+
+pub fn validate_icon_map(icon_map: &BTreeMap<String, String>) -> IconMapValidation {
+    let unmapped_codes: Vec<String> = if icon_map.is_empty() {
+        Vec::new()
+    } else {
+        PROVIDER_ICONS.iter()
+            .filter(|code| !icon_map.contains_key(**code))
+            .map(|code| code.to_string())
+            .collect()
+    };
+    let unknown_codes: Vec<String> = icon_map.keys()
+        .filter(|code| !PROVIDER_ICONS.contains(&code.as_str()))
+        .cloned()
+        .collect();
+    let mut unknown_device_icons: Vec<String> = icon_map.values()
+        .filter(|icon| !DEVICE_ICONS.contains(&icon.as_str()))
+        .cloned()
+        .collect();
+    unknown_device_icons.sort();
+    unknown_device_icons.dedup();
+
+    IconMapValidation {
+        valid: unmapped_codes.is_empty() && unknown_codes.is_empty() && unknown_device_icons.is_empty(),
+        unmapped_codes,
+        unknown_codes,
+        unknown_device_icons,
+    }
+}
@@ -0,0 +1,204 @@
+use crate::config::InfluxSettings;
+use crate::sinks::DataSink;
+use crate::types::SensorData;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, MissedTickBehavior};
+use tracing::{info, error, warn, debug};
+
+// Readings queued while a batch is being written; newer ones are dropped beyond this
+const QUEUE_CAPACITY: usize = 10_000;
+const SHUTDOWN_TIMEOUT_SECS: u64 = 5;
+pub const SINK_NAME: &str = "influxdb";
+
+enum SinkMessage {
+    Reading(String),
+    Shutdown,
+}
+
+// Writes sensor readings to InfluxDB v2 as line protocol, batched and retried
+pub struct InfluxSink {
+    sender: mpsc::Sender<SinkMessage>,
+    worker_handle: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+    measurement: String,
+}
+
+impl InfluxSink {
+    pub fn start(settings: InfluxSettings) -> Result<Self> {
+        if settings.url.is_empty() || settings.org.is_empty() || settings.bucket.is_empty() {
+            return Err(anyhow!("InfluxDB url, org and bucket must be configured"));
+        }
+
+        info!("Starting InfluxDB sink to {} (bucket {})", settings.url, settings.bucket);
+        let client = Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs.max(1)))
+            .build()?;
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let measurement = settings.measurement.clone();
+        let worker_handle = tokio::spawn(Self::run(client, settings, receiver));
+
+        Ok(Self {
+            sender,
+            worker_handle: std::sync::Mutex::new(Some(worker_handle)),
+            measurement,
+        })
+    }
+
+    async fn run(client: Client, settings: InfluxSettings, mut receiver: mpsc::Receiver<SinkMessage>) {
+        let batch_size = settings.batch_size.max(1);
+        let mut batch: Vec<String> = Vec::with_capacity(batch_size);
+        let mut ticker = tokio::time::interval(Duration::from_secs(settings.flush_interval_secs.max(1)));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                message = receiver.recv() => match message {
+                    Some(SinkMessage::Reading(line)) => {
+                        batch.push(line);
+                        if batch.len() >= batch_size {
+                            Self::flush(&client, &settings, &mut batch).await;
+                        }
+                    }
+                    Some(SinkMessage::Shutdown) | None => {
+                        Self::flush(&client, &settings, &mut batch).await;
+                        break;
+                    }
+                },
+                _ = ticker.tick() => {
+                    Self::flush(&client, &settings, &mut batch).await;
+                }
+            }
+        }
+    }
+
+    async fn flush(client: &Client, settings: &InfluxSettings, batch: &mut Vec<String>) {
+        if batch.is_empty() {
+            return;
+        }
+        let body = batch.join("\n");
+        let mut attempt = 0;
+        loop {
+            match Self::write(client, settings, &body).await {
+                Ok(()) => {
+                    debug!("Wrote {} points to InfluxDB", batch.len());
+                    break;
+                }
+                Err(WriteError::Rejected(e)) => {
+                    // Retrying a malformed batch or a bad token won't help
+                    error!("InfluxDB rejected {} points: {}", batch.len(), e);
+                    break;
+                }
+                Err(WriteError::Retryable(e)) if attempt < settings.max_retries => {
+                    attempt += 1;
+                    let delay = Duration::from_secs(1 << attempt.min(6));
+                    warn!("InfluxDB write failed: {}, retry {}/{} in {:?}", e, attempt, settings.max_retries, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(WriteError::Retryable(e)) => {
+                    error!("InfluxDB write failed after {} retries, dropping {} points: {}", attempt, batch.len(), e);
+                    break;
+                }
+            }
+        }
+        batch.clear();
+    }
+
+    async fn write(client: &Client, settings: &InfluxSettings, body: &str) -> Result<(), WriteError> {
+        let url = format!("{}/api/v2/write", settings.url.trim_end_matches('/'));
+        let response = client.post(&url)
+            .query(&[("org", settings.org.as_str()), ("bucket", settings.bucket.as_str()), ("precision", "ms")])
+            .header("Authorization", format!("Token {}", settings.token))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| WriteError::Retryable(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let message = format!("{}: {}", status, response.text().await.unwrap_or_default());
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            Err(WriteError::Retryable(message))
+        } else {
+            Err(WriteError::Rejected(message))
+        }
+    }
+}
+
+#[async_trait]
+impl DataSink for InfluxSink {
+    fn name(&self) -> &str {
+        SINK_NAME
+    }
+
+    // Queues a reading without waiting on the network
+    fn handle_sensor_data(&self, reading: &SensorData) {
+        let Some(line) = to_line_protocol(&self.measurement, reading) else {
+            return;
+        };
+        if let Err(e) = self.sender.try_send(SinkMessage::Reading(line)) {
+            warn!("Dropping InfluxDB point: {}", e);
+        }
+    }
+
+    // Flushes queued points, then stops the worker
+    async fn shutdown(&self) {
+        info!("Stopping InfluxDB sink");
+        let _ = self.sender.send(SinkMessage::Shutdown).await;
+        let handle = self.worker_handle.lock().ok().and_then(|mut guard| guard.take());
+        if let Some(mut handle) = handle {
+            if tokio::time::timeout(Duration::from_secs(SHUTDOWN_TIMEOUT_SECS), &mut handle).await.is_err() {
+                warn!("InfluxDB sink did not flush in time, aborting");
+                handle.abort();
+            }
+        }
+    }
+}
+
+enum WriteError {
+    Retryable(String),
+    Rejected(String),
+}
+
+fn to_line_protocol(measurement: &str, reading: &SensorData) -> Option<String> {
+    let mut line = escape(measurement, &[',', ' ']);
+    if let Some(device_id) = &reading.device_id {
+        line.push_str(&format!(",device_id={}", escape(device_id, &[',', ' ', '='])));
+    }
+    if let Some(device_name) = &reading.device_name {
+        line.push_str(&format!(",device_name={}", escape(device_name, &[',', ' ', '='])));
+    }
+
+    let fields: Vec<String> = [
+        ("temperature", Some(reading.temperature)),
+        ("humidity", Some(reading.humidity)),
+        ("pressure", Some(reading.pressure)),
+        ("co2", reading.co2),
+        ("tvoc", reading.tvoc),
+        ("lux", reading.lux),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.filter(|v| v.is_finite()).map(|v| format!("{}={}", name, v)))
+    .collect();
+    if fields.is_empty() {
+        return None;
+    }
+
+    let timestamp = reading.received_at.unwrap_or_else(chrono::Utc::now).timestamp_millis();
+    Some(format!("{} {} {}", line, fields.join(","), timestamp))
+}
+
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,418 @@
+use crate::config::{AppConfig, TracingSettings};
+use crate::config_transfer;
+use crate::storage;
+use anyhow::{Result, anyhow};
+use opentelemetry::trace::{Status, TracerProvider as _};
+use opentelemetry::{KeyValue, Value};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter as ExportSpans};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use tracing::{Level, Span};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Layer, Registry};
+
+const LOG_DIR_NAME: &str = "logs";
+const LOG_FILE_PREFIX: &str = "weather-station";
+const LOG_FILE_SUFFIX: &str = "log";
+// One file per day
+const MAX_LOG_FILES: usize = 7;
+const DEFAULT_LOG_LIMIT: usize = 200;
+const MAX_LOG_LIMIT: usize = 5000;
+const MASK: &str = "***";
+// Shorter configured secrets aren't masked by value, they'd match all over the log
+const MIN_SECRET_LEN: usize = 4;
+// Query parameters and JSON fields whose values are masked even when they aren't
+// configured secrets, e.g. a key typed into a one-off fetch
+const SECRET_FIELDS: [&str; 7] = ["appid", "api_key", "apikey", "key", "token", "password", "access_token"];
+
+// Empty until OTLP export is switched on; swapped at runtime when the setting changes
+type OtlpLayer = Option<Box<dyn Layer<Registry> + Send + Sync>>;
+static OTLP_LAYER: OnceLock<reload::Handle<OtlpLayer, Registry>> = OnceLock::new();
+// The running exporter and the settings it was started with
+static OTLP_EXPORT: Mutex<Option<(TracingSettings, TracerProvider)>> = Mutex::new(None);
+// Configured secret values, longest first so one containing another is masked whole
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+static REVEAL_RAW_PAYLOADS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    // As written, e.g. "2026-10-16T07:00:00.123456Z"
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+pub fn log_dir() -> PathBuf {
+    storage::data_dir().join(LOG_DIR_NAME)
+}
+
+// Logs to stdout and to daily rotating files in the data dir, since stdout isn't
+// visible in release builds on Windows. The guard flushes the file on drop, so it
+// has to live as long as the app.
+pub fn init() -> Option<WorkerGuard> {
+    let appender = Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir());
+
+    let (otlp, otlp_handle) = reload::Layer::<OtlpLayer, Registry>::new(None);
+    let _ = OTLP_LAYER.set(otlp_handle);
+    let registry = tracing_subscriber::registry()
+        .with(otlp)
+        .with(LevelFilter::INFO)
+        .with(fmt::layer().with_writer(Redacting(io::stdout)));
+    match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            registry.with(fmt::layer().with_ansi(false).with_writer(Redacting(writer))).init();
+            Some(guard)
+        }
+        Err(e) => {
+            registry.init();
+            tracing::warn!("File logging disabled: {}", e);
+            None
+        }
+    }
+}
+
+// Starts, stops or restarts the OTLP exporter to match the settings. Has to run on the
+// tokio runtime, which drives the batch exporter.
+pub fn set_otlp_export(settings: &TracingSettings) {
+    let Some(handle) = OTLP_LAYER.get() else {
+        return;
+    };
+    let mut export = OTLP_EXPORT.lock().unwrap();
+    let wanted = settings.otlp_enabled.then_some(settings);
+    let unchanged = match (export.as_ref(), wanted) {
+        (Some((current, _)), Some(wanted)) => {
+            current.otlp_endpoint == wanted.otlp_endpoint && current.service_name == wanted.service_name
+        }
+        (current, wanted) => current.is_none() && wanted.is_none(),
+    };
+    if unchanged {
+        return;
+    }
+
+    let previous = export.take();
+    let layer: OtlpLayer = match wanted.map(otlp_provider) {
+        Some(Ok(provider)) => {
+            let tracer = provider.tracer(LOG_FILE_PREFIX);
+            *export = Some((settings.clone(), provider));
+            tracing::info!("Exporting traces to {}", settings.otlp_endpoint);
+            Some(tracing_opentelemetry::layer().with_tracer(tracer).boxed())
+        }
+        Some(Err(e)) => {
+            tracing::warn!("Failed to start OTLP trace export: {}", e);
+            None
+        }
+        None => None,
+    };
+    drop(export);
+    if let Err(e) = handle.reload(layer) {
+        tracing::warn!("Failed to update trace export: {}", e);
+    }
+    // Flushing waits on the exporter task, so it can't block a runtime thread
+    if let Some((_, provider)) = previous {
+        tokio::task::spawn_blocking(move || shutdown_provider(provider));
+    }
+}
+
+// Sends the spans still waiting in the batch; called on exit
+pub fn shutdown_otlp_export() {
+    let export = OTLP_EXPORT.lock().unwrap().take();
+    if let Some((_, provider)) = export {
+        shutdown_provider(provider);
+    }
+}
+
+fn otlp_provider(settings: &TracingSettings) -> Result<TracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(settings.otlp_endpoint.clone())
+        .build()?;
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(RedactingExporter(exporter), runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", settings.service_name.clone())]))
+        .build())
+}
+
+// Masks secrets in span and event fields before export, as the log writers do for text
+#[derive(Debug)]
+struct RedactingExporter<E>(E);
+
+impl<E: ExportSpans> ExportSpans for RedactingExporter<E> {
+    fn export(&mut self, mut batch: Vec<SpanData>) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        for span in &mut batch {
+            redact_attributes(&mut span.attributes);
+            for event in span.events.events.iter_mut() {
+                // Event names carry the log message
+                if let Some(name) = redacted(&event.name) {
+                    event.name = name.into();
+                }
+                redact_attributes(&mut event.attributes);
+            }
+            if let Status::Error { description } = &mut span.status {
+                if let Some(masked) = redacted(description) {
+                    *description = masked.into();
+                }
+            }
+        }
+        self.0.export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        self.0.shutdown();
+    }
+
+    fn force_flush(&mut self) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        self.0.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.0.set_resource(resource);
+    }
+}
+
+fn redact_attributes(attributes: &mut [KeyValue]) {
+    for attribute in attributes {
+        if let Value::String(value) = &attribute.value {
+            if let Some(masked) = redacted(value.as_str()) {
+                attribute.value = Value::String(masked.into());
+            }
+        }
+    }
+}
+
+// The masked text, or None when there was nothing to mask
+fn redacted(text: &str) -> Option<String> {
+    match redact(text) {
+        Cow::Owned(masked) => Some(masked),
+        Cow::Borrowed(_) => None,
+    }
+}
+
+fn shutdown_provider(provider: TracerProvider) {
+    if let Err(e) = provider.shutdown() {
+        tracing::warn!("Failed to flush exported traces: {}", e);
+    }
+}
+
+// Fills in the `outcome` field the current span declared as Empty
+pub fn record_outcome<T, E: std::fmt::Display>(result: &std::result::Result<T, E>) {
+    let span = Span::current();
+    match result {
+        Ok(_) => span.record("outcome", "ok"),
+        Err(e) => span.record("outcome", tracing::field::display(format_args!("error: {}", redact(&e.to_string())))),
+    };
+}
+
+// Picks up the secrets to mask and whether console payload dumps may show them; called
+// whenever the config is loaded or saved
+pub fn apply_redaction(config: &AppConfig) {
+    let mut secrets: Vec<String> = config_transfer::secret_values(config)
+        .into_iter()
+        .filter(|secret| secret.len() >= MIN_SECRET_LEN)
+        .collect();
+    secrets.sort_by(|a, b| b.len().cmp(&a.len()));
+    secrets.dedup();
+    *SECRETS.write().unwrap() = secrets;
+    let reveal = cfg!(debug_assertions) && config.app.tracing.reveal_raw_payloads;
+    if REVEAL_RAW_PAYLOADS.swap(reveal, Ordering::Relaxed) != reveal && reveal {
+        tracing::warn!("Console payload dumps will include secrets");
+    }
+}
+
+// Masks configured secrets, secret query parameters and JSON fields, and bearer tokens
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut redacted = Cow::Borrowed(text);
+    for secret in SECRETS.read().unwrap().iter() {
+        if redacted.contains(secret.as_str()) {
+            redacted = Cow::Owned(redacted.replace(secret.as_str(), MASK));
+        }
+    }
+    for marker in secret_markers() {
+        if let Some(masked) = mask_after(&redacted, marker) {
+            redacted = Cow::Owned(masked);
+        }
+    }
+    if let Some(masked) = mask_after(&redacted, "Bearer ") {
+        redacted = Cow::Owned(masked);
+    }
+    redacted
+}
+
+// Query parameter and JSON prefixes of each secret field, built once
+fn secret_markers() -> &'static [String] {
+    static MARKERS: OnceLock<Vec<String>> = OnceLock::new();
+    MARKERS.get_or_init(|| {
+        SECRET_FIELDS.iter()
+            .flat_map(|field| [
+                format!("?{}=", field),
+                format!("&{}=", field),
+                format!("\"{}\":\"", field),
+                format!("\"{}\": \"", field),
+            ])
+            .collect()
+    })
+}
+
+// Replaces the value following each `marker`, up to the next separator. None when
+// there's nothing to mask.
+fn mask_after(text: &str, marker: &str) -> Option<String> {
+    if !text.contains(marker) {
+        return None;
+    }
+    let is_end = |c: char| c.is_whitespace() || matches!(c, '&' | '"' | '\'' | ')' | ',' | '>');
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut changed = false;
+    while let Some(start) = rest.find(marker) {
+        let value_start = start + marker.len();
+        masked.push_str(&rest[..value_start]);
+        let value = &rest[value_start..];
+        let end = value.find(is_end).unwrap_or(value.len());
+        if end > 0 {
+            masked.push_str(MASK);
+            changed |= &value[..end] != MASK;
+        }
+        rest = &value[end..];
+    }
+    masked.push_str(rest);
+    changed.then_some(masked)
+}
+
+// Pretty-prints an outgoing payload to the console, in debug builds only. Secrets are
+// masked unless tracing.reveal_raw_payloads is on.
+pub fn print_payload<T: Serialize + std::fmt::Debug>(label: &str, payload: &T) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let text = serde_json::to_string_pretty(payload).unwrap_or_else(|e| {
+        tracing::warn!("Failed to serialize payload for printing: {}", e);
+        format!("{:?}", payload)
+    });
+    if REVEAL_RAW_PAYLOADS.load(Ordering::Relaxed) {
+        println!("{}:\n{}", label, text);
+    } else {
+        println!("{}:\n{}", label, redact(&text));
+    }
+}
+
+// Hands out writers that mask secrets in each formatted event before writing it
+struct Redacting<M>(M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+// The fmt layer writes each event in a single call, so secrets are never split
+// across writes
+struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Newest entries last, at or above `min_level` (error, warn, info, debug, trace)
+pub fn recent(min_level: Option<&str>, limit: Option<usize>) -> Result<Vec<LogEntry>> {
+    let min_level = match min_level {
+        Some(level) => Level::from_str(level).map_err(|_| anyhow!("Unknown log level: {}", level))?,
+        None => Level::TRACE,
+    };
+    let limit = limit.unwrap_or(DEFAULT_LOG_LIMIT).clamp(1, MAX_LOG_LIMIT);
+
+    let mut files: Vec<PathBuf> = match fs::read_dir(log_dir()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with(LOG_FILE_PREFIX)))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    // File names end in the date, so they sort chronologically
+    files.sort();
+
+    let mut entries = Vec::new();
+    for file in files.iter().rev() {
+        let content = fs::read_to_string(file)?;
+        let mut from_file: Vec<LogEntry> = parse(&content)
+            .into_iter()
+            .filter(|entry| Level::from_str(&entry.level).is_ok_and(|level| level <= min_level))
+            .collect();
+        let room = limit - entries.len();
+        if from_file.len() > room {
+            from_file.drain(..from_file.len() - room);
+        }
+        from_file.append(&mut entries);
+        entries = from_file;
+        if entries.len() >= limit {
+            break;
+        }
+    }
+    Ok(entries)
+}
+
+// Lines look like "2026-10-16T07:00:00.123456Z  INFO weather_station_desktop::config: Saved config".
+// Lines that don't, e.g. multi-line messages, belong to the entry before them.
+fn parse(content: &str) -> Vec<LogEntry> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    for line in content.lines() {
+        match parse_line(line) {
+            Some(entry) => entries.push(entry),
+            None => {
+                if let Some(last) = entries.last_mut() {
+                    last.message.push('\n');
+                    last.message.push_str(line);
+                }
+            }
+        }
+    }
+    entries
+}
+
+fn parse_line(line: &str) -> Option<LogEntry> {
+    let (timestamp, rest) = line.split_once(' ')?;
+    if !timestamp.ends_with('Z') || !timestamp.contains('T') {
+        return None;
+    }
+    let (level, rest) = rest.trim_start().split_once(' ')?;
+    Level::from_str(level).ok()?;
+    let (target, message) = rest.split_once(": ").unwrap_or(("", rest));
+    Some(LogEntry {
+        timestamp: timestamp.to_string(),
+        level: level.to_string(),
+        target: target.to_string(),
+        message: message.to_string(),
+    })
+}
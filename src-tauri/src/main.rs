@@ -3,161 +3,581 @@
 
 mod mqtt_client;
 mod weather_api;
+mod fetch_limiter;
 mod types;
 mod config;
+mod bridge;
+mod delivery;
+mod proxy;
+mod devices;
+mod geo;
+mod auto_location;
+mod providers;
+mod api_usage;
+mod severe_weather;
+mod icons;
+mod icon_cache;
+mod validation;
+mod storage;
+mod history;
+mod export;
+mod retention;
+mod influx;
+mod sinks;
+mod csv_log;
+mod wunderground;
+mod opensensemap;
+mod csv_import;
+mod statistics;
+mod anomaly;
+mod smoothing;
+mod metrics;
+mod conversions;
+mod forecasting;
+mod daily_summary;
+mod chart_data;
+mod sun_automation;
+mod scheduler;
+mod drift;
+mod grafana;
+mod rest_api;
+mod grpc;
+mod simulator;
+#[cfg(feature = "ble")]
+mod ble;
+mod payload_templates;
+mod alert_rules;
+mod webhooks;
+mod email;
+mod notifications;
+mod chat;
+mod triggers;
+mod forecast_warnings;
+mod rain_alerts;
+mod config_transfer;
+mod config_validation;
+mod config_watcher;
+mod autostart;
+mod tray;
+mod logging;
+mod deep_link;
+mod crash;
+mod app_error;
+mod events;
+mod service;
+mod supervisor;
+mod mqtt_health;
+mod coordination;
+mod health_check;
+mod startup;
+mod scripting;
+mod error;
 
-use mqtt_client::MqttManager;
-use weather_api::WeatherApiClient;
+use mqtt_client::{MqttHandle, MqttManager};
+use mqtt_health::MqttStatus;
+use weather_api::{WeatherApiClient, WeatherCacheInfo};
+use fetch_limiter::FetchLimiter;
+use severe_weather::SevereWeatherMonitor;
+use rain_alerts::RainMonitor;
+use icons::IconMapValidation;
+use storage::{PruneReport, StorageUsage};
+use history::{AlertHistoryFilter, BackupInfo, AlertHistoryPage, HourlyHistoryPage, RetentionStatus, SensorHistoryPage, WeatherSnapshotPage, DEFAULT_PAGE_SIZE};
+use export::{ExportFormat, ExportRange, ExportSummary};
+use csv_import::{CsvColumnMapping, ImportSummary};
+use config_transfer::ConfigImportPreview;
+use config_validation::ConfigFieldError;
+use deep_link::DeepLinkAction;
+use error::AppError;
+use events::{AppEvent, DesktopAction};
+use tauri_plugin_deep_link::DeepLinkExt;
+use statistics::SensorStatistics;
 use types::*;
-use config::{ConfigManager, AppConfig, MqttSettings, WeatherApiSettings, AppSettings};
+use api_usage::ApiUsage;
+use delivery::DeliveryRecord;
+use devices::DeviceInfo;
+use notifications::{ChannelTestResult, NotificationChannel, TestOutcome};
+use config::{ConfigManager, AppConfig, ConfigBackup, MqttSettings, WeatherApiSettings, AppSettings, DeviceSettings, AlertRule, ScheduledTask, WebhookSettings, EmailSettings, TelegramSettings, DiscordSettings, TriggerSettings, GrafanaSettings, RestApiSettings, GrpcSettings, NamedLocation};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tauri::{State, Emitter, Manager};
-use tracing::{info, error};
+use tracing::{info, error, warn, instrument};
+
+const DEVICE_ACK_TIMEOUT_SECS: u64 = 15;
 
 // Application state
 #[derive(Clone)]
 pub struct AppState {
-    mqtt_manager: Arc<Mutex<MqttManager>>,
+    mqtt_manager: MqttHandle,
     weather_api: Arc<WeatherApiClient>,
+    // Provider fetches triggered from the UI, coalesced and rate limited per place
+    weather_fetches: Arc<FetchLimiter<WeatherData>>,
+    current_fetches: Arc<FetchLimiter<CurrentWeather>>,
     config_manager: Arc<Mutex<ConfigManager>>,
-    app_handle: Arc<Mutex<Option<tauri::AppHandle>>>,
+    grafana: Arc<Mutex<Option<grafana::GrafanaServer>>>,
+    rest_api: Arc<Mutex<Option<rest_api::RestApiServer>>>,
+    grpc: Arc<Mutex<Option<grpc::GrpcServer>>>,
+    simulator: Arc<Mutex<Option<simulator::Simulator>>>,
+    #[cfg(feature = "ble")]
+    ble: Arc<Mutex<Option<ble::BleTransport>>>,
 }
 
 #[tauri::command]
+#[instrument(skip(state))]
 async fn connect_mqtt(
     broker_host: String,
     broker_port: u16,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     info!("Connecting to MQTT broker: {}:{}", broker_host, broker_port);
     
-    // Set app handle in MQTT manager
-    let app_handle_guard = state.app_handle.lock().await;
-    if let Some(ref handle) = *app_handle_guard {
-        let mut mqtt_manager = state.mqtt_manager.lock().await;
-        mqtt_manager.set_app_handle(handle.clone());
-    }
-    drop(app_handle_guard);
+    let (mqtt_settings, device_settings) = {
+        let config_manager = state.config_manager.lock().await;
+        (config_manager.mqtt_settings().clone(), config_manager.device_settings().clone())
+    };
     
-    let mut mqtt_manager = state.mqtt_manager.lock().await;
-    match mqtt_manager.connect(&broker_host, broker_port).await {
+    state.mqtt_manager.set_device_settings(device_settings).await;
+    match state.mqtt_manager.connect(mqtt_settings, &broker_host, broker_port).await {
         Ok(_) => {
             info!("Successfully connected to MQTT broker");
             Ok("Connected successfully".to_string())
         }
         Err(e) => {
             error!("Failed to connect to MQTT broker: {}", e);
-            Err(format!("Connection failed: {}", e))
+            Err(AppError::from_error("Connection failed", e))
         }
     }
 }
 
 #[tauri::command]
-async fn disconnect_mqtt(state: State<'_, AppState>) -> Result<String, String> {
+#[instrument(skip_all)]
+async fn disconnect_mqtt(state: State<'_, AppState>) -> Result<String, AppError> {
     info!("Disconnecting from MQTT broker");
     
-    let mut mqtt_manager = state.mqtt_manager.lock().await;
-    match mqtt_manager.disconnect().await {
+    match state.mqtt_manager.disconnect().await {
         Ok(_) => {
             info!("Successfully disconnected from MQTT broker");
             Ok("Disconnected successfully".to_string())
         }
         Err(e) => {
             error!("Failed to disconnect from MQTT broker: {}", e);
-            Err(format!("Disconnect failed: {}", e))
+            Err(AppError::from_error("Disconnect failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_mqtt_status(state: State<'_, AppState>) -> Result<MqttStatus, AppError> {
+    Ok(state.mqtt_manager.status())
+}
+
+// Actively checks the broker, weather API, cache dir and database for the diagnostics page
+#[tauri::command]
+async fn run_health_check(state: State<'_, AppState>) -> Result<health_check::HealthReport, AppError> {
+    let (mqtt, weather_api) = {
+        let config_manager = state.config_manager.lock().await;
+        (config_manager.mqtt_settings().clone(), config_manager.weather_api_settings().clone())
+    };
+    Ok(health_check::run(mqtt, weather_api, state.weather_api.clone(), state.mqtt_manager.sensor_history()).await)
+}
+
+// Names of the registered data sinks, e.g. influxdb, csv and webhooks
+#[tauri::command]
+async fn get_data_sinks() -> Result<Vec<String>, AppError> {
+    Ok(sinks::names())
+}
+
+// Upload counts and the last error; None until uploads have been enabled
+#[tauri::command]
+async fn get_pws_upload_status() -> Result<Option<sinks::UploadStatus>, AppError> {
+    Ok(wunderground::status())
+}
+
+#[tauri::command]
+async fn get_opensensemap_status() -> Result<Option<sinks::UploadStatus>, AppError> {
+    Ok(opensensemap::status())
+}
+
+// Scripts in the data dir's scripts folder and their hooks and errors
+#[tauri::command]
+async fn get_script_status() -> Result<Vec<scripting::ScriptStatus>, AppError> {
+    Ok(scripting::status())
+}
+
+#[tauri::command]
+async fn reload_scripts() -> Result<Vec<scripting::ScriptStatus>, AppError> {
+    match tokio::task::spawn_blocking(scripting::reload).await {
+        Ok(scripts) => {
+            info!("Reloaded {} scripts", scripts.len());
+            Ok(scripts)
+        }
+        Err(e) => {
+            error!("Failed to reload scripts: {}", e);
+            Err(AppError::from_error("Failed to reload scripts", e))
         }
     }
 }
 
+// Steps already reported through "startup-progress", for a window that missed them
+#[tauri::command]
+async fn get_startup_progress() -> Result<Vec<startup::StartupProgress>, AppError> {
+    Ok(startup::progress())
+}
+
+// How often the status bar gets a "connection-status" event
+const CONNECTION_STATUS_INTERVAL_SECS: u64 = 10;
+
+async fn connection_status(state: &AppState) -> ConnectionStatus {
+    let mqtt = state.mqtt_manager.is_connected();
+    let devices = state.mqtt_manager.get_devices().await;
+    let (lat, lon) = state.config_manager.lock().await.active_coordinates();
+    let cache = state.weather_api.cache_info(lat, lon).await;
+    let fetch = state.weather_api.fetch_status();
+    let api = match (fetch.last_success, fetch.last_failure) {
+        (Some(success), Some(failure)) => success > failure,
+        (_, failure) => failure.is_none(),
+    };
+
+    ConnectionStatus {
+        mqtt,
+        api,
+        last_update: cache.last_updated.map(|t| t.with_timezone(&chrono::Utc)),
+        api_last_success: fetch.last_success,
+        api_last_failure: fetch.last_failure,
+        api_last_error: fetch.last_error,
+        api_degraded: state.weather_api.is_degraded(),
+        cache_valid: cache.valid,
+        cache_age_minutes: cache.age_minutes,
+        devices_online: devices.iter().filter(|d| d.online).count(),
+        devices_total: devices.len(),
+        checked_at: chrono::Utc::now(),
+    }
+}
+
 #[tauri::command]
-async fn get_mqtt_status(state: State<'_, AppState>) -> Result<bool, String> {
-    let mqtt_manager = state.mqtt_manager.lock().await;
-    Ok(mqtt_manager.is_connected())
+async fn get_connection_status(state: State<'_, AppState>) -> Result<ConnectionStatus, AppError> {
+    Ok(connection_status(&state).await)
 }
 
 #[tauri::command]
+#[instrument(skip_all)]
 async fn publish_weather_data(
     data: WeatherData,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     info!("Publishing weather data to MQTT");
     
-    let mqtt_manager = state.mqtt_manager.lock().await;
-    match mqtt_manager.publish_weather_data(&data).await {
+    match state.mqtt_manager.publish_weather_data(&data).await {
         Ok(_) => {
             info!("Weather data published successfully");
             Ok("Data published successfully".to_string())
         }
         Err(e) => {
             error!("Failed to publish weather data: {}", e);
-            Err(format!("Publish failed: {}", e))
+            Err(AppError::from_error("Publish failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+#[instrument(skip_all)]
+async fn publish_retained_snapshot(state: State<'_, AppState>) -> Result<String, AppError> {
+    info!("Publishing retained weather snapshot");
+    
+    match state.mqtt_manager.publish_retained_snapshot().await {
+        Ok(_) => {
+            info!("Retained weather snapshot published successfully");
+            Ok("Retained snapshot published successfully".to_string())
+        }
+        Err(e) => {
+            error!("Failed to publish retained weather snapshot: {}", e);
+            Err(AppError::from_error("Publish failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_latest_weather_data(state: State<'_, AppState>) -> Result<Option<WeatherData>, AppError> {
+    Ok(state.mqtt_manager.get_latest_weather_data())
+}
+
+#[tauri::command]
+async fn get_sensor_data(state: State<'_, AppState>) -> Result<Option<SensorData>, AppError> {
+    Ok(state.mqtt_manager.get_latest_sensor_data())
+}
+
+#[tauri::command]
+async fn get_latest_alert(state: State<'_, AppState>) -> Result<Option<AlertData>, AppError> {
+    match state.mqtt_manager.get_latest_alert().await {
+        Ok(alert) => Ok(alert),
+        Err(e) => {
+            error!("Failed to load latest alert: {}", e);
+            Err(AppError::from_error("Failed to load latest alert", e))
+        }
+    }
+}
+
+// Stored summary for the day, computed on the fly when it hasn't been generated yet
+#[tauri::command]
+async fn get_daily_summary(
+    date: chrono::NaiveDate,
+    state: State<'_, AppState>,
+) -> Result<daily_summary::DailySummary, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    match tokio::task::spawn_blocking(move || daily_summary::get(&history, date)).await {
+        Ok(Ok(summary)) => Ok(summary),
+        Ok(Err(e)) => {
+            error!("Failed to build daily summary: {}", e);
+            Err(AppError::from_error("Failed to build daily summary", e))
+        }
+        Err(e) => {
+            error!("Daily summary task failed: {}", e);
+            Err(AppError::from_error("Failed to build daily summary", e))
+        }
+    }
+}
+
+// One metric over a recent range, downsampled for plotting
+#[tauri::command]
+async fn get_chart_data(
+    metric: chart_data::ChartMetric,
+    range: chart_data::ChartRange,
+    max_points: Option<usize>,
+    device_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<chart_data::ChartSeries, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    let max_points = max_points.unwrap_or(chart_data::DEFAULT_MAX_POINTS);
+    match tokio::task::spawn_blocking(move || chart_data::series(&history, metric, range, max_points, device_id.as_deref())).await {
+        Ok(Ok(series)) => Ok(series),
+        Ok(Err(e)) => {
+            error!("Failed to load chart data: {}", e);
+            Err(AppError::from_error("Failed to load chart data", e))
+        }
+        Err(e) => {
+            error!("Chart data task failed: {}", e);
+            Err(AppError::from_error("Failed to load chart data", e))
         }
     }
 }
 
+// Today's sunrise and sunset with the upcoming automation schedule; None until a
+// weather report with sun times has arrived
+#[tauri::command]
+async fn get_sun_times(state: State<'_, AppState>) -> Result<Option<sun_automation::SunTimes>, AppError> {
+    let settings = state.config_manager.lock().await.get_config().app.sun_automation.clone();
+    Ok(state.mqtt_manager.get_latest_weather_data()
+        .and_then(|weather| sun_automation::sun_times(&weather, &settings)))
+}
+
+// Zambretti forecast from the station's own pressure, tendency and the current wind
+#[tauri::command]
+async fn get_local_forecast(state: State<'_, AppState>) -> Result<forecasting::LocalForecast, AppError> {
+    state.mqtt_manager.local_forecast().await
+        .ok_or_else(|| AppError::NotFound { message: "No sensor data received yet".to_string() })
+}
+
+// Converts between units of one quantity, e.g. (1013.25, "hPa", "inHg"); units are
+// given by symbol or name
 #[tauri::command]
-async fn get_latest_weather_data(state: State<'_, AppState>) -> Result<Option<WeatherData>, String> {
-    let mqtt_manager = state.mqtt_manager.lock().await;
-    Ok(mqtt_manager.get_latest_weather_data().await)
+async fn convert_value(value: f64, from_unit: String, to_unit: String) -> Result<f64, AppError> {
+    let convert = || conversions::convert(value, from_unit.parse()?, to_unit.parse()?);
+    convert().map_err(|e| AppError::from_error("Conversion failed", e))
 }
 
+// In-memory readings for live sparklines; defaults to the last hour
 #[tauri::command]
-async fn get_sensor_data(state: State<'_, AppState>) -> Result<Option<SensorData>, String> {
-    let mqtt_manager = state.mqtt_manager.lock().await;
-    Ok(mqtt_manager.get_latest_sensor_data().await)
+async fn get_recent_sensor_data(
+    minutes: Option<u32>,
+    device_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SensorData>, AppError> {
+    Ok(state.mqtt_manager.get_recent_sensor_data(minutes.unwrap_or(60), device_id.as_deref()).await)
 }
 
 #[tauri::command]
+#[instrument(skip(state, api_key))]
 async fn fetch_weather_api(
     lat: f64,
     lon: f64,
     api_key: String,
     state: State<'_, AppState>,
-) -> Result<WeatherData, String> {
-    info!("Fetching weather data from API for lat: {}, lon: {}", lat, lon);
-    
-    match state.weather_api.fetch_weather(lat, lon, &api_key).await {
-        Ok(weather_data) => {
-            info!("Weather data fetched successfully");
-            Ok(weather_data)
-        }
-        Err(e) => {
-            error!("Failed to fetch weather data: {}", e);
-            Err(format!("API fetch failed: {}", e))
+) -> Result<WeatherData, AppError> {
+    // Keyed by the API key too, so a corrected key isn't answered with the old failure
+    let key = fetch_limiter::key(&fetch_limiter::api_key_scope(&api_key), lat, lon);
+    let interval = min_fetch_interval(&state).await;
+    state.weather_fetches.fetch(key, interval, || async {
+        info!("Fetching weather data from API for lat: {}, lon: {}", lat, lon);
+        match state.weather_api.fetch_weather(lat, lon, &api_key).await {
+            Ok(weather_data) => {
+                info!("Weather data fetched successfully");
+                Ok(weather_data)
+            }
+            Err(e) => {
+                error!("Failed to fetch weather data: {}", e);
+                Err(AppError::from_error("API fetch failed", e))
+            }
         }
-    }
+    }).await
+}
+
+async fn min_fetch_interval(state: &AppState) -> std::time::Duration {
+    let secs = state.config_manager.lock().await.weather_api_settings().min_fetch_interval_secs;
+    std::time::Duration::from_secs(secs)
 }
 
 #[tauri::command]
+#[instrument(skip(state))]
 async fn send_alert(
     message: String,
     level: AlertLevel,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     info!("Sending alert: {} (level: {:?})", message, level);
     
-    let mqtt_manager = state.mqtt_manager.lock().await;
     let alert = AlertData {
         message,
         level,
         timestamp: chrono::Utc::now(),
+        id: None,
     };
     
-    match mqtt_manager.send_alert(&alert).await {
-        Ok(_) => {
+    match state.mqtt_manager.send_alert(&alert, AlertSource::Manual).await {
+        Ok(Some(message_id)) => {
+            info!("Alert sent successfully (message id {})", message_id);
+            Ok(format!("Alert sent successfully (message id {})", message_id))
+        }
+        Ok(None) => {
             info!("Alert sent successfully");
             Ok("Alert sent successfully".to_string())
         }
         Err(e) => {
             error!("Failed to send alert: {}", e);
-            Err(format!("Alert failed: {}", e))
+            Err(AppError::from_error("Alert failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_delivery_status(
+    message_id: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<DeliveryRecord>, AppError> {
+    Ok(state.mqtt_manager.get_delivery_status(message_id))
+}
+
+#[tauri::command]
+async fn get_devices(state: State<'_, AppState>) -> Result<Vec<DeviceInfo>, AppError> {
+    Ok(state.mqtt_manager.get_devices().await)
+}
+
+#[tauri::command]
+async fn push_device_config(
+    device_id: String,
+    config: DeviceConfig,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    info!("Pushing configuration to device {}", device_id);
+    
+    let (request_id, ack) = match state.mqtt_manager.push_device_config(&device_id, config).await {
+        Ok(pushed) => pushed,
+        Err(e) => {
+            error!("Failed to push device config: {}", e);
+            return Err(AppError::from_error("Config push failed", e));
+        }
+    };
+    
+    match tokio::time::timeout(tokio::time::Duration::from_secs(DEVICE_ACK_TIMEOUT_SECS), ack).await {
+        Ok(Ok(ack)) if ack.success => {
+            info!("Device {} applied config {}", device_id, request_id);
+            Ok("Device configuration applied".to_string())
+        }
+        Ok(Ok(ack)) => {
+            let reason = ack.message.unwrap_or_else(|| "unknown error".to_string());
+            error!("Device {} rejected config {}: {}", device_id, request_id, reason);
+            Err(AppError::DeviceRejected { message: format!("Device rejected configuration: {}", reason) })
+        }
+        Ok(Err(_)) | Err(_) => {
+            state.mqtt_manager.cancel_ack(&request_id).await;
+            error!("Device {} did not acknowledge config {}", device_id, request_id);
+            Err(AppError::DeviceTimeout { message: format!("Device did not acknowledge within {} seconds (config is retained and will apply when it reconnects)", DEVICE_ACK_TIMEOUT_SECS) })
+        }
+    }
+}
+
+async fn run_device_command(
+    device_id: &str,
+    command: &str,
+    state: &State<'_, AppState>,
+) -> Result<DeviceAck, AppError> {
+    let (request_id, ack) = state.mqtt_manager.send_device_command(device_id, command, None).await.map_err(|e| {
+        error!("Failed to send '{}' to device {}: {}", command, device_id, e);
+        AppError::from_error("Command failed", e)
+    })?;
+    
+    match tokio::time::timeout(tokio::time::Duration::from_secs(DEVICE_ACK_TIMEOUT_SECS), ack).await {
+        Ok(Ok(ack)) => Ok(ack),
+        Ok(Err(_)) | Err(_) => {
+            state.mqtt_manager.cancel_ack(&request_id).await;
+            error!("Device {} did not acknowledge '{}' ({})", device_id, command, request_id);
+            Err(AppError::DeviceTimeout { message: format!("Device did not acknowledge within {} seconds", DEVICE_ACK_TIMEOUT_SECS) })
+        }
+    }
+}
+
+#[tauri::command]
+async fn reboot_device(
+    device_id: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    info!("Rebooting device {}", device_id);
+    
+    let ack = run_device_command(&device_id, "reboot", &state).await?;
+    if ack.success {
+        info!("Device {} is rebooting", device_id);
+        Ok("Device is rebooting".to_string())
+    } else {
+        let reason = ack.message.unwrap_or_else(|| "unknown error".to_string());
+        error!("Device {} refused reboot: {}", device_id, reason);
+        Err(AppError::DeviceRejected { message: format!("Device refused reboot: {}", reason) })
+    }
+}
+
+#[tauri::command]
+async fn factory_reset_device(
+    device_id: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    info!("Factory resetting device {}", device_id);
+    
+    let ack = run_device_command(&device_id, "factory_reset", &state).await?;
+    if ack.success {
+        info!("Device {} is performing a factory reset", device_id);
+        Ok("Device is performing a factory reset".to_string())
+    } else {
+        let reason = ack.message.unwrap_or_else(|| "unknown error".to_string());
+        error!("Device {} refused factory reset: {}", device_id, reason);
+        Err(AppError::DeviceRejected { message: format!("Device refused factory reset: {}", reason) })
+    }
+}
+
+#[tauri::command]
+async fn sync_device_time(state: State<'_, AppState>) -> Result<TimeSync, AppError> {
+    info!("Syncing device time on demand");
+    
+    match state.mqtt_manager.sync_device_time().await {
+        Ok(time_sync) => {
+            info!("Time sync published: {}", time_sync.iso);
+            Ok(time_sync)
+        }
+        Err(e) => {
+            error!("Failed to publish time sync: {}", e);
+            Err(AppError::from_error("Time sync failed", e))
         }
     }
 }
 
 #[tauri::command]
-async fn get_config(state: State<'_, AppState>) -> Result<AppConfig, String> {
+async fn get_config(state: State<'_, AppState>) -> Result<AppConfig, AppError> {
     let config_manager = state.config_manager.lock().await;
     Ok(config_manager.get_config().clone())
 }
@@ -166,16 +586,90 @@ async fn get_config(state: State<'_, AppState>) -> Result<AppConfig, String> {
 async fn save_config(
     config: AppConfig,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     let mut config_manager = state.config_manager.lock().await;
-    match config_manager.update_config(config) {
+    match config_manager.update_config(config).await {
         Ok(_) => {
+            apply_config(&config_manager, &state).await;
             info!("Configuration saved successfully");
             Ok("Configuration saved successfully".to_string())
         }
         Err(e) => {
             error!("Failed to save configuration: {}", e);
-            Err(format!("Failed to save configuration: {}", e))
+            Err(AppError::from_error("Failed to save configuration", e))
+        }
+    }
+}
+
+// Per-field problems with a config, empty when it can be saved
+#[tauri::command]
+async fn validate_config(config: AppConfig) -> Result<Vec<ConfigFieldError>, AppError> {
+    Ok(config_validation::validate(&config))
+}
+
+// Pushes a newly saved config to the running components
+async fn apply_config(config_manager: &ConfigManager, state: &AppState) {
+    state.weather_api.update_settings(config_manager.weather_api_settings().clone()).await;
+    let device_settings = config_manager.device_settings().clone();
+    let alert_rules = config_manager.alert_rules().to_vec();
+    state.mqtt_manager.set_device_settings(device_settings).await;
+    state.mqtt_manager.set_alert_rules(alert_rules).await;
+    state.mqtt_manager.alert_channels().apply_settings(&config_manager.get_config().app);
+    events::perform(DesktopAction::LaunchAtLogin(config_manager.get_config().app.launch_at_login));
+    tray::set_run_in_background(config_manager.get_config().app.run_in_background);
+    events::set_sensor_event_settings(&config_manager.get_config().app.sensor_events);
+    logging::set_otlp_export(&config_manager.get_config().app.tracing);
+    scripting::apply_settings(&config_manager.get_config().app.scripting);
+}
+
+#[tauri::command]
+async fn export_config(
+    path: String,
+    include_secrets: bool,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let config = state.config_manager.lock().await.get_config().clone();
+    match config_transfer::export(&config, std::path::Path::new(&path), include_secrets) {
+        Ok(_) => Ok(format!("Configuration exported to {}", path)),
+        Err(e) => {
+            error!("Config export failed: {}", e);
+            Err(AppError::from_error("Config export failed", e))
+        }
+    }
+}
+
+// Validates a config file and lists what importing it would change, without applying it
+#[tauri::command]
+async fn preview_config_import(
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<ConfigImportPreview, AppError> {
+    let config_manager = state.config_manager.lock().await;
+    config_transfer::preview(std::path::Path::new(&path), config_manager.get_config()).map_err(|e| {
+        error!("Config import preview failed: {}", e);
+        AppError::from_error("Config import preview failed", e)
+    })
+}
+
+#[tauri::command]
+async fn import_config(
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    let result = match config_transfer::load(std::path::Path::new(&path), config_manager.get_config()) {
+        Ok((config, _)) => config_manager.update_config(config).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => {
+            apply_config(&config_manager, &state).await;
+            info!("Configuration imported from {}", path);
+            Ok(format!("Configuration imported from {}", path))
+        }
+        Err(e) => {
+            error!("Config import failed: {}", e);
+            Err(AppError::from_error("Config import failed", e))
         }
     }
 }
@@ -184,34 +678,56 @@ async fn save_config(
 async fn save_mqtt_settings(
     mqtt_settings: MqttSettings,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     let mut config_manager = state.config_manager.lock().await;
-    match config_manager.update_mqtt_settings(mqtt_settings) {
+    match config_manager.update_mqtt_settings(mqtt_settings).await {
         Ok(_) => {
             info!("MQTT settings saved successfully");
             Ok("MQTT settings saved successfully".to_string())
         }
         Err(e) => {
             error!("Failed to save MQTT settings: {}", e);
-            Err(format!("Failed to save MQTT settings: {}", e))
+            Err(AppError::from_error("Failed to save MQTT settings", e))
+        }
+    }
+}
+
+// New broker identity for this installation; a live connection reconnects with it
+#[tauri::command]
+async fn regenerate_client_id(state: State<'_, AppState>) -> Result<String, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    let client_id = match config_manager.regenerate_client_id().await {
+        Ok(client_id) => client_id,
+        Err(e) => {
+            error!("Failed to regenerate client id: {}", e);
+            return Err(AppError::from_error("Failed to regenerate client id", e));
         }
+    };
+    let mqtt_settings = config_manager.mqtt_settings().clone();
+    drop(config_manager);
+
+    if let Err(e) = state.mqtt_manager.apply_settings(mqtt_settings).await {
+        error!("Failed to reconnect with the new client id: {}", e);
+        return Err(AppError::from_error(&format!("Client id changed to {}, but reconnecting failed", client_id), e));
     }
+    Ok(client_id)
 }
 
 #[tauri::command]
 async fn save_weather_api_settings(
     weather_api_settings: WeatherApiSettings,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     let mut config_manager = state.config_manager.lock().await;
-    match config_manager.update_weather_api_settings(weather_api_settings) {
+    match config_manager.update_weather_api_settings(weather_api_settings.clone()).await {
         Ok(_) => {
+            state.weather_api.update_settings(weather_api_settings).await;
             info!("Weather API settings saved successfully");
             Ok("Weather API settings saved successfully".to_string())
         }
         Err(e) => {
             error!("Failed to save Weather API settings: {}", e);
-            Err(format!("Failed to save Weather API settings: {}", e))
+            Err(AppError::from_error("Failed to save Weather API settings", e))
         }
     }
 }
@@ -220,116 +736,915 @@ async fn save_weather_api_settings(
 async fn save_app_settings(
     app_settings: AppSettings,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
+    let grafana_settings = app_settings.grafana.clone();
+    let rest_api_settings = app_settings.rest_api.clone();
+    let grpc_settings = app_settings.grpc.clone();
+    #[cfg(feature = "ble")]
+    let ble_settings = app_settings.ble.clone();
     let mut config_manager = state.config_manager.lock().await;
-    match config_manager.update_app_settings(app_settings) {
+    match config_manager.update_app_settings(app_settings).await {
         Ok(_) => {
+            let alert_channels = state.mqtt_manager.alert_channels();
+            alert_channels.apply_settings(&config_manager.get_config().app);
+            events::perform(DesktopAction::LaunchAtLogin(config_manager.get_config().app.launch_at_login));
+            tray::set_run_in_background(config_manager.get_config().app.run_in_background);
+            events::set_sensor_event_settings(&config_manager.get_config().app.sensor_events);
+            logging::set_otlp_export(&config_manager.get_config().app.tracing);
+            scripting::apply_settings(&config_manager.get_config().app.scripting);
+            if let Err(e) = apply_grafana_settings(&state, grafana_settings).await {
+                error!("Failed to start Grafana datasource: {}", e);
+                return Err(AppError::from_error("Settings saved, but the Grafana datasource failed to start", e));
+            }
+            if let Err(e) = apply_rest_api_settings(&state, rest_api_settings).await {
+                error!("Failed to start REST API: {}", e);
+                return Err(AppError::from_error("Settings saved, but the REST API failed to start", e));
+            }
+            if let Err(e) = apply_grpc_settings(&state, grpc_settings).await {
+                error!("Failed to start gRPC API: {}", e);
+                return Err(AppError::from_error("Settings saved, but the gRPC API failed to start", e));
+            }
+            #[cfg(feature = "ble")]
+            if let Err(e) = apply_ble_settings(&state, ble_settings).await {
+                error!("Failed to start BLE transport: {}", e);
+                return Err(AppError::from_error("Settings saved, but the BLE transport failed to start", e));
+            }
             info!("App settings saved successfully");
             Ok("App settings saved successfully".to_string())
         }
         Err(e) => {
             error!("Failed to save app settings: {}", e);
-            Err(format!("Failed to save app settings: {}", e))
+            Err(AppError::from_error("Failed to save app settings", e))
+        }
+    }
+}
+
+// Restarts the Grafana datasource only when its settings changed
+async fn apply_grafana_settings(state: &AppState, grafana_settings: GrafanaSettings) -> anyhow::Result<()> {
+    let mut grafana = state.grafana.lock().await;
+    let running = grafana.as_ref().map(|server| server.settings().clone());
+    let wanted = Some(grafana_settings).filter(|settings| settings.enabled);
+    if running != wanted {
+        if let Some(server) = grafana.take() {
+            server.shutdown().await;
+        }
+        if let Some(settings) = wanted {
+            let history = state.mqtt_manager.sensor_history();
+            *grafana = Some(grafana::GrafanaServer::start(settings, history).await?);
+        }
+    }
+    Ok(())
+}
+
+// Restarts the REST API only when its settings changed
+async fn apply_rest_api_settings(state: &AppState, rest_api_settings: RestApiSettings) -> anyhow::Result<()> {
+    let mut rest_api = state.rest_api.lock().await;
+    let running = rest_api.as_ref().map(|server| server.settings().clone());
+    let wanted = Some(rest_api_settings).filter(|settings| settings.enabled);
+    if running != wanted {
+        if let Some(server) = rest_api.take() {
+            server.shutdown().await;
+        }
+        if let Some(settings) = wanted {
+            *rest_api = Some(rest_api::RestApiServer::start(settings, state.mqtt_manager.clone()).await?);
+        }
+    }
+    Ok(())
+}
+
+// Restarts the gRPC API only when its settings changed
+async fn apply_grpc_settings(state: &AppState, grpc_settings: GrpcSettings) -> anyhow::Result<()> {
+    let mut grpc = state.grpc.lock().await;
+    let running = grpc.as_ref().map(|server| server.settings().clone());
+    let wanted = Some(grpc_settings).filter(|settings| settings.enabled);
+    if running != wanted {
+        if let Some(server) = grpc.take() {
+            server.shutdown().await;
+        }
+        if let Some(settings) = wanted {
+            *grpc = Some(grpc::GrpcServer::start(settings, state.mqtt_manager.clone()).await?);
+        }
+    }
+    Ok(())
+}
+
+// Restarts the BLE transport only when its settings changed
+#[cfg(feature = "ble")]
+async fn apply_ble_settings(state: &AppState, ble_settings: config::BleSettings) -> anyhow::Result<()> {
+    let mut ble = state.ble.lock().await;
+    let running = ble.as_ref().map(|transport| transport.settings().clone());
+    let wanted = Some(ble_settings).filter(|settings| settings.enabled);
+    if running != wanted {
+        if let Some(transport) = ble.take() {
+            transport.stop().await;
+        }
+        if let Some(settings) = wanted {
+            *ble = Some(ble::BleTransport::start(settings, state.mqtt_manager.clone())?);
         }
     }
+    Ok(())
+}
+
+// Applies edits made to config.toml while the app is running. An invalid file is
+// ignored and the running config kept.
+async fn reload_config(app_handle: &tauri::AppHandle) {
+    let state: State<AppState> = app_handle.state();
+    let previous = match state.config_manager.lock().await.reload().await {
+        Ok(Some(previous)) => previous,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Ignoring config.toml change: {}", e);
+            events::publish(AppEvent::ConfigReloadFailed(e.to_string()));
+            return;
+        }
+    };
+    info!("Reloaded config.toml after an external change");
+    let config = apply_replaced_config(&state, &previous).await;
+    events::publish(AppEvent::ConfigReloaded(config));
+}
+
+// Pushes a config that replaced `previous` wholesale to the running components,
+// including the MQTT connection, the Grafana datasource and the REST API
+async fn apply_replaced_config(state: &AppState, previous: &AppConfig) -> AppConfig {
+    let config = {
+        let config_manager = state.config_manager.lock().await;
+        apply_config(&config_manager, state).await;
+        config_manager.get_config().clone()
+    };
+
+    if serde_json::to_value(&previous.mqtt).ok() != serde_json::to_value(&config.mqtt).ok() {
+        info!("MQTT settings changed, applying them to the connection");
+        if let Err(e) = state.mqtt_manager.apply_settings(config.mqtt.clone()).await {
+            error!("Failed to apply new MQTT settings: {}", e);
+        }
+    }
+    let coordinates = config.weather_api.active_coordinates();
+    if let Err(e) = switch_location(state, previous.weather_api.active_coordinates(), coordinates).await {
+        error!("Failed to switch location: {}", e);
+    }
+    if let Err(e) = apply_grafana_settings(state, config.app.grafana.clone()).await {
+        error!("Failed to start Grafana datasource: {}", e);
+    }
+    if let Err(e) = apply_rest_api_settings(state, config.app.rest_api.clone()).await {
+        error!("Failed to start REST API: {}", e);
+    }
+    if let Err(e) = apply_grpc_settings(state, config.app.grpc.clone()).await {
+        error!("Failed to start gRPC API: {}", e);
+    }
+    #[cfg(feature = "ble")]
+    if let Err(e) = apply_ble_settings(state, config.app.ble.clone()).await {
+        error!("Failed to start BLE transport: {}", e);
+    }
+    config
 }
 
 #[tauri::command]
-async fn test_emit_sensor_data(
+async fn list_config_backups(state: State<'_, AppState>) -> Result<Vec<ConfigBackup>, AppError> {
+    state.config_manager.lock().await.config_backups().await.map_err(|e| {
+        error!("Failed to list config backups: {}", e);
+        AppError::from_error("Failed to list config backups", e)
+    })
+}
+
+// Rolls back to an automatic backup, e.g. after saving a wrong broker address
+#[tauri::command]
+async fn restore_config_backup(
+    timestamp: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
-    let test_sensor_data = SensorData {
-        temperature: 25.5,
-        humidity: 60.0,
-        pressure: 1013.2,
-        timestamp: "2025-07-03T12:00:00".to_string(),
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let (previous, result) = {
+        let mut config_manager = state.config_manager.lock().await;
+        let previous = config_manager.get_config().clone();
+        (previous, config_manager.restore_config_backup(&timestamp).await)
     };
-    
-    info!("Testing sensor data event emission");
-    match app.emit_to("main", "sensor-data-updated", &test_sensor_data) {
+    match result {
         Ok(_) => {
-            info!("Test sensor data event emitted successfully");
-            Ok("Test event emitted".to_string())
+            let config = apply_replaced_config(&state, &previous).await;
+            if let Err(e) = app.emit("config-changed", config) {
+                warn!("Failed to emit config-changed: {}", e);
+            }
+            Ok(format!("Configuration restored from {}", timestamp))
         }
         Err(e) => {
-            error!("Failed to emit test sensor data event: {}", e);
-            Err(format!("Failed to emit test event: {}", e))
+            error!("Config restore failed: {}", e);
+            Err(AppError::from_error("Config restore failed", e))
         }
     }
 }
 
+// Changes a single setting, e.g. ("app.dark_mode", true), so concurrent edits to other
+// settings aren't overwritten with a stale copy of the whole config
 #[tauri::command]
-async fn start_automated_weather_publishing(
-    lat: f64,
-    lon: f64,
+async fn update_config_field(
+    path: String,
+    value: serde_json::Value,
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
-) -> Result<String, String> {
-    info!("Starting automated weather publishing for coordinates: {}, {}", lat, lon);
-    
-    let mut mqtt_manager = state.mqtt_manager.lock().await;
-    match mqtt_manager.start_automated_weather_publishing(lat, lon).await {
+) -> Result<String, AppError> {
+    let (previous, result) = {
+        let mut config_manager = state.config_manager.lock().await;
+        let previous = config_manager.get_config().clone();
+        (previous, config_manager.update_field(&path, value).await)
+    };
+    match result {
         Ok(_) => {
-            info!("Automated weather publishing started successfully");
-            Ok("Automated weather publishing started".to_string())
+            let config = apply_replaced_config(&state, &previous).await;
+            info!("Updated setting {}", path);
+            if let Err(e) = app.emit("config-changed", config) {
+                warn!("Failed to emit config-changed: {}", e);
+            }
+            Ok(format!("Updated {}", path))
         }
         Err(e) => {
-            error!("Failed to start automated weather publishing: {}", e);
-            Err(format!("Failed to start automated publishing: {}", e))
+            error!("Failed to update {}: {}", path, e);
+            Err(AppError::from_error(&format!("Failed to update {}", path), e))
         }
     }
 }
 
+// Restores all settings, or one section (mqtt, weather_api, app), to the defaults.
+// Returns where the previous config was archived.
 #[tauri::command]
-async fn stop_automated_weather_publishing(
+async fn reset_config(
+    section: Option<String>,
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
-) -> Result<String, String> {
-    info!("Stopping automated weather publishing");
-    
-    let mut mqtt_manager = state.mqtt_manager.lock().await;
-    match mqtt_manager.stop_automated_weather_publishing().await {
-        Ok(_) => {
-            info!("Automated weather publishing stopped successfully");
-            Ok("Automated weather publishing stopped".to_string())
+) -> Result<Option<String>, AppError> {
+    let (previous, result) = {
+        let mut config_manager = state.config_manager.lock().await;
+        let previous = config_manager.get_config().clone();
+        (previous, config_manager.reset(section.as_deref()).await)
+    };
+    match result {
+        Ok(archive) => {
+            let config = apply_replaced_config(&state, &previous).await;
+            info!("Configuration reset to defaults ({})", section.as_deref().unwrap_or("all sections"));
+            if let Err(e) = app.emit("config-changed", config) {
+                warn!("Failed to emit config-changed: {}", e);
+            }
+            Ok(archive.map(|path| path.display().to_string()))
         }
         Err(e) => {
-            error!("Failed to stop automated weather publishing: {}", e);
-            Err(format!("Failed to stop automated publishing: {}", e))
+            error!("Config reset failed: {}", e);
+            Err(AppError::from_error("Config reset failed", e))
         }
     }
 }
 
+// Sends a sample alert to the webhook so its settings can be checked before saving
 #[tauri::command]
-async fn is_auto_publishing(state: State<'_, AppState>) -> Result<bool, String> {
-    let mqtt_manager = state.mqtt_manager.lock().await;
-    Ok(mqtt_manager.is_auto_publishing())
+async fn test_webhook(webhook: WebhookSettings, state: State<'_, AppState>) -> Result<String, AppError> {
+    let alert_channels = state.mqtt_manager.alert_channels();
+    let alert = AlertData {
+        message: "Test alert from the weather station".to_string(),
+        level: AlertLevel::Info,
+        timestamp: chrono::Utc::now(),
+        id: None,
+    };
+    match alert_channels.webhooks.send_test(&webhook, &alert).await {
+        Ok(_) => Ok("Webhook delivered".to_string()),
+        Err(e) => {
+            error!("Webhook test failed: {}", e);
+            Err(AppError::from_error("Webhook test failed", e))
+        }
+    }
 }
 
+// Sends a test message with the given settings, which need not be saved yet
 #[tauri::command]
-async fn fetch_weather_with_default_key(
-    lat: f64,
-    lon: f64,
-    state: State<'_, AppState>,
-) -> Result<WeatherData, String> {
-    info!("Fetching weather data with default API key for coordinates: {}, {}", lat, lon);
-    
-    match state.weather_api.fetch_weather_with_default_key(lat, lon).await {
-        Ok(weather_data) => {
-            info!("Weather data fetched successfully with default key");
-            Ok(weather_data)
-        }
+async fn send_test_email(email: EmailSettings, state: State<'_, AppState>) -> Result<String, AppError> {
+    let alert_channels = state.mqtt_manager.alert_channels();
+    match alert_channels.email.send_test(&email).await {
+        Ok(_) => Ok("Test email sent".to_string()),
         Err(e) => {
-            error!("Failed to fetch weather data with default key: {}", e);
-            Err(format!("API fetch failed: {}", e))
+            error!("Test email failed: {}", e);
+            Err(AppError::from_error("Test email failed", e))
         }
     }
 }
 
 #[tauri::command]
-async fn refresh_weather_cache(
-    lat: f64,
-    lon: f64,
+async fn send_test_telegram(telegram: TelegramSettings, state: State<'_, AppState>) -> Result<String, AppError> {
+    let alert_channels = state.mqtt_manager.alert_channels();
+    match alert_channels.chat.send_test_telegram(&telegram).await {
+        Ok(_) => Ok("Telegram message sent".to_string()),
+        Err(e) => {
+            error!("Telegram test failed: {}", e);
+            Err(AppError::from_error("Telegram test failed", e))
+        }
+    }
+}
+
+// Fires alert_fired with the given settings, which need not be saved yet
+#[tauri::command]
+async fn send_test_trigger(triggers: TriggerSettings, state: State<'_, AppState>) -> Result<String, AppError> {
+    let alert_channels = state.mqtt_manager.alert_channels();
+    match alert_channels.triggers.send_test(&triggers).await {
+        Ok(_) => Ok("Test trigger sent".to_string()),
+        Err(e) => {
+            error!("Trigger test failed: {}", e);
+            Err(AppError::from_error("Trigger test failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn send_test_discord(discord: DiscordSettings, state: State<'_, AppState>) -> Result<String, AppError> {
+    let alert_channels = state.mqtt_manager.alert_channels();
+    match alert_channels.chat.send_test_discord(&discord).await {
+        Ok(_) => Ok("Discord message sent".to_string()),
+        Err(e) => {
+            error!("Discord test failed: {}", e);
+            Err(AppError::from_error("Discord test failed", e))
+        }
+    }
+}
+
+// Pushes a synthetic alert through the MQTT and notification pipeline so the setup
+// can be checked; reports the outcome per channel
+#[tauri::command]
+async fn send_test_notification(
+    channel: NotificationChannel,
+    level: AlertLevel,
+    state: State<'_, AppState>,
+) -> Result<Vec<ChannelTestResult>, AppError> {
+    info!("Sending test notification ({:?}, {:?})", channel, level);
+    let alert = AlertData {
+        message: format!("Test {:?} alert from the weather station", level),
+        level,
+        timestamp: chrono::Utc::now(),
+        id: None,
+    };
+
+    let mut results = Vec::new();
+    if matches!(channel, NotificationChannel::All | NotificationChannel::Mqtt) {
+        results.push(match state.mqtt_manager.publish_test_alert(&alert).await {
+            Ok(Some(message_id)) => ChannelTestResult::new("mqtt", TestOutcome::Delivered, format!("Published (message id {})", message_id)),
+            Ok(None) => ChannelTestResult::new("mqtt", TestOutcome::Delivered, "Published"),
+            Err(e) => {
+                error!("Test alert publish failed: {}", e);
+                ChannelTestResult::new("mqtt", TestOutcome::Failed, e.to_string())
+            }
+        });
+    }
+    results.extend(state.mqtt_manager.alert_channels().send_test(channel, &alert).await);
+    Ok(results)
+}
+
+#[tauri::command]
+async fn list_alert_rules(state: State<'_, AppState>) -> Result<Vec<AlertRule>, AppError> {
+    Ok(state.config_manager.lock().await.alert_rules().to_vec())
+}
+
+#[tauri::command]
+async fn create_alert_rule(rule: AlertRule, state: State<'_, AppState>) -> Result<AlertRule, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    match config_manager.add_alert_rule(rule).await {
+        Ok(rule) => {
+            let rules = config_manager.alert_rules().to_vec();
+            state.mqtt_manager.set_alert_rules(rules).await;
+            info!("Alert rule {} created", rule.id);
+            Ok(rule)
+        }
+        Err(e) => {
+            error!("Failed to create alert rule: {}", e);
+            Err(AppError::from_error("Failed to create alert rule", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn update_alert_rule(rule: AlertRule, state: State<'_, AppState>) -> Result<AlertRule, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    match config_manager.update_alert_rule(rule).await {
+        Ok(rule) => {
+            let rules = config_manager.alert_rules().to_vec();
+            state.mqtt_manager.set_alert_rules(rules).await;
+            info!("Alert rule {} updated", rule.id);
+            Ok(rule)
+        }
+        Err(e) => {
+            error!("Failed to update alert rule: {}", e);
+            Err(AppError::from_error("Failed to update alert rule", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn delete_alert_rule(id: u32, state: State<'_, AppState>) -> Result<String, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    match config_manager.delete_alert_rule(id).await {
+        Ok(_) => {
+            let rules = config_manager.alert_rules().to_vec();
+            state.mqtt_manager.set_alert_rules(rules).await;
+            info!("Alert rule {} deleted", id);
+            Ok("Alert rule deleted".to_string())
+        }
+        Err(e) => {
+            error!("Failed to delete alert rule: {}", e);
+            Err(AppError::from_error("Failed to delete alert rule", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn set_alert_rule_enabled(id: u32, enabled: bool, state: State<'_, AppState>) -> Result<AlertRule, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    match config_manager.set_alert_rule_enabled(id, enabled).await {
+        Ok(rule) => {
+            let rules = config_manager.alert_rules().to_vec();
+            state.mqtt_manager.set_alert_rules(rules).await;
+            info!("Alert rule {} {}", id, if enabled { "enabled" } else { "disabled" });
+            Ok(rule)
+        }
+        Err(e) => {
+            error!("Failed to toggle alert rule: {}", e);
+            Err(AppError::from_error("Failed to toggle alert rule", e))
+        }
+    }
+}
+
+fn task_context(state: &AppState) -> scheduler::TaskContext {
+    scheduler::TaskContext {
+        config_manager: state.config_manager.clone(),
+        weather_api: state.weather_api.clone(),
+        mqtt_manager: state.mqtt_manager.clone(),
+    }
+}
+
+// Saved tasks with their next and last run
+#[tauri::command]
+async fn list_scheduled_tasks(state: State<'_, AppState>) -> Result<Vec<scheduler::ScheduledTaskInfo>, AppError> {
+    Ok(scheduler::describe(state.config_manager.lock().await.scheduled_tasks()))
+}
+
+#[tauri::command]
+async fn add_scheduled_task(task: ScheduledTask, state: State<'_, AppState>) -> Result<ScheduledTask, AppError> {
+    match state.config_manager.lock().await.add_scheduled_task(task).await {
+        Ok(task) => {
+            info!("Scheduled task {} added", task.id);
+            Ok(task)
+        }
+        Err(e) => {
+            error!("Failed to add scheduled task: {}", e);
+            Err(AppError::from_error("Failed to add scheduled task", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn remove_scheduled_task(id: u32, state: State<'_, AppState>) -> Result<String, AppError> {
+    match state.config_manager.lock().await.delete_scheduled_task(id).await {
+        Ok(_) => {
+            info!("Scheduled task {} removed", id);
+            Ok("Scheduled task removed".to_string())
+        }
+        Err(e) => {
+            error!("Failed to remove scheduled task: {}", e);
+            Err(AppError::from_error("Failed to remove scheduled task", e))
+        }
+    }
+}
+
+// Runs a saved task straight away, whether or not it is enabled
+#[tauri::command]
+async fn run_scheduled_task(id: u32, state: State<'_, AppState>) -> Result<String, AppError> {
+    let task = state.config_manager.lock().await.scheduled_tasks().iter().find(|t| t.id == id).cloned();
+    let Some(task) = task else {
+        return Err(AppError::NotFound { message: format!("Unknown scheduled task: {}", id) });
+    };
+    scheduler::run(&task_context(&state), &task).await
+        .map_err(|e| AppError::from_error("Scheduled task failed", e))
+}
+
+#[tauri::command]
+async fn save_device_settings(
+    device_id: String,
+    device_settings: DeviceSettings,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    match config_manager.update_device_settings(device_id.clone(), device_settings).await {
+        Ok(_) => {
+            let device_settings = config_manager.device_settings().clone();
+            state.mqtt_manager.set_device_settings(device_settings).await;
+            info!("Device settings for {} saved successfully", device_id);
+            Ok("Device settings saved successfully".to_string())
+        }
+        Err(e) => {
+            error!("Failed to save device settings: {}", e);
+            Err(AppError::from_error("Failed to save device settings", e))
+        }
+    }
+}
+
+// Drops alerts raised from the device's readings for `duration_minutes`; 0 unmutes
+#[tauri::command]
+async fn mute_device_alerts(
+    device_id: String,
+    duration_minutes: u32,
+    state: State<'_, AppState>,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, AppError> {
+    let until = (duration_minutes > 0).then(|| chrono::Utc::now() + chrono::Duration::minutes(i64::from(duration_minutes)));
+    let mut config_manager = state.config_manager.lock().await;
+    match config_manager.set_device_muted_until(&device_id, until).await {
+        Ok(_) => {
+            let device_settings = config_manager.device_settings().clone();
+            state.mqtt_manager.set_device_settings(device_settings).await;
+            match until {
+                Some(until) => info!("Alerts from {} muted until {}", device_id, until),
+                None => info!("Alerts from {} unmuted", device_id),
+            }
+            Ok(until)
+        }
+        Err(e) => {
+            error!("Failed to mute device alerts: {}", e);
+            Err(AppError::from_error("Failed to mute device alerts", e))
+        }
+    }
+}
+
+// Publishes synthetic readings as if from an M5Go; replaces a simulator already running
+#[tauri::command]
+async fn start_simulator(
+    options: Option<simulator::SimulatorOptions>,
+    state: State<'_, AppState>,
+) -> Result<simulator::SimulatorStatus, AppError> {
+    if !state.mqtt_manager.is_connected() {
+        return Err(AppError::from_error("Failed to start the simulator", mqtt_client::NotConnected));
+    }
+    let mut running = state.simulator.lock().await;
+    if let Some(previous) = running.take() {
+        previous.stop().await;
+    }
+    let simulator = simulator::Simulator::start(options.unwrap_or_default(), state.mqtt_manager.clone());
+    let status = simulator.status();
+    *running = Some(simulator);
+    Ok(status)
+}
+
+#[tauri::command]
+async fn stop_simulator(state: State<'_, AppState>) -> Result<String, AppError> {
+    match state.simulator.lock().await.take() {
+        Some(simulator) => {
+            simulator.stop().await;
+            Ok("Simulator stopped".to_string())
+        }
+        None => Ok("Simulator was not running".to_string()),
+    }
+}
+
+// None while the simulator isn't running
+#[tauri::command]
+async fn get_simulator_status(state: State<'_, AppState>) -> Result<Option<simulator::SimulatorStatus>, AppError> {
+    Ok(state.simulator.lock().await.as_ref().map(simulator::Simulator::status))
+}
+
+// None until the BLE transport has been enabled once
+#[cfg(feature = "ble")]
+#[tauri::command]
+async fn get_ble_status() -> Result<Option<ble::BleStatus>, AppError> {
+    Ok(ble::status())
+}
+
+#[tauri::command]
+async fn test_emit_sensor_data(
+    app: tauri::AppHandle,
+) -> Result<String, AppError> {
+    let test_sensor_data = SensorData {
+        temperature: 25.5,
+        humidity: 60.0,
+        pressure: 1013.2,
+        timestamp: "2025-07-03T12:00:00".to_string(),
+        co2: Some(650.0),
+        tvoc: Some(120.0),
+        lux: Some(340.0),
+        schema_version: SENSOR_SCHEMA_VERSION,
+        device_id: None,
+        device_name: None,
+        raw: None,
+        received_at: Some(chrono::Utc::now()),
+        age_secs: Some(0),
+        stale: false,
+        anomalies: Vec::new(),
+        quality: vec![ReadingQuality::Ok],
+        comfort: Some(metrics::ComfortMetrics::from_reading(25.5, 60.0)),
+        pressure_tendency: Some(metrics::PressureTendency::from_change(-1.2)),
+    };
+    
+    info!("Testing sensor data event emission");
+    match app.emit_to("main", "sensor-data-updated", &test_sensor_data) {
+        Ok(_) => {
+            info!("Test sensor data event emitted successfully");
+            Ok("Test event emitted".to_string())
+        }
+        Err(e) => {
+            error!("Failed to emit test sensor data event: {}", e);
+            Err(AppError::from_error("Failed to emit test event", e))
+        }
+    }
+}
+
+// Named location wins, then explicit coordinates, then the active location from the config
+async fn resolve_coordinates(
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
+    state: &State<'_, AppState>,
+) -> Result<(f64, f64), AppError> {
+    let config_manager = state.config_manager.lock().await;
+    if let Some(name) = location {
+        return config_manager.find_location(&name)
+            .map(|l| (l.latitude, l.longitude))
+            .ok_or_else(|| AppError::NotFound { message: format!("Unknown location: {}", name) });
+    }
+    match (lat, lon) {
+        (Some(lat), Some(lon)) => Ok((lat, lon)),
+        _ => Ok(config_manager.active_coordinates()),
+    }
+}
+
+#[tauri::command]
+async fn set_active_location(
+    name: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let (previous, (lat, lon)) = {
+        let mut config_manager = state.config_manager.lock().await;
+        let previous = config_manager.active_coordinates();
+        if let Err(e) = config_manager.set_active_location(name.clone()).await {
+            error!("Failed to set active location: {}", e);
+            return Err(AppError::from_error("Failed to set active location", e));
+        }
+        (previous, config_manager.active_coordinates())
+    };
+    
+    if let Err(e) = switch_location(&state, previous, (lat, lon)).await {
+        error!("Failed to switch location: {}", e);
+        return Err(AppError::from_error("Active location saved, but switching to it failed", e));
+    }
+    
+    let label = name.unwrap_or_else(|| "default coordinates".to_string());
+    info!("Active location set to {}", label);
+    Ok(format!("Active location set to {}", label))
+}
+
+// Drops the old location's cache and moves automated publishing to the new one
+async fn switch_location(state: &AppState, from: (f64, f64), to: (f64, f64)) -> anyhow::Result<()> {
+    auto_location::switch(&state.weather_api, &state.mqtt_manager, from, to).await
+}
+
+// Where a deep link moves the active location
+enum LocationTarget {
+    Coordinates(f64, f64),
+    Preset(String),
+}
+
+// Applies a change to the active location, then follows it with the cache and publisher
+async fn update_location(state: &AppState, target: LocationTarget) -> anyhow::Result<()> {
+    let (previous, current) = {
+        let mut config_manager = state.config_manager.lock().await;
+        let previous = config_manager.active_coordinates();
+        match target {
+            LocationTarget::Coordinates(lat, lon) => config_manager.set_coordinates(lat, lon).await?,
+            LocationTarget::Preset(name) => config_manager.set_active_location(Some(name)).await?,
+        }
+        state.weather_api.update_settings(config_manager.weather_api_settings().clone()).await;
+        (previous, config_manager.active_coordinates())
+    };
+    switch_location(state, previous, current).await
+}
+
+// weatherstation:// links opened by other tools and shortcuts
+async fn handle_deep_link(app_handle: tauri::AppHandle, url: url::Url) {
+    let action = match deep_link::parse(&url) {
+        Ok(action) => action,
+        Err(e) => {
+            warn!("Ignoring deep link {}: {}", url, e);
+            return;
+        }
+    };
+    info!("Handling deep link: {:?}", action);
+    let state: State<AppState> = app_handle.state();
+    let result = match action {
+        DeepLinkAction::Alert { level, message } => {
+            let alert = AlertData {
+                message,
+                level,
+                timestamp: chrono::Utc::now(),
+                id: None,
+            };
+            state.mqtt_manager.send_alert(&alert, AlertSource::DeepLink).await.map(|_| ())
+        }
+        DeepLinkAction::Coordinates { lat, lon } => {
+            update_location(&state, LocationTarget::Coordinates(lat, lon)).await
+        }
+        DeepLinkAction::Location(name) => {
+            update_location(&state, LocationTarget::Preset(name)).await
+        }
+    };
+    if let Err(e) = result {
+        error!("Deep link {} failed: {}", url, e);
+    }
+}
+
+#[tauri::command]
+async fn add_location_preset(
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let mut config_manager = state.config_manager.lock().await;
+    match config_manager.add_location(NamedLocation { name: name.clone(), latitude, longitude }).await {
+        Ok(_) => {
+            state.weather_api.update_settings(config_manager.weather_api_settings().clone()).await;
+            info!("Added location preset {}", name);
+            Ok(format!("Added location {}", name))
+        }
+        Err(e) => {
+            error!("Failed to add location preset: {}", e);
+            Err(AppError::from_error("Failed to add location", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn remove_location_preset(
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let (previous, current) = {
+        let mut config_manager = state.config_manager.lock().await;
+        let previous = config_manager.active_coordinates();
+        if let Err(e) = config_manager.remove_location(&name).await {
+            error!("Failed to remove location preset: {}", e);
+            return Err(AppError::from_error("Failed to remove location", e));
+        }
+        state.weather_api.update_settings(config_manager.weather_api_settings().clone()).await;
+        (previous, config_manager.active_coordinates())
+    };
+    // Removing the active preset falls back to the default coordinates
+    if let Err(e) = switch_location(&state, previous, current).await {
+        error!("Failed to switch location: {}", e);
+    }
+    info!("Removed location preset {}", name);
+    Ok(format!("Removed location {}", name))
+}
+
+#[tauri::command]
+async fn start_automated_weather_publishing(
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let (lat, lon) = resolve_coordinates(lat, lon, location, &state).await?;
+    info!("Starting automated weather publishing for coordinates: {}, {}", lat, lon);
+    
+    match state.mqtt_manager.start_automated_weather_publishing(lat, lon).await {
+        Ok(_) => {
+            info!("Automated weather publishing started successfully");
+            Ok("Automated weather publishing started".to_string())
+        }
+        Err(e) => {
+            error!("Failed to start automated weather publishing: {}", e);
+            Err(AppError::from_error("Failed to start automated publishing", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn stop_automated_weather_publishing(
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    info!("Stopping automated weather publishing");
+    
+    match state.mqtt_manager.stop_automated_weather_publishing().await {
+        Ok(_) => {
+            info!("Automated weather publishing stopped successfully");
+            Ok("Automated weather publishing stopped".to_string())
+        }
+        Err(e) => {
+            error!("Failed to stop automated weather publishing: {}", e);
+            Err(AppError::from_error("Failed to stop automated publishing", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn is_auto_publishing(state: State<'_, AppState>) -> Result<bool, AppError> {
+    Ok(state.mqtt_manager.is_auto_publishing())
+}
+
+// Which desktop on the broker publishes weather, when several share it
+#[tauri::command]
+async fn get_coordination_status(state: State<'_, AppState>) -> Result<coordination::CoordinationStatus, AppError> {
+    Ok(state.mqtt_manager.coordination_status())
+}
+
+#[tauri::command]
+async fn fetch_weather_with_default_key(
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<WeatherData, AppError> {
+    let (lat, lon) = resolve_coordinates(lat, lon, location, &state).await?;
+    let interval = min_fetch_interval(&state).await;
+    state.weather_fetches.fetch(fetch_limiter::key("default", lat, lon), interval, || async {
+        info!("Fetching weather data with default API key for coordinates: {}, {}", lat, lon);
+        match state.weather_api.fetch_weather_with_default_key(lat, lon).await {
+            Ok(weather_data) => {
+                info!("Weather data fetched successfully with default key");
+                Ok(weather_data)
+            }
+            Err(e) => {
+                error!("Failed to fetch weather data with default key: {}", e);
+                Err(AppError::from_error("API fetch failed", e))
+            }
+        }
+    }).await
+}
+
+#[tauri::command]
+async fn search_locations(
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<LocationCandidate>, AppError> {
+    info!("Searching locations for: {}", query);
+    
+    match state.weather_api.search_locations(&query).await {
+        Ok(locations) => Ok(locations),
+        Err(e) => {
+            error!("Location search failed: {}", e);
+            Err(AppError::from_error("Location search failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn fetch_current_conditions(
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<CurrentWeather, AppError> {
+    let (lat, lon) = resolve_coordinates(lat, lon, location, &state).await?;
+    let interval = min_fetch_interval(&state).await;
+    state.current_fetches.fetch(fetch_limiter::key("current", lat, lon), interval, || async {
+        match state.weather_api.fetch_current_conditions(lat, lon).await {
+            Ok(current) => Ok(current),
+            Err(e) => {
+                error!("Failed to fetch current conditions: {}", e);
+                Err(AppError::from_error("Current conditions fetch failed", e))
+            }
+        }
+    }).await
+}
+
+#[tauri::command]
+async fn compare_locations(state: State<'_, AppState>) -> Result<Vec<LocationComparison>, AppError> {
+    let locations = state.config_manager.lock().await.weather_api_settings().locations.clone();
+    if locations.is_empty() {
+        return Err(AppError::NotFound { message: "No saved locations to compare, add some in the weather API settings".to_string() });
+    }
+    
+    info!("Comparing weather across {} locations", locations.len());
+    Ok(state.weather_api.compare_locations(locations).await)
+}
+
+#[tauri::command]
+async fn get_weather_alerts(
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<WeatherAlert>, AppError> {
+    let (lat, lon) = resolve_coordinates(lat, lon, location, &state).await?;
+    match state.weather_api.get_weather_alerts(lat, lon).await {
+        Ok(alerts) => Ok(alerts),
+        Err(e) => {
+            error!("Failed to read weather alerts: {}", e);
+            Err(AppError::from_error("Failed to read weather alerts", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_api_usage(state: State<'_, AppState>) -> Result<ApiUsage, AppError> {
+    Ok(state.weather_api.api_usage())
+}
+
+#[tauri::command]
+async fn refresh_weather_cache(
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let (lat, lon) = resolve_coordinates(lat, lon, location, &state).await?;
     info!("Manually refreshing weather cache for coordinates: {}, {}", lat, lon);
     
     match state.weather_api.ensure_daily_cache(lat, lon).await {
@@ -339,111 +1654,933 @@ async fn refresh_weather_cache(
         }
         Err(e) => {
             error!("Failed to refresh weather cache: {}", e);
-            Err(format!("Cache refresh failed: {}", e))
+            Err(AppError::from_error("Cache refresh failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_weather_cache_info(
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<WeatherCacheInfo, AppError> {
+    let (lat, lon) = resolve_coordinates(lat, lon, location, &state).await?;
+    Ok(state.weather_api.cache_info(lat, lon).await)
+}
+
+#[tauri::command]
+async fn clear_weather_cache(state: State<'_, AppState>) -> Result<String, AppError> {
+    match state.weather_api.clear_cache().await {
+        Ok(removed) => {
+            info!("Weather cache cleared");
+            Ok(format!("Cleared {} cached location(s)", removed))
+        }
+        Err(e) => {
+            error!("Failed to clear weather cache: {}", e);
+            Err(AppError::from_error("Failed to clear weather cache", e))
+        }
+    }
+}
+
+// Only runs when the user asks, since it sends the public IP to a third party
+#[tauri::command]
+async fn detect_location(state: State<'_, AppState>) -> Result<LocationCandidate, AppError> {
+    let location = match state.weather_api.detect_location().await {
+        Ok(location) => location,
+        Err(e) => {
+            error!("Failed to detect location: {}", e);
+            return Err(AppError::from_error("Location detection failed", e));
+        }
+    };
+    
+    let mut config_manager = state.config_manager.lock().await;
+    let mut settings = config_manager.weather_api_settings().clone();
+    settings.latitude = location.lat;
+    settings.longitude = location.lon;
+    settings.location_name = Some(match &location.region {
+        Some(region) => format!("{}, {}, {}", location.name, region, location.country),
+        None => format!("{}, {}", location.name, location.country),
+    });
+    if let Err(e) = config_manager.update_weather_api_settings(settings.clone()).await {
+        error!("Failed to save detected location: {}", e);
+        return Err(AppError::from_error("Failed to save detected location", e));
+    }
+    state.weather_api.update_settings(settings).await;
+    
+    info!("Detected location: {} ({}, {})", location.name, location.lat, location.lon);
+    Ok(location)
+}
+
+// Checks an icon map before it is saved; uses the saved one when none is given
+#[tauri::command]
+async fn validate_icon_map(
+    icon_map: Option<std::collections::BTreeMap<String, String>>,
+    state: State<'_, AppState>,
+) -> Result<IconMapValidation, AppError> {
+    let icon_map = match icon_map {
+        Some(icon_map) => icon_map,
+        None => state.config_manager.lock().await.mqtt_settings().icon_map.clone(),
+    };
+    let validation = icons::validate_icon_map(&icon_map);
+    if !validation.valid {
+        info!("Icon map has problems: {:?}", validation);
+    }
+    Ok(validation)
+}
+
+// A weather icon by OpenWeatherMap code, from the local cache once it has been downloaded
+#[tauri::command]
+async fn get_icon(code: String, state: State<'_, AppState>) -> Result<icon_cache::IconAsset, AppError> {
+    let settings = state.config_manager.lock().await.weather_api_settings().icon_pack.clone();
+    match icon_cache::get(&settings, &code).await {
+        Ok(icon) => Ok(icon),
+        Err(e) => {
+            error!("Failed to get icon {}: {}", code, e);
+            Err(AppError::from_error("Failed to get icon", e))
         }
     }
 }
 
+// Downloads every icon not cached yet; returns how many were downloaded
+#[tauri::command]
+async fn prefetch_icons(state: State<'_, AppState>) -> Result<usize, AppError> {
+    let settings = state.config_manager.lock().await.weather_api_settings().icon_pack.clone();
+    match icon_cache::prefetch(&settings).await {
+        Ok(downloaded) => Ok(downloaded),
+        Err(e) => {
+            error!("Failed to prefetch icons: {}", e);
+            Err(AppError::from_error("Failed to prefetch icons", e))
+        }
+    }
+}
+
+// Renders a payload template, which need not be saved yet, with the latest weather
+// data or a sample alert; returns the payload as pretty-printed JSON
+#[tauri::command]
+async fn preview_payload_template(
+    kind: payload_templates::PayloadKind,
+    template: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let rendered = match kind {
+        payload_templates::PayloadKind::Weather => {
+            let Some(mut weather) = state.mqtt_manager.get_latest_weather_data() else {
+                return Err(AppError::NotFound { message: "No weather data to render yet".to_string() });
+            };
+            let icon_map = state.config_manager.lock().await.mqtt_settings().icon_map.clone();
+            icons::apply_icon_map(&mut weather, &icon_map);
+            payload_templates::render(&template, &weather)
+        }
+        payload_templates::PayloadKind::Alert => {
+            let alert = AlertData {
+                message: "Storm warning until 18:00".to_string(),
+                level: AlertLevel::Warning,
+                timestamp: chrono::Utc::now(),
+                id: None,
+            }.with_id();
+            payload_templates::render(&template, &alert)
+        }
+    };
+    match rendered.and_then(|value| Ok(serde_json::to_string_pretty(&value)?)) {
+        Ok(payload) => Ok(payload),
+        Err(e) => {
+            info!("Payload template preview failed: {}", e);
+            Err(AppError::from_error("Failed to render payload template", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_sensor_history(
+    device_id: Option<String>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<SensorHistoryPage, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    history.query(device_id.as_deref(), from, to, limit.unwrap_or(DEFAULT_PAGE_SIZE), offset.unwrap_or(0))
+        .map_err(|e| {
+            error!("Failed to read sensor history: {}", e);
+            AppError::from_error("Failed to read sensor history", e)
+        })
+}
+
+// Extremes, averages and 24h changes over the stored history
+#[tauri::command]
+async fn get_statistics(
+    device_id: Option<String>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    state: State<'_, AppState>,
+) -> Result<SensorStatistics, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    let result = tokio::task::spawn_blocking(move || {
+        statistics::compute(&history, device_id.as_deref(), from, to)
+    }).await;
+
+    match result {
+        Ok(Ok(statistics)) => Ok(statistics),
+        Ok(Err(e)) => {
+            error!("Failed to compute statistics: {}", e);
+            Err(AppError::from_error("Failed to compute statistics", e))
+        }
+        Err(e) => {
+            error!("Statistics task failed: {}", e);
+            Err(AppError::from_error("Failed to compute statistics", e))
+        }
+    }
+}
+
+// Sensor minus API readings over the window (default: the last 7 days), to spot calibration drift
+#[tauri::command]
+async fn compare_sensor_to_api(
+    device_id: Option<String>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    state: State<'_, AppState>,
+) -> Result<drift::DriftComparison, AppError> {
+    let altitude_m = state.config_manager.lock().await.get_config().mqtt.local_forecast.station_altitude_m;
+    let history = state.mqtt_manager.sensor_history();
+    let to = to.unwrap_or_else(chrono::Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::days(7));
+    let result = tokio::task::spawn_blocking(move || {
+        drift::compare(&history, device_id.as_deref(), from, to, altitude_m)
+    }).await;
+
+    match result {
+        Ok(Ok(comparison)) => Ok(comparison),
+        Ok(Err(e)) => {
+            error!("Failed to compare sensor to API: {}", e);
+            Err(AppError::from_error("Failed to compare sensor to API", e))
+        }
+        Err(e) => {
+            error!("Drift comparison task failed: {}", e);
+            Err(AppError::from_error("Failed to compare sensor to API", e))
+        }
+    }
+}
+
+// Downsampled hourly min/max/avg rows, for long-term charts
+#[tauri::command]
+async fn get_hourly_sensor_history(
+    device_id: Option<String>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<HourlyHistoryPage, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    history.query_hourly(device_id.as_deref(), from, to, limit.unwrap_or(DEFAULT_PAGE_SIZE), offset.unwrap_or(0))
+        .map_err(|e| {
+            error!("Failed to read hourly sensor history: {}", e);
+            AppError::from_error("Failed to read hourly sensor history", e)
+        })
+}
+
+// Weather reports as they were published, for comparing past forecasts with what happened
+#[tauri::command]
+async fn get_weather_snapshots(
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<WeatherSnapshotPage, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    history.weather_snapshots(from, to, limit.unwrap_or(DEFAULT_PAGE_SIZE), offset.unwrap_or(0))
+        .map_err(|e| {
+            error!("Failed to read weather snapshots: {}", e);
+            AppError::from_error("Failed to read weather snapshots", e)
+        })
+}
+
+// Imports readings from another logger's CSV into the sensor history
+#[tauri::command]
+async fn import_sensor_data_csv(
+    path: String,
+    column_mapping: CsvColumnMapping,
+    state: State<'_, AppState>,
+) -> Result<ImportSummary, AppError> {
+    info!("Importing sensor data from {}", path);
+    let history = state.mqtt_manager.sensor_history();
+
+    let result = tokio::task::spawn_blocking(move || {
+        csv_import::import_sensor_csv(&history, std::path::Path::new(&path), &column_mapping)
+    }).await;
+
+    match result {
+        Ok(Ok(summary)) => Ok(summary),
+        Ok(Err(e)) => {
+            error!("CSV import failed: {}", e);
+            Err(AppError::from_error("CSV import failed", e))
+        }
+        Err(e) => {
+            error!("CSV import task failed: {}", e);
+            Err(AppError::from_error("CSV import failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_alert_history(
+    filter: Option<AlertHistoryFilter>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<AlertHistoryPage, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    history.query_alerts(&filter.unwrap_or_default(), limit.unwrap_or(DEFAULT_PAGE_SIZE), offset.unwrap_or(0))
+        .map_err(|e| {
+            error!("Failed to read alert history: {}", e);
+            AppError::from_error("Failed to read alert history", e)
+        })
+}
+
+#[tauri::command]
+async fn export_alert_history_csv(
+    path: String,
+    filter: Option<AlertHistoryFilter>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ExportSummary, AppError> {
+    info!("Exporting alert history to {}", path);
+    let history = state.mqtt_manager.sensor_history();
+    let filter = filter.unwrap_or_default();
+
+    let result = tokio::task::spawn_blocking(move || {
+        export::export_alerts_csv(&history, std::path::Path::new(&path), &filter, |progress| {
+            let _ = app.emit("export-progress", progress);
+        })
+    }).await;
+
+    match result {
+        Ok(Ok(summary)) => Ok(summary),
+        Ok(Err(e)) => {
+            error!("Alert export failed: {}", e);
+            Err(AppError::from_error("Alert export failed", e))
+        }
+        Err(e) => {
+            error!("Alert export task failed: {}", e);
+            Err(AppError::from_error("Alert export failed", e))
+        }
+    }
+}
+
+// Emits "export-progress" after each batch so the UI can show a progress bar
+#[tauri::command]
+async fn export_sensor_data_csv(
+    path: String,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    device_id: Option<String>,
+    include_weather: Option<bool>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ExportSummary, AppError> {
+    info!("Exporting sensor data to {}", path);
+    let history = state.mqtt_manager.sensor_history();
+    let range = ExportRange { device_id, from, to };
+    
+    let result = tokio::task::spawn_blocking(move || {
+        export::export_csv(&history, std::path::Path::new(&path), &range, include_weather.unwrap_or(false), |progress| {
+            let _ = app.emit("export-progress", progress);
+        })
+    }).await;
+    
+    match result {
+        Ok(Ok(summary)) => Ok(summary),
+        Ok(Err(e)) => {
+            error!("CSV export failed: {}", e);
+            Err(AppError::from_error("CSV export failed", e))
+        }
+        Err(e) => {
+            error!("CSV export task failed: {}", e);
+            Err(AppError::from_error("CSV export failed", e))
+        }
+    }
+}
+
+// Sensor history as CSV, JSONL or Parquet, with dew point and absolute humidity columns
+#[tauri::command]
+async fn export_sensor_data(
+    path: String,
+    format: ExportFormat,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    device_id: Option<String>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ExportSummary, AppError> {
+    info!("Exporting sensor data to {} as {:?}", path, format);
+    let history = state.mqtt_manager.sensor_history();
+    let range = ExportRange { device_id, from, to };
+
+    let result = tokio::task::spawn_blocking(move || {
+        export::export_sensor_history(&history, std::path::Path::new(&path), format, &range, |progress| {
+            let _ = app.emit("export-progress", progress);
+        })
+    }).await;
+
+    match result {
+        Ok(Ok(summary)) => Ok(summary),
+        Ok(Err(e)) => {
+            error!("Export failed: {}", e);
+            Err(AppError::from_error("Export failed", e))
+        }
+        Err(e) => {
+            error!("Export task failed: {}", e);
+            Err(AppError::from_error("Export failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_storage_usage(state: State<'_, AppState>) -> Result<StorageUsage, AppError> {
+    let config_path = state.config_manager.lock().await.config_path().clone();
+    storage::storage_usage(Some(&config_path)).map_err(|e| {
+        error!("Failed to read storage usage: {}", e);
+        AppError::from_error("Failed to read storage usage", e)
+    })
+}
+
+#[tauri::command]
+async fn prune_storage(state: State<'_, AppState>) -> Result<PruneReport, AppError> {
+    let settings = state.config_manager.lock().await.get_config().app.storage.clone();
+    storage::prune(&settings).map_err(|e| {
+        error!("Storage cleanup failed: {}", e);
+        AppError::from_error("Storage cleanup failed", e)
+    })
+}
+
+#[tauri::command]
+async fn backup_database(path: String, state: State<'_, AppState>) -> Result<BackupInfo, AppError> {
+    info!("Backing up sensor history to {}", path);
+    let history = state.mqtt_manager.sensor_history();
+
+    match tokio::task::spawn_blocking(move || history.backup(std::path::Path::new(&path))).await {
+        Ok(Ok(info)) => Ok(info),
+        Ok(Err(e)) => {
+            error!("Database backup failed: {}", e);
+            Err(AppError::from_error("Database backup failed", e))
+        }
+        Err(e) => {
+            error!("Database backup task failed: {}", e);
+            Err(AppError::from_error("Database backup failed", e))
+        }
+    }
+}
+
+// Emits "database-restored" so open views can reload their history
+#[tauri::command]
+async fn restore_database(
+    path: String,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<BackupInfo, AppError> {
+    info!("Restoring sensor history from {}", path);
+    let history = state.mqtt_manager.sensor_history();
+
+    match tokio::task::spawn_blocking(move || history.restore(std::path::Path::new(&path))).await {
+        Ok(Ok(info)) => {
+            let _ = app.emit("database-restored", info.clone());
+            Ok(info)
+        }
+        Ok(Err(e)) => {
+            error!("Database restore failed: {}", e);
+            Err(AppError::from_error("Database restore failed", e))
+        }
+        Err(e) => {
+            error!("Database restore task failed: {}", e);
+            Err(AppError::from_error("Database restore failed", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn list_crash_reports() -> Result<Vec<crash::CrashReport>, AppError> {
+    crash::reports().map_err(|e| {
+        error!("Failed to list crash reports: {}", e);
+        AppError::from_error("Failed to list crash reports", e)
+    })
+}
+
+#[tauri::command]
+async fn export_crash_report(name: String, path: String) -> Result<String, AppError> {
+    match crash::export(&name, std::path::Path::new(&path)) {
+        Ok(_) => Ok(format!("Crash report exported to {}", path)),
+        Err(e) => {
+            error!("Crash report export failed: {}", e);
+            Err(AppError::from_error("Crash report export failed", e))
+        }
+    }
+}
+
+// Background tasks and how often they were restarted, for the diagnostics panel
+#[tauri::command]
+async fn get_task_health() -> Result<Vec<supervisor::TaskHealth>, AppError> {
+    Ok(supervisor::health())
+}
+
+// For the diagnostics panel; newest entries last
+#[tauri::command]
+async fn get_recent_logs(level: Option<String>, limit: Option<usize>) -> Result<Vec<logging::LogEntry>, AppError> {
+    match tokio::task::spawn_blocking(move || logging::recent(level.as_deref(), limit)).await {
+        Ok(Ok(entries)) => Ok(entries),
+        Ok(Err(e)) => {
+            error!("Failed to read logs: {}", e);
+            Err(AppError::from_error("Failed to read logs", e))
+        }
+        Err(e) => {
+            error!("Log read task failed: {}", e);
+            Err(AppError::from_error("Failed to read logs", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_retention_status(state: State<'_, AppState>) -> Result<RetentionStatus, AppError> {
+    let history = state.mqtt_manager.sensor_history();
+    history.retention_status().map_err(|e| {
+        error!("Failed to read retention status: {}", e);
+        AppError::from_error("Failed to read retention status", e)
+    })
+}
+
+// Jobs that watch the weather, sensors and schedule; both the app and the service run them
+fn spawn_monitors(state: &AppState) {
+    supervisor::supervise("sun automation", {
+        let state = state.clone();
+        move || sun_automation::spawn(state.config_manager.clone(), state.mqtt_manager.clone())
+    });
+    supervisor::supervise("automatic location", {
+        let state = state.clone();
+        move || auto_location::spawn(
+            state.config_manager.clone(),
+            state.weather_api.clone(),
+            state.mqtt_manager.clone(),
+        )
+    });
+    supervisor::supervise("scheduler", {
+        let state = state.clone();
+        move || scheduler::spawn(task_context(&state))
+    });
+    supervisor::supervise("severe weather monitor", {
+        let state = state.clone();
+        move || SevereWeatherMonitor::spawn(
+            state.config_manager.clone(),
+            state.weather_api.clone(),
+            state.mqtt_manager.clone(),
+        )
+    });
+    supervisor::supervise("forecast warnings", {
+        let state = state.clone();
+        move || forecast_warnings::spawn(
+            state.config_manager.clone(),
+            state.weather_api.clone(),
+            state.mqtt_manager.clone(),
+        )
+    });
+    supervisor::supervise("rain monitor", {
+        let state = state.clone();
+        move || RainMonitor::spawn(
+            state.config_manager.clone(),
+            state.weather_api.clone(),
+            state.mqtt_manager.clone(),
+        )
+    });
+}
+
 #[tokio::main]
 async fn main() {
     // Initialize tracing
-    tracing_subscriber::fmt::init();
+    let _log_guard = logging::init();
+    crash::install_panic_hook();
     
     info!("Starting Weather Station Desktop Application");
+    let service_command = service::command();
+    // Install or remove the background service, then exit without starting the app
+    let install_result = match service_command {
+        Some(service::ServiceCommand::Install) => Some(service::install().map(|_| "installed")),
+        Some(service::ServiceCommand::Uninstall) => Some(service::uninstall().map(|_| "removed")),
+        _ => None,
+    };
+    if let Some(result) = install_result {
+        service::attach_console();
+        match result {
+            Ok(done) => println!("Service {} {}", service::SERVICE_NAME, done),
+            Err(e) => {
+                error!("Service setup failed: {}", e);
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(dir) = storage::portable_dir() {
+        info!("Running in portable mode, storing data in {:?}", dir);
+    }
     
     // Ensure config file exists
-    if let Err(e) = config::ensure_config_file_exists() {
+    if let Err(e) = config::ensure_config_file_exists().await {
         error!("Failed to ensure config file exists: {}", e);
     }
     
     // Initialize configuration manager
-    let config_manager = match ConfigManager::new() {
+    let config_manager = match ConfigManager::new().await {
         Ok(manager) => Arc::new(Mutex::new(manager)),
         Err(e) => {
             error!("Failed to initialize config manager: {}", e);
-            Arc::new(Mutex::new(ConfigManager::new().unwrap_or_else(|_| {
+            Arc::new(Mutex::new(ConfigManager::new().await.unwrap_or_else(|_| {
                 panic!("Could not create config manager")
             })))
         }
     };
     
+    // Drop stale caches and oversized debug dumps before anything reads them
+    let storage_settings = config_manager.lock().await.get_config().app.storage.clone();
+    if let Err(e) = storage::prune(&storage_settings) {
+        error!("Storage cleanup failed: {}", e);
+    }
+    
     // Initialize application state
-    let mqtt_manager = Arc::new(Mutex::new(MqttManager::new()));
-    let weather_api = Arc::new(WeatherApiClient::new());
+    // Shared with the MQTT manager so provider changes apply to automated publishing too
+    let weather_api_settings = config_manager.lock().await.weather_api_settings().clone();
+    let weather_api = Arc::new(WeatherApiClient::new(weather_api_settings));
+    let mqtt_manager = MqttManager::new(Arc::clone(&weather_api)).spawn();
+    let sensor_history = mqtt_manager.sensor_history();
+    weather_api.set_sensor_history(Arc::clone(&sensor_history));
+    supervisor::supervise("retention", {
+        let (config_manager, sensor_history) = (Arc::clone(&config_manager), Arc::clone(&sensor_history));
+        move || retention::spawn(Arc::clone(&config_manager), Arc::clone(&sensor_history))
+    });
+    let alert_rules = config_manager.lock().await.alert_rules().to_vec();
+    mqtt_manager.set_alert_rules(alert_rules).await;
+    let app_settings = config_manager.lock().await.get_config().app.clone();
+    let config_writer = config_manager.lock().await.writer();
+    let alert_channels = mqtt_manager.alert_channels();
+    alert_channels.apply_settings(&app_settings);
+    Arc::clone(&alert_channels).spawn_release();
+
+    let grafana_settings = config_manager.lock().await.get_config().app.grafana.clone();
+    let grafana_server = if grafana_settings.enabled {
+        match grafana::GrafanaServer::start(grafana_settings, Arc::clone(&sensor_history)).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                error!("Failed to start Grafana datasource: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let rest_api_server = if app_settings.rest_api.enabled {
+        match rest_api::RestApiServer::start(app_settings.rest_api.clone(), mqtt_manager.clone()).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                error!("Failed to start REST API: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let grpc_server = if app_settings.grpc.enabled {
+        match grpc::GrpcServer::start(app_settings.grpc.clone(), mqtt_manager.clone()).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                error!("Failed to start gRPC API: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    #[cfg(feature = "ble")]
+    let ble_transport = if app_settings.ble.enabled {
+        match ble::BleTransport::start(app_settings.ble.clone(), mqtt_manager.clone()) {
+            Ok(transport) => Some(transport),
+            Err(e) => {
+                error!("Failed to start BLE transport: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
     
     let app_state = AppState {
-        mqtt_manager: Arc::clone(&mqtt_manager),
+        mqtt_manager: mqtt_manager.clone(),
         weather_api,
+        weather_fetches: Arc::new(FetchLimiter::default()),
+        current_fetches: Arc::new(FetchLimiter::default()),
         config_manager: Arc::clone(&config_manager),
-        app_handle: Arc::new(Mutex::new(None)),
+        grafana: Arc::new(Mutex::new(grafana_server)),
+        rest_api: Arc::new(Mutex::new(rest_api_server)),
+        grpc: Arc::new(Mutex::new(grpc_server)),
+        simulator: Arc::new(Mutex::new(None)),
+        #[cfg(feature = "ble")]
+        ble: Arc::new(Mutex::new(ble_transport)),
     };
     
     
+    let summary_mqtt = mqtt_manager.clone();
+    let summary_config = Arc::clone(&config_manager);
+    crash::set_state_summary(move || {
+        let mut summary = String::new();
+        summary.push_str(&format!(
+            "MQTT connected: {}, auto publishing: {}\n",
+            summary_mqtt.is_connected(),
+            summary_mqtt.is_auto_publishing()
+        ));
+        match summary_config.try_lock() {
+            Ok(config_manager) => {
+                let config = config_manager.get_config();
+                summary.push_str(&format!(
+                    "Broker: {}:{}, provider: {:?}, units: {:?}, portable: {}\n",
+                    config.mqtt.broker_host,
+                    config.mqtt.broker_port,
+                    config.weather_api.provider,
+                    config.weather_api.units,
+                    storage::portable_dir().is_some()
+                ));
+            }
+            Err(_) => summary.push_str("Config busy\n"),
+        }
+        summary
+    });
+    tray::set_run_in_background(app_settings.run_in_background);
+    events::set_sensor_event_settings(&app_settings.sensor_events);
+    logging::set_otlp_export(&app_settings.tracing);
+    scripting::init(mqtt_manager.clone(), &app_settings.scripting);
+    supervisor::supervise("scripts", {
+        let mqtt_manager = mqtt_manager.clone();
+        move || scripting::spawn(mqtt_manager.clone())
+    });
+
+    // No window or tray when running under systemd or the Windows service manager
+    if service_command == Some(service::ServiceCommand::Run) {
+        mqtt_manager.restore_last_known().await;
+        spawn_monitors(&app_state);
+        let headless = service::Headless {
+            mqtt_manager: mqtt_manager.clone(),
+            config_manager: Arc::clone(&config_manager),
+        };
+        if let Err(e) = service::run(headless).await {
+            error!("Service stopped with an error: {}", e);
+        }
+        return;
+    }
+    
     tauri::Builder::default()
+        // Must come first: a second launch just brings back the running instance's window
+        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| tray::show_main_window(app)))
+        .plugin(tauri_plugin_notification::init())
+        .plugin(autostart::plugin())
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
             connect_mqtt,
             disconnect_mqtt,
             get_mqtt_status,
+            run_health_check,
+            get_connection_status,
+            get_startup_progress,
+            get_script_status,
+            get_data_sinks,
+            get_pws_upload_status,
+            get_opensensemap_status,
+            reload_scripts,
             publish_weather_data,
+            publish_retained_snapshot,
             get_latest_weather_data,
             get_sensor_data,
+            get_latest_alert,
+            get_recent_sensor_data,
+            get_local_forecast,
+            convert_value,
+            get_daily_summary,
+            get_chart_data,
+            get_sun_times,
+            compare_sensor_to_api,
+            list_alert_rules,
+            create_alert_rule,
+            update_alert_rule,
+            delete_alert_rule,
+            set_alert_rule_enabled,
+            list_scheduled_tasks,
+            add_scheduled_task,
+            remove_scheduled_task,
+            run_scheduled_task,
+            test_webhook,
+            send_test_email,
+            send_test_telegram,
+            send_test_discord,
+            send_test_trigger,
+            send_test_notification,
             fetch_weather_api,
             fetch_weather_with_default_key,
             refresh_weather_cache,
+            get_weather_cache_info,
+            clear_weather_cache,
+            validate_icon_map,
+            get_icon,
+            prefetch_icons,
+            preview_payload_template,
+            detect_location,
+            fetch_current_conditions,
+            compare_locations,
+            get_sensor_history,
+            get_hourly_sensor_history,
+            get_statistics,
+            get_weather_snapshots,
+            get_alert_history,
+            export_alert_history_csv,
+            export_sensor_data_csv,
+            export_sensor_data,
+            import_sensor_data_csv,
+            get_storage_usage,
+            prune_storage,
+            get_retention_status,
+            get_recent_logs,
+            get_task_health,
+            list_crash_reports,
+            export_crash_report,
+            backup_database,
+            restore_database,
+            set_active_location,
+            add_location_preset,
+            remove_location_preset,
+            get_weather_alerts,
+            search_locations,
+            get_api_usage,
             send_alert,
+            get_delivery_status,
+            get_devices,
+            push_device_config,
+            reboot_device,
+            factory_reset_device,
+            sync_device_time,
             get_config,
             save_config,
+            validate_config,
+            export_config,
+            preview_config_import,
+            import_config,
+            reset_config,
+            update_config_field,
+            list_config_backups,
+            restore_config_backup,
             save_mqtt_settings,
+            regenerate_client_id,
             save_weather_api_settings,
             save_app_settings,
+            save_device_settings,
+            mute_device_alerts,
             test_emit_sensor_data,
+            start_simulator,
+            stop_simulator,
+            get_simulator_status,
+            #[cfg(feature = "ble")]
+            get_ble_status,
             start_automated_weather_publishing,
             stop_automated_weather_publishing,
-            is_auto_publishing
+            is_auto_publishing,
+            get_coordination_status
         ])
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_opener::init())
+        .on_window_event(tray::handle_window_event)
         .setup(move |app| {
             let app_handle = app.handle().clone();
+            supervisor::supervise("event forwarder", {
+                let app_handle = app_handle.clone();
+                move || events::spawn_forwarder(app_handle.clone())
+            });
+            // Installed apps register the scheme at install time; Linux and dev builds do it here
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                warn!("Failed to register the {}:// scheme: {}", deep_link::SCHEME, e);
+            }
+            let deep_link_handle = app_handle.clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    tokio::spawn(handle_deep_link(deep_link_handle.clone(), url));
+                }
+            });
+            // A link that started the app
+            if let Ok(Some(urls)) = app.deep_link().get_current() {
+                for url in urls {
+                    tokio::spawn(handle_deep_link(app_handle.clone(), url));
+                }
+            }
+            if let Err(e) = tray::setup(&app_handle) {
+                warn!("Failed to create the tray icon: {}", e);
+            }
             let state: State<AppState> = app.state();
-            let app_handle_arc = state.app_handle.clone();
-            let config_manager_clone = state.config_manager.clone();
-            let mqtt_manager_clone = state.mqtt_manager.clone();
+            spawn_monitors(state.inner());
+            crash::offer_new_report(&app_handle);
+
+            let status_handle = app_handle.clone();
+            supervisor::supervise("connection status", move || {
+                let status_handle = status_handle.clone();
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(std::time::Duration::from_secs(CONNECTION_STATUS_INTERVAL_SECS));
+                    loop {
+                        interval.tick().await;
+                        let state: State<AppState> = status_handle.state();
+                        let status = connection_status(&state).await;
+                        events::publish(AppEvent::ConnectionStatus(status));
+                    }
+                })
+            });
             
-            // Store app handle in the app state and handle auto-connect
+            let watch_handle = app_handle.clone();
             tokio::spawn(async move {
-                // Store app handle first
-                {
-                    let mut handle_guard = app_handle_arc.lock().await;
-                    *handle_guard = Some(app_handle.clone());
-                }
-                
-                // Small delay to ensure everything is initialized
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-                
-                // Auto-connect to MQTT if enabled
-                let config_guard = config_manager_clone.lock().await;
-                if config_guard.should_auto_connect_mqtt() {
-                    let mqtt_settings = config_guard.mqtt_settings().clone();
-                    info!("Auto-connecting to MQTT broker: {}:{}", mqtt_settings.broker_host, mqtt_settings.broker_port);
-                    
-                    drop(config_guard); // Release lock before MQTT operation
-                    
-                    // Set app handle in MQTT manager first
-                    {
-                        let mut mqtt_manager = mqtt_manager_clone.lock().await;
-                        mqtt_manager.set_app_handle(app_handle);
+                let state: State<AppState> = watch_handle.state();
+                let config_path = state.config_manager.lock().await.config_path().clone();
+                let (_watcher, mut changes) = match config_watcher::watch(&config_path) {
+                    Ok(watch) => watch,
+                    Err(e) => {
+                        warn!("Config hot reload disabled: {}", e);
+                        return;
                     }
-                    
-                    let mut mqtt_guard = mqtt_manager_clone.lock().await;
-                    match mqtt_guard.connect(&mqtt_settings.broker_host, mqtt_settings.broker_port).await {
-                        Ok(_) => info!("Auto-connected to MQTT successfully"),
-                        Err(e) => error!("Auto-connect to MQTT failed: {}", e),
+                };
+                while changes.next().await.is_some() {
+                    reload_config(&watch_handle).await;
+                }
+            });
+
+            let icon_config = state.config_manager.clone();
+            tokio::spawn(async move {
+                let settings = icon_config.lock().await.weather_api_settings().icon_pack.clone();
+                if settings.prefetch {
+                    // Retried on the next launch or first use, e.g. when starting offline
+                    if let Err(e) = icon_cache::prefetch(&settings).await {
+                        warn!("Icon prefetch incomplete: {}", e);
                     }
                 }
             });
+
+            tokio::spawn(startup::run(
+                app_handle.clone(),
+                state.config_manager.clone(),
+                state.mqtt_manager.clone(),
+                state.weather_api.clone(),
+            ));
             
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(move |_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                config_writer.flush_blocking();
+                logging::shutdown_otlp_export();
+            }
+        });
 }
\ No newline at end of file
@@ -0,0 +1,97 @@
+use crate::conversions::{convert_same, Unit};
+use serde::{Deserialize, Serialize};
+
+// Values the ENV unit can't measure directly, derived from temperature and humidity
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ComfortMetrics {
+    pub dew_point: f64,
+    pub heat_index: f64,
+    pub humidex: f64,
+    // Grams of water vapour per cubic metre of air
+    pub absolute_humidity: f64,
+}
+
+impl ComfortMetrics {
+    pub fn from_reading(temp_c: f64, humidity: f64) -> Self {
+        let dew_point = dew_point(temp_c, humidity);
+        Self {
+            dew_point,
+            heat_index: heat_index(temp_c, humidity),
+            humidex: humidex(temp_c, dew_point),
+            absolute_humidity: absolute_humidity(temp_c, humidity),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PressureTrend {
+    RisingRapidly,
+    Rising,
+    Steady,
+    Falling,
+    FallingRapidly,
+}
+
+// Change over the last three hours, as reported by weather stations
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PressureTendency {
+    pub change_3h: f64,
+    pub trend: PressureTrend,
+}
+
+impl PressureTendency {
+    // Thresholds follow the Met Office wording: under 1 hPa is steady, 3.6 hPa or more is rapid
+    pub fn from_change(change_3h: f64) -> Self {
+        let trend = match change_3h {
+            c if c >= 3.6 => PressureTrend::RisingRapidly,
+            c if c >= 1.0 => PressureTrend::Rising,
+            c if c <= -3.6 => PressureTrend::FallingRapidly,
+            c if c <= -1.0 => PressureTrend::Falling,
+            _ => PressureTrend::Steady,
+        };
+        Self { change_3h, trend }
+    }
+}
+
+// Magnus formula, good to about ±0.4°C between -45 and 60°C
+pub fn dew_point(temp_c: f64, humidity: f64) -> f64 {
+    const A: f64 = 17.62;
+    const B: f64 = 243.12;
+    let gamma = (humidity.max(1.0) / 100.0).ln() + A * temp_c / (B + temp_c);
+    B * gamma / (A - gamma)
+}
+
+pub fn absolute_humidity(temp_c: f64, humidity: f64) -> f64 {
+    let saturation_hpa = 6.112 * (17.67 * temp_c / (temp_c + 243.5)).exp();
+    saturation_hpa * humidity * 2.1674 / (273.15 + temp_c)
+}
+
+// NWS heat index: Steadman's simple formula, switching to the Rothfusz regression
+// (with its low/high humidity adjustments) above 80°F
+pub fn heat_index(temp_c: f64, humidity: f64) -> f64 {
+    let t = convert_same(temp_c, Unit::Celsius, Unit::Fahrenheit);
+    let rh = humidity.clamp(0.0, 100.0);
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let index_f = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.04901523 * t + 10.14333127 * rh
+            - 0.22475541 * t * rh - 0.00683783 * t * t - 0.05481717 * rh * rh
+            + 0.00122874 * t * t * rh + 0.00085282 * t * rh * rh - 0.00000199 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= ((13.0 - rh) / 4.0) * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += ((rh - 85.0) / 10.0) * ((87.0 - t) / 5.0);
+        }
+        hi
+    };
+    convert_same(index_f, Unit::Fahrenheit, Unit::Celsius)
+}
+
+// Environment Canada humidex from the dew point
+pub fn humidex(temp_c: f64, dew_point_c: f64) -> f64 {
+    let vapour_pressure = 6.11 * (5417.7530 * (1.0 / 273.16 - 1.0 / (273.15 + dew_point_c))).exp();
+    temp_c + 0.5555 * (vapour_pressure - 10.0)
+}
@@ -1,76 +1,285 @@
 use crate::types::*;
 use crate::weather_api::WeatherApiClient;
+use crate::config::{MqttSettings, DeviceSettings, ButtonAction, AlertRule, AlertOutputSettings, AlertQos, PayloadTemplateSettings, CoordinationSettings, InstanceRole};
+use crate::alert_rules::{RuleEvaluator, RuleInputs};
+use crate::notifications::{self, AlertChannels};
+use crate::bridge::UplinkBridge;
+use crate::sinks;
+use crate::proxy::ProxyTunnel;
+use crate::geo;
+use crate::devices::{DeviceRegistry, DeviceInfo, DEVICE_STATUS_TOPIC, DEVICE_TELEMETRY_TOPIC, DEVICE_ACK_TOPIC, DEVICE_BUTTON_TOPIC, device_id_from_topic, device_topic};
+use crate::delivery::{DeliveryTracker, DeliveryRecord, DeliveryEvent, DeliveryStatus};
+use crate::icons::apply_icon_map;
+use crate::payload_templates;
+use crate::history::{AlertHistoryFilter, SensorHistory};
+use crate::anomaly::AnomalyDetector;
+use crate::smoothing::SensorSmoother;
+use crate::validation;
+use crate::coordination::{Coordinator, CoordinationStatus, PublisherClaim};
+use crate::metrics::{ComfortMetrics, PressureTendency, PressureTrend};
+use crate::forecasting::{self, LocalForecast};
+use crate::app_error::{self, AppErrorCode};
+use crate::events::{self, AppEvent};
+use crate::logging;
+use crate::supervisor::StoppableTask;
+use crate::mqtt_health::{ConnectionHealth, MqttStatus, EVENT_LOOP_TASK};
 use anyhow::{Result, anyhow};
-use rumqttc::{AsyncClient, MqttOptions, Event, Packet, QoS};
+use rumqttc::{AsyncClient, ClientError, MqttOptions, Event, Packet, QoS, ConnectionError, Outgoing};
+use serde::Serialize;
 use serde_json;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::{Mutex, RwLock, mpsc, oneshot, watch};
 use tokio::time::{timeout, Duration, interval};
-use tracing::{info, error, warn, debug};
+use tracing::{info, error, warn, debug, instrument, Instrument};
+use tracing::field::Empty;
 // Removed unused imports: Local and ChronoDuration
-use tauri::{AppHandle, Emitter};
+
+// Handled by one instance when a shared subscription group is set
+const DATA_TOPICS: [&str; 4] = [
+    "weather/data",
+    "weather/sensor_data",
+    "weather/alert_trigger",
+    DEVICE_BUTTON_TOPIC,
+];
+// Every instance tracks device state and waits on its own acks, so these are never shared
+const DEVICE_STATE_TOPICS: [&str; 3] = [
+    DEVICE_STATUS_TOPIC,
+    DEVICE_TELEMETRY_TOPIC,
+    DEVICE_ACK_TOPIC,
+];
+
+// Requests waiting for a device acknowledgement, keyed by request id
+type PendingAcks = Arc<Mutex<HashMap<String, oneshot::Sender<DeviceAck>>>>;
+type SharedDeviceSettings = Arc<RwLock<HashMap<String, DeviceSettings>>>;
+type SharedAlertRules = Arc<RwLock<Vec<AlertRule>>>;
+// Coordinates used by the automated publisher, updated when the device moves
+type ActiveLocation = Arc<Mutex<Option<(f64, f64)>>>;
+const MAX_RECONNECT_BACKOFF_SECS: u64 = 30;
+const LED_TOPIC: &str = "weather/output/led";
+const SPEAKER_TOPIC: &str = "weather/output/speaker";
+const WEATHER_DELTA_TOPIC: &str = "weather/data/delta";
+// Used for readings without a device id; identified devices get weather/devices/<id>/comfort
+const COMFORT_TOPIC: &str = "weather/comfort";
+const PRESSURE_TENDENCY_TOPIC: &str = "weather/pressure_tendency";
+const LOCAL_FORECAST_TOPIC: &str = "weather/local_forecast";
+// The reference reading may be this far from exactly three hours ago
+const TENDENCY_TOLERANCE_MINUTES: i64 = 30;
+// Two hours at the device's 5-second interval
+const RECENT_READINGS_CAPACITY: usize = 1440;
+const COMMAND_CHANNEL_CAPACITY: usize = 32;
+const INGEST_CHANNEL_CAPACITY: usize = 64;
+// Requests the client queues for the event loop. QoS1 publishes use try_publish, which
+// fails rather than waits once this is full.
+const CLIENT_REQUEST_CAPACITY: usize = 100;
+
+// Newest value of something the event loop or publisher keeps replacing. Readers
+// borrow it without waiting on the writer; subscribe() follows each change.
+type Latest<T> = Arc<watch::Sender<Option<T>>>;
+
+fn latest<T>() -> Latest<T> {
+    Arc::new(watch::channel(None).0)
+}
+
+// Fills in a value restored from history unless a live one arrived first
+fn restore<T>(slot: &mut Option<T>, value: T) -> bool {
+    if slot.is_some() {
+        return false;
+    }
+    *slot = Some(value);
+    true
+}
+
+// Shared state handed to the event loop's message handler
+#[derive(Clone)]
+struct MessageContext {
+    client: AsyncClient,
+    settings: MqttSettings,
+    weather_data: Latest<WeatherData>,
+    sensor_data: Latest<SensorData>,
+    latest_alert: Arc<Mutex<Option<AlertData>>>,
+    recent_readings: Arc<Mutex<VecDeque<SensorData>>>,
+    sensor_history: Arc<SensorHistory>,
+    anomaly_detector: Arc<std::sync::Mutex<AnomalyDetector>>,
+    smoother: Arc<std::sync::Mutex<SensorSmoother>>,
+    // Last trend published per device id, so the retained topic only changes on a new trend
+    pressure_trends: Arc<std::sync::Mutex<HashMap<String, PressureTrend>>>,
+    // Last Zambretti letter published per device id
+    local_forecasts: Arc<std::sync::Mutex<HashMap<String, String>>>,
+    devices: Arc<Mutex<DeviceRegistry>>,
+    delivery: Arc<std::sync::Mutex<DeliveryTracker>>,
+    pending_acks: PendingAcks,
+    device_settings: SharedDeviceSettings,
+    alert_rules: SharedAlertRules,
+    rule_evaluator: Arc<std::sync::Mutex<RuleEvaluator>>,
+    alert_channels: Arc<AlertChannels>,
+    air_quality_alert_active: Arc<AtomicBool>,
+    weather_api_client: Arc<WeatherApiClient>,
+    active_location: ActiveLocation,
+    coordinator: Arc<Coordinator>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorStaleEvent {
+    pub last_received: chrono::DateTime<chrono::Utc>,
+    pub age_secs: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertAcknowledgedEvent {
+    pub alert_id: String,
+    pub device_id: String,
+    pub acknowledged_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionLostEvent {
+    pub error: String,
+    pub retrying: bool,
+}
+
+// Returned when an operation needs the broker connection and there is none
+#[derive(Debug)]
+pub struct NotConnected;
+
+impl std::fmt::Display for NotConnected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MQTT client not connected")
+    }
+}
+
+impl std::error::Error for NotConnected {}
 
 pub struct MqttManager {
     client: Option<AsyncClient>,
-    config: MqttConfig,
-    connected: bool,
-    latest_weather_data: Arc<Mutex<Option<WeatherData>>>,
-    latest_sensor_data: Arc<Mutex<Option<SensorData>>>,
-    event_loop_handle: Option<tokio::task::JoinHandle<()>>,
-    weather_publish_handle: Option<tokio::task::JoinHandle<()>>,
-    app_handle: Option<AppHandle>,
+    settings: MqttSettings,
+    connected: Arc<AtomicBool>,
+    health: Arc<ConnectionHealth>,
+    latest_weather_data: Latest<WeatherData>,
+    latest_sensor_data: Latest<SensorData>,
+    // Newest alert received on weather/alert_trigger from another client
+    latest_alert: Arc<Mutex<Option<AlertData>>>,
+    // Recent readings kept in memory for live charts, oldest first
+    recent_readings: Arc<Mutex<VecDeque<SensorData>>>,
+    sensor_history: Arc<SensorHistory>,
+    event_loop_task: Option<StoppableTask>,
+    weather_publish_task: Option<StoppableTask>,
+    // Mirrors weather_publish_task for MqttHandle::is_auto_publishing
+    publishing: Arc<AtomicBool>,
+    // Publishing was running when the connection went away; restarted at the active
+    // location on the next successful connect
+    resume_publishing: bool,
+    // Publish interval set by the sun automation at night; 0 uses the configured one
+    interval_override: Arc<AtomicU64>,
     weather_api_client: Arc<WeatherApiClient>,
+    uplink: Option<Arc<UplinkBridge>>,
+    proxy_tunnel: Option<ProxyTunnel>,
+    last_disconnect: Option<std::time::Instant>,
+    delivery: Arc<std::sync::Mutex<DeliveryTracker>>,
+    devices: Arc<Mutex<DeviceRegistry>>,
+    device_monitor_task: Option<StoppableTask>,
+    time_sync_task: Option<StoppableTask>,
+    pending_acks: PendingAcks,
+    device_settings: SharedDeviceSettings,
+    alert_rules: SharedAlertRules,
+    alert_channels: Arc<AlertChannels>,
+    active_location: ActiveLocation,
+    // Which desktop on the broker publishes weather
+    coordinator: Arc<Coordinator>,
+    // Handles messages from other transports; the live connection's context, or a
+    // detached one while there is none
+    ingest_ctx: Option<MessageContext>,
+    // Feeds the task that handles those messages one at a time, so a device's readings
+    // are handled in the order they arrived
+    ingest_tx: Option<mpsc::Sender<(MessageContext, String, Vec<u8>)>>,
 }
 
 impl MqttManager {
-    pub fn new() -> Self {
+    pub fn new(weather_api_client: Arc<WeatherApiClient>) -> Self {
+        let alert_channels = Arc::new(AlertChannels::default());
+        sinks::register(alert_channels.webhooks.clone());
+        sinks::register(alert_channels.triggers.clone());
         Self {
             client: None,
-            config: MqttConfig::default(),
-            connected: false,
-            latest_weather_data: Arc::new(Mutex::new(None)),
-            latest_sensor_data: Arc::new(Mutex::new(None)),
-            event_loop_handle: None,
-            weather_publish_handle: None,
-            app_handle: None,
-            weather_api_client: Arc::new(WeatherApiClient::new()),
+            settings: MqttSettings::default(),
+            connected: Arc::new(AtomicBool::new(false)),
+            health: Arc::new(ConnectionHealth::default()),
+            latest_weather_data: latest(),
+            latest_sensor_data: latest(),
+            latest_alert: Arc::new(Mutex::new(None)),
+            recent_readings: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_READINGS_CAPACITY))),
+            sensor_history: Arc::new(SensorHistory::new()),
+            event_loop_task: None,
+            weather_publish_task: None,
+            publishing: Arc::new(AtomicBool::new(false)),
+            resume_publishing: false,
+            interval_override: Arc::new(AtomicU64::new(0)),
+            weather_api_client,
+            uplink: None,
+            proxy_tunnel: None,
+            last_disconnect: None,
+            delivery: Arc::new(std::sync::Mutex::new(DeliveryTracker::default())),
+            devices: Arc::new(Mutex::new(DeviceRegistry::default())),
+            device_monitor_task: None,
+            time_sync_task: None,
+            pending_acks: Arc::new(Mutex::new(HashMap::new())),
+            device_settings: Arc::new(RwLock::new(HashMap::new())),
+            alert_rules: Arc::new(RwLock::new(Vec::new())),
+            alert_channels,
+            active_location: Arc::new(Mutex::new(None)),
+            coordinator: Arc::new(Coordinator::default()),
+            ingest_ctx: None,
+            ingest_tx: None,
         }
     }
 
-    pub fn set_app_handle(&mut self, app_handle: AppHandle) {
-        self.app_handle = Some(app_handle);
-    }
-
-    pub async fn connect(&mut self, host: &str, port: u16) -> Result<()> {
+    async fn connect(&mut self, host: &str, port: u16) -> Result<()> {
         info!("Connecting to MQTT broker at {}:{}", host, port);
 
         // Disconnect any existing connection
-        if self.event_loop_handle.is_some() {
+        if self.event_loop_task.is_some() {
             self.disconnect().await?;
         }
 
         // Update config
-        self.config.broker_host = host.to_string();
-        self.config.broker_port = port;
+        self.settings.broker_host = host.to_string();
+        self.settings.broker_port = port;
+
+        // Route the connection through the proxy tunnel if configured
+        if let Some(tunnel) = self.proxy_tunnel.take() {
+            tunnel.shutdown();
+        }
+        let (connect_host, connect_port) = if self.settings.proxy.enabled {
+            let tunnel = ProxyTunnel::start(self.settings.proxy.clone(), host.to_string(), port).await?;
+            let local_addr = tunnel.local_addr();
+            self.proxy_tunnel = Some(tunnel);
+            (local_addr.ip().to_string(), local_addr.port())
+        } else {
+            (host.to_string(), port)
+        };
 
         // Create MQTT options
-        let mut mqttoptions = MqttOptions::new(&self.config.client_id, host, port);
-        mqttoptions.set_keep_alive(Duration::from_secs(60));
-        mqttoptions.set_clean_session(true);
+        let mut mqttoptions = MqttOptions::new(&self.settings.client_id, connect_host, connect_port);
+        let keep_alive = Duration::from_secs(self.settings.keep_alive_secs.max(5));
+        mqttoptions.set_keep_alive(keep_alive);
+        mqttoptions.set_clean_session(self.should_start_clean_session());
 
-        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+        if let (Some(username), Some(password)) = (&self.settings.username, &self.settings.password) {
             mqttoptions.set_credentials(username, password);
         }
 
         // Create client and event loop
-        let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
+        let (client, mut eventloop) = AsyncClient::new(mqttoptions, CLIENT_REQUEST_CAPACITY);
+        let subscribe_qos = self.subscription_qos();
+        let subscription_filters = self.subscription_filters();
         
         // Test initial connection
         match timeout(Duration::from_secs(10), async {
             // Subscribe to topics
-            client.subscribe("weather/data", QoS::AtMostOnce).await?;
-            client.subscribe("weather/sensor_data", QoS::AtMostOnce).await?;
-            client.subscribe("weather/alert_trigger", QoS::AtMostOnce).await?;
+            for filter in &subscription_filters {
+                client.subscribe(filter, subscribe_qos).await?;
+            }
             
             // Wait for connection confirmation
             loop {
@@ -88,34 +297,121 @@ impl MqttManager {
             }
             Ok::<(), anyhow::Error>(())
         }).await {
-            Ok(_) => {
+            Ok(Err(e)) => {
+                error!("{}", e);
+                self.health.failed(&e.to_string(), false);
+                Err(e)
+            }
+            Ok(Ok(())) => {
                 self.client = Some(client.clone());
-                self.connected = true;
+                self.connected.store(true, Ordering::SeqCst);
+                self.health.connected(format!("{}:{}", host, port), keep_alive);
+                
+                // Start the cloud uplink if configured
+                if self.settings.uplink.enabled {
+                    match UplinkBridge::start(self.settings.uplink.clone()) {
+                        Ok(bridge) => self.uplink = Some(Arc::new(bridge)),
+                        Err(e) => error!("Failed to start uplink bridge: {}", e),
+                    }
+                }
+                
+                sinks::start_configured(&self.settings);
                 
                 // Start persistent event loop in background
-                let weather_data = Arc::clone(&self.latest_weather_data);
-                let sensor_data = Arc::clone(&self.latest_sensor_data);
-                let app_handle = self.app_handle.clone();
+                let ctx = self.message_context(client.clone());
+                self.ingest_ctx = Some(ctx.clone());
+                let monitor_ctx = ctx.clone();
+                let uplink = self.uplink.clone();
+                let connected = Arc::clone(&self.connected);
+                let health = Arc::clone(&self.health);
                 
-                let handle = tokio::spawn(async move {
-                    info!("Starting MQTT event loop");
-                    loop {
-                        match eventloop.poll().await {
-                            Ok(Event::Incoming(Packet::Publish(publish))) => {
-                                Self::handle_message_static(&publish.topic, &publish.payload, &weather_data, &sensor_data, &app_handle).await;
+                // Shared so a restarted loop picks up the same connection
+                let eventloop = Arc::new(Mutex::new(eventloop));
+                let task = StoppableTask::spawn(EVENT_LOOP_TASK, move |cancel| {
+                    let eventloop = Arc::clone(&eventloop);
+                    let ctx = ctx.clone();
+                    let uplink = uplink.clone();
+                    let connected = Arc::clone(&connected);
+                    let health = Arc::clone(&health);
+                    let subscription_filters = subscription_filters.clone();
+                    tokio::spawn(async move {
+                        let mut eventloop = eventloop.lock().await;
+                        info!("Starting MQTT event loop");
+                        let mut backoff_secs = 1;
+                        loop {
+                            let event = eventloop.poll().await;
+                            Self::track_delivery(&event, &ctx.delivery, &ctx.sensor_history, &ctx.alert_channels);
+                            if event.is_ok() {
+                                health.heartbeat();
                             }
-                            Ok(_) => continue,
-                            Err(e) => {
-                                error!("MQTT event loop error: {}", e);
-                                break;
+                            match event {
+                                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                                    // Parsed straight from the receive buffer; only the uplink keeps a handle to it
+                                    Self::handle_message_static(&publish.topic, &publish.payload, &ctx).await;
+                                    if let Some(bridge) = &uplink {
+                                        bridge.forward(&publish.topic, publish.payload).await;
+                                    }
+                                }
+                                // Sent by disconnect(); nothing left to do on this connection
+                                Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                                    info!("Sent disconnect to the MQTT broker");
+                                    break;
+                                }
+                                Ok(Event::Incoming(Packet::ConnAck(connack))) => {
+                                    backoff_secs = 1;
+                                    if !connected.swap(true, Ordering::SeqCst) {
+                                        info!("MQTT connection re-established");
+                                        health.reconnected();
+                                        // A clean session loses its subscriptions on reconnect
+                                        if !connack.session_present {
+                                            Self::resubscribe(&ctx.client, &subscription_filters, subscribe_qos);
+                                        }
+                                        events::publish(AppEvent::MqttReconnected);
+                                    }
+                                }
+                                Ok(_) => continue,
+                                Err(e) => {
+                                    let retrying = Self::is_transient_error(&e);
+                                    let was_connected = connected.swap(false, Ordering::SeqCst);
+                                    health.failed(&e.to_string(), retrying);
+                                    if was_connected {
+                                        events::publish(AppEvent::MqttConnectionLost(ConnectionLostEvent {
+                                            error: e.to_string(),
+                                            retrying,
+                                        }));
+                                        let body = if retrying {
+                                            format!("{}. Reconnecting…", e)
+                                        } else {
+                                            e.to_string()
+                                        };
+                                        ctx.alert_channels.notify_desktop(&AlertLevel::Warning, "MQTT connection lost", &body);
+                                    }
+
+                                    if !retrying {
+                                        error!("MQTT event loop stopped on fatal error: {}", e);
+                                        app_error::report(AppErrorCode::EventLoopStopped, "mqtt", format!("MQTT connection stopped: {}", e), false);
+                                        break;
+                                    }
+
+                                    // The next poll() attempts to reconnect
+                                    warn!("MQTT event loop error: {}, reconnecting in {}s", e, backoff_secs);
+                                    tokio::select! {
+                                        _ = cancel.cancelled() => break,
+                                        _ = tokio::time::sleep(Duration::from_secs(backoff_secs)) => {}
+                                    }
+                                    backoff_secs = (backoff_secs * 2).min(MAX_RECONNECT_BACKOFF_SECS);
+                                }
                             }
                         }
-                    }
-                    info!("MQTT event loop ended");
+                        info!("MQTT event loop ended");
+                    })
                 });
                 
-                self.event_loop_handle = Some(handle);
+                self.event_loop_task = Some(task);
+                self.start_device_monitor(monitor_ctx).await;
+                self.start_time_sync().await;
                 info!("MQTT client connected successfully");
+                self.resume_publishing().await;
                 Ok(())
             }
             Err(_) => {
@@ -125,273 +421,2071 @@ impl MqttManager {
         }
     }
 
-    async fn handle_message_static(
-        topic: &str, 
-        payload: &[u8], 
-        weather_data: &Arc<Mutex<Option<WeatherData>>>, 
-        sensor_data: &Arc<Mutex<Option<SensorData>>>,
-        app_handle: &Option<AppHandle>
+    fn is_transient_error(error: &ConnectionError) -> bool {
+        match error {
+            // Bad credentials or the client being dropped won't fix themselves
+            ConnectionError::ConnectionRefused(_) | ConnectionError::RequestsDone => false,
+            _ => true,
+        }
+    }
+
+    fn subscription_filters(&self) -> Vec<String> {
+        let mut filters: Vec<String> = match self.settings.shared_subscription_group.as_deref().map(str::trim) {
+            Some(group) if !group.is_empty() => {
+                info!("Using shared subscriptions with group '{}'", group);
+                DATA_TOPICS.iter()
+                    .map(|topic| format!("$share/{}/{}", group, topic))
+                    .collect()
+            }
+            _ => DATA_TOPICS.iter().map(|topic| topic.to_string()).collect(),
+        };
+        filters.extend(DEVICE_STATE_TOPICS.iter().map(|topic| topic.to_string()));
+        // Never shared; every instance needs to see the claim
+        if self.settings.coordination.enabled {
+            filters.push(self.settings.coordination.topic.clone());
+        }
+        filters
+    }
+
+    fn resubscribe(client: &AsyncClient, filters: &[String], qos: QoS) {
+        for filter in filters {
+            // try_subscribe avoids blocking the event loop that drains the request queue
+            if let Err(e) = client.try_subscribe(filter, qos) {
+                error!("Failed to resubscribe to {}: {}", filter, e);
+            }
+        }
+    }
+
+    fn track_delivery(
+        event: &Result<Event, ConnectionError>,
+        delivery: &Arc<std::sync::Mutex<DeliveryTracker>>,
+        history: &SensorHistory,
+        alert_channels: &AlertChannels,
     ) {
-        debug!("Received message on topic: {}", topic);
-        
-        match topic {
-            "weather/data" => {
-                match serde_json::from_slice::<WeatherData>(payload) {
-                    Ok(weather) => {
-                        info!("Received weather data update");
-                        let mut data = weather_data.lock().await;
-                        *data = Some(weather);
-                    }
-                    Err(e) => {
-                        error!("Failed to parse weather data: {}", e);
-                    }
+        let mut tracker = delivery.lock().unwrap();
+        match event {
+            Ok(Event::Outgoing(Outgoing::Publish(pkid))) => tracker.mark_sent(*pkid),
+            Ok(Event::Incoming(Packet::ConnAck(connack))) => tracker.connected(connack.session_present),
+            Err(_) => {
+                for record in tracker.connection_lost() {
+                    warn!("Connection lost before message {} on {} was acknowledged", record.message_id, record.topic);
+                    Self::record_alert_delivery(history, alert_channels, &record);
+                    events::publish(AppEvent::PublishFailed(DeliveryEvent::from(&record)));
                 }
             }
-            "weather/sensor_data" => {
-                match serde_json::from_slice::<SensorData>(payload) {
-                    Ok(sensor) => {
-                        println!("M5Go Sensor Data: Temperature: {}°C, Humidity: {}%, Pressure: {} hPa, Timestamp: {}", 
-                                sensor.temperature, sensor.humidity, sensor.pressure, sensor.timestamp);
-                        info!("Received sensor data update");
-                        
-                        // Update stored data
-                        let mut data = sensor_data.lock().await;
-                        *data = Some(sensor.clone());
-                        
-                        // Emit event to frontend
-                        if let Some(handle) = app_handle {
-                            info!("Emitting sensor-data-updated event to frontend");
-                            // Try to emit to all windows
-                            match handle.emit_to("main", "sensor-data-updated", &sensor) {
-                                Ok(_) => info!("Successfully emitted sensor data event to main window"),
-                                Err(e) => {
-                                    error!("Failed to emit sensor data event to main window: {}", e);
-                                    // Fallback to global emit
-                                    if let Err(e2) = handle.emit("sensor-data-updated", &sensor) {
-                                        error!("Failed to emit sensor data event globally: {}", e2);
-                                    } else {
-                                        info!("Successfully emitted sensor data event globally");
-                                    }
-                                }
-                            }
-                        } else {
-                            warn!("No app handle available to emit sensor data event");
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to parse sensor data: {}", e);
-                    }
+            Ok(Event::Incoming(Packet::PubAck(ack))) => {
+                if let Some(record) = tracker.confirm(ack.pkid) {
+                    debug!("Delivery confirmed for message {} on {}", record.message_id, record.topic);
+                    Self::record_alert_delivery(history, alert_channels, &record);
+                    events::publish(AppEvent::PublishConfirmed(DeliveryEvent::from(&record)));
                 }
             }
-            "weather/alert_trigger" => {
-                match serde_json::from_slice::<AlertData>(payload) {
-                    Ok(alert_data) => {
-                        info!("Received alert: {}", alert_data.message);
-                        // Handle alert (could emit to frontend)
+            _ => {}
+        }
+
+        for record in tracker.expire_stale() {
+            warn!("No acknowledgement for message {} on {}", record.message_id, record.topic);
+            Self::record_alert_delivery(history, alert_channels, &record);
+            events::publish(AppEvent::PublishFailed(DeliveryEvent::from(&record)));
+        }
+    }
+
+    // Publishes with QoS1 and tracks the PubAck, returning the message id
+    #[instrument(name = "mqtt_publish", skip(self, payload), fields(qos = 1, bytes = payload.len(), outcome = Empty))]
+    async fn publish_confirmed(&self, topic: &str, retain: bool, payload: Vec<u8>) -> Result<u64> {
+        let result = self.publish_qos1(topic, retain, payload, true)
+            .map(|message_id| message_id.expect("tracked publishes are registered"));
+        logging::record_outcome(&result);
+        result
+    }
+
+    // QoS1 without a delivery record, for routine traffic that would push alerts out of
+    // the tracker's history
+    #[instrument(name = "mqtt_publish", skip(self, payload), fields(qos = 1, bytes = payload.len(), outcome = Empty))]
+    async fn publish_untracked(&self, topic: &str, retain: bool, payload: Vec<u8>) -> Result<()> {
+        let result = self.publish_qos1(topic, retain, payload, false).map(|_| ());
+        logging::record_outcome(&result);
+        result
+    }
+
+    fn publish_qos1(&self, topic: &str, retain: bool, payload: Vec<u8>, tracked: bool) -> Result<Option<u64>> {
+        let client = self.client.as_ref().ok_or_else(|| anyhow::Error::new(NotConnected))?;
+        Self::send_qos1(client, &self.delivery, topic, retain, payload, tracked).map_err(|(e, record)| {
+            if let Some(record) = record {
+                events::publish(AppEvent::PublishFailed(DeliveryEvent::from(&record)));
+            }
+            e.into()
+        })
+    }
+
+    // Hands a QoS1 publish to the client while holding the tracker, so packet ids are
+    // paired with messages in the order the client sends them. try_publish never waits
+    // on the event loop, so this is safe to call from it. On failure, returns the failed
+    // record when the publish was tracked.
+    fn send_qos1(
+        client: &AsyncClient,
+        delivery: &std::sync::Mutex<DeliveryTracker>,
+        topic: &str,
+        retain: bool,
+        payload: Vec<u8>,
+        tracked: bool,
+    ) -> std::result::Result<Option<u64>, (ClientError, Option<DeliveryRecord>)> {
+        let mut tracker = delivery.lock().unwrap();
+        let message_id = if tracked {
+            Some(tracker.register(topic))
+        } else {
+            tracker.reserve();
+            None
+        };
+        match client.try_publish(topic, QoS::AtLeastOnce, retain, payload) {
+            Ok(()) => Ok(message_id),
+            Err(e) => {
+                let record = tracker.cancel_last(&e.to_string());
+                Err((e, record))
+            }
+        }
+    }
+
+    async fn apply_calibration(sensor: &mut SensorData, device_settings: &SharedDeviceSettings) {
+        let settings = device_settings.read().await;
+        let device_key = sensor.device_id.as_deref().unwrap_or("default");
+        let Some(offsets) = settings.get(device_key).map(|s| &s.calibration) else {
+            return;
+        };
+        if offsets.is_zero() {
+            return;
+        }
+
+        sensor.keep_raw();
+        sensor.temperature += offsets.temperature;
+        sensor.humidity = (sensor.humidity + offsets.humidity).clamp(0.0, 100.0);
+        sensor.pressure += offsets.pressure;
+        debug!("Applied calibration offsets for {}: {:?}", device_key, offsets);
+    }
+
+    async fn handle_button(event: ButtonEvent, ctx: &MessageContext) {
+        info!("Button {} ({}) pressed on {}", event.button, event.press_type, event.device_id);
+        events::publish(AppEvent::DeviceButtonPressed(event.clone()));
+
+        let action = ctx.settings.button_actions.get(&event.button.to_uppercase())
+            .copied()
+            .unwrap_or(ButtonAction::None);
+
+        match action {
+            ButtonAction::None => {}
+            ButtonAction::RefreshWeather => {
+                let Some(weather) = ctx.weather_data.borrow().clone() else {
+                    warn!("No weather location known yet, ignoring refresh from button {}", event.button);
+                    return;
+                };
+                let weather_api_client = Arc::clone(&ctx.weather_api_client);
+                // Run the HTTP request outside the event loop
+                tokio::spawn(async move {
+                    match weather_api_client.ensure_daily_cache(weather.gps_lat, weather.gps_lon).await {
+                        Ok(_) => info!("Weather cache refreshed from device button"),
+                        Err(e) => {
+                            error!("Failed to refresh weather from device button: {}", e);
+                            app_error::report(AppErrorCode::CacheRefreshFailed, "weather_api", format!("Weather refresh failed: {}", e), true);
+                        }
                     }
-                    Err(e) => {
-                        error!("Failed to parse alert data: {}", e);
+                });
+            }
+            ButtonAction::SendTestAlert => {
+                let alert = AlertData {
+                    message: format!(
+                        "Test alert from button {} on {}",
+                        event.button,
+                        Self::device_display_name(&event.device_id, &ctx.device_settings).await
+                    ),
+                    level: AlertLevel::Info,
+                    timestamp: chrono::Utc::now(),
+                    id: None,
+                };
+                Self::publish_alert_from_loop(ctx, &alert, AlertSource::ButtonTest);
+            }
+            ButtonAction::PublishSnapshot => {
+                let Some(weather) = ctx.weather_data.borrow().clone() else {
+                    warn!("No weather data to publish for button {}", event.button);
+                    return;
+                };
+                match payload_templates::weather_payload(&ctx.settings.payload_templates, &weather) {
+                    Ok(payload) => {
+                        if let Err(e) = ctx.client.try_publish("weather/data", QoS::AtMostOnce, true, payload) {
+                            error!("Failed to publish weather snapshot from button: {}", e);
+                        }
                     }
+                    Err(e) => error!("Failed to serialize weather data: {}", e),
                 }
             }
-            _ => {
-                warn!("Received message on unknown topic: {}", topic);
+            ButtonAction::AcknowledgeAlert => {
+                let filter = AlertHistoryFilter {
+                    direction: Some(AlertDirection::Sent),
+                    acknowledged: Some(false),
+                    ..Default::default()
+                };
+                match ctx.sensor_history.query_alerts(&filter, 1, 0) {
+                    Ok(page) => match page.alerts.first().and_then(|record| record.alert_id.clone()) {
+                        Some(alert_id) => Self::acknowledge_alert(&alert_id, &event.device_id, ctx),
+                        None => debug!("No unacknowledged alert for button {}", event.button),
+                    },
+                    Err(e) => error!("Failed to look up alert to acknowledge: {}", e),
+                }
             }
         }
     }
 
-    pub async fn disconnect(&mut self) -> Result<()> {
-        info!("Disconnecting from MQTT broker");
-        
-        // Abort the event loop task
-        if let Some(handle) = self.event_loop_handle.take() {
-            handle.abort();
+    async fn follow_device_location(device_id: &str, lat: f64, lon: f64, ctx: &MessageContext) {
+        if !ctx.settings.follow_device_gps || !geo::is_valid_coordinate(lat, lon) {
+            return;
         }
-        
-        // Abort the weather publishing task
-        if let Some(handle) = self.weather_publish_handle.take() {
-            handle.abort();
-            info!("Automated weather publishing stopped due to disconnect");
+
+        let distance_km = {
+            let mut active = ctx.active_location.lock().await;
+            let distance_km = active.map(|(active_lat, active_lon)| geo::distance_km(active_lat, active_lon, lat, lon));
+            // Ignore GPS jitter below the configured threshold
+            if distance_km.map_or(false, |d| d < ctx.settings.gps_min_distance_km) {
+                return;
+            }
+            *active = Some((lat, lon));
+            distance_km
+        };
+
+        info!("Device {} moved to {:.4}, {:.4}, updating weather location", device_id, lat, lon);
+        events::publish(AppEvent::WeatherLocationChanged(LocationChange {
+            lat,
+            lon,
+            source: format!("device:{}", device_id),
+            distance_km,
+        }));
+
+        let weather_api_client = Arc::clone(&ctx.weather_api_client);
+        tokio::spawn(async move {
+            if let Err(e) = weather_api_client.ensure_daily_cache(lat, lon).await {
+                error!("Failed to refresh weather cache for new device location: {}", e);
+                app_error::report(AppErrorCode::CacheRefreshFailed, "weather_api", format!("Weather refresh for the new location failed: {}", e), true);
+            }
+        });
+    }
+
+    fn check_air_quality(sensor: &SensorData, muted: bool, ctx: &MessageContext) {
+        if sensor.co2.is_none() && sensor.tvoc.is_none() {
+            return;
         }
-        
-        // Disconnect the client
-        if let Some(client) = &self.client {
-            client.disconnect().await?;
-            self.client = None;
+
+        let co2_high = sensor.co2.map_or(false, |co2| co2 >= ctx.settings.co2_alert_threshold_ppm);
+        let tvoc_high = sensor.tvoc.map_or(false, |tvoc| tvoc >= ctx.settings.tvoc_alert_threshold_ppb);
+        let is_poor = co2_high || tvoc_high;
+
+        // Only alert when air quality first turns poor
+        if is_poor && !ctx.air_quality_alert_active.swap(true, Ordering::SeqCst) {
+            let alert = AlertData {
+                message: format!(
+                    "Poor air quality: CO2 {} ppm, TVOC {} ppb",
+                    sensor.co2.map_or("-".to_string(), |v| format!("{:.0}", v)),
+                    sensor.tvoc.map_or("-".to_string(), |v| format!("{:.0}", v))
+                ),
+                level: AlertLevel::Warning,
+                timestamp: chrono::Utc::now(),
+                id: None,
+            };
+            warn!("{}", alert.message);
+            if muted {
+                debug!("Alerts muted for this device, not sending: {}", alert.message);
+                return;
+            }
+            Self::publish_alert_from_loop(ctx, &alert, AlertSource::AirQuality);
+        } else if !is_poor && ctx.air_quality_alert_active.swap(false, Ordering::SeqCst) {
+            info!("Air quality back to normal");
         }
-        
-        self.connected = false;
-        info!("MQTT client disconnected");
-        Ok(())
     }
 
-    pub fn is_connected(&self) -> bool {
-        self.connected
+    fn acknowledge_alert(alert_id: &str, device_id: &str, ctx: &MessageContext) {
+        match ctx.sensor_history.acknowledge_alert(alert_id, device_id) {
+            Ok(true) => {
+                info!("Alert {} acknowledged on {}", alert_id, device_id);
+                events::publish(AppEvent::AlertAcknowledged(AlertAcknowledgedEvent {
+                    alert_id: alert_id.to_string(),
+                    device_id: device_id.to_string(),
+                    acknowledged_at: chrono::Utc::now(),
+                }));
+                // Stop the LED bar repeating the alert pattern
+                let clear = LedCommand {
+                    color: "#000000".to_string(),
+                    pattern: "solid".to_string(),
+                    interval_ms: 0,
+                    duration_secs: 0,
+                };
+                match serde_json::to_vec(&clear) {
+                    Ok(payload) => {
+                        if let Err(e) = ctx.client.try_publish(LED_TOPIC, QoS::AtMostOnce, false, payload) {
+                            warn!("Failed to clear alert LED: {}", e);
+                        }
+                    }
+                    Err(e) => error!("Failed to serialize LED command: {}", e),
+                }
+            }
+            Ok(false) => debug!("Ignoring ack for unknown or already acknowledged alert {}", alert_id),
+            Err(e) => error!("Failed to record alert acknowledgement: {}", e),
+        }
     }
 
-    pub async fn publish_weather_data(&self, data: &WeatherData) -> Result<()> {
-        if let Some(client) = &self.client {
-            let payload = serde_json::to_vec(data)?;
-            
-            // Print payload before sending
-            // match serde_json::to_string_pretty(data) {
-            //     Ok(json_str) => {
-            //         println!("Publishing weather data payload:\n{}", json_str);
-            //     }
-            //     Err(e) => {
-            //         warn!("Failed to serialize weather data for printing: {}", e);
-            //         println!("Publishing weather data payload: {:?}", data);
-            //     }
-            // }
-            
-            client.publish("weather/data", QoS::AtMostOnce, false, payload).await?;
-            info!("Published weather data to MQTT");
-            Ok(())
-        } else {
-            Err(anyhow!("MQTT client not connected"))
+    async fn check_alert_rules(sensor: &SensorData, muted: bool, ctx: &MessageContext) {
+        let rules = ctx.alert_rules.read().await;
+        if rules.is_empty() {
+            return;
+        }
+        let weather = ctx.weather_data.borrow().clone();
+        let inputs = RuleInputs { sensor, weather: weather.as_ref() };
+        let alerts = match ctx.rule_evaluator.lock() {
+            Ok(mut evaluator) => evaluator.evaluate(&inputs, &rules),
+            Err(_) => return,
+        };
+        drop(rules);
+
+        for alert in alerts {
+            warn!("{}", alert.message);
+            if muted {
+                debug!("Alerts muted for this device, not sending: {}", alert.message);
+                continue;
+            }
+            ctx.alert_channels.notify_desktop(&alert.level, &notifications::alert_title(&alert), &alert.message);
+            Self::publish_alert_from_loop(ctx, &alert, AlertSource::Rule);
         }
     }
 
-    pub async fn send_alert(&self, alert: &AlertData) -> Result<()> {
-        if let Some(client) = &self.client {
-            let payload = serde_json::to_vec(alert)?;
-            client.publish("weather/alert_trigger", QoS::AtMostOnce, false, payload).await?;
-            info!("Published alert to MQTT: {}", alert.message);
-            // Print payload before sending
-            match serde_json::to_string_pretty(alert) {
-                Ok(json_str) => {
-                    println!("Publishing weather data payload:\n{}", json_str);
-                }
-                Err(e) => {
-                    warn!("Failed to serialize weather data for printing: {}", e);
-                    println!("Publishing weather data payload: {:?}", alert);
-                }
+    async fn handle_telemetry(telemetry: DeviceTelemetry, ctx: &MessageContext) {
+        debug!("Telemetry from {}: battery {:?}%, charging {:?}, RSSI {:?} dBm",
+               telemetry.device_id, telemetry.battery_percent, telemetry.charging, telemetry.rssi);
+
+        let update = ctx.devices.lock().await
+            .record_telemetry(telemetry.clone(), ctx.settings.low_battery_threshold_percent);
+
+        if let Some(device) = update.state_change {
+            Self::emit_device_state(ctx, device).await;
+        }
+        events::publish(AppEvent::DeviceTelemetryUpdated(telemetry.clone()));
+
+        if let (Some(lat), Some(lon)) = (telemetry.gps_lat, telemetry.gps_lon) {
+            Self::follow_device_location(&telemetry.device_id, lat, lon, ctx).await;
+        }
+
+        if update.low_battery && !Self::alerts_muted(Some(&telemetry.device_id), &ctx.device_settings).await {
+            let device_name = Self::device_display_name(&telemetry.device_id, &ctx.device_settings).await;
+            let alert = AlertData {
+                message: format!(
+                    "Low battery on {}: {:.0}%",
+                    device_name,
+                    telemetry.battery_percent.unwrap_or_default()
+                ),
+                level: AlertLevel::Warning,
+                timestamp: chrono::Utc::now(),
+                id: None,
+            };
+            warn!("{}", alert.message);
+            Self::publish_alert_from_loop(ctx, &alert, AlertSource::LowBattery);
+        }
+    }
+
+    fn pressure_tendency(sensor: &SensorData, ctx: &MessageContext) -> Option<PressureTendency> {
+        let at = sensor.received_at? - chrono::Duration::hours(3);
+        let tolerance = chrono::Duration::minutes(TENDENCY_TOLERANCE_MINUTES);
+        match ctx.sensor_history.pressure_near(sensor.device_id.as_deref(), at, tolerance) {
+            Ok(earlier) => earlier.map(|earlier| PressureTendency::from_change(sensor.pressure - earlier)),
+            Err(e) => {
+                warn!("Failed to read pressure history: {}", e);
+                None
             }
-            Ok(())
-        } else {
-            Err(anyhow!("MQTT client not connected"))
         }
     }
 
+    // Retained, and only when the trend changes, so the device can show it after a restart
+    fn publish_pressure_tendency(sensor: &SensorData, ctx: &MessageContext) {
+        let Some(tendency) = sensor.pressure_tendency else {
+            return;
+        };
+        let device_id = sensor.device_id.clone().unwrap_or_default();
+        if ctx.pressure_trends.lock().unwrap().insert(device_id.clone(), tendency.trend) == Some(tendency.trend) {
+            return;
+        }
 
-    pub async fn get_latest_weather_data(&self) -> Option<WeatherData> {
-        let data = self.latest_weather_data.lock().await;
-        data.clone()
+        let topic = if device_id.is_empty() {
+            PRESSURE_TENDENCY_TOPIC.to_string()
+        } else {
+            device_topic(&device_id, "pressure_tendency")
+        };
+        match serde_json::to_vec(&tendency) {
+            Ok(payload) => {
+                if let Err(e) = ctx.client.try_publish(&topic, QoS::AtMostOnce, true, payload) {
+                    warn!("Failed to publish pressure tendency: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize pressure tendency: {}", e),
+        }
     }
 
-    pub async fn get_latest_sensor_data(&self) -> Option<SensorData> {
-        let data = self.latest_sensor_data.lock().await;
-        data.clone()
+    async fn build_local_forecast(
+        sensor: &SensorData,
+        weather_data: &Latest<WeatherData>,
+        active_location: &ActiveLocation,
+        altitude_m: f64,
+    ) -> LocalForecast {
+        let weather = weather_data.borrow().clone();
+        // Only the hemisphere matters, so any known latitude will do
+        let latitude = match &weather {
+            Some(weather) => weather.gps_lat,
+            None => active_location.lock().await.map_or(0.0, |(lat, _)| lat),
+        };
+        forecasting::from_observations(sensor, weather.as_ref(), latitude, altitude_m)
     }
 
-    pub async fn start_automated_weather_publishing(&mut self, lat: f64, lon: f64) -> Result<()> {
-        if self.weather_publish_handle.is_some() {
-            info!("Automated weather publishing is already running");
-            return Ok(());
+    async fn publish_local_forecast(sensor: &SensorData, ctx: &MessageContext) {
+        let forecast = Self::build_local_forecast(sensor, &ctx.weather_data, &ctx.active_location, ctx.settings.local_forecast.station_altitude_m).await;
+        let device_id = sensor.device_id.clone().unwrap_or_default();
+        if ctx.local_forecasts.lock().unwrap().insert(device_id.clone(), forecast.letter.clone()).as_deref() == Some(forecast.letter.as_str()) {
+            return;
         }
 
-        if !self.connected {
-            return Err(anyhow!("MQTT client not connected"));
+        info!("Local forecast: {} ({})", forecast.text, forecast.letter);
+        let topic = if device_id.is_empty() {
+            LOCAL_FORECAST_TOPIC.to_string()
+        } else {
+            device_topic(&device_id, "local_forecast")
+        };
+        match serde_json::to_vec(&forecast) {
+            Ok(payload) => {
+                if let Err(e) = ctx.client.try_publish(&topic, QoS::AtMostOnce, true, payload) {
+                    warn!("Failed to publish local forecast: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize local forecast: {}", e),
         }
+    }
 
-        let client = self.client.as_ref().ok_or_else(|| anyhow!("MQTT client not available"))?.clone();
-        let weather_api_client = Arc::clone(&self.weather_api_client);
-        
-        info!("Starting automated weather publishing every 5 seconds for coordinates: {}, {}", lat, lon);
-        
-        let weather_data_arc = Arc::clone(&self.latest_weather_data);
-        let app_handle = self.app_handle.clone();
-        
-        let handle = tokio::spawn(async move {
-            // Ensure we have cached data for today
-            info!("Ensuring daily weather cache is available...");
-            if let Err(e) = weather_api_client.ensure_daily_cache(lat, lon).await {
-                error!("Failed to ensure daily cache: {}", e);
+    fn publish_comfort_metrics(sensor: &SensorData, ctx: &MessageContext) {
+        let Some(comfort) = &sensor.comfort else {
+            return;
+        };
+        let topic = sensor.device_id.as_deref()
+            .map(|device_id| device_topic(device_id, "comfort"))
+            .unwrap_or_else(|| COMFORT_TOPIC.to_string());
+        match serde_json::to_vec(comfort) {
+            // try_publish: awaiting here could deadlock against the event loop's own poll()
+            Ok(payload) => {
+                if let Err(e) = ctx.client.try_publish(&topic, QoS::AtMostOnce, false, payload) {
+                    warn!("Failed to publish comfort metrics: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize comfort metrics: {}", e),
+        }
+    }
+
+    // Publishes an alert from inside the event loop, where awaiting the request
+    // queue could deadlock against our own poll()
+    fn publish_alert_from_loop(ctx: &MessageContext, alert: &AlertData, source: AlertSource) {
+        let alert = &alert.with_id();
+        let payload = match payload_templates::alert_payload(&ctx.settings.payload_templates, alert) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to serialize alert: {}", e);
                 return;
             }
+        };
 
-            let mut interval = interval(Duration::from_secs(5));
-            
-            loop {
-                interval.tick().await;
-                
-                // Read from cache file only - never call API
-                match weather_api_client.read_cached_weather_only(lat, lon).await {
-                    Ok(Some(weather_data)) => {
-                        // Store the weather data in memory
-                        {
-                            let mut stored_data = weather_data_arc.lock().await;
-                            *stored_data = Some(weather_data.clone());
-                        }
-                        
-                        // Print payload before sending
-                        match serde_json::to_string_pretty(&weather_data) {
-                            Ok(json_str) => {
-                                println!("Publishing cached weather data from file:\n{}", json_str);
-                            }
-                            Err(e) => {
-                                warn!("Failed to serialize weather data for printing: {}", e);
-                                println!("Publishing cached weather data from file: {:?}", weather_data);
-                            }
-                        }
-                        
-                        // Publish to MQTT
-                        match serde_json::to_vec(&weather_data) {
-                            Ok(payload) => {
-                                match client.publish("weather/data", QoS::AtMostOnce, false, payload).await {
-                                    Ok(_) => {
-                                        info!("Published weather data from cache file to MQTT");
-                                        
-                                        // Emit event to frontend if app handle is available
-                                        if let Some(handle) = &app_handle {
-                                            if let Err(e) = handle.emit("weather-data-updated", &weather_data) {
-                                                warn!("Failed to emit weather data event: {}", e);
-                                            } else {
-                                                debug!("Emitted weather data event to frontend");
-                                            }
-                                        }
-                                    },
-                                    Err(e) => error!("Failed to publish weather data: {}", e),
-                                }
-                            }
-                            Err(e) => error!("Failed to serialize weather data: {}", e),
-                        }
-                    }
-                    Ok(None) => {
-                        warn!("No cached weather data available - cache may have expired");
-                        
-                        // Try to refresh cache once
-                        info!("Attempting to refresh weather cache...");
-                        if let Err(e) = weather_api_client.ensure_daily_cache(lat, lon).await {
-                            error!("Failed to refresh cache: {}", e);
-                        } else {
-                            info!("Cache refreshed successfully");
+        let route = ctx.alert_channels.route(&alert.level);
+        let topic = notifications::route_topic(&route);
+        ctx.alert_channels.dispatch(alert, source, AlertDirection::Sent);
+        match route.qos {
+            AlertQos::AtLeastOnce => {
+                match Self::send_qos1(&ctx.client, &ctx.delivery, topic, route.retain, payload, true) {
+                    Ok(message_id) => Self::record_sent_alert(&ctx.sensor_history, &ctx.delivery, alert, source, message_id),
+                    Err((e, record)) => {
+                        error!("Failed to publish alert: {}", e);
+                        Self::record_unconfirmed_alert(&ctx.sensor_history, alert, source, Some(e.to_string()));
+                        if let Some(record) = record {
+                            events::publish(AppEvent::PublishFailed(DeliveryEvent::from(&record)));
                         }
                     }
-                    Err(e) => {
-                        error!("Failed to read cached weather data: {}", e);
+                }
+            }
+            AlertQos::AtMostOnce => {
+                let result = ctx.client.try_publish(topic, QoS::AtMostOnce, route.retain, payload);
+                if let Err(e) = &result {
+                    error!("Failed to publish alert: {}", e);
+                }
+                Self::record_unconfirmed_alert(&ctx.sensor_history, alert, source, result.err().map(|e| e.to_string()));
+            }
+        }
+
+        match Self::alert_output_commands(&ctx.settings.alert_outputs, &alert.level, route.buzz) {
+            Ok(commands) => {
+                for (topic, payload) in commands {
+                    if let Err(e) = ctx.client.try_publish(topic, QoS::AtMostOnce, false, payload) {
+                        warn!("Failed to publish alert outputs: {}", e);
                     }
                 }
             }
-        });
-        
-        self.weather_publish_handle = Some(handle);
-        info!("Automated weather publishing started");
-        Ok(())
+            Err(e) => error!("Failed to serialize alert outputs: {}", e),
+        }
     }
 
-    pub async fn stop_automated_weather_publishing(&mut self) -> Result<()> {
-        if let Some(handle) = self.weather_publish_handle.take() {
-            handle.abort();
-            info!("Automated weather publishing stopped");
-            Ok(())
-        } else {
-            info!("Automated weather publishing was not running");
-            Ok(())
+    // The PubAck may already have been processed, so it starts from the tracker's current status
+    fn record_sent_alert(
+        history: &SensorHistory,
+        delivery: &std::sync::Mutex<DeliveryTracker>,
+        alert: &AlertData,
+        source: AlertSource,
+        message_id: Option<u64>,
+    ) {
+        let status = message_id
+            .and_then(|message_id| delivery.lock().unwrap().get(message_id))
+            .map_or(DeliveryStatus::Queued, |record| record.status);
+        if let Err(e) = history.insert_sent_alert(alert, source, message_id, status, None) {
+            error!("Failed to record alert in history: {}", e);
         }
     }
 
-    pub fn is_auto_publishing(&self) -> bool {
-        self.weather_publish_handle.is_some()
+    // QoS0 alerts have no PubAck, so they're recorded as sent (or failed) straight away
+    fn record_unconfirmed_alert(history: &SensorHistory, alert: &AlertData, source: AlertSource, error: Option<String>) {
+        let status = if error.is_some() { DeliveryStatus::Failed } else { DeliveryStatus::Sent };
+        if let Err(e) = history.insert_sent_alert(alert, source, None, status, error.as_deref()) {
+            error!("Failed to record alert in history: {}", e);
+        }
+    }
+
+    // Copies a delivery result into the alert history when the message was an alert
+    fn record_alert_delivery(history: &SensorHistory, alert_channels: &AlertChannels, record: &DeliveryRecord) {
+        if !alert_channels.is_alert_topic(&record.topic) {
+            return;
+        }
+        if let Err(e) = history.update_alert_delivery(record.message_id, record.status, record.error.as_deref()) {
+            error!("Failed to record alert delivery: {}", e);
+        }
     }
-}
\ No newline at end of file
+
+    async fn emit_device_state(ctx: &MessageContext, device: DeviceInfo) {
+        let device = Self::with_metadata(device, &ctx.device_settings).await;
+        let name = device.name.clone().unwrap_or_else(|| device.device_id.clone());
+        if device.online {
+            info!("Device {} is online", name);
+            events::publish(AppEvent::DeviceOnline(device));
+        } else {
+            warn!("Device {} went offline", name);
+            ctx.alert_channels.notify_device_offline(&device);
+            events::publish(AppEvent::DeviceOffline(device));
+        }
+    }
+
+    // Fills in the user-assigned name and location from the device settings
+    async fn with_metadata(mut device: DeviceInfo, device_settings: &SharedDeviceSettings) -> DeviceInfo {
+        if let Some(settings) = device_settings.read().await.get(&device.device_id) {
+            device.name = settings.name.clone();
+            device.location = settings.location.clone();
+            device.muted_until = settings.muted_until.filter(|_| settings.is_muted(chrono::Utc::now()));
+        }
+        device
+    }
+
+    async fn alerts_muted(device_id: Option<&str>, device_settings: &SharedDeviceSettings) -> bool {
+        let Some(device_id) = device_id else {
+            return false;
+        };
+        device_settings.read().await.get(device_id).is_some_and(|settings| settings.is_muted(chrono::Utc::now()))
+    }
+
+    async fn device_display_name(device_id: &str, device_settings: &SharedDeviceSettings) -> String {
+        device_settings.read().await.get(device_id)
+            .and_then(|settings| settings.name.clone())
+            .unwrap_or_else(|| device_id.to_string())
+    }
+
+    // Periodically checks for offline devices and a stale sensor feed
+    async fn start_device_monitor(&mut self, ctx: MessageContext) {
+        if let Some(task) = self.device_monitor_task.take() {
+            task.stop().await;
+        }
+
+        let timeout_secs = self.settings.device_offline_timeout_secs;
+
+        let task = StoppableTask::spawn("device monitor", move |cancel| {
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                let mut interval = interval(Duration::from_secs(10));
+                let mut sensor_stale = false;
+                loop {
+                    tokio::select! {
+                        _ = cancel.cancelled() => break,
+                        _ = interval.tick() => {}
+                    }
+                    let expired = ctx.devices.lock().await.expire(timeout_secs);
+                    for device in expired {
+                        Self::emit_device_state(&ctx, device).await;
+                    }
+
+                    sensor_stale = Self::check_sensor_staleness(&ctx, sensor_stale).await;
+                }
+                debug!("Device monitor stopped");
+            })
+        });
+
+        self.device_monitor_task = Some(task);
+    }
+
+    // Returns the new stale state, emitting an event when the feed first goes stale
+    async fn check_sensor_staleness(ctx: &MessageContext, was_stale: bool) -> bool {
+        let stale_after = chrono::Duration::minutes(ctx.settings.sensor_stale_after_minutes as i64);
+        let last_received = ctx.sensor_data.borrow().as_ref().and_then(|s| s.received_at);
+        let Some(last_received) = last_received else {
+            return was_stale;
+        };
+
+        let age = chrono::Utc::now() - last_received;
+        let is_stale = ctx.settings.sensor_stale_after_minutes > 0 && age > stale_after;
+
+        if is_stale && !was_stale {
+            warn!("No sensor data received for {} minutes", age.num_minutes());
+            events::publish(AppEvent::SensorDataStale(SensorStaleEvent {
+                last_received,
+                age_secs: age.num_seconds(),
+            }));
+
+            if ctx.settings.alert_on_stale_sensor {
+                let alert = AlertData {
+                    message: format!("No sensor data received for {} minutes", age.num_minutes()),
+                    level: AlertLevel::Warning,
+                    timestamp: chrono::Utc::now(),
+                    id: None,
+                };
+                Self::publish_alert_from_loop(ctx, &alert, AlertSource::StaleSensor);
+            }
+        } else if !is_stale && was_stale {
+            info!("Sensor data feed resumed");
+        }
+
+        is_stale
+    }
+
+    async fn start_time_sync(&mut self) {
+        if let Some(task) = self.time_sync_task.take() {
+            task.stop().await;
+        }
+
+        let interval_secs = self.settings.time_sync_interval_secs;
+        if interval_secs == 0 {
+            info!("Periodic time sync disabled");
+            return;
+        }
+
+        let Some(client) = self.client.clone() else {
+            return;
+        };
+        let topic = self.settings.time_sync_topic.clone();
+
+        let task = StoppableTask::spawn("time sync", move |cancel| {
+            let (client, topic) = (client.clone(), topic.clone());
+            tokio::spawn(async move {
+                // The first tick fires immediately, syncing right after connecting
+                let mut interval = interval(Duration::from_secs(interval_secs));
+                loop {
+                    tokio::select! {
+                        _ = cancel.cancelled() => break,
+                        _ = interval.tick() => {}
+                    }
+                    if let Err(e) = Self::publish_time(&client, &topic).await {
+                        error!("Failed to publish time sync: {}", e);
+                    }
+                }
+                debug!("Time sync stopped");
+            })
+        });
+
+        self.time_sync_task = Some(task);
+    }
+
+    async fn publish_time(client: &AsyncClient, topic: &str) -> Result<TimeSync> {
+        let time_sync = TimeSync::now();
+        let payload = serde_json::to_vec(&time_sync)?;
+        client.publish(topic, QoS::AtMostOnce, false, payload).await?;
+        debug!("Published time sync {} to {}", time_sync.iso, topic);
+        Ok(time_sync)
+    }
+
+    async fn sync_device_time(&self) -> Result<TimeSync> {
+        let client = self.client.as_ref().ok_or_else(|| anyhow::Error::new(NotConnected))?;
+        Self::publish_time(client, &self.settings.time_sync_topic).await
+    }
+
+    fn should_start_clean_session(&self) -> bool {
+        if self.settings.clean_session {
+            return true;
+        }
+
+        // Drop the persisted session if we've been away longer than the configured expiry
+        match (self.settings.session_expiry_secs, self.last_disconnect) {
+            (0, _) | (_, None) => false,
+            (expiry, Some(last)) => {
+                let expired = last.elapsed() > Duration::from_secs(expiry);
+                if expired {
+                    info!("Persistent MQTT session expired, starting a clean session");
+                }
+                expired
+            }
+        }
+    }
+
+    fn subscription_qos(&self) -> QoS {
+        // The broker only queues messages for persistent sessions on QoS1+ subscriptions
+        if self.settings.clean_session {
+            QoS::AtMostOnce
+        } else {
+            QoS::AtLeastOnce
+        }
+    }
+
+    async fn handle_message_static(
+        topic: &str, 
+        payload: &[u8], 
+        ctx: &MessageContext
+    ) {
+        debug!("Received message on topic: {}", topic);
+        let weather_data = &ctx.weather_data;
+        let sensor_data = &ctx.sensor_data;
+        
+        if let Some(device_id) = device_id_from_topic(topic, "status") {
+            debug!("Heartbeat from device {}", device_id);
+            let changed = ctx.devices.lock().await.record_heartbeat(device_id, payload);
+            if let Some(device) = changed {
+                Self::emit_device_state(ctx, device).await;
+            }
+            return;
+        }
+        
+        if let Some(device_id) = device_id_from_topic(topic, "ack") {
+            match serde_json::from_slice::<DeviceAck>(payload) {
+                Ok(ack) => {
+                    info!("Device {} acknowledged request {} (success: {})", device_id, ack.request_id, ack.success);
+                    if let Some(waiter) = ctx.pending_acks.lock().await.remove(&ack.request_id) {
+                        let _ = waiter.send(ack);
+                    } else if ack.success {
+                        // Not a pending request, so the user acknowledged an alert on the device
+                        Self::acknowledge_alert(&ack.request_id, device_id, ctx);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to parse ack from {}: {}", device_id, e);
+                }
+            }
+            return;
+        }
+        
+        if let Some(device_id) = device_id_from_topic(topic, "button") {
+            match serde_json::from_slice::<ButtonEvent>(payload) {
+                Ok(mut event) => {
+                    event.device_id = device_id.to_string();
+                    Self::handle_button(event, ctx).await;
+                }
+                Err(e) => {
+                    error!("Failed to parse button event from {}: {}", device_id, e);
+                }
+            }
+            return;
+        }
+        
+        if let Some(device_id) = device_id_from_topic(topic, "telemetry") {
+            match serde_json::from_slice::<DeviceTelemetry>(payload) {
+                Ok(mut telemetry) => {
+                    telemetry.device_id = device_id.to_string();
+                    Self::handle_telemetry(telemetry, ctx).await;
+                }
+                Err(e) => {
+                    error!("Failed to parse telemetry from {}: {}", device_id, e);
+                }
+            }
+            return;
+        }
+        
+        if ctx.settings.coordination.enabled && topic == ctx.settings.coordination.topic {
+            ctx.coordinator.record(payload);
+            return;
+        }
+        
+        match topic {
+            "weather/data" => {
+                match serde_json::from_slice::<WeatherData>(payload) {
+                    Ok(weather) => {
+                        info!("Received weather data update");
+                        if let Err(e) = ctx.sensor_history.insert_weather(&weather) {
+                            error!("Failed to record weather snapshot: {}", e);
+                        }
+                        weather_data.send_replace(Some(weather));
+                    }
+                    // Our own templated snapshot coming back
+                    Err(e) if !ctx.settings.payload_templates.weather.trim().is_empty() => {
+                        debug!("Ignoring weather data not in the standard format: {}", e);
+                    }
+                    Err(e) => {
+                        error!("Failed to parse weather data: {}", e);
+                    }
+                }
+            }
+            "weather/sensor_data" => {
+                match serde_json::from_slice::<SensorData>(payload) {
+                    Ok(mut sensor) => {
+                        sensor.received_at = Some(chrono::Utc::now());
+                        if let Some(device_id) = sensor.device_id.clone() {
+                            sensor.device_name = Some(Self::device_display_name(&device_id, &ctx.device_settings).await);
+                        }
+                        Self::apply_calibration(&mut sensor, &ctx.device_settings).await;
+                        // Console echo for development; too costly at high message rates in release builds
+                        if cfg!(debug_assertions) {
+                            println!("M5Go Sensor Data: Temperature: {}°C, Humidity: {}%, Pressure: {} hPa, CO2: {:?} ppm, TVOC: {:?} ppb, Light: {:?} lx, Timestamp: {}", 
+                                    sensor.temperature, sensor.humidity, sensor.pressure, sensor.co2, sensor.tvoc, sensor.lux, sensor.timestamp);
+                        }
+                        info!("Received sensor data update (schema v{})", sensor.schema_version);
+                        
+                        sensor.anomalies = ctx.anomaly_detector.lock().unwrap().check(&sensor, &ctx.settings.anomaly_detection);
+                        sensor.quality = validation::sensor_quality(&sensor, ctx.settings.sensor_stale_after_minutes);
+                        // Checked for anomalies first, so a glitch neither hides in nor skews the average
+                        if sensor.anomalies.is_empty() {
+                            ctx.smoother.lock().unwrap().apply(&mut sensor, &ctx.settings.smoothing);
+                        }
+                        sensor.comfort = Some(ComfortMetrics::from_reading(sensor.temperature, sensor.humidity));
+                        sensor.pressure_tendency = Self::pressure_tendency(&sensor, ctx);
+                        // SQLite blocks; keep it off the async workers
+                        let history = Arc::clone(&ctx.sensor_history);
+                        let reading = sensor.clone();
+                        match tokio::task::spawn_blocking(move || history.insert(&reading)).await {
+                            Ok(Ok(())) => {}
+                            Ok(Err(e)) => error!("Failed to store sensor reading: {}", e),
+                            Err(e) => error!("Sensor reading store task failed: {}", e),
+                        }
+                        // Kept for diagnostics only: no alerts, forwarding or UI update
+                        if !sensor.anomalies.is_empty() {
+                            let reasons: Vec<&str> = sensor.anomalies.iter().map(|flag| flag.reason.as_str()).collect();
+                            warn!("Ignoring anomalous sensor reading: {}", reasons.join(", "));
+                            events::publish(AppEvent::AnomalousReading(sensor.clone()));
+                            return;
+                        }
+                        
+                        // Muted devices still update the alert state, so nothing fires the moment the mute ends
+                        let muted = Self::alerts_muted(sensor.device_id.as_deref(), &ctx.device_settings).await;
+                        Self::check_air_quality(&sensor, muted, ctx);
+                        Self::check_alert_rules(&sensor, muted, ctx).await;
+                        if ctx.settings.publish_comfort_metrics {
+                            Self::publish_comfort_metrics(&sensor, ctx);
+                        }
+                        Self::publish_pressure_tendency(&sensor, ctx);
+                        if ctx.settings.local_forecast.publish {
+                            Self::publish_local_forecast(&sensor, ctx).await;
+                        }
+                        sinks::sensor_data(&sensor);
+                        
+                        // Update stored data
+                        sensor_data.send_replace(Some(sensor.clone()));
+                        {
+                            let mut recent = ctx.recent_readings.lock().await;
+                            if recent.len() >= RECENT_READINGS_CAPACITY {
+                                recent.pop_front();
+                            }
+                            recent.push_back(sensor.clone());
+                        }
+                        
+                        // Emit event to frontend
+                        events::publish(AppEvent::SensorDataUpdated(sensor));
+                    }
+                    Err(e) => {
+                        error!("Failed to parse sensor data: {}", e);
+                    }
+                }
+            }
+            "weather/alert_trigger" => {
+                match serde_json::from_slice::<AlertData>(payload) {
+                    Ok(alert_data) => {
+                        info!("Received alert: {}", alert_data.message);
+                        // Echoes of our own alerts aren't recorded or shown again
+                        let is_new = ctx.sensor_history.insert_received_alert(&alert_data).unwrap_or_else(|e| {
+                            error!("Failed to record received alert: {}", e);
+                            true
+                        });
+                        if is_new {
+                            *ctx.latest_alert.lock().await = Some(alert_data.clone());
+                            events::publish(AppEvent::AlertReceived(alert_data.clone()));
+                            ctx.alert_channels.notify_desktop(&alert_data.level, &notifications::alert_title(&alert_data), &alert_data.message);
+                            ctx.alert_channels.dispatch(&alert_data, AlertSource::External, AlertDirection::Received);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to parse alert data: {}", e);
+                    }
+                }
+            }
+            _ => {
+                warn!("Received message on unknown topic: {}", topic);
+            }
+        }
+    }
+
+    // Each connection starts with fresh per-connection state (anomaly baselines, rule
+    // evaluation) around the shared stores
+    fn message_context(&self, client: AsyncClient) -> MessageContext {
+        MessageContext {
+            client,
+            settings: self.settings.clone(),
+            weather_data: Arc::clone(&self.latest_weather_data),
+            sensor_data: Arc::clone(&self.latest_sensor_data),
+            latest_alert: Arc::clone(&self.latest_alert),
+            recent_readings: Arc::clone(&self.recent_readings),
+            sensor_history: Arc::clone(&self.sensor_history),
+            anomaly_detector: Arc::new(std::sync::Mutex::new(AnomalyDetector::default())),
+            smoother: Arc::new(std::sync::Mutex::new(SensorSmoother::default())),
+            pressure_trends: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            local_forecasts: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            devices: Arc::clone(&self.devices),
+            delivery: Arc::clone(&self.delivery),
+            pending_acks: Arc::clone(&self.pending_acks),
+            device_settings: Arc::clone(&self.device_settings),
+            alert_rules: Arc::clone(&self.alert_rules),
+            rule_evaluator: Arc::new(std::sync::Mutex::new(RuleEvaluator::default())),
+            alert_channels: Arc::clone(&self.alert_channels),
+            air_quality_alert_active: Arc::new(AtomicBool::new(false)),
+            weather_api_client: Arc::clone(&self.weather_api_client),
+            active_location: Arc::clone(&self.active_location),
+            coordinator: Arc::clone(&self.coordinator),
+        }
+    }
+
+    // Runs a message from another transport, e.g. Bluetooth, through the same handling as
+    // one from the broker. Without a connection, whatever the handler publishes fails and
+    // is logged, but readings are still stored, checked and shown. Handled on a task of its
+    // own so the commands queued behind it don't wait.
+    async fn ingest(&mut self, topic: String, payload: Vec<u8>) {
+        if self.ingest_ctx.is_none() {
+            info!("Handling device messages without a broker connection");
+            let (client, _eventloop) = AsyncClient::new(MqttOptions::new(&self.settings.client_id, "localhost", 1883), 10);
+            sinks::start_configured(&self.settings);
+            self.ingest_ctx = Some(self.message_context(client));
+        }
+        let Some(ctx) = self.ingest_ctx.clone() else {
+            return;
+        };
+        let sender = self.ingest_tx.get_or_insert_with(|| {
+            let (sender, mut receiver) = mpsc::channel::<(MessageContext, String, Vec<u8>)>(INGEST_CHANNEL_CAPACITY);
+            tokio::spawn(async move {
+                while let Some((ctx, topic, payload)) = receiver.recv().await {
+                    Self::handle_message_static(&topic, &payload, &ctx).await;
+                }
+            });
+            sender
+        });
+        if let Err(mpsc::error::SendError((_, topic, _))) = sender.send((ctx, topic, payload)).await {
+            // The task died; the next message starts a new one
+            warn!("Ingest task stopped, dropped message on {}", topic);
+            self.ingest_tx = None;
+        }
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        info!("Disconnecting from MQTT broker");
+        
+        // Let a publish in flight finish before the connection goes away
+        if let Some(task) = self.weather_publish_task.take() {
+            task.stop().await;
+            self.resume_publishing = true;
+            info!("Automated weather publishing paused until the next connect");
+        }
+        
+        if let Some(task) = self.device_monitor_task.take() {
+            task.stop().await;
+        }
+        
+        if let Some(task) = self.time_sync_task.take() {
+            task.stop().await;
+        }
+        
+        // Stop the cloud uplink
+        if let Some(bridge) = self.uplink.take() {
+            bridge.shutdown().await;
+        }
+        
+        sinks::stop_configured().await;
+        self.ingest_ctx = None;
+        
+        // The event loop sends the disconnect packet, then exits on its own
+        if let Some(client) = self.client.take() {
+            if let Err(e) = client.disconnect().await {
+                warn!("Failed to queue the MQTT disconnect: {}", e);
+            }
+        }
+        if let Some(task) = self.event_loop_task.take() {
+            task.stop().await;
+        }
+        
+        if let Some(tunnel) = self.proxy_tunnel.take() {
+            tunnel.shutdown();
+        }
+        
+        self.connected.store(false, Ordering::SeqCst);
+        self.health.disconnected();
+        self.last_disconnect = Some(std::time::Instant::now());
+        info!("MQTT client disconnected");
+        Ok(())
+    }
+
+    // The event loop and the automated publisher copy the settings when they start, so a
+    // live connection is re-established with the new ones and publishing resumed
+    async fn apply_settings(&mut self, settings: MqttSettings) -> Result<()> {
+        let connected = self.is_connected();
+        let publishing = self.is_auto_publishing();
+        if connected {
+            self.disconnect().await?;
+        }
+        self.settings = settings;
+        if !connected {
+            return Ok(());
+        }
+
+        let (host, port) = (self.settings.broker_host.clone(), self.settings.broker_port);
+        self.connect(&host, port).await?;
+        let location = *self.active_location.lock().await;
+        if let (true, Some((lat, lon))) = (publishing, location) {
+            self.start_automated_weather_publishing(lat, lon).await?;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    #[instrument(name = "mqtt_publish", skip_all, fields(topic = "weather/data", qos = 0, bytes = Empty, outcome = Empty))]
+    async fn publish_weather_data(&self, data: &WeatherData) -> Result<()> {
+        let result = self.publish_weather_payload(data).await;
+        logging::record_outcome(&result);
+        result
+    }
+
+    async fn publish_weather_payload(&self, data: &WeatherData) -> Result<()> {
+        if let Some(client) = &self.client {
+            let mut data = data.clone();
+            apply_icon_map(&mut data, &self.settings.icon_map);
+            let payload = payload_templates::weather_payload(&self.settings.payload_templates, &data)?;
+            tracing::Span::current().record("bytes", payload.len());
+            
+            // Print payload before sending
+            // match serde_json::to_string_pretty(data) {
+            //     Ok(json_str) => {
+            //         println!("Publishing weather data payload:\n{}", json_str);
+            //     }
+            //     Err(e) => {
+            //         warn!("Failed to serialize weather data for printing: {}", e);
+            //         println!("Publishing weather data payload: {:?}", data);
+            //     }
+            // }
+            
+            client.publish("weather/data", QoS::AtMostOnce, self.settings.retain_weather_data, payload).await?;
+            info!("Published weather data to MQTT");
+            // Duplicates of a report the publisher already recorded are ignored
+            if let Err(e) = self.sensor_history.insert_weather(data) {
+                error!("Failed to record weather snapshot: {}", e);
+            }
+            Ok(())
+        } else {
+            Err(anyhow::Error::new(NotConnected))
+        }
+    }
+
+    async fn publish_retained_snapshot(&self) -> Result<()> {
+        let mut data = self.latest_weather_data.borrow().clone()
+            .ok_or_else(|| anyhow!("No weather data available to publish"))?;
+        apply_icon_map(&mut data, &self.settings.icon_map);
+
+        // Always retained, regardless of the retain_weather_data setting
+        let payload = payload_templates::weather_payload(&self.settings.payload_templates, &data)?;
+        self.publish_untracked("weather/data", true, payload).await?;
+        info!("Published retained weather snapshot to MQTT");
+        Ok(())
+    }
+
+    // Returns the delivery tracker's message id, or None for QoS0 routes which aren't tracked
+    async fn send_alert(&self, alert: &AlertData, source: AlertSource) -> Result<Option<u64>> {
+        if self.client.is_some() {
+            let alert = &alert.with_id();
+            let route = self.alert_channels.route(&alert.level);
+            let topic = notifications::route_topic(&route);
+            self.alert_channels.dispatch(alert, source, AlertDirection::Sent);
+            let payload = payload_templates::alert_payload(&self.settings.payload_templates, alert)?;
+            let message_id = match route.qos {
+                AlertQos::AtLeastOnce => {
+                    let message_id = match self.publish_confirmed(topic, route.retain, payload).await {
+                        Ok(message_id) => message_id,
+                        Err(e) => {
+                            if let Err(history_error) = self.sensor_history.insert_sent_alert(alert, source, None, DeliveryStatus::Failed, Some(&e.to_string())) {
+                                error!("Failed to record alert in history: {}", history_error);
+                            }
+                            return Err(e);
+                        }
+                    };
+                    Self::record_sent_alert(&self.sensor_history, &self.delivery, alert, source, Some(message_id));
+                    info!("Published alert to {}: {} (message id {})", topic, alert.message, message_id);
+                    Some(message_id)
+                }
+                AlertQos::AtMostOnce => {
+                    let client = self.client.as_ref().ok_or_else(|| anyhow::Error::new(NotConnected))?;
+                    let result = client.publish(topic, QoS::AtMostOnce, route.retain, payload).await;
+                    Self::record_unconfirmed_alert(&self.sensor_history, alert, source, result.as_ref().err().map(|e| e.to_string()));
+                    result?;
+                    info!("Published alert to {}: {}", topic, alert.message);
+                    None
+                }
+            };
+            
+            // Signal the alert on the device's LED bar and speaker
+            if let Err(e) = self.publish_alert_outputs(&alert.level, route.buzz).await {
+                warn!("Failed to publish alert outputs: {}", e);
+            }
+            // Print payload before sending, in debug builds only
+            logging::print_payload("Publishing alert payload", alert);
+            Ok(message_id)
+        } else {
+            Err(anyhow::Error::new(NotConnected))
+        }
+    }
+
+
+    // Publishes a retained config for the device; the receiver resolves when the device acks
+    async fn push_device_config(&self, device_id: &str, mut config: DeviceConfig) -> Result<(String, oneshot::Receiver<DeviceAck>)> {
+        if config.request_id.is_empty() {
+            config.request_id = uuid::Uuid::new_v4().to_string();
+        }
+        let request_id = config.request_id.clone();
+        let payload = serde_json::to_vec(&config)?;
+
+        let receiver = self.register_ack(&request_id).await;
+        if let Err(e) = self.publish_confirmed(&device_topic(device_id, "config"), true, payload).await {
+            self.pending_acks.lock().await.remove(&request_id);
+            return Err(e);
+        }
+
+        info!("Pushed config {} to device {}", request_id, device_id);
+        Ok((request_id, receiver))
+    }
+
+    // Sends a management command (never retained); the receiver resolves when the device acks
+    async fn send_device_command(&self, device_id: &str, command: &str, params: Option<serde_json::Value>) -> Result<(String, oneshot::Receiver<DeviceAck>)> {
+        let request = DeviceCommand {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            command: command.to_string(),
+            params,
+            timestamp: chrono::Utc::now(),
+        };
+        let payload = serde_json::to_vec(&request)?;
+
+        let receiver = self.register_ack(&request.request_id).await;
+        if let Err(e) = self.publish_confirmed(&device_topic(device_id, "command"), false, payload).await {
+            self.pending_acks.lock().await.remove(&request.request_id);
+            return Err(e);
+        }
+
+        info!("Sent '{}' command {} to device {}", command, request.request_id, device_id);
+        Ok((request.request_id, receiver))
+    }
+
+    async fn register_ack(&self, request_id: &str) -> oneshot::Receiver<DeviceAck> {
+        let (sender, receiver) = oneshot::channel();
+        self.pending_acks.lock().await.insert(request_id.to_string(), sender);
+        receiver
+    }
+
+    // Publishes a test alert on its level's route, including the LED and buzzer, without
+    // recording it or fanning it out to the other channels
+    async fn publish_test_alert(&self, alert: &AlertData) -> Result<Option<u64>> {
+        let client = self.client.as_ref().ok_or_else(|| anyhow::Error::new(NotConnected))?;
+        let alert = &alert.with_id();
+        let route = self.alert_channels.route(&alert.level);
+        let topic = notifications::route_topic(&route);
+        let payload = payload_templates::alert_payload(&self.settings.payload_templates, alert)?;
+        let message_id = match route.qos {
+            AlertQos::AtLeastOnce => Some(self.publish_confirmed(topic, route.retain, payload).await?),
+            AlertQos::AtMostOnce => {
+                client.publish(topic, QoS::AtMostOnce, route.retain, payload).await?;
+                None
+            }
+        };
+        self.publish_alert_outputs(&alert.level, route.buzz).await?;
+        info!("Published test alert to {}", topic);
+        Ok(message_id)
+    }
+
+    async fn publish_alert_outputs(&self, level: &AlertLevel, buzz: bool) -> Result<()> {
+        let client = self.client.as_ref().ok_or_else(|| anyhow::Error::new(NotConnected))?;
+        for (topic, payload) in Self::alert_output_commands(&self.settings.alert_outputs, level, buzz)? {
+            client.publish(topic, QoS::AtMostOnce, false, payload).await?;
+        }
+        Ok(())
+    }
+
+    // LED bar and, when `buzz` is set, speaker commands for an alert at `level`
+    fn alert_output_commands(outputs: &AlertOutputSettings, level: &AlertLevel, buzz: bool) -> Result<Vec<(&'static str, Vec<u8>)>> {
+        let output = outputs.for_level(level);
+        if !output.enabled {
+            return Ok(Vec::new());
+        }
+
+        let led = LedCommand {
+            color: output.led_color.clone(),
+            pattern: output.led_pattern.clone(),
+            interval_ms: output.blink_interval_ms,
+            duration_secs: output.led_duration_secs,
+        };
+        debug!("LED command for {:?} alert: {:?}", level, led);
+        let mut commands = vec![(LED_TOPIC, serde_json::to_vec(&led)?)];
+
+        if buzz && output.tone_hz > 0 && output.tone_duration_ms > 0 {
+            let speaker = SpeakerCommand {
+                frequency_hz: output.tone_hz,
+                duration_ms: output.tone_duration_ms,
+                repeat: output.tone_repeat.max(1),
+            };
+            debug!("Speaker command for {:?} alert: {:?}", level, speaker);
+            commands.push((SPEAKER_TOPIC, serde_json::to_vec(&speaker)?));
+        }
+
+        Ok(commands)
+    }
+
+    // Top-level fields that differ from the previous snapshot, ignoring the timestamp
+    fn weather_delta(previous: &serde_json::Value, current: &serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        let mut delta = serde_json::Map::new();
+        let Some(current) = current.as_object() else {
+            return delta;
+        };
+
+        for (key, value) in current {
+            if key != "timestamp" && previous.get(key) != Some(value) {
+                delta.insert(key.clone(), value.clone());
+            }
+        }
+
+        if !delta.is_empty() {
+            if let Some(timestamp) = current.get("timestamp") {
+                delta.insert("timestamp".to_string(), timestamp.clone());
+            }
+        }
+        delta
+    }
+
+    // Publishes each active warning/emergency alert from the provider once
+    async fn forward_weather_alerts(
+        client: &AsyncClient,
+        delivery: &Arc<std::sync::Mutex<DeliveryTracker>>,
+        history: &SensorHistory,
+        alert_channels: &AlertChannels,
+        templates: &PayloadTemplateSettings,
+        alerts: &[WeatherAlert],
+        forwarded: &mut HashSet<String>,
+    ) {
+        let now = chrono::Utc::now();
+        for weather_alert in alerts.iter().filter(|a| a.severity != AlertLevel::Info && a.is_active(now)) {
+            let key = weather_alert.id();
+            if forwarded.contains(&key) {
+                continue;
+            }
+
+            let alert = AlertData {
+                message: format!("{} until {}", weather_alert.event, weather_alert.end.with_timezone(&chrono::Local).format("%d/%m %H:%M")),
+                level: weather_alert.severity.clone(),
+                timestamp: now,
+                id: None,
+            }.with_id();
+            let payload = match payload_templates::alert_payload(templates, &alert) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    error!("Failed to serialize weather alert: {}", e);
+                    continue;
+                }
+            };
+
+            let route = alert_channels.route(&alert.level);
+            let topic = notifications::route_topic(&route);
+            alert_channels.dispatch(&alert, AlertSource::WeatherProvider, AlertDirection::Sent);
+            if route.qos == AlertQos::AtMostOnce {
+                let result = client.publish(topic, QoS::AtMostOnce, route.retain, payload).await;
+                Self::record_unconfirmed_alert(history, &alert, AlertSource::WeatherProvider, result.as_ref().err().map(|e| e.to_string()));
+                match result {
+                    Ok(_) => {
+                        info!("Forwarded weather alert: {} ({})", weather_alert.event, weather_alert.sender);
+                        forwarded.insert(key);
+                        events::publish(AppEvent::WeatherAlert(weather_alert.clone()));
+                    }
+                    Err(e) => error!("Failed to forward weather alert: {}", e),
+                }
+                continue;
+            }
+
+            match Self::send_qos1(client, delivery, topic, route.retain, payload, true) {
+                Ok(message_id) => {
+                    Self::record_sent_alert(history, delivery, &alert, AlertSource::WeatherProvider, message_id);
+                    info!("Forwarded weather alert: {} ({})", weather_alert.event, weather_alert.sender);
+                    forwarded.insert(key);
+                    events::publish(AppEvent::WeatherAlert(weather_alert.clone()));
+                }
+                Err((e, record)) => {
+                    error!("Failed to forward weather alert: {}", e);
+                    Self::record_unconfirmed_alert(history, &alert, AlertSource::WeatherProvider, Some(e.to_string()));
+                    if let Some(record) = record {
+                        events::publish(AppEvent::PublishFailed(DeliveryEvent::from(&record)));
+                    }
+                }
+            }
+        }
+
+        // Forget alerts that are no longer reported so the set doesn't grow forever
+        forwarded.retain(|key| alerts.iter().any(|a| a.id() == *key));
+    }
+
+    // Splits WeatherData into (topic, value) pairs: weather/current/<field>,
+    // weather/forecast/<day>/<field> and weather/history/<day>/<field>
+    fn flatten_weather(snapshot: &serde_json::Value) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        let Some(object) = snapshot.as_object() else {
+            return fields;
+        };
+
+        for (key, value) in object {
+            match value {
+                serde_json::Value::Array(days) => {
+                    for (index, day) in days.iter().enumerate() {
+                        for (field, field_value) in day.as_object().into_iter().flatten() {
+                            fields.push((format!("weather/{}/{}/{}", key, index, field), Self::flat_value(field_value)));
+                        }
+                    }
+                }
+                _ => fields.push((format!("weather/current/{}", key), Self::flat_value(value))),
+            }
+        }
+        fields
+    }
+
+    fn flat_value(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    async fn start_automated_weather_publishing(&mut self, lat: f64, lon: f64) -> Result<()> {
+        if self.weather_publish_task.is_some() {
+            info!("Automated weather publishing is already running");
+            return Ok(());
+        }
+
+        if !self.is_connected() {
+            return Err(anyhow::Error::new(NotConnected));
+        }
+
+        let client = self.client.as_ref().ok_or_else(|| anyhow!("MQTT client not available"))?.clone();
+        let weather_api_client = Arc::clone(&self.weather_api_client);
+        
+        let configured_interval_secs = self.settings.publish_interval_secs.max(1);
+        let interval_override = Arc::clone(&self.interval_override);
+        let battery_saver = self.settings.battery_saver.clone();
+        let devices = Arc::clone(&self.devices);
+        
+        info!("Starting automated weather publishing every {} seconds for coordinates: {}, {}", configured_interval_secs, lat, lon);
+        
+        let weather_data_arc = Arc::clone(&self.latest_weather_data);
+        let sensor_history = Arc::clone(&self.sensor_history);
+        let retain = self.settings.retain_weather_data;
+        let delta_publishing = self.settings.delta_publishing;
+        let flat_topics = self.settings.flat_topics;
+        let forward_weather_alerts = self.settings.forward_weather_alerts;
+        let icon_map = self.settings.icon_map.clone();
+        let templates = self.settings.payload_templates.clone();
+        let delivery = Arc::clone(&self.delivery);
+        let alert_channels = Arc::clone(&self.alert_channels);
+        let full_snapshot_interval = chrono::Duration::minutes(self.settings.full_snapshot_interval_minutes.max(1) as i64);
+        let active_location = Arc::clone(&self.active_location);
+        *active_location.lock().await = Some((lat, lon));
+        let coordination = self.settings.coordination.clone();
+        let instance_id = self.settings.client_id.clone();
+        let coordinator = Arc::clone(&self.coordinator);
+        
+        let task = StoppableTask::spawn("weather publishing", move |cancel| {
+            let client = client.clone();
+            let weather_api_client = Arc::clone(&weather_api_client);
+            let battery_saver = battery_saver.clone();
+            let devices = Arc::clone(&devices);
+            let weather_data_arc = Arc::clone(&weather_data_arc);
+            let sensor_history = Arc::clone(&sensor_history);
+            let icon_map = icon_map.clone();
+            let templates = templates.clone();
+            let interval_override = Arc::clone(&interval_override);
+            let delivery = Arc::clone(&delivery);
+            let alert_channels = Arc::clone(&alert_channels);
+            let active_location = Arc::clone(&active_location);
+            let coordination = coordination.clone();
+            let instance_id = instance_id.clone();
+            let coordinator = Arc::clone(&coordinator);
+            tokio::spawn(async move {
+                // Ensure we have cached data for today
+                info!("Ensuring daily weather cache is available...");
+                // Keep the task alive on failure; the loop retries while the cache is missing
+                if let Err(e) = weather_api_client.ensure_daily_cache(lat, lon).await {
+                    error!("Failed to ensure daily cache: {}", e);
+                    app_error::report(AppErrorCode::CacheRefreshFailed, "weather_api", format!("Weather refresh failed: {}", e), true);
+                }
+
+                let mut saving_power = false;
+                let mut last_published: Option<serde_json::Value> = None;
+                let mut last_full_snapshot: Option<chrono::DateTime<chrono::Utc>> = None;
+                let mut last_flat_fields: HashMap<String, String> = HashMap::new();
+                let mut forwarded_alerts: HashSet<String> = HashSet::new();
+                let mut last_recorded: Option<chrono::DateTime<chrono::Utc>> = None;
+                let mut standing_by = false;
+            
+                loop {
+                    let normal_interval_secs = match interval_override.load(Ordering::SeqCst) {
+                        0 => configured_interval_secs,
+                        secs => secs,
+                    };
+                    // Slow down while the device is running on a low battery
+                    let low_battery = battery_saver.enabled
+                        && devices.lock().await.any_low_battery(battery_saver.battery_threshold_percent);
+                    if low_battery != saving_power {
+                        saving_power = low_battery;
+                        if saving_power {
+                            info!("Device battery low, publishing every {} seconds", battery_saver.publish_interval_secs);
+                        } else {
+                            info!("Device battery recovered, publishing every {} seconds", normal_interval_secs);
+                        }
+                    }
+                
+                    let publish_interval_secs = if saving_power {
+                        battery_saver.publish_interval_secs.max(normal_interval_secs)
+                    } else {
+                        normal_interval_secs
+                    };
+                    tokio::select! {
+                        _ = cancel.cancelled() => break,
+                        _ = tokio::time::sleep(Duration::from_secs(publish_interval_secs)) => {}
+                    }
+                
+                    // Another desktop on the same broker may be the one publishing
+                    if !coordinator.should_publish(&coordination, &instance_id) {
+                        if !standing_by {
+                            standing_by = true;
+                            if coordination.role == InstanceRole::Viewer {
+                                info!("Viewer instance, not publishing weather");
+                            } else {
+                                info!("Another instance is publishing weather, standing by");
+                            }
+                        }
+                        continue;
+                    }
+                    if std::mem::take(&mut standing_by) {
+                        info!("Taking over weather publishing");
+                    }
+                    if coordination.enabled {
+                        Self::renew_claim(&client, &coordinator, &coordination, &instance_id, publish_interval_secs).await;
+                    }
+                
+                    // The device may have moved since the last tick
+                    let (lat, lon) = active_location.lock().await.unwrap_or((lat, lon));
+                
+                    // Read from cache file only - never call API
+                    match weather_api_client.read_cached_weather_only(lat, lon).await {
+                        Ok(Some(mut weather_data)) => {
+                            // Store the weather data in memory
+                            weather_data_arc.send_replace(Some(weather_data.clone()));
+                        
+                            // Record each fetched report once, not every publish tick
+                            if last_recorded != Some(weather_data.timestamp) {
+                                sinks::weather_data(&weather_data);
+                                match sensor_history.insert_weather(&weather_data) {
+                                    Ok(_) => last_recorded = Some(weather_data.timestamp),
+                                    Err(e) => error!("Failed to record weather snapshot: {}", e),
+                                }
+                            }
+                        
+                            if forward_weather_alerts {
+                                Self::forward_weather_alerts(&client, &delivery, &sensor_history, &alert_channels, &templates, &weather_data.alerts, &mut forwarded_alerts).await;
+                            }
+                        
+                            if saving_power && battery_saver.reduce_payload {
+                                weather_data.history.clear();
+                                weather_data.hourly.clear();
+                                weather_data.forecast.truncate(battery_saver.reduced_forecast_days);
+                            }
+                        
+                            apply_icon_map(&mut weather_data, &icon_map);
+                        
+                            // Print payload before sending, in debug builds only
+                            logging::print_payload("Publishing cached weather data from file", &weather_data);
+                        
+                            let snapshot = match serde_json::to_value(&weather_data) {
+                                Ok(value) => value,
+                                Err(e) => {
+                                    error!("Failed to serialize weather data: {}", e);
+                                    continue;
+                                }
+                            };
+                        
+                            // In delta mode only send what changed, with a full snapshot every so often
+                            let full_snapshot_due = last_full_snapshot
+                                .map_or(true, |at| chrono::Utc::now() - at >= full_snapshot_interval);
+                        
+                            if flat_topics {
+                                // Retained per-field topics only need republishing when the value changes
+                                let fields = Self::flatten_weather(&snapshot);
+                                let mut published = 0;
+                                for (topic, value) in &fields {
+                                    if !full_snapshot_due && last_flat_fields.get(topic) == Some(value) {
+                                        continue;
+                                    }
+                                    match client.publish(topic.as_str(), QoS::AtMostOnce, true, value.clone()).await {
+                                        Ok(_) => published += 1,
+                                        Err(e) => error!("Failed to publish {}: {}", topic, e),
+                                    }
+                                }
+                                if full_snapshot_due {
+                                    last_full_snapshot = Some(chrono::Utc::now());
+                                }
+                                last_flat_fields = fields.into_iter().collect();
+                            
+                                if published > 0 {
+                                    info!("Published {} flat weather topics", published);
+                                    events::publish(AppEvent::WeatherDataUpdated(weather_data));
+                                }
+                                continue;
+                            }
+                            let (topic, payload, message_retain) = match (&last_published, delta_publishing && !full_snapshot_due) {
+                                (Some(previous), true) => {
+                                    let delta = Self::weather_delta(previous, &snapshot);
+                                    if delta.is_empty() {
+                                        debug!("Weather data unchanged, skipping publish");
+                                        continue;
+                                    }
+                                    (WEATHER_DELTA_TOPIC, serde_json::Value::Object(delta), false)
+                                }
+                                _ => {
+                                    last_full_snapshot = Some(chrono::Utc::now());
+                                    ("weather/data", snapshot.clone(), retain)
+                                }
+                            };
+                            last_published = Some(snapshot);
+                        
+                            // Templates only reshape full snapshots
+                            let encoded = if topic == WEATHER_DELTA_TOPIC {
+                                serde_json::to_vec(&payload).map_err(anyhow::Error::from)
+                            } else {
+                                payload_templates::weather_payload(&templates, &weather_data)
+                            };
+                        
+                            // Publish to MQTT
+                            match encoded {
+                                Ok(payload) => {
+                                    match client.publish(topic, QoS::AtMostOnce, message_retain, payload).await {
+                                        Ok(_) => {
+                                            info!("Published weather data from cache file to {}", topic);
+                                        
+                                            // Emit event to frontend
+                                            events::publish(AppEvent::WeatherDataUpdated(weather_data));
+                                        },
+                                        Err(e) => {
+                                            error!("Failed to publish weather data: {}", e);
+                                            app_error::report(AppErrorCode::PublishFailed, "mqtt", format!("Publishing weather data failed: {}", e), true);
+                                        }
+                                    }
+                                }
+                                Err(e) => error!("Failed to serialize weather data: {}", e),
+                            }
+                        }
+                        Ok(None) => {
+                            warn!("No cached weather data available - cache may have expired");
+                        
+                            // Try to refresh cache once
+                            info!("Attempting to refresh weather cache...");
+                            if let Err(e) = weather_api_client.ensure_daily_cache(lat, lon).await {
+                                error!("Failed to refresh cache: {}", e);
+                                app_error::report(AppErrorCode::CacheRefreshFailed, "weather_api", format!("Weather refresh failed: {}", e), true);
+                            } else {
+                                info!("Cache refreshed successfully");
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to read cached weather data: {}", e);
+                        }
+                    }
+                }
+
+                // Hands over straight away instead of letting the lease run out
+                if coordination.enabled && coordinator.holds_claim(&instance_id) {
+                    if let Err(e) = client.publish(coordination.topic.as_str(), QoS::AtMostOnce, true, Vec::new()).await {
+                        error!("Failed to release the publisher claim: {}", e);
+                    }
+                    coordinator.record(&[]);
+                }
+            })
+        });
+        
+        self.weather_publish_task = Some(task);
+        info!("Automated weather publishing started");
+        Ok(())
+    }
+
+    // Retained, so an instance that connects later learns straight away who publishes.
+    // QoS0 keeps it out of delivery tracking; it's renewed every interval anyway.
+    async fn renew_claim(
+        client: &AsyncClient,
+        coordinator: &Coordinator,
+        settings: &CoordinationSettings,
+        instance_id: &str,
+        publish_interval_secs: u64,
+    ) {
+        let claim = PublisherClaim::new(instance_id, settings.role, publish_interval_secs);
+        match serde_json::to_vec(&claim) {
+            Ok(payload) => {
+                if let Err(e) = client.publish(settings.topic.as_str(), QoS::AtMostOnce, true, payload).await {
+                    error!("Failed to publish the publisher claim: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize the publisher claim: {}", e),
+        }
+        coordinator.claim(claim);
+    }
+
+    // Points the automated publisher at new coordinates from the next tick on
+    async fn set_active_location(&self, lat: f64, lon: f64) {
+        *self.active_location.lock().await = Some((lat, lon));
+        info!("Active weather location set to {}, {}", lat, lon);
+    }
+
+    // Restarts a running publisher at the new coordinates so it fetches their weather
+    // straight away instead of on the next tick
+    async fn change_location(&mut self, lat: f64, lon: f64) -> Result<()> {
+        if !self.is_auto_publishing() {
+            self.set_active_location(lat, lon).await;
+            return Ok(());
+        }
+        self.stop_automated_weather_publishing().await?;
+        self.start_automated_weather_publishing(lat, lon).await
+    }
+
+    async fn resume_publishing(&mut self) {
+        if !std::mem::take(&mut self.resume_publishing) {
+            return;
+        }
+        let Some((lat, lon)) = *self.active_location.lock().await else {
+            return;
+        };
+        match self.start_automated_weather_publishing(lat, lon).await {
+            Ok(_) => info!("Resumed automated weather publishing after reconnect"),
+            Err(e) => error!("Failed to resume automated weather publishing: {}", e),
+        }
+    }
+
+    async fn stop_automated_weather_publishing(&mut self) -> Result<()> {
+        // Stopping while disconnected cancels the pending resume
+        self.resume_publishing = false;
+        if let Some(task) = self.weather_publish_task.take() {
+            task.stop().await;
+            info!("Automated weather publishing stopped");
+            Ok(())
+        } else {
+            info!("Automated weather publishing was not running");
+            Ok(())
+        }
+    }
+
+    fn is_auto_publishing(&self) -> bool {
+        self.weather_publish_task.is_some()
+    }
+}
+
+// Work that needs the connection, handled one at a time on the manager's own task
+enum Command {
+    Connect { settings: MqttSettings, host: String, port: u16, reply: oneshot::Sender<Result<()>> },
+    Disconnect { reply: oneshot::Sender<Result<()>> },
+    ApplySettings { settings: MqttSettings, reply: oneshot::Sender<Result<()>> },
+    PublishWeatherData { data: Box<WeatherData>, reply: oneshot::Sender<Result<()>> },
+    PublishRetainedSnapshot { reply: oneshot::Sender<Result<()>> },
+    Publish { topic: String, payload: Vec<u8>, retain: bool, reply: oneshot::Sender<Result<()>> },
+    SendAlert { alert: AlertData, source: AlertSource, reply: oneshot::Sender<Result<Option<u64>>> },
+    PublishTestAlert { alert: AlertData, reply: oneshot::Sender<Result<Option<u64>>> },
+    PushDeviceConfig { device_id: String, config: DeviceConfig, reply: oneshot::Sender<Result<PendingAck>> },
+    SendDeviceCommand { device_id: String, command: String, params: Option<serde_json::Value>, reply: oneshot::Sender<Result<PendingAck>> },
+    SyncDeviceTime { reply: oneshot::Sender<Result<TimeSync>> },
+    StartPublishing { lat: f64, lon: f64, reply: oneshot::Sender<Result<()>> },
+    StopPublishing { reply: oneshot::Sender<Result<()>> },
+    ChangeLocation { lat: f64, lon: f64, reply: oneshot::Sender<Result<()>> },
+    Ingest { topic: String, payload: Vec<u8> },
+}
+
+impl Command {
+    // Span name for the trace of the command
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Connect { .. } => "connect",
+            Self::Disconnect { .. } => "disconnect",
+            Self::ApplySettings { .. } => "apply_settings",
+            Self::PublishWeatherData { .. } => "publish_weather_data",
+            Self::PublishRetainedSnapshot { .. } => "publish_retained_snapshot",
+            Self::Publish { .. } => "publish",
+            Self::SendAlert { .. } => "send_alert",
+            Self::PublishTestAlert { .. } => "publish_test_alert",
+            Self::PushDeviceConfig { .. } => "push_device_config",
+            Self::SendDeviceCommand { .. } => "send_device_command",
+            Self::SyncDeviceTime { .. } => "sync_device_time",
+            Self::StartPublishing { .. } => "start_publishing",
+            Self::StopPublishing { .. } => "stop_publishing",
+            Self::ChangeLocation { .. } => "change_location",
+            Self::Ingest { .. } => "ingest",
+        }
+    }
+}
+
+// Request id and the receiver its acknowledgement arrives on
+type PendingAck = (String, oneshot::Receiver<DeviceAck>);
+
+impl MqttManager {
+    // Moves the manager onto its own task. Reads go straight to the shared state through
+    // the handle, so a slow connect only holds up other commands, not the dashboard.
+    pub fn spawn(mut self) -> MqttHandle {
+        let (commands, mut receiver) = mpsc::channel(COMMAND_CHANNEL_CAPACITY);
+        let handle = MqttHandle {
+            commands,
+            settings: Arc::new(std::sync::RwLock::new(self.settings.clone())),
+            publishing: Arc::clone(&self.publishing),
+            interval_override: Arc::clone(&self.interval_override),
+            connected: Arc::clone(&self.connected),
+            health: Arc::clone(&self.health),
+            latest_weather_data: Arc::clone(&self.latest_weather_data),
+            latest_sensor_data: Arc::clone(&self.latest_sensor_data),
+            latest_alert: Arc::clone(&self.latest_alert),
+            recent_readings: Arc::clone(&self.recent_readings),
+            sensor_history: Arc::clone(&self.sensor_history),
+            delivery: Arc::clone(&self.delivery),
+            devices: Arc::clone(&self.devices),
+            pending_acks: Arc::clone(&self.pending_acks),
+            device_settings: Arc::clone(&self.device_settings),
+            alert_rules: Arc::clone(&self.alert_rules),
+            alert_channels: Arc::clone(&self.alert_channels),
+            active_location: Arc::clone(&self.active_location),
+            coordinator: Arc::clone(&self.coordinator),
+        };
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                let span = tracing::info_span!("mqtt_command", command = command.name());
+                self.handle_command(command).instrument(span).await;
+                self.publishing.store(self.is_auto_publishing(), Ordering::SeqCst);
+            }
+            info!("MQTT manager stopped");
+        });
+        handle
+    }
+
+    // Send errors only mean the caller stopped waiting for the reply
+    async fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Connect { settings, host, port, reply } => {
+                self.settings = settings;
+                let _ = reply.send(self.connect(&host, port).await);
+            }
+            Command::Disconnect { reply } => {
+                let _ = reply.send(self.disconnect().await);
+            }
+            Command::ApplySettings { settings, reply } => {
+                let _ = reply.send(self.apply_settings(settings).await);
+            }
+            Command::PublishWeatherData { data, reply } => {
+                let _ = reply.send(self.publish_weather_data(&data).await);
+            }
+            Command::PublishRetainedSnapshot { reply } => {
+                let _ = reply.send(self.publish_retained_snapshot().await);
+            }
+            Command::Publish { topic, payload, retain, reply } => {
+                let _ = reply.send(self.publish_untracked(&topic, retain, payload).await);
+            }
+            Command::SendAlert { alert, source, reply } => {
+                let _ = reply.send(self.send_alert(&alert, source).await);
+            }
+            Command::PublishTestAlert { alert, reply } => {
+                let _ = reply.send(self.publish_test_alert(&alert).await);
+            }
+            Command::PushDeviceConfig { device_id, config, reply } => {
+                let _ = reply.send(self.push_device_config(&device_id, config).await);
+            }
+            Command::SendDeviceCommand { device_id, command, params, reply } => {
+                let _ = reply.send(self.send_device_command(&device_id, &command, params).await);
+            }
+            Command::SyncDeviceTime { reply } => {
+                let _ = reply.send(self.sync_device_time().await);
+            }
+            Command::StartPublishing { lat, lon, reply } => {
+                let _ = reply.send(self.start_automated_weather_publishing(lat, lon).await);
+            }
+            Command::StopPublishing { reply } => {
+                let _ = reply.send(self.stop_automated_weather_publishing().await);
+            }
+            Command::ChangeLocation { lat, lon, reply } => {
+                let _ = reply.send(self.change_location(lat, lon).await);
+            }
+            Command::Ingest { topic, payload } => {
+                self.ingest(topic, payload).await;
+            }
+        }
+    }
+}
+
+// Cheap to clone; every clone talks to the same manager task
+#[derive(Clone)]
+pub struct MqttHandle {
+    commands: mpsc::Sender<Command>,
+    // Copy of the manager's settings for the reads that depend on them
+    settings: Arc<std::sync::RwLock<MqttSettings>>,
+    publishing: Arc<AtomicBool>,
+    interval_override: Arc<AtomicU64>,
+    connected: Arc<AtomicBool>,
+    health: Arc<ConnectionHealth>,
+    latest_weather_data: Latest<WeatherData>,
+    latest_sensor_data: Latest<SensorData>,
+    latest_alert: Arc<Mutex<Option<AlertData>>>,
+    recent_readings: Arc<Mutex<VecDeque<SensorData>>>,
+    sensor_history: Arc<SensorHistory>,
+    delivery: Arc<std::sync::Mutex<DeliveryTracker>>,
+    devices: Arc<Mutex<DeviceRegistry>>,
+    pending_acks: PendingAcks,
+    device_settings: SharedDeviceSettings,
+    alert_rules: SharedAlertRules,
+    alert_channels: Arc<AlertChannels>,
+    active_location: ActiveLocation,
+    coordinator: Arc<Coordinator>,
+}
+
+impl MqttHandle {
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<Result<T>>) -> Command) -> Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(command(reply)).await
+            .map_err(|_| anyhow!("MQTT manager has stopped"))?;
+        response.await.map_err(|_| anyhow!("MQTT manager has stopped"))?
+    }
+
+    pub async fn connect(&self, settings: MqttSettings, host: &str, port: u16) -> Result<()> {
+        *self.settings.write().unwrap() = settings.clone();
+        let host = host.to_string();
+        self.request(|reply| Command::Connect { settings, host, port, reply }).await
+    }
+
+    pub async fn disconnect(&self) -> Result<()> {
+        self.request(|reply| Command::Disconnect { reply }).await
+    }
+
+    pub async fn apply_settings(&self, settings: MqttSettings) -> Result<()> {
+        *self.settings.write().unwrap() = settings.clone();
+        self.request(|reply| Command::ApplySettings { settings, reply }).await
+    }
+
+    pub async fn publish_weather_data(&self, data: &WeatherData) -> Result<()> {
+        let data = Box::new(data.clone());
+        self.request(|reply| Command::PublishWeatherData { data, reply }).await
+    }
+
+    pub async fn publish_retained_snapshot(&self) -> Result<()> {
+        self.request(|reply| Command::PublishRetainedSnapshot { reply }).await
+    }
+
+    // Hands a message received over another transport to the usual handling, as if it
+    // arrived on `topic`
+    pub async fn ingest(&self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        self.commands.send(Command::Ingest { topic: topic.to_string(), payload }).await
+            .map_err(|_| anyhow!("MQTT manager has stopped"))
+    }
+
+    // QoS1 to any topic, for scripts, the simulator and the scheduler; not delivery-tracked
+    pub async fn publish(&self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        let topic = topic.to_string();
+        self.request(|reply| Command::Publish { topic, payload, retain: false, reply }).await
+    }
+
+    pub async fn publish_retained(&self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        let topic = topic.to_string();
+        self.request(|reply| Command::Publish { topic, payload, retain: true, reply }).await
+    }
+
+    pub async fn send_alert(&self, alert: &AlertData, source: AlertSource) -> Result<Option<u64>> {
+        let alert = alert.clone();
+        self.request(|reply| Command::SendAlert { alert, source, reply }).await
+    }
+
+    pub async fn publish_test_alert(&self, alert: &AlertData) -> Result<Option<u64>> {
+        let alert = alert.clone();
+        self.request(|reply| Command::PublishTestAlert { alert, reply }).await
+    }
+
+    pub async fn push_device_config(&self, device_id: &str, config: DeviceConfig) -> Result<PendingAck> {
+        let device_id = device_id.to_string();
+        self.request(|reply| Command::PushDeviceConfig { device_id, config, reply }).await
+    }
+
+    pub async fn send_device_command(&self, device_id: &str, command: &str, params: Option<serde_json::Value>) -> Result<PendingAck> {
+        let (device_id, command) = (device_id.to_string(), command.to_string());
+        self.request(|reply| Command::SendDeviceCommand { device_id, command, params, reply }).await
+    }
+
+    pub async fn sync_device_time(&self) -> Result<TimeSync> {
+        self.request(|reply| Command::SyncDeviceTime { reply }).await
+    }
+
+    pub async fn start_automated_weather_publishing(&self, lat: f64, lon: f64) -> Result<()> {
+        self.request(|reply| Command::StartPublishing { lat, lon, reply }).await
+    }
+
+    pub async fn stop_automated_weather_publishing(&self) -> Result<()> {
+        self.request(|reply| Command::StopPublishing { reply }).await
+    }
+
+    pub async fn change_location(&self, lat: f64, lon: f64) -> Result<()> {
+        self.request(|reply| Command::ChangeLocation { lat, lon, reply }).await
+    }
+
+    // Live connection only: false while reconnecting, after the event loop died, or
+    // when the broker has gone quiet
+    pub fn is_connected(&self) -> bool {
+        self.status().connected
+    }
+
+    pub fn status(&self) -> MqttStatus {
+        self.health.status(self.connected.load(Ordering::SeqCst))
+    }
+
+    pub fn is_auto_publishing(&self) -> bool {
+        self.publishing.load(Ordering::SeqCst)
+    }
+
+    pub fn coordination_status(&self) -> CoordinationStatus {
+        let settings = self.settings.read().unwrap();
+        self.coordinator.status(&settings.coordination, &settings.client_id, self.is_auto_publishing())
+    }
+
+    // Applies from the next publish; None goes back to the configured interval
+    pub fn set_publish_interval_override(&self, secs: Option<u64>) {
+        let previous = self.interval_override.swap(secs.unwrap_or(0), Ordering::SeqCst);
+        if previous != secs.unwrap_or(0) {
+            match secs {
+                Some(secs) => info!("Publishing every {} seconds until further notice", secs),
+                None => info!("Publishing at the configured interval again"),
+            }
+        }
+    }
+
+    // Takes effect immediately, including for the running event loop
+    pub async fn set_device_settings(&self, device_settings: HashMap<String, DeviceSettings>) {
+        *self.device_settings.write().await = device_settings;
+    }
+
+    // Takes effect from the next sensor reading
+    pub async fn set_alert_rules(&self, rules: Vec<AlertRule>) {
+        *self.alert_rules.write().await = rules;
+    }
+
+    pub fn alert_channels(&self) -> Arc<AlertChannels> {
+        Arc::clone(&self.alert_channels)
+    }
+
+    pub fn sensor_history(&self) -> Arc<SensorHistory> {
+        Arc::clone(&self.sensor_history)
+    }
+
+    pub fn get_delivery_status(&self, message_id: Option<u64>) -> Vec<DeliveryRecord> {
+        let tracker = self.delivery.lock().unwrap();
+        match message_id {
+            Some(id) => tracker.get(id).into_iter().collect(),
+            None => tracker.records(),
+        }
+    }
+
+    pub async fn cancel_ack(&self, request_id: &str) {
+        self.pending_acks.lock().await.remove(request_id);
+    }
+
+    pub async fn get_devices(&self) -> Vec<DeviceInfo> {
+        let devices = self.devices.lock().await.list();
+        let mut enriched = Vec::with_capacity(devices.len());
+        for device in devices {
+            enriched.push(MqttManager::with_metadata(device, &self.device_settings).await);
+        }
+        enriched
+    }
+
+    // Zambretti forecast from the latest reading; None until a reading has arrived
+    pub async fn local_forecast(&self) -> Option<LocalForecast> {
+        let sensor = self.latest_sensor_data.borrow().clone()?;
+        let station_altitude_m = self.settings.read().unwrap().local_forecast.station_altitude_m;
+        Some(MqttManager::build_local_forecast(&sensor, &self.latest_weather_data, &self.active_location, station_altitude_m).await)
+    }
+
+    // Loads the last stored weather report and sensor reading so the dashboard isn't empty
+    // until the next MQTT message; readings keep their received_at and show as stale
+    pub async fn restore_last_known(&self) {
+        match self.sensor_history.latest_weather() {
+            Ok(Some(weather)) => {
+                info!("Restored weather data from {}", weather.timestamp);
+                self.latest_weather_data.send_if_modified(|data| restore(data, weather));
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to restore last weather data: {}", e),
+        }
+        match self.sensor_history.latest_reading() {
+            Ok(Some(sensor)) => {
+                info!("Restored sensor reading from {:?}", sensor.received_at);
+                self.latest_sensor_data.send_if_modified(|data| restore(data, sensor));
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to restore last sensor reading: {}", e),
+        }
+    }
+
+    pub fn get_latest_weather_data(&self) -> Option<WeatherData> {
+        self.latest_weather_data.borrow().clone()
+    }
+
+    // Newest received alert, from the alert history when none arrived since startup
+    pub async fn get_latest_alert(&self) -> Result<Option<AlertData>> {
+        if let Some(alert) = self.latest_alert.lock().await.clone() {
+            return Ok(Some(alert));
+        }
+        let filter = AlertHistoryFilter {
+            direction: Some(AlertDirection::Received),
+            ..Default::default()
+        };
+        let page = self.sensor_history.query_alerts(&filter, 1, 0)?;
+        Ok(page.alerts.into_iter().next().map(|record| AlertData {
+            message: record.message,
+            level: record.level,
+            timestamp: record.alert_timestamp,
+            id: record.alert_id,
+        }))
+    }
+
+    pub fn get_latest_sensor_data(&self) -> Option<SensorData> {
+        let data = self.latest_sensor_data.borrow().clone();
+        data.map(|mut sensor| {
+            if let Some(received_at) = sensor.received_at {
+                let age_secs = (chrono::Utc::now() - received_at).num_seconds();
+                sensor.age_secs = Some(age_secs);
+                let stale_after_minutes = self.settings.read().unwrap().sensor_stale_after_minutes;
+                sensor.stale = stale_after_minutes > 0 && age_secs > stale_after_minutes as i64 * 60;
+            }
+            sensor
+        })
+    }
+
+    // Readings received in the last `minutes`, oldest first, without touching the database
+    pub async fn get_recent_sensor_data(&self, minutes: u32, device_id: Option<&str>) -> Vec<SensorData> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::minutes(i64::from(minutes));
+        self.recent_readings.lock().await.iter()
+            .filter(|sensor| sensor.received_at.map_or(false, |at| at >= cutoff))
+            .filter(|sensor| device_id.map_or(true, |id| sensor.device_id.as_deref() == Some(id)))
+            .cloned()
+            .collect()
+    }
+}
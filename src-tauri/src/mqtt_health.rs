@@ -0,0 +1,116 @@
+use crate::supervisor::{self, TaskState};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Name the event loop is supervised under
+pub const EVENT_LOOP_TASK: &str = "mqtt event loop";
+// rumqttc pings after a keep-alive interval of silence, so a live connection hears
+// from the broker at least this often
+const STALE_AFTER_KEEP_ALIVES: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttState {
+    Connected,
+    // Lost the broker and the event loop is retrying
+    Reconnecting,
+    // Connected as far as we know, but nothing from the broker for too long
+    Stale,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MqttStatus {
+    // Only true when the state is connected
+    pub connected: bool,
+    pub state: MqttState,
+    pub broker: Option<String>,
+    pub connected_since: Option<DateTime<Utc>>,
+    // Last packet in either direction, including keep-alive pings
+    pub last_activity: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct Health {
+    broker: Option<String>,
+    keep_alive: Duration,
+    connected_since: Option<DateTime<Utc>>,
+    last_activity: Option<(Instant, DateTime<Utc>)>,
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+    retrying: bool,
+}
+
+// Written by the event loop, read by get_mqtt_status and the status bar
+#[derive(Default)]
+pub struct ConnectionHealth {
+    health: Mutex<Health>,
+}
+
+impl ConnectionHealth {
+    pub fn connected(&self, broker: String, keep_alive: Duration) {
+        let mut health = self.health.lock().unwrap();
+        health.broker = Some(broker);
+        health.keep_alive = keep_alive;
+        health.retrying = false;
+        health.connected_since = Some(Utc::now());
+        health.last_activity = Some((Instant::now(), Utc::now()));
+    }
+
+    // ConnAck after the event loop reconnected on its own
+    pub fn reconnected(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.retrying = false;
+        health.connected_since = Some(Utc::now());
+    }
+
+    pub fn heartbeat(&self) {
+        self.health.lock().unwrap().last_activity = Some((Instant::now(), Utc::now()));
+    }
+
+    pub fn failed(&self, error: &str, retrying: bool) {
+        let mut health = self.health.lock().unwrap();
+        health.retrying = retrying;
+        health.connected_since = None;
+        health.last_error = Some(error.to_string());
+        health.last_error_at = Some(Utc::now());
+    }
+
+    // Disconnected on purpose; the last error is kept for the diagnostics view
+    pub fn disconnected(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.retrying = false;
+        health.connected_since = None;
+    }
+
+    // `connected` is the flag the event loop keeps; it can't notice the loop dying or hanging
+    pub fn status(&self, connected: bool) -> MqttStatus {
+        let health = self.health.lock().unwrap();
+        let event_loop = supervisor::task_state(EVENT_LOOP_TASK);
+        let stale_after = health.keep_alive * STALE_AFTER_KEEP_ALIVES;
+        let quiet = health.last_activity.is_none_or(|(at, _)| at.elapsed() > stale_after);
+
+        let state = match event_loop {
+            Some(TaskState::Running) if connected && quiet => MqttState::Stale,
+            Some(TaskState::Running) if connected => MqttState::Connected,
+            Some(TaskState::Running) if health.retrying => MqttState::Reconnecting,
+            // Panicked and waiting to be restarted on the same connection
+            Some(TaskState::Restarting) if connected || health.retrying => MqttState::Reconnecting,
+            _ => MqttState::Disconnected,
+        };
+
+        MqttStatus {
+            connected: state == MqttState::Connected,
+            state,
+            broker: health.broker.clone(),
+            connected_since: health.connected_since.filter(|_| state == MqttState::Connected),
+            last_activity: health.last_activity.map(|(_, at)| at),
+            last_error: health.last_error.clone(),
+            last_error_at: health.last_error_at,
+        }
+    }
+}
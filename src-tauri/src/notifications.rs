@@ -0,0 +1,369 @@
+use crate::chat::ChatNotifier;
+use crate::daily_summary::DailySummary;
+use crate::devices::DeviceInfo;
+use crate::config::{AlertRoute, AlertRoutingSettings, AppSettings, QuietHoursMode, QuietHoursSettings};
+use crate::email::EmailNotifier;
+use crate::events::{self, DesktopAction};
+use crate::sinks;
+use crate::triggers::TriggerNotifier;
+use crate::types::{AlertData, AlertDirection, AlertLevel, AlertSource};
+use crate::webhooks::{webhook_label, WebhookDispatcher};
+use anyhow::Result;
+use chrono::{Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, debug, warn};
+
+pub const DEFAULT_ALERT_TOPIC: &str = "weather/alert_trigger";
+
+// How often held notifications are checked for release
+const RELEASE_CHECK_SECS: u64 = 60;
+// Oldest held notifications are dropped beyond this
+const MAX_HELD: usize = 200;
+
+// Target of send_test_notification
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    All,
+    Mqtt,
+    Desktop,
+    Webhooks,
+    Email,
+    Telegram,
+    Discord,
+}
+
+impl NotificationChannel {
+    fn includes(self, channel: NotificationChannel) -> bool {
+        self == NotificationChannel::All || self == channel
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOutcome {
+    Delivered,
+    Failed,
+    // Disabled, not configured or routed away for the alert's level
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelTestResult {
+    // "mqtt", "desktop", "email", or "webhook: <name>"
+    pub channel: String,
+    pub outcome: TestOutcome,
+    pub detail: String,
+}
+
+impl ChannelTestResult {
+    pub fn new(channel: &str, outcome: TestOutcome, detail: impl Into<String>) -> Self {
+        Self { channel: channel.to_string(), outcome, detail: detail.into() }
+    }
+
+    pub fn from_result(channel: &str, result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Self::new(channel, TestOutcome::Delivered, "Sent"),
+            Err(e) => Self::new(channel, TestOutcome::Failed, e.to_string()),
+        }
+    }
+}
+
+enum Held {
+    Alert(AlertData, AlertSource, AlertDirection),
+    Desktop { title: String, body: String },
+    DailySummary(DailySummary),
+    DeviceOffline(DeviceInfo),
+}
+
+// Outbound channels every alert is fanned out to besides MQTT, plus desktop
+// notifications, all subject to quiet hours
+#[derive(Default)]
+pub struct AlertChannels {
+    pub webhooks: Arc<WebhookDispatcher>,
+    pub email: EmailNotifier,
+    pub chat: ChatNotifier,
+    // Also registered as a data sink, which is how it gets alerts
+    pub triggers: Arc<TriggerNotifier>,
+    desktop_enabled: AtomicBool,
+    quiet_hours: RwLock<QuietHoursSettings>,
+    routing: RwLock<AlertRoutingSettings>,
+    held: Mutex<Vec<Held>>,
+}
+
+impl AlertChannels {
+    pub fn apply_settings(&self, settings: &AppSettings) {
+        self.webhooks.set_webhooks(settings.webhooks.clone());
+        self.email.set_settings(settings.email.clone());
+        self.chat.set_settings(settings.telegram.clone(), settings.discord.clone());
+        self.triggers.set_settings(settings.triggers.clone());
+        self.desktop_enabled.store(settings.desktop_notifications, Ordering::SeqCst);
+        if let Ok(mut guard) = self.quiet_hours.write() {
+            *guard = settings.quiet_hours.clone();
+        }
+        if let Ok(mut guard) = self.routing.write() {
+            *guard = settings.alert_routing.clone();
+        }
+    }
+
+    // Routing table entry for `level`
+    pub fn route(&self, level: &AlertLevel) -> AlertRoute {
+        self.routing.read()
+            .map(|routing| routing.for_level(level).clone())
+            .unwrap_or_default()
+    }
+
+    // Whether `topic` is one alerts are published on
+    pub fn is_alert_topic(&self, topic: &str) -> bool {
+        let Ok(routing) = self.routing.read() else {
+            return topic == DEFAULT_ALERT_TOPIC;
+        };
+        [&routing.info, &routing.warning, &routing.emergency].iter().any(|route| route_topic(route) == topic)
+    }
+
+    pub fn dispatch(&self, alert: &AlertData, source: AlertSource, direction: AlertDirection) {
+        match self.quiet_mode(&alert.level) {
+            None => self.deliver(alert, source, direction),
+            Some(QuietHoursMode::Queue) => self.hold(Held::Alert(alert.clone(), source, direction)),
+            Some(QuietHoursMode::Suppress) => debug!("Quiet hours, not forwarding alert: {}", alert.message),
+        }
+    }
+
+    // Native OS notification, when enabled in the app settings
+    pub fn notify_desktop(&self, level: &AlertLevel, title: &str, body: &str) {
+        if !self.desktop_enabled.load(Ordering::SeqCst) || !self.route(level).desktop {
+            return;
+        }
+        match self.quiet_mode(level) {
+            None => self.show_desktop(title, body),
+            Some(QuietHoursMode::Queue) => self.hold(Held::Desktop { title: title.to_string(), body: body.to_string() }),
+            Some(QuietHoursMode::Suppress) => debug!("Quiet hours, not showing notification: {}", title),
+        }
+    }
+
+    // Emails the end-of-day summary and fires its trigger, when those are enabled.
+    // Routing and quiet hours apply as for an info alert.
+    pub fn send_daily_summary(&self, summary: &DailySummary) {
+        match self.quiet_mode(&AlertLevel::Info) {
+            None => self.deliver_daily_summary(summary),
+            Some(QuietHoursMode::Queue) => self.hold(Held::DailySummary(summary.clone())),
+            Some(QuietHoursMode::Suppress) => debug!("Quiet hours, not sending the summary for {}", summary.date),
+        }
+    }
+
+    // Fires the device-offline trigger; routing and quiet hours apply as for a warning
+    pub fn notify_device_offline(&self, device: &DeviceInfo) {
+        match self.quiet_mode(&AlertLevel::Warning) {
+            None => self.deliver_device_offline(device),
+            Some(QuietHoursMode::Queue) => self.hold(Held::DeviceOffline(device.clone())),
+            Some(QuietHoursMode::Suppress) => debug!("Quiet hours, not announcing that {} went offline", device.device_id),
+        }
+    }
+
+    // Sends `alert` to the selected channels straight away and reports each outcome.
+    // Quiet hours are ignored; the routing table and each channel's settings are not.
+    pub async fn send_test(&self, channel: NotificationChannel, alert: &AlertData) -> Vec<ChannelTestResult> {
+        let route = self.route(&alert.level);
+        let routed_away = |name: &str| ChannelTestResult::new(name, TestOutcome::Skipped, "Routing disables this channel for the alert's level");
+        let mut results = Vec::new();
+
+        if channel.includes(NotificationChannel::Desktop) {
+            results.push(if !self.desktop_enabled.load(Ordering::SeqCst) {
+                ChannelTestResult::new("desktop", TestOutcome::Skipped, "Desktop notifications are off")
+            } else if !route.desktop {
+                routed_away("desktop")
+            } else {
+                self.show_desktop(&alert_title(alert), &alert.message);
+                ChannelTestResult::new("desktop", TestOutcome::Delivered, "Sent")
+            });
+        }
+
+        if channel.includes(NotificationChannel::Webhooks) {
+            let webhooks = self.webhooks.webhooks();
+            if webhooks.is_empty() {
+                results.push(ChannelTestResult::new("webhooks", TestOutcome::Skipped, "No webhooks configured"));
+            }
+            for webhook in webhooks {
+                let name = format!("webhook: {}", webhook_label(&webhook));
+                results.push(if !webhook.enabled || webhook.url.is_empty() {
+                    ChannelTestResult::new(&name, TestOutcome::Skipped, "Not enabled")
+                } else if alert.level < webhook.min_level {
+                    ChannelTestResult::new(&name, TestOutcome::Skipped, "Below the webhook's minimum level")
+                } else if !route.webhooks {
+                    routed_away(&name)
+                } else {
+                    ChannelTestResult::from_result(&name, self.webhooks.send_test(&webhook, alert).await)
+                });
+            }
+        }
+
+        if channel.includes(NotificationChannel::Email) {
+            let settings = self.email.settings();
+            results.push(if !settings.enabled {
+                ChannelTestResult::new("email", TestOutcome::Skipped, "Not enabled")
+            } else if alert.level < settings.min_level {
+                ChannelTestResult::new("email", TestOutcome::Skipped, "Below the minimum email level")
+            } else if !route.email {
+                routed_away("email")
+            } else {
+                ChannelTestResult::from_result("email", self.email.send_alert_now(alert).await)
+            });
+        }
+
+        if channel.includes(NotificationChannel::Telegram) {
+            let settings = self.chat.telegram_settings();
+            results.push(if !settings.enabled {
+                ChannelTestResult::new("telegram", TestOutcome::Skipped, "Not enabled")
+            } else if alert.level < settings.min_level {
+                ChannelTestResult::new("telegram", TestOutcome::Skipped, "Below the minimum Telegram level")
+            } else if !route.chat {
+                routed_away("telegram")
+            } else {
+                ChannelTestResult::from_result("telegram", self.chat.send_telegram_now(alert).await)
+            });
+        }
+
+        if channel.includes(NotificationChannel::Discord) {
+            let settings = self.chat.discord_settings();
+            results.push(if !settings.enabled {
+                ChannelTestResult::new("discord", TestOutcome::Skipped, "Not enabled")
+            } else if alert.level < settings.min_level {
+                ChannelTestResult::new("discord", TestOutcome::Skipped, "Below the minimum Discord level")
+            } else if !route.chat {
+                routed_away("discord")
+            } else {
+                ChannelTestResult::from_result("discord", self.chat.send_discord_now(alert).await)
+            });
+        }
+
+        results
+    }
+
+    // Delivers held notifications once quiet hours are over
+    pub fn spawn_release(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(RELEASE_CHECK_SECS));
+            loop {
+                interval.tick().await;
+                if self.is_quiet() {
+                    continue;
+                }
+                let held = match self.held.lock() {
+                    Ok(mut guard) => std::mem::take(&mut *guard),
+                    Err(_) => continue,
+                };
+                if held.is_empty() {
+                    continue;
+                }
+
+                info!("Quiet hours over, delivering {} held notification(s)", held.len());
+                for item in held {
+                    match item {
+                        Held::Alert(alert, source, direction) => self.deliver(&alert, source, direction),
+                        Held::Desktop { title, body } => {
+                            if self.desktop_enabled.load(Ordering::SeqCst) {
+                                self.show_desktop(&title, &body);
+                            }
+                        }
+                        Held::DailySummary(summary) => self.deliver_daily_summary(&summary),
+                        Held::DeviceOffline(device) => self.deliver_device_offline(&device),
+                    }
+                }
+            }
+        })
+    }
+
+    fn deliver(&self, alert: &AlertData, source: AlertSource, direction: AlertDirection) {
+        let route = self.route(&alert.level);
+        // Webhooks and any other registered data sinks, each by its own route flag
+        sinks::alert(alert, source, direction, &route);
+        // Only alerts raised here are emailed or posted to chats, so several desktops
+        // don't all send the same one
+        if direction == AlertDirection::Sent {
+            if route.email {
+                self.email.notify_alert(alert);
+            }
+            if route.chat {
+                self.chat.notify_alert(alert);
+            }
+        }
+    }
+
+    fn deliver_daily_summary(&self, summary: &DailySummary) {
+        self.email.notify_daily_summary(summary);
+        if self.route(&AlertLevel::Info).sinks {
+            self.triggers.notify_daily_summary(summary);
+        }
+    }
+
+    fn deliver_device_offline(&self, device: &DeviceInfo) {
+        if self.route(&AlertLevel::Warning).sinks {
+            self.triggers.notify_device_offline(device);
+        }
+    }
+
+    fn show_desktop(&self, title: &str, body: &str) {
+        events::perform(DesktopAction::Notify { title: title.to_string(), body: body.to_string() });
+    }
+
+    fn hold(&self, item: Held) {
+        if let Ok(mut held) = self.held.lock() {
+            held.push(item);
+            if held.len() > MAX_HELD {
+                held.remove(0);
+            }
+        }
+    }
+
+    // How a notification at `level` is handled right now; None means deliver it
+    fn quiet_mode(&self, level: &AlertLevel) -> Option<QuietHoursMode> {
+        if *level == AlertLevel::Emergency || !self.is_quiet() {
+            return None;
+        }
+        self.quiet_hours.read().ok().map(|settings| settings.mode)
+    }
+
+    fn is_quiet(&self) -> bool {
+        let Some(settings) = self.quiet_hours.read().ok().map(|guard| guard.clone()) else {
+            return false;
+        };
+        if !settings.enabled {
+            return false;
+        }
+        let (Ok(start), Ok(end)) = (
+            NaiveTime::parse_from_str(&settings.start, "%H:%M"),
+            NaiveTime::parse_from_str(&settings.end, "%H:%M"),
+        ) else {
+            warn!("Invalid quiet hours {}–{}, expected HH:MM", settings.start, settings.end);
+            return false;
+        };
+
+        let now = Local::now().time();
+        if start <= end {
+            now >= start && now < end
+        } else {
+            now >= start || now < end
+        }
+    }
+}
+
+// MQTT topic for alerts on `route`
+pub fn route_topic(route: &AlertRoute) -> &str {
+    match route.topic.trim() {
+        "" => DEFAULT_ALERT_TOPIC,
+        topic => topic,
+    }
+}
+
+// Desktop notification title for an alert
+pub fn alert_title(alert: &AlertData) -> String {
+    match alert.level {
+        AlertLevel::Info => "Weather station".to_string(),
+        AlertLevel::Warning => "Weather station warning".to_string(),
+        AlertLevel::Emergency => "Weather station emergency".to_string(),
+    }
+}
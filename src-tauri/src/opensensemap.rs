@@ -0,0 +1,76 @@
+use crate::config::OpenSenseMapSettings;
+use crate::sinks::{IntervalUploader, UploadStatus, UploadStatusSlot};
+use crate::types::SensorData;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+
+pub const SINK_NAME: &str = "opensensemap";
+pub const MIN_INTERVAL_SECS: u64 = 60;
+const API_URL: &str = "https://api.opensensemap.org";
+const TIMEOUT_SECS: u64 = 15;
+
+static STATUS: UploadStatusSlot = Mutex::new(None);
+
+// None until the uploader has been started once
+pub fn status() -> Option<UploadStatus> {
+    STATUS.lock().unwrap().clone()
+}
+
+// Posts the newest reading to the senseBox once per interval
+pub fn start(settings: OpenSenseMapSettings) -> Result<IntervalUploader> {
+    if settings.box_id.is_empty() || settings.api_key.is_empty() {
+        return Err(anyhow!("openSenseMap box id and API key must be configured"));
+    }
+    let client = Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .build()?;
+    let interval = Duration::from_secs(settings.interval_secs.max(MIN_INTERVAL_SECS));
+    let (target, device_id) = (settings.box_id.clone(), settings.device_id.clone());
+    let settings = Arc::new(settings);
+    Ok(IntervalUploader::start(SINK_NAME, "openSenseMap", &target, device_id, interval, &STATUS, move |reading| {
+        let (client, settings) = (client.clone(), Arc::clone(&settings));
+        async move { upload(&client, &settings, &reading).await }
+    }))
+}
+
+// One measurement per mapped sensor, all stamped with the reading's time
+fn measurements(settings: &OpenSenseMapSettings, reading: &SensorData) -> Vec<Value> {
+    let created_at = reading.received_at.unwrap_or_else(Utc::now).to_rfc3339();
+    let sensors = &settings.sensors;
+    [
+        (&sensors.temperature, reading.temperature),
+        (&sensors.humidity, reading.humidity),
+        (&sensors.pressure, reading.pressure),
+    ]
+    .into_iter()
+    .filter(|(sensor_id, value)| !sensor_id.trim().is_empty() && value.is_finite())
+    .map(|(sensor_id, value)| json!({
+        "sensor": sensor_id.trim(),
+        "value": format!("{:.2}", value),
+        "createdAt": created_at,
+    }))
+    .collect()
+}
+
+async fn upload(client: &Client, settings: &OpenSenseMapSettings, reading: &SensorData) -> Result<()> {
+    let body = measurements(settings, reading);
+    if body.is_empty() {
+        return Ok(());
+    }
+    let url = format!("{}/boxes/{}/data", API_URL, settings.box_id.trim());
+    let response = client.post(&url)
+        .header("Authorization", settings.api_key.as_str())
+        .json(&body)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow!("HTTP {}: {}", status, text.trim()));
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use crate::config::PayloadTemplateSettings;
+use crate::types::{AlertData, WeatherData};
+use anyhow::{Result, anyhow};
+use handlebars::{handlebars_helper, Handlebars, Template};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadKind {
+    Weather,
+    Alert,
+}
+
+// Strings are inserted as they are, so `{{json field}}` is needed to get a quoted,
+// escaped JSON value
+handlebars_helper!(json: |value: Json| serde_json::to_string(value).unwrap_or_default());
+handlebars_helper!(round: |value: f64, digits: u64| {
+    let factor = 10f64.powi(digits.min(6) as i32);
+    (value * factor).round() / factor
+});
+
+fn registry() -> Handlebars<'static> {
+    let mut registry = Handlebars::new();
+    registry.register_escape_fn(handlebars::no_escape);
+    // A misspelt field fails the render rather than silently going missing
+    registry.set_strict_mode(true);
+    registry.register_helper("json", Box::new(json));
+    registry.register_helper("round", Box::new(round));
+    registry
+}
+
+// Syntax check, for validating settings before they are saved
+pub fn check(template: &str) -> Result<()> {
+    Template::compile(template).map(|_| ()).map_err(|e| anyhow!("{}", e))
+}
+
+// Renders `template` with the fields of `data`; the result must be JSON
+pub fn render(template: &str, data: &impl Serialize) -> Result<Value> {
+    let rendered = registry().render_template(template, data)?;
+    serde_json::from_str(&rendered).map_err(|e| anyhow!("Template output is not valid JSON: {}", e))
+}
+
+// Without a template, or when it fails, the payload is the struct as serialized, so
+// the device still gets the data in the standard format
+fn payload(kind: PayloadKind, template: &str, data: &impl Serialize) -> Result<Vec<u8>> {
+    if !template.trim().is_empty() {
+        match render(template, data) {
+            Ok(value) => return Ok(serde_json::to_vec(&value)?),
+            Err(e) => warn!("Failed to render {:?} payload template, publishing the default payload: {}", kind, e),
+        }
+    }
+    Ok(serde_json::to_vec(data)?)
+}
+
+// Full weather/data snapshots only; delta and flat topic publishing use the standard fields
+pub fn weather_payload(templates: &PayloadTemplateSettings, data: &WeatherData) -> Result<Vec<u8>> {
+    payload(PayloadKind::Weather, &templates.weather, data)
+}
+
+pub fn alert_payload(templates: &PayloadTemplateSettings, alert: &AlertData) -> Result<Vec<u8>> {
+    payload(PayloadKind::Alert, &templates.alert, alert)
+}
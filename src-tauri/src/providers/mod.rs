@@ -0,0 +1,205 @@
+use crate::api_usage::ApiUsageTracker;
+use crate::config::HttpRetrySettings;
+use crate::types::{ForecastDay, HistoryDay, HourlyForecast, LocationCandidate, NowcastInterval, UnitSystem, WeatherAlert};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate, Utc};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+pub mod open_meteo;
+pub mod openweathermap;
+
+pub use open_meteo::OpenMeteoProvider;
+pub use openweathermap::OpenWeatherMapProvider;
+
+#[derive(Debug, Clone)]
+pub struct CurrentConditions {
+    pub condition: String,
+    pub icon: String,
+    pub temp: f64,
+    pub humidity: i32,
+    pub pressure: i32,
+    pub wind_speed: f64,
+    pub wind_deg: f64,
+    pub feels_like: Option<f64>,
+    pub wind_gust: Option<f64>,
+    pub uv_index: Option<f64>,
+    pub visibility: Option<i32>,
+    // Local "HH:MM" at the location
+    pub sunrise: Option<String>,
+    pub sunset: Option<String>,
+    // Last hour, in millimetres
+    pub rain_mm: Option<f64>,
+    pub snow_mm: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WeatherReport {
+    // Units the provider was asked for
+    pub units: UnitSystem,
+    pub current: CurrentConditions,
+    pub forecast: Vec<ForecastDay>,
+    pub history: Vec<HistoryDay>,
+    // Set when no real history was available and a fixed pattern was used instead
+    pub history_synthetic: bool,
+    // Next 48 hours where the provider offers it
+    pub hourly: Vec<HourlyForecast>,
+    // Official warnings, empty for providers that don't offer them
+    pub alerts: Vec<WeatherAlert>,
+}
+
+#[async_trait]
+pub trait WeatherProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Fetches everything in as few requests as the provider allows
+    async fn fetch_report(&self, lat: f64, lon: f64) -> Result<WeatherReport>;
+
+    // Looks up places by name for the location picker
+    async fn search_locations(&self, query: &str) -> Result<Vec<LocationCandidate>>;
+
+    async fn fetch_current(&self, lat: f64, lon: f64) -> Result<CurrentConditions> {
+        Ok(self.fetch_report(lat, lon).await?.current)
+    }
+
+    async fn fetch_forecast(&self, lat: f64, lon: f64) -> Result<Vec<ForecastDay>> {
+        Ok(self.fetch_report(lat, lon).await?.forecast)
+    }
+
+    async fn fetch_history(&self, lat: f64, lon: f64) -> Result<Vec<HistoryDay>> {
+        Ok(self.fetch_report(lat, lon).await?.history)
+    }
+
+    async fn fetch_hourly(&self, lat: f64, lon: f64) -> Result<Vec<HourlyForecast>> {
+        Ok(self.fetch_report(lat, lon).await?.hourly)
+    }
+
+    // Precipitation over roughly the next two hours, oldest first
+    async fn fetch_nowcast(&self, _lat: f64, _lon: f64) -> Result<Vec<NowcastInterval>> {
+        Err(anyhow!("{} doesn't offer a precipitation nowcast", self.name()))
+    }
+}
+
+pub(crate) const HOURLY_FORECAST_HOURS: usize = 48;
+// Days including today; the cache keeps all of them and output is trimmed to the setting
+pub(crate) const MAX_FORECAST_DAYS: usize = 8;
+pub(crate) const MAX_LOCATION_RESULTS: usize = 5;
+
+// Sends the request, retrying timeouts, connection errors, 429 and 5xx with
+// exponential backoff. The last response is returned as-is once attempts run out.
+// Every attempt counts against the usage tracker when one is given.
+pub(crate) async fn send_with_retry(
+    request: RequestBuilder,
+    retry: &HttpRetrySettings,
+    usage: Option<&ApiUsageTracker>,
+) -> Result<Response> {
+    let attempts = retry.max_attempts.max(1);
+    let mut backoff_ms = retry.initial_backoff_ms;
+    let request = request.timeout(Duration::from_secs(retry.request_timeout_secs.max(1)));
+
+    for attempt in 1..=attempts {
+        let current = request.try_clone().ok_or_else(|| anyhow!("Request cannot be retried"))?;
+        let is_last = attempt == attempts;
+
+        if let Some(usage) = usage {
+            usage.try_acquire()?;
+        }
+
+        match current.send().await {
+            Ok(response) => {
+                if let Some(usage) = usage {
+                    usage.record_headers(response.headers());
+                }
+                let status = response.status();
+                let retryable = status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+                if !retryable || is_last {
+                    return Ok(response);
+                }
+                warn!("Weather API returned {} (attempt {}/{}), retrying in {} ms", status, attempt, attempts, backoff_ms);
+            }
+            Err(e) => {
+                // The URL may carry an API key, keep it out of logs and error messages
+                let e = e.without_url();
+                let retryable = e.is_timeout() || e.is_connect();
+                if !retryable || is_last {
+                    if e.is_timeout() {
+                        return Err(anyhow!("Weather API timed out after {} attempt(s), check your connection or raise the timeout settings", attempt));
+                    }
+                    if e.is_connect() {
+                        return Err(anyhow!("Could not connect to the weather API after {} attempt(s): {}", attempt, e));
+                    }
+                    return Err(e.into());
+                }
+                warn!("Weather API request failed (attempt {}/{}): {}, retrying in {} ms", attempt, attempts, e, backoff_ms);
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
+        backoff_ms = (backoff_ms * 2).min(retry.max_backoff_ms);
+    }
+
+    unreachable!("retry loop always returns on the last attempt")
+}
+
+// Today's date at the location rather than on this desktop
+pub(crate) fn local_today(utc_offset_secs: i64) -> NaiveDate {
+    (Utc::now() + chrono::Duration::seconds(utc_offset_secs)).date_naive()
+}
+
+// "TODAY" for today, else the weekday abbreviation the device displays
+pub(crate) fn day_label(date: NaiveDate, today: NaiveDate) -> String {
+    if date == today {
+        return "TODAY".to_string();
+    }
+    match date.weekday() {
+        chrono::Weekday::Mon => "MON",
+        chrono::Weekday::Tue => "TUE",
+        chrono::Weekday::Wed => "WED",
+        chrono::Weekday::Thu => "THU",
+        chrono::Weekday::Fri => "FRI",
+        chrono::Weekday::Sat => "SAT",
+        chrono::Weekday::Sun => "SUN",
+    }.to_string()
+}
+
+// Last resort when no provider could supply real history
+pub(crate) fn synthetic_history(current_temp: f64, current_humidity: i32, today: NaiveDate) -> Vec<HistoryDay> {
+    info!("⚠️  Generating historical data with fixed temperature pattern");
+
+    let mut history = Vec::new();
+
+    // Fixed temperature pattern: 32, 31, 34, 28, 32, 27, ...
+    let temp_pattern = [32.0, 31.0, 34.0, 28.0, 32.0, 27.0, 30.0];
+
+    // Fixed humidity pattern: 85, 72, 68, 91, 76, 82, ...
+    let humidity_pattern = [85, 72, 68, 91, 76, 82, 79];
+
+    // Generate history for past 6 days (today-6 to today-1) + today
+    for days_back in (0..7).rev() { // 6, 5, 4, 3, 2, 1, 0 (today)
+        let historical_date = today - chrono::Duration::days(days_back);
+
+        // Use the pattern for historical days, actual current values for today
+        let (historical_temp, historical_humidity) = if days_back == 0 {
+            (current_temp, current_humidity)
+        } else {
+            let pattern_index = (6 - days_back) as usize % temp_pattern.len();
+            (temp_pattern[pattern_index], humidity_pattern[pattern_index])
+        };
+
+        history.push(HistoryDay {
+            day: day_label(historical_date, today),
+            date: historical_date.format("%d/%m").to_string(),
+            temp: historical_temp,
+            humidity: historical_humidity,
+        });
+
+        info!("Generated history day {}: {} {} - temp: {:.1}°C, humidity: {}%",
+              6 - days_back, history.last().unwrap().day, history.last().unwrap().date,
+              historical_temp, historical_humidity);
+    }
+
+    info!("Generated {} days of historical data with temp pattern {:?} and humidity pattern {:?}", history.len(), temp_pattern, humidity_pattern);
+    history
+}
@@ -0,0 +1,375 @@
+use super::{day_label, local_today, send_with_retry, synthetic_history, CurrentConditions, WeatherProvider, WeatherReport, HOURLY_FORECAST_HOURS, MAX_FORECAST_DAYS, MAX_LOCATION_RESULTS};
+use crate::types::{ForecastDay, HistoryDay, HourlyForecast, LocationCandidate, NowcastInterval, UnitSystem};
+use crate::config::{HttpRetrySettings, OpenMeteoSettings, OpenMeteoVariableSet};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use reqwest::Client;
+use serde_json::Value;
+use tracing::{info, error};
+
+const OPEN_METEO_FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+// Two hours of 15-minute nowcast steps
+const NOWCAST_STEPS: usize = 8;
+const OPEN_METEO_GEOCODING_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+
+// Free provider, no API key required
+pub struct OpenMeteoProvider {
+    client: Client,
+    units: UnitSystem,
+    retry: HttpRetrySettings,
+    settings: OpenMeteoSettings,
+}
+
+impl OpenMeteoProvider {
+    pub fn new(client: Client, units: UnitSystem, retry: HttpRetrySettings) -> Self {
+        Self { client, units, retry, settings: OpenMeteoSettings::default() }
+    }
+
+    pub fn with_settings(mut self, settings: OpenMeteoSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    // Query parameters for the chosen model and variable set
+    fn variable_params(&self) -> String {
+        let model = format!("models={}", self.settings.model.as_param());
+        match self.settings.variables {
+            OpenMeteoVariableSet::Full => format!(
+                "{}&current=temperature_2m,relative_humidity_2m,pressure_msl,wind_speed_10m,wind_direction_10m,weather_code,is_day,rain,snowfall,apparent_temperature,wind_gusts_10m,uv_index,visibility&daily=weather_code,temperature_2m_max,temperature_2m_min,apparent_temperature_max,relative_humidity_2m_mean,precipitation_probability_max,rain_sum,snowfall_sum,sunrise,sunset&hourly=temperature_2m,precipitation_probability,weather_code,is_day&past_hours=0&forecast_hours={}",
+                model, HOURLY_FORECAST_HOURS
+            ),
+            OpenMeteoVariableSet::Compact => format!(
+                "{}&current=temperature_2m,relative_humidity_2m,pressure_msl,wind_speed_10m,wind_direction_10m,weather_code,is_day,rain,snowfall,apparent_temperature,wind_gusts_10m&daily=weather_code,temperature_2m_max,temperature_2m_min,apparent_temperature_max,relative_humidity_2m_mean,precipitation_probability_max,rain_sum,snowfall_sum,sunrise,sunset",
+                model
+            ),
+        }
+    }
+
+    fn units_params(&self) -> &'static str {
+        match self.units {
+            UnitSystem::Metric => "temperature_unit=celsius&wind_speed_unit=ms",
+            UnitSystem::Imperial => "temperature_unit=fahrenheit&wind_speed_unit=mph",
+        }
+    }
+
+    pub fn parse_response(data: &Value, units: UnitSystem) -> Result<WeatherReport> {
+        let current = data.get("current")
+            .ok_or_else(|| anyhow!("Missing current weather data"))?;
+        let daily = data.get("daily")
+            .ok_or_else(|| anyhow!("Missing daily weather data"))?;
+
+        let current_f64 = |key: &str| current.get(key).and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let is_day = current.get("is_day").and_then(|v| v.as_i64()).unwrap_or(1) == 1;
+        let weather_code = current.get("weather_code").and_then(|v| v.as_i64()).unwrap_or(0);
+        let (condition, current_icon) = Self::wmo_condition(weather_code, is_day);
+
+        // Dates are local to the location because of timezone=auto
+        let utc_offset = data.get("utc_offset_seconds").and_then(|o| o.as_i64()).unwrap_or(0);
+        let today = current.get("time")
+            .and_then(|t| t.as_str())
+            .and_then(|t| t.get(..10))
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .unwrap_or_else(|| local_today(utc_offset));
+
+        let daily_array = |key: &str| daily.get(key).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        let dates = daily_array("time");
+        let sunrises = daily_array("sunrise");
+        let sunsets = daily_array("sunset");
+
+        // Sun times are local ISO strings, e.g. 2024-05-01T06:12
+        let today_index = dates.iter().position(|d| d.as_str() == Some(today.format("%Y-%m-%d").to_string().as_str()));
+        let sun_time = |values: &[Value]| today_index
+            .and_then(|i| values.get(i))
+            .and_then(|v| v.as_str())
+            .and_then(|t| t.get(11..16))
+            .map(|t| t.to_string());
+
+        let (forecast, history) = Self::parse_daily(daily, today);
+
+        // Open-Meteo normally has history, but fall back rather than publish nothing
+        let history_synthetic = history.is_empty();
+        let history = if history_synthetic {
+            synthetic_history(current_f64("temperature_2m"), current_f64("relative_humidity_2m").round() as i32, today)
+        } else {
+            history
+        };
+
+        let hourly = data.get("hourly")
+            .map(|hourly| Self::parse_hourly(hourly, utc_offset))
+            .unwrap_or_default();
+
+        Ok(WeatherReport {
+            units,
+            current: CurrentConditions {
+                condition: condition.to_string(),
+                icon: current_icon,
+                temp: current_f64("temperature_2m"),
+                humidity: current_f64("relative_humidity_2m").round() as i32,
+                pressure: current_f64("pressure_msl").round() as i32,
+                wind_speed: current_f64("wind_speed_10m"),
+                wind_deg: current_f64("wind_direction_10m"),
+                feels_like: current.get("apparent_temperature").and_then(|v| v.as_f64()),
+                wind_gust: current.get("wind_gusts_10m").and_then(|v| v.as_f64()),
+                uv_index: current.get("uv_index").and_then(|v| v.as_f64()),
+                visibility: current.get("visibility").and_then(|v| v.as_f64()).map(|v| v.round() as i32),
+                sunrise: sun_time(&sunrises),
+                sunset: sun_time(&sunsets),
+                rain_mm: current.get("rain").and_then(|v| v.as_f64()),
+                snow_mm: current.get("snowfall").and_then(|v| v.as_f64()).map(|cm| cm * 10.0),
+            },
+            forecast,
+            history,
+            history_synthetic,
+            hourly,
+            alerts: Vec::new(),
+        })
+    }
+
+    // Splits the daily arrays into history (up to today) and forecast (from today)
+    fn parse_daily(daily: &Value, today: NaiveDate) -> (Vec<ForecastDay>, Vec<HistoryDay>) {
+        let daily_array = |key: &str| daily.get(key).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        let dates = daily_array("time");
+        let codes = daily_array("weather_code");
+        let max_temps = daily_array("temperature_2m_max");
+        let min_temps = daily_array("temperature_2m_min");
+        let humidities = daily_array("relative_humidity_2m_mean");
+        let feels_like = daily_array("apparent_temperature_max");
+        let pops = daily_array("precipitation_probability_max");
+        let rain_sums = daily_array("rain_sum");
+        let snowfall_sums = daily_array("snowfall_sum");
+        let value = |values: &[Value], i: usize| values.get(i).and_then(|v| v.as_f64()).unwrap_or(0.0);
+
+        let mut forecast = Vec::new();
+        let mut history = Vec::new();
+
+        for (i, date_value) in dates.iter().enumerate() {
+            let Some(date) = date_value.as_str().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()) else {
+                continue;
+            };
+            let day = day_label(date, today);
+            let date_str = date.format("%d/%m").to_string();
+            let temp = max_temps.get(i).and_then(|v| v.as_f64()).unwrap_or(0.0);
+            let humidity = humidities.get(i).and_then(|v| v.as_f64()).unwrap_or(0.0).round() as i32;
+
+            // History runs up to and including today, forecast starts today
+            if date <= today {
+                history.push(HistoryDay {
+                    day: day.clone(),
+                    date: date_str.clone(),
+                    temp,
+                    humidity,
+                });
+            }
+            if date >= today {
+                let code = codes.get(i).and_then(|v| v.as_i64()).unwrap_or(0);
+                forecast.push(ForecastDay {
+                    day,
+                    date: date_str,
+                    temp,
+                    temp_min: min_temps.get(i).and_then(|v| v.as_f64()),
+                    humidity,
+                    icon: Self::wmo_condition(code, true).1,
+                    feels_like: feels_like.get(i).and_then(|v| v.as_f64()),
+                    pop: value(&pops, i),
+                    rain_mm: value(&rain_sums, i),
+                    // Snowfall is reported in centimetres
+                    snow_mm: value(&snowfall_sums, i) * 10.0,
+                });
+            }
+        }
+
+        (forecast, history)
+    }
+
+    // Past six days plus today, used for providers without free history
+    pub async fn fetch_daily_history(&self, lat: f64, lon: f64) -> Result<Vec<HistoryDay>> {
+        let url = format!(
+            "{}?latitude={}&longitude={}&models={}&daily=weather_code,temperature_2m_max,relative_humidity_2m_mean&past_days=6&forecast_days=1&{}&timezone=auto",
+            OPEN_METEO_FORECAST_URL, lat, lon, self.settings.model.as_param(), self.units_params()
+        );
+
+        let response = send_with_retry(self.client.get(&url), &self.retry, None).await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(anyhow!("History request failed: {} - {}", status, error_text));
+        }
+
+        let data: Value = response.json().await?;
+        let daily = data.get("daily")
+            .ok_or_else(|| anyhow!("Missing daily weather data"))?;
+
+        // With forecast_days=1 the last date is today at the location
+        let today = daily.get("time")
+            .and_then(|t| t.as_array())
+            .and_then(|dates| dates.last())
+            .and_then(|d| d.as_str())
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .ok_or_else(|| anyhow!("Missing daily dates"))?;
+
+        Ok(Self::parse_daily(daily, today).1)
+    }
+
+    fn parse_hourly(hourly: &Value, utc_offset: i64) -> Vec<HourlyForecast> {
+        let hourly_array = |key: &str| hourly.get(key).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        let times = hourly_array("time");
+        let temps = hourly_array("temperature_2m");
+        let probabilities = hourly_array("precipitation_probability");
+        let codes = hourly_array("weather_code");
+        let is_day = hourly_array("is_day");
+
+        times.iter()
+            .enumerate()
+            .filter_map(|(i, time)| {
+                // Times are local ISO strings without seconds, e.g. 2024-05-01T14:00
+                let local = NaiveDateTime::parse_from_str(time.as_str()?, "%Y-%m-%dT%H:%M").ok()?;
+                let code = codes.get(i).and_then(|v| v.as_i64()).unwrap_or(0);
+                let day = is_day.get(i).and_then(|v| v.as_i64()).unwrap_or(1) == 1;
+                Some(HourlyForecast {
+                    time: local.format("%H:%M").to_string(),
+                    dt: local.and_utc().timestamp() - utc_offset,
+                    temp: temps.get(i).and_then(|v| v.as_f64()).unwrap_or(0.0),
+                    precipitation_probability: probabilities.get(i).and_then(|v| v.as_f64()).unwrap_or(0.0),
+                    icon: Self::wmo_condition(code, day).1,
+                })
+            })
+            .take(HOURLY_FORECAST_HOURS)
+            .collect()
+    }
+
+    // Maps WMO weather codes to a description and the OpenWeatherMap icon the device expects
+    fn wmo_condition(code: i64, is_day: bool) -> (&'static str, String) {
+        let (description, icon) = match code {
+            0 => ("clear sky", "01"),
+            1 => ("mainly clear", "02"),
+            2 => ("partly cloudy", "03"),
+            3 => ("overcast", "04"),
+            45 | 48 => ("fog", "50"),
+            51 | 53 | 55 | 56 | 57 => ("drizzle", "09"),
+            61 | 63 | 65 | 66 | 67 => ("rain", "10"),
+            71 | 73 | 75 | 77 | 85 | 86 => ("snow", "13"),
+            80 | 81 | 82 => ("rain showers", "09"),
+            95 | 96 | 99 => ("thunderstorm", "11"),
+            _ => ("unknown", "03"),
+        };
+        (description, format!("{}{}", icon, if is_day { "d" } else { "n" }))
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for OpenMeteoProvider {
+    fn name(&self) -> &'static str {
+        "Open-Meteo"
+    }
+
+    async fn fetch_report(&self, lat: f64, lon: f64) -> Result<WeatherReport> {
+        info!("🌤️  CALLING OPEN-METEO API!");
+        info!("Fetching weather data for coordinates: {}, {}", lat, lon);
+
+        // past_days gives us real history from the same request
+        let url = format!(
+            "{}?latitude={}&longitude={}&{}&past_days=6&forecast_days={}&{}&timezone=auto",
+            OPEN_METEO_FORECAST_URL, lat, lon, self.variable_params(), MAX_FORECAST_DAYS, self.units_params()
+        );
+
+        info!("Making API request to: {}", url);
+
+        let response = send_with_retry(self.client.get(&url), &self.retry, None).await?;
+
+        info!("API response status: {}", response.status());
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            error!("API request failed with status {}: {}", status, error_text);
+            return Err(anyhow!("API request failed: {} - {}", status, error_text));
+        }
+
+        let data: Value = response.json().await?;
+        info!("✅ SUCCESSFULLY RECEIVED OPEN-METEO RESPONSE");
+
+        Self::parse_response(&data, self.units)
+    }
+
+    // Current values plus today's sun times only, a fraction of the full payload
+    async fn fetch_current(&self, lat: f64, lon: f64) -> Result<CurrentConditions> {
+        let url = format!(
+            "{}?latitude={}&longitude={}&models={}&current=temperature_2m,relative_humidity_2m,pressure_msl,wind_speed_10m,wind_direction_10m,weather_code,is_day,rain,snowfall,apparent_temperature,wind_gusts_10m,uv_index,visibility&daily=sunrise,sunset&forecast_days=1&{}&timezone=auto",
+            OPEN_METEO_FORECAST_URL, lat, lon, self.settings.model.as_param(), self.units_params()
+        );
+
+        let response = send_with_retry(self.client.get(&url), &self.retry, None).await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            error!("API request failed with status {}: {}", status, error_text);
+            return Err(anyhow!("API request failed: {} - {}", status, error_text));
+        }
+
+        let data: Value = response.json().await?;
+        Ok(Self::parse_response(&data, self.units)?.current)
+    }
+
+    // 15-minute steps; each value is the total for the 15 minutes up to its timestamp
+    async fn fetch_nowcast(&self, lat: f64, lon: f64) -> Result<Vec<NowcastInterval>> {
+        let url = format!(
+            "{}?latitude={}&longitude={}&minutely_15=precipitation&forecast_minutely_15={}&timeformat=unixtime",
+            OPEN_METEO_FORECAST_URL, lat, lon, NOWCAST_STEPS
+        );
+
+        let response = send_with_retry(self.client.get(&url), &self.retry, None).await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            error!("Nowcast request failed with status {}: {}", status, error_text);
+            return Err(anyhow!("Nowcast request failed: {} - {}", status, error_text));
+        }
+
+        let data: Value = response.json().await?;
+        let minutely = data.get("minutely_15").ok_or_else(|| anyhow!("Missing minutely_15 data"))?;
+        let array = |key: &str| minutely.get(key).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        let times = array("time");
+        let precipitation = array("precipitation");
+
+        Ok(times.iter()
+            .zip(precipitation.iter())
+            .filter_map(|(time, mm)| {
+                let end = chrono::DateTime::from_timestamp(time.as_i64()?, 0)?;
+                Some(NowcastInterval {
+                    start: end - chrono::Duration::minutes(15),
+                    minutes: 15,
+                    precipitation_mm_h: mm.as_f64().unwrap_or(0.0) * 4.0,
+                })
+            })
+            .collect())
+    }
+
+    async fn search_locations(&self, query: &str) -> Result<Vec<LocationCandidate>> {
+        info!("Searching Open-Meteo geocoding for: {}", query);
+
+        let count = MAX_LOCATION_RESULTS.to_string();
+        let request = self.client.get(OPEN_METEO_GEOCODING_URL)
+            .query(&[("name", query), ("count", count.as_str()), ("format", "json")]);
+        let response = send_with_retry(request, &self.retry, None).await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            error!("Geocoding request failed with status {}: {}", status, error_text);
+            return Err(anyhow!("Geocoding request failed: {} - {}", status, error_text));
+        }
+
+        // "results" is missing entirely when nothing matches
+        let data: Value = response.json().await?;
+        let results = data.get("results").and_then(|r| r.as_array()).cloned().unwrap_or_default();
+        Ok(results.iter()
+            .filter_map(|place| Some(LocationCandidate {
+                name: place.get("name")?.as_str()?.to_string(),
+                region: place.get("admin1").and_then(|s| s.as_str()).map(|s| s.to_string()),
+                country: place.get("country_code").and_then(|c| c.as_str()).unwrap_or_default().to_string(),
+                lat: place.get("latitude")?.as_f64()?,
+                lon: place.get("longitude")?.as_f64()?,
+            }))
+            .collect())
+    }
+}
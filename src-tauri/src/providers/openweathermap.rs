@@ -0,0 +1,646 @@
+use super::{day_label, local_today, send_with_retry, synthetic_history, CurrentConditions, OpenMeteoProvider, WeatherProvider, WeatherReport, HOURLY_FORECAST_HOURS, MAX_FORECAST_DAYS, MAX_LOCATION_RESULTS};
+use crate::types::{AlertLevel, ForecastDay, HourlyForecast, LocationCandidate, NowcastInterval, UnitSystem, WeatherAlert};
+use crate::api_usage::ApiUsageTracker;
+use crate::config::HttpRetrySettings;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::{NaiveDate, Timelike};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{info, error, warn};
+
+const OWM_BASE_URL: &str = "https://api.openweathermap.org";
+
+// Key One Call 3.0 last refused. Providers are built per request, so this lives here;
+// a new key gets tried again.
+static ONE_CALL_REFUSED_KEY: Mutex<Option<String>> = Mutex::new(None);
+
+// OpenWeatherMap One Call API 3.0, or the free 2.5 endpoints for keys without a subscription
+pub struct OpenWeatherMapProvider {
+    client: Client,
+    api_key: String,
+    units: UnitSystem,
+    retry: HttpRetrySettings,
+    // Raw responses are saved here for debugging
+    debug_path: Option<PathBuf>,
+    usage: Option<Arc<ApiUsageTracker>>,
+}
+
+impl OpenWeatherMapProvider {
+    pub fn new(client: Client, api_key: String, units: UnitSystem, retry: HttpRetrySettings, debug_path: Option<PathBuf>) -> Self {
+        Self {
+            client,
+            api_key,
+            units,
+            retry,
+            debug_path,
+            usage: None,
+        }
+    }
+
+    // Counts calls against the daily budget and refuses them once it's spent
+    pub fn with_usage_tracker(mut self, usage: Arc<ApiUsageTracker>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    pub fn parse_response(data: &Value, units: UnitSystem) -> Result<WeatherReport> {
+        info!("🔧 PARSING WEATHER RESPONSE");
+
+        // The error names the offending field, e.g. "current.temp: missing field `temp`"
+        let response: OneCallResponse = serde_path_to_error::deserialize(data)
+            .map_err(|e| anyhow!("Unexpected OpenWeatherMap response at {}: {}", e.path(), e.inner()))?;
+        info!("✅ Found daily forecast data with {} entries", response.daily.len());
+
+        // Hour labels and sun times are local to the location
+        let timezone_offset = response.timezone_offset;
+
+        let current = Self::parse_current(&response.current, timezone_offset);
+        let forecast = Self::parse_forecast(&response.daily, timezone_offset);
+        let hourly = Self::parse_hourly(&response.hourly, timezone_offset);
+        let alerts: Vec<WeatherAlert> = response.alerts.iter().filter_map(Self::parse_alert).collect();
+
+        // One Call has no free history; fetch_report replaces this with real data when it can
+        let history = synthetic_history(current.temp, current.humidity, local_today(timezone_offset));
+
+        info!("✅ SUCCESSFULLY PARSED WEATHER DATA");
+        info!("📊 Current: {}°C, {}, {}", current.temp, current.condition, current.icon);
+        info!("📈 History entries: {}", history.len());
+        info!("📅 Forecast entries: {}", forecast.len());
+        info!("🕐 Hourly entries: {}", hourly.len());
+        info!("⚠️  Weather alerts: {}", alerts.len());
+
+        Ok(WeatherReport {
+            units,
+            current,
+            forecast,
+            history,
+            history_synthetic: true,
+            hourly,
+            alerts,
+        })
+    }
+
+    // Fetches and logs a JSON response. Returns None on 401 so the caller can fall back.
+    async fn get_json(&self, url: &str) -> Result<Option<Value>> {
+        info!("Making API request to: {}", url.replace(&self.api_key, "***"));
+
+        let response = send_with_retry(self.client.get(url), &self.retry, self.usage.as_deref()).await?;
+
+        info!("API response status: {}", response.status());
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            error!("API request failed with status {}: {}", status, error_text);
+            return Err(anyhow!("API request failed: {} - {}", status, error_text));
+        }
+
+        let data: Value = response.json().await?;
+        info!("✅ SUCCESSFULLY RECEIVED API RESPONSE");
+
+        // LOG THE COMPLETE API RESPONSE
+        let pretty_json = serde_json::to_string_pretty(&data)?;
+        info!("📋 COMPLETE API RESPONSE:\n{}", pretty_json);
+
+        // Save raw API response to a file for debugging
+        if let Some(debug_path) = &self.debug_path {
+            if let Err(e) = fs::write(debug_path, &pretty_json) {
+                warn!("Failed to save debug API response: {}", e);
+            } else {
+                info!("💾 Saved raw API response to: {:?}", debug_path);
+            }
+        }
+
+        Ok(Some(data))
+    }
+
+    // Current weather and the 5 day / 3 hour forecast, both available on free keys
+    async fn fetch_free_report(&self, lat: f64, lon: f64) -> Result<WeatherReport> {
+        let query = format!("lat={}&lon={}&appid={}&units={}", lat, lon, self.api_key, Self::units_param(self.units));
+        let unauthorized = || anyhow!("OpenWeatherMap rejected the API key (401), check that it is correct and activated");
+
+        let current = self.get_json(&format!("{}/data/2.5/weather?{}", OWM_BASE_URL, query)).await?
+            .ok_or_else(unauthorized)?;
+        let forecast = self.get_json(&format!("{}/data/2.5/forecast?{}", OWM_BASE_URL, query)).await?
+            .ok_or_else(unauthorized)?;
+        Self::parse_free_response(&current, &forecast, self.units)
+    }
+
+    pub fn parse_free_response(current: &Value, forecast: &Value, units: UnitSystem) -> Result<WeatherReport> {
+        info!("🔧 PARSING FREE-TIER WEATHER RESPONSE");
+
+        let (conditions, timezone_offset) = Self::parse_free_current(current)?;
+        let forecast: FreeForecastResponse = serde_path_to_error::deserialize(forecast)
+            .map_err(|e| anyhow!("Unexpected OpenWeatherMap forecast response at {}: {}", e.path(), e.inner()))?;
+
+        let today = local_today(timezone_offset);
+        let daily = Self::parse_free_forecast(&forecast.list, timezone_offset, today);
+        // 3-hour steps stand in for the hourly forecast
+        let hourly = forecast.list.iter()
+            .take(HOURLY_FORECAST_HOURS / 3)
+            .filter_map(|entry| {
+                let local = chrono::DateTime::from_timestamp(entry.dt + timezone_offset, 0)?;
+                Some(HourlyForecast {
+                    time: local.format("%H:%M").to_string(),
+                    dt: entry.dt,
+                    temp: entry.main.temp,
+                    precipitation_probability: entry.pop * 100.0,
+                    icon: OneCallWeather::icon(&entry.weather),
+                })
+            })
+            .collect();
+        let history = synthetic_history(conditions.temp, conditions.humidity, today);
+
+        info!("✅ SUCCESSFULLY PARSED FREE-TIER WEATHER DATA");
+        info!("📅 Forecast entries: {}", daily.len());
+
+        Ok(WeatherReport {
+            units,
+            current: conditions,
+            forecast: daily,
+            history,
+            history_synthetic: true,
+            hourly,
+            alerts: Vec::new(),
+        })
+    }
+
+    fn parse_free_current(current: &Value) -> Result<(CurrentConditions, i64)> {
+        let current: FreeCurrentResponse = serde_path_to_error::deserialize(current)
+            .map_err(|e| anyhow!("Unexpected OpenWeatherMap current weather response at {}: {}", e.path(), e.inner()))?;
+
+        let timezone_offset = current.timezone;
+        let local_time = |time: Option<i64>| time
+            .and_then(|t| chrono::DateTime::from_timestamp(t + timezone_offset, 0))
+            .map(|t| t.format("%H:%M").to_string());
+
+        let conditions = CurrentConditions {
+            condition: current.weather.first()
+                .map(|weather| weather.description.clone())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            icon: OneCallWeather::icon(&current.weather),
+            temp: current.main.temp,
+            humidity: current.main.humidity,
+            pressure: current.main.pressure,
+            wind_speed: current.wind.speed,
+            wind_deg: current.wind.deg,
+            feels_like: current.main.feels_like,
+            wind_gust: current.wind.gust,
+            uv_index: None,
+            visibility: current.visibility,
+            sunrise: local_time(current.sys.sunrise),
+            sunset: local_time(current.sys.sunset),
+            rain_mm: current.rain.as_ref().and_then(|r| r.one_hour),
+            snow_mm: current.snow.as_ref().and_then(|s| s.one_hour),
+        };
+        Ok((conditions, timezone_offset))
+    }
+
+    // Groups 3-hour entries into days: highest temperature, mean humidity and the icon nearest midday
+    fn parse_free_forecast(entries: &[FreeForecastEntry], timezone_offset: i64, today: NaiveDate) -> Vec<ForecastDay> {
+        let mut days: Vec<(NaiveDate, Vec<(u32, &FreeForecastEntry)>)> = Vec::new();
+        for entry in entries {
+            let Some(local) = chrono::DateTime::from_timestamp(entry.dt + timezone_offset, 0) else {
+                continue;
+            };
+            let date = local.date_naive();
+            match days.last_mut() {
+                Some((last, group)) if *last == date => group.push((local.hour(), entry)),
+                _ => days.push((date, vec![(local.hour(), entry)])),
+            }
+        }
+
+        days.into_iter()
+            .take(MAX_FORECAST_DAYS)
+            .map(|(date, group)| {
+                let temp = group.iter().map(|(_, e)| e.main.temp_max).fold(f64::MIN, f64::max);
+                let temp_min = group.iter().filter_map(|(_, e)| e.main.temp_min).reduce(f64::min);
+                let humidity = group.iter().map(|(_, e)| e.main.humidity).sum::<i32>() / group.len() as i32;
+                let pop = group.iter().map(|(_, e)| e.pop).fold(0.0, f64::max) * 100.0;
+                let rain_mm = group.iter().filter_map(|(_, e)| e.rain.as_ref()?.three_hours).sum();
+                let snow_mm = group.iter().filter_map(|(_, e)| e.snow.as_ref()?.three_hours).sum();
+                let midday = group.iter().min_by_key(|(hour, _)| (*hour as i32 - 12).abs()).map(|(_, e)| *e);
+                let icon = midday
+                    .map(|e| OneCallWeather::icon(&e.weather))
+                    .unwrap_or_else(|| "unknown".to_string());
+                let feels_like = midday.and_then(|e| e.main.feels_like);
+                ForecastDay {
+                    day: day_label(date, today),
+                    date: date.format("%d/%m").to_string(),
+                    temp,
+                    temp_min,
+                    humidity,
+                    icon,
+                    feels_like,
+                    pop,
+                    rain_mm,
+                    snow_mm,
+                }
+            })
+            .collect()
+    }
+
+    fn units_param(units: UnitSystem) -> &'static str {
+        match units {
+            UnitSystem::Metric => "metric",
+            UnitSystem::Imperial => "imperial",
+        }
+    }
+
+    fn parse_alert(alert: &OneCallAlert) -> Option<WeatherAlert> {
+        Some(WeatherAlert {
+            sender: alert.sender_name.clone(),
+            severity: Self::alert_severity(&alert.event),
+            event: alert.event.clone(),
+            start: chrono::DateTime::from_timestamp(alert.start, 0)?,
+            end: chrono::DateTime::from_timestamp(alert.end, 0)?,
+            description: alert.description.clone(),
+        })
+    }
+
+    // One Call doesn't report a severity, so derive it from the event name
+    fn alert_severity(event: &str) -> AlertLevel {
+        let event = event.to_lowercase();
+        if ["extreme", "emergency", "red"].iter().any(|word| event.contains(word)) {
+            AlertLevel::Emergency
+        } else if ["advisory", "statement", "watch", "yellow"].iter().any(|word| event.contains(word)) {
+            AlertLevel::Info
+        } else {
+            AlertLevel::Warning
+        }
+    }
+
+    fn parse_hourly(hours: &[OneCallHourly], timezone_offset: i64) -> Vec<HourlyForecast> {
+        hours.iter()
+            .take(HOURLY_FORECAST_HOURS)
+            .filter_map(|hour| {
+                let local = chrono::DateTime::from_timestamp(hour.dt + timezone_offset, 0)?;
+                Some(HourlyForecast {
+                    time: local.format("%H:%M").to_string(),
+                    dt: hour.dt,
+                    temp: hour.temp,
+                    // pop is reported as 0..1
+                    precipitation_probability: hour.pop * 100.0,
+                    icon: OneCallWeather::icon(&hour.weather),
+                })
+            })
+            .collect()
+    }
+
+    fn parse_current(current: &OneCallCurrent, timezone_offset: i64) -> CurrentConditions {
+        let local_time = |time: Option<i64>| time
+            .and_then(|t| chrono::DateTime::from_timestamp(t + timezone_offset, 0))
+            .map(|t| t.format("%H:%M").to_string());
+
+        CurrentConditions {
+            condition: current.weather.first()
+                .map(|weather| weather.description.clone())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            icon: OneCallWeather::icon(&current.weather),
+            temp: current.temp,
+            humidity: current.humidity,
+            pressure: current.pressure,
+            wind_speed: current.wind_speed,
+            wind_deg: current.wind_deg,
+            feels_like: current.feels_like,
+            wind_gust: current.wind_gust,
+            uv_index: current.uvi,
+            visibility: current.visibility,
+            sunrise: local_time(current.sunrise),
+            sunset: local_time(current.sunset),
+            rain_mm: current.rain.as_ref().and_then(|r| r.one_hour),
+            snow_mm: current.snow.as_ref().and_then(|s| s.one_hour),
+        }
+    }
+
+    // dt is midday UTC-based; shifting by the location's offset gives its local calendar day
+    fn parse_forecast(daily: &[OneCallDaily], timezone_offset: i64) -> Vec<ForecastDay> {
+        let mut forecast = Vec::new();
+        let today = local_today(timezone_offset);
+
+        for (i, day_data) in daily.iter().take(MAX_FORECAST_DAYS).enumerate() {
+            let icon = OneCallWeather::icon(&day_data.weather);
+
+            // Date format: DD/MM (e.g. 31/12)
+            let local = chrono::DateTime::from_timestamp(day_data.dt + timezone_offset, 0).filter(|_| day_data.dt > 0);
+            let (day_name, date) = match local {
+                Some(datetime) => (day_label(datetime.date_naive(), today), datetime.format("%d/%m").to_string()),
+                None => (format!("DAY{}", i + 1), "".to_string()),
+            };
+
+            info!("Parsed forecast day {}: {} {} - temp: {} (max), humidity: {}, icon: {}",
+                  i, day_name, date, day_data.temp.max, day_data.humidity, icon);
+
+            forecast.push(ForecastDay {
+                day: day_name,
+                date,
+                // Use the max temperature for the day
+                temp: day_data.temp.max,
+                temp_min: Some(day_data.temp.min),
+                humidity: day_data.humidity,
+                icon,
+                feels_like: day_data.feels_like.as_ref().map(|f| f.day),
+                // pop is reported as 0..1
+                pop: day_data.pop * 100.0,
+                rain_mm: day_data.rain,
+                snow_mm: day_data.snow,
+            });
+        }
+
+        forecast
+    }
+}
+
+// One Call 3.0 response, only the fields we use. Required fields fail the parse
+// instead of silently becoming zero.
+#[derive(Debug, Deserialize)]
+struct OneCallResponse {
+    #[serde(default)]
+    timezone_offset: i64,
+    current: OneCallCurrent,
+    daily: Vec<OneCallDaily>,
+    #[serde(default)]
+    hourly: Vec<OneCallHourly>,
+    #[serde(default)]
+    alerts: Vec<OneCallAlert>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallCurrent {
+    temp: f64,
+    feels_like: Option<f64>,
+    humidity: i32,
+    pressure: i32,
+    wind_speed: f64,
+    #[serde(default)]
+    wind_deg: f64,
+    wind_gust: Option<f64>,
+    uvi: Option<f64>,
+    visibility: Option<i32>,
+    sunrise: Option<i64>,
+    sunset: Option<i64>,
+    rain: Option<Precipitation>,
+    snow: Option<Precipitation>,
+    #[serde(default)]
+    weather: Vec<OneCallWeather>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallDaily {
+    dt: i64,
+    temp: OneCallDailyTemp,
+    feels_like: Option<OneCallDailyFeelsLike>,
+    humidity: i32,
+    #[serde(default)]
+    pop: f64,
+    // Daily totals in mm, absent when none is expected
+    #[serde(default)]
+    rain: f64,
+    #[serde(default)]
+    snow: f64,
+    #[serde(default)]
+    weather: Vec<OneCallWeather>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallDailyTemp {
+    max: f64,
+    min: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallDailyFeelsLike {
+    day: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallHourly {
+    dt: i64,
+    temp: f64,
+    #[serde(default)]
+    pop: f64,
+    #[serde(default)]
+    weather: Vec<OneCallWeather>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallMinutely {
+    dt: i64,
+    // mm/h
+    #[serde(default)]
+    precipitation: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallWeather {
+    #[serde(default)]
+    description: String,
+    icon: String,
+}
+
+impl OneCallWeather {
+    // Icon of the first (primary) weather condition
+    fn icon(weather: &[OneCallWeather]) -> String {
+        weather.first()
+            .map(|weather| weather.icon.clone())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OneCallAlert {
+    #[serde(default)]
+    sender_name: String,
+    event: String,
+    start: i64,
+    end: i64,
+    #[serde(default)]
+    description: String,
+}
+
+// Free-tier /data/2.5/weather response
+#[derive(Debug, Deserialize)]
+struct FreeCurrentResponse {
+    #[serde(default)]
+    timezone: i64,
+    main: FreeMain,
+    wind: FreeWind,
+    visibility: Option<i32>,
+    sys: FreeSys,
+    rain: Option<Precipitation>,
+    snow: Option<Precipitation>,
+    #[serde(default)]
+    weather: Vec<OneCallWeather>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FreeMain {
+    temp: f64,
+    feels_like: Option<f64>,
+    #[serde(default)]
+    temp_max: f64,
+    temp_min: Option<f64>,
+    humidity: i32,
+    pressure: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct FreeWind {
+    speed: f64,
+    #[serde(default)]
+    deg: f64,
+    gust: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FreeSys {
+    sunrise: Option<i64>,
+    sunset: Option<i64>,
+}
+
+// Free-tier /data/2.5/forecast response, 3-hour steps for 5 days
+#[derive(Debug, Deserialize)]
+struct FreeForecastResponse {
+    list: Vec<FreeForecastEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FreeForecastEntry {
+    dt: i64,
+    main: FreeMain,
+    #[serde(default)]
+    pop: f64,
+    rain: Option<Precipitation>,
+    snow: Option<Precipitation>,
+    #[serde(default)]
+    weather: Vec<OneCallWeather>,
+}
+
+// Rain or snow volume in mm, e.g. {"1h": 0.3} or {"3h": 1.2}
+#[derive(Debug, Deserialize)]
+struct Precipitation {
+    #[serde(rename = "1h")]
+    one_hour: Option<f64>,
+    #[serde(rename = "3h")]
+    three_hours: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodingResult {
+    name: String,
+    state: Option<String>,
+    #[serde(default)]
+    country: String,
+    lat: f64,
+    lon: f64,
+}
+
+#[async_trait]
+impl WeatherProvider for OpenWeatherMapProvider {
+    fn name(&self) -> &'static str {
+        "OpenWeatherMap"
+    }
+
+    async fn fetch_report(&self, lat: f64, lon: f64) -> Result<WeatherReport> {
+        info!("🌤️  CALLING OPENWEATHERMAP API!");
+        info!("Fetching weather data for coordinates: {}, {}", lat, lon);
+
+        let url = format!(
+            "{}/data/3.0/onecall?lat={}&lon={}&appid={}&units={}&exclude=minutely",
+            OWM_BASE_URL, lat, lon, self.api_key, Self::units_param(self.units)
+        );
+
+        // Free keys aren't subscribed to One Call 3.0 and get a 401. Remembered so each
+        // fetch doesn't spend a call on it.
+        let refused = ONE_CALL_REFUSED_KEY.lock().unwrap().as_deref() == Some(self.api_key.as_str());
+        let one_call = if refused { None } else { self.get_json(&url).await? };
+        let mut report = match one_call {
+            Some(data) => Self::parse_response(&data, self.units)?,
+            None => {
+                if !refused {
+                    warn!("One Call 3.0 rejected the API key, using the free 2.5 endpoints from now on");
+                    *ONE_CALL_REFUSED_KEY.lock().unwrap() = Some(self.api_key.clone());
+                }
+                self.fetch_free_report(lat, lon).await?
+            }
+        };
+
+        // The timemachine API requires a paid subscription, so take history from Open-Meteo
+        match OpenMeteoProvider::new(self.client.clone(), self.units, self.retry.clone()).fetch_daily_history(lat, lon).await {
+            Ok(history) if !history.is_empty() => {
+                info!("📈 Using {} days of real history from Open-Meteo", history.len());
+                report.history = history;
+                report.history_synthetic = false;
+            }
+            Ok(_) => warn!("No real history available, keeping synthetic history"),
+            Err(e) => warn!("Failed to fetch real history, keeping synthetic history: {}", e),
+        }
+
+        Ok(report)
+    }
+
+    // The small 2.5 current weather endpoint, available on every key
+    async fn fetch_current(&self, lat: f64, lon: f64) -> Result<CurrentConditions> {
+        let url = format!(
+            "{}/data/2.5/weather?lat={}&lon={}&appid={}&units={}",
+            OWM_BASE_URL, lat, lon, self.api_key, Self::units_param(self.units)
+        );
+        let data = self.get_json(&url).await?
+            .ok_or_else(|| anyhow!("OpenWeatherMap rejected the API key (401), check that it is correct and activated"))?;
+        Ok(Self::parse_free_current(&data)?.0)
+    }
+
+    // One Call minutely data: one hour of per-minute rates, subscribers only
+    async fn fetch_nowcast(&self, lat: f64, lon: f64) -> Result<Vec<NowcastInterval>> {
+        let url = format!(
+            "{}/data/3.0/onecall?lat={}&lon={}&appid={}&exclude=current,hourly,daily,alerts",
+            OWM_BASE_URL, lat, lon, self.api_key
+        );
+        let data = self.get_json(&url).await?
+            .ok_or_else(|| anyhow!("The precipitation nowcast needs a One Call 3.0 subscription"))?;
+        let minutely: Vec<OneCallMinutely> = serde_json::from_value(data.get("minutely").cloned().unwrap_or_default())
+            .unwrap_or_default();
+
+        Ok(minutely.into_iter()
+            .filter_map(|minute| Some(NowcastInterval {
+                start: chrono::DateTime::from_timestamp(minute.dt, 0)?,
+                minutes: 1,
+                precipitation_mm_h: minute.precipitation,
+            }))
+            .collect())
+    }
+
+    async fn search_locations(&self, query: &str) -> Result<Vec<LocationCandidate>> {
+        info!("Searching OpenWeatherMap geocoding for: {}", query);
+
+        let limit = MAX_LOCATION_RESULTS.to_string();
+        let request = self.client.get(format!("{}/geo/1.0/direct", OWM_BASE_URL))
+            .query(&[("q", query), ("limit", limit.as_str()), ("appid", self.api_key.as_str())]);
+        let response = send_with_retry(request, &self.retry, self.usage.as_deref()).await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            error!("Geocoding request failed with status {}: {}", status, error_text);
+            return Err(anyhow!("Geocoding request failed: {} - {}", status, error_text));
+        }
+
+        let results: Vec<GeocodingResult> = response.json().await?;
+        Ok(results.into_iter()
+            .map(|place| LocationCandidate {
+                name: place.name,
+                region: place.state,
+                country: place.country,
+                lat: place.lat,
+                lon: place.lon,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,121 @@
+use crate::config::{ProxySettings, ProxyType};
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_socks::tcp::Socks5Stream;
+use tracing::{info, error, warn, debug};
+
+const MAX_CONNECT_RESPONSE_BYTES: usize = 8192;
+
+// Local TCP listener that tunnels every accepted connection through the
+// configured proxy. rumqttc connects to the listener instead of the broker.
+pub struct ProxyTunnel {
+    local_addr: SocketAddr,
+    accept_handle: tokio::task::JoinHandle<()>,
+}
+
+impl ProxyTunnel {
+    pub async fn start(settings: ProxySettings, target_host: String, target_port: u16) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let local_addr = listener.local_addr()?;
+
+        info!(
+            "Tunnelling MQTT to {}:{} through {:?} proxy {}:{} (local port {})",
+            target_host, target_port, settings.proxy_type, settings.host, settings.port, local_addr.port()
+        );
+
+        let accept_handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((inbound, _)) => {
+                        let settings = settings.clone();
+                        let target_host = target_host.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = Self::relay(inbound, &settings, &target_host, target_port).await {
+                                warn!("Proxy tunnel closed with error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Proxy tunnel listener failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            accept_handle,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn relay(mut inbound: TcpStream, settings: &ProxySettings, host: &str, port: u16) -> Result<()> {
+        let mut outbound = Self::open(settings, host, port).await?;
+        let (sent, received) = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await?;
+        debug!("Proxy tunnel finished ({} bytes sent, {} bytes received)", sent, received);
+        Ok(())
+    }
+
+    async fn open(settings: &ProxySettings, host: &str, port: u16) -> Result<TcpStream> {
+        let proxy = (settings.host.as_str(), settings.port);
+
+        match settings.proxy_type {
+            ProxyType::Socks5 => {
+                let stream = match (&settings.username, &settings.password) {
+                    (Some(username), Some(password)) => {
+                        Socks5Stream::connect_with_password(proxy, (host, port), username, password).await?
+                    }
+                    _ => Socks5Stream::connect(proxy, (host, port)).await?,
+                };
+                Ok(stream.into_inner())
+            }
+            ProxyType::Http => {
+                let mut stream = TcpStream::connect(proxy).await?;
+                Self::http_connect(&mut stream, settings, host, port).await?;
+                Ok(stream)
+            }
+        }
+    }
+
+    async fn http_connect(stream: &mut TcpStream, settings: &ProxySettings, host: &str, port: u16) -> Result<()> {
+        let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            let credentials = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", username, password));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read the response headers byte by byte so no tunnelled data is consumed
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if stream.read(&mut byte).await? == 0 {
+                return Err(anyhow!("Proxy closed the connection during CONNECT"));
+            }
+            response.push(byte[0]);
+            if response.len() > MAX_CONNECT_RESPONSE_BYTES {
+                return Err(anyhow!("Proxy CONNECT response too large"));
+            }
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some("200") => Ok(()),
+            _ => Err(anyhow!("Proxy CONNECT failed: {}", status_line)),
+        }
+    }
+
+    pub fn shutdown(&self) {
+        self.accept_handle.abort();
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::{ConfigManager, RainAlertSettings};
+use crate::events::{self, AppEvent};
+use crate::mqtt_client::MqttHandle;
+use crate::types::{AlertData, AlertSource, NowcastInterval};
+use crate::weather_api::WeatherApiClient;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+// How often to re-check the settings while polling is disabled
+const DISABLED_RECHECK_SECS: u64 = 60;
+
+// Polls the precipitation nowcast and raises one alert per rain event, shortly before it starts
+pub struct RainMonitor {
+    config_manager: Arc<Mutex<ConfigManager>>,
+    weather_api: Arc<WeatherApiClient>,
+    mqtt_manager: MqttHandle,
+    // Set from the alert (or from rain already falling) until the nowcast turns dry again
+    in_event: bool,
+}
+
+impl RainMonitor {
+    pub fn spawn(
+        config_manager: Arc<Mutex<ConfigManager>>,
+        weather_api: Arc<WeatherApiClient>,
+        mqtt_manager: MqttHandle,
+    ) -> JoinHandle<()> {
+        let mut monitor = Self {
+            config_manager,
+            weather_api,
+            mqtt_manager,
+            in_event: false,
+        };
+        tokio::spawn(async move { monitor.run().await })
+    }
+
+    async fn run(&mut self) {
+        info!("Rain alert monitor started");
+        loop {
+            let (settings, (lat, lon)) = {
+                let config_manager = self.config_manager.lock().await;
+                (config_manager.weather_api_settings().rain_alerts.clone(), config_manager.active_coordinates())
+            };
+
+            if !settings.enabled {
+                self.in_event = false;
+                tokio::time::sleep(Duration::from_secs(DISABLED_RECHECK_SECS)).await;
+                continue;
+            }
+
+            match self.weather_api.fetch_nowcast(lat, lon).await {
+                Ok(nowcast) => self.handle_nowcast(&nowcast, &settings).await,
+                Err(e) => warn!("Nowcast poll failed: {}", e),
+            }
+
+            let interval_secs = u64::from(settings.poll_interval_minutes.max(1)) * 60;
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+        }
+    }
+
+    async fn handle_nowcast(&mut self, nowcast: &[NowcastInterval], settings: &RainAlertSettings) {
+        let now = Utc::now();
+        let horizon = now + chrono::Duration::minutes(i64::from(settings.lead_time_minutes));
+        let Some(start) = rain_start(nowcast, settings.min_intensity_mm_h, now).filter(|start| *start <= horizon) else {
+            // Dry for the whole lead time, so the next rain is a new event
+            self.in_event = false;
+            return;
+        };
+        if self.in_event {
+            return;
+        }
+        self.in_event = true;
+
+        let minutes = (start - now).num_minutes();
+        if minutes <= 0 {
+            // Already raining, e.g. at startup; too late to warn
+            info!("Rain already falling, no rain alert sent");
+            return;
+        }
+
+        let alert = AlertData {
+            message: format!("Rain starting in {} minutes", minutes),
+            level: settings.level.clone(),
+            timestamp: now,
+            id: None,
+        };
+        info!("{}", alert.message);
+        self.raise(&alert).await;
+    }
+
+    async fn raise(&self, alert: &AlertData) {
+        if self.mqtt_manager.is_connected() {
+            if let Err(e) = self.mqtt_manager.send_alert(alert, AlertSource::Nowcast).await {
+                error!("Failed to send rain alert over MQTT: {}", e);
+            }
+        }
+        self.mqtt_manager.alert_channels().notify_desktop(&alert.level, "Rain alert", &alert.message);
+        events::publish(AppEvent::RainAlert(alert.clone()));
+    }
+}
+
+// Start of the first interval at or above `min_intensity` that hasn't ended yet
+fn rain_start(nowcast: &[NowcastInterval], min_intensity: f64, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    nowcast.iter()
+        .filter(|interval| interval.start + chrono::Duration::minutes(i64::from(interval.minutes)) > now)
+        .find(|interval| interval.precipitation_mm_h >= min_intensity)
+        .map(|interval| interval.start)
+}
@@ -0,0 +1,260 @@
+use crate::config::RestApiSettings;
+use crate::events::{self, AppEvent};
+use crate::history::DEFAULT_PAGE_SIZE;
+use crate::mqtt_client::MqttHandle;
+use crate::types::{AlertData, AlertLevel, AlertSource};
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::oneshot;
+use tracing::{info, error, warn};
+
+type ApiResult = std::result::Result<Json<Value>, (StatusCode, String)>;
+
+struct ApiState {
+    mqtt_manager: MqttHandle,
+    token: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct HistoryQuery {
+    device_id: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlertRequest {
+    message: String,
+    level: AlertLevel,
+}
+
+// Serves the same data as the get_sensor_data, get_sensor_history and
+// get_latest_weather_data commands, and sends alerts like send_alert.
+// /ws streams live updates as {"event": "<name>", "data": ...} frames.
+pub struct RestApiServer {
+    settings: RestApiSettings,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    server_handle: tokio::task::JoinHandle<()>,
+}
+
+impl RestApiServer {
+    pub async fn start(settings: RestApiSettings, mqtt_manager: MqttHandle) -> Result<Self> {
+        let listener = TcpListener::bind((settings.bind_address.as_str(), settings.port)).await?;
+        info!("REST API listening on {}", listener.local_addr()?);
+
+        let state = Arc::new(ApiState {
+            mqtt_manager,
+            token: settings.token.clone(),
+        });
+        let router = Router::new()
+            .route("/current", get(current))
+            .route("/sensor/history", get(sensor_history))
+            .route("/weather", get(weather))
+            .route("/alert", post(alert))
+            .route("/ws", get(live))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&state), require_token))
+            .with_state(state);
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server_handle = tokio::spawn(async move {
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                error!("REST API stopped: {}", e);
+            }
+        });
+
+        Ok(Self {
+            settings,
+            shutdown_tx: Some(shutdown_tx),
+            server_handle,
+        })
+    }
+
+    pub fn settings(&self) -> &RestApiSettings {
+        &self.settings
+    }
+
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        let _ = self.server_handle.await;
+        info!("REST API stopped");
+    }
+}
+
+// Compares in constant time so the token can't be guessed byte by byte
+pub(crate) fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Browsers can't set headers on a WebSocket, so /ws also takes ?token=
+fn query_token(request: &Request) -> Option<String> {
+    if request.uri().path() != "/ws" {
+        return None;
+    }
+    let query = request.uri().query()?;
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+async fn require_token(State(state): State<Arc<ApiState>>, request: Request, next: Next) -> Response {
+    let given = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .or_else(|| query_token(&request));
+    match given.as_deref() {
+        Some(token) if !state.token.is_empty() && token_matches(token, &state.token) => next.run(request).await,
+        _ => {
+            warn!("Rejected REST API request to {} without a valid token", request.uri().path());
+            (StatusCode::UNAUTHORIZED, "Missing or invalid bearer token").into_response()
+        }
+    }
+}
+
+async fn current(State(state): State<Arc<ApiState>>) -> ApiResult {
+    Ok(Json(json!(state.mqtt_manager.get_latest_sensor_data())))
+}
+
+async fn weather(State(state): State<Arc<ApiState>>) -> ApiResult {
+    Ok(Json(json!(state.mqtt_manager.get_latest_weather_data())))
+}
+
+async fn sensor_history(State(state): State<Arc<ApiState>>, Query(query): Query<HistoryQuery>) -> ApiResult {
+    let history = state.mqtt_manager.sensor_history();
+    let result = tokio::task::spawn_blocking(move || {
+        history.query(
+            query.device_id.as_deref(),
+            query.from,
+            query.to,
+            query.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+            query.offset.unwrap_or(0),
+        )
+    }).await;
+    match result {
+        Ok(Ok(page)) => Ok(Json(json!(page))),
+        Ok(Err(e)) => {
+            error!("REST API history query failed: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Query failed: {}", e)))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Query failed: {}", e))),
+    }
+}
+
+async fn alert(State(state): State<Arc<ApiState>>, Json(request): Json<AlertRequest>) -> ApiResult {
+    if request.message.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Message must not be empty".to_string()));
+    }
+    info!("Sending alert from the REST API: {} (level: {:?})", request.message, request.level);
+    let alert = AlertData {
+        message: request.message,
+        level: request.level,
+        timestamp: Utc::now(),
+        id: None,
+    };
+    match state.mqtt_manager.send_alert(&alert, AlertSource::RestApi).await {
+        Ok(message_id) => Ok(Json(json!({ "sent": true, "message_id": message_id }))),
+        Err(e) => {
+            error!("Failed to send alert from the REST API: {}", e);
+            Err((StatusCode::SERVICE_UNAVAILABLE, format!("Alert failed: {}", e)))
+        }
+    }
+}
+
+async fn live(State(state): State<Arc<ApiState>>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| stream_live(socket, state))
+}
+
+// The payload of events worth streaming; the rest only matter to the app's own window
+fn live_data(event: &AppEvent) -> Option<Value> {
+    Some(match event {
+        AppEvent::SensorDataUpdated(sensor) => json!(sensor),
+        AppEvent::WeatherDataUpdated(weather) => json!(weather),
+        AppEvent::WeatherAlert(alert) => json!(alert),
+        AppEvent::AlertReceived(alert) | AppEvent::ForecastWarning(alert) | AppEvent::RainAlert(alert) => json!(alert),
+        AppEvent::ConnectionStatus(status) => json!(status),
+        AppEvent::MqttReconnected => json!(true),
+        AppEvent::MqttConnectionLost(lost) => json!(lost),
+        _ => return None,
+    })
+}
+
+fn frame(event: &str, data: Value) -> Message {
+    Message::Text(json!({ "event": event, "data": data }).to_string())
+}
+
+// Starts with the latest reading and weather so a fresh screen isn't empty until the next update
+async fn stream_live(mut socket: WebSocket, state: Arc<ApiState>) {
+    info!("REST API WebSocket client connected");
+    let mut events = events::subscribe();
+    let mut snapshot = Vec::new();
+    if let Some(sensor) = state.mqtt_manager.get_latest_sensor_data() {
+        snapshot.push(frame("sensor-data-updated", json!(sensor)));
+    }
+    if let Some(weather) = state.mqtt_manager.get_latest_weather_data() {
+        snapshot.push(frame("weather-data-updated", json!(weather)));
+    }
+    for message in snapshot {
+        if socket.send(message).await.is_err() {
+            return;
+        }
+    }
+
+    // The status event repeats every few seconds; only changes are streamed
+    let mut last_connection = None;
+    loop {
+        tokio::select! {
+            received = events.recv() => {
+                let event = match received {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("WebSocket client fell behind, dropped {} events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if let AppEvent::ConnectionStatus(status) = &event {
+                    let connection = Some((status.mqtt, status.api));
+                    if connection == last_connection {
+                        continue;
+                    }
+                    last_connection = connection;
+                }
+                let Some(data) = live_data(&event) else {
+                    continue;
+                };
+                if socket.send(frame(event.name(), data)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    info!("REST API WebSocket client disconnected");
+}
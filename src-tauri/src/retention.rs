@@ -0,0 +1,51 @@
+use crate::config::ConfigManager;
+use crate::history::SensorHistory;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tracing::{info, error};
+
+// Downsampling runs every round while retention is enabled; pruning only every
+// prune_interval_hours
+const ROUND_INTERVAL_SECS: u64 = 3600;
+
+// Maintains the sensor history database in the background: rolls old readings
+// into hourly aggregates and applies the retention policy, starting at launch
+pub fn spawn(config_manager: Arc<Mutex<ConfigManager>>, history: Arc<SensorHistory>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        info!("Retention job started");
+        let mut last_prune: Option<Instant> = None;
+        loop {
+            // Settings are re-read every round so changes apply without a restart
+            let settings = config_manager.lock().await.get_config().app.storage.retention.clone();
+            let prune_interval = Duration::from_secs(u64::from(settings.prune_interval_hours.max(1)) * 3600);
+            let prune_due = settings.enabled && last_prune.map_or(true, |at| at.elapsed() >= prune_interval);
+
+            let job_history = Arc::clone(&history);
+            let job_settings = settings.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                if !job_settings.enabled {
+                    return anyhow::Ok(());
+                }
+                job_history.downsample(job_settings.downsample_after_days)?;
+                if prune_due {
+                    job_history.apply_retention(&job_settings)?;
+                }
+                anyhow::Ok(())
+            }).await;
+
+            match result {
+                Ok(Ok(())) => {
+                    if prune_due {
+                        last_prune = Some(Instant::now());
+                    }
+                }
+                Ok(Err(e)) => error!("Sensor history maintenance failed: {}", e),
+                Err(e) => error!("Sensor history maintenance task failed: {}", e),
+            }
+
+            tokio::time::sleep(Duration::from_secs(ROUND_INTERVAL_SECS)).await;
+        }
+    })
+}
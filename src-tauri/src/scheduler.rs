@@ -0,0 +1,273 @@
+use crate::config::{ConfigManager, ScheduledAction, ScheduledTask};
+use crate::daily_summary;
+use crate::mqtt_client::MqttHandle;
+use crate::storage;
+use crate::types::WeatherData;
+use crate::weather_api::WeatherApiClient;
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+// Far enough ahead to reach the next 29 February
+const MAX_SEARCH_DAYS: u32 = 4 * 366;
+
+// Five fields, minute hour day-of-month month day-of-week, each a number, a range, a
+// list, "*" or a step such as "*/15". Numbers only; Sunday is 0 or 7.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    // As in cron, a day matches either field when both are restricted
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let &[minute, hour, day_of_month, month, day_of_week] = fields.as_slice() else {
+            bail!("Expected 5 cron fields (minute hour day month weekday), got '{}'", expression);
+        };
+        let mut days_of_week = parse_field(day_of_week, 0, 7)?;
+        if days_of_week & (1u64 << 7) != 0 {
+            days_of_week |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days_of_month: parse_field(day_of_month, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            days_of_week,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1u64 << date.month()) == 0 {
+            return false;
+        }
+        let day_of_month = self.days_of_month & (1u64 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1u64 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (true, false) => day_of_week,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        }
+    }
+
+    // First matching minute strictly after `after`. Times skipped by a clock change
+    // don't run that day.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local();
+        let mut date = start.date();
+        for _ in 0..MAX_SEARCH_DAYS {
+            if self.matches_date(date) {
+                for hour in (0..24).filter(|hour| self.hours & (1u64 << hour) != 0) {
+                    for minute in (0..60).filter(|minute| self.minutes & (1u64 << minute) != 0) {
+                        let naive = date.and_hms_opt(hour, minute, 0)?;
+                        if naive <= start {
+                            continue;
+                        }
+                        if let Some(at) = Local.from_local_datetime(&naive).earliest() {
+                            return Some(at);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let number = |text: &str| text.parse::<u32>().map_err(|_| anyhow!("Expected a number, got '{}'", text));
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, number(step)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("Step must be at least 1 in '{}'", part);
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (number(start)?, number(end)?)
+        } else {
+            // "5/10" means from 5 to the end, every 10
+            let start = number(range)?;
+            (start, if step > 1 { max } else { start })
+        };
+        if start < min || end > max || start > end {
+            bail!("Expected values between {} and {}, got '{}'", min, max, part);
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1u64 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRun {
+    pub at: DateTime<Utc>,
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledTaskInfo {
+    #[serde(flatten)]
+    pub task: ScheduledTask,
+    // None while disabled or when the expression never matches
+    pub next_run: Option<DateTime<Local>>,
+    // Since the app started
+    pub last_run: Option<TaskRun>,
+}
+
+// Last run of each task by id; not persisted
+static LAST_RUNS: std::sync::Mutex<Option<HashMap<u32, TaskRun>>> = std::sync::Mutex::new(None);
+
+pub fn describe(tasks: &[ScheduledTask]) -> Vec<ScheduledTaskInfo> {
+    let now = Local::now();
+    let last_runs = LAST_RUNS.lock().unwrap();
+    tasks.iter()
+        .map(|task| ScheduledTaskInfo {
+            task: task.clone(),
+            next_run: task.enabled
+                .then(|| CronSchedule::parse(&task.schedule).ok()?.next_after(now))
+                .flatten(),
+            last_run: last_runs.as_ref().and_then(|runs| runs.get(&task.id)).cloned(),
+        })
+        .collect()
+}
+
+// What the actions need to run
+#[derive(Clone)]
+pub struct TaskContext {
+    pub config_manager: Arc<Mutex<ConfigManager>>,
+    pub weather_api: Arc<WeatherApiClient>,
+    pub mqtt_manager: MqttHandle,
+}
+
+// Wakes at the start of every minute and runs the enabled tasks due since the last
+// wake. Runs missed while the app was closed are skipped, not caught up. The built-in
+// daily summary is checked on every wake too; the sun automation and the weather
+// monitors follow sunrise or poll an API, so they keep their own loops.
+pub fn spawn(context: TaskContext) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        info!("Scheduler started");
+        let history = context.mqtt_manager.sensor_history();
+        let alert_channels = context.mqtt_manager.alert_channels();
+        daily_summary::run_due(&context.config_manager, &history, &alert_channels).await;
+        let mut last_check = Local::now();
+        loop {
+            let wait_ms = 60_000 - Local::now().timestamp_millis().rem_euclid(60_000);
+            tokio::time::sleep(Duration::from_millis(wait_ms as u64)).await;
+
+            let now = Local::now();
+            let tasks = context.config_manager.lock().await.scheduled_tasks().to_vec();
+            for task in tasks.into_iter().filter(|task| task.enabled) {
+                let due = match CronSchedule::parse(&task.schedule) {
+                    Ok(schedule) => schedule.next_after(last_check).is_some_and(|at| at <= now),
+                    Err(e) => {
+                        warn!("Skipping scheduled task '{}': {}", task.name, e);
+                        false
+                    }
+                };
+                if due {
+                    // Own task, so a slow action doesn't hold up the others
+                    let context = context.clone();
+                    tokio::spawn(async move {
+                        let _ = run(&context, &task).await;
+                    });
+                }
+            }
+            last_check = now;
+            daily_summary::run_due(&context.config_manager, &history, &alert_channels).await;
+        }
+    })
+}
+
+// Runs the task now and records the result; the message says what was done
+pub async fn run(context: &TaskContext, task: &ScheduledTask) -> Result<String> {
+    info!("Running scheduled task '{}'", task.name);
+    let result = run_action(context, &task.action).await;
+    let (success, message) = match &result {
+        Ok(message) => {
+            info!("Scheduled task '{}': {}", task.name, message);
+            (true, message.clone())
+        }
+        Err(e) => {
+            error!("Scheduled task '{}' failed: {}", task.name, e);
+            (false, e.to_string())
+        }
+    };
+    LAST_RUNS.lock().unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(task.id, TaskRun { at: Utc::now(), success, message });
+    result
+}
+
+async fn run_action(context: &TaskContext, action: &ScheduledAction) -> Result<String> {
+    let (lat, lon) = context.config_manager.lock().await.active_coordinates();
+    match action {
+        ScheduledAction::RefreshWeatherCache => {
+            context.weather_api.invalidate_cache(lat, lon).await?;
+            context.weather_api.ensure_daily_cache(lat, lon).await?;
+            Ok(format!("Weather cache refreshed for {}, {}", lat, lon))
+        }
+        ScheduledAction::PublishWeather => {
+            let weather = cached_weather(context, lat, lon).await?;
+            context.mqtt_manager.publish_weather_data(&weather).await?;
+            Ok("Weather data published".to_string())
+        }
+        ScheduledAction::PublishForecast { topic } => {
+            let weather = cached_weather(context, lat, lon).await?;
+            let payload = serde_json::to_vec(&weather.forecast)?;
+            context.mqtt_manager.publish(topic, payload).await?;
+            Ok(format!("{}-day forecast published to {}", weather.forecast.len(), topic))
+        }
+        ScheduledAction::DailySummary => {
+            let history = context.mqtt_manager.sensor_history();
+            let alert_channels = context.mqtt_manager.alert_channels();
+            // A partial summary stored for today would stop the evening one being sent
+            let yesterday = Local::now().date_naive() - chrono::Duration::days(1);
+            let summary = daily_summary::generate_and_send(&history, &alert_channels, yesterday).await?;
+            Ok(format!("Summary for {} sent, {} readings", summary.date, summary.samples))
+        }
+        ScheduledAction::PruneStorage => {
+            let settings = context.config_manager.lock().await.get_config().app.storage.clone();
+            let report = tokio::task::spawn_blocking(move || storage::prune(&settings)).await??;
+            Ok(format!("Removed {} file(s), {} bytes", report.removed_files.len(), report.freed_bytes))
+        }
+    }
+}
+
+// Today's report, fetched first if it isn't cached yet
+async fn cached_weather(context: &TaskContext, lat: f64, lon: f64) -> Result<WeatherData> {
+    context.weather_api.ensure_daily_cache(lat, lon).await?;
+    context.weather_api.read_cached_weather_only(lat, lon).await?
+        .ok_or_else(|| anyhow!("No weather data cached for {}, {}", lat, lon))
+}
@@ -0,0 +1,299 @@
+use crate::config::ScriptingSettings;
+use crate::events::{self, AppEvent, DesktopAction};
+use crate::mqtt_client::MqttHandle;
+use crate::storage;
+use crate::types::{AlertData, AlertLevel, AlertSource};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+const SCRIPTS_DIR_NAME: &str = "scripts";
+const SCRIPT_EXTENSION: &str = "rhai";
+const HOOKS: [&str; 3] = ["on_sensor_data", "on_weather_update", "on_alert"];
+// The in-memory readings get_history reads from cover two hours
+const MAX_HISTORY_MINUTES: i64 = 120;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_ARRAY_SIZE: usize = 10_000;
+
+static HOST: OnceLock<Mutex<ScriptHost>> = OnceLock::new();
+
+// What a script asked for. Carried out once the hook returns, so scripts never wait
+// on the broker.
+enum ScriptAction {
+    Publish { topic: String, payload: String },
+    Alert(AlertData),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptStatus {
+    pub name: String,
+    // Hooks the script defines
+    pub hooks: Vec<String>,
+    // The compile error, or the last error a hook raised
+    pub error: Option<String>,
+}
+
+struct Script {
+    name: String,
+    ast: Option<AST>,
+    error: Option<String>,
+}
+
+impl Script {
+    fn has_hook(&self, hook: &str) -> bool {
+        self.ast.as_ref().is_some_and(|ast| ast.iter_functions().any(|f| f.name == hook && f.params.len() == 1))
+    }
+
+    fn status(&self) -> ScriptStatus {
+        ScriptStatus {
+            name: self.name.clone(),
+            hooks: HOOKS.iter().filter(|hook| self.has_hook(hook)).map(|hook| hook.to_string()).collect(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+struct ScriptHost {
+    engine: Engine,
+    settings: ScriptingSettings,
+    scripts: Vec<Script>,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+}
+
+pub fn scripts_dir() -> PathBuf {
+    storage::data_dir().join(SCRIPTS_DIR_NAME)
+}
+
+fn host() -> Option<&'static Mutex<ScriptHost>> {
+    HOST.get()
+}
+
+// Only what scripts need: no file or network access beyond these functions
+fn build_engine(mqtt_manager: MqttHandle, runtime: Handle, actions: Arc<Mutex<Vec<ScriptAction>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.disable_symbol("eval");
+    engine.on_print(|text| info!("[script] {}", text));
+    engine.on_debug(|text, _, _| info!("[script] {}", text));
+
+    engine.register_fn("log", |message: &str| info!("[script] {}", message));
+    engine.register_fn("notify", |title: &str, body: &str| {
+        events::perform(DesktopAction::Notify { title: title.to_string(), body: body.to_string() });
+    });
+
+    let pending = Arc::clone(&actions);
+    // Strings go out as-is, anything else as JSON
+    engine.register_fn("publish", move |topic: &str, payload: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        if topic.is_empty() || topic.contains(['+', '#']) {
+            return Err(format!("Invalid topic: {}", topic).into());
+        }
+        let payload = match payload.clone().into_string() {
+            Ok(text) => text,
+            Err(_) => serde_json::to_string(&payload).map_err(|e| e.to_string())?,
+        };
+        pending.lock().unwrap().push(ScriptAction::Publish { topic: topic.to_string(), payload });
+        Ok(())
+    });
+
+    let pending = Arc::clone(&actions);
+    engine.register_fn("alert", move |level: &str, message: &str| -> Result<(), Box<EvalAltResult>> {
+        let level: AlertLevel = serde_json::from_value(serde_json::json!(level))
+            .map_err(|_| format!("Unknown alert level: {}", level))?;
+        pending.lock().unwrap().push(ScriptAction::Alert(AlertData {
+            message: message.to_string(),
+            level,
+            timestamp: chrono::Utc::now(),
+            id: None,
+        }));
+        Ok(())
+    });
+
+    // Readings from the last `minutes`, oldest first
+    engine.register_fn("get_history", move |minutes: i64| -> Result<Array, Box<EvalAltResult>> {
+        let minutes = minutes.clamp(1, MAX_HISTORY_MINUTES) as u32;
+        let readings = runtime.block_on(mqtt_manager.get_recent_sensor_data(minutes, None));
+        readings.iter()
+            .map(rhai::serde::to_dynamic)
+            .collect()
+    });
+
+    engine
+}
+
+fn load_scripts(engine: &Engine) -> Vec<Script> {
+    let dir = scripts_dir();
+    if let Err(e) = fs::create_dir_all(&dir) {
+        warn!("Failed to create scripts directory {:?}: {}", dir, e);
+        return Vec::new();
+    }
+    let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == SCRIPT_EXTENSION))
+            .collect(),
+        Err(e) => {
+            warn!("Failed to read scripts directory {:?}: {}", dir, e);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+
+    paths.into_iter()
+        .map(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let compiled = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| engine.compile(source).map_err(|e| e.to_string()));
+            match compiled {
+                Ok(ast) => {
+                    info!("Loaded script {}", name);
+                    Script { name, ast: Some(ast), error: None }
+                }
+                Err(e) => {
+                    warn!("Failed to load script {}: {}", name, e);
+                    Script { name, ast: None, error: Some(e) }
+                }
+            }
+        })
+        .collect()
+}
+
+impl ScriptHost {
+    fn apply_settings(&mut self, settings: &ScriptingSettings) {
+        let enabling = settings.enabled && !self.settings.enabled;
+        self.engine.set_max_operations(settings.max_operations);
+        self.settings = settings.clone();
+        if enabling {
+            self.scripts = load_scripts(&self.engine);
+        }
+    }
+
+    fn run_hook(&mut self, hook: &str, arg: &Dynamic) -> Vec<ScriptAction> {
+        for script in &mut self.scripts {
+            let Some(ast) = script.ast.as_ref().filter(|_| script.has_hook(hook)) else {
+                continue;
+            };
+            let mut scope = Scope::new();
+            if let Err(e) = self.engine.call_fn::<Dynamic>(&mut scope, ast, hook, (arg.clone(),)) {
+                warn!("Script {} failed in {}: {}", script.name, hook, e);
+                script.error = Some(format!("{}: {}", hook, e));
+            }
+        }
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+}
+
+// Called once at startup, before spawn
+pub fn init(mqtt_manager: MqttHandle, settings: &ScriptingSettings) {
+    let actions = Arc::new(Mutex::new(Vec::new()));
+    let engine = build_engine(mqtt_manager, Handle::current(), Arc::clone(&actions));
+    let mut host = ScriptHost {
+        engine,
+        settings: ScriptingSettings { enabled: false, ..settings.clone() },
+        scripts: Vec::new(),
+        actions,
+    };
+    host.apply_settings(settings);
+    let _ = HOST.set(Mutex::new(host));
+}
+
+pub fn apply_settings(settings: &ScriptingSettings) {
+    if let Some(host) = host() {
+        host.lock().unwrap().apply_settings(settings);
+    }
+}
+
+// Re-reads every script from disk
+pub fn reload() -> Vec<ScriptStatus> {
+    let Some(host) = host() else {
+        return Vec::new();
+    };
+    let mut host = host.lock().unwrap();
+    host.scripts = load_scripts(&host.engine);
+    host.scripts.iter().map(Script::status).collect()
+}
+
+pub fn status() -> Vec<ScriptStatus> {
+    host().map(|host| host.lock().unwrap().scripts.iter().map(Script::status).collect()).unwrap_or_default()
+}
+
+// The hook an event runs and the value passed to it
+fn hook_for(event: &AppEvent) -> Option<(&'static str, Dynamic)> {
+    let (hook, arg) = match event {
+        AppEvent::SensorDataUpdated(sensor) => ("on_sensor_data", rhai::serde::to_dynamic(sensor)),
+        AppEvent::WeatherDataUpdated(weather) => ("on_weather_update", rhai::serde::to_dynamic(weather)),
+        AppEvent::AlertReceived(alert)
+        | AppEvent::ForecastWarning(alert)
+        | AppEvent::RainAlert(alert) => ("on_alert", rhai::serde::to_dynamic(alert)),
+        AppEvent::WeatherAlert(alert) => ("on_alert", rhai::serde::to_dynamic(alert)),
+        _ => return None,
+    };
+    match arg {
+        Ok(arg) => Some((hook, arg)),
+        Err(e) => {
+            warn!("Failed to pass {} to scripts: {}", event.name(), e);
+            None
+        }
+    }
+}
+
+async fn perform(mqtt_manager: &MqttHandle, action: ScriptAction) {
+    match action {
+        ScriptAction::Publish { topic, payload } => {
+            if let Err(e) = mqtt_manager.publish(&topic, payload.into_bytes()).await {
+                warn!("Script publish to {} failed: {}", topic, e);
+            }
+        }
+        ScriptAction::Alert(alert) => {
+            if let Err(e) = mqtt_manager.send_alert(&alert, AlertSource::Script).await {
+                warn!("Script alert failed: {}", e);
+            }
+        }
+    }
+}
+
+// Runs the hooks for each event, one event at a time so scripts see them in order
+pub fn spawn(mqtt_manager: MqttHandle) -> JoinHandle<()> {
+    let mut events = events::subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Scripts fell behind, skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let Some(host) = host() else {
+                continue;
+            };
+            if !host.lock().unwrap().settings.enabled {
+                continue;
+            }
+            let Some((hook, arg)) = hook_for(&event) else {
+                continue;
+            };
+            // Hooks block: they run the script and may read history
+            let actions = match tokio::task::spawn_blocking(move || host.lock().unwrap().run_hook(hook, &arg)).await {
+                Ok(actions) => actions,
+                Err(e) => {
+                    error!("Script hook {} panicked: {}", hook, e);
+                    continue;
+                }
+            };
+            for action in actions {
+                perform(&mqtt_manager, action).await;
+            }
+        }
+    })
+}
@@ -0,0 +1,360 @@
+use crate::config::ConfigManager;
+use crate::logging;
+use crate::mqtt_client::MqttHandle;
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+use tracing::{error, info, warn};
+
+// Used for the systemd unit file and the Windows service name
+pub const SERVICE_NAME: &str = "weather-station";
+const SERVICE_DISPLAY_NAME: &str = "Weather Station";
+
+const INSTALL_ARG: &str = "--install-service";
+const UNINSTALL_ARG: &str = "--uninstall-service";
+// Passed by systemd or the Windows service manager
+const RUN_ARG: &str = "--service";
+
+// The broker is often not up yet when the machine boots
+const CONNECT_RETRY_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceCommand {
+    Install,
+    Uninstall,
+    Run,
+}
+
+pub fn command() -> Option<ServiceCommand> {
+    std::env::args().skip(1).find_map(|arg| match arg.as_str() {
+        INSTALL_ARG => Some(ServiceCommand::Install),
+        UNINSTALL_ARG => Some(ServiceCommand::Uninstall),
+        RUN_ARG => Some(ServiceCommand::Run),
+        _ => None,
+    })
+}
+
+// The backend without a window: connects to the broker and keeps publishing
+pub struct Headless {
+    pub mqtt_manager: MqttHandle,
+    pub config_manager: Arc<Mutex<ConfigManager>>,
+}
+
+impl Headless {
+    async fn start(&self) {
+        let (mqtt_settings, device_settings, (lat, lon)) = {
+            let config_manager = self.config_manager.lock().await;
+            (
+                config_manager.mqtt_settings().clone(),
+                config_manager.device_settings().clone(),
+                config_manager.active_coordinates(),
+            )
+        };
+
+        self.mqtt_manager.set_device_settings(device_settings).await;
+        loop {
+            match self.mqtt_manager.connect(mqtt_settings.clone(), &mqtt_settings.broker_host, mqtt_settings.broker_port).await {
+                Ok(_) => break,
+                Err(e) => {
+                    warn!("Service could not connect to MQTT broker: {}, retrying in {}s", e, CONNECT_RETRY_SECS);
+                    tokio::time::sleep(Duration::from_secs(CONNECT_RETRY_SECS)).await;
+                }
+            }
+        }
+        match self.mqtt_manager.start_automated_weather_publishing(lat, lon).await {
+            Ok(_) => info!("Service publishing weather for {}, {}", lat, lon),
+            Err(e) => error!("Service failed to start automated weather publishing: {}", e),
+        }
+    }
+
+    async fn stop(&self) {
+        if let Err(e) = self.mqtt_manager.disconnect().await {
+            warn!("Failed to disconnect from MQTT broker on shutdown: {}", e);
+        }
+        let writer = self.config_manager.lock().await.writer();
+        if let Err(e) = writer.flush().await {
+            warn!("Failed to save config on shutdown: {}", e);
+        }
+        let _ = tokio::task::spawn_blocking(logging::shutdown_otlp_export).await;
+    }
+
+    // Runs until the shutdown future resolves, even if the broker never comes up
+    async fn run_until(&self, shutdown: impl std::future::Future<Output = ()>) {
+        tokio::pin!(shutdown);
+        let started = tokio::select! {
+            _ = self.start() => true,
+            _ = &mut shutdown => false,
+        };
+        if started {
+            shutdown.await;
+        }
+        info!("Service shutting down");
+        self.stop().await;
+    }
+}
+
+pub async fn run(headless: Headless) -> Result<()> {
+    info!("Running as a background service");
+    #[cfg(windows)]
+    {
+        windows::run(headless)
+    }
+    #[cfg(not(windows))]
+    {
+        headless.run_until(shutdown_signal()).await;
+        Ok(())
+    }
+}
+
+// SIGTERM from systemd, or Ctrl+C when started by hand
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+// Release builds on Windows have no console, so the install messages would go nowhere.
+// Uses the one the command was run from, or opens one.
+#[cfg(windows)]
+pub fn attach_console() {
+    use windows_sys::Win32::System::Console::{AllocConsole, AttachConsole, ATTACH_PARENT_PROCESS};
+
+    // SAFETY: plain Win32 calls without pointers; failure just leaves output unseen
+    unsafe {
+        if AttachConsole(ATTACH_PARENT_PROCESS) == 0 {
+            AllocConsole();
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+#[cfg(target_os = "linux")]
+pub fn install() -> Result<()> {
+    linux::install()
+}
+
+#[cfg(target_os = "linux")]
+pub fn uninstall() -> Result<()> {
+    linux::uninstall()
+}
+
+#[cfg(windows)]
+pub fn install() -> Result<()> {
+    windows::install()
+}
+
+#[cfg(windows)]
+pub fn uninstall() -> Result<()> {
+    windows::uninstall()
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn install() -> Result<()> {
+    Err(anyhow!("Installing as a service is only supported on Linux (systemd) and Windows"))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn uninstall() -> Result<()> {
+    Err(anyhow!("Installing as a service is only supported on Linux (systemd) and Windows"))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    fn unit_path() -> PathBuf {
+        PathBuf::from("/etc/systemd/system").join(format!("{}.service", SERVICE_NAME))
+    }
+
+    fn systemctl(args: &[&str]) -> Result<()> {
+        let status = Command::new("systemctl").args(args).status()?;
+        if !status.success() {
+            return Err(anyhow!("systemctl {} failed with {}", args.join(" "), status));
+        }
+        Ok(())
+    }
+
+    fn unit_file() -> Result<String> {
+        let exe = std::env::current_exe()?;
+        // Run as the user who ran sudo so the service shares their config and history
+        let user = std::env::var("SUDO_USER")
+            .map(|user| format!("User={}\n", user))
+            .unwrap_or_default();
+        Ok(format!(
+            "[Unit]\n\
+             Description={}\n\
+             Wants=network-online.target\n\
+             After=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             ExecStart=\"{}\" {}\n\
+             {}Restart=on-failure\n\
+             RestartSec=10\n\
+             KillSignal=SIGTERM\n\
+             TimeoutStopSec=15\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n",
+            SERVICE_DISPLAY_NAME,
+            exe.display(),
+            RUN_ARG,
+            user
+        ))
+    }
+
+    pub fn install() -> Result<()> {
+        let path = unit_path();
+        fs::write(&path, unit_file()?)
+            .map_err(|e| anyhow!("Failed to write {:?}: {} (run with sudo)", path, e))?;
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", "--now", SERVICE_NAME])?;
+        info!("Installed systemd unit {:?}", path);
+        Ok(())
+    }
+
+    pub fn uninstall() -> Result<()> {
+        let path = unit_path();
+        if !path.exists() {
+            return Err(anyhow!("Service is not installed"));
+        }
+        systemctl(&["disable", "--now", SERVICE_NAME])?;
+        fs::remove_file(&path)
+            .map_err(|e| anyhow!("Failed to remove {:?}: {} (run with sudo)", path, e))?;
+        systemctl(&["daemon-reload"])?;
+        info!("Removed systemd unit {:?}", path);
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::*;
+    use std::ffi::{OsStr, OsString};
+    use std::sync::OnceLock;
+    use tokio::runtime::Handle;
+    use tokio::sync::Notify;
+    use windows_service::service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    // The dispatcher calls service_main on its own thread, with no way to pass arguments
+    static SERVICE: OnceLock<(Handle, Headless)> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn run(headless: Headless) -> Result<()> {
+        if SERVICE.set((Handle::current(), headless)).is_err() {
+            return Err(anyhow!("Service is already running"));
+        }
+        tokio::task::block_in_place(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main))?;
+        Ok(())
+    }
+
+    fn status(state: ServiceState, controls_accepted: ServiceControlAccept) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: Duration::from_secs(15),
+            process_id: None,
+        }
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        if let Err(e) = run_service() {
+            error!("Windows service failed: {}", e);
+        }
+    }
+
+    fn run_service() -> Result<()> {
+        let (handle, headless) = SERVICE.get().ok_or_else(|| anyhow!("Service state not set"))?;
+        let shutdown = Arc::new(Notify::new());
+        let stop = Arc::clone(&shutdown);
+        let status_handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                stop.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+        status_handle.set_service_status(status(
+            ServiceState::Running,
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        ))?;
+        handle.block_on(headless.run_until(shutdown.notified()));
+        status_handle.set_service_status(status(ServiceState::Stopped, ServiceControlAccept::empty()))?;
+        Ok(())
+    }
+
+    pub fn install() -> Result<()> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )
+        .map_err(|e| anyhow!("Failed to open the service manager: {} (run as administrator)", e))?;
+        let info = ServiceInfo {
+            name: OsString::from(SERVICE_NAME),
+            display_name: OsString::from(SERVICE_DISPLAY_NAME),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: std::env::current_exe()?,
+            // LocalSystem has a profile of its own; point it at the installing user's
+            // config and history instead
+            launch_arguments: vec![
+                OsString::from(RUN_ARG),
+                OsString::from(crate::storage::DATA_DIR_ARG),
+                crate::storage::data_dir().into_os_string(),
+            ],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        };
+        let service = manager.create_service(&info, ServiceAccess::START)?;
+        service.start::<&OsStr>(&[])?;
+        info!("Installed Windows service {}", SERVICE_NAME);
+        Ok(())
+    }
+
+    pub fn uninstall() -> Result<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+            .map_err(|e| anyhow!("Failed to open the service manager: {} (run as administrator)", e))?;
+        let service = manager.open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )?;
+        if service.query_status()?.current_state != ServiceState::Stopped {
+            service.stop()?;
+        }
+        service.delete()?;
+        info!("Removed Windows service {}", SERVICE_NAME);
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::config::ConfigManager;
+use crate::events::{self, AppEvent};
+use crate::mqtt_client::MqttHandle;
+use crate::types::{AlertData, AlertLevel, AlertSource, WeatherAlert};
+use crate::weather_api::WeatherApiClient;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+// How often to re-check the settings while polling is disabled
+const DISABLED_RECHECK_SECS: u64 = 60;
+
+// Polls for severe weather warnings in the background and raises each new one
+// once over MQTT and as a desktop notification
+pub struct SevereWeatherMonitor {
+    config_manager: Arc<Mutex<ConfigManager>>,
+    weather_api: Arc<WeatherApiClient>,
+    mqtt_manager: MqttHandle,
+    seen: HashSet<String>,
+}
+
+impl SevereWeatherMonitor {
+    pub fn spawn(
+        config_manager: Arc<Mutex<ConfigManager>>,
+        weather_api: Arc<WeatherApiClient>,
+        mqtt_manager: MqttHandle,
+    ) -> JoinHandle<()> {
+        let mut monitor = Self {
+            config_manager,
+            weather_api,
+            mqtt_manager,
+            seen: HashSet::new(),
+        };
+        tokio::spawn(async move { monitor.run().await })
+    }
+
+    async fn run(&mut self) {
+        info!("Severe weather monitor started");
+        loop {
+            // Settings are re-read every round so changes apply without a restart
+            let (settings, (lat, lon)) = {
+                let config_manager = self.config_manager.lock().await;
+                (config_manager.weather_api_settings().severe_weather.clone(), config_manager.active_coordinates())
+            };
+
+            if !settings.enabled {
+                tokio::time::sleep(Duration::from_secs(DISABLED_RECHECK_SECS)).await;
+                continue;
+            }
+
+            match self.weather_api.poll_alerts(lat, lon).await {
+                Ok(alerts) => self.handle_alerts(&alerts).await,
+                Err(e) => warn!("Severe weather poll failed: {}", e),
+            }
+
+            let interval_secs = u64::from(settings.poll_interval_minutes.max(1)) * 60;
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+        }
+    }
+
+    async fn handle_alerts(&mut self, alerts: &[WeatherAlert]) {
+        let now = chrono::Utc::now();
+        for weather_alert in alerts.iter().filter(|a| a.severity != AlertLevel::Info && a.is_active(now)) {
+            if !self.seen.insert(weather_alert.id()) {
+                continue;
+            }
+            info!("New severe weather alert: {} ({})", weather_alert.event, weather_alert.sender);
+            self.send_mqtt_alert(weather_alert).await;
+            self.notify_desktop(weather_alert).await;
+            events::publish(AppEvent::WeatherAlert(weather_alert.clone()));
+        }
+
+        // Forget alerts that are no longer reported so the set doesn't grow forever
+        self.seen.retain(|id| alerts.iter().any(|a| a.id() == *id));
+    }
+
+    async fn send_mqtt_alert(&self, weather_alert: &WeatherAlert) {
+        let forwarded_by_publisher = self.config_manager.lock().await.mqtt_settings().forward_weather_alerts;
+        if !self.mqtt_manager.is_connected() {
+            return;
+        }
+        // The automated publisher already forwards alerts from the cache this poll refreshed
+        if forwarded_by_publisher && self.mqtt_manager.is_auto_publishing() {
+            return;
+        }
+
+        let alert = AlertData {
+            message: format!("{} until {}", weather_alert.event, weather_alert.end.with_timezone(&chrono::Local).format("%d/%m %H:%M")),
+            level: weather_alert.severity.clone(),
+            timestamp: chrono::Utc::now(),
+            id: None,
+        };
+        if let Err(e) = self.mqtt_manager.send_alert(&alert, AlertSource::SevereWeather).await {
+            error!("Failed to send severe weather alert over MQTT: {}", e);
+        }
+    }
+
+    async fn notify_desktop(&self, weather_alert: &WeatherAlert) {
+        let alert_channels = self.mqtt_manager.alert_channels();
+        alert_channels.notify_desktop(
+            &weather_alert.severity,
+            &format!("{} ({})", weather_alert.event, weather_alert.sender),
+            &weather_alert.description,
+        );
+    }
+}
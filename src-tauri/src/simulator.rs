@@ -0,0 +1,172 @@
+use crate::devices;
+use crate::mqtt_client::MqttHandle;
+use crate::supervisor::StoppableTask;
+use crate::types::SENSOR_SCHEMA_VERSION;
+use chrono::{DateTime, Local, Timelike, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+const TASK_NAME: &str = "device simulator";
+const MIN_INTERVAL_SECS: u64 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulatorOptions {
+    pub device_id: String,
+    pub interval_secs: u64,
+    // Chance per reading of a temperature jump, for trying out anomaly handling and alert rules
+    pub spike_probability: f64,
+    // Adds CO2, TVOC and light like an ENV III / TVOC unit
+    pub extended_sensors: bool,
+}
+
+impl Default for SimulatorOptions {
+    fn default() -> Self {
+        Self {
+            device_id: "simulator".to_string(),
+            interval_secs: 5,
+            spike_probability: 0.01,
+            extended_sensors: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulatorStatus {
+    pub options: SimulatorOptions,
+    pub started_at: DateTime<Utc>,
+    pub published: u64,
+    pub last_error: Option<String>,
+}
+
+// Publishes synthetic readings and heartbeats on the topics a real M5Go uses, so
+// they go through the same pipeline as real ones
+pub struct Simulator {
+    task: StoppableTask,
+    status: Arc<Mutex<SimulatorStatus>>,
+    mqtt_manager: MqttHandle,
+}
+
+impl Simulator {
+    pub fn start(mut options: SimulatorOptions, mqtt_manager: MqttHandle) -> Self {
+        options.interval_secs = options.interval_secs.max(MIN_INTERVAL_SECS);
+        options.spike_probability = options.spike_probability.clamp(0.0, 1.0);
+        info!("Starting device simulator as {} every {}s", options.device_id, options.interval_secs);
+
+        let status = Arc::new(Mutex::new(SimulatorStatus {
+            options: options.clone(),
+            started_at: Utc::now(),
+            published: 0,
+            last_error: None,
+        }));
+        let task_status = Arc::clone(&status);
+        let task_mqtt = mqtt_manager.clone();
+        let task = StoppableTask::spawn(TASK_NAME, move |cancel| {
+            tokio::spawn(run(options.clone(), task_mqtt.clone(), Arc::clone(&task_status), cancel))
+        });
+        Self { task, status, mqtt_manager }
+    }
+
+    pub fn status(&self) -> SimulatorStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    // Stops publishing and marks the simulated device offline
+    pub async fn stop(self) {
+        let device_id = self.status().options.device_id;
+        self.task.stop().await;
+        if let Err(e) = self.mqtt_manager.publish(&devices::device_topic(&device_id, "status"), b"offline".to_vec()).await {
+            warn!("Failed to mark the simulated device offline: {}", e);
+        }
+        info!("Device simulator stopped");
+    }
+}
+
+async fn run(options: SimulatorOptions, mqtt_manager: MqttHandle, status: Arc<Mutex<SimulatorStatus>>, cancel: CancellationToken) {
+    let mut weather = SimulatedWeather::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(options.interval_secs));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let heartbeat = json!({ "status": "online", "firmware_version": "simulator" }).to_string();
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = cancel.cancelled() => break,
+        }
+        let reading = weather.next(&options);
+        let result = async {
+            mqtt_manager.publish(&devices::device_topic(&options.device_id, "status"), heartbeat.clone().into_bytes()).await?;
+            mqtt_manager.publish("weather/sensor_data", reading.into_bytes()).await
+        }.await;
+
+        let mut status = status.lock().unwrap();
+        match result {
+            Ok(_) => {
+                status.published += 1;
+                status.last_error = None;
+            }
+            Err(e) => {
+                // Usually not connected yet; the next tick tries again
+                warn!("Device simulator failed to publish: {}", e);
+                status.last_error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+// Values follow the time of day: warmest mid-afternoon, most humid before dawn, light
+// only while the sun is up. Pressure drifts slowly.
+struct SimulatedWeather {
+    pressure: f64,
+    co2: f64,
+}
+
+impl SimulatedWeather {
+    fn new() -> Self {
+        Self { pressure: 1013.0, co2: 500.0 }
+    }
+
+    fn next(&mut self, options: &SimulatorOptions) -> String {
+        let mut rng = rand::thread_rng();
+        let now = Local::now();
+        let hour = now.hour() as f64 + now.minute() as f64 / 60.0;
+        // 1 at 15:00, -1 at 03:00
+        let diurnal = (2.0 * PI * (hour - 9.0) / 24.0).sin();
+
+        let mut temperature = 18.0 + 6.0 * diurnal + rng.gen_range(-0.3..0.3);
+        if rng.gen_bool(options.spike_probability) {
+            temperature += if rng.gen_bool(0.5) { 12.0 } else { -12.0 };
+        }
+        let humidity = (60.0 - 15.0 * diurnal + rng.gen_range(-1.5..1.5)).clamp(5.0, 100.0);
+        self.pressure = (self.pressure + rng.gen_range(-0.15..0.15)).clamp(985.0, 1040.0);
+
+        let mut reading = json!({
+            "temperature": round(temperature),
+            "humidity": round(humidity),
+            "pressure": round(self.pressure),
+            "timestamp": now.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            "schema_version": SENSOR_SCHEMA_VERSION,
+            "device_id": options.device_id,
+            "device_name": "Simulated M5Go",
+        });
+        if options.extended_sensors {
+            // Drifts back towards outdoor levels with the odd burst, like a room being used
+            self.co2 = (self.co2 + (450.0 - self.co2) * 0.05 + rng.gen_range(-10.0..25.0)).clamp(400.0, 2500.0);
+            let daylight = (PI * (hour - 6.0) / 12.0).sin().max(0.0);
+            reading["co2"] = json!(self.co2.round());
+            reading["tvoc"] = json!(((self.co2 - 400.0) * 0.4 + rng.gen_range(0.0..20.0)).round());
+            reading["lux"] = json!((daylight * 800.0 + rng.gen_range(0.0..15.0)).round());
+        }
+        reading.to_string()
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
@@ -0,0 +1,274 @@
+use crate::config::{AlertRoute, MqttSettings};
+use crate::csv_log::{self, CsvLogSink};
+use crate::influx::{self, InfluxSink};
+use crate::opensensemap;
+use crate::types::{AlertData, AlertDirection, AlertSource, SensorData, WeatherData};
+use crate::wunderground;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, MissedTickBehavior};
+use tracing::{info, debug, error, warn};
+
+// Somewhere readings, weather and alerts are forwarded to, e.g. InfluxDB or webhooks.
+// Handlers run on the MQTT event loop and the publisher, so they queue work instead of
+// waiting on it. Each has a no-op default, so a sink only implements what it stores.
+#[async_trait]
+pub trait DataSink: Send + Sync {
+    // Unique among registered sinks; registering the same name replaces the old one
+    fn name(&self) -> &str;
+
+    fn handle_sensor_data(&self, _reading: &SensorData) {}
+
+    fn handle_weather_data(&self, _weather: &WeatherData) {}
+
+    // Only called for alerts routed to the sink and outside quiet hours
+    fn handle_alert(&self, _alert: &AlertData, _source: AlertSource, _direction: AlertDirection) {}
+
+    // Whether alerts on `route` are handed to this sink
+    fn routed(&self, route: &AlertRoute) -> bool {
+        route.sinks
+    }
+
+    // Flushes anything queued; called once when the sink is removed
+    async fn shutdown(&self) {}
+}
+
+// Kept by the sinks that upload to a public service, for their status commands
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UploadStatus {
+    // Station or box the readings go to
+    pub target: String,
+    pub running: bool,
+    pub uploads: u64,
+    pub failures: u64,
+    pub last_upload_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+// Where an uploader keeps its UploadStatus; None until it has been started once
+pub type UploadStatusSlot = Mutex<Option<UploadStatus>>;
+
+// Uploads the newest reading once per interval, for services that take one reading at
+// a time. Readings in between only replace the pending one, so the interval is also
+// the rate limit.
+pub struct IntervalUploader {
+    name: &'static str,
+    // For log messages, e.g. "Weather Underground"
+    service: &'static str,
+    device_id: Option<String>,
+    status: &'static UploadStatusSlot,
+    pending: Arc<Mutex<Option<SensorData>>>,
+    stop_tx: watch::Sender<bool>,
+    worker_handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl IntervalUploader {
+    // Only readings from `device_id` are uploaded when it is set
+    pub fn start<F, Fut>(
+        name: &'static str,
+        service: &'static str,
+        target: &str,
+        device_id: Option<String>,
+        interval: Duration,
+        status: &'static UploadStatusSlot,
+        upload: F,
+    ) -> Self
+    where
+        F: Fn(SensorData) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        info!("Starting {} uploads to {}", service, target);
+        *status.lock().unwrap() = Some(UploadStatus {
+            target: target.to_string(),
+            running: true,
+            ..Default::default()
+        });
+        let pending = Arc::new(Mutex::new(None));
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let worker_handle = tokio::spawn(Self::run(service, interval, status, Arc::clone(&pending), stop_rx, upload));
+        Self {
+            name,
+            service,
+            device_id,
+            status,
+            pending,
+            stop_tx,
+            worker_handle: Mutex::new(Some(worker_handle)),
+        }
+    }
+
+    async fn run<F, Fut>(
+        service: &'static str,
+        interval: Duration,
+        status: &'static UploadStatusSlot,
+        pending: Arc<Mutex<Option<SensorData>>>,
+        mut stop_rx: watch::Receiver<bool>,
+        upload: F,
+    ) where
+        F: Fn(SensorData) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = stop_rx.changed() => break,
+            }
+            let Some(reading) = pending.lock().unwrap().take() else {
+                continue;
+            };
+            match upload(reading).await {
+                Ok(()) => {
+                    debug!("Uploaded reading to {}", service);
+                    update_status(status, |status| {
+                        status.uploads += 1;
+                        status.last_upload_at = Some(Utc::now());
+                    });
+                }
+                Err(e) => {
+                    warn!("{} upload failed: {}", service, e);
+                    update_status(status, |status| {
+                        status.failures += 1;
+                        status.last_error = Some(e.to_string());
+                        status.last_error_at = Some(Utc::now());
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DataSink for IntervalUploader {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn handle_sensor_data(&self, reading: &SensorData) {
+        if self.device_id.is_some() && reading.device_id != self.device_id {
+            return;
+        }
+        *self.pending.lock().unwrap() = Some(reading.clone());
+    }
+
+    async fn shutdown(&self) {
+        info!("Stopping {} uploads", self.service);
+        let _ = self.stop_tx.send(true);
+        let handle = self.worker_handle.lock().ok().and_then(|mut guard| guard.take());
+        if let Some(handle) = handle {
+            let _ = handle.await;
+        }
+        update_status(self.status, |status| status.running = false);
+    }
+}
+
+fn update_status(slot: &UploadStatusSlot, update: impl FnOnce(&mut UploadStatus)) {
+    if let Some(status) = slot.lock().unwrap().as_mut() {
+        update(status);
+    }
+}
+
+static SINKS: RwLock<Vec<Arc<dyn DataSink>>> = RwLock::new(Vec::new());
+
+// Returns the sink it replaced, which the caller should shut down
+pub fn register(sink: Arc<dyn DataSink>) -> Option<Arc<dyn DataSink>> {
+    let mut sinks = SINKS.write().unwrap();
+    let replaced = sinks.iter()
+        .position(|existing| existing.name() == sink.name())
+        .map(|index| sinks.remove(index));
+    info!("Registered data sink {}", sink.name());
+    sinks.push(sink);
+    replaced
+}
+
+pub fn unregister(name: &str) -> Option<Arc<dyn DataSink>> {
+    let mut sinks = SINKS.write().unwrap();
+    let index = sinks.iter().position(|sink| sink.name() == name)?;
+    info!("Unregistered data sink {}", name);
+    Some(sinks.remove(index))
+}
+
+// Unregisters the sink and waits for it to flush
+pub async fn remove(name: &str) {
+    if let Some(sink) = unregister(name) {
+        sink.shutdown().await;
+    }
+}
+
+// Sinks built from the MQTT settings run only while connected
+pub fn start_configured(settings: &MqttSettings) {
+    if settings.influxdb.enabled {
+        match InfluxSink::start(settings.influxdb.clone()) {
+            Ok(sink) => replace(Arc::new(sink)),
+            Err(e) => error!("Failed to start InfluxDB sink: {}", e),
+        }
+    }
+    if settings.csv_log.enabled {
+        match CsvLogSink::start(&settings.csv_log) {
+            Ok(sink) => replace(Arc::new(sink)),
+            Err(e) => error!("Failed to start CSV log: {}", e),
+        }
+    }
+    if settings.wunderground.enabled {
+        match wunderground::start(settings.wunderground.clone()) {
+            Ok(sink) => replace(Arc::new(sink)),
+            Err(e) => error!("Failed to start Weather Underground uploads: {}", e),
+        }
+    }
+    if settings.opensensemap.enabled {
+        match opensensemap::start(settings.opensensemap.clone()) {
+            Ok(sink) => replace(Arc::new(sink)),
+            Err(e) => error!("Failed to start openSenseMap uploads: {}", e),
+        }
+    }
+}
+
+pub async fn stop_configured() {
+    remove(influx::SINK_NAME).await;
+    remove(csv_log::SINK_NAME).await;
+    remove(wunderground::SINK_NAME).await;
+    remove(opensensemap::SINK_NAME).await;
+}
+
+// Registers without waiting for the replaced sink to flush
+fn replace(sink: Arc<dyn DataSink>) {
+    if let Some(old) = register(sink) {
+        tokio::spawn(async move { old.shutdown().await });
+    }
+}
+
+pub fn names() -> Vec<String> {
+    SINKS.read().unwrap().iter().map(|sink| sink.name().to_string()).collect()
+}
+
+// Handlers run outside the lock, so a sink may register or remove others
+fn snapshot() -> Vec<Arc<dyn DataSink>> {
+    SINKS.read().unwrap().clone()
+}
+
+pub fn sensor_data(reading: &SensorData) {
+    for sink in snapshot() {
+        sink.handle_sensor_data(reading);
+    }
+}
+
+pub fn weather_data(weather: &WeatherData) {
+    for sink in snapshot() {
+        sink.handle_weather_data(weather);
+    }
+}
+
+pub fn alert(alert: &AlertData, source: AlertSource, direction: AlertDirection, route: &AlertRoute) {
+    for sink in snapshot().into_iter().filter(|sink| sink.routed(route)) {
+        sink.handle_alert(alert, source, direction);
+    }
+}
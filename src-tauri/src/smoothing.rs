@@ -0,0 +1,94 @@
+use crate::config::{SmoothingMethod, SmoothingSettings};
+use crate::types::{ReadingQuality, SensorData};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// After a gap this long the device starts over rather than averaging with old values
+const MAX_GAP: Duration = Duration::from_secs(600);
+
+#[derive(Default)]
+struct Filter {
+    window: VecDeque<f64>,
+    average: Option<f64>,
+}
+
+impl Filter {
+    fn apply(&mut self, value: f64, settings: &SmoothingSettings) -> f64 {
+        match settings.method {
+            SmoothingMethod::MovingAverage => {
+                self.window.push_back(value);
+                while self.window.len() > settings.window.max(1) {
+                    self.window.pop_front();
+                }
+                self.window.iter().sum::<f64>() / self.window.len() as f64
+            }
+            SmoothingMethod::Exponential => {
+                let alpha = settings.alpha.clamp(0.0, 1.0);
+                let smoothed = self.average.map_or(value, |average| average + alpha * (value - average));
+                self.average = Some(smoothed);
+                smoothed
+            }
+        }
+    }
+}
+
+struct DeviceFilters {
+    last_reading: Instant,
+    temperature: Filter,
+    humidity: Filter,
+    pressure: Filter,
+    co2: Filter,
+    tvoc: Filter,
+    lux: Filter,
+}
+
+impl DeviceFilters {
+    fn new() -> Self {
+        Self {
+            last_reading: Instant::now(),
+            temperature: Filter::default(),
+            humidity: Filter::default(),
+            pressure: Filter::default(),
+            co2: Filter::default(),
+            tvoc: Filter::default(),
+            lux: Filter::default(),
+        }
+    }
+}
+
+// Filter state per device, so two stations' readings never mix
+#[derive(Default)]
+pub struct SensorSmoother {
+    // Settings the filters were built with; a change starts them over
+    settings: Option<SmoothingSettings>,
+    devices: HashMap<String, DeviceFilters>,
+}
+
+impl SensorSmoother {
+    // Replaces the reading's values with smoothed ones, keeping the reported values as raw
+    pub fn apply(&mut self, sensor: &mut SensorData, settings: &SmoothingSettings) {
+        if self.settings.as_ref() != Some(settings) {
+            self.devices.clear();
+            self.settings = Some(settings.clone());
+        }
+        if !settings.enabled {
+            return;
+        }
+
+        let device = sensor.device_id.clone().unwrap_or_default();
+        let filters = self.devices.entry(device).or_insert_with(DeviceFilters::new);
+        if filters.last_reading.elapsed() > MAX_GAP {
+            *filters = DeviceFilters::new();
+        }
+        filters.last_reading = Instant::now();
+
+        sensor.keep_raw();
+        sensor.temperature = filters.temperature.apply(sensor.temperature, settings);
+        sensor.humidity = filters.humidity.apply(sensor.humidity, settings);
+        sensor.pressure = filters.pressure.apply(sensor.pressure, settings);
+        sensor.co2 = sensor.co2.map(|co2| filters.co2.apply(co2, settings));
+        sensor.tvoc = sensor.tvoc.map(|tvoc| filters.tvoc.apply(tvoc, settings));
+        sensor.lux = sensor.lux.map(|lux| filters.lux.apply(lux, settings));
+        sensor.flag(ReadingQuality::Smoothed);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::autostart;
+use crate::config::{ConfigManager, DeviceSettings, MqttSettings};
+use crate::events::{self, AppEvent};
+use crate::mqtt_client::MqttHandle;
+use crate::weather_api::WeatherApiClient;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, OnceLock};
+use tauri::AppHandle;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+static PROGRESS: OnceLock<std::sync::Mutex<BTreeMap<StartupStep, StartupProgress>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupStep {
+    // Last weather and sensor reading from the history database
+    History,
+    // Today's cached weather for the active location, read into memory
+    WeatherCache,
+    MqttConnect,
+    Publishing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepState {
+    Running,
+    Done,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupProgress {
+    pub step: StartupStep,
+    pub state: StepState,
+    pub message: Option<String>,
+}
+
+fn report(step: StartupStep, state: StepState, message: Option<String>) {
+    let progress = StartupProgress { step, state, message };
+    PROGRESS.get_or_init(Default::default).lock().unwrap().insert(step, progress.clone());
+    events::publish(AppEvent::StartupProgress(progress));
+}
+
+// Where each step got to, for a window that loaded after the events went out
+pub fn progress() -> Vec<StartupProgress> {
+    PROGRESS.get_or_init(Default::default).lock().unwrap().values().cloned().collect()
+}
+
+// Everything startup needs from the config, read under one lock
+struct Plan {
+    launch_at_login: bool,
+    coordinates: (f64, f64),
+    connect: Option<(MqttSettings, HashMap<String, DeviceSettings>)>,
+    publish_at: Option<(f64, f64)>,
+}
+
+// Runs once from setup. The history restore, cache warm-up and MQTT connect don't
+// depend on each other, so they run at the same time.
+pub async fn run(app: AppHandle, config_manager: Arc<Mutex<ConfigManager>>, mqtt_manager: MqttHandle, weather_api: Arc<WeatherApiClient>) {
+    // Progress published before the forwarder subscribes would never reach the webview
+    events::forwarder_ready().await;
+
+    // Launched at login: connect and resume publishing without waiting for the user
+    let launched_at_login = autostart::launched_at_login();
+    let plan = {
+        let config = config_manager.lock().await;
+        let coordinates = config.active_coordinates();
+        Plan {
+            launch_at_login: config.get_config().app.launch_at_login,
+            coordinates,
+            connect: (config.should_auto_connect_mqtt() || launched_at_login)
+                .then(|| (config.mqtt_settings().clone(), config.device_settings().clone())),
+            publish_at: config.auto_publish_coordinates().or(launched_at_login.then_some(coordinates)),
+        }
+    };
+    if let Err(e) = autostart::apply(&app, plan.launch_at_login) {
+        warn!("Failed to update launch at login: {}", e);
+    }
+
+    tokio::join!(
+        restore_history(&mqtt_manager),
+        warm_weather_cache(&weather_api, plan.coordinates),
+        connect_and_publish(&mqtt_manager, plan.connect, plan.publish_at),
+    );
+    info!("Startup finished");
+}
+
+async fn restore_history(mqtt_manager: &MqttHandle) {
+    report(StartupStep::History, StepState::Running, None);
+    mqtt_manager.restore_last_known().await;
+    report(StartupStep::History, StepState::Done, None);
+}
+
+// Only reads the cache; a stale one is refreshed by the first publish or fetch
+async fn warm_weather_cache(weather_api: &WeatherApiClient, (lat, lon): (f64, f64)) {
+    report(StartupStep::WeatherCache, StepState::Running, None);
+    match weather_api.read_cached_weather_only(lat, lon).await {
+        Ok(Some(_)) => report(StartupStep::WeatherCache, StepState::Done, None),
+        Ok(None) => report(StartupStep::WeatherCache, StepState::Skipped, Some("No weather cached for today".to_string())),
+        Err(e) => {
+            warn!("Failed to warm the weather cache: {}", e);
+            report(StartupStep::WeatherCache, StepState::Failed, Some(e.to_string()));
+        }
+    }
+}
+
+async fn connect_and_publish(
+    mqtt_manager: &MqttHandle,
+    connect: Option<(MqttSettings, HashMap<String, DeviceSettings>)>,
+    publish_at: Option<(f64, f64)>,
+) {
+    let Some((mqtt_settings, device_settings)) = connect else {
+        report(StartupStep::MqttConnect, StepState::Skipped, Some("Auto-connect is off".to_string()));
+        report(StartupStep::Publishing, StepState::Skipped, None);
+        return;
+    };
+
+    let (host, port) = (mqtt_settings.broker_host.clone(), mqtt_settings.broker_port);
+    info!("Auto-connecting to MQTT broker: {}:{}", host, port);
+    report(StartupStep::MqttConnect, StepState::Running, Some(format!("{}:{}", host, port)));
+    mqtt_manager.set_device_settings(device_settings).await;
+    match mqtt_manager.connect(mqtt_settings, &host, port).await {
+        Ok(_) => {
+            info!("Auto-connected to MQTT successfully");
+            report(StartupStep::MqttConnect, StepState::Done, None);
+        }
+        Err(e) => {
+            error!("Auto-connect to MQTT failed: {}", e);
+            report(StartupStep::MqttConnect, StepState::Failed, Some(e.to_string()));
+            report(StartupStep::Publishing, StepState::Skipped, Some("Not connected".to_string()));
+            return;
+        }
+    }
+
+    let Some((lat, lon)) = publish_at else {
+        report(StartupStep::Publishing, StepState::Skipped, None);
+        return;
+    };
+    report(StartupStep::Publishing, StepState::Running, None);
+    match mqtt_manager.start_automated_weather_publishing(lat, lon).await {
+        Ok(_) => {
+            info!("Started automated weather publishing at launch for {:.4}, {:.4}", lat, lon);
+            report(StartupStep::Publishing, StepState::Done, None);
+        }
+        Err(e) => {
+            error!("Failed to start automated weather publishing at launch: {}", e);
+            report(StartupStep::Publishing, StepState::Failed, Some(e.to_string()));
+        }
+    }
+}
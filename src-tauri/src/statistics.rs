@@ -0,0 +1,241 @@
+use crate::history::SensorHistory;
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+const DAY_MS: i64 = 86_400_000;
+// How far the "24 hours ago" reading may be from exactly 24 hours
+const DELTA_TOLERANCE_MS: i64 = 3_600_000;
+// A day counts as recorded once readings cover this many distinct hours
+const MIN_COVERED_HOURS: i64 = 6;
+
+// Raw readings plus hourly aggregates for the hours before the oldest raw reading,
+// so records survive pruning. ?1 = device id or NULL, ?2/?3 = range in ms.
+const SAMPLES_CTE: &str = "WITH samples (at, t_min, t_max, t_avg, h_min, h_max, h_avg, p_min, p_max, p_avg, n) AS (
+         SELECT recorded_at, temperature, temperature, temperature, humidity, humidity, humidity,
+                pressure, pressure, pressure, 1
+         FROM sensor_readings
+         WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3
+         UNION ALL
+         SELECT hour_start, temperature_min, temperature_max, temperature_avg, humidity_min, humidity_max, humidity_avg,
+                pressure_min, pressure_max, pressure_avg, samples
+         FROM sensor_hourly
+         WHERE (?1 IS NULL OR device_id = ?1) AND hour_start BETWEEN ?2 AND ?3
+           AND hour_start < (SELECT COALESCE(MIN(recorded_at), ?3 + 1) FROM sensor_readings)
+     )";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extreme {
+    pub value: f64,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayRecord {
+    // Local calendar day
+    pub date: NaiveDate,
+    pub avg_temperature: f64,
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+}
+
+// Latest reading minus the one closest to 24 hours earlier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyChange {
+    pub temperature: f64,
+    pub humidity: f64,
+    pub pressure: f64,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorStatistics {
+    pub samples: u64,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    pub max_temperature: Option<Extreme>,
+    pub min_temperature: Option<Extreme>,
+    pub max_humidity: Option<Extreme>,
+    pub min_humidity: Option<Extreme>,
+    pub max_pressure: Option<Extreme>,
+    pub min_pressure: Option<Extreme>,
+    pub avg_temperature: Option<f64>,
+    pub avg_humidity: Option<f64>,
+    pub avg_pressure: Option<f64>,
+    pub hottest_day: Option<DayRecord>,
+    pub coldest_day: Option<DayRecord>,
+    pub change_24h: Option<DailyChange>,
+}
+
+// Mean of one local calendar day of recorded readings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyAverage {
+    pub date: NaiveDate,
+    pub temperature: f64,
+    pub humidity: f64,
+    pub covered_hours: u32,
+}
+
+// Daily means for [from, to], skipping days with too little data to be representative
+pub fn daily_averages(history: &SensorHistory, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyAverage>> {
+    let local_midnight = |date: NaiveDate| {
+        Local.from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+            .earliest()
+            .map(|t| t.timestamp_millis())
+    };
+    let (Some(from_ms), Some(to_ms)) = (local_midnight(from), local_midnight(to + chrono::Duration::days(1))) else {
+        return Ok(Vec::new());
+    };
+
+    let rows = history.with_connection(|connection| {
+        let mut statement = connection.prepare(&format!(
+            "{} SELECT date(at / 1000, 'unixepoch', 'localtime') AS day,
+                    SUM(t_avg * n) / SUM(n), SUM(h_avg * n) / SUM(n), COUNT(DISTINCT at / 3600000) AS hours
+             FROM samples GROUP BY day HAVING hours >= ?4 ORDER BY day ASC",
+            SAMPLES_CTE
+        ))?;
+        let rows = statement
+            .query_map(params![None::<String>, from_ms, to_ms - 1, MIN_COVERED_HOURS], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?, row.get::<_, f64>(2)?, row.get::<_, i64>(3)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    })?;
+
+    Ok(rows.into_iter()
+        .filter_map(|(day, temperature, humidity, hours)| Some(DailyAverage {
+            date: NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok()?,
+            temperature,
+            humidity,
+            covered_hours: hours as u32,
+        }))
+        .collect())
+}
+
+// Records and averages over the stored history, for a "records" panel
+pub fn compute(
+    history: &SensorHistory,
+    device_id: Option<&str>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<SensorStatistics> {
+    let from_ms = from.map(|t| t.timestamp_millis()).unwrap_or(i64::MIN);
+    let to_ms = to.map(|t| t.timestamp_millis()).unwrap_or(i64::MAX - 1);
+
+    history.with_connection(|connection| {
+        let range = params![device_id, from_ms, to_ms];
+        let (samples, first, last, avg_temperature, avg_humidity, avg_pressure) = connection.query_row(
+            &format!(
+                "{} SELECT COALESCE(SUM(n), 0), MIN(at), MAX(at),
+                        SUM(t_avg * n) / SUM(n), SUM(h_avg * n) / SUM(n), SUM(p_avg * n) / SUM(n)
+                 FROM samples",
+                SAMPLES_CTE
+            ),
+            range,
+            |row| Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, Option<f64>>(3)?,
+                row.get::<_, Option<f64>>(4)?,
+                row.get::<_, Option<f64>>(5)?,
+            )),
+        )?;
+
+        let extreme = |column: &str, order: &str| extreme(connection, column, order, device_id, from_ms, to_ms);
+        Ok(SensorStatistics {
+            samples: samples as u64,
+            first: first.and_then(DateTime::from_timestamp_millis),
+            last: last.and_then(DateTime::from_timestamp_millis),
+            max_temperature: extreme("t_max", "DESC")?,
+            min_temperature: extreme("t_min", "ASC")?,
+            max_humidity: extreme("h_max", "DESC")?,
+            min_humidity: extreme("h_min", "ASC")?,
+            max_pressure: extreme("p_max", "DESC")?,
+            min_pressure: extreme("p_min", "ASC")?,
+            avg_temperature,
+            avg_humidity,
+            avg_pressure,
+            hottest_day: day_record(connection, "DESC", device_id, from_ms, to_ms)?,
+            coldest_day: day_record(connection, "ASC", device_id, from_ms, to_ms)?,
+            change_24h: change_24h(connection, device_id, from_ms, to_ms)?,
+        })
+    })
+}
+
+fn extreme(
+    connection: &Connection,
+    column: &str,
+    order: &str,
+    device_id: Option<&str>,
+    from_ms: i64,
+    to_ms: i64,
+) -> rusqlite::Result<Option<Extreme>> {
+    let row = connection.query_row(
+        &format!("{} SELECT {col}, at FROM samples ORDER BY {col} {order}, at ASC LIMIT 1", SAMPLES_CTE, col = column, order = order),
+        params![device_id, from_ms, to_ms],
+        |row| Ok((row.get::<_, f64>(0)?, row.get::<_, i64>(1)?)),
+    ).optional()?;
+    Ok(row.and_then(|(value, at)| Some(Extreme { value, at: DateTime::from_timestamp_millis(at)? })))
+}
+
+// Day with the highest (DESC) or lowest (ASC) mean temperature
+fn day_record(
+    connection: &Connection,
+    order: &str,
+    device_id: Option<&str>,
+    from_ms: i64,
+    to_ms: i64,
+) -> rusqlite::Result<Option<DayRecord>> {
+    let row = connection.query_row(
+        &format!(
+            "{} SELECT date(at / 1000, 'unixepoch', 'localtime') AS day,
+                    SUM(t_avg * n) / SUM(n) AS mean, MIN(t_min), MAX(t_max)
+             FROM samples GROUP BY day ORDER BY mean {} LIMIT 1",
+            SAMPLES_CTE, order
+        ),
+        params![device_id, from_ms, to_ms],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?, row.get::<_, f64>(2)?, row.get::<_, f64>(3)?)),
+    ).optional()?;
+
+    Ok(row.and_then(|(day, avg_temperature, min_temperature, max_temperature)| {
+        Some(DayRecord {
+            date: NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok()?,
+            avg_temperature,
+            min_temperature,
+            max_temperature,
+        })
+    }))
+}
+
+fn change_24h(
+    connection: &Connection,
+    device_id: Option<&str>,
+    from_ms: i64,
+    to_ms: i64,
+) -> rusqlite::Result<Option<DailyChange>> {
+    let reading_near = |target: i64, tolerance: i64| {
+        connection.query_row(
+            "SELECT recorded_at, temperature, humidity, pressure FROM sensor_readings
+             WHERE (?1 IS NULL OR device_id = ?1) AND recorded_at BETWEEN ?2 AND ?3
+             ORDER BY ABS(recorded_at - ?4) ASC LIMIT 1",
+            params![device_id, target.saturating_sub(tolerance).max(from_ms), target.saturating_add(tolerance).min(to_ms), target],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?, row.get::<_, f64>(2)?, row.get::<_, f64>(3)?)),
+        ).optional()
+    };
+
+    let Some((latest_at, temperature, humidity, pressure)) = reading_near(to_ms.min(Utc::now().timestamp_millis()), DAY_MS)? else {
+        return Ok(None);
+    };
+    let Some((earlier_at, earlier_temperature, earlier_humidity, earlier_pressure)) = reading_near(latest_at - DAY_MS, DELTA_TOLERANCE_MS)? else {
+        return Ok(None);
+    };
+
+    Ok(DateTime::from_timestamp_millis(earlier_at).map(|since| DailyChange {
+        temperature: temperature - earlier_temperature,
+        humidity: humidity - earlier_humidity,
+        pressure: pressure - earlier_pressure,
+        since,
+    }))
+}
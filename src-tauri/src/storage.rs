@@ -0,0 +1,207 @@
+use crate::config::StorageSettings;
+use crate::history::HISTORY_FILE_NAME;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+const CACHE_FILE_PREFIX: &str = "weather_cache";
+const DEBUG_FILE_NAME: &str = "api_response_debug.json";
+const APP_DIR_NAME: &str = "weather-station-desktop";
+const PORTABLE_MARKER_FILE: &str = "portable";
+const PORTABLE_ARG: &str = "--portable";
+const PORTABLE_DATA_DIR: &str = "data";
+pub const DATA_DIR_ARG: &str = "--data-dir";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub name: String,
+    pub path: String,
+    // config, weather_cache, debug, api_usage, sensor_history or other
+    pub kind: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub data_dir: String,
+    pub portable: bool,
+    pub config_path: Option<String>,
+    pub total_bytes: u64,
+    pub files: Vec<StoredFile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub removed_files: Vec<String>,
+    pub freed_bytes: u64,
+}
+
+// Where caches, usage counters and debug dumps live
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = data_dir_override().or_else(portable_dir) {
+        return dir.to_path_buf();
+    }
+    dirs::data_dir()
+        .or_else(|| dirs::home_dir())
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_DIR_NAME)
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    match data_dir_override().or_else(portable_dir) {
+        Some(dir) => Some(dir.to_path_buf()),
+        None => dirs::config_dir().map(|dir| dir.join(APP_DIR_NAME)),
+    }
+}
+
+// In portable mode config, caches and the database all live in a "data" folder next to
+// the executable, e.g. to run from a USB stick. Enabled by a file named "portable"
+// beside the executable or the --portable argument.
+pub fn portable_dir() -> Option<&'static Path> {
+    static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PORTABLE_DIR.get_or_init(|| {
+        let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+        let requested = std::env::args().any(|arg| arg == PORTABLE_ARG) || exe_dir.join(PORTABLE_MARKER_FILE).exists();
+        requested.then(|| exe_dir.join(PORTABLE_DATA_DIR))
+    }).as_deref()
+}
+
+// Config and data both in the folder given after --data-dir. The Windows service runs
+// as LocalSystem and is installed with the installing user's folder so the two share them.
+pub fn data_dir_override() -> Option<&'static Path> {
+    static DATA_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    DATA_DIR.get_or_init(|| {
+        let mut args = std::env::args().skip(1);
+        args.find(|arg| arg == DATA_DIR_ARG)?;
+        args.next().map(PathBuf::from)
+    }).as_deref()
+}
+
+pub fn storage_usage(config_path: Option<&Path>) -> Result<StorageUsage> {
+    let dir = data_dir();
+    let mut files = list_files(&dir)?;
+    if let Some(config_path) = config_path {
+        if let Some(file) = stored_file(config_path) {
+            // The config dir and data dir are the same on some platforms
+            if !files.iter().any(|f| f.path == file.path) {
+                files.push(file);
+            }
+        }
+    }
+    files.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+
+    Ok(StorageUsage {
+        data_dir: dir.display().to_string(),
+        portable: portable_dir().is_some(),
+        config_path: config_path.map(|p| p.display().to_string()),
+        total_bytes: files.iter().map(|f| f.size_bytes).sum(),
+        files,
+    })
+}
+
+// Deletes weather caches and debug dumps past the age limit, then the oldest
+// caches beyond the count limit. The config and usage counters are never touched.
+pub fn prune(settings: &StorageSettings) -> Result<PruneReport> {
+    let dir = data_dir();
+    let mut report = PruneReport::default();
+    if !dir.exists() {
+        return Ok(report);
+    }
+
+    let max_age = Duration::from_secs(u64::from(settings.max_cache_age_days) * 24 * 60 * 60);
+    let now = SystemTime::now();
+    let mut caches: Vec<(PathBuf, SystemTime, u64)> = Vec::new();
+
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let metadata = entry.metadata()?;
+        let modified = metadata.modified().unwrap_or(now);
+        let age = now.duration_since(modified).unwrap_or_default();
+
+        if name == DEBUG_FILE_NAME {
+            let too_big = metadata.len() > settings.max_debug_file_kb * 1024;
+            if age > max_age || too_big {
+                remove(&path, metadata.len(), &mut report);
+            }
+        } else if is_weather_cache(&name) {
+            if age > max_age {
+                remove(&path, metadata.len(), &mut report);
+            } else {
+                caches.push((path, modified, metadata.len()));
+            }
+        }
+    }
+
+    // Newest first, drop everything past the limit
+    caches.sort_by(|a, b| b.1.cmp(&a.1));
+    for (path, _, size) in caches.into_iter().skip(settings.max_cache_files) {
+        remove(&path, size, &mut report);
+    }
+
+    if !report.removed_files.is_empty() {
+        info!("Storage cleanup removed {} file(s), {} bytes", report.removed_files.len(), report.freed_bytes);
+    }
+    Ok(report)
+}
+
+fn remove(path: &Path, size: u64, report: &mut PruneReport) {
+    match fs::remove_file(path) {
+        Ok(_) => {
+            report.removed_files.push(path.display().to_string());
+            report.freed_bytes += size;
+        }
+        Err(e) => warn!("Failed to remove {:?}: {}", path, e),
+    }
+}
+
+fn is_weather_cache(name: &str) -> bool {
+    name.starts_with(CACHE_FILE_PREFIX) && name.ends_with(".json")
+}
+
+fn list_files(dir: &Path) -> Result<Vec<StoredFile>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.extend(stored_file(&path));
+        }
+    }
+    Ok(files)
+}
+
+fn stored_file(path: &Path) -> Option<StoredFile> {
+    let metadata = fs::metadata(path).ok()?;
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let kind = if name == "config.toml" {
+        "config"
+    } else if is_weather_cache(&name) {
+        "weather_cache"
+    } else if name == DEBUG_FILE_NAME {
+        "debug"
+    } else if name == "api_usage.json" {
+        "api_usage"
+    } else if name.starts_with(HISTORY_FILE_NAME) {
+        "sensor_history"
+    } else {
+        "other"
+    };
+
+    Some(StoredFile {
+        name,
+        path: path.display().to_string(),
+        kind: kind.to_string(),
+        size_bytes: metadata.len(),
+        modified: metadata.modified().ok().map(DateTime::<Local>::from),
+    })
+}
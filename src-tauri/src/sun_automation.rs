@@ -0,0 +1,165 @@
+use crate::config::{ConfigManager, SunAutomationSettings};
+use crate::events::{self, AppEvent};
+use crate::mqtt_client::MqttHandle;
+use crate::types::WeatherData;
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveTime, TimeZone};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+// Retained, so a device that boots at night starts in night mode
+pub const NIGHT_MODE_TOPIC: &str = "weather/output/night_mode";
+// Actions run within this long of the scheduled time
+const CHECK_INTERVAL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledSunAction {
+    pub event: SunEvent,
+    // Event time plus its offset
+    pub at: DateTime<Local>,
+    pub actions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SunTimes {
+    pub sunrise: DateTime<Local>,
+    pub sunset: DateTime<Local>,
+    // Between the offset sunset and the offset sunrise
+    pub night: bool,
+    // The next sunrise and sunset, soonest first; empty while the automation is off
+    pub schedule: Vec<ScheduledSunAction>,
+}
+
+// Today's sunrise and sunset from the latest weather report. The report gives local
+// times at the location, taken here as the computer's local time. None in polar day
+// or night, or before the first report.
+fn todays_times(weather: &WeatherData) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    let today = Local::now().date_naive();
+    let at = |time: &Option<String>| {
+        let time = NaiveTime::parse_from_str(time.as_deref()?, "%H:%M").ok()?;
+        Local.from_local_datetime(&today.and_time(time)).earliest()
+    };
+    Some((at(&weather.sunrise)?, at(&weather.sunset)?))
+}
+
+fn offset(minutes: i32) -> ChronoDuration {
+    ChronoDuration::minutes(minutes as i64)
+}
+
+fn is_night(now: DateTime<Local>, sunrise: DateTime<Local>, sunset: DateTime<Local>, settings: &SunAutomationSettings) -> bool {
+    now < sunrise + offset(settings.sunrise_offset_minutes) || now >= sunset + offset(settings.sunset_offset_minutes)
+}
+
+// What the automation does at `event`, as shown in the schedule
+fn actions(event: SunEvent, settings: &SunAutomationSettings) -> Vec<String> {
+    let night = event == SunEvent::Sunset;
+    let mut actions = Vec::new();
+    if settings.device_night_mode {
+        actions.push(format!("Turn device night mode {}", if night { "on" } else { "off" }));
+    }
+    if settings.toggle_dark_mode {
+        actions.push(format!("Switch to the {} theme", if night { "dark" } else { "light" }));
+    }
+    if settings.night_publish_interval_secs > 0 {
+        actions.push(if night {
+            format!("Publish every {} seconds", settings.night_publish_interval_secs)
+        } else {
+            "Publish at the configured interval".to_string()
+        });
+    }
+    actions
+}
+
+pub fn sun_times(weather: &WeatherData, settings: &SunAutomationSettings) -> Option<SunTimes> {
+    let (sunrise, sunset) = todays_times(weather)?;
+    let now = Local::now();
+    let mut schedule = Vec::new();
+    if settings.enabled {
+        for (event, time, minutes) in [
+            (SunEvent::Sunrise, sunrise, settings.sunrise_offset_minutes),
+            (SunEvent::Sunset, sunset, settings.sunset_offset_minutes),
+        ] {
+            // Tomorrow's event is assumed to be at today's time, which is off by a minute or two
+            let mut at = time + offset(minutes);
+            if at <= now {
+                at += ChronoDuration::days(1);
+            }
+            schedule.push(ScheduledSunAction { event, at, actions: actions(event, settings) });
+        }
+        schedule.sort_by_key(|action| action.at);
+    }
+    Some(SunTimes {
+        sunrise,
+        sunset,
+        night: is_night(now, sunrise, sunset, settings),
+        schedule,
+    })
+}
+
+pub fn spawn(config_manager: Arc<Mutex<ConfigManager>>, mqtt_manager: MqttHandle) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        info!("Sun automation started");
+        // Night or day as last applied; None until it has run once, so the current state
+        // is applied on startup and when the automation is switched on
+        let mut applied: Option<bool> = None;
+
+        loop {
+            let settings = config_manager.lock().await.get_config().app.sun_automation.clone();
+            if !settings.enabled {
+                if applied.take().is_some() {
+                    mqtt_manager.set_publish_interval_override(None);
+                }
+            } else if let Some(times) = mqtt_manager.get_latest_weather_data().and_then(|weather| sun_times(&weather, &settings)) {
+                if applied != Some(times.night) {
+                    let event = if times.night { SunEvent::Sunset } else { SunEvent::Sunrise };
+                    info!("Running {:?} automation", event);
+                    // Retried on the next check when the device command can't be sent yet
+                    match apply(times.night, &settings, &config_manager, &mqtt_manager).await {
+                        Ok(()) => applied = Some(times.night),
+                        Err(e) => warn!("Sun automation incomplete, retrying: {}", e),
+                    }
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(CHECK_INTERVAL_SECS)).await;
+        }
+    })
+}
+
+async fn apply(
+    night: bool,
+    settings: &SunAutomationSettings,
+    config_manager: &Arc<Mutex<ConfigManager>>,
+    mqtt_manager: &MqttHandle,
+) -> Result<()> {
+    let interval = Some(settings.night_publish_interval_secs).filter(|secs| night && *secs > 0);
+    mqtt_manager.set_publish_interval_override(interval);
+
+    if settings.toggle_dark_mode {
+        let mut config_manager = config_manager.lock().await;
+        // Left alone when it already matches, so the config isn't rewritten for nothing
+        if config_manager.get_config().app.dark_mode != night {
+            match config_manager.update_field("app.dark_mode", json!(night)).await {
+                Ok(_) => events::publish(AppEvent::DarkModeChanged(night)),
+                Err(e) => error!("Failed to switch dark mode: {}", e),
+            }
+        }
+    }
+
+    if settings.device_night_mode {
+        let payload = json!({ "enabled": night }).to_string().into_bytes();
+        mqtt_manager.publish_retained(NIGHT_MODE_TOPIC, payload).await?;
+    }
+    Ok(())
+}
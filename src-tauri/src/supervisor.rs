@@ -0,0 +1,171 @@
+use crate::app_error::{self, AppErrorCode};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+const INITIAL_RESTART_BACKOFF_SECS: u64 = 1;
+const MAX_RESTART_BACKOFF_SECS: u64 = 60;
+// A task that ran this long before failing starts over at the initial backoff
+const STABLE_RUN_SECS: u64 = 300;
+// How long a stopped task gets to finish its current iteration before it's aborted
+const SHUTDOWN_GRACE_SECS: u64 = 5;
+
+static TASKS: OnceLock<Mutex<BTreeMap<&'static str, TaskHealth>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    // Waiting out the backoff after a panic
+    Restarting,
+    // Returned on its own, e.g. the MQTT event loop after a fatal error
+    Finished,
+    // Stopped by the app, e.g. on disconnect
+    Stopped,
+}
+
+// One supervised task, for the diagnostics view
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskHealth {
+    pub name: String,
+    pub state: TaskState,
+    pub restarts: u32,
+    pub started_at: DateTime<Utc>,
+    pub last_exit: Option<String>,
+    pub last_exit_at: Option<DateTime<Utc>>,
+}
+
+fn tasks() -> &'static Mutex<BTreeMap<&'static str, TaskHealth>> {
+    TASKS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+fn update(name: &'static str, apply: impl FnOnce(&mut TaskHealth)) {
+    let mut tasks = tasks().lock().unwrap();
+    let health = tasks.entry(name).or_insert_with(|| TaskHealth {
+        name: name.to_string(),
+        state: TaskState::Running,
+        restarts: 0,
+        started_at: Utc::now(),
+        last_exit: None,
+        last_exit_at: None,
+    });
+    apply(health);
+}
+
+fn record_exit(name: &'static str, state: TaskState, reason: &str) {
+    update(name, |health| {
+        health.state = state;
+        health.last_exit = Some(reason.to_string());
+        health.last_exit_at = Some(Utc::now());
+    });
+}
+
+pub fn health() -> Vec<TaskHealth> {
+    tasks().lock().unwrap().values().cloned().collect()
+}
+
+pub fn task_state(name: &str) -> Option<TaskState> {
+    tasks().lock().unwrap().get(name).map(|health| health.state)
+}
+
+// Aborts the running attempt when the supervisor itself is aborted, so stopping a
+// supervised task stops the work and not just the watching
+struct Attempt {
+    name: &'static str,
+    handle: JoinHandle<()>,
+    done: bool,
+}
+
+impl Drop for Attempt {
+    fn drop(&mut self) {
+        if !self.done {
+            self.handle.abort();
+            record_exit(self.name, TaskState::Stopped, "Stopped");
+        }
+    }
+}
+
+// Runs the task from `spawn` and starts it again with backoff whenever it panics.
+// A task that returns is left finished; aborting the returned handle stops it.
+// The panic hook has already written a crash report by the time a restart is logged.
+pub fn supervise<F>(name: &'static str, mut spawn: F) -> JoinHandle<()>
+where
+    F: FnMut() -> JoinHandle<()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut backoff_secs = INITIAL_RESTART_BACKOFF_SECS;
+        loop {
+            let started = Instant::now();
+            update(name, |health| {
+                health.state = TaskState::Running;
+                health.started_at = Utc::now();
+            });
+            let mut attempt = Attempt { name, handle: spawn(), done: false };
+            let result = (&mut attempt.handle).await;
+            attempt.done = true;
+
+            match result {
+                Ok(()) => {
+                    info!("Background task '{}' finished", name);
+                    record_exit(name, TaskState::Finished, "Finished");
+                    return;
+                }
+                Err(e) if e.is_panic() => {
+                    if started.elapsed() >= Duration::from_secs(STABLE_RUN_SECS) {
+                        backoff_secs = INITIAL_RESTART_BACKOFF_SECS;
+                    }
+                    error!("Background task '{}' panicked, restarting in {}s", name, backoff_secs);
+                    record_exit(name, TaskState::Restarting, "Panicked, see the crash report");
+                    app_error::report(AppErrorCode::TaskStopped, name, format!("Background task '{}' stopped unexpectedly and is restarting", name), true);
+                }
+                Err(_) => {
+                    warn!("Background task '{}' was cancelled", name);
+                    record_exit(name, TaskState::Stopped, "Cancelled");
+                    return;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
+            backoff_secs = (backoff_secs * 2).min(MAX_RESTART_BACKOFF_SECS);
+            update(name, |health| health.restarts += 1);
+        }
+    })
+}
+
+// A supervised task that is asked to stop through its CancellationToken rather than
+// aborted, so an in-flight publish completes and the task can clean up
+pub struct StoppableTask {
+    name: &'static str,
+    cancel: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+impl StoppableTask {
+    // `spawn` gets the token to watch; a restarted task gets the same one
+    pub fn spawn<F>(name: &'static str, mut spawn: F) -> Self
+    where
+        F: FnMut(CancellationToken) -> JoinHandle<()> + Send + 'static,
+    {
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        let handle = supervise(name, move || spawn(token.clone()));
+        Self { name, cancel, handle }
+    }
+
+    // Waits up to SHUTDOWN_GRACE_SECS for the task to notice, then aborts it
+    pub async fn stop(mut self) {
+        self.cancel.cancel();
+        match timeout(Duration::from_secs(SHUTDOWN_GRACE_SECS), &mut self.handle).await {
+            Ok(_) => record_exit(self.name, TaskState::Stopped, "Stopped"),
+            Err(_) => {
+                warn!("Background task '{}' didn't stop within {}s, aborting it", self.name, SHUTDOWN_GRACE_SECS);
+                self.handle.abort();
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{Result, anyhow};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::menu::{Menu, MenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, Window, WindowEvent};
+use tracing::{info, warn};
+
+const MAIN_WINDOW: &str = "main";
+
+// Mirrors AppSettings.run_in_background for the close handler, which can't wait on the config lock
+static RUN_IN_BACKGROUND: AtomicBool = AtomicBool::new(false);
+
+pub fn set_run_in_background(enabled: bool) {
+    RUN_IN_BACKGROUND.store(enabled, Ordering::SeqCst);
+}
+
+// Tray icon with Show/Quit; a left click also brings the window back
+pub fn setup(app: &AppHandle) -> Result<()> {
+    let show = MenuItem::with_id(app, "show", "Show Weather Station", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show, &quit])?;
+    let icon = app.default_window_icon().cloned().ok_or_else(|| anyhow!("No application icon for the tray"))?;
+
+    TrayIconBuilder::with_id("main")
+        .icon(icon)
+        .tooltip("Weather Station Desktop")
+        .menu(&menu)
+        .on_menu_event(|app, event| match event.id().as_ref() {
+            "show" => show_main_window(app),
+            "quit" => {
+                info!("Quit from the tray");
+                app.exit(0);
+            }
+            _ => {}
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                show_main_window(tray.app_handle());
+            }
+        })
+        .build(app)?;
+    Ok(())
+}
+
+pub fn show_main_window(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        warn!("Main window not found");
+        return;
+    };
+    if let Err(e) = window.unminimize().and_then(|_| window.show()).and_then(|_| window.set_focus()) {
+        warn!("Failed to show the main window: {}", e);
+    }
+}
+
+// Hides instead of closing while running in the background, so the MQTT tasks live on
+pub fn handle_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::CloseRequested { api, .. } = event {
+        if window.label() == MAIN_WINDOW && RUN_IN_BACKGROUND.load(Ordering::SeqCst) {
+            api.prevent_close();
+            match window.hide() {
+                Ok(_) => info!("Window hidden, still running in the background"),
+                Err(e) => warn!("Failed to hide the window: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::config::{TriggerEventSettings, TriggerSettings};
+use crate::daily_summary::DailySummary;
+use crate::devices::DeviceInfo;
+use crate::history::enum_text;
+use crate::sinks::DataSink;
+use crate::types::{AlertData, AlertDirection, AlertSource};
+use crate::webhooks::post_with_retry;
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use tracing::error;
+
+pub const SINK_NAME: &str = "triggers";
+const MAX_RETRIES: u32 = 3;
+const TIMEOUT_SECS: u64 = 10;
+const IFTTT_URL: &str = "https://maker.ifttt.com/trigger";
+
+// What one event sends: a title and message for ntfy, value1-3 for IFTTT
+struct Trigger {
+    title: String,
+    message: String,
+    values: [String; 3],
+    // ntfy tags, shown as emoji in the app
+    tags: &'static str,
+}
+
+// Fires IFTTT Webhooks and ntfy pushes for alerts, devices going offline and the
+// daily summary, each switched on separately
+pub struct TriggerNotifier {
+    client: Client,
+    settings: RwLock<TriggerSettings>,
+}
+
+impl Default for TriggerNotifier {
+    fn default() -> Self {
+        Self {
+            client: Client::new(),
+            settings: RwLock::new(TriggerSettings::default()),
+        }
+    }
+}
+
+impl TriggerNotifier {
+    pub fn set_settings(&self, settings: TriggerSettings) {
+        if let Ok(mut guard) = self.settings.write() {
+            *guard = settings;
+        }
+    }
+
+    fn settings(&self) -> TriggerSettings {
+        self.settings.read().map(|guard| guard.clone()).unwrap_or_default()
+    }
+
+    fn notify_alert(&self, alert: &AlertData) {
+        let settings = self.settings();
+        if alert.level < settings.alert_min_level {
+            return;
+        }
+        let level = enum_text(&alert.level);
+        let trigger = Trigger {
+            title: format!("Weather station {}", level),
+            message: alert.message.clone(),
+            values: [level, alert.message.clone(), alert.timestamp.to_rfc3339()],
+            tags: "warning",
+        };
+        self.fire(&settings, &settings.alert_fired, trigger);
+    }
+
+    pub fn notify_device_offline(&self, device: &DeviceInfo) {
+        let settings = self.settings();
+        let name = device.name.clone().unwrap_or_else(|| device.device_id.clone());
+        let last_seen = device.last_seen.with_timezone(&chrono::Local).format("%d/%m %H:%M");
+        let trigger = Trigger {
+            title: format!("{} went offline", name),
+            message: format!("Last seen {}", last_seen),
+            values: [name, device.device_id.clone(), device.last_seen.to_rfc3339()],
+            tags: "electric_plug",
+        };
+        self.fire(&settings, &settings.device_offline, trigger);
+    }
+
+    pub fn notify_daily_summary(&self, summary: &DailySummary) {
+        let settings = self.settings();
+        let body = summary.notification_body();
+        let trigger = Trigger {
+            title: format!("Weather summary for {}", summary.date.format("%d/%m")),
+            message: body.clone(),
+            values: [summary.date.to_string(), body, summary.alerts.len().to_string()],
+            tags: "sunny",
+        };
+        self.fire(&settings, &settings.daily_summary, trigger);
+    }
+
+    // Sends in the background to each enabled service
+    fn fire(&self, settings: &TriggerSettings, event: &TriggerEventSettings, trigger: Trigger) {
+        if !event.enabled {
+            return;
+        }
+        let client = self.client.clone();
+        let settings = settings.clone();
+        let event = event.clone();
+        tokio::spawn(async move {
+            if settings.ifttt.enabled {
+                if let Err(e) = send_ifttt(&client, &settings, &event, &trigger).await {
+                    error!("Failed to trigger IFTTT event {}: {}", event.ifttt_event, e);
+                }
+            }
+            if settings.ntfy.enabled {
+                if let Err(e) = send_ntfy(&client, &settings, &event, &trigger).await {
+                    error!("Failed to send ntfy push: {}", e);
+                }
+            }
+        });
+    }
+
+    // Fires alert_fired on each enabled service with `settings`, which need not be saved
+    // yet, and waits for the results
+    pub async fn send_test(&self, settings: &TriggerSettings) -> Result<()> {
+        if !settings.ifttt.enabled && !settings.ntfy.enabled {
+            return Err(anyhow!("Neither IFTTT nor ntfy is enabled"));
+        }
+        let trigger = Trigger {
+            title: "Weather station test".to_string(),
+            message: "Test trigger from the weather station".to_string(),
+            values: ["info".to_string(), "Test trigger from the weather station".to_string(), chrono::Utc::now().to_rfc3339()],
+            tags: "white_check_mark",
+        };
+        if settings.ifttt.enabled {
+            send_ifttt(&self.client, settings, &settings.alert_fired, &trigger).await?;
+        }
+        if settings.ntfy.enabled {
+            send_ntfy(&self.client, settings, &settings.alert_fired, &trigger).await?;
+        }
+        Ok(())
+    }
+}
+
+impl DataSink for TriggerNotifier {
+    fn name(&self) -> &str {
+        SINK_NAME
+    }
+
+    // Only alerts raised here, as with email and chat
+    fn handle_alert(&self, alert: &AlertData, _source: AlertSource, direction: AlertDirection) {
+        if direction == AlertDirection::Sent {
+            self.notify_alert(alert);
+        }
+    }
+}
+
+async fn send_ifttt(client: &Client, settings: &TriggerSettings, event: &TriggerEventSettings, trigger: &Trigger) -> Result<()> {
+    if settings.ifttt.key.is_empty() || event.ifttt_event.is_empty() {
+        return Err(anyhow!("IFTTT key and event name must be configured"));
+    }
+    let url = format!("{}/{}/with/key/{}", IFTTT_URL, event.ifttt_event, settings.ifttt.key);
+    let [value1, value2, value3] = &trigger.values;
+    let body = json!({ "value1": value1, "value2": value2, "value3": value3 }).to_string();
+    post_with_retry(client, "IFTTT", &url, &BTreeMap::new(), body, MAX_RETRIES, TIMEOUT_SECS).await
+}
+
+// Publishes as JSON to the server root, which names the topic in the body
+async fn send_ntfy(client: &Client, settings: &TriggerSettings, event: &TriggerEventSettings, trigger: &Trigger) -> Result<()> {
+    let ntfy = &settings.ntfy;
+    if ntfy.topic.is_empty() {
+        return Err(anyhow!("ntfy topic must be configured"));
+    }
+    let mut headers = BTreeMap::new();
+    if !ntfy.token.is_empty() {
+        headers.insert("Authorization".to_string(), format!("Bearer {}", ntfy.token));
+    }
+    let body = json!({
+        "topic": ntfy.topic,
+        "title": trigger.title,
+        "message": trigger.message,
+        "priority": event.ntfy_priority.clamp(1, 5),
+        "tags": [trigger.tags],
+    }).to_string();
+    post_with_retry(client, "ntfy", ntfy.server.trim_end_matches('/'), &headers, body, MAX_RETRIES, TIMEOUT_SECS).await
+}
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use crate::metrics::{ComfortMetrics, PressureTendency};
+use crate::conversions::{self, Unit};
 use chrono::{DateTime, Utc};
 
 // Default timestamp function for when timestamp field is missing
@@ -6,6 +8,12 @@ fn default_timestamp() -> DateTime<Utc> {
     Utc::now()
 }
 
+pub const SENSOR_SCHEMA_VERSION: u32 = 2;
+
+fn default_schema_version() -> u32 {
+    1
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeatherData {
     pub location: String,
@@ -18,19 +26,344 @@ pub struct WeatherData {
     pub current_temp: f64,
     pub humidity: i32,
     pub pressure: i32,
+    #[serde(default)]
+    pub feels_like: Option<f64>,
+    // Same unit as wind_speed
+    #[serde(default)]
+    pub wind_gust: Option<f64>,
+    // Temperatures in °C or °F and wind speed in m/s or mph
+    #[serde(default)]
+    pub units: UnitSystem,
+    // Day labels, date order and decimal separator used in the text fields
+    #[serde(default)]
+    pub locale: Locale,
+    #[serde(default)]
+    pub uv_index: Option<f64>,
+    // Visibility in meters
+    #[serde(default)]
+    pub visibility: Option<i32>,
+    // Local time at the location, e.g. "06:12"
+    #[serde(default)]
+    pub sunrise: Option<String>,
+    #[serde(default)]
+    pub sunset: Option<String>,
+    // Precipitation over the last hour in millimetres
+    #[serde(default)]
+    pub rain_mm: Option<f64>,
+    #[serde(default)]
+    pub snow_mm: Option<f64>,
     pub forecast: Vec<ForecastDay>,
     pub history: Vec<HistoryDay>,
+    // True when history is a placeholder pattern rather than observed data
+    #[serde(default)]
+    pub history_synthetic: bool,
+    // Official warnings issued for the location
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<WeatherAlert>,
+    // Only present when hourly data is enabled in the weather API settings
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hourly: Vec<HourlyForecast>,
+    // Name of the provider that supplied the data, e.g. "Open-Meteo" when the fallback was used
+    #[serde(default)]
+    pub provider: String,
+    // Values that failed the sanity checks; they are published but shouldn't be trusted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quality_flags: Vec<QualityFlag>,
     #[serde(default = "default_timestamp")]
     pub timestamp: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    pub fn temperature_unit(self) -> Unit {
+        match self {
+            UnitSystem::Metric => Unit::Celsius,
+            UnitSystem::Imperial => Unit::Fahrenheit,
+        }
+    }
+
+    pub fn speed_unit(self) -> Unit {
+        match self {
+            UnitSystem::Metric => Unit::MetrePerSecond,
+            UnitSystem::Imperial => Unit::MilePerHour,
+        }
+    }
+
+    pub fn convert_temp(self, value: f64, to: UnitSystem) -> f64 {
+        conversions::convert_same(value, self.temperature_unit(), to.temperature_unit())
+    }
+
+    pub fn convert_speed(self, value: f64, to: UnitSystem) -> f64 {
+        conversions::convert_same(value, self.speed_unit(), to.speed_unit())
+    }
+}
+
+// How day names, dates and decimals are written in text fields. Numeric fields stay
+// plain JSON numbers whatever the locale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Locale {
+    pub language: Language,
+    pub date_order: DateOrder,
+    pub decimal_separator: DecimalSeparator,
+}
+
+// Languages with weekday labels short enough for the device display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    De,
+    Fr,
+    Es,
+    It,
+    Pt,
+    Nl,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateOrder {
+    // "DD/MM"
+    #[default]
+    DayMonth,
+    // "MM/DD"
+    MonthDay,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecimalSeparator {
+    #[default]
+    Point,
+    Comma,
+}
+
+impl Language {
+    // "TODAY" followed by Monday to Sunday, ASCII only for the device font
+    fn day_labels(self) -> [&'static str; 8] {
+        match self {
+            Language::En => ["TODAY", "MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"],
+            Language::De => ["HEUTE", "MO", "DI", "MI", "DO", "FR", "SA", "SO"],
+            Language::Fr => ["AUJ", "LUN", "MAR", "MER", "JEU", "VEN", "SAM", "DIM"],
+            Language::Es => ["HOY", "LUN", "MAR", "MIE", "JUE", "VIE", "SAB", "DOM"],
+            Language::It => ["OGGI", "LUN", "MAR", "MER", "GIO", "VEN", "SAB", "DOM"],
+            Language::Pt => ["HOJE", "SEG", "TER", "QUA", "QUI", "SEX", "SAB", "DOM"],
+            Language::Nl => ["VANDAAG", "MA", "DI", "WO", "DO", "VR", "ZA", "ZO"],
+        }
+    }
+
+    // Translates a label written in this language; unknown labels are kept
+    fn relabel(self, label: &str, to: Language) -> String {
+        match self.day_labels().iter().position(|known| *known == label) {
+            Some(index) => to.day_labels()[index].to_string(),
+            None => label.to_string(),
+        }
+    }
+}
+
+impl DateOrder {
+    // Swaps "DD/MM" and "MM/DD"
+    fn reorder(self, date: &str, to: DateOrder) -> String {
+        match date.split_once('/') {
+            Some((first, second)) if self != to => format!("{}/{}", second, first),
+            _ => date.to_string(),
+        }
+    }
+}
+
+impl Locale {
+    pub fn format_number(&self, value: f64, decimals: usize) -> String {
+        let text = format!("{:.*}", decimals, value);
+        match self.decimal_separator {
+            DecimalSeparator::Point => text,
+            DecimalSeparator::Comma => text.replace('.', ","),
+        }
+    }
+
+    // The location shown when no place name is known
+    pub fn coordinate_label(&self, lat: f64, lon: f64) -> String {
+        // A comma separator would make ", " between the two ambiguous
+        let between = match self.decimal_separator {
+            DecimalSeparator::Point => ", ",
+            DecimalSeparator::Comma => "; ",
+        };
+        format!("LAT: {}{}LON: {}", self.format_number(lat, 4), between, self.format_number(lon, 4))
+    }
+}
+
+impl WeatherData {
+    // Rewrites day labels, dates and the coordinate label in place, like convert_units
+    pub fn apply_locale(&mut self, to: Locale) {
+        let from = self.locale;
+        if from == to {
+            return;
+        }
+
+        for day in &mut self.forecast {
+            day.day = from.language.relabel(&day.day, to.language);
+            day.date = from.date_order.reorder(&day.date, to.date_order);
+        }
+        for day in &mut self.history {
+            day.day = from.language.relabel(&day.day, to.language);
+            day.date = from.date_order.reorder(&day.date, to.date_order);
+        }
+        if self.location == from.coordinate_label(self.gps_lat, self.gps_lon) {
+            self.location = to.coordinate_label(self.gps_lat, self.gps_lon);
+        }
+        self.locale = to;
+    }
+
+    // Converts temperatures and wind speed in place, e.g. for cached data after the setting changed
+    pub fn convert_units(&mut self, to: UnitSystem) {
+        let from = self.units;
+        if from == to {
+            return;
+        }
+
+        let temp = |value: f64| (from.convert_temp(value, to) * 10.0).round() / 10.0;
+        self.current_temp = temp(self.current_temp);
+        self.feels_like = self.feels_like.map(temp);
+        self.wind_speed = (from.convert_speed(self.wind_speed, to) * 10.0).round() / 10.0;
+        self.wind_gust = self.wind_gust.map(|gust| (from.convert_speed(gust, to) * 10.0).round() / 10.0);
+        for day in &mut self.forecast {
+            day.temp = temp(day.temp);
+            day.temp_min = day.temp_min.map(temp);
+            day.feels_like = day.feels_like.map(temp);
+        }
+        for day in &mut self.history {
+            day.temp = temp(day.temp);
+        }
+        for hour in &mut self.hourly {
+            hour.temp = temp(hour.temp);
+        }
+        self.units = to;
+    }
+}
+
+// Current conditions only, for quick refreshes and comparing against the sensors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentWeather {
+    pub condition: String,
+    pub icon: String,
+    pub temp: f64,
+    pub humidity: i32,
+    pub pressure: i32,
+    pub wind_speed: f64,
+    pub wind_direction: String,
+    pub feels_like: Option<f64>,
+    pub wind_gust: Option<f64>,
+    pub uv_index: Option<f64>,
+    pub visibility: Option<i32>,
+    pub units: UnitSystem,
+    pub provider: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+// One row of the multi-location view; error is set when that location couldn't be fetched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationComparison {
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub temp: Option<f64>,
+    pub humidity: Option<i32>,
+    pub condition: Option<String>,
+    pub icon: Option<String>,
+    pub units: UnitSystem,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityFlag {
+    // e.g. "humidity" or "forecast[2].temp"
+    pub field: String,
+    pub reason: String,
+}
+
+// Government weather warning as reported by the weather provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherAlert {
+    pub sender: String,
+    pub event: String,
+    pub severity: AlertLevel,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub description: String,
+}
+
+impl WeatherAlert {
+    // Providers don't give alerts a stable ID, so identify them by issuer, event and start
+    pub fn id(&self) -> String {
+        format!("{}|{}|{}", self.sender, self.event, self.start.timestamp())
+    }
+
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.start <= now && now < self.end
+    }
+}
+
+// Geocoding result offered to the user when picking a location
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationCandidate {
+    pub name: String,
+    // State or region, when the geocoder reports one
+    pub region: Option<String>,
+    pub country: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+// One step of a short-range precipitation nowcast
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NowcastInterval {
+    pub start: DateTime<Utc>,
+    pub minutes: u32,
+    // Rate over the interval in mm/h
+    pub precipitation_mm_h: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourlyForecast {
+    // Local time at the location, e.g. "14:00"
+    pub time: String,
+    // Unix timestamp of the hour
+    pub dt: i64,
+    pub temp: f64,
+    // Chance of precipitation in percent
+    pub precipitation_probability: f64,
+    pub icon: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ForecastDay {
     pub day: String,
     pub date: String,
     pub temp: f64,
+    // Overnight low
+    #[serde(default)]
+    pub temp_min: Option<f64>,
     pub humidity: i32,
     pub icon: String,
+    // Daytime feels-like temperature
+    #[serde(default)]
+    pub feels_like: Option<f64>,
+    // Chance of precipitation in percent
+    #[serde(default)]
+    pub pop: f64,
+    // Daily totals in millimetres
+    #[serde(default)]
+    pub rain_mm: f64,
+    #[serde(default)]
+    pub snow_mm: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,9 +380,233 @@ pub struct SensorData {
     pub humidity: f64,
     pub pressure: f64,
     pub timestamp: String,
+    // Optional readings from ENV III / TVOC units
+    #[serde(default)]
+    pub co2: Option<f64>,
+    #[serde(default)]
+    pub tvoc: Option<f64>,
+    #[serde(default)]
+    pub lux: Option<f64>,
+    // Payloads without a version predate the extended schema
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub device_name: Option<String>,
+    // Values as reported by the device, before calibration and smoothing; None when
+    // neither changed anything
+    #[serde(default)]
+    pub raw: Option<RawSensorValues>,
+    // Set by the app when the reading arrives
+    #[serde(default)]
+    pub received_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub age_secs: Option<i64>,
+    #[serde(default)]
+    pub stale: bool,
+    // Set when the reading changed implausibly fast; such readings are stored but not acted on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anomalies: Vec<QualityFlag>,
+    // Set on arrival; empty for readings stored before quality was recorded
+    #[serde(default)]
+    pub quality: Vec<ReadingQuality>,
+    // Derived by the app on arrival
+    #[serde(default)]
+    pub comfort: Option<ComfortMetrics>,
+    // None until three hours of readings are stored
+    #[serde(default)]
+    pub pressure_tendency: Option<PressureTendency>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawSensorValues {
+    pub temperature: f64,
+    pub humidity: f64,
+    pub pressure: f64,
+    // Only recorded since smoothing; calibration leaves these alone
+    #[serde(default)]
+    pub co2: Option<f64>,
+    #[serde(default)]
+    pub tvoc: Option<f64>,
+    #[serde(default)]
+    pub lux: Option<f64>,
+}
+
+// How far a reading can be trusted. Ok only appears on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadingQuality {
+    Ok,
+    // A value outside its plausible range, or one that changed implausibly fast
+    OutOfRange,
+    // Taken well before it arrived, e.g. buffered on the device while offline
+    Stale,
+    // Filled in by the sender rather than measured; the app keeps it as received
+    Interpolated,
+    // Values are a running average; the reported ones are in raw
+    Smoothed,
+}
+
+impl ReadingQuality {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadingQuality::Ok => "ok",
+            ReadingQuality::OutOfRange => "out_of_range",
+            ReadingQuality::Stale => "stale",
+            ReadingQuality::Interpolated => "interpolated",
+            ReadingQuality::Smoothed => "smoothed",
+        }
+    }
+}
+
+impl SensorData {
+    // Adds a flag, dropping Ok
+    pub fn flag(&mut self, quality: ReadingQuality) {
+        self.quality.retain(|flag| *flag != ReadingQuality::Ok);
+        if !self.quality.contains(&quality) {
+            self.quality.push(quality);
+        }
+    }
+
+    // Whether the values can be acted on. Smoothing only changes how they were arrived
+    // at, so it doesn't count against a reading.
+    pub fn quality_ok(&self) -> bool {
+        self.quality.iter().all(|flag| matches!(flag, ReadingQuality::Ok | ReadingQuality::Smoothed))
+    }
+
+    // Records the reported values before the first adjustment; later adjustments keep them
+    pub fn keep_raw(&mut self) {
+        if self.raw.is_none() {
+            self.raw = Some(RawSensorValues {
+                temperature: self.temperature,
+                humidity: self.humidity,
+                pressure: self.pressure,
+                co2: self.co2,
+                tvoc: self.tvoc,
+                lux: self.lux,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationChange {
+    pub lat: f64,
+    pub lon: f64,
+    pub source: String,
+    pub distance_km: Option<f64>,
+}
+
+// Battery and radio telemetry published on weather/devices/<id>/telemetry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceTelemetry {
+    #[serde(default)]
+    pub device_id: String,
+    pub battery_percent: Option<f64>,
+    #[serde(default)]
+    pub charging: Option<bool>,
+    pub rssi: Option<i32>,
+    #[serde(default)]
+    pub gps_lat: Option<f64>,
+    #[serde(default)]
+    pub gps_lon: Option<f64>,
+    #[serde(default = "default_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+// Settings pushed to weather/devices/<id>/config (retained)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    #[serde(default)]
+    pub request_id: String,
+    pub display_units: String,
+    pub refresh_interval_secs: u32,
+    pub temp_high_threshold: Option<f64>,
+    pub temp_low_threshold: Option<f64>,
+    pub humidity_high_threshold: Option<f64>,
+    #[serde(default)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+// Button press published on weather/devices/<id>/button
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ButtonEvent {
+    #[serde(default)]
+    pub device_id: String,
+    // "A", "B" or "C"
+    pub button: String,
+    // "short", "long" or "double"
+    #[serde(default = "default_press_type")]
+    pub press_type: String,
+    #[serde(default = "default_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+fn default_press_type() -> String {
+    "short".to_string()
+}
+
+// Published periodically so the device can correct its clock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeSync {
+    pub epoch: i64,
+    pub timezone_offset_secs: i32,
+    pub iso: String,
+}
+
+impl TimeSync {
+    pub fn now() -> Self {
+        let now = chrono::Local::now();
+        Self {
+            epoch: now.timestamp(),
+            timezone_offset_secs: now.offset().local_minus_utc(),
+            iso: now.to_rfc3339(),
+        }
+    }
 }
 
+// Management command published to weather/devices/<id>/command
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceCommand {
+    pub request_id: String,
+    pub command: String,
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
+    #[serde(default = "default_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+// Acknowledgement published by the device on weather/devices/<id>/ack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceAck {
+    pub request_id: String,
+    #[serde(default = "default_ack_success")]
+    pub success: bool,
+    pub message: Option<String>,
+}
+
+fn default_ack_success() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedCommand {
+    pub color: String,
+    pub pattern: String,
+    pub interval_ms: u32,
+    pub duration_secs: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeakerCommand {
+    pub frequency_hz: u32,
+    pub duration_ms: u32,
+    pub repeat: u32,
+}
+
+// Ordered by severity
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum AlertLevel {
     Info,
@@ -70,6 +627,60 @@ pub struct AlertData {
     pub level: AlertLevel,
     #[serde(default = "default_timestamp")]
     pub timestamp: DateTime<Utc>,
+    // Assigned when we publish the alert; the device acks with it as the request id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+impl AlertData {
+    // Copy of the alert carrying an id, keeping one it already has
+    pub fn with_id(&self) -> Self {
+        static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+        let mut alert = self.clone();
+        if alert.id.is_none() {
+            let sequence = NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            alert.id = Some(format!("alert-{}-{}", Utc::now().timestamp_millis(), sequence));
+        }
+        alert
+    }
+}
+
+// What raised an alert, kept in the alert history
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSource {
+    // send_alert from the UI
+    Manual,
+    AirQuality,
+    LowBattery,
+    StaleSensor,
+    ButtonTest,
+    // Forwarded by the automated publisher from the weather provider
+    WeatherProvider,
+    SevereWeather,
+    // Published on weather/alert_trigger by another client
+    External,
+    // A user-defined alert rule
+    Rule,
+    // Rain about to start according to the precipitation nowcast
+    Nowcast,
+    // Frost or heat expected in the daily forecast
+    ForecastWarning,
+    // A weatherstation://alert link opened by another tool
+    DeepLink,
+    // POST /alert on the local REST API
+    RestApi,
+    // alert() called from a user script
+    Script,
+    // SendAlert on the gRPC API
+    Grpc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertDirection {
+    Sent,
+    Received,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -96,6 +707,18 @@ impl Default for MqttConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionStatus {
     pub mqtt: bool,
+    // False once the most recent provider fetch failed
     pub api: bool,
-    pub last_update: DateTime<Utc>,
+    // Newest weather data we have, from the cache for the active location
+    pub last_update: Option<DateTime<Utc>>,
+    pub api_last_success: Option<DateTime<Utc>>,
+    pub api_last_failure: Option<DateTime<Utc>>,
+    pub api_last_error: Option<String>,
+    // Serving data from the fallback provider
+    pub api_degraded: bool,
+    pub cache_valid: bool,
+    pub cache_age_minutes: Option<i64>,
+    pub devices_online: usize,
+    pub devices_total: usize,
+    pub checked_at: DateTime<Utc>,
 }
\ No newline at end of file
@@ -0,0 +1,148 @@
+use crate::types::{CurrentWeather, QualityFlag, ReadingQuality, SensorData, UnitSystem, WeatherData};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use std::ops::RangeInclusive;
+use tracing::warn;
+
+// Physically plausible ranges, in metric units
+const TEMP_RANGE_C: RangeInclusive<f64> = -80.0..=60.0;
+const PRESSURE_RANGE_HPA: RangeInclusive<f64> = 870.0..=1085.0;
+const HUMIDITY_RANGE: RangeInclusive<f64> = 0.0..=100.0;
+// Strongest gust ever recorded is about 113 m/s
+const WIND_SPEED_RANGE_MS: RangeInclusive<f64> = 0.0..=113.0;
+const PRECIPITATION_RANGE: RangeInclusive<f64> = 0.0..=100.0;
+// Station pressure, not reduced to sea level, so high-altitude readings pass
+const STATION_PRESSURE_RANGE_HPA: RangeInclusive<f64> = 300.0..=1100.0;
+const CO2_RANGE_PPM: RangeInclusive<f64> = 0.0..=40000.0;
+const TVOC_RANGE_PPB: RangeInclusive<f64> = 0.0..=60000.0;
+const LUX_RANGE: RangeInclusive<f64> = 0.0..=200000.0;
+
+// Flags implausible values in place. Fails when the current temperature or
+// pressure is out of range, which means the response itself is broken and
+// should be neither cached nor published.
+pub fn validate_weather(data: &mut WeatherData) -> Result<()> {
+    let mut flags = Vec::new();
+    let to_celsius = |value: f64| data.units.convert_temp(value, UnitSystem::Metric);
+
+    check_current(&mut flags, data.units, data.current_temp, data.pressure as f64, data.humidity as f64, data.feels_like, data.wind_speed)?;
+    for (i, day) in data.forecast.iter().enumerate() {
+        check(&mut flags, &format!("forecast[{}].temp", i), to_celsius(day.temp), &TEMP_RANGE_C, "°C");
+        check(&mut flags, &format!("forecast[{}].humidity", i), day.humidity as f64, &HUMIDITY_RANGE, "%");
+        check(&mut flags, &format!("forecast[{}].pop", i), day.pop, &PRECIPITATION_RANGE, "%");
+    }
+    for (i, day) in data.history.iter().enumerate() {
+        check(&mut flags, &format!("history[{}].temp", i), to_celsius(day.temp), &TEMP_RANGE_C, "°C");
+        check(&mut flags, &format!("history[{}].humidity", i), day.humidity as f64, &HUMIDITY_RANGE, "%");
+    }
+    for (i, hour) in data.hourly.iter().enumerate() {
+        check(&mut flags, &format!("hourly[{}].temp", i), to_celsius(hour.temp), &TEMP_RANGE_C, "°C");
+        check(&mut flags, &format!("hourly[{}].precipitation_probability", i), hour.precipitation_probability, &PRECIPITATION_RANGE, "%");
+    }
+
+    if !flags.is_empty() {
+        warn!("Weather data has {} suspect value(s)", flags.len());
+    }
+    data.quality_flags = flags;
+    Ok(())
+}
+
+// The same checks for a current conditions fetch. It has nowhere to carry flags, so
+// suspect values other than temperature and pressure are only logged.
+pub fn validate_current(current: &CurrentWeather) -> Result<()> {
+    let mut flags = Vec::new();
+    check_current(&mut flags, current.units, current.temp, current.pressure as f64, current.humidity as f64, current.feels_like, current.wind_speed)?;
+    if !flags.is_empty() {
+        let reasons: Vec<String> = flags.into_iter().map(|flag| flag.reason).collect();
+        warn!("Current conditions have suspect values: {}", reasons.join(", "));
+    }
+    Ok(())
+}
+
+// Checks shared by full reports and current conditions. Fails when the temperature or
+// pressure is out of range.
+fn check_current(
+    flags: &mut Vec<QualityFlag>,
+    units: UnitSystem,
+    temp: f64,
+    pressure: f64,
+    humidity: f64,
+    feels_like: Option<f64>,
+    wind_speed: f64,
+) -> Result<()> {
+    let to_celsius = |value: f64| units.convert_temp(value, UnitSystem::Metric);
+    check(flags, "current_temp", to_celsius(temp), &TEMP_RANGE_C, "°C");
+    check(flags, "pressure", pressure, &PRESSURE_RANGE_HPA, " hPa");
+    let broken: Vec<String> = flags.iter().map(|flag: &QualityFlag| flag.reason.clone()).collect();
+    if !broken.is_empty() {
+        warn!("Rejecting weather data: {}", broken.join(", "));
+        return Err(anyhow!("Weather data failed sanity checks: {}", broken.join(", ")));
+    }
+
+    check(flags, "humidity", humidity, &HUMIDITY_RANGE, "%");
+    if let Some(feels_like) = feels_like {
+        check(flags, "feels_like", to_celsius(feels_like), &TEMP_RANGE_C, "°C");
+    }
+    check(flags, "wind_speed", units.convert_speed(wind_speed, UnitSystem::Metric), &WIND_SPEED_RANGE_MS, " m/s");
+    Ok(())
+}
+
+// Rejects a sensor reading with any implausible value, e.g. when importing old data
+pub fn validate_sensor_reading(reading: &SensorData) -> Result<()> {
+    let flags = sensor_range_flags(reading);
+    if flags.is_empty() {
+        Ok(())
+    } else {
+        let reasons: Vec<String> = flags.into_iter().map(|flag| flag.reason).collect();
+        Err(anyhow!(reasons.join(", ")))
+    }
+}
+
+// Quality of a reading that has just arrived and been checked for anomalies. Stale
+// goes by the device's own timestamp, so it's only set when that is RFC 3339.
+pub fn sensor_quality(reading: &SensorData, stale_after_minutes: u64) -> Vec<ReadingQuality> {
+    let mut quality = Vec::new();
+    if !reading.anomalies.is_empty() || !sensor_range_flags(reading).is_empty() {
+        quality.push(ReadingQuality::OutOfRange);
+    }
+    let taken_at = DateTime::parse_from_rfc3339(&reading.timestamp).ok();
+    if let (Some(taken_at), Some(received_at)) = (taken_at, reading.received_at) {
+        let age_secs = (received_at - taken_at.with_timezone(&Utc)).num_seconds();
+        if stale_after_minutes > 0 && age_secs > stale_after_minutes as i64 * 60 {
+            quality.push(ReadingQuality::Stale);
+        }
+    }
+    if reading.quality.contains(&ReadingQuality::Interpolated) {
+        quality.push(ReadingQuality::Interpolated);
+    }
+    if quality.is_empty() {
+        quality.push(ReadingQuality::Ok);
+    }
+    quality
+}
+
+fn sensor_range_flags(reading: &SensorData) -> Vec<QualityFlag> {
+    let mut flags = Vec::new();
+    check(&mut flags, "temperature", reading.temperature, &TEMP_RANGE_C, "°C");
+    check(&mut flags, "humidity", reading.humidity, &HUMIDITY_RANGE, "%");
+    check(&mut flags, "pressure", reading.pressure, &STATION_PRESSURE_RANGE_HPA, " hPa");
+    if let Some(co2) = reading.co2 {
+        check(&mut flags, "co2", co2, &CO2_RANGE_PPM, " ppm");
+    }
+    if let Some(tvoc) = reading.tvoc {
+        check(&mut flags, "tvoc", tvoc, &TVOC_RANGE_PPB, " ppb");
+    }
+    if let Some(lux) = reading.lux {
+        check(&mut flags, "lux", lux, &LUX_RANGE, " lx");
+    }
+    flags
+}
+
+fn check(flags: &mut Vec<QualityFlag>, field: &str, value: f64, range: &RangeInclusive<f64>, unit: &str) {
+    if value.is_finite() && range.contains(&value) {
+        return;
+    }
+    flags.push(QualityFlag {
+        field: field.to_string(),
+        reason: format!("{} is {:.1}{}, expected {}..{}{}", field, value, unit, range.start(), range.end(), unit),
+    });
+}
@@ -1,15 +1,39 @@
+use crate::api_usage::{ApiUsage, ApiUsageTracker};
+use crate::config::{NamedLocation, WeatherApiSettings, WeatherProviderType};
+use crate::events::{self, AppEvent};
+use crate::geo;
+use crate::logging;
+use crate::history::SensorHistory;
+use crate::statistics;
+use crate::storage;
+use crate::providers::{send_with_retry, MAX_FORECAST_DAYS, OpenMeteoProvider, OpenWeatherMapProvider, WeatherProvider, WeatherReport};
 use crate::types::*;
+use crate::validation::{validate_current, validate_weather};
 use anyhow::{Result, anyhow};
 use reqwest::Client;
-use serde_json::Value;
-use tracing::{info, error, warn};
-use chrono::{Utc, DateTime, Local, Datelike};
+use tracing::{info, warn, instrument, Span};
+use tracing::field::Empty;
+use chrono::{Utc, DateTime, Local};
 use serde::{Serialize, Deserialize};
-use std::fs;
-use std::path::PathBuf;
-
-const OPENWEATHERMAP_API_KEY: &str = "API_KEY_HERE";
-const CACHE_FILE_NAME: &str = "weather_cache.json";
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::fs;
+use std::time::{Duration, SystemTime};
+
+// Shipped in older default configs, treated the same as an empty key
+const PLACEHOLDER_API_KEY: &str = "API_KEY_HERE";
+// One cache file per location: weather_cache_<lat>_<lon>.json
+const CACHE_FILE_PREFIX: &str = "weather_cache";
+// Free, keyless IP geolocation; only called when the user asks for it
+const IP_GEOLOCATION_URL: &str = "https://ipapi.co/json/";
+// Sent with every provider request; some APIs throttle anonymous clients harder
+const USER_AGENT: &str = concat!("weather-station-desktop/", env!("CARGO_PKG_VERSION"));
+// Connections are reused across the publisher's fetches, but only to a few hosts
+const POOL_MAX_IDLE_PER_HOST: usize = 4;
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct WeatherCache {
@@ -18,77 +42,502 @@ struct WeatherCache {
     pub coordinates: (f64, f64), // (lat, lon)
 }
 
+// A parsed cache file, reused until the file's modification time changes
+struct MemoryCacheEntry {
+    modified: SystemTime,
+    cache: WeatherCache,
+}
+
+enum CacheLoad {
+    Missing,
+    Unreadable(String),
+    Corrupt(String),
+    Loaded { cache: WeatherCache, size_bytes: u64 },
+}
+
+// Emitted as "weather-provider-degraded" when the fallback provider supplied the data
+#[derive(Serialize, Clone, Debug)]
+pub struct ProviderDegradedEvent {
+    pub primary: String,
+    pub fallback: String,
+    pub reason: String,
+}
+
+// What's on disk for one location, for the settings page
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WeatherCacheInfo {
+    pub path: String,
+    pub exists: bool,
+    pub size_bytes: Option<u64>,
+    pub last_updated: Option<DateTime<Local>>,
+    pub age_minutes: Option<i64>,
+    pub coordinates: Option<(f64, f64)>,
+    // Whether the next fetch would be served from this file
+    pub valid: bool,
+    // Why the cache is not valid, if it isn't
+    pub reason: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ApiKeyMissing;
+
+impl std::fmt::Display for ApiKeyMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OpenWeatherMap API key is not configured, add it in the weather API settings")
+    }
+}
+
+impl std::error::Error for ApiKeyMissing {}
+
+// Outcome of the most recent provider fetches, for the connection status bar
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FetchStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
 pub struct WeatherApiClient {
-    client: Client,
-    cache_path: PathBuf,
+    client: RwLock<Client>,
+    cache_dir: PathBuf,
+    // Parsed cache files by path, so the publisher doesn't re-read them on every tick
+    memory_cache: RwLock<HashMap<PathBuf, MemoryCacheEntry>>,
+    settings: RwLock<WeatherApiSettings>,
+    usage: Arc<ApiUsageTracker>,
+    // Set while data is coming from the fallback provider
+    degraded: AtomicBool,
+    fetch_status: RwLock<FetchStatus>,
+    // Recorded M5Go readings, preferred over provider history when they cover a day
+    sensor_history: RwLock<Option<Arc<SensorHistory>>>,
 }
 
 impl WeatherApiClient {
-    pub fn new() -> Self {
-        let cache_path = Self::get_cache_path();
+    pub fn new(settings: WeatherApiSettings) -> Self {
+        let cache_dir = Self::get_cache_dir();
+        let usage = Arc::new(ApiUsageTracker::new(settings.daily_call_budget));
         Self {
-            client: Client::new(),
-            cache_path,
+            client: RwLock::new(Self::build_client(&settings)),
+            cache_dir,
+            memory_cache: RwLock::new(HashMap::new()),
+            settings: RwLock::new(settings),
+            usage,
+            degraded: AtomicBool::new(false),
+            fetch_status: RwLock::new(FetchStatus::default()),
+            sensor_history: RwLock::new(None),
         }
     }
 
-    fn get_cache_path() -> PathBuf {
-        // Use the same directory as the config file
-        let mut path = dirs::data_dir()
-            .or_else(|| dirs::home_dir())
-            .unwrap_or_else(|| PathBuf::from("."));
-        
-        path.push("weather-station-desktop");
+    pub fn set_sensor_history(&self, history: Arc<SensorHistory>) {
+        *self.sensor_history.write().unwrap() = Some(history);
+    }
+
+    pub async fn update_settings(&self, settings: WeatherApiSettings) {
+        let provider_changed = {
+            let mut current = self.settings.write().unwrap();
+            let provider_changed = (current.provider != settings.provider).then_some(settings.provider);
+            if current.connect_timeout_secs != settings.connect_timeout_secs
+                || current.read_timeout_secs != settings.read_timeout_secs
+            {
+                *self.client.write().unwrap() = Self::build_client(&settings);
+            }
+            self.usage.set_budget(settings.daily_call_budget);
+            *current = settings;
+            provider_changed
+        };
+        if let Some(provider) = provider_changed {
+            // Cached data came from the old provider, fetch fresh data on next use
+            info!("Weather provider changed to {:?}, clearing cache", provider);
+            if let Err(e) = self.clear_cache_files().await {
+                warn!("Failed to clear weather cache: {}", e);
+            }
+        }
+    }
+
+    fn build_client(settings: &WeatherApiSettings) -> Client {
+        let connect_timeout = Duration::from_secs(settings.connect_timeout_secs.max(1));
+        let read_timeout = Duration::from_secs(settings.read_timeout_secs.max(1));
+        info!("Building weather HTTP client (connect timeout {:?}, read timeout {:?})", connect_timeout, read_timeout);
+        Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(connect_timeout)
+            .read_timeout(read_timeout)
+            .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .tcp_keepalive(TCP_KEEPALIVE)
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to build weather HTTP client, using defaults: {}", e);
+                Client::new()
+            })
+    }
+
+    fn http_client(&self) -> Client {
+        self.client.read().unwrap().clone()
+    }
+
+    pub fn api_usage(&self) -> ApiUsage {
+        self.usage.snapshot()
+    }
+
+    // The provider selected in the settings
+    fn provider(&self) -> Result<Box<dyn WeatherProvider>> {
+        let provider = self.settings.read().unwrap().provider;
+        self.provider_for(provider)
+    }
+
+    fn provider_for(&self, provider: WeatherProviderType) -> Result<Box<dyn WeatherProvider>> {
+        let (api_key, units, retry) = {
+            let settings = self.settings.read().unwrap();
+            (settings.api_key.clone(), settings.units, settings.retry.clone())
+        };
+        match provider {
+            WeatherProviderType::OpenWeatherMap => Ok(Box::new(self.openweathermap(api_key)?)),
+            WeatherProviderType::OpenMeteo => {
+                let open_meteo = self.settings.read().unwrap().open_meteo.clone();
+                Ok(Box::new(OpenMeteoProvider::new(self.http_client(), units, retry).with_settings(open_meteo)))
+            }
+        }
+    }
+
+    fn openweathermap(&self, api_key: String) -> Result<OpenWeatherMapProvider> {
+        let api_key = api_key.trim().to_string();
+        if api_key.is_empty() || api_key == PLACEHOLDER_API_KEY {
+            return Err(ApiKeyMissing.into());
+        }
+
+        let debug_path = self.cache_dir.join("api_response_debug.json");
+        let (units, retry) = {
+            let settings = self.settings.read().unwrap();
+            (settings.units, settings.retry.clone())
+        };
+        Ok(OpenWeatherMapProvider::new(self.http_client(), api_key, units, retry, Some(debug_path))
+            .with_usage_tracker(Arc::clone(&self.usage)))
+    }
+
+    pub fn fetch_status(&self) -> FetchStatus {
+        self.fetch_status.read().unwrap().clone()
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::SeqCst)
+    }
+
+    #[instrument(name = "weather_fetch", skip(self), fields(provider = Empty, outcome = Empty))]
+    async fn fetch_from_provider(&self, lat: f64, lon: f64) -> Result<WeatherData> {
+        let result = self.fetch_with_fallback(lat, lon).await;
+        if let Ok(data) = &result {
+            Span::current().record("provider", data.provider.as_str());
+        }
+        logging::record_outcome(&result);
+        let mut status = self.fetch_status.write().unwrap();
+        match &result {
+            Ok(_) => status.last_success = Some(Utc::now()),
+            Err(e) => {
+                status.last_failure = Some(Utc::now());
+                status.last_error = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    // Tries the primary provider, then the fallback if one is configured
+    async fn fetch_with_fallback(&self, lat: f64, lon: f64) -> Result<WeatherData> {
+        let (primary, fallback) = {
+            let settings = self.settings.read().unwrap();
+            (settings.provider, settings.fallback_provider.filter(|f| *f != settings.provider))
+        };
+
+        let primary_error = match self.fetch_with(primary, lat, lon).await {
+            Ok(data) => {
+                if self.degraded.swap(false, Ordering::SeqCst) {
+                    info!("Primary weather provider {:?} is working again", primary);
+                    events::publish(AppEvent::WeatherProviderRestored(data.provider.clone()));
+                }
+                return Ok(data);
+            }
+            Err(e) => e,
+        };
+
+        let Some(fallback) = fallback else {
+            return Err(primary_error);
+        };
+        warn!("Weather provider {:?} failed: {}, falling back to {:?}", primary, primary_error, fallback);
+
+        match self.fetch_with(fallback, lat, lon).await {
+            Ok(data) => {
+                self.degraded.store(true, Ordering::SeqCst);
+                events::publish(AppEvent::WeatherProviderDegraded(ProviderDegradedEvent {
+                    primary: format!("{:?}", primary),
+                    fallback: data.provider.clone(),
+                    reason: primary_error.to_string(),
+                }));
+                Ok(data)
+            }
+            Err(e) => Err(anyhow!("{} (fallback {:?} also failed: {})", primary_error, fallback, e)),
+        }
+    }
+
+    // Always a fresh API call; falls back like full reports do
+    pub async fn fetch_nowcast(&self, lat: f64, lon: f64) -> Result<Vec<NowcastInterval>> {
+        let (primary, fallback) = {
+            let settings = self.settings.read().unwrap();
+            (settings.provider, settings.fallback_provider.filter(|f| *f != settings.provider))
+        };
+
+        let primary_error = match self.provider_for(primary)?.fetch_nowcast(lat, lon).await {
+            Ok(nowcast) => return Ok(nowcast),
+            Err(e) => e,
+        };
+        let Some(fallback) = fallback else {
+            return Err(primary_error);
+        };
+        match self.provider_for(fallback)?.fetch_nowcast(lat, lon).await {
+            Ok(nowcast) => Ok(nowcast),
+            Err(e) => Err(anyhow!("{} (fallback {:?} also failed: {})", primary_error, fallback, e)),
+        }
+    }
+
+    #[instrument(name = "provider_fetch", skip(self), fields(outcome = Empty))]
+    async fn fetch_with(&self, provider: WeatherProviderType, lat: f64, lon: f64) -> Result<WeatherData> {
+        let result = self.fetch_report_with(provider, lat, lon).await;
+        logging::record_outcome(&result);
+        result
+    }
+
+    async fn fetch_report_with(&self, provider: WeatherProviderType, lat: f64, lon: f64) -> Result<WeatherData> {
+        let provider = self.provider_for(provider)?;
+        info!("Fetching weather data from {}", provider.name());
+        let report = provider.fetch_report(lat, lon).await?;
+        let mut weather_data = self.build_weather_data(report, provider.name(), lat, lon);
+        validate_weather(&mut weather_data)?;
+        Ok(weather_data)
+    }
+
+    fn get_cache_dir() -> PathBuf {
+        let path = storage::data_dir();
         
         // Create directory if it doesn't exist
-        if let Err(e) = fs::create_dir_all(&path) {
+        if let Err(e) = std::fs::create_dir_all(&path) {
             warn!("Failed to create cache directory: {}", e);
         }
         
-        path.push(CACHE_FILE_NAME);
         path
     }
 
+    fn cache_path(&self, lat: f64, lon: f64) -> PathBuf {
+        self.cache_dir.join(format!("{}_{:.4}_{:.4}.json", CACHE_FILE_PREFIX, lat, lon))
+    }
+
+    // Removes the cache files for every location, returning how many were deleted
+    async fn clear_cache_files(&self) -> Result<usize> {
+        self.memory_cache.write().unwrap().clear();
+        let mut removed = 0;
+        let mut entries = fs::read_dir(&self.cache_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_cache = path.file_name()
+                .and_then(|n| n.to_str())
+                .map_or(false, |n| n.starts_with(CACHE_FILE_PREFIX) && n.ends_with(".json"));
+            if is_cache {
+                fs::remove_file(&path).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    pub async fn clear_cache(&self) -> Result<usize> {
+        let removed = self.clear_cache_files().await?;
+        info!("Cleared {} weather cache file(s)", removed);
+        Ok(removed)
+    }
+
+    // Drops one location's cache, e.g. after switching away from it
+    pub async fn invalidate_cache(&self, lat: f64, lon: f64) -> Result<bool> {
+        let cache_path = self.cache_path(lat, lon);
+        self.memory_cache.write().unwrap().remove(&cache_path);
+        match fs::remove_file(&cache_path).await {
+            Ok(()) => {
+                info!("Cleared weather cache for {}, {}", lat, lon);
+                Ok(true)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn cache_info(&self, lat: f64, lon: f64) -> WeatherCacheInfo {
+        let cache_path = self.cache_path(lat, lon);
+        let mut info = WeatherCacheInfo {
+            path: cache_path.display().to_string(),
+            exists: false,
+            size_bytes: None,
+            last_updated: None,
+            age_minutes: None,
+            coordinates: None,
+            valid: false,
+            reason: None,
+        };
+
+        let cache = match self.load_cache(&cache_path).await {
+            CacheLoad::Loaded { cache, size_bytes } => {
+                info.exists = true;
+                info.size_bytes = Some(size_bytes);
+                cache
+            }
+            CacheLoad::Missing => {
+                info.reason = Some("No cache file for this location".to_string());
+                return info;
+            }
+            CacheLoad::Unreadable(e) => {
+                info.reason = Some(format!("Failed to read cache file: {}", e));
+                return info;
+            }
+            CacheLoad::Corrupt(e) => {
+                info.exists = true;
+                info.reason = Some(format!("Cache file is corrupt: {}", e));
+                return info;
+            }
+        };
+        info.last_updated = Some(cache.last_updated);
+        info.age_minutes = Some((Local::now() - cache.last_updated).num_minutes());
+        info.coordinates = Some(cache.coordinates);
+
+        let coord_match = (cache.coordinates.0 - lat).abs() < 0.001
+            && (cache.coordinates.1 - lon).abs() < 0.001;
+        if !coord_match {
+            info.reason = Some("Cached coordinates don't match the requested location".to_string());
+        } else if cache.last_updated.date_naive() != Local::now().date_naive() {
+            info.reason = Some(format!("Cache is from {}, it will be refreshed on next fetch", cache.last_updated.date_naive()));
+        } else {
+            info.valid = true;
+        }
+        info
+    }
+
     pub async fn fetch_weather(&self, lat: f64, lon: f64, api_key: &str) -> Result<WeatherData> {
-        info!("🌤️  CALLING OPENWEATHERMAP API!");
-        info!("API Key: {}", api_key);
-        info!("Fetching weather data for coordinates: {}, {}", lat, lon);
+        let provider = self.openweathermap(api_key.to_string())?;
+        let report = provider.fetch_report(lat, lon).await?;
+        let mut weather_data = self.build_weather_data(report, provider.name(), lat, lon);
+        validate_weather(&mut weather_data)?;
+        Ok(self.apply_output_settings(weather_data))
+    }
 
-        // Using OpenWeatherMap One Call API 3.0
-        let url = format!(
-            "https://api.openweathermap.org/data/3.0/onecall?lat={}&lon={}&appid={}&units=metric&exclude=minutely,hourly,alerts",
-            lat, lon, api_key
-        );
+    pub async fn search_locations(&self, query: &str) -> Result<Vec<LocationCandidate>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.provider()?.search_locations(query).await
+    }
 
-        info!("Making API request to: {}", url);
+    // Cache-first current conditions for every location, fetched in parallel
+    pub async fn compare_locations(self: &Arc<Self>, locations: Vec<NamedLocation>) -> Vec<LocationComparison> {
+        let handles: Vec<_> = locations.into_iter()
+            .map(|location| {
+                let client = Arc::clone(self);
+                tokio::spawn(async move {
+                    let result = client.fetch_weather_with_default_key(location.latitude, location.longitude).await;
+                    (location, result)
+                })
+            })
+            .collect();
+
+        let units = self.settings.read().unwrap().units;
+        let mut comparison = Vec::with_capacity(handles.len());
+        for handle in handles {
+            let (location, result) = match handle.await {
+                Ok(done) => done,
+                Err(e) => {
+                    warn!("Location comparison task failed: {}", e);
+                    continue;
+                }
+            };
+            let mut row = LocationComparison {
+                name: location.name,
+                lat: location.latitude,
+                lon: location.longitude,
+                temp: None,
+                humidity: None,
+                condition: None,
+                icon: None,
+                units,
+                error: None,
+            };
+            match result {
+                Ok(data) => {
+                    row.temp = Some(data.current_temp);
+                    row.humidity = Some(data.humidity);
+                    row.condition = Some(data.condition);
+                    row.icon = Some(data.current_icon);
+                    row.units = data.units;
+                }
+                Err(e) => {
+                    warn!("Failed to fetch weather for {}: {}", row.name, e);
+                    row.error = Some(e.to_string());
+                }
+            }
+            comparison.push(row);
+        }
+        comparison
+    }
 
-        let response = self.client.get(&url).send().await?;
-        
-        info!("API response status: {}", response.status());
+    // Current conditions straight from the provider; the daily cache is left alone
+    pub async fn fetch_current_conditions(&self, lat: f64, lon: f64) -> Result<CurrentWeather> {
+        let provider = self.provider()?;
+        info!("Fetching current conditions from {}", provider.name());
+        let current = provider.fetch_current(lat, lon).await?;
+        let current = CurrentWeather {
+            condition: current.condition,
+            icon: current.icon,
+            temp: current.temp,
+            humidity: current.humidity,
+            pressure: current.pressure,
+            wind_speed: current.wind_speed,
+            wind_direction: self.wind_deg_to_direction(current.wind_deg),
+            feels_like: current.feels_like,
+            wind_gust: current.wind_gust,
+            uv_index: current.uv_index,
+            visibility: current.visibility,
+            units: self.settings.read().unwrap().units,
+            provider: provider.name().to_string(),
+            timestamp: Utc::now(),
+        };
+        validate_current(&current)?;
+        Ok(current)
+    }
+
+    // Approximate location of this machine from its public IP address
+    pub async fn detect_location(&self) -> Result<LocationCandidate> {
+        #[derive(Deserialize)]
+        struct IpLocation {
+            city: Option<String>,
+            region: Option<String>,
+            country_name: Option<String>,
+            latitude: f64,
+            longitude: f64,
+        }
+
+        info!("Detecting location via IP geolocation");
+        let retry = self.settings.read().unwrap().retry.clone();
+        let response = send_with_retry(self.http_client().get(IP_GEOLOCATION_URL), &retry, None).await?;
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("API request failed with status {}: {}", status, error_text);
-            return Err(anyhow!("API request failed: {} - {}", status, error_text));
+            return Err(anyhow!("IP geolocation failed: {}", response.status()));
         }
 
-        let data: Value = response.json().await?;
-        info!("✅ SUCCESSFULLY RECEIVED API RESPONSE");
-        
-        // LOG THE COMPLETE API RESPONSE
-        let pretty_json = serde_json::to_string_pretty(&data)?;
-        info!("📋 COMPLETE API RESPONSE:\n{}", pretty_json);
-        
-        // Save raw API response to a file for debugging
-        let mut debug_path = self.cache_path.clone();
-        debug_path.set_file_name("api_response_debug.json");
-        if let Err(e) = fs::write(&debug_path, &pretty_json) {
-            warn!("Failed to save debug API response: {}", e);
-        } else {
-            info!("💾 Saved raw API response to: {:?}", debug_path);
+        let location: IpLocation = response.json().await
+            .map_err(|e| anyhow!("Unexpected IP geolocation response: {}", e))?;
+        if !geo::is_valid_coordinate(location.latitude, location.longitude) {
+            return Err(anyhow!("IP geolocation returned invalid coordinates"));
         }
 
-        self.parse_weather_response(data, lat, lon).await
+        Ok(LocationCandidate {
+            name: location.city.unwrap_or_else(|| "Unknown".to_string()),
+            region: location.region,
+            country: location.country_name.unwrap_or_default(),
+            lat: location.latitude,
+            lon: location.longitude,
+        })
     }
 
     pub async fn fetch_weather_with_default_key(&self, lat: f64, lon: f64) -> Result<WeatherData> {
@@ -97,12 +546,12 @@ impl WeatherApiClient {
         // Check cache first
         if let Some(cached_data) = self.get_cached_weather(lat, lon).await? {
             info!("📄 USING CACHED WEATHER DATA - NO API CALL");
-            return Ok(cached_data);
+            return Ok(self.apply_output_settings(cached_data));
         }
 
         // If cache is expired or missing, fetch from API
         info!("💾 Cache expired or missing, FETCHING FROM API");
-        let weather_data = self.fetch_weather(lat, lon, OPENWEATHERMAP_API_KEY).await?;
+        let weather_data = self.fetch_from_provider(lat, lon).await?;
         
         // Cache the new data
         info!("💾 Caching fresh weather data...");
@@ -112,60 +561,94 @@ impl WeatherApiClient {
             info!("✅ Weather data cached successfully");
         }
 
-        Ok(weather_data)
+        Ok(self.apply_output_settings(weather_data))
     }
 
-    async fn get_cached_weather(&self, lat: f64, lon: f64) -> Result<Option<WeatherData>> {
-        info!("🔍 CHECKING CACHE at: {:?}", self.cache_path);
-        
-        if !self.cache_path.exists() {
-            info!("❌ Cache file does not exist");
-            return Ok(None);
+    // Reads a cache file, or reuses the parsed copy from memory while the file on disk
+    // hasn't been modified since
+    async fn load_cache(&self, cache_path: &Path) -> CacheLoad {
+        let metadata = match fs::metadata(cache_path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return CacheLoad::Missing,
+            Err(e) => return CacheLoad::Unreadable(e.to_string()),
+        };
+        let modified = metadata.modified().ok();
+        let in_memory = self.memory_cache.read().unwrap()
+            .get(cache_path)
+            .filter(|entry| Some(entry.modified) == modified)
+            .map(|entry| entry.cache.clone());
+        if let Some(cache) = in_memory {
+            return CacheLoad::Loaded { cache, size_bytes: metadata.len() };
         }
 
-        match fs::read_to_string(&self.cache_path) {
-            Ok(content) => {
-                info!("📄 Cache file exists, size: {} bytes", content.len());
-                match serde_json::from_str::<WeatherCache>(&content) {
-                    Ok(cache) => {
-                        info!("📅 Cache last updated: {}", cache.last_updated);
-                        info!("📍 Cache coordinates: {:?}, requested: ({}, {})", cache.coordinates, lat, lon);
-                        
-                        // Check if coordinates match (within small tolerance)
-                        let coord_match = (cache.coordinates.0 - lat).abs() < 0.001 
-                            && (cache.coordinates.1 - lon).abs() < 0.001;
-                        
-                        if !coord_match {
-                            info!("❌ Cache coordinates don't match, ignoring cache");
-                            return Ok(None);
-                        }
-
-                        // Check if cache is from today
-                        let now = Local::now();
-                        let cache_date = cache.last_updated.date_naive();
-                        let today = now.date_naive();
-                        
-                        info!("📅 Cache date: {}, Today: {}", cache_date, today);
-                        
-                        if cache_date == today {
-                            info!("✅ Found VALID cache from today - USING CACHED DATA");
-                            info!("📊 Cached weather data timestamp: {}", cache.data.timestamp);
-                            Ok(Some(cache.data))
-                        } else {
-                            info!("⏰ Cache is from different day ({} vs {}), will refresh", cache_date, today);
-                            Ok(None)
-                        }
-                    }
-                    Err(e) => {
-                        warn!("❌ Failed to parse cache file: {}", e);
-                        Ok(None)
-                    }
+        let content = match fs::read_to_string(cache_path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return CacheLoad::Missing,
+            Err(e) => return CacheLoad::Unreadable(e.to_string()),
+        };
+        info!("📄 Read cache file from disk, size: {} bytes", content.len());
+        match serde_json::from_str::<WeatherCache>(&content) {
+            Ok(cache) => {
+                if let Some(modified) = modified {
+                    self.memory_cache.write().unwrap().insert(
+                        cache_path.to_path_buf(),
+                        MemoryCacheEntry { modified, cache: cache.clone() },
+                    );
                 }
+                CacheLoad::Loaded { cache, size_bytes: content.len() as u64 }
             }
             Err(e) => {
+                self.memory_cache.write().unwrap().remove(cache_path);
+                CacheLoad::Corrupt(e.to_string())
+            }
+        }
+    }
+
+    async fn get_cached_weather(&self, lat: f64, lon: f64) -> Result<Option<WeatherData>> {
+        let cache_path = self.cache_path(lat, lon);
+        info!("🔍 CHECKING CACHE at: {:?}", cache_path);
+
+        let cache = match self.load_cache(&cache_path).await {
+            CacheLoad::Loaded { cache, .. } => cache,
+            CacheLoad::Missing => {
+                info!("❌ Cache file does not exist");
+                return Ok(None);
+            }
+            CacheLoad::Unreadable(e) => {
                 warn!("❌ Failed to read cache file: {}", e);
-                Ok(None)
+                return Ok(None);
+            }
+            CacheLoad::Corrupt(e) => {
+                warn!("❌ Failed to parse cache file: {}", e);
+                return Ok(None);
             }
+        };
+        info!("📅 Cache last updated: {}", cache.last_updated);
+        info!("📍 Cache coordinates: {:?}, requested: ({}, {})", cache.coordinates, lat, lon);
+
+        // Check if coordinates match (within small tolerance)
+        let coord_match = (cache.coordinates.0 - lat).abs() < 0.001 
+            && (cache.coordinates.1 - lon).abs() < 0.001;
+
+        if !coord_match {
+            info!("❌ Cache coordinates don't match, ignoring cache");
+            return Ok(None);
+        }
+
+        // Check if cache is from today
+        let now = Local::now();
+        let cache_date = cache.last_updated.date_naive();
+        let today = now.date_naive();
+
+        info!("📅 Cache date: {}, Today: {}", cache_date, today);
+
+        if cache_date == today {
+            info!("✅ Found VALID cache from today - USING CACHED DATA");
+            info!("📊 Cached weather data timestamp: {}", cache.data.timestamp);
+            Ok(Some(cache.data))
+        } else {
+            info!("⏰ Cache is from different day ({} vs {}), will refresh", cache_date, today);
+            Ok(None)
         }
     }
 
@@ -177,9 +660,18 @@ impl WeatherApiClient {
         };
 
         let cache_json = serde_json::to_string_pretty(&cache)?;
-        fs::write(&self.cache_path, &cache_json)?;
+        let cache_path = self.cache_path(lat, lon);
+        fs::write(&cache_path, &cache_json).await?;
+        match fs::metadata(&cache_path).await.and_then(|metadata| metadata.modified()) {
+            Ok(modified) => {
+                self.memory_cache.write().unwrap().insert(cache_path.clone(), MemoryCacheEntry { modified, cache });
+            }
+            Err(_) => {
+                self.memory_cache.write().unwrap().remove(&cache_path);
+            }
+        }
         
-        info!("💾 WEATHER DATA CACHED successfully to: {:?}", self.cache_path);
+        info!("💾 WEATHER DATA CACHED successfully to: {:?}", cache_path);
         info!("📊 Cached data timestamp: {}", data.timestamp);
         info!("📍 Cached coordinates: ({}, {})", lat, lon);
         info!("📄 Cache file size: {} bytes", cache_json.len());
@@ -188,7 +680,26 @@ impl WeatherApiClient {
 
     pub async fn read_cached_weather_only(&self, lat: f64, lon: f64) -> Result<Option<WeatherData>> {
         // Only read from cache, never call API
-        self.get_cached_weather(lat, lon).await
+        Ok(self.get_cached_weather(lat, lon).await?.map(|data| self.apply_output_settings(data)))
+    }
+
+    // Alerts from the cached data that haven't expired yet, never calls the API
+    pub async fn get_weather_alerts(&self, lat: f64, lon: f64) -> Result<Vec<WeatherAlert>> {
+        let now = Utc::now();
+        let alerts = self.get_cached_weather(lat, lon).await?
+            .map(|data| data.alerts)
+            .unwrap_or_default();
+        Ok(alerts.into_iter().filter(|alert| alert.end > now).collect())
+    }
+
+    // Fetches fresh data, bypassing the daily cache, and returns the current alerts
+    pub async fn poll_alerts(&self, lat: f64, lon: f64) -> Result<Vec<WeatherAlert>> {
+        let weather_data = self.fetch_from_provider(lat, lon).await?;
+        if let Err(e) = self.cache_weather_data(&weather_data, lat, lon).await {
+            warn!("Failed to cache weather data: {}", e);
+        }
+        let now = Utc::now();
+        Ok(weather_data.alerts.into_iter().filter(|alert| alert.end > now).collect())
     }
 
     pub async fn ensure_daily_cache(&self, lat: f64, lon: f64) -> Result<()> {
@@ -202,7 +713,7 @@ impl WeatherApiClient {
             }
             None => {
                 info!("⚠️  Cache is missing or expired, UPDATING FROM API");
-                let weather_data = self.fetch_weather(lat, lon, OPENWEATHERMAP_API_KEY).await?;
+                let weather_data = self.fetch_from_provider(lat, lon).await?;
                 self.cache_weather_data(&weather_data, lat, lon).await?;
                 info!("✅ Daily cache updated successfully");
                 Ok(())
@@ -210,237 +721,93 @@ impl WeatherApiClient {
         }
     }
 
-
-    fn generate_historical_data(&self, current_temp: f64, current_humidity: i32) -> Vec<HistoryDay> {
-        use chrono::Datelike;
-        
-        info!("⚠️  Generating historical data with fixed temperature pattern");
-        
-        let mut history = Vec::new();
-        let today = Utc::now().date_naive();
-        
-        // Fixed temperature pattern: 32, 31, 34, 28, 32, 27, ...
-        let temp_pattern = [32.0, 31.0, 34.0, 28.0, 32.0, 27.0, 30.0];
-        
-        // Fixed humidity pattern: 85, 72, 68, 91, 76, 82, ...
-        let humidity_pattern = [85, 72, 68, 91, 76, 82, 79];
-
-        // Generate history for past 6 days (today-6 to today-1) + today
-        for days_back in (0..7).rev() { // 6, 5, 4, 3, 2, 1, 0 (today)
-            let historical_date = today - chrono::Duration::days(days_back);
-            
-            // Use fixed temperature pattern for historical days, current temp for today
-            let historical_temp = if days_back == 0 {
-                current_temp // Use actual current temperature for today
-            } else {
-                let pattern_index = (6 - days_back) as usize % temp_pattern.len();
-                temp_pattern[pattern_index]
-            };
-            
-            // Use fixed humidity pattern for historical days, current humidity for today
-            let historical_humidity = if days_back == 0 {
-                current_humidity // Use actual current humidity for today
-            } else {
-                let pattern_index = (6 - days_back) as usize % humidity_pattern.len();
-                humidity_pattern[pattern_index]
-            };
-            
-            // Format date as DD/MM
-            let date_str = historical_date.format("%d/%m").to_string();
-            
-            // Calculate day name
-            let day_name = if days_back == 0 {
-                "TODAY".to_string()
-            } else {
-                match historical_date.weekday() {
-                    chrono::Weekday::Mon => "MON",
-                    chrono::Weekday::Tue => "TUE", 
-                    chrono::Weekday::Wed => "WED",
-                    chrono::Weekday::Thu => "THU",
-                    chrono::Weekday::Fri => "FRI",
-                    chrono::Weekday::Sat => "SAT",
-                    chrono::Weekday::Sun => "SUN",
-                }.to_string()
-            };
-            
-            history.push(HistoryDay {
-                day: day_name,
-                date: date_str,
-                temp: historical_temp,
-                humidity: historical_humidity,
-            });
-            
-            info!("Generated history day {}: {} {} - temp: {:.1}°C, humidity: {}%", 
-                  6 - days_back, history.last().unwrap().day, history.last().unwrap().date, 
-                  historical_temp, historical_humidity);
-        }
-
-        info!("Generated {} days of historical data with temp pattern {:?} and humidity pattern {:?}", history.len(), temp_pattern, humidity_pattern);
-        history
-    }
-
-    async fn parse_weather_response(&self, data: Value, lat: f64, lon: f64) -> Result<WeatherData> {
-        info!("🔧 PARSING WEATHER RESPONSE");
-        
-        let current = data.get("current")
-            .ok_or_else(|| anyhow!("Missing current weather data"))?;
-        info!("✅ Found current weather data");
-
-        let daily = data.get("daily")
-            .and_then(|d| d.as_array())
-            .ok_or_else(|| anyhow!("Missing daily forecast data"))?;
-        info!("✅ Found daily forecast data with {} entries", daily.len());
-
-        let empty_vec = vec![];
-        let _hourly = data.get("hourly")
-            .and_then(|h| h.as_array())
-            .unwrap_or(&empty_vec);
-
-        // Parse current weather
-        let current_temp = current.get("temp")
-            .and_then(|t| t.as_f64())
-            .unwrap_or(0.0);
-
-        let humidity = current.get("humidity")
-            .and_then(|h| h.as_i64())
-            .unwrap_or(0) as i32;
-
-        let pressure = current.get("pressure")
-            .and_then(|p| p.as_i64())
-            .unwrap_or(0) as i32;
-
-        let wind_speed = current.get("wind_speed")
-            .and_then(|w| w.as_f64())
-            .unwrap_or(0.0);
-
-        let wind_deg = current.get("wind_deg")
-            .and_then(|w| w.as_f64())
-            .unwrap_or(0.0);
-
-        let condition = current.get("weather")
-            .and_then(|w| w.as_array())
-            .and_then(|arr| arr.get(0))
-            .and_then(|weather| weather.get("description"))
-            .and_then(|desc| desc.as_str())
-            .unwrap_or("Unknown")
-            .to_string();
-
-        let current_icon = current.get("weather")
-            .and_then(|w| w.as_array())
-            .and_then(|arr| arr.get(0))
-            .and_then(|weather| weather.get("icon"))
-            .and_then(|icon| icon.as_str())
-            .unwrap_or("unknown")
-            .to_string();
-
-        // Parse forecast
-        let forecast = self.parse_forecast(daily)?;
-
-        // Generate historical data (timemachine API requires paid subscription)
-        let history = self.generate_historical_data(current_temp, humidity);
-
-        let weather_data = WeatherData {
-            location: format!("LAT: {:.4}, LON: {:.4}", lat, lon),
+    // Turns a provider report into the payload published to the device
+    fn build_weather_data(&self, report: WeatherReport, provider: &str, lat: f64, lon: f64) -> WeatherData {
+        let mut weather_data = WeatherData {
+            location: Locale::default().coordinate_label(lat, lon),
             gps_lat: lat,
             gps_lon: lon,
-            condition: condition.clone(),
-            current_icon: current_icon.clone(),
-            wind_speed,
-            wind_direction: self.wind_deg_to_direction(wind_deg),
-            current_temp,
-            humidity,
-            pressure,
-            forecast: forecast.clone(),
-            history: history.clone(),
+            condition: report.current.condition,
+            current_icon: report.current.icon,
+            wind_speed: report.current.wind_speed,
+            wind_direction: self.wind_deg_to_direction(report.current.wind_deg),
+            current_temp: report.current.temp,
+            humidity: report.current.humidity,
+            pressure: report.current.pressure,
+            feels_like: report.current.feels_like,
+            wind_gust: report.current.wind_gust,
+            units: report.units,
+            // Providers write English labels and DD/MM dates; the locale is applied on output
+            locale: Locale::default(),
+            uv_index: report.current.uv_index,
+            visibility: report.current.visibility,
+            sunrise: report.current.sunrise,
+            sunset: report.current.sunset,
+            rain_mm: report.current.rain_mm,
+            snow_mm: report.current.snow_mm,
+            forecast: report.forecast,
+            history: report.history,
+            history_synthetic: report.history_synthetic,
+            alerts: report.alerts,
+            hourly: report.hourly,
+            provider: provider.to_string(),
+            quality_flags: Vec::new(),
             timestamp: Utc::now(),
         };
+        self.apply_recorded_history(&mut weather_data);
 
-        info!("✅ SUCCESSFULLY PARSED WEATHER DATA");
-        info!("📊 Current: {}°C, {}, {}", current_temp, condition, current_icon);
-        info!("📈 History entries: {}", history.len());
-        info!("📅 Forecast entries: {}", forecast.len());
         info!("🕐 Data timestamp: {}", weather_data.timestamp);
-        
-        Ok(weather_data)
+        weather_data
     }
 
-    fn parse_forecast(&self, daily: &[Value]) -> Result<Vec<ForecastDay>> {
-        let mut forecast = Vec::new();
-        let today = Utc::now().date_naive();
-
-        for (i, day_data) in daily.iter().take(6).enumerate() {
-            // Map according to your requirements:
-            // temp -> [].temp.max (use max temperature for the day)
-            let temp = day_data.get("temp")
-                .and_then(|t| t.get("max"))
-                .and_then(|max_temp| max_temp.as_f64())
-                .unwrap_or(0.0);
-
-            // humidity -> humidity (direct mapping)
-            let humidity = day_data.get("humidity")
-                .and_then(|h| h.as_i64())
-                .unwrap_or(0) as i32;
-
-            // icon -> weather[0].icon (first weather object's icon)
-            let icon = day_data.get("weather")
-                .and_then(|w| w.as_array())
-                .and_then(|arr| arr.first())
-                .and_then(|weather| weather.get("icon"))
-                .and_then(|icon| icon.as_str())
-                .unwrap_or("unknown")
-                .to_string();
-
-            // dt -> convert for day name and date
-            let dt = day_data.get("dt")
-                .and_then(|dt| dt.as_i64())
-                .unwrap_or(0);
-
-            let (day_name, date) = if dt > 0 {
-                if let Some(datetime) = chrono::DateTime::from_timestamp(dt, 0) {
-                    let forecast_date = datetime.date_naive();
-                    
-                    // Date format: DD/MM (as requested: 31/12)
-                    let date_str = datetime.format("%d/%m").to_string();
-                    
-                    // Day name: "TODAY" for today, else weekday abbreviation
-                    let day_name = if forecast_date == today {
-                        "TODAY".to_string()
-                    } else {
-                        // Calculate the actual day name based on weekday
-                        match forecast_date.weekday() {
-                            chrono::Weekday::Mon => "MON",
-                            chrono::Weekday::Tue => "TUE", 
-                            chrono::Weekday::Wed => "WED",
-                            chrono::Weekday::Thu => "THU",
-                            chrono::Weekday::Fri => "FRI",
-                            chrono::Weekday::Sat => "SAT",
-                            chrono::Weekday::Sun => "SUN",
-                        }.to_string()
-                    };
-                    
-                    (day_name, date_str)
-                } else {
-                    (format!("DAY{}", i + 1), "".to_string())
-                }
-            } else {
-                (format!("DAY{}", i + 1), "".to_string())
-            };
-
-            info!("Parsed forecast day {}: {} {} - temp: {} (max), humidity: {}, icon: {}", 
-                  i, day_name, date, temp, humidity, icon);
+    // Replaces past history days with daily means of the station's own readings.
+    // Today keeps the current conditions; days without enough readings keep the provider's values.
+    fn apply_recorded_history(&self, data: &mut WeatherData) {
+        let Some(history) = self.sensor_history.read().unwrap().clone() else {
+            return;
+        };
+        let today = Local::now().date_naive();
+        let days = match statistics::daily_averages(&history, today - chrono::Duration::days(7), today - chrono::Duration::days(1)) {
+            Ok(days) => days,
+            Err(e) => {
+                warn!("Failed to read recorded daily averages: {}", e);
+                return;
+            }
+        };
 
-            forecast.push(ForecastDay {
-                day: day_name,
-                date,
-                temp,
-                humidity,
-                icon,
-            });
+        let mut replaced = 0;
+        let past_days = data.history.iter().filter(|day| day.date != today.format("%d/%m").to_string()).count();
+        for day in data.history.iter_mut() {
+            if let Some(recorded) = days.iter().find(|recorded| recorded.date.format("%d/%m").to_string() == day.date) {
+                // The sensor reports metric values
+                day.temp = (UnitSystem::Metric.convert_temp(recorded.temperature, data.units) * 10.0).round() / 10.0;
+                day.humidity = recorded.humidity.round() as i32;
+                replaced += 1;
+            }
         }
 
-        Ok(forecast)
+        if replaced > 0 {
+            info!("Using recorded sensor data for {} of {} history days", replaced, past_days);
+            if replaced >= past_days {
+                data.history_synthetic = false;
+            }
+        }
     }
 
+    // The cache keeps the full hourly forecast; callers only see it when enabled.
+    // Cached data fetched before a units change is converted here.
+    fn apply_output_settings(&self, mut data: WeatherData) -> WeatherData {
+        let settings = self.settings.read().unwrap();
+        data.convert_units(settings.units);
+        data.apply_locale(settings.locale);
+        data.forecast.truncate(settings.forecast_days.clamp(1, MAX_FORECAST_DAYS));
+        if settings.include_hourly {
+            data.hourly.truncate(settings.hourly_forecast_hours);
+        } else {
+            data.hourly.clear();
+        }
+        data
+    }
 
     fn wind_deg_to_direction(&self, deg: f64) -> String {
         let directions = [
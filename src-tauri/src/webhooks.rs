@@ -0,0 +1,182 @@
+use crate::config::{AlertRoute, WebhookSettings};
+use crate::history::enum_text;
+use crate::sinks::DataSink;
+use crate::types::{AlertData, AlertDirection, AlertSource};
+use anyhow::{Result, anyhow};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use tokio::time::Duration;
+use tracing::{info, error, warn};
+
+pub const SINK_NAME: &str = "webhooks";
+
+#[derive(Debug, Clone, Serialize)]
+struct WebhookPayload<'a> {
+    message: &'a str,
+    level: &'a crate::types::AlertLevel,
+    source: AlertSource,
+    direction: AlertDirection,
+    timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+// POSTs alerts to the configured webhooks in the background, retrying
+// network errors, 5xx and 429 responses with exponential backoff
+pub struct WebhookDispatcher {
+    client: Client,
+    webhooks: RwLock<Vec<WebhookSettings>>,
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        Self {
+            client: Client::new(),
+            webhooks: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl WebhookDispatcher {
+    pub fn set_webhooks(&self, webhooks: Vec<WebhookSettings>) {
+        if let Ok(mut guard) = self.webhooks.write() {
+            *guard = webhooks;
+        }
+    }
+
+    pub fn webhooks(&self) -> Vec<WebhookSettings> {
+        self.webhooks.read().map(|guard| guard.clone()).unwrap_or_default()
+    }
+
+    // Queues the alert for every matching webhook without waiting on the network
+    pub fn dispatch(&self, alert: &AlertData, source: AlertSource, direction: AlertDirection) {
+        let webhooks: Vec<WebhookSettings> = match self.webhooks.read() {
+            Ok(guard) => guard.iter()
+                .filter(|webhook| webhook.enabled && !webhook.url.is_empty())
+                .filter(|webhook| alert.level >= webhook.min_level)
+                .filter(|webhook| direction == AlertDirection::Sent || webhook.include_received)
+                .cloned()
+                .collect(),
+            Err(_) => return,
+        };
+
+        for webhook in webhooks {
+            let client = self.client.clone();
+            let alert = alert.clone();
+            tokio::spawn(async move {
+                if let Err(e) = deliver(&client, &webhook, &alert, source, direction).await {
+                    error!("Webhook {} failed: {}", webhook_label(&webhook), e);
+                }
+            });
+        }
+    }
+
+    // Sends one request to the webhook and reports the outcome, for testing settings
+    pub async fn send_test(&self, webhook: &WebhookSettings, alert: &AlertData) -> Result<()> {
+        deliver(&self.client, webhook, alert, AlertSource::Manual, AlertDirection::Sent).await
+    }
+}
+
+impl DataSink for WebhookDispatcher {
+    fn name(&self) -> &str {
+        SINK_NAME
+    }
+
+    fn handle_alert(&self, alert: &AlertData, source: AlertSource, direction: AlertDirection) {
+        self.dispatch(alert, source, direction);
+    }
+
+    fn routed(&self, route: &AlertRoute) -> bool {
+        route.webhooks
+    }
+}
+
+async fn deliver(
+    client: &Client,
+    webhook: &WebhookSettings,
+    alert: &AlertData,
+    source: AlertSource,
+    direction: AlertDirection,
+) -> Result<()> {
+    let body = render_body(webhook, alert, source, direction)?;
+    post_with_retry(client, &format!("webhook {}", webhook_label(webhook)), &webhook.url, &webhook.headers, body, webhook.max_retries, webhook.timeout_secs).await
+}
+
+// POSTs `body`, retrying network errors, 5xx and 429 responses. Sent as JSON unless
+// `headers` sets a Content-Type.
+pub(crate) async fn post_with_retry(
+    client: &Client,
+    label: &str,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    body: String,
+    max_retries: u32,
+    timeout_secs: u64,
+) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        let mut request = client.post(url)
+            .timeout(Duration::from_secs(timeout_secs.max(1)));
+        if !headers.keys().any(|name| name.eq_ignore_ascii_case(CONTENT_TYPE.as_str())) {
+            request = request.header(CONTENT_TYPE, "application/json");
+        }
+        for (name, value) in headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        let retryable = match request.body(body.clone()).send().await {
+            Ok(response) if response.status().is_success() => {
+                info!("Alert delivered to {}", label);
+                return Ok(());
+            }
+            Ok(response) => {
+                let status = response.status();
+                let text = response.text().await.unwrap_or_default();
+                let error = anyhow!("HTTP {}: {}", status, text.trim());
+                if !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS) {
+                    return Err(error);
+                }
+                error
+            }
+            // The URL may carry a token, e.g. Telegram's bot URLs
+            Err(e) => anyhow!(e.without_url()),
+        };
+
+        if attempt >= max_retries {
+            return Err(retryable);
+        }
+        attempt += 1;
+        let backoff = Duration::from_secs(1 << attempt.min(6));
+        warn!("Delivery to {} attempt {} failed: {}, retrying in {:?}", label, attempt, retryable, backoff);
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+fn render_body(webhook: &WebhookSettings, alert: &AlertData, source: AlertSource, direction: AlertDirection) -> Result<String> {
+    let Some(template) = &webhook.body_template else {
+        return Ok(serde_json::to_string(&WebhookPayload {
+            message: &alert.message,
+            level: &alert.level,
+            source,
+            direction,
+            timestamp: alert.timestamp,
+        })?);
+    };
+
+    // Values are escaped so templates can place them inside JSON strings
+    let escape = |value: &str| -> Result<String> {
+        let quoted = serde_json::to_string(value)?;
+        Ok(quoted[1..quoted.len() - 1].to_string())
+    };
+    Ok(template
+        .replace("{{message}}", &escape(&alert.message)?)
+        .replace("{{level}}", &escape(&enum_text(&alert.level))?)
+        .replace("{{source}}", &escape(&enum_text(&source))?)
+        .replace("{{direction}}", &escape(&enum_text(&direction))?)
+        .replace("{{timestamp}}", &alert.timestamp.to_rfc3339()))
+}
+
+pub(crate) fn webhook_label(webhook: &WebhookSettings) -> &str {
+    if webhook.name.is_empty() { &webhook.url } else { &webhook.name }
+}
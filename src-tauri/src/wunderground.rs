@@ -0,0 +1,66 @@
+use crate::config::WundergroundSettings;
+use crate::conversions::{self, Unit};
+use crate::metrics::dew_point;
+use crate::sinks::{IntervalUploader, UploadStatus, UploadStatusSlot};
+use crate::types::{SensorData, UnitSystem};
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use reqwest::Client;
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+
+pub const SINK_NAME: &str = "wunderground";
+pub const MIN_INTERVAL_SECS: u64 = 60;
+const UPLOAD_URL: &str = "https://weatherstation.wunderground.com/weatherstation/updateweatherstation.php";
+const TIMEOUT_SECS: u64 = 15;
+
+static STATUS: UploadStatusSlot = Mutex::new(None);
+
+// None until the uploader has been started once
+pub fn status() -> Option<UploadStatus> {
+    STATUS.lock().unwrap().clone()
+}
+
+// Uploads the newest reading once per interval in Weather Underground's PWS protocol
+pub fn start(settings: WundergroundSettings) -> Result<IntervalUploader> {
+    if settings.station_id.is_empty() || settings.station_key.is_empty() {
+        return Err(anyhow!("Weather Underground station id and key must be configured"));
+    }
+    let client = Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .build()?;
+    let interval = Duration::from_secs(settings.interval_secs.max(MIN_INTERVAL_SECS));
+    let (target, device_id) = (settings.station_id.clone(), settings.device_id.clone());
+    let settings = Arc::new(settings);
+    Ok(IntervalUploader::start(SINK_NAME, "Weather Underground", &target, device_id, interval, &STATUS, move |reading| {
+        let (client, settings) = (client.clone(), Arc::clone(&settings));
+        async move { upload(&client, &settings, &reading).await }
+    }))
+}
+
+async fn upload(client: &Client, settings: &WundergroundSettings, reading: &SensorData) -> Result<()> {
+    let fahrenheit = |celsius: f64| format!("{:.1}", UnitSystem::Metric.convert_temp(celsius, UnitSystem::Imperial));
+    let measured_at = reading.received_at.unwrap_or_else(Utc::now);
+    let query = [
+        ("ID", settings.station_id.clone()),
+        ("PASSWORD", settings.station_key.clone()),
+        ("action", "updateraw".to_string()),
+        ("softwaretype", format!("m5go-weather-station-desktop/{}", env!("CARGO_PKG_VERSION"))),
+        ("dateutc", measured_at.format("%Y-%m-%d %H:%M:%S").to_string()),
+        ("tempf", fahrenheit(reading.temperature)),
+        ("humidity", format!("{:.0}", reading.humidity)),
+        ("dewptf", fahrenheit(dew_point(reading.temperature, reading.humidity))),
+        ("baromin", format!("{:.2}", conversions::convert_same(reading.pressure, Unit::Hectopascal, Unit::InchOfMercury))),
+    ];
+
+    let response = client.get(UPLOAD_URL).query(&query).send().await
+        // The URL carries the station key
+        .map_err(|e| anyhow!(e.without_url()))?;
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    // Bad credentials still come back as 200 with an error in the body
+    if !status.is_success() || !text.trim().eq_ignore_ascii_case("success") {
+        return Err(anyhow!("HTTP {}: {}", status, text.trim()));
+    }
+    Ok(())
+}
@@ -71,6 +71,12 @@ export async function saveAppConfig(config: AppSettings) {
   }
 }
 
+// Sun automation changed app.dark_mode in the backend
+export function applyDarkModeChange(darkMode: boolean) {
+  appSettings.update((settings) => settings && { ...settings, dark_mode: darkMode });
+  appConfig.update((config) => config && { ...config, app: { ...config.app, dark_mode: darkMode } });
+}
+
 // Auto-clear messages after 5 seconds
 export function showMessage(message: string, isError: boolean = false) {
   if (isError) {
@@ -1,4 +1,57 @@
-import { invoke } from '@tauri-apps/api/core';
+import { invoke as invokeCommand } from '@tauri-apps/api/core';
+
+export type AppErrorKind =
+  | 'mqtt_not_connected'
+  | 'api_quota_exceeded'
+  | 'api_key_missing'
+  | 'config_invalid'
+  | 'device_timeout'
+  | 'device_rejected'
+  | 'not_found'
+  | 'failed';
+
+export interface ConfigFieldError {
+  path: string;
+  message: string;
+}
+
+interface AppErrorPayload {
+  kind: AppErrorKind;
+  message: string;
+  field?: string;
+  errors?: ConfigFieldError[];
+}
+
+// What every command rejects with. Stringifies to the message, so `${error}` reads as before.
+export class AppError extends Error {
+  kind: AppErrorKind;
+  // Set for config_invalid: the first invalid setting's dotted path, and all of them
+  field?: string;
+  errors?: ConfigFieldError[];
+
+  constructor(error: AppErrorPayload) {
+    super(error.message);
+    this.name = 'AppError';
+    this.kind = error.kind;
+    this.field = error.field;
+    this.errors = error.errors;
+  }
+
+  toString(): string {
+    return this.message;
+  }
+}
+
+async function invoke<T>(command: string, args?: Record<string, unknown>): Promise<T> {
+  try {
+    return await invokeCommand<T>(command, args);
+  } catch (error) {
+    if (typeof error === 'object' && error !== null && 'kind' in error && 'message' in error) {
+      throw new AppError(error as AppErrorPayload);
+    }
+    throw error;
+  }
+}
 
 export interface WeatherData {
   location: string;
@@ -31,11 +84,15 @@ export interface HistoryDay {
   humidity: number;
 }
 
+export type ReadingQuality = 'ok' | 'out_of_range' | 'stale' | 'interpolated' | 'smoothed';
+
 export interface SensorData {
   temperature: number;
   humidity: number;
   pressure: number;
   timestamp: string;
+  // Empty for readings stored before quality was recorded
+  quality: ReadingQuality[];
 }
 
 export type AlertLevel = 'info' | 'warning' | 'emergency';
@@ -84,10 +141,38 @@ export async function disconnectMqtt(): Promise<string> {
   return await invoke('disconnect_mqtt');
 }
 
-export async function getMqttStatus(): Promise<boolean> {
+export interface MqttStatus {
+  connected: boolean;
+  state: 'connected' | 'reconnecting' | 'stale' | 'disconnected';
+  broker?: string;
+  connected_since?: string;
+  last_activity?: string;
+  last_error?: string;
+  last_error_at?: string;
+}
+
+export async function getMqttStatus(): Promise<MqttStatus> {
   return await invoke('get_mqtt_status');
 }
 
+export interface ComponentCheck {
+  component: 'broker' | 'weather_api' | 'cache' | 'database';
+  status: 'ok' | 'warning' | 'failed';
+  message: string;
+  duration_ms: number;
+}
+
+export interface HealthReport {
+  checked_at: string;
+  healthy: boolean;
+  components: ComponentCheck[];
+}
+
+// Makes a real broker connection and one weather API call
+export async function runHealthCheck(): Promise<HealthReport> {
+  return await invoke('run_health_check');
+}
+
 export async function publishWeatherData(data: WeatherData): Promise<string> {
   return await invoke('publish_weather_data', { data });
 }
@@ -134,6 +219,106 @@ export async function saveAppSettings(appSettings: AppSettings): Promise<string>
   return await invoke('save_app_settings', { appSettings });
 }
 
+export interface ScheduledSunAction {
+  event: 'sunrise' | 'sunset';
+  at: string;
+  actions: string[];
+}
+
+export interface SunTimes {
+  sunrise: string;
+  sunset: string;
+  night: boolean;
+  schedule: ScheduledSunAction[];
+}
+
+export async function getSunTimes(): Promise<SunTimes | null> {
+  return await invoke('get_sun_times');
+}
+
+// Units by symbol or name, e.g. convertValue(1013.25, 'hPa', 'inHg')
+export async function convertValue(value: number, fromUnit: string, toUnit: string): Promise<number> {
+  return await invoke('convert_value', { value, fromUnit, toUnit });
+}
+
+export type ChartMetric = 'temperature' | 'humidity' | 'pressure' | 'co2' | 'tvoc' | 'lux';
+export type ChartRange = 'hour' | 'day' | 'week' | 'month' | 'year';
+
+export interface ChartPoint {
+  at: string;
+  value: number;
+}
+
+export interface ChartSeries {
+  metric: ChartMetric;
+  from: string;
+  to: string;
+  source_points: number;
+  points: ChartPoint[];
+}
+
+export async function getChartData(
+  metric: ChartMetric,
+  range: ChartRange,
+  maxPoints?: number,
+  deviceId?: string
+): Promise<ChartSeries> {
+  return await invoke('get_chart_data', { metric, range, maxPoints, deviceId });
+}
+
+export interface IconAsset {
+  code: string;
+  path: string;
+  mime_type: string;
+  data_url: string;
+}
+
+// By OpenWeatherMap code, e.g. getIcon('10d'); served from the local cache once downloaded
+export async function getIcon(code: string): Promise<IconAsset> {
+  return await invoke('get_icon', { code });
+}
+
+export async function prefetchIcons(): Promise<number> {
+  return await invoke('prefetch_icons');
+}
+
+export type ScheduledAction =
+  | { type: 'refresh_weather_cache' }
+  | { type: 'publish_weather' }
+  | { type: 'publish_forecast'; topic: string }
+  | { type: 'daily_summary' }
+  | { type: 'prune_storage' };
+
+export interface ScheduledTask {
+  id: number;
+  name: string;
+  enabled: boolean;
+  // Cron expression in local time, e.g. "0 6 * * *" or "@daily"
+  schedule: string;
+  action: ScheduledAction;
+}
+
+export interface ScheduledTaskInfo extends ScheduledTask {
+  next_run: string | null;
+  last_run: { at: string; success: boolean; message: string } | null;
+}
+
+export async function listScheduledTasks(): Promise<ScheduledTaskInfo[]> {
+  return await invoke('list_scheduled_tasks');
+}
+
+export async function addScheduledTask(task: Omit<ScheduledTask, 'id'>): Promise<ScheduledTask> {
+  return await invoke('add_scheduled_task', { task: { ...task, id: 0 } });
+}
+
+export async function removeScheduledTask(id: number): Promise<string> {
+  return await invoke('remove_scheduled_task', { id });
+}
+
+export async function runScheduledTask(id: number): Promise<string> {
+  return await invoke('run_scheduled_task', { id });
+}
+
 export async function testEmitSensorData(): Promise<string> {
   return await invoke('test_emit_sensor_data');
 }
@@ -151,6 +336,28 @@ export async function isAutoPublishing(): Promise<boolean> {
   return await invoke('is_auto_publishing');
 }
 
+export type InstanceRole = 'auto' | 'publisher' | 'viewer';
+
+export interface PublisherClaim {
+  instance_id: string;
+  role: InstanceRole;
+  lease_secs: number;
+  claimed_at: string;
+}
+
+export interface CoordinationStatus {
+  enabled: boolean;
+  role: InstanceRole;
+  instance_id: string;
+  publisher: PublisherClaim | null;
+  publishing_here: boolean;
+}
+
+// Which desktop on the broker publishes weather when several share it
+export async function getCoordinationStatus(): Promise<CoordinationStatus> {
+  return await invoke('get_coordination_status');
+}
+
 export async function refreshWeatherCache(lat: number, lon: number): Promise<string> {
   return await invoke('refresh_weather_cache', { lat, lon });
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
 /** @type {import('tailwindcss').Config} */
 export default {
   content: ['./src/**/*.{html,js,svelte,ts}'],
+  // Follows app.dark_mode rather than the OS setting
+  darkMode: 'class',
   theme: {
     extend: {
       colors: {